@@ -7,10 +7,13 @@
 ///
 use crate::context::*;
 use go_parser::ast::*;
-use go_parser::{AssignStmtKey, Map, Token};
+use go_parser::{AssignStmtKey, AstObjects, Map, Parser, Token};
 use go_types::ObjKey as TCObjKey;
 use go_vm::types::*;
 
+/// the minimum number of case values for a switch to use SWITCH_TABLE
+const SWITCH_TABLE_MIN_CASES: usize = 4;
+
 /// branch points of break and continue
 pub(crate) struct BranchBlock {
     points: Vec<(usize, Token, Option<TCObjKey>)>,
@@ -32,6 +35,7 @@ impl BranchBlock {
 pub(crate) struct BranchHelper {
     block_stack: Vec<BranchBlock>,
     next_block_label: Option<TCObjKey>,
+    // the labels of the statements, as the labels of the functions they're in
     labels: Map<TCObjKey, usize>,
 }
 
@@ -44,17 +48,29 @@ impl BranchHelper {
         }
     }
 
-    pub fn labels(&self) -> &Map<TCObjKey, usize> {
-        &self.labels
-    }
-
-    pub fn add_label(&mut self, label: TCObjKey, offset: usize, is_breakable: bool) {
-        self.labels.insert(label, offset);
+    pub fn add_label(
+        &mut self,
+        fctx: &mut FuncCtx,
+        label: TCObjKey,
+        offset: usize,
+        is_breakable: bool,
+    ) {
+        let l = self.label(fctx, label);
+        fctx.bind_label(l, offset);
         if is_breakable {
             self.next_block_label = Some(label);
         }
     }
 
+    /// The address to jump to the label with, it may not be added yet.
+    pub fn label_addr(&mut self, fctx: &mut FuncCtx, label: TCObjKey) -> Addr {
+        Addr::Label(self.label(fctx, label))
+    }
+
+    fn label(&mut self, fctx: &mut FuncCtx, label: TCObjKey) -> usize {
+        *self.labels.entry(label).or_insert_with(|| fctx.new_label())
+    }
+
     pub fn add_jump_point(
         &mut self,
         fctx: &mut FuncCtx,
@@ -77,8 +93,38 @@ impl BranchHelper {
     }
 
     pub fn leave_block(&mut self, fctx: &mut FuncCtx, begin: Option<usize>) {
+        let points = self.resolve(fctx, begin);
+        if !points.is_empty() {
+            // these break/continue try to jump out of an outer block
+            // so we add them to outer block's jump out points
+            self.block_stack.last_mut().unwrap().points.extend(points);
+        }
+    }
+
+    /// Leaves the block of a range over a function, the break and continue
+    /// that jump out of it are returned, as they're in the yield function and
+    /// the outer blocks are in the function the loop is in.
+    pub fn leave_range_func_block(
+        &mut self,
+        fctx: &mut FuncCtx,
+        begin: usize,
+    ) -> Vec<(usize, Token, TCObjKey)> {
+        self.resolve(fctx, Some(begin))
+            .into_iter()
+            .map(|(index, token, label)| (index, token, label.unwrap()))
+            .collect()
+    }
+
+    /// Patches the jump points of the block being left that jump to its end or
+    /// beginning, and returns the others.
+    fn resolve(
+        &mut self,
+        fctx: &mut FuncCtx,
+        begin: Option<usize>,
+    ) -> Vec<(usize, Token, Option<TCObjKey>)> {
         let end = fctx.next_code_index();
         let block = self.block_stack.pop().unwrap();
+        let mut rest = vec![];
         for (index, token, label) in block.points.into_iter() {
             let label_match = label.is_none() || label == block.label;
             // for select&switch, 'break' breaks current block
@@ -89,18 +135,45 @@ impl BranchHelper {
                 _ => unreachable!(),
             };
             if label_match && break_this {
-                let current_pc = index as OpIndex + 1;
-                fctx.inst_mut(index).d = Addr::Imm(target.unwrap() as OpIndex - current_pc);
+                fctx.inst_mut(index).d = fctx.label_at(target.unwrap());
             } else {
-                // this break/continue tries to jump out of an outer block
-                // so we add it to outer block's jump out points
-                self.block_stack
-                    .last_mut()
-                    .unwrap()
-                    .points
-                    .push((index, token, label));
+                rest.push((index, token, label));
             }
         }
+        rest
+    }
+}
+
+/// The operands of a chain of `&&` or `||` are evaluated to the same register,
+/// each one but the last is followed by a jump to the end of the chain, taken
+/// when the register already holds the result.
+pub(crate) struct ShortCircuit {
+    exits: Vec<usize>,
+}
+
+impl ShortCircuit {
+    pub fn new() -> ShortCircuit {
+        ShortCircuit { exits: vec![] }
+    }
+
+    /// Emits JUMP_IF for `||` or JUMP_IF_NOT for `&&`, testing `result`.
+    pub fn add_exit(&mut self, fctx: &mut FuncCtx, op: Token, result: Addr, pos: Option<usize>) {
+        let code = match op {
+            Token::LAND => Opcode::JUMP_IF_NOT,
+            Token::LOR => Opcode::JUMP_IF,
+            _ => unreachable!(),
+        };
+        self.exits.push(fctx.next_code_index());
+        let inst = InterInst::with_op_index(code, Addr::Void, result, Addr::Void);
+        fctx.emit_inst(inst, pos);
+    }
+
+    /// Points the jumps to the next instruction.
+    pub fn patch_exits(self, fctx: &mut FuncCtx) {
+        let end = fctx.label_at(fctx.next_code_index());
+        for i in self.exits.into_iter() {
+            fctx.inst_mut(i).d = end;
+        }
     }
 }
 
@@ -130,18 +203,25 @@ impl SwitchJumpPoints {
     }
 
     pub fn patch_case(&mut self, func: &mut FuncCtx, case: usize, loc: usize) {
+        let target = func.label_at(loc);
         for i in self.cases[case].iter() {
-            func.inst_mut(*i).d = Addr::Imm((loc - i) as OpIndex - 1);
+            func.inst_mut(*i).d = target;
         }
     }
 
     pub fn patch_default(&mut self, func: &mut FuncCtx, loc: usize) {
         if let Some(de) = self.default {
-            func.inst_mut(de).d = Addr::Imm((loc - de) as OpIndex - 1);
+            func.inst_mut(de).d = func.label_at(loc);
         }
     }
 }
 
+/// A constant case value of a switch, see `SwitchHelper::emit_table`
+pub(crate) enum SwitchKey {
+    Int(i64),
+    Str(Vec<u8>, Addr),
+}
+
 pub(crate) struct SwitchHelper {
     // the beginnings of the cases
     pub tags: SwitchJumpPoints,
@@ -169,6 +249,123 @@ impl SwitchHelper {
         self.ends.patch_default(func, loc);
     }
 
+    /// Emits a SWITCH_TABLE followed by its entries and the jump to default, if
+    /// there are enough case values and the integer ones are dense. `keys` are
+    /// the case values with the indices of their case clauses, in source order.
+    /// Returns false if a linear chain of SWITCH is better.
+    pub fn emit_table(
+        &mut self,
+        fctx: &mut FuncCtx,
+        tag: Addr,
+        tag_type: ValueType,
+        keys: Vec<(usize, SwitchKey)>,
+        pos: Option<usize>,
+    ) -> bool {
+        if keys.len() < SWITCH_TABLE_MIN_CASES {
+            return false;
+        }
+        let table = fctx.next_code_index();
+        match &keys[0].1 {
+            SwitchKey::Int(_) => {
+                let ints = keys.iter().map(|(_, k)| match k {
+                    SwitchKey::Int(i) => *i as i128,
+                    SwitchKey::Str(_, _) => unreachable!(),
+                });
+                let min = ints.clone().min().unwrap();
+                let span = ints.max().unwrap() - min + 1;
+                if span > 2 * keys.len() as i128 {
+                    return false;
+                }
+                // the first of duplicate values wins, like in the linear chain
+                let mut slots = vec![None; span as usize];
+                for (case, k) in keys.iter() {
+                    if let SwitchKey::Int(i) = k {
+                        let slot = &mut slots[(*i as i128 - min) as usize];
+                        slot.get_or_insert(*case);
+                    }
+                }
+                let min_addr = fctx.add_comparable((min as i64).into());
+                fctx.emit_inst(
+                    InterInst::with_op_t_index(
+                        Opcode::SWITCH_TABLE,
+                        Some(tag_type),
+                        None,
+                        Addr::Imm(span as OpIndex),
+                        tag,
+                        min_addr,
+                    ),
+                    pos,
+                );
+                let default = fctx.label_at(table + 1 + slots.len());
+                for slot in slots.into_iter() {
+                    let index = fctx.next_code_index();
+                    let mut entry = InterInst::with_op(Opcode::VOID);
+                    match slot {
+                        Some(case) => self.tags.add_case(case, index),
+                        None => entry.d = default,
+                    }
+                    fctx.emit_inst(entry, pos);
+                }
+            }
+            SwitchKey::Str(_, _) => {
+                let count = keys.len().next_power_of_two();
+                let mut buckets = vec![vec![]; count];
+                for (case, k) in keys.into_iter() {
+                    if let SwitchKey::Str(s, addr) = k {
+                        buckets[switch_hash(&s) & (count - 1)].push((case, addr));
+                    }
+                }
+                fctx.emit_inst(
+                    InterInst::with_op_t_index(
+                        Opcode::SWITCH_TABLE,
+                        Some(tag_type),
+                        None,
+                        Addr::Imm(count as OpIndex),
+                        tag,
+                        Addr::Void,
+                    ),
+                    pos,
+                );
+                for _ in 0..count {
+                    fctx.emit_inst(InterInst::with_op(Opcode::VOID), pos);
+                }
+                // each bucket is a chain of SWITCH, then a jump to default
+                let mut to_default: Vec<usize> = vec![];
+                for (i, bucket) in buckets.iter().enumerate() {
+                    let entry = table + 1 + i;
+                    if bucket.is_empty() {
+                        to_default.push(entry);
+                        continue;
+                    }
+                    fctx.inst_mut(entry).d = fctx.label_at(fctx.next_code_index());
+                    for (case, addr) in bucket.iter() {
+                        self.tags.add_case(*case, fctx.next_code_index());
+                        fctx.emit_inst(
+                            InterInst::with_op_t_index(
+                                Opcode::SWITCH,
+                                Some(tag_type),
+                                None,
+                                Addr::Void,
+                                tag,
+                                *addr,
+                            ),
+                            pos,
+                        );
+                    }
+                    to_default.push(fctx.next_code_index());
+                    fctx.emit_inst(InterInst::with_op(Opcode::JUMP), pos);
+                }
+                let default = fctx.label_at(fctx.next_code_index());
+                for i in to_default.into_iter() {
+                    fctx.inst_mut(i).d = default;
+                }
+            }
+        }
+        self.tags.add_default(fctx.next_code_index());
+        fctx.emit_inst(InterInst::with_op(Opcode::JUMP), None);
+        true
+    }
+
     pub fn to_case_clause(s: &Stmt) -> &CaseClause {
         match s {
             Stmt::Case(c) => c,
@@ -176,9 +373,19 @@ impl SwitchHelper {
         }
     }
 
-    pub fn has_fall_through(s: &Stmt) -> bool {
+    /// Reports whether the case ends with a fallthrough, which may be labeled or
+    /// followed by empty statements, as the checker allows.
+    pub fn has_fall_through(s: &Stmt, objs: &AstObjects) -> bool {
         let case = Self::to_case_clause(s);
-        case.body.last().map_or(false, |x| match x {
+        let mut last = case
+            .body
+            .iter()
+            .rev()
+            .find(|x| !matches!(x, Stmt::Empty(_)));
+        while let Some(Stmt::Labeled(l)) = last {
+            last = Some(&objs.l_stmts[*l].stmt);
+        }
+        last.map_or(false, |x| match x {
             Stmt::Branch(b) => b.token == Token::FALLTHROUGH,
             _ => false,
         })
@@ -213,6 +420,7 @@ pub(crate) struct SelectComm {
     typ: CommType,
     chan_addr: Option<Addr>,
     pos: usize,
+    // the first instruction of the block, and the jump at its end
     begin: usize,
     end: usize,
     offset: usize,
@@ -306,19 +514,17 @@ impl SelectHelper {
         }
     }
 
+    /// Called after the last block, the entries jump to their blocks, and the
+    /// blocks but the last one end with a jump to after the last one.
     pub fn patch_select(&self, fctx: &mut FuncCtx) {
-        let blocks_begin = self.comms.first().unwrap().begin as OpIndex;
-        let blocks_end = self.comms.last().unwrap().end as OpIndex;
-        // set the block offset for each block.
         for comm in self.comms.iter() {
-            let diff = comm.begin as OpIndex - blocks_begin;
-            fctx.inst_mut(comm.offset).d = Addr::Imm(diff);
+            let begin = fctx.label_at(comm.begin);
+            fctx.inst_mut(comm.offset).d = begin;
         }
-        // set where to jump when the block ends
+        let exit = fctx.new_label();
+        fctx.bind_label(exit, fctx.next_code_index());
         for comm in self.comms[..self.comms.len() - 1].iter() {
-            let block_end = comm.end;
-            let diff = blocks_end - block_end as OpIndex;
-            fctx.inst_mut(block_end).d = Addr::Imm(diff);
+            fctx.inst_mut(comm.end).d = Addr::Label(exit);
         }
     }
 
@@ -330,7 +536,7 @@ impl SelectHelper {
     }
 
     pub fn unwrap_recv(e: &Expr) -> (&Expr, usize) {
-        match e {
+        match Parser::unparen(e) {
             Expr::Unary(ue) => {
                 assert_eq!(ue.op, Token::ARROW);
                 (&ue.expr, ue.op_pos)
@@ -0,0 +1,344 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The control-flow graph of the code of a function in the IR, with the
+//! `verify` feature: the basic blocks, their edges and their dominators.
+//!
+//! Building it checks every jump, a target out of the code, or in the middle
+//! of an instruction with extra words, like the entries of a SWITCH_TABLE, or
+//! a fall-through off the end of the code is an error, as the VM would run
+//! garbage instead.
+
+use crate::ir::{Addr, InterInst};
+use go_vm::types::*;
+
+#[derive(Debug)]
+pub(crate) struct Block {
+    /// The index of the first instruction.
+    pub begin: usize,
+    /// The index after the last word of the last instruction.
+    pub end: usize,
+    pub succs: Vec<usize>,
+    pub preds: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Cfg {
+    /// In the order of the code, the entry is the first one.
+    pub blocks: Vec<Block>,
+    /// The immediate dominator of every block, none for the entry and the
+    /// unreachable ones.
+    pub idom: Vec<Option<usize>>,
+}
+
+impl Cfg {
+    /// Fails with the index of the instruction that's wrong and why.
+    pub fn new(code: &[InterInst], labels: &[Option<usize>]) -> Result<Cfg, (usize, String)> {
+        // the instructions, with their successors
+        let mut insts: Vec<(usize, Vec<usize>, bool)> = vec![];
+        let mut starts = vec![false; code.len()];
+        let mut i = 0;
+        while i < code.len() {
+            let (width, targets, falls) = Self::successors(code, i, labels).map_err(|e| (i, e))?;
+            starts[i] = true;
+            insts.push((i, targets, falls));
+            i += width;
+        }
+        if let Some((index, _, _)) = insts.last().filter(|_| i > code.len()) {
+            let msg = format!("the extra words of #{} are out of the code", index);
+            return Err((*index, msg));
+        }
+
+        let mut leaders = vec![false; code.len()];
+        if !code.is_empty() {
+            leaders[0] = true;
+        }
+        for (i, (index, targets, falls)) in insts.iter().enumerate() {
+            for &t in targets.iter() {
+                if t >= code.len() || !starts[t] {
+                    let msg = format!("#{} jumps to #{}, not an instruction", index, t);
+                    return Err((*index, msg));
+                }
+                leaders[t] = true;
+            }
+            let next = insts.get(i + 1).map_or(code.len(), |x| x.0);
+            if *falls && next == code.len() {
+                let msg = format!("#{} falls off the end of the code", index);
+                return Err((*index, msg));
+            }
+            if (!targets.is_empty() || !falls) && next < code.len() {
+                leaders[next] = true;
+            }
+        }
+
+        let mut blocks: Vec<Block> = vec![];
+        let mut block_of = vec![0; code.len()];
+        for (i, (index, _, _)) in insts.iter().enumerate() {
+            if leaders[*index] {
+                blocks.push(Block {
+                    begin: *index,
+                    end: 0,
+                    succs: vec![],
+                    preds: vec![],
+                });
+            }
+            let b = blocks.len() - 1;
+            block_of[*index] = b;
+            blocks[b].end = insts.get(i + 1).map_or(code.len(), |x| x.0);
+        }
+        for (i, (index, targets, falls)) in insts.iter().enumerate() {
+            let b = block_of[*index];
+            let last = insts.get(i + 1).map_or(true, |x| leaders[x.0]);
+            if !last {
+                continue;
+            }
+            let next = insts.get(i + 1).filter(|_| *falls).map(|x| x.0);
+            for t in targets.iter().copied().chain(next) {
+                let s = block_of[t];
+                if !blocks[b].succs.contains(&s) {
+                    blocks[b].succs.push(s);
+                    blocks[s].preds.push(b);
+                }
+            }
+        }
+
+        let idom = Self::dominators(&blocks);
+        Ok(Cfg { blocks, idom })
+    }
+
+    /// Reports whether the block `a` dominates the block `b`, which is
+    /// reachable.
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        let mut cur = Some(b);
+        while let Some(x) = cur {
+            if x == a {
+                return true;
+            }
+            cur = self.idom[x];
+        }
+        false
+    }
+
+    pub fn reachable(&self, b: usize) -> bool {
+        b == 0 || self.idom[b].is_some()
+    }
+
+    // The width of the instruction at `i`, the instructions it may jump to
+    // and whether it may go on to the next one, as the VM runs them.
+    fn successors(
+        code: &[InterInst],
+        i: usize,
+        labels: &[Option<usize>],
+    ) -> Result<(usize, Vec<usize>, bool), String> {
+        // the target of an offset from `base`, labels are from the next
+        // instruction
+        let target = |addr: Addr, base: usize| -> Result<usize, String> {
+            let t = match addr {
+                Addr::Imm(o) => base as OpIndex + o,
+                Addr::Label(l) => match labels.get(l) {
+                    Some(Some(t)) => *t as OpIndex,
+                    _ => return Err(format!("#{} jumps to the unbound label {}", i, l)),
+                },
+                _ => return Err(format!("#{} jumps to {:?}", i, addr)),
+            };
+            usize::try_from(t).map_err(|_| format!("#{} jumps to #{}", i, t))
+        };
+        let count = |addr: Addr| match addr {
+            Addr::Imm(n) if n >= 0 => Ok(n as usize),
+            _ => Err(format!("#{} has {:?} extra words", i, addr)),
+        };
+        let words = |range: std::ops::Range<usize>| {
+            code.get(range)
+                .ok_or_else(|| format!("the extra words of #{} are out of the code", i))
+        };
+        let inst = &code[i];
+        let next = i + 1;
+        Ok(match inst.op0 {
+            Opcode::JUMP => (1, vec![target(inst.d, next)?], false),
+            Opcode::JUMP_IF | Opcode::JUMP_IF_NOT | Opcode::SWITCH | Opcode::IMPORT => {
+                (1, vec![target(inst.d, next)?], true)
+            }
+            Opcode::RETURN | Opcode::PANIC => (1, vec![], false),
+            Opcode::LOAD_INIT_FUNC => (1, vec![next + 2], true),
+            Opcode::RANGE => (2, vec![target(inst.s0, next + 1)?], true),
+            Opcode::LOAD_MAP | Opcode::STORE_MAP | Opcode::SLICE | Opcode::LITERAL => {
+                (2, vec![], true)
+            }
+            Opcode::TYPE_ASSERT if inst.t1 == ValueType::FlagB => (2, vec![], true),
+            Opcode::MAKE if inst.t0 == ValueType::FlagC => (2, vec![], true),
+            // the entries jump from where they are, the word after them jumps
+            // to default
+            Opcode::SWITCH_TABLE => {
+                let n = count(inst.d)?;
+                let entries = words(next..next + n)?;
+                let targets = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(k, x)| target(x.d, next + k + 1))
+                    .collect::<Result<_, _>>()?;
+                (n + 1, targets, true)
+            }
+            // the blocks are from the word after the entries on
+            Opcode::SELECT => {
+                let n = count(inst.s0)?;
+                let blocks = next + n;
+                let entries = words(next..blocks)?;
+                let mut targets = entries
+                    .iter()
+                    .map(|x| target(x.d, blocks))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if inst.t0 == ValueType::FlagE {
+                    targets.push(target(inst.d, blocks)?);
+                }
+                (n + 1, targets, true)
+            }
+            _ => (1, vec![], true),
+        })
+    }
+
+    // The iterative algorithm of Cooper, Harvey and Kennedy, over the blocks
+    // in reverse postorder.
+    fn dominators(blocks: &[Block]) -> Vec<Option<usize>> {
+        let mut idom = vec![None; blocks.len()];
+        if blocks.is_empty() {
+            return idom;
+        }
+        let mut order = vec![];
+        let mut visited = vec![false; blocks.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((b, k)) = stack.pop() {
+            match blocks[b].succs.get(k) {
+                Some(&s) => {
+                    stack.push((b, k + 1));
+                    if !visited[s] {
+                        visited[s] = true;
+                        stack.push((s, 0));
+                    }
+                }
+                None => order.push(b),
+            }
+        }
+        order.reverse();
+        let mut rpo = vec![usize::MAX; blocks.len()];
+        for (i, &b) in order.iter().enumerate() {
+            rpo[b] = i;
+        }
+
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in order.iter().skip(1) {
+                let mut new: Option<usize> = None;
+                for &p in blocks[b].preds.iter().filter(|&&p| idom[p].is_some()) {
+                    new = Some(match new {
+                        None => p,
+                        Some(mut x) => {
+                            let mut y = p;
+                            while x != y {
+                                while rpo[x] > rpo[y] {
+                                    x = idom[x].unwrap();
+                                }
+                                while rpo[y] > rpo[x] {
+                                    y = idom[y].unwrap();
+                                }
+                            }
+                            x
+                        }
+                    });
+                }
+                if new.is_some() && idom[b] != new {
+                    idom[b] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        idom
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn jump(op: Opcode, offset: OpIndex) -> InterInst {
+        InterInst::with_op_index(op, Addr::Imm(offset), Addr::Void, Addr::Void)
+    }
+
+    fn cfg(code: &[InterInst]) -> Cfg {
+        Cfg::new(code, &[]).unwrap()
+    }
+
+    fn error(code: &[InterInst]) -> (usize, String) {
+        Cfg::new(code, &[]).unwrap_err()
+    }
+
+    #[test]
+    fn test_switch_table_words() {
+        // the entries jump to #4, then #3 jumps back into them
+        let code = [
+            jump(Opcode::SWITCH_TABLE, 2),
+            jump(Opcode::VOID, 2),
+            jump(Opcode::VOID, 1),
+            jump(Opcode::JUMP, -3),
+            InterInst::with_op(Opcode::RETURN),
+        ];
+        let msg = "#3 jumps to #1, not an instruction".to_owned();
+        assert_eq!(error(&code), (3, msg));
+
+        let msg = "the extra words of #0 are out of the code".to_owned();
+        assert_eq!(error(&code[..2]), (0, msg));
+    }
+
+    #[test]
+    fn test_fall_off_end() {
+        let code = [jump(Opcode::JUMP_IF, -1)];
+        let msg = "#0 falls off the end of the code".to_owned();
+        assert_eq!(error(&code), (0, msg));
+
+        let code = [jump(Opcode::JUMP, -1)];
+        assert_eq!(cfg(&code).blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_loop() {
+        let code = [
+            InterInst::with_op(Opcode::VOID),
+            jump(Opcode::JUMP_IF, 2),
+            InterInst::with_op(Opcode::VOID),
+            jump(Opcode::JUMP, -3),
+            InterInst::with_op(Opcode::RETURN),
+            InterInst::with_op(Opcode::RETURN),
+        ];
+        let cfg = cfg(&code);
+        let bounds: Vec<_> = cfg.blocks.iter().map(|b| (b.begin, b.end)).collect();
+        assert_eq!(bounds, vec![(0, 1), (1, 2), (2, 4), (4, 5), (5, 6)]);
+        assert_eq!(cfg.blocks[1].succs, vec![3, 2]);
+        assert_eq!(cfg.blocks[1].preds, vec![0, 2]);
+        // the exit and the body are dominated by the header, which is by the
+        // entry
+        assert_eq!(cfg.idom, vec![None, Some(0), Some(1), Some(1), None]);
+        assert!(cfg.dominates(1, 2) && cfg.dominates(0, 3));
+        assert!(!cfg.dominates(2, 3));
+        assert!(cfg.reachable(3) && !cfg.reachable(4));
+    }
+
+    #[test]
+    fn test_back_edge_to_non_dominator() {
+        // #2 jumps back to #1, which #0 can skip
+        let code = [
+            jump(Opcode::JUMP_IF, 1),
+            InterInst::with_op(Opcode::VOID),
+            jump(Opcode::JUMP_IF, -2),
+            InterInst::with_op(Opcode::RETURN),
+        ];
+        let cfg = cfg(&code);
+        assert_eq!(cfg.blocks[2].succs, vec![1, 3]);
+        assert_eq!(cfg.idom, vec![None, Some(0), Some(0), Some(2)]);
+        assert!(!cfg.dominates(1, 2));
+        assert!(cfg.dominates(0, 2) && cfg.dominates(2, 3));
+    }
+}
@@ -22,9 +22,12 @@ use std::hash::Hash;
 use std::iter::FromIterator;
 
 macro_rules! func_ctx {
-    ($gen:ident) => {
-        $gen.func_ctx_stack.last_mut().unwrap()
-    };
+    ($gen:ident) => {{
+        let depth = $gen.expr_ctx_stack.last().map(|x| x.cur_reg);
+        let fctx = $gen.func_ctx_stack.last_mut().unwrap();
+        fctx.set_depth(depth);
+        fctx
+    }};
 }
 
 macro_rules! expr_ctx {
@@ -89,7 +92,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         let mode = expr.map_or(&OperandMode::Value, |x| self.t.expr_mode(x));
         match mode {
             OperandMode::TypeExpr => {
-                let tctype = self.t.underlying_tc(self.t.obj_use_tc_type(*ident));
+                // named types based on basic types are not their underlying types
+                let tctype = self.t.obj_use_tc_type(*ident);
                 match self.t.basic_type_meta(tctype, self.vmctx.prim_meta()) {
                     Some(meta) => VirtualAddr::Direct(func_ctx!(self).add_metadata(meta)),
                     None => {
@@ -103,14 +107,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     }
                 }
             }
-            OperandMode::Value => {
-                let id = &self.ast_objs.idents[*ident];
-                match &*id.name {
-                    "true" => VirtualAddr::Direct(func_ctx!(self).add_comparable(true.into())),
-                    "false" => VirtualAddr::Direct(func_ctx!(self).add_comparable(false.into())),
-                    "nil" => VirtualAddr::Direct(Addr::UntypedNil),
-                    _ => self.resolve_var_ident(ident),
-                }
+            // true and false are constants, which never get here
+            OperandMode::Value if self.t.is_nil_ident(*ident) => {
+                VirtualAddr::Direct(Addr::UntypedNil)
             }
             _ => self.resolve_var_ident(ident),
         }
@@ -125,13 +124,14 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         // 2. try upvalue
         let upvalue = self
             .func_ctx_stack
-            .iter()
+            .iter_mut()
             .skip(1) // skip package constructor
             .rev()
             .skip(1) // skip itself
             .find_map(|ctx| {
                 let index = ctx.entity_index(&okey).map(|x| *x);
                 if let Some(ind) = index {
+                    ctx.capture_local(ind.as_var_index());
                     let desc = ValueDesc::new(
                         ctx.f_key,
                         ind.as_var_index() as OpIndex,
@@ -165,13 +165,16 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
         if is_def {
             let tc_obj = self.t.object_def(*ikey);
-            let (index, tc_type, _) = self.add_local_var(tc_obj);
-            let ctx = func_ctx!(self);
-            if ctx.is_ctor(&self.vmctx.functions()) {
-                let pkg_key = self.vmctx.functions()[ctx.f_key].package;
-                let pkg = &mut self.vmctx.packages_mut()[pkg_key];
-                pkg.add_var_mapping(ident.name.clone(), index.as_var_index() as OpIndex);
+            if func_ctx!(self).is_ctor(&self.vmctx.functions()) {
+                // package vars are stored in the members right away, so that
+                // the funcs called by the initializers of the others see them
+                let tc_type = self.t.obj_tc_type(tc_obj);
+                let va = self
+                    .pkg_helper
+                    .get_member_index(func_ctx!(self), tc_obj, *ikey);
+                return (va, Some(tc_type), pos);
             }
+            let (index, tc_type, _) = self.add_local_var(tc_obj);
             (VirtualAddr::Direct(index), Some(tc_type), pos)
         } else {
             let index = self.resolve_var_ident(ikey);
@@ -226,8 +229,13 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         lhs_exprs: &Vec<&Expr>,
         rhs: RightHandSide,
     ) -> Option<usize> {
+        // in a tuple assignment the operands of the index expressions and
+        // pointer indirections on the left are evaluated before any store,
+        // so the ones read from locals are copied, as the stores may change them
+        let tuple = lhs_exprs.len() > 1;
         let lhs = lhs_exprs
             .iter()
+            .map(|expr| Parser::unparen(expr))
             .map(|expr| match expr {
                 Expr::Ident(ident) => {
                     let is_def = self.t.ident_is_def(ident);
@@ -236,9 +244,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 }
                 Expr::Index(ind_expr) => {
                     let obj = &ind_expr.as_ref().expr;
-                    let obj_addr = self.load_mode_call(|g| g.gen_expr(obj));
+                    let mut obj_addr = self.load_mode_call(|g| g.gen_expr(obj));
                     let ind = &ind_expr.as_ref().index;
-                    let ind_addr = match self.t.need_cast_container_index(obj, ind) {
+                    let mut ind_addr = match self.t.need_cast_container_index(obj, ind) {
                         None => self.load_mode_call(|g| g.gen_expr(ind)),
                         Some(t) => {
                             let iface_addr = expr_ctx!(self).inc_cur_reg();
@@ -248,9 +256,20 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                             iface_addr
                         }
                     };
-                    let obj_typ = self.t.expr_value_type(obj);
+                    let mut obj_typ = self.t.expr_value_type(obj);
                     let typ = self.t.expr_tc_type(expr);
                     let pos = ind_expr.as_ref().l_brack;
+                    if obj_typ == ValueType::Pointer {
+                        obj_addr = self.gen_load_pointer(obj_addr, Some(pos));
+                        obj_typ = ValueType::Array // has to be array
+                    }
+                    if tuple {
+                        // an array is a variable, not an operand, stores go to it
+                        if obj_typ != ValueType::Array {
+                            obj_addr = self.copy_if_local(obj_addr, Some(pos));
+                        }
+                        ind_addr = self.copy_if_local(ind_addr, Some(pos));
+                    }
                     let va = match obj_typ {
                         ValueType::Array => VirtualAddr::ArrayEntry(obj_addr, ind_addr),
                         ValueType::Slice => VirtualAddr::SliceEntry(obj_addr, ind_addr),
@@ -268,8 +287,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     match self.t.try_pkg_key(&sexpr.expr) {
                         Some(key) => {
                             let pkg = self.pkg_helper.get_runtime_key(key);
-                            let pkg_addr = func_ctx!(self).add_comparable(FfiCtx::new_package(pkg));
-                            let index_addr = Addr::PkgMemberIndex(pkg, sexpr.sel);
+                            let name = &self.ast_objs.idents[sexpr.sel].name;
+                            let fctx = func_ctx!(self);
+                            let pkg_addr = fctx.add_comparable(FfiCtx::new_package(pkg));
+                            let index_addr = fctx.pkg_member_index(pkg, name);
                             (VirtualAddr::PackageMember(pkg_addr, index_addr), typ, pos)
                         }
                         None => {
@@ -305,7 +326,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 Expr::Star(sexpr) => {
                     let typ = Some(self.t.expr_tc_type(expr));
                     let pos = sexpr.star;
-                    let addr = self.load_mode_call(|g| g.gen_expr(&sexpr.expr));
+                    let mut addr = self.load_mode_call(|g| g.gen_expr(&sexpr.expr));
+                    if tuple {
+                        addr = self.copy_if_local(addr, Some(pos));
+                    }
                     (VirtualAddr::Pointee(addr), typ, pos)
                 }
                 _ => unreachable!(),
@@ -360,6 +384,19 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
     }
 
+    /// Copies a local to a register, so that it keeps the value it has now
+    /// when the local is assigned to.
+    fn copy_if_local(&mut self, addr: Addr, pos: Option<usize>) -> Addr {
+        match addr {
+            Addr::LocalVar(_) => {
+                let reg = expr_ctx!(self).inc_cur_reg();
+                func_ctx!(self).emit_assign(VirtualAddr::Direct(reg), addr, None, pos);
+                reg
+            }
+            _ => addr,
+        }
+    }
+
     fn gen_op_assign(
         &mut self,
         left: &VirtualAddr,
@@ -399,7 +436,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 None
             }
             RightHandSide::Values(values) => {
-                let val0 = &values[0];
+                // (<-ch) is a comma-ok expression as well
+                let val0 = Parser::unparen(&values[0]);
                 let val0_mode = self.t.expr_mode(val0);
                 if values.len() == 1
                     && (val0_mode == &OperandMode::CommaOk || val0_mode == &OperandMode::MapIndex)
@@ -446,18 +484,19 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                             g.gen_expr(&values[0])
                         });
                     } else {
-                        // define or assign with values
-                        // todo: we put the rhs at a temporary register to deal with cases like this: x, y = y, x+y
-                        // but there is probably a better solution to this problem.
-                        let rhs: Vec<(Addr, TCTypeKey)> = values
-                            .iter()
-                            .map(|v| {
-                                (
-                                    self.load_mode_call(|g| g.gen_expr(v)),
-                                    self.t.expr_tc_type(v),
-                                )
-                            })
-                            .collect();
+                        // define or assign with values, all of them are evaluated
+                        // before any store, e.g. x, y = y, x+y
+                        let mut rhs: Vec<(Addr, TCTypeKey)> = Vec::with_capacity(values.len());
+                        for v in values.iter() {
+                            // a nil gets the type of what it's assigned to
+                            let addr = if self.t.is_nil_expr(v) {
+                                Addr::UntypedNil
+                            } else {
+                                let addr = self.load_mode_call(|g| g.gen_expr(v));
+                                self.copy_if_local(addr, Some(v.pos(&self.ast_objs)))
+                            };
+                            rhs.push((addr, self.t.expr_tc_type(v)));
+                        }
                         for (i, l) in lhs.iter().enumerate() {
                             self.store_mode_call(l.0.clone(), l.1, |g| {
                                 g.cur_expr_emit_direct_assign(rhs[i].1, rhs[i].0, Some(l.2))
@@ -471,35 +510,51 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     // now assgin the return values
                     let reg_begin = expr_ctx!(self).cur_reg;
                     let types = self.t.expr_tuple_tc_types(val0);
+                    // the values are kept in the registers until they are all stored
+                    expr_ctx!(self).cur_reg = reg_begin + types.len();
                     for (i, l) in lhs.iter().enumerate() {
                         self.store_mode_call(l.0.clone(), l.1, |g| {
                             g.cur_expr_emit_direct_assign(
                                 types[i],
-                                Addr::Regsiter(reg_begin + i),
+                                Addr::Register(reg_begin + i),
                                 Some(l.2),
                             );
                         });
                     }
+                    expr_ctx!(self).cur_reg = reg_begin;
                 }
                 None
             }
             RightHandSide::Range(r) => {
                 // the range statement
-                let right_addr = self.load_mode_call(|g| g.gen_expr(r));
+                let mut right_addr = self.load_mode_call(|g| g.gen_expr(r));
                 let tkv = self.t.expr_range_tc_types(r);
-                let types = [
+                let mut types = [
                     Some(self.t.tc_type_to_value_type(tkv[0])),
                     //Some(self.t.tc_type_to_value_type(tkv[1])),
                     Some(self.t.tc_type_to_value_type(tkv[2])),
                 ];
                 let pos = Some(r.pos(&self.ast_objs));
+                if self.t.expr_value_type(r) == ValueType::Pointer {
+                    if lhs[1].0.is_blank() {
+                        // only the indices are needed, the pointer can be nil
+                        right_addr = self.add_zero_val(tkv[0]);
+                    } else {
+                        // the array is not copied, the loop sees the changes made to it
+                        let array_addr = self.gen_load_pointer(right_addr, pos);
+                        right_addr = self.gen_slice_whole_array(array_addr, types[1], pos);
+                        types[0] = Some(ValueType::Slice);
+                    }
+                }
+                // the iters are kept by the VM by the depth of the loop
+                let depth = Addr::Imm(func_ctx!(self).range_depth);
                 let init_inst = InterInst::with_op_t_index(
                     Opcode::RANGE_INIT,
                     types[0],
                     types[1],
                     Addr::Void,
                     right_addr,
-                    Addr::Void,
+                    depth,
                 );
                 func_ctx!(self).emit_inst(init_inst, pos);
                 let range_marker = func_ctx!(self).next_code_index();
@@ -514,7 +569,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     &mut ectx_ex,
                     Opcode::RANGE,
                     Addr::Imm(0), // the block_end address, to be set
-                    Addr::Void,
+                    depth,
                     tkv[0],
                     Some(tkv[2]),
                     types[0],
@@ -526,13 +581,22 @@ impl<'a, 'c> CodeGen<'a, 'c> {
 
                 Some(range_marker)
             }
+            RightHandSide::YieldParams(params) => {
+                // the keys of a range over a function with fewer of them are blank
+                for (l, (addr, t)) in lhs.iter().zip(params.iter()) {
+                    self.store_mode_call(l.0.clone(), l.1, |g| {
+                        g.cur_expr_emit_direct_assign(*t, *addr, Some(l.2))
+                    });
+                }
+                None
+            }
             // For Select, the result is already in registers
             RightHandSide::SelectRecv(addr, ok) => {
                 let l = &lhs[0];
                 func_ctx!(self).emit_assign(l.0.clone(), *addr, None, Some(l.2));
                 if *ok {
                     let l = &lhs[1];
-                    let reg = Addr::Regsiter(addr.as_reg_index() + 1);
+                    let reg = Addr::Register(addr.as_reg_index() + 1);
                     func_ctx!(self).emit_assign(l.0.clone(), reg, None, Some(l.2));
                 }
                 None
@@ -545,43 +609,75 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         body: &BlockStmt,
         tag_addr: Addr,
         tag_type: ValueType,
+        tag_tc_type: Option<TCTypeKey>,
         type_switch_local_vars: Option<(Addr, Addr, Vec<Addr>, Option<Pos>)>,
     ) {
         let mut helper = SwitchHelper::new();
         let mut has_default = false;
-        for (i, stmt) in body.list.iter().enumerate() {
+        for stmt in body.list.iter() {
             helper.add_case_clause();
-            let cc = SwitchHelper::to_case_clause(stmt);
-            match &cc.list {
-                Some(l) => {
-                    for c in l.iter() {
-                        let pos = Some(stmt.pos(&self.ast_objs));
-                        let addr = self.load_mode_call(|g| g.gen_expr(c));
-                        let fctx = func_ctx!(self);
-                        helper.tags.add_case(i, fctx.next_code_index());
-                        fctx.emit_inst(
-                            InterInst::with_op_t_index(
-                                Opcode::SWITCH,
-                                Some(tag_type),
-                                None,
-                                Addr::Void,
-                                tag_addr,
-                                addr,
-                            ),
-                            pos,
-                        );
-                    }
-                }
-                None => has_default = true,
-            }
+            has_default |= SwitchHelper::to_case_clause(stmt).list.is_none();
         }
 
+        let keys = self.switch_table_keys(body, tag_type);
+        let pos = body.list.first().map(|x| x.pos(&self.ast_objs));
         let fctx = func_ctx!(self);
-        helper.tags.add_default(fctx.next_code_index());
-        fctx.emit_inst(InterInst::with_op(Opcode::JUMP), None);
+        let by_table = keys.map_or(false, |k| {
+            helper.emit_table(fctx, tag_addr, tag_type, k, pos)
+        });
+        if !by_table {
+            for (i, stmt) in body.list.iter().enumerate() {
+                let cc = SwitchHelper::to_case_clause(stmt);
+                for c in cc.list.iter().flatten() {
+                    let pos = Some(stmt.pos(&self.ast_objs));
+                    let addr = match tag_tc_type {
+                        // the case values are compared as values of the interface type
+                        Some(t) if tag_type == ValueType::Interface => {
+                            let addr = expr_ctx!(self).inc_cur_reg();
+                            let va = VirtualAddr::Direct(addr);
+                            self.store_mode_call(va, Some(t), |g| g.gen_expr(c));
+                            addr
+                        }
+                        _ if tag_type == ValueType::Metadata && self.t.is_nil_expr(c) => {
+                            let none = self.vmctx.prim_meta().none;
+                            func_ctx!(self).add_metadata(none)
+                        }
+                        _ => self.load_mode_call(|g| g.gen_expr(c)),
+                    };
+                    let fctx = func_ctx!(self);
+                    helper.tags.add_case(i, fctx.next_code_index());
+                    fctx.emit_inst(
+                        InterInst::with_op_t_index(
+                            Opcode::SWITCH,
+                            Some(tag_type),
+                            None,
+                            Addr::Void,
+                            tag_addr,
+                            addr,
+                        ),
+                        pos,
+                    );
+                }
+            }
+
+            let fctx = func_ctx!(self);
+            helper.tags.add_default(fctx.next_code_index());
+            fctx.emit_inst(InterInst::with_op(Opcode::JUMP), None);
+        }
 
         for (i, stmt) in body.list.iter().enumerate() {
             let cc = SwitchHelper::to_case_clause(stmt);
+            // A case listing exactly one interface type needs the value converted to that interface
+            let iface_case = match (&type_switch_local_vars, &cc.list) {
+                (Some(_), Some(l)) if l.len() == 1 => {
+                    let tc_type = self.t.expr_tc_type(&l[0]);
+                    (self.t.obj_underlying_value_type(tc_type) == ValueType::Interface).then(|| {
+                        let meta = self.t.tc_type_to_meta(tc_type, self.vmctx);
+                        func_ctx!(self).add_comparable(FfiCtx::new_metadata(meta))
+                    })
+                }
+                _ => None,
+            };
             let fctx = func_ctx!(self);
             let default = cc.list.is_none();
             if default {
@@ -592,16 +688,29 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             if let Some((val_src, iface_src, ref dsts, p)) = type_switch_local_vars {
                 // Specs: In clauses with a case listing exactly one type, the variable has that type; otherwise,
                 // the variable has the type of the expression in the TypeSwitchGuard.
-                let src = if default { iface_src } else { val_src };
-                fctx.emit_inst(
-                    InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], src, Addr::Void),
-                    p,
-                );
+                let inst = match iface_case {
+                    Some(meta_addr) => InterInst::with_op_t_index(
+                        Opcode::TYPE_ASSERT,
+                        None,
+                        Some(ValueType::FlagA),
+                        dsts[i],
+                        iface_src,
+                        meta_addr,
+                    ),
+                    None => {
+                        let src = match &cc.list {
+                            Some(l) if l.len() == 1 && !self.t.is_nil_expr(&l[0]) => val_src,
+                            _ => iface_src,
+                        };
+                        InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], src, Addr::Void)
+                    }
+                };
+                fctx.emit_inst(inst, p);
             }
             for s in cc.body.iter() {
                 self.visit_stmt(s);
             }
-            if !SwitchHelper::has_fall_through(stmt) {
+            if !SwitchHelper::has_fall_through(stmt, &self.ast_objs) {
                 let fctx = func_ctx!(self);
                 if default {
                     helper.ends.add_default(fctx.next_code_index());
@@ -620,6 +729,51 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
     }
 
+    /// Returns the case values of a switch with an integer or string tag, if
+    /// they are all constants, for `SwitchHelper::emit_table`.
+    fn switch_table_keys(
+        &mut self,
+        body: &BlockStmt,
+        tag_type: ValueType,
+    ) -> Option<Vec<(usize, SwitchKey)>> {
+        let is_int = matches!(
+            tag_type,
+            ValueType::Int
+                | ValueType::Int8
+                | ValueType::Int16
+                | ValueType::Int32
+                | ValueType::Int64
+                | ValueType::Uint
+                | ValueType::Uint8
+                | ValueType::Uint16
+                | ValueType::Uint32
+                | ValueType::Uint64
+        );
+        if !is_int && tag_type != ValueType::String {
+            return None;
+        }
+        let mut keys = vec![];
+        for (i, stmt) in body.list.iter().enumerate() {
+            let cc = SwitchHelper::to_case_clause(stmt);
+            for c in cc.list.iter().flatten() {
+                let val = self.t.try_tc_const_value(c.id())?;
+                let key = if is_int {
+                    let val = val.to_int();
+                    match val.int_as_i64() {
+                        (i, true) => SwitchKey::Int(i),
+                        _ => SwitchKey::Int(val.int_as_u64().0 as i64),
+                    }
+                } else {
+                    let s = val.str_as_bytes().to_vec();
+                    let addr = func_ctx!(self).add_comparable(self.t.const_type_value(c.id()).1);
+                    SwitchKey::Str(s, addr)
+                };
+                keys.push((i, key));
+            }
+        }
+        Some(keys)
+    }
+
     fn gen_func_def(
         &mut self,
         tc_type: TCTypeKey, // Meta,
@@ -652,7 +806,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         func_ctx!(self).emit_return(None, Some(body.r_brace), self.vmctx.functions());
 
         let f = self.func_ctx_stack.pop().unwrap();
-        let cls = CodeGenVMCtx::new_closure_static(fkey, Some(&f.up_ptrs), fmeta);
+        let cls = CodeGenVMCtx::new_closure_static(fkey, Some(&f.ir.up_ptrs), fmeta);
         self.results.push(f);
         (fkey, cls)
     }
@@ -666,15 +820,6 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         ellipsis: bool,
         pos: Option<usize>,
     ) {
-        let slice_op_types = |g: &mut CodeGen| {
-            let t0 = if ellipsis && g.t.expr_value_type(&params[1]) == ValueType::String {
-                ValueType::String
-            } else {
-                ValueType::Slice
-            };
-            let (_, t_elem) = g.t.sliceable_expr_value_types(&params[0], g.vmctx);
-            (t0, g.t.tc_type_to_value_type(t_elem))
-        };
         match builtin {
             Builtin::Make => {
                 let meta_addr = self.load_mode_call(|g| g.gen_expr(&params[0]));
@@ -715,6 +860,51 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     f.emit_inst(inst, p);
                 });
             }
+            Builtin::Min | Builtin::Max => {
+                // the arguments have the type of the result, folded pairwise into a
+                // register, so that the destination is written last
+                let op = match builtin {
+                    Builtin::Min => Opcode::MIN,
+                    _ => Opcode::MAX,
+                };
+                let t = self.t.tc_type_to_value_type(return_types[0]);
+                let addrs: Vec<Addr> = params
+                    .iter()
+                    .map(|x| self.load_mode_call(|g| g.gen_expr(x)))
+                    .collect();
+                let (last, rest) = addrs.split_last().unwrap();
+                let acc = match rest {
+                    [] => *last,
+                    [first] => *first,
+                    [first, others @ ..] => {
+                        let reg = expr_ctx!(self).inc_cur_reg();
+                        let mut acc = *first;
+                        for addr in others.iter() {
+                            let inst =
+                                InterInst::with_op_t_index(op, Some(t), None, reg, acc, *addr);
+                            func_ctx!(self).emit_inst(inst, pos);
+                            acc = reg;
+                        }
+                        acc
+                    }
+                };
+                if rest.is_empty() {
+                    self.cur_expr_emit_direct_assign(return_types[0], acc, pos);
+                } else {
+                    self.cur_expr_emit_assign(return_types[0], pos, |f, d, p| {
+                        let inst = InterInst::with_op_t_index(op, Some(t), None, d, acc, *last);
+                        f.emit_inst(inst, p);
+                    });
+                }
+            }
+            Builtin::Len | Builtin::Cap if self.t.expr_array_len(&params[0]).is_some() => {
+                // not a constant for the calls or receives in the operand, which still run,
+                // the length comes from the type, even with a nil pointer
+                let len = self.t.expr_array_len(&params[0]).unwrap();
+                self.discard_mode_call(|g| g.gen_expr(&params[0]));
+                let addr = func_ctx!(self).add_comparable((len as isize).into());
+                self.cur_expr_emit_direct_assign(return_types[0], addr, pos);
+            }
             Builtin::New
             | Builtin::Real
             | Builtin::Imag
@@ -746,15 +936,15 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 let ft = self.t.try_expr_tc_type(func_expr).unwrap();
                 let init_reg = expr_ctx!(self).cur_reg;
                 self.gen_call_params(ft, params, ellipsis);
-                let types = slice_op_types(self);
+                let types = self.slice_op_types(params, ellipsis);
                 self.cur_expr_emit_assign(return_types[0], pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::APPEND,
                         Some(types.0),
                         Some(types.1),
                         d,
-                        Addr::Regsiter(init_reg),
-                        Addr::Regsiter(init_reg + 1),
+                        Addr::Register(init_reg),
+                        Addr::Register(init_reg + 1),
                     );
                     f.emit_inst(inst, p);
                 });
@@ -763,7 +953,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             Builtin::Copy => {
                 let addr0 = self.load_mode_call(|g| g.gen_expr(&params[0]));
                 let addr1 = self.load_mode_call(|g| g.gen_expr(&params[1]));
-                let types = slice_op_types(self);
+                let types = self.slice_op_types(params, ellipsis);
                 self.cur_expr_emit_assign(return_types[0], pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::COPY,
@@ -776,20 +966,44 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     f.emit_inst(inst, p);
                 });
             }
-            Builtin::Delete | Builtin::Close | Builtin::Panic | Builtin::Assert => {
+            Builtin::Delete => {
+                let map_addr = self.load_mode_call(|g| g.gen_expr(&params[0]));
+                let map_type = self.t.expr_tc_type(&params[0]);
+                let typ = self.tc_objs.types[map_type].underlying_val(&self.tc_objs);
+                // the key is converted to the key type, an interface for example
+                let key_type = typ.try_as_map().unwrap().key();
+                let key_addr = expr_ctx!(self).inc_cur_reg();
+                let va = VirtualAddr::Direct(key_addr);
+                self.store_mode_call(va, Some(key_type), |g| g.gen_expr(&params[1]));
+                let inst = InterInst::with_op_index(Opcode::DELETE, Addr::Void, map_addr, key_addr);
+                func_ctx!(self).emit_inst(inst, pos);
+            }
+            Builtin::Clear => {
+                let addr = self.load_mode_call(|g| g.gen_expr(&params[0]));
+                let meta = self.builtin_clear_meta(&params[0]);
+                let meta_addr = func_ctx!(self).add_metadata(meta);
+                let inst = InterInst::with_op_index(Opcode::CLEAR, Addr::Void, addr, meta_addr);
+                func_ctx!(self).emit_inst(inst, pos);
+            }
+            Builtin::Panic => {
+                // the value is converted to interface{}, so that recover() returns
+                // it with its type
+                let ft = self.t.expr_tc_type(func_expr);
+                let (param_types, _) = self.t.sig_params_tc_types(ft);
+                let addr = expr_ctx!(self).inc_cur_reg();
+                let va = VirtualAddr::Direct(addr);
+                self.store_mode_call(va, Some(param_types[0]), |g| g.gen_expr(&params[0]));
+                let inst = InterInst::with_op_index(Opcode::PANIC, Addr::Void, addr, Addr::Void);
+                func_ctx!(self).emit_inst(inst, pos);
+            }
+            Builtin::Close | Builtin::Assert => {
                 let addr0 = self.load_mode_call(|g| g.gen_expr(&params[0]));
                 let addr1 = if params.len() > 1 {
                     self.load_mode_call(|g| g.gen_expr(&params[1]))
                 } else {
                     Addr::Void
                 };
-                let op = match builtin {
-                    Builtin::Delete => Opcode::DELETE,
-                    Builtin::Close => Opcode::CLOSE,
-                    Builtin::Panic => Opcode::PANIC,
-                    Builtin::Assert => Opcode::ASSERT,
-                    _ => unreachable!(),
-                };
+                let op = Self::builtin_stmt_opcode(builtin);
                 let inst = InterInst::with_op_index(op, Addr::Void, addr0, addr1);
                 func_ctx!(self).emit_inst(inst, pos);
             }
@@ -804,6 +1018,227 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         };
     }
 
+    /// `defer` and `go` evaluate the arguments right away and make the call later,
+    /// builtins can't do that by themselves so they are wrapped in a function with
+    /// the signature recorded by the checker.
+    fn gen_builtin_thunk(
+        &mut self,
+        func_expr: &Expr,
+        params: &Vec<Expr>,
+        builtin: &Builtin,
+        ellipsis: bool,
+        style: CallStyle,
+        pos: Option<usize>,
+    ) {
+        if *builtin == Builtin::Recover {
+            // a deferred recover() is not called by a deferred function, so it does nothing
+            return;
+        }
+        let ft = self.t.expr_tc_type(func_expr);
+        let return_types = self.t.sig_returns_tc_types(ft);
+        let fmeta = self.t.tc_type_to_meta(ft, &mut self.vmctx);
+        let f = self
+            .vmctx
+            .function_with_meta(Some(self.pkg_key), fmeta, FuncFlag::Default);
+        let fkey = *f.as_function();
+        let mut fctx = FuncCtx::new(fkey, Some(ft), self.consts);
+        let ret = match return_types.is_empty() {
+            true => Addr::Void,
+            false => fctx.add_local(None, None),
+        };
+        let args: Vec<Addr> = params.iter().map(|_| fctx.add_local(None, None)).collect();
+        let arg = |i: usize| args.get(i).copied().unwrap_or(Addr::Void);
+        let inst = match builtin {
+            Builtin::Copy => {
+                let types = self.slice_op_types(params, ellipsis);
+                InterInst::with_op_t_index(
+                    Opcode::COPY,
+                    Some(types.0),
+                    Some(types.1),
+                    ret,
+                    arg(0),
+                    arg(1),
+                )
+            }
+            Builtin::Clear => {
+                let meta = self.builtin_clear_meta(&params[0]);
+                InterInst::with_op_index(Opcode::CLEAR, Addr::Void, arg(0), fctx.add_metadata(meta))
+            }
+            _ => {
+                let op = Self::builtin_stmt_opcode(builtin);
+                InterInst::with_op_index(op, Addr::Void, arg(0), arg(1))
+            }
+        };
+        fctx.emit_inst(inst, pos);
+        fctx.emit_return(None, pos, self.vmctx.functions());
+        self.results.push(fctx);
+
+        let cls = func_ctx!(self).add_comparable(FfiCtx::new_function(fkey));
+        let next_sb = expr_ctx!(self).cur_reg;
+        expr_ctx!(self).cur_reg = next_sb + return_types.len();
+        self.gen_call_params(ft, params, ellipsis);
+        let func_addr = expr_ctx!(self).inc_cur_reg();
+        let fctx = func_ctx!(self);
+        fctx.emit_closure(func_addr, cls, pos);
+        fctx.emit_call(func_addr, next_sb, false, style, pos);
+    }
+
+    /// A method expression `T.m` is the function of the method, if it's
+    /// declared with the receiver `T`. Otherwise it's a thunk, which binds the
+    /// method to its first argument, as a method value does, and calls it with
+    /// the others.
+    fn gen_method_expr(
+        &mut self,
+        recv_type: TCTypeKey,
+        ft: TCTypeKey,
+        indices: &[usize],
+        ptr_recv: bool,
+        pos: Option<usize>,
+    ) {
+        let recv_meta = self.t.tc_type_to_meta(recv_type, self.vmctx);
+        let final_index = indices[indices.len() - 1];
+        if indices.len() == 1
+            && !recv_meta.is_interface(self.vmctx.metas())
+            && (recv_meta.ptr_depth > 0) == ptr_recv
+        {
+            self.cur_expr_emit_assign(ft, pos, |f, d, p| {
+                let addr = f.add_method(recv_meta, final_index);
+                f.emit_closure(d, addr, p);
+            });
+            return;
+        }
+
+        let return_count = self.t.sig_returns_tc_types(ft).len();
+        let param_count = self.t.sig_params_tc_types(ft).0.len();
+        let fmeta = self.t.tc_type_to_meta(ft, self.vmctx);
+        let f = self
+            .vmctx
+            .function_with_meta(Some(self.pkg_key), fmeta, FuncFlag::Default);
+        let fkey = *f.as_function();
+        let mut fctx = FuncCtx::new(fkey, Some(ft), self.consts);
+        for _ in 0..return_count {
+            fctx.add_local(None, None);
+        }
+        let recv = fctx.add_local(None, None);
+        let args: Vec<Addr> = (1..param_count)
+            .map(|_| fctx.add_local(None, None))
+            .collect();
+        self.func_ctx_stack.push(fctx);
+        self.push_expr_ctx(ExprMode::Discard, 0);
+
+        let cls = self.gen_bind_method(recv, recv_meta, indices, ptr_recv, pos);
+        let next_sb = expr_ctx!(self).cur_reg;
+        expr_ctx!(self).cur_reg = next_sb + return_count;
+        for arg in args.into_iter() {
+            let reg = expr_ctx!(self).inc_cur_reg();
+            func_ctx!(self).emit_assign(VirtualAddr::Direct(reg), arg, None, pos);
+        }
+        let fctx = func_ctx!(self);
+        fctx.emit_call(cls, next_sb, false, CallStyle::Default, pos);
+        for i in 0..return_count {
+            let ret = VirtualAddr::Direct(Addr::LocalVar(i));
+            fctx.emit_assign(ret, Addr::Register(next_sb + i), None, pos);
+        }
+        fctx.emit_return(None, pos, self.vmctx.functions());
+
+        self.pop_expr_ctx();
+        let f = self.func_ctx_stack.pop().unwrap();
+        self.results.push(f);
+        let cls = func_ctx!(self).add_comparable(FfiCtx::new_function(fkey));
+        self.cur_expr_emit_assign(ft, pos, |f, d, p| f.emit_closure(d, cls, p));
+    }
+
+    /// Binds the method at `indices` to the receiver in `recv`, of the type of
+    /// `recv_meta`, following the embedded fields, and returns the register of
+    /// the closure.
+    fn gen_bind_method(
+        &mut self,
+        recv: Addr,
+        recv_meta: Meta,
+        indices: &[usize],
+        ptr_recv: bool,
+        pos: Option<usize>,
+    ) -> Addr {
+        let (embedded, final_index) = indices.split_at(indices.len() - 1);
+        let mut addr = recv;
+        let mut meta = recv_meta;
+        let mut method_meta = recv_meta;
+        if !embedded.is_empty() {
+            method_meta = self.get_field_meta(&recv_meta, embedded);
+            let by_ref = ptr_recv
+                && method_meta.ptr_depth == 0
+                && !method_meta.is_interface(self.vmctx.metas());
+            let default_op = match by_ref {
+                true => Opcode::REF_STRUCT_FIELD,
+                false => Opcode::LOAD_STRUCT,
+            };
+            let rt_indices = embedded.iter().map(|x| *x as OpIndex).collect();
+            let (op, index) = self.get_struct_field_op_index(rt_indices, default_op);
+            if recv_meta.ptr_depth > 0 {
+                addr = self.gen_load_pointer(addr, pos);
+            }
+            let field = expr_ctx!(self).inc_cur_reg();
+            let inst = InterInst::with_op_index(op, field, addr, Addr::Imm(index));
+            func_ctx!(self).emit_inst(inst, pos);
+            addr = field;
+            meta = match by_ref {
+                true => method_meta.ptr_to(),
+                false => method_meta,
+            };
+        }
+        let is_iface = method_meta.is_interface(self.vmctx.metas());
+        if meta.ptr_depth > 0 && !ptr_recv && !is_iface {
+            addr = self.gen_load_pointer(addr, pos);
+        }
+
+        let cls = expr_ctx!(self).inc_cur_reg();
+        let fctx = func_ctx!(self);
+        let inst = match is_iface {
+            true => InterInst::with_op_index(
+                Opcode::BIND_I_METHOD,
+                cls,
+                addr,
+                Addr::Imm(final_index[0] as OpIndex),
+            ),
+            false => InterInst::with_op_index(
+                Opcode::BIND_METHOD,
+                cls,
+                addr,
+                fctx.add_method(method_meta, final_index[0]),
+            ),
+        };
+        fctx.emit_inst(inst, pos);
+        cls
+    }
+
+    /// The meta of the map or the slice cleared by `clear`, which has the zero
+    /// value of the elements of a slice.
+    fn builtin_clear_meta(&mut self, param: &Expr) -> Meta {
+        let tct = self.t.expr_tc_type(param);
+        self.t.tc_type_to_meta(tct, &mut self.vmctx)
+    }
+
+    /// The opcode of a builtin that can only be called as a statement.
+    fn builtin_stmt_opcode(builtin: &Builtin) -> Opcode {
+        match builtin {
+            Builtin::Delete => Opcode::DELETE,
+            Builtin::Close => Opcode::CLOSE,
+            Builtin::Panic => Opcode::PANIC,
+            Builtin::Assert => Opcode::ASSERT,
+            _ => unreachable!(),
+        }
+    }
+
+    fn slice_op_types(&mut self, params: &Vec<Expr>, ellipsis: bool) -> (ValueType, ValueType) {
+        let t0 = if ellipsis && self.t.expr_value_type(&params[1]) == ValueType::String {
+            ValueType::String
+        } else {
+            ValueType::Slice
+        };
+        let (_, t_elem) = self.t.sliceable_expr_value_types(&params[0], self.vmctx);
+        (t0, self.t.tc_type_to_value_type(t_elem))
+    }
+
     fn gen_conversion(&mut self, to: &Expr, from: &Expr, pos: Option<usize>) {
         // conversion
         // from the specs:
@@ -842,7 +1277,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             match typ_to {
                 ValueType::Interface => {
                     if typ_from != ValueType::Void {
-                        let iface_index = self.iface_selector.add((n_tc_to, n_tc_from));
+                        let iface_index = self.iface_index(n_tc_to, n_tc_from);
                         self.cur_expr_emit_assign(n_tc_to, pos, |f, d, p| {
                             f.emit_cast_iface(d, from_addr, iface_index, p);
                         });
@@ -882,7 +1317,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     };
                     let t2 = t_extra.map(|x| self.t.tc_type_to_value_type(x));
 
-                    self.cur_expr_emit_assign(tc_to, pos, |f, d, p| {
+                    // the named type, if it's assigned to an interface it has the methods
+                    self.cur_expr_emit_assign(n_tc_to, pos, |f, d, p| {
                         f.emit_cast(d, from_addr, Addr::Void, typ_to, Some(typ_from), t2, p);
                     });
                     converted = true;
@@ -911,6 +1347,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
 
         match *self.t.expr_mode(func_expr) {
             // built in function
+            OperandMode::Builtin(builtin) if style != CallStyle::Default => {
+                self.gen_builtin_thunk(func_expr, params, &builtin, ellipsis, style, pos);
+            }
             OperandMode::Builtin(builtin) => {
                 let return_types = self.t.sig_returns_tc_types(ft);
                 self.gen_builtin_call(func_expr, params, &builtin, &return_types, ellipsis, pos);
@@ -925,17 +1364,17 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 let next_sb = expr_ctx!(self).cur_reg;
                 // make sure params are at the right place
                 let return_types = self.t.sig_returns_tc_types(ft);
-                let reg_usage =
-                    return_types.len() + if self.t.is_method(func_expr) { 1 } else { 0 };
+                let is_method = self.t.is_method(func_expr);
+                let reg_usage = return_types.len() + if is_method { 1 } else { 0 };
                 expr_ctx!(self).cur_reg = next_sb + reg_usage;
                 self.gen_call_params(ft, params, ellipsis);
                 let func_addr = self.load_mode_call(|g| g.gen_expr(func_expr));
-                func_ctx!(self).emit_call(func_addr, next_sb, style, pos);
+                func_ctx!(self).emit_call(func_addr, next_sb, is_method, style, pos);
 
                 if !return_types.is_empty() {
                     // assgin the first return value
                     // the cases of returning multiple values are handled elsewhere
-                    self.cur_expr_emit_direct_assign(return_types[0], Addr::Regsiter(next_sb), pos);
+                    self.cur_expr_emit_direct_assign(return_types[0], Addr::Register(next_sb), pos);
                 }
             }
         }
@@ -962,11 +1401,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             if let Some(t) = variadic {
                 let variadic_count = params.len() - non_variadic_count;
                 let variadic_begin_reg = init_reg + non_variadic_count;
-                let pos =
-                    (!params.is_empty()).then(|| params[non_variadic_count].pos(&self.ast_objs));
+                let pos = params
+                    .get(non_variadic_count)
+                    .map(|e| e.pos(&self.ast_objs));
                 let t_elem = self.t.tc_type_to_value_type(t);
-                let begin = Addr::Regsiter(variadic_begin_reg);
-                let end = Addr::Regsiter(variadic_begin_reg + variadic_count);
+                let begin = Addr::Register(variadic_begin_reg);
+                let end = Addr::Register(variadic_begin_reg + variadic_count);
                 let inst = InterInst::with_op_t_index(
                     Opcode::PACK_VARIADIC,
                     Some(t_elem),
@@ -982,6 +1422,21 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
     }
 
+    /// The operands of a select are evaluated once before choosing, a local
+    /// variable is copied in case the operands of a later case change it.
+    fn gen_select_operand(&mut self, e: &Expr) -> Addr {
+        let addr = self.load_mode_call(|g| g.gen_expr(e));
+        match addr {
+            Addr::LocalVar(_) => {
+                let reg = expr_ctx!(self).inc_cur_reg();
+                let inst = InterInst::with_op_index(Opcode::DUPLICATE, reg, addr, Addr::Void);
+                func_ctx!(self).emit_inst(inst, Some(e.pos(&self.ast_objs)));
+                reg
+            }
+            _ => addr,
+        }
+    }
+
     fn gen_expr_recv(
         &mut self,
         channel: &Expr,
@@ -1069,7 +1524,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     ValueType::String => (Opcode::LOAD_SLICE, ValueType::Void),
                     _ => unreachable!(),
                 };
-                self.cur_expr_emit_assign(val_tc_type, pos, |f, d, p| {
+                self.cur_expr_emit_load(val_tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         op,
                         None,
@@ -1143,6 +1598,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         let entity_key = self.t.object_use(*ikey);
                         let fctx = func_ctx!(self);
                         let ind = *fctx.entity_index(&entity_key).unwrap();
+                        fctx.capture_local(ind.as_var_index());
                         let desc =
                             ValueDesc::new(fctx.f_key, ind.as_var_index() as OpIndex, t, true);
                         // for package ctors, all locals are "closed"
@@ -1185,9 +1641,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             Expr::Index(iexpr) => {
                 let (t0, _) = self.t.sliceable_expr_value_types(&iexpr.expr, self.vmctx);
                 let t1 = self.t.expr_value_type(&iexpr.index);
-                let lhs_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.expr));
-                let index_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.index));
+                let mut lhs_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.expr));
                 let pos = Some(iexpr.index.pos(&self.ast_objs));
+                if self.t.expr_value_type(&iexpr.expr) == ValueType::Pointer {
+                    lhs_addr = self.gen_load_pointer(lhs_addr, pos);
+                }
+                let index_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.index));
                 self.cur_expr_emit_assign(ref_tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::REF_SLICE_MEMBER,
@@ -1203,8 +1662,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             Expr::Selector(sexpr) => match self.t.try_pkg_key(&sexpr.expr) {
                 Some(key) => {
                     let pkey = self.pkg_helper.get_runtime_key(key);
-                    let pkg_addr = func_ctx!(self).add_package(pkey);
-                    let index = Addr::PkgMemberIndex(pkey, sexpr.sel);
+                    let name = &self.ast_objs.idents[sexpr.sel].name;
+                    let fctx = func_ctx!(self);
+                    let pkg_addr = fctx.add_package(pkey);
+                    let index = fctx.pkg_member_index(pkey, name);
                     self.cur_expr_emit_assign(ref_tc_type, pos, |f, d, p| {
                         let inst =
                             InterInst::with_op_index(Opcode::REF_PKG_MEMBER, d, pkg_addr, index);
@@ -1235,6 +1696,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     f.emit_inst(inst, p);
                 });
             }
+            Expr::Paren(p) => self.gen_expr_ref(&p.expr, ref_tc_type),
+            // &*p is p
+            Expr::Star(s) => self.gen_expr(&s.expr),
             _ => {
                 dbg!(&expr);
                 unimplemented!()
@@ -1278,8 +1742,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         }
                         _ => (-1, expr),
                     };
-                    let fctx = func_ctx!(self);
                     let key_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
+                    let fctx = func_ctx!(self);
                     let index_addr = fctx.add_comparable((key as i32).into());
                     fctx.emit_assign(key_reg, index_addr, None, pos);
                     let elem_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
@@ -1319,8 +1783,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         }
                         _ => (i, expr),
                     };
-                    let fctx = func_ctx!(self);
                     let key_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
+                    let fctx = func_ctx!(self);
                     let index_addr = fctx.add_comparable(index.into());
                     fctx.emit_assign(key_reg, index_addr, None, pos);
                     let elem_reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
@@ -1340,7 +1804,13 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
             f.emit_literal(d, reg_base, count, meta_addr, p);
         });
-        expr_ctx!(self).cur_reg = reg_base + 1; //reset register allocation
+        // reset register allocation, the literal loaded to a register after
+        // the ones of the elements keeps it, until the expression is done
+        let ectx = expr_ctx!(self);
+        ectx.cur_reg = match (&ectx.mode, ectx.load_addr) {
+            (ExprMode::Load, Addr::Register(r)) => r + 1,
+            _ => reg_base + 1,
+        };
     }
 
     fn gen_load_pointer(&mut self, ptr: Addr, pos: Option<usize>) -> Addr {
@@ -1350,6 +1820,329 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         addr
     }
 
+    /// A range over a function calls it with the body wrapped in a yield function,
+    /// which returns false when the loop is left by break or return.
+    /// A return stores the results of the function the loop is in and sets its
+    /// flag, so that it returns right after the call as well.
+    /// A break, continue or goto out of the body sets the exit code of the
+    /// function the loop is in to the number of the branch, which is taken after
+    /// the call, leaving the body the loop is in the same way if need be.
+    /// A defer is for the function the loop is in.
+    /// Calling the yield function again after it returned false panics.
+    fn gen_range_func(&mut self, rstmt: &RangeStmt) {
+        self.branch_helper.enter_block(true);
+        let pos = Some(rstmt.token_pos);
+        let done = func_ctx!(self).add_local(None, Some(false.into()));
+        let seq_type = self.t.expr_tc_type(&rstmt.expr);
+        let yield_type = self.t.sig_params_tc_types(seq_type).0[0];
+        let (param_types, _) = self.t.sig_params_tc_types(yield_type);
+        let meta = self.t.tc_type_to_meta(yield_type, &mut self.vmctx);
+        let f = self
+            .vmctx
+            .function_with_meta(Some(self.pkg_key), meta, FuncFlag::Default);
+        let fkey = *f.as_function();
+        let mut fctx = FuncCtx::new(fkey, Some(yield_type), self.consts);
+        fctx.range_body = true;
+        fctx.range_span = Some((rstmt.body.l_brace, rstmt.body.r_brace));
+        fctx.add_local(None, None); // the result
+        let params: Vec<(Addr, TCTypeKey)> = param_types
+            .iter()
+            .map(|t| (fctx.add_local(None, None), *t))
+            .collect();
+        let pctx = func_ctx!(self);
+        fctx.range_level = pctx.range_level + 1;
+        pctx.capture_local(done.as_var_index());
+        let desc = ValueDesc::new(
+            pctx.f_key,
+            done.as_var_index() as OpIndex,
+            ValueType::Bool,
+            false,
+        );
+        fctx.range_done = Some(fctx.add_upvalue_desc(desc));
+        self.func_ctx_stack.push(fctx);
+        self.gen_yield_check_done(pos);
+
+        let blank = Expr::Ident(self.blank_ident);
+        let lhs = vec![
+            rstmt.key.as_ref().unwrap_or(&blank),
+            rstmt.val.as_ref().unwrap_or(&blank),
+        ];
+        self.gen_assign(&rstmt.token, &lhs, RightHandSide::YieldParams(&params));
+        self.visit_stmt_block(&rstmt.body);
+        let continue_marker = func_ctx!(self).next_code_index();
+        self.gen_yield_return(true, pos);
+        let exits = self
+            .branch_helper
+            .leave_range_func_block(func_ctx!(self), continue_marker);
+        func_ctx!(self).range_exits.extend(exits);
+        self.gen_yield_return(false, pos);
+        let exits = std::mem::take(&mut func_ctx!(self).range_exits);
+        if !exits.is_empty() {
+            let code = self.range_exit_code();
+            for (i, (index, _, _)) in exits.iter().enumerate() {
+                let fctx = func_ctx!(self);
+                fctx.inst_mut(*index).d = fctx.label_at(fctx.next_code_index());
+                let addr = fctx.add_comparable((i as isize + 1).into());
+                fctx.emit_assign(code.clone(), addr, None, pos);
+                self.gen_yield_return(false, pos);
+            }
+        }
+        let f = self.func_ctx_stack.pop().unwrap();
+        self.results.push(f);
+
+        // the function is called with the yield function, the flags are cleared
+        // before, as the locals are set where they are declared
+        let fctx = func_ctx!(self);
+        let false_addr = fctx.add_comparable(false.into());
+        fctx.emit_assign(VirtualAddr::Direct(done), false_addr, None, pos);
+        if let (false, Some(flag)) = (fctx.range_body, fctx.range_return) {
+            fctx.emit_assign(VirtualAddr::Direct(flag), false_addr, None, pos);
+        }
+        if !exits.is_empty() {
+            let code = self.range_exit_code();
+            let fctx = func_ctx!(self);
+            let zero = fctx.add_comparable(0isize.into());
+            fctx.emit_assign(code, zero, None, pos);
+        }
+        let cls = func_ctx!(self).add_comparable(FfiCtx::new_function(fkey));
+        let next_sb = expr_ctx!(self).cur_reg;
+        let yield_addr = expr_ctx!(self).inc_cur_reg();
+        func_ctx!(self).emit_closure(yield_addr, cls, pos);
+        let seq_addr = self.load_mode_call(|g| g.gen_expr(&rstmt.expr));
+        func_ctx!(self).emit_call(seq_addr, next_sb, false, CallStyle::Default, pos);
+
+        // a return in the body, this one or a nested one, leaves here as well
+        let owner = self.range_owner();
+        if self.func_ctx_stack[owner].range_return.is_some() {
+            let flag = self.range_return_flag();
+            let flag = self.load_range_owner_local(flag, pos);
+            let fctx = func_ctx!(self);
+            let go_on = fctx.new_label();
+            fctx.emit_inst(
+                InterInst::with_op_index(Opcode::JUMP_IF_NOT, Addr::Label(go_on), flag, Addr::Void),
+                pos,
+            );
+            if fctx.range_body {
+                self.gen_yield_return(false, pos);
+            } else {
+                fctx.emit_return(None, pos, &self.vmctx.functions());
+            }
+            let fctx = func_ctx!(self);
+            fctx.bind_label(go_on, fctx.next_code_index());
+        }
+        if !exits.is_empty() {
+            let code = self.range_exit_code();
+            let code = self.load_range_owner_local(code, pos);
+            let fctx = func_ctx!(self);
+            let labels: Vec<usize> = exits.iter().map(|_| fctx.new_label()).collect();
+            for (i, l) in labels.iter().enumerate() {
+                let addr = fctx.add_comparable((i as isize + 1).into());
+                fctx.emit_inst(
+                    InterInst::with_op_t_index(
+                        Opcode::SWITCH,
+                        Some(ValueType::Int),
+                        None,
+                        Addr::Label(*l),
+                        code,
+                        addr,
+                    ),
+                    pos,
+                );
+            }
+            let end = fctx.new_label();
+            fctx.emit_inst(
+                InterInst::with_op_index(Opcode::JUMP, Addr::Label(end), Addr::Void, Addr::Void),
+                pos,
+            );
+            for ((_, token, label), l) in exits.into_iter().zip(labels) {
+                let fctx = func_ctx!(self);
+                fctx.bind_label(l, fctx.next_code_index());
+                self.gen_branch(token, Some(label), rstmt.token_pos);
+            }
+            let fctx = func_ctx!(self);
+            fctx.bind_label(end, fctx.next_code_index());
+        }
+        expr_ctx!(self).cur_reg = next_sb;
+    }
+
+    /// Panics if the yield function of a range over a function is called after
+    /// it returned false.
+    fn gen_yield_check_done(&mut self, pos: Option<usize>) {
+        let reg = expr_ctx!(self).inc_cur_reg();
+        let fctx = func_ctx!(self);
+        let uv = fctx.range_done.clone().unwrap().as_up_value_addr();
+        let inst = InterInst::with_op_index(Opcode::LOAD_UP_VALUE, reg, uv, Addr::Void);
+        fctx.emit_inst(inst, pos);
+        let go_on = fctx.new_label();
+        fctx.emit_inst(
+            InterInst::with_op_index(Opcode::JUMP_IF_NOT, Addr::Label(go_on), reg, Addr::Void),
+            pos,
+        );
+        let msg = "range function continued iteration after function for loop body returned false";
+        let msg = fctx.add_comparable(msg.to_owned().into());
+        let inst = InterInst::with_op_index(Opcode::PANIC, Addr::Void, msg, Addr::Void);
+        fctx.emit_inst(inst, pos);
+        fctx.bind_label(go_on, fctx.next_code_index());
+        expr_ctx!(self).cur_reg -= 1;
+    }
+
+    /// Returns from the yield function of a range over a function, true to go on.
+    fn gen_yield_return(&mut self, go_on: bool, pos: Option<usize>) {
+        let fctx = func_ctx!(self);
+        if !go_on {
+            let done = fctx.range_done.clone().unwrap();
+            let true_addr = fctx.add_comparable(true.into());
+            fctx.emit_assign(done, true_addr, None, pos);
+        }
+        let result = fctx.add_comparable(go_on.into());
+        fctx.emit_assign(VirtualAddr::Direct(Addr::LocalVar(0)), result, None, pos);
+        fctx.emit_return(None, pos, &self.vmctx.functions());
+    }
+
+    /// The index of the function that the body of a range over a function is in,
+    /// or the current one.
+    fn range_owner(&self) -> usize {
+        self.func_ctx_stack
+            .iter()
+            .rposition(|x| !x.range_body)
+            .unwrap()
+    }
+
+    /// A local of the function that the body of a range over a function is in,
+    /// which is an upvalue in the body.
+    fn range_owner_local(&mut self, local: Addr, typ: ValueType) -> VirtualAddr {
+        let owner = self.range_owner();
+        if owner == self.func_ctx_stack.len() - 1 {
+            return VirtualAddr::Direct(local);
+        }
+        let octx = &mut self.func_ctx_stack[owner];
+        octx.capture_local(local.as_var_index());
+        let desc = ValueDesc::new(octx.f_key, local.as_var_index() as OpIndex, typ, false);
+        func_ctx!(self).add_upvalue_desc(desc)
+    }
+
+    /// The flag of the function that the body of a range over a function is in,
+    /// set when the body returns, which is added when it's first needed.
+    fn range_return_flag(&mut self) -> VirtualAddr {
+        let owner = self.range_owner();
+        let octx = &mut self.func_ctx_stack[owner];
+        let flag = match octx.range_return {
+            Some(flag) => flag,
+            None => {
+                let flag = octx.add_local(None, Some(false.into()));
+                octx.range_return = Some(flag);
+                flag
+            }
+        };
+        self.range_owner_local(flag, ValueType::Bool)
+    }
+
+    /// Emits a break, continue or goto, to the statement labeled `label` if any.
+    fn gen_branch(&mut self, token: Token, label: Option<TCObjKey>, pos: usize) {
+        match token {
+            Token::BREAK | Token::CONTINUE => {
+                self.branch_helper
+                    .add_jump_point(func_ctx!(self), token, label, pos);
+            }
+            Token::GOTO => {
+                let label = label.unwrap();
+                let fctx = func_ctx!(self);
+                let target = self.t.obj_pos(label);
+                if fctx
+                    .range_span
+                    .is_some_and(|(begin, end)| target < begin || target > end)
+                {
+                    // out of the body of a range over a function, see gen_range_func
+                    let index = fctx.next_code_index();
+                    fctx.emit_jump(0, Some(pos));
+                    fctx.range_exits.push((index, token, label));
+                    return;
+                }
+                let addr = self.branch_helper.label_addr(fctx, label);
+                fctx.set_goto();
+                fctx.emit_inst(
+                    InterInst::with_op_index(Opcode::JUMP, addr, Addr::Void, Addr::Void),
+                    Some(pos),
+                )
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// The exit code of the function that the body of a range over a function
+    /// is in, see `gen_range_func`, which is added when it's first needed.
+    fn range_exit_code(&mut self) -> VirtualAddr {
+        let owner = self.range_owner();
+        let octx = &mut self.func_ctx_stack[owner];
+        let code = match octx.range_exit {
+            Some(code) => code,
+            None => {
+                let code = octx.add_local(None, Some(0isize.into()));
+                octx.range_exit = Some(code);
+                code
+            }
+        };
+        self.range_owner_local(code, ValueType::Int)
+    }
+
+    /// Loads a local of the function that the body of a range over a function
+    /// is in to a register, if it's an upvalue.
+    fn load_range_owner_local(&mut self, va: VirtualAddr, pos: Option<usize>) -> Addr {
+        match va {
+            VirtualAddr::UpValue(uv) => {
+                let reg = expr_ctx!(self).inc_cur_reg();
+                let inst = InterInst::with_op_index(Opcode::LOAD_UP_VALUE, reg, uv, Addr::Void);
+                func_ctx!(self).emit_inst(inst, pos);
+                reg
+            }
+            va => va.as_direct_addr(),
+        }
+    }
+
+    /// A loop body declares new variables in each iteration. If closures or
+    /// pointers refer to any of the locals declared from `begin` on, their
+    /// upvalues are closed, so the ones made in the next iteration are new.
+    fn gen_close_up_values(&mut self, begin: usize, pos: Option<usize>) -> bool {
+        let fctx = func_ctx!(self);
+        let end = fctx.local_count();
+        if !fctx.any_captured(begin..end) {
+            return false;
+        }
+        let inst = InterInst::with_op_index(
+            Opcode::CLOSE_UP_VALUES,
+            Addr::Void,
+            Addr::Imm(begin as OpIndex),
+            Addr::Imm(end as OpIndex),
+        );
+        fctx.emit_inst(inst, pos);
+        true
+    }
+
+    /// Generates array[:], a slice sharing the array
+    fn gen_slice_whole_array(
+        &mut self,
+        array: Addr,
+        t_elem: Option<ValueType>,
+        pos: Option<usize>,
+    ) -> Addr {
+        let addr = expr_ctx!(self).inc_cur_reg();
+        let fctx = func_ctx!(self);
+        let low_addr = fctx.add_comparable(0isize.into());
+        let high_addr = fctx.add_comparable((-1isize).into());
+        let inst = InterInst::with_op_t_index(
+            Opcode::SLICE,
+            Some(ValueType::Array),
+            t_elem,
+            addr,
+            array,
+            low_addr,
+        );
+        let inst_ex = InterInst::with_op_index(Opcode::VOID, Addr::Void, high_addr, high_addr);
+        fctx.emit_inst(inst, pos);
+        fctx.emit_inst(inst_ex, pos);
+        addr
+    }
+
     fn gen_expr_type(&mut self, typ: &Expr) {
         let m = self.t.node_meta(typ.id(), self.vmctx);
         let pos = Some(typ.pos(&self.ast_objs));
@@ -1398,7 +2191,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             let ident = &self.ast_objs.idents[*n];
             let meta = self.t.obj_def_meta(*n, self.vmctx);
             let val = self.vmctx.ffi_ctx().zero_val(&meta);
-            self.vmctx.packages_mut()[pkey].add_member(ident.name.clone(), val);
+            self.vmctx.packages_mut()[pkey].add_var(ident.name.clone(), val, meta);
         }
     }
 
@@ -1444,12 +2237,21 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     ) {
         let val_ectx = expr_ctx!(self);
         let (val_addr, val_direct, val_cast_i) =
-            CodeGen::get_store_addr(&mut self.t, self.iface_selector, val_ectx, t0);
+            CodeGen::get_store_addr(&mut self.t, self.vmctx, self.iface_selector, val_ectx, t0);
         let t1 = t1.unwrap_or(self.t.bool_tc_type());
         let (ok_addr, ok_direct, ok_cast_i) =
-            CodeGen::get_store_addr(&mut self.t, self.iface_selector, ectx_ex, t1);
+            CodeGen::get_store_addr(&mut self.t, self.vmctx, self.iface_selector, ectx_ex, t1);
 
-        let fctx = func_ctx!(self);
+        let copy = op == Opcode::LOAD_MAP
+            && val_direct
+            && matches!(
+                self.t.tc_type_to_value_type(t0),
+                ValueType::Array | ValueType::Struct
+            );
+
+        // both contexts are in use, the depth is the deeper one
+        let fctx = self.func_ctx_stack.last_mut().unwrap();
+        fctx.set_depth(Some(val_ectx.cur_reg.max(ectx_ex.cur_reg)));
         if s1 != Addr::Void {
             let inst = InterInst::with_op_t_index(op, vt0, vt1, val_addr, s0, s1);
             let inst_ex = InterInst::with_op_index(Opcode::VOID, ok_addr, ex_s0, Addr::Void);
@@ -1459,6 +2261,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             let inst = InterInst::with_op_t_index(op, vt0, vt1, val_addr, s0, ok_addr);
             fctx.emit_inst(inst, pos);
         }
+        if copy {
+            let inst = InterInst::with_op_index(Opcode::DUPLICATE, val_addr, val_addr, Addr::Void);
+            fctx.emit_inst(inst, pos);
+        }
 
         if !val_direct {
             val_ectx.direct_assign(fctx, val_addr, val_cast_i, pos);
@@ -1470,6 +2276,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
 
     fn get_store_addr(
         t: &mut TypeLookup,
+        vmctx: &mut CodeGenVMCtx,
         iface_sel: &mut IfaceSelector,
         ectx: &mut ExprCtx,
         t_rhs: TCTypeKey,
@@ -1481,20 +2288,46 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             true => va.as_direct_addr(),
             false => ectx.inc_cur_reg(),
         };
-        let cast_index = need_cast.then(|| iface_sel.add((typ.unwrap(), t_rhs)));
+        let cast_index = need_cast.then(|| {
+            let key = (
+                t.tc_type_to_meta(typ.unwrap(), vmctx),
+                t.tc_type_to_meta(t_rhs, vmctx),
+            );
+            iface_sel.add(key)
+        });
         (addr, direct, cast_index)
     }
 
+    /// Generates an operand of == or !=, converted to the type of the other
+    /// operand if that's an interface, so that the dynamic types are compared.
+    fn gen_comparison_operand(&mut self, e: &Expr, other: &Expr) -> Addr {
+        let (t, other_t) = (self.t.expr_tc_type(e), self.t.expr_tc_type(other));
+        if self.t.should_cast_to_iface(other_t, t) {
+            let addr = expr_ctx!(self).inc_cur_reg();
+            self.store_mode_call(VirtualAddr::Direct(addr), Some(other_t), |g| g.gen_expr(e));
+            addr
+        } else {
+            self.load_mode_call(|g| g.gen_expr(e))
+        }
+    }
+
     fn cast_to_iface_index(&mut self, lhs: TCTypeKey, rhs: TCTypeKey) -> Option<OpIndex> {
-        match self.t.should_cast_to_iface(lhs, rhs) {
-            true => {
-                let index = self.iface_selector.add((lhs, rhs));
-                Some(index)
-            }
+        match self.t.should_cast_to_iface(lhs, rhs) || self.t.should_rebind_iface(lhs, rhs) {
+            true => Some(self.iface_index(lhs, rhs)),
             false => None,
         }
     }
 
+    /// The index of the binding of `rhs` to the interface `lhs`, the same
+    /// pair of metas gets the same index in all the packages.
+    fn iface_index(&mut self, lhs: TCTypeKey, rhs: TCTypeKey) -> OpIndex {
+        let key = (
+            self.t.tc_type_to_meta(lhs, self.vmctx),
+            self.t.tc_type_to_meta(rhs, self.vmctx),
+        );
+        self.iface_selector.add(key)
+    }
+
     fn visit_stmt(&mut self, stmt: &Stmt) {
         let init_reg = self.expr_ctx_stack.last().map(|x| x.cur_reg).unwrap_or(0);
         self.push_expr_ctx(ExprMode::Discard, init_reg);
@@ -1502,6 +2335,43 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         self.pop_expr_ctx();
     }
 
+    /// Generates `left && right` or `left || right`, with the operands of a chain
+    /// of the same operator evaluated to one register, see `ShortCircuit`.
+    /// The result is only assigned when the chain is done, as the operands may
+    /// read what it's assigned to, like `x = f() || x`.
+    fn gen_short_circuit(&mut self, this: &Expr, left: &Expr, op: &Token, right: &Expr) {
+        let mut operands = vec![right];
+        let mut first = left;
+        while let Expr::Binary(b) = first {
+            if b.op != *op {
+                break;
+            }
+            operands.push(&b.expr_b);
+            first = &b.expr_a;
+        }
+        operands.push(first);
+        operands.reverse();
+
+        let typ = self.t.expr_tc_type(this);
+        let result = expr_ctx!(self).inc_cur_reg();
+        let mut sc = ShortCircuit::new();
+        for (i, e) in operands.iter().enumerate() {
+            if i > 0 {
+                let pos = Some(e.pos(&self.ast_objs));
+                sc.add_exit(func_ctx!(self), op.clone(), result, pos);
+            }
+            self.store_mode_call(VirtualAddr::Direct(result), None, |g| g.gen_expr(e));
+        }
+        sc.patch_exits(func_ctx!(self));
+
+        let pos = Some(left.pos(&self.ast_objs));
+        self.cur_expr_emit_direct_assign(typ, result, pos);
+        // in load mode the result stays in the register
+        if !matches!(expr_ctx!(self).mode, ExprMode::Load) {
+            expr_ctx!(self).dec_cur_reg();
+        }
+    }
+
     /// Push ExprMode::Load context for calling 'f'
     /// and restore after calling 'f'
     fn load_mode_call<F>(&mut self, f: F) -> Addr
@@ -1554,14 +2424,42 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     {
         let lhs = expr_ctx!(self).lhs_type();
         let index = lhs.map(|x| self.cast_to_iface_index(x, rhs_type)).flatten();
-        expr_ctx!(self).assign_with(func_ctx!(self), index, pos, f);
+        let fctx = self.func_ctx_stack.last_mut().unwrap();
+        expr_ctx!(self).assign_with(fctx, index, pos, f);
+    }
+
+    /// Same as cur_expr_emit_assign, for 'f' reading a variable, a field or an element.
+    /// An array or a struct read straight into a local or an argument is copied, as it's a value.
+    fn cur_expr_emit_load<F>(&mut self, rhs_type: TCTypeKey, pos: Option<Pos>, f: F)
+    where
+        F: FnOnce(&mut FuncCtx, Addr, Option<Pos>),
+    {
+        let copy = matches!(
+            expr_ctx!(self).mode,
+            ExprMode::Store(VirtualAddr::Direct(_), _)
+        ) && matches!(
+            self.t.tc_type_to_value_type(rhs_type),
+            ValueType::Array | ValueType::Struct
+        );
+        self.cur_expr_emit_assign(rhs_type, pos, |fctx, d, p| {
+            f(fctx, d, p);
+            if copy {
+                let inst = InterInst::with_op_index(Opcode::DUPLICATE, d, d, Addr::Void);
+                fctx.emit_inst(inst, p);
+            }
+        });
     }
 
     fn cur_expr_emit_direct_assign(&mut self, rhs_type: TCTypeKey, src: Addr, pos: Option<Pos>) {
         let lhs = expr_ctx!(self).lhs_type();
         let index = lhs.map(|x| self.cast_to_iface_index(x, rhs_type)).flatten();
         let src = if src == Addr::UntypedNil {
-            let nil = match lhs {
+            // a converted nil, like []int(nil), has the type it's converted to
+            let nil_type = match self.t.tc_type_to_value_type(rhs_type) {
+                ValueType::Void => lhs,
+                _ => Some(rhs_type),
+            };
+            let nil = match nil_type {
                 Some(tct) => {
                     let typ = self.t.tc_type_to_value_type(tct);
                     match typ {
@@ -1578,7 +2476,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         } else {
             src
         };
-        expr_ctx!(self).direct_assign(func_ctx!(self), src, index, pos);
+        let fctx = self.func_ctx_stack.last_mut().unwrap();
+        expr_ctx!(self).direct_assign(fctx, src, index, pos);
     }
 
     pub fn gen_with_files(
@@ -1604,7 +2503,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         let (names, vars) = self.pkg_helper.sort_var_decls(files, self.t.type_info());
         self.add_pkg_var_member(pkey, &names);
 
-        self.pkg_helper.gen_imports(tcpkg, &mut func_ctx!(self));
+        self.pkg_helper.gen_imports(tcpkg, func_ctx!(self));
 
         for f in files.iter() {
             for d in f.decls.iter() {
@@ -1633,12 +2532,12 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
         match va {
             VirtualAddr::Direct(addr) => self.cur_expr_emit_direct_assign(tc_type, addr, pos),
             VirtualAddr::PackageMember(pkg, index) => {
-                self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
+                self.cur_expr_emit_load(tc_type, pos, |f, d, p| {
                     f.emit_load_pkg(d, pkg, index, p)
                 });
             }
             VirtualAddr::UpValue(uv) => {
-                self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
+                self.cur_expr_emit_load(tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_index(Opcode::LOAD_UP_VALUE, d, uv, Addr::Void);
                     f.emit_inst(inst, p);
                 });
@@ -1683,9 +2582,9 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
             let pkg = self.pkg_helper.get_runtime_key(key);
             let fctx = func_ctx!(self);
             let pkg_addr = fctx.add_package(pkg);
-            let index = Addr::PkgMemberIndex(pkg, *ident);
+            let index = fctx.pkg_member_index(pkg, &self.ast_objs.idents[*ident].name);
             let tc_type = self.t.expr_tc_type(this);
-            self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
+            self.cur_expr_emit_load(tc_type, pos, |f, d, p| {
                 f.emit_load_pkg(d, pkg_addr, index, p)
             });
             return;
@@ -1714,7 +2613,10 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                     if !lhs_has_embedded {
                         self.load_mode_call(|g| g.gen_expr_ref(lhs_expr, recv_type))
                     } else {
-                        let lhs_addr = self.load_mode_call(|g| g.gen_expr(lhs_expr));
+                        let mut lhs_addr = self.load_mode_call(|g| g.gen_expr(lhs_expr));
+                        if lhs_meta.ptr_depth > 0 {
+                            lhs_addr = self.gen_load_pointer(lhs_addr, pos);
+                        }
                         let rt_indices = embedded_indices.iter().map(|x| *x as OpIndex).collect();
                         let (op, index) =
                             self.get_struct_field_op_index(rt_indices, Opcode::REF_STRUCT_FIELD);
@@ -1769,6 +2671,9 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                     });
                 }
             }
+            SelectionType::MethodExpr(ptr_recv) => {
+                self.gen_method_expr(recv_type, expr_type, &indices, *ptr_recv, pos);
+            }
             SelectionType::NonMethod => {
                 let mut lhs_addr = self.load_mode_call(|g| g.gen_expr(lhs_expr));
                 let rt_indices = indices.iter().map(|x| *x as OpIndex).collect();
@@ -1776,7 +2681,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                 if op == Opcode::LOAD_STRUCT && lhs_meta.ptr_depth > 0 {
                     lhs_addr = self.gen_load_pointer(lhs_addr, pos);
                 }
-                self.cur_expr_emit_assign(expr_type, pos, |f, d, p| {
+                self.cur_expr_emit_load(expr_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_index(op, d, lhs_addr, Addr::Imm(index));
                     f.emit_inst(inst, p);
                 });
@@ -1791,7 +2696,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
 
     fn visit_expr_slice(
         &mut self,
-        _: &Expr,
+        this: &Expr,
         expr: &Expr,
         low: &Option<Expr>,
         high: &Option<Expr>,
@@ -1800,7 +2705,10 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
         let (t0, tct_elem) = self.t.sliceable_expr_value_types(expr, self.vmctx);
         let pos = Some(expr.pos(&self.ast_objs));
 
-        let slice_array_addr = self.load_mode_call(|g| g.gen_expr(expr));
+        let mut slice_array_addr = self.load_mode_call(|g| g.gen_expr(expr));
+        if self.t.expr_value_type(expr) == ValueType::Pointer {
+            slice_array_addr = self.gen_load_pointer(slice_array_addr, pos);
+        }
         let low_addr = match low {
             None => func_ctx!(self).add_comparable(0isize.into()),
             Some(e) => self.load_mode_call(|g| g.gen_expr(e)),
@@ -1814,7 +2722,8 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
             Some(e) => self.load_mode_call(|g| g.gen_expr(e)),
         };
         let t_elem = self.t.tc_type_to_value_type(tct_elem);
-        self.cur_expr_emit_assign(tct_elem, pos, |f, d, p| {
+        let typ = self.t.expr_tc_type(this);
+        self.cur_expr_emit_assign(typ, pos, |f, d, p| {
             let inst = InterInst::with_op_t_index(
                 Opcode::SLICE,
                 Some(t0),
@@ -1846,7 +2755,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                 let pos = Some(expr.pos(&self.ast_objs));
                 let typ = self.t.expr_tc_type(this);
                 let addr = self.load_mode_call(|g| g.gen_expr(expr));
-                self.cur_expr_emit_assign(typ, pos, |f, d, p| {
+                self.cur_expr_emit_load(typ, pos, |f, d, p| {
                     let inst = InterInst::with_op_index(Opcode::LOAD_POINTER, d, addr, Addr::Void);
                     f.emit_inst(inst, p);
                 });
@@ -1887,9 +2796,19 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
     }
 
     fn visit_expr_binary(&mut self, this: &Expr, left: &Expr, op: &Token, right: &Expr) {
+        if matches!(op, Token::LAND | Token::LOR) {
+            return self.gen_short_circuit(this, left, op, right);
+        }
         let typ = self.t.expr_tc_type(this);
-        let left_addr = self.load_mode_call(|g| g.gen_expr(left));
-        let t = self.t.expr_value_type(left);
+        let is_eql = matches!(op, Token::EQL | Token::NEQ);
+        let left_addr = match is_eql {
+            true => self.gen_comparison_operand(left, right),
+            false => self.load_mode_call(|g| g.gen_expr(left)),
+        };
+        let t = match is_eql && self.t.expr_value_type(right) == ValueType::Interface {
+            true => ValueType::Interface,
+            false => self.t.expr_value_type(left),
+        };
         let code = match op {
             Token::ADD => Opcode::ADD,
             Token::SUB => Opcode::SUB,
@@ -1902,8 +2821,6 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
             Token::SHL => Opcode::SHL,
             Token::SHR => Opcode::SHR,
             Token::AND_NOT => Opcode::AND_NOT,
-            Token::LAND => Opcode::JUMP_IF_NOT,
-            Token::LOR => Opcode::JUMP_IF,
             Token::EQL => Opcode::EQL,
             Token::LSS => Opcode::LSS,
             Token::GTR => Opcode::GTR,
@@ -1913,49 +2830,18 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
             _ => unreachable!(),
         };
         let pos = Some(left.pos(&self.ast_objs));
-        // handles short circuit
-        let mark = match code {
-            Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => {
-                let ectx_backup = expr_ctx!(self).clone();
-                self.cur_expr_emit_assign(typ, pos, |f, d, p| {
-                    let inst =
-                        InterInst::with_op_index(Opcode::DUPLICATE, d, left_addr, Addr::Void);
-                    f.emit_inst(inst, p);
-                });
-
-                let fctx = func_ctx!(self);
-                let inst = InterInst::with_op_index(code, Addr::Void, left_addr, Addr::Void);
-                fctx.emit_inst(inst, pos);
-                Some((fctx.next_code_index() - 1, ectx_backup))
-            }
+        let right_addr = match is_eql {
+            true => self.gen_comparison_operand(right, left),
+            false => self.load_mode_call(|g| g.gen_expr(right)),
+        };
+        let t1 = match code {
+            Opcode::SHL | Opcode::SHR | Opcode::EQL => Some(self.t.expr_value_type(right)),
             _ => None,
         };
-
-        let right_addr = self.load_mode_call(|g| g.gen_expr(right));
-
-        if let Some((i, ectx_backup)) = mark {
-            // the two assignment must result in the same register allocation result,
-            // ectx_backup is used to make sure of that.
-            self.expr_ctx_stack.push(ectx_backup);
-            self.cur_expr_emit_assign(typ, pos, |f, d, p| {
-                let inst = InterInst::with_op_index(Opcode::DUPLICATE, d, right_addr, Addr::Void);
-                f.emit_inst(inst, p);
-            });
-            self.pop_expr_ctx();
-
-            let fctx = func_ctx!(self);
-            let diff = fctx.next_code_index() - i - 1;
-            fctx.inst_mut(i).d = Addr::Imm(diff as OpIndex);
-        } else {
-            let t1 = match code {
-                Opcode::SHL | Opcode::SHR | Opcode::EQL => Some(self.t.expr_value_type(right)),
-                _ => None,
-            };
-            self.cur_expr_emit_assign(typ, pos, |f, d, p| {
-                let inst = InterInst::with_op_t_index(code, Some(t), t1, d, left_addr, right_addr);
-                f.emit_inst(inst, p);
-            });
-        }
+        self.cur_expr_emit_assign(typ, pos, |f, d, p| {
+            let inst = InterInst::with_op_t_index(code, Some(t), t1, d, left_addr, right_addr);
+            f.emit_inst(inst, p);
+        });
     }
 
     fn visit_expr_key_value(&mut self, _e: &Expr, _key: &Expr, _val: &Expr) {
@@ -1987,7 +2873,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
     }
 
     fn visit_bad_expr(&mut self, _: &Expr, _e: &BadExpr) {
-        unreachable!();
+        unreachable!("files with syntax errors are not compiled");
     }
 }
 
@@ -2057,7 +2943,8 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             Stmt::For(_) | Stmt::Range(_) | Stmt::Select(_) | Stmt::Switch(_) => true,
             _ => false,
         };
-        self.branch_helper.add_label(entity, offset, is_breakable);
+        self.branch_helper
+            .add_label(func_ctx!(self), entity, offset, is_breakable);
         self.visit_stmt(&stmt.stmt);
     }
 
@@ -2096,7 +2983,8 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     }
 
     fn visit_stmt_defer(&mut self, dstmt: &DeferStmt) {
-        self.vmctx.functions_mut()[func_ctx!(self).f_key].flag = FuncFlag::HasDefer;
+        let owner = self.func_ctx_stack[self.range_owner()].f_key;
+        self.vmctx.functions_mut()[owner].flag = FuncFlag::HasDefer;
         match &dstmt.call {
             Expr::Call(call) => {
                 self.gen_expr_call(
@@ -2111,33 +2999,63 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     }
 
     fn visit_stmt_return(&mut self, rstmt: &ReturnStmt) {
+        let pos = Some(rstmt.ret);
         if !rstmt.results.is_empty() {
-            let types = self.t.sig_returns_tc_types(func_ctx!(self).tc_key.unwrap());
-            for (i, expr) in rstmt.results.iter().enumerate() {
-                let va = VirtualAddr::Direct(Addr::LocalVar(i));
-                self.store_mode_call(va, Some(types[i]), |g| g.gen_expr(expr));
+            let owner = self.range_owner();
+            let types = self
+                .t
+                .sig_returns_tc_types(self.func_ctx_stack[owner].tc_key.unwrap());
+            let results: Vec<VirtualAddr> = types
+                .iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    let typ = self.t.tc_type_to_value_type(*t);
+                    self.range_owner_local(Addr::LocalVar(i), typ)
+                })
+                .collect();
+            if rstmt.results.len() < types.len() {
+                // return f(), where f returns multiple values
+                let call = &rstmt.results[0];
+                self.discard_mode_call(|g| g.gen_expr(call));
+                let reg_begin = expr_ctx!(self).cur_reg;
+                let rtypes = self.t.expr_tuple_tc_types(call);
+                // the values are kept in the registers until they are all stored
+                expr_ctx!(self).cur_reg = reg_begin + rtypes.len();
+                for (i, t) in types.iter().enumerate() {
+                    let va = results[i].clone();
+                    self.store_mode_call(va, Some(*t), |g| {
+                        g.cur_expr_emit_direct_assign(
+                            rtypes[i],
+                            Addr::Register(reg_begin + i),
+                            Some(rstmt.ret),
+                        );
+                    });
+                }
+                expr_ctx!(self).cur_reg = reg_begin;
+            } else {
+                for (i, expr) in rstmt.results.iter().enumerate() {
+                    let va = results[i].clone();
+                    self.store_mode_call(va, Some(types[i]), |g| g.gen_expr(expr));
+                }
             }
         }
-        func_ctx!(self).emit_return(None, Some(rstmt.ret), &self.vmctx.functions());
+        if func_ctx!(self).range_body {
+            // the function the loop is in returns when the one ranged over does
+            let flag = self.range_return_flag();
+            let fctx = func_ctx!(self);
+            let true_addr = fctx.add_comparable(true.into());
+            fctx.emit_assign(flag, true_addr, None, pos);
+            self.gen_yield_return(false, pos);
+        } else {
+            func_ctx!(self).emit_return(None, pos, &self.vmctx.functions());
+        }
     }
 
     fn visit_stmt_branch(&mut self, bstmt: &BranchStmt) {
         match bstmt.token {
-            Token::BREAK | Token::CONTINUE => {
+            Token::BREAK | Token::CONTINUE | Token::GOTO => {
                 let entity = bstmt.label.map(|x| self.t.object_use(x));
-                self.branch_helper.add_jump_point(
-                    func_ctx!(self),
-                    bstmt.token.clone(),
-                    entity,
-                    bstmt.token_pos,
-                );
-            }
-            Token::GOTO => {
-                let addr = Addr::Label(self.t.object_use(bstmt.label.unwrap()));
-                func_ctx!(self).emit_inst(
-                    InterInst::with_op_index(Opcode::JUMP, addr, Addr::Void, Addr::Void),
-                    Some(bstmt.token_pos),
-                )
+                self.gen_branch(bstmt.token.clone(), entity, bstmt.token_pos);
             }
             Token::FALLTHROUGH => {
                 // handled in gen_switch_body
@@ -2158,35 +3076,40 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         }
         let cond_addr = self.load_mode_call(|g| g.gen_expr(&ifstmt.cond));
         let fctx = func_ctx!(self);
-        // imm to be set later
+        let else_label = fctx.new_label();
         fctx.emit_inst(
-            InterInst::with_op_index(Opcode::JUMP_IF_NOT, Addr::Void, cond_addr, Addr::Void),
+            InterInst::with_op_index(
+                Opcode::JUMP_IF_NOT,
+                Addr::Label(else_label),
+                cond_addr,
+                Addr::Void,
+            ),
             Some(ifstmt.if_pos),
         );
-        let top_marker = fctx.next_code_index();
 
         self.visit_stmt_block(&ifstmt.body);
-        let marker_if_arm_end = if ifstmt.els.is_some() {
+        let end_label = if ifstmt.els.is_some() {
             let fctx = func_ctx!(self);
-            // imm to be set later
-            fctx.emit_inst(InterInst::with_op(Opcode::JUMP), Some(ifstmt.if_pos));
-            Some(fctx.next_code_index())
+            let end_label = fctx.new_label();
+            let inst = InterInst::with_op_index(
+                Opcode::JUMP,
+                Addr::Label(end_label),
+                Addr::Void,
+                Addr::Void,
+            );
+            fctx.emit_inst(inst, Some(ifstmt.if_pos));
+            Some(end_label)
         } else {
             None
         };
 
-        // set the correct else jump target
         let fctx = func_ctx!(self);
-        let offset = fctx.offset(top_marker);
-        fctx.inst_mut(top_marker - 1).d = Addr::Imm(offset);
+        fctx.bind_label(else_label, fctx.next_code_index());
 
         if let Some(els) = &ifstmt.els {
             self.visit_stmt(els);
-            // set the correct if_arm_end jump target
             let fctx = func_ctx!(self);
-            let marker = marker_if_arm_end.unwrap();
-            let offset = fctx.offset(marker);
-            fctx.inst_mut(marker - 1).d = Addr::Imm(offset);
+            fctx.bind_label(end_label.unwrap(), fctx.next_code_index());
         }
     }
 
@@ -2200,14 +3123,19 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         if let Some(init) = &sstmt.init {
             self.visit_stmt(init);
         }
-        let (addr, typ) = match &sstmt.tag {
+        let (addr, typ, tc_type) = match &sstmt.tag {
             Some(e) => (
                 self.load_mode_call(|g| g.gen_expr(e)),
                 self.t.expr_value_type(e),
+                Some(self.t.expr_tc_type(e)),
+            ),
+            None => (
+                func_ctx!(self).add_comparable(true.into()),
+                ValueType::Bool,
+                None,
             ),
-            None => (func_ctx!(self).add_comparable(true.into()), ValueType::Bool),
         };
-        self.gen_switch_body(&*sstmt.body, addr, typ, None);
+        self.gen_switch_body(&*sstmt.body, addr, typ, tc_type, None);
 
         self.branch_helper.leave_block(func_ctx!(self), None);
     }
@@ -2266,7 +3194,13 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             None
         };
 
-        self.gen_switch_body(&*tstmt.body, tag_dst, ValueType::Metadata, local_var_info);
+        self.gen_switch_body(
+            &*tstmt.body,
+            tag_dst,
+            ValueType::Metadata,
+            None,
+            local_var_info,
+        );
     }
 
     fn visit_stmt_comm(&mut self, _cclause: &CommClause) {
@@ -2311,14 +3245,14 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             let (typ, chan_addr, pos) = match &c.comm {
                 Some(comm) => match comm {
                     Stmt::Send(send_stmt) => {
-                        let chan_addr = self.load_mode_call(|g| g.gen_expr(&send_stmt.chan));
-                        let val_addr = self.load_mode_call(|g| g.gen_expr(&send_stmt.val));
+                        let chan_addr = self.gen_select_operand(&send_stmt.chan);
+                        let val_addr = self.gen_select_operand(&send_stmt.val);
                         (CommType::Send(val_addr), Some(chan_addr), send_stmt.arrow)
                     }
                     Stmt::Assign(ass_key) => {
                         let ass = &self.ast_objs.a_stmts[*ass_key];
                         let (e, pos) = SelectHelper::unwrap_recv(&ass.rhs[0]);
-                        let chan_addr = self.load_mode_call(|g| g.gen_expr(e));
+                        let chan_addr = self.gen_select_operand(e);
                         let val_reg = expr_ctx!(self).inc_cur_reg();
                         let t = match &ass.lhs.len() {
                             1 => CommType::Recv(*ass_key, val_reg, false),
@@ -2332,7 +3266,7 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
                     }
                     Stmt::Expr(expr_stmt) => {
                         let (e, pos) = SelectHelper::unwrap_recv(expr_stmt);
-                        let chan_addr = self.load_mode_call(|g| g.gen_expr(e));
+                        let chan_addr = self.gen_select_operand(e);
                         (CommType::RecvNoLhs, Some(chan_addr), pos)
                     }
                     _ => unreachable!(),
@@ -2364,12 +3298,10 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
                 self.visit_stmt(stmt);
             }
             let fctx = func_ctx!(self);
-            let mut end = fctx.next_code_index();
+            let end = fctx.next_code_index();
             // the last block doesn't jump
             if i < last_index {
                 fctx.emit_inst(InterInst::with_op(Opcode::JUMP), None);
-            } else {
-                end -= 1;
             }
 
             helper.set_block_begin_end(i, begin, end);
@@ -2387,23 +3319,33 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             self.visit_stmt(init);
         }
         let top_marker = func_ctx!(self).next_code_index();
-        let out_marker = if let Some(cond) = &fstmt.cond {
+        let out_label = if let Some(cond) = &fstmt.cond {
             let cond_addr = self.load_mode_call(|g| g.gen_expr(&cond));
             let fctx = func_ctx!(self);
+            let out_label = fctx.new_label();
             fctx.emit_inst(
-                InterInst::with_op_index(Opcode::JUMP_IF_NOT, Addr::Void, cond_addr, Addr::Void),
+                InterInst::with_op_index(
+                    Opcode::JUMP_IF_NOT,
+                    Addr::Label(out_label),
+                    cond_addr,
+                    Addr::Void,
+                ),
                 Some(fstmt.for_pos),
             );
-            Some(fctx.next_code_index() - 1)
+            Some(out_label)
         } else {
             None
         };
+        let locals_begin = func_ctx!(self).local_count();
         self.visit_stmt_block(&fstmt.body);
+        let m = func_ctx!(self).next_code_index();
+        let closes = self.gen_close_up_values(locals_begin, Some(fstmt.for_pos));
         let continue_marker = if let Some(post) = &fstmt.post {
             // "continue" jumps to post statements
-            let m = func_ctx!(self).next_code_index();
             self.visit_stmt(post);
             m
+        } else if closes {
+            m
         } else {
             // "continue" jumps to top directly if no post statements
             top_marker
@@ -2411,24 +3353,28 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
 
         // jump to the top
         let fctx = func_ctx!(self);
-        let offset = -fctx.offset(top_marker) - 1;
+        let top = fctx.label_at(top_marker);
         fctx.emit_inst(
-            InterInst::with_op_index(Opcode::JUMP, Addr::Imm(offset), Addr::Void, Addr::Void),
+            InterInst::with_op_index(Opcode::JUMP, top, Addr::Void, Addr::Void),
             Some(fstmt.for_pos),
         );
 
-        // set the correct else jump out target
-        if let Some(m) = out_marker {
-            let fctx = func_ctx!(self);
-            let offset = fctx.offset(m) - 1;
-            fctx.inst_mut(m).d = Addr::Imm(offset);
+        if let Some(l) = out_label {
+            fctx.bind_label(l, fctx.next_code_index());
         }
 
         self.branch_helper
             .leave_block(func_ctx!(self), Some(continue_marker));
+        if closes {
+            // for the loop left by "break"
+            self.gen_close_up_values(locals_begin, Some(fstmt.for_pos));
+        }
     }
 
     fn visit_stmt_range(&mut self, rstmt: &RangeStmt) {
+        if self.t.expr_value_type(&rstmt.expr) == ValueType::Closure {
+            return self.gen_range_func(rstmt);
+        }
         self.branch_helper.enter_block(true);
 
         let blank = Expr::Ident(self.blank_ident);
@@ -2440,20 +3386,31 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             .gen_assign(&rstmt.token, &lhs, RightHandSide::Range(&rstmt.expr))
             .unwrap();
 
+        let locals_begin = func_ctx!(self).local_count();
+        func_ctx!(self).range_depth += 1;
         self.visit_stmt_block(&rstmt.body);
+        func_ctx!(self).range_depth -= 1;
+        let close_marker = func_ctx!(self).next_code_index();
+        let closes = self.gen_close_up_values(locals_begin, Some(rstmt.token_pos));
         // jump to the top
         let fctx = func_ctx!(self);
-        let offset = -fctx.offset(marker) - 1;
         // tell Opcode::RANGE where to jump after it's done
-        let end_offset = fctx.offset(marker);
-        fctx.inst_mut(marker).s0 = Addr::Imm(end_offset);
+        let exit = fctx.new_label();
+        fctx.inst_mut(marker).s0 = Addr::Label(exit);
+        let top = fctx.label_at(marker);
         fctx.emit_inst(
-            InterInst::with_op_index(Opcode::JUMP, Addr::Imm(offset), Addr::Void, Addr::Void),
+            InterInst::with_op_index(Opcode::JUMP, top, Addr::Void, Addr::Void),
             Some(rstmt.token_pos),
         );
+        fctx.bind_label(exit, fctx.next_code_index());
 
+        let continue_marker = if closes { close_marker } else { marker };
         self.branch_helper
-            .leave_block(func_ctx!(self), Some(marker));
+            .leave_block(func_ctx!(self), Some(continue_marker));
+        if closes {
+            // for the loop left by "break"
+            self.gen_close_up_values(locals_begin, Some(rstmt.token_pos));
+        }
     }
 
     fn visit_expr_stmt(&mut self, e: &Expr) {
@@ -2463,15 +3420,16 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     fn visit_empty_stmt(&mut self, _e: &EmptyStmt) {}
 
     fn visit_bad_stmt(&mut self, _b: &BadStmt) {
-        unreachable!();
+        unreachable!("files with syntax errors are not compiled");
     }
 
     fn visit_bad_decl(&mut self, _b: &BadDecl) {
-        unreachable!();
+        unreachable!("files with syntax errors are not compiled");
     }
 }
 
-pub type IfaceSelector = Selector<(TCTypeKey, TCTypeKey)>;
+/// The interface and the type of the value converted to it.
+pub type IfaceSelector = Selector<(Meta, Meta)>;
 
 pub type StructSelector = Selector<Vec<OpIndex>>;
 
@@ -3,70 +3,13 @@
 // license that can be found in the LICENSE file.
 
 use super::consts::Consts;
+pub(crate) use super::ir::*;
 use super::types::TypeLookup;
 use go_parser::ast::*;
-use go_parser::{AstObjects, IdentKey, Map, Pos};
+use go_parser::{AstObjects, Map, Pos, Token};
 use go_types::{ObjKey as TCObjKey, TypeKey as TCTypeKey};
 use go_vm::types::*;
 use go_vm::*;
-use std::convert::TryFrom;
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Addr {
-    Const(usize),
-    LocalVar(usize),
-    Regsiter(usize),
-    Imm(OpIndex),
-    PkgMemberIndex(PackageKey, IdentKey), // deferred resolve
-    Label(TCObjKey),                      // deferred resolve
-    UntypedNil,                           // will be typed when assigned to var
-    Void,
-}
-
-impl Addr {
-    pub fn as_var_index(self) -> usize {
-        match self {
-            Self::LocalVar(i) => i,
-            _ => unreachable!(),
-        }
-    }
-
-    pub fn as_reg_index(self) -> usize {
-        match self {
-            Self::Regsiter(i) => i,
-            _ => unreachable!(),
-        }
-    }
-
-    fn into_index(
-        self,
-        reg_base: usize,
-        ast_objs: &AstObjects,
-        packages: &PackageObjs,
-        inst_index: usize,
-        labels: &Map<TCObjKey, usize>,
-        cst_map: &Map<usize, usize>,
-    ) -> OpIndex {
-        // Zero values are the first batch of consts
-        match self {
-            Self::Const(i) => -(cst_map[&i] as OpIndex) - 1,
-            Self::LocalVar(i) => i as OpIndex,
-            Self::Regsiter(i) => (reg_base + i) as OpIndex,
-            Self::PkgMemberIndex(key, ident) => {
-                let pkg = &packages[key];
-                let id = &ast_objs.idents[ident];
-                *pkg.member_index(&id.name).unwrap()
-            }
-            Self::Label(key) => {
-                let label_offset = labels[&key];
-                (label_offset as OpIndex) - (inst_index as OpIndex) - 1
-            }
-            Self::Imm(i) => i,
-            Self::UntypedNil => unreachable!(),
-            Self::Void => std::i32::MAX,
-        }
-    }
-}
 
 #[derive(Clone, Debug)]
 pub enum VirtualAddr {
@@ -159,9 +102,11 @@ impl ExprCtx {
     ) where
         F: FnOnce(&mut FuncCtx, Addr, Option<Pos>),
     {
+        fctx.set_depth(Some(self.cur_reg));
         match self.mode.clone() {
             ExprMode::Load => {
                 self.load_addr = self.inc_cur_reg();
+                fctx.set_depth(Some(self.cur_reg));
                 f(fctx, self.load_addr, pos);
             }
             ExprMode::Store(va, _) => match va {
@@ -171,6 +116,7 @@ impl ExprCtx {
                 }
                 _ => {
                     let d = self.inc_cur_reg();
+                    fctx.set_depth(Some(self.cur_reg));
                     f(fctx, d, pos);
                     Self::cast_to_iface(cast_index, fctx, d, d, pos);
                     fctx.emit_assign(va.clone(), d, None, pos);
@@ -179,6 +125,7 @@ impl ExprCtx {
             },
             ExprMode::Discard => {
                 self.load_addr = self.inc_cur_reg();
+                fctx.set_depth(Some(self.cur_reg));
                 f(fctx, self.load_addr, pos);
                 self.dec_cur_reg(); // done with the reg
             }
@@ -193,6 +140,7 @@ impl ExprCtx {
         cast_index: Option<OpIndex>,
         pos: Option<Pos>,
     ) {
+        fctx.set_depth(Some(self.cur_reg));
         match self.mode.clone() {
             ExprMode::Load => {
                 self.load_addr = src;
@@ -205,6 +153,7 @@ impl ExprCtx {
                 }
                 _ => {
                     let reg = self.inc_cur_reg();
+                    fctx.set_depth(Some(self.cur_reg));
                     let src = if Self::cast_to_iface(cast_index, fctx, reg, src, pos) {
                         reg
                     } else {
@@ -219,7 +168,7 @@ impl ExprCtx {
     }
 
     pub fn inc_cur_reg(&mut self) -> Addr {
-        let r = Addr::Regsiter(self.cur_reg);
+        let r = Addr::Register(self.cur_reg);
         self.cur_reg += 1;
         r
     }
@@ -246,96 +195,16 @@ impl ExprCtx {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct InterInst {
-    pub op0: Opcode,
-    pub op1: Opcode,
-    pub t0: ValueType,
-    pub t1: ValueType,
-    pub d: Addr,
-    pub s0: Addr,
-    pub s1: Addr,
-}
-
-impl InterInst {
-    pub fn with_op(op: Opcode) -> Self {
-        InterInst {
-            op0: op,
-            op1: Opcode::VOID,
-            t0: ValueType::Void,
-            t1: ValueType::Void,
-            d: Addr::Void,
-            s0: Addr::Void,
-            s1: Addr::Void,
-        }
-    }
-
-    pub fn with_op_index(op: Opcode, d: Addr, s0: Addr, s1: Addr) -> Self {
-        Self::with_op_t_index(op, None, None, d, s0, s1)
-    }
-
-    pub fn with_op_t(op: Opcode, t0: Option<ValueType>, t1: Option<ValueType>) -> Self {
-        Self::with_op_t_index(op, t0, t1, Addr::Void, Addr::Void, Addr::Void)
-    }
-
-    pub fn with_op_t_index(
-        op: Opcode,
-        t0: Option<ValueType>,
-        t1: Option<ValueType>,
-        d: Addr,
-        s0: Addr,
-        s1: Addr,
-    ) -> Self {
-        Self {
-            op0: op,
-            op1: Opcode::VOID,
-            t0: t0.unwrap_or(ValueType::Void),
-            t1: t1.unwrap_or(ValueType::Void),
-            d,
-            s0,
-            s1,
-        }
-    }
-
-    pub fn set_op1_with_t(&mut self, t: ValueType) {
-        self.op1 = unsafe { std::mem::transmute(t) }
-    }
-
-    pub fn into_runtime_inst(
-        self,
-        reg_base: usize,
-        ast_objs: &AstObjects,
-        packages: &PackageObjs,
-        inst_index: usize,
-        labels: &Map<TCObjKey, usize>,
-        cst_map: &Map<usize, usize>,
-    ) -> Instruction {
-        Instruction {
-            op0: self.op0,
-            op1: self.op1,
-            t0: self.t0,
-            t1: self.t1,
-            d: self
-                .d
-                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map),
-            s0: self
-                .s0
-                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map),
-            s1: self
-                .s1
-                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map),
-        }
-    }
-}
-
 pub enum RightHandSide<'a> {
     Nothing,
     Values(&'a Vec<Expr>),
     Range(&'a Expr),
+    // the params of the yield function of a range over a function
+    YieldParams(&'a [(Addr, TCTypeKey)]),
     SelectRecv(Addr, bool),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CallStyle {
     Default,
     Async,
@@ -356,15 +225,31 @@ pub(crate) struct FuncCtx<'c> {
     pub f_key: FunctionKey,
     pub tc_key: Option<TCTypeKey>, // for casting return values to interfaces
     consts: &'c Consts,
-
-    code: Vec<InterInst>,
-    pos: Vec<Option<usize>>,
-    pub up_ptrs: Vec<ValueDesc>,
-    local_zeros: Vec<GosValue>,
+    // the code being generated
+    pub ir: IrFunc,
 
     entities: Map<TCObjKey, Addr>,
     uv_entities: Map<TCObjKey, Addr>,
-    local_alloc: usize,
+    // locals that closures or pointers refer to
+    captured: Vec<usize>,
+    // the number of range loops around the code being generated
+    pub range_depth: OpIndex,
+    // the body of a range over a function, wrapped in the yield function
+    pub range_body: bool,
+    // the flag set by a return in the body of a range over a function
+    pub range_return: Option<Addr>,
+    // the flag of a yield function set when it returns false
+    pub range_done: Option<VirtualAddr>,
+    // the number of yield functions this one is in, itself included
+    pub range_level: OpIndex,
+    // the positions of the braces of the body of a range over a function
+    pub range_span: Option<(usize, usize)>,
+    // the jumps out of the body of a range over a function, with the branches
+    // they're for, taken after the function ranged over returns
+    pub range_exits: Vec<(usize, Token, TCObjKey)>,
+    // the code of the jump out of the body of a range over a function, set
+    // before the yield function returns false, 0 for none
+    pub range_exit: Option<Addr>,
 }
 
 impl<'a> FuncCtx<'a> {
@@ -373,13 +258,18 @@ impl<'a> FuncCtx<'a> {
             f_key,
             tc_key,
             consts,
-            code: vec![],
-            pos: vec![],
-            up_ptrs: vec![],
-            local_zeros: vec![],
+            ir: IrFunc::new(f_key),
             entities: Map::new(),
             uv_entities: Map::new(),
-            local_alloc: 0,
+            captured: vec![],
+            range_depth: 0,
+            range_body: false,
+            range_return: None,
+            range_done: None,
+            range_level: 0,
+            range_span: None,
+            range_exits: vec![],
+            range_exit: None,
         }
     }
 
@@ -387,17 +277,28 @@ impl<'a> FuncCtx<'a> {
         funcs[self.f_key].is_ctor()
     }
 
-    pub fn offset(&self, loc: usize) -> OpIndex {
-        // todo: don't crash if OpIndex overflows
-        OpIndex::try_from((self.code.len() - loc) as isize).unwrap()
+    pub fn next_code_index(&self) -> usize {
+        self.ir.next_code_index()
     }
 
-    pub fn next_code_index(&self) -> usize {
-        self.code.len()
+    pub fn pkg_member_index(&mut self, pkg: PackageKey, name: &str) -> Addr {
+        self.ir.pkg_member_index(pkg, name)
+    }
+
+    pub fn new_label(&mut self) -> usize {
+        self.ir.new_label()
+    }
+
+    pub fn bind_label(&mut self, label: usize, offset: usize) {
+        self.ir.bind_label(label, offset)
+    }
+
+    pub fn label_at(&mut self, offset: usize) -> Addr {
+        self.ir.label_at(offset)
     }
 
     pub fn inst_mut(&mut self, i: usize) -> &mut InterInst {
-        self.code.get_mut(i).unwrap()
+        self.ir.inst_mut(i)
     }
 
     pub fn entity_index(&self, entity: &TCObjKey) -> Option<&Addr> {
@@ -432,26 +333,33 @@ impl<'a> FuncCtx<'a> {
     }
 
     pub fn add_local(&mut self, entity: Option<TCObjKey>, zero_val: Option<GosValue>) -> Addr {
-        let addr = Addr::LocalVar(self.local_alloc);
+        let addr = self.ir.add_local(zero_val);
         if let Some(key) = entity {
             let old = self.entities.insert(key, addr);
             assert_eq!(old, None);
         };
-        self.local_alloc += 1;
+        addr
+    }
+
+    pub fn local_count(&self) -> usize {
+        self.ir.local_count()
+    }
 
-        if let Some(zero) = zero_val {
-            self.local_zeros.push(zero);
+    pub fn capture_local(&mut self, index: usize) {
+        if !self.captured.contains(&index) {
+            self.captured.push(index);
         }
-        addr
+    }
+
+    pub fn any_captured(&self, locals: std::ops::Range<usize>) -> bool {
+        self.captured.iter().any(|x| locals.contains(x))
     }
 
     pub(crate) fn add_upvalue(&mut self, entity: &TCObjKey, uv: ValueDesc) -> VirtualAddr {
         let addr = match self.uv_entities.get(entity) {
             Some(i) => *i,
             None => {
-                self.up_ptrs.push(uv);
-                let i = (self.up_ptrs.len() - 1).try_into().unwrap();
-                let et = Addr::Imm(i);
+                let et = self.add_upvalue_desc(uv).as_up_value_addr();
                 self.uv_entities.insert(*entity, et);
                 et
             }
@@ -459,6 +367,23 @@ impl<'a> FuncCtx<'a> {
         VirtualAddr::UpValue(addr)
     }
 
+    /// Adds an upvalue by the local it refers to, for the ones that are not
+    /// variables, like the results of the function a range over a function is in.
+    pub(crate) fn add_upvalue_desc(&mut self, uv: ValueDesc) -> VirtualAddr {
+        let found =
+            self.ir.up_ptrs.iter().position(|x| {
+                x.func == uv.func && x.index == uv.index && x.is_local == uv.is_local
+            });
+        let i = match found {
+            Some(i) => i,
+            None => {
+                self.ir.up_ptrs.push(uv);
+                self.ir.up_ptrs.len() - 1
+            }
+        };
+        VirtualAddr::UpValue(Addr::Imm(i.try_into().unwrap()))
+    }
+
     pub(crate) fn add_params(
         &mut self,
         fl: &FieldList,
@@ -589,7 +514,7 @@ impl<'a> FuncCtx<'a> {
         let inst = InterInst::with_op_index(
             Opcode::LITERAL,
             d,
-            Addr::Regsiter(begin),
+            Addr::Register(begin),
             Addr::Imm(count as OpIndex),
         );
         self.emit_inst(inst, pos);
@@ -639,21 +564,30 @@ impl<'a> FuncCtx<'a> {
         self.emit_inst(inst, pos);
     }
 
+    /// `recv_slot` tells that the register after the return values is left
+    /// for the receiver, i.e. it's a method call like `x.M()`.
     pub fn emit_call(
         &mut self,
         cls: Addr,
         stack_base: usize,
+        recv_slot: bool,
         style: CallStyle,
         pos: Option<usize>,
     ) {
         let flag = style.into_flag();
+        // a defer in the body of a range over a function is for the function
+        // the loop is in, the number of yield functions up
+        let owner = match style {
+            CallStyle::Defer => Addr::Imm(self.range_level),
+            _ => Addr::Void,
+        };
         let inst = InterInst::with_op_t_index(
             Opcode::CALL,
             Some(flag),
-            None,
+            recv_slot.then_some(ValueType::FlagA),
             cls,
-            Addr::Regsiter(stack_base),
-            Addr::Void,
+            Addr::Register(stack_base),
+            owner,
         );
         self.emit_inst(inst, pos);
     }
@@ -680,8 +614,8 @@ impl<'a> FuncCtx<'a> {
         let pkg_addr = self.add_package(pkg);
         let zero_addr = Addr::Const(self.consts.add_comparable(0i32.into()));
         let imm0 = Addr::Imm(0);
-        let reg0 = Addr::Regsiter(0);
-        let reg1 = Addr::Regsiter(1);
+        let reg0 = Addr::Register(0);
+        let reg1 = Addr::Register(1);
         let cd = vec![
             InterInst::with_op_index(Opcode::LOAD_PKG, reg0, pkg_addr, imm0),
             InterInst::with_op_t_index(
@@ -717,40 +651,32 @@ impl<'a> FuncCtx<'a> {
         }
     }
 
-    pub fn into_runtime_func(
-        self,
-        asto: &AstObjects,
-        vmctx: &mut CodeGenVMCtx,
-        labels: &Map<TCObjKey, usize>,
-        cst_map: &Map<usize, usize>,
-    ) {
-        let code: Vec<Instruction> = self
-            .code
-            .into_iter()
-            .enumerate()
-            .map(|(i, x)| {
-                x.into_runtime_inst(self.local_alloc, asto, vmctx.packages(), i, labels, cst_map)
-            })
-            .collect();
-        let func = &mut vmctx.functions_mut()[self.f_key];
-        func.pos = self
-            .pos
-            .into_iter()
-            .map(|x| {
-                x.map(|y| {
-                    assert!(y <= u32::MAX as usize);
-                    y as u32
-                })
-            })
-            .collect();
-        func.up_ptrs = self.up_ptrs;
-        func.max_write_index = Instruction::max_write_index(&code);
-        func.local_zeros = self.local_zeros;
-        func.code = code;
+    /// The code generated, the rest is only needed to generate it.
+    pub fn into_ir(self) -> IrFunc {
+        self.ir
+    }
+
+    /// Tells the verifier the register depth of the expression being
+    /// generated, or that there's none, as in the code generated outside of
+    /// any expression. It does nothing without the `verify` feature.
+    #[allow(unused_variables)]
+    pub fn set_depth(&mut self, depth: Option<usize>) {
+        #[cfg(feature = "verify")]
+        {
+            self.ir.verifier.depth = depth;
+        }
+    }
+
+    /// Tells the verifier the function has a goto, so its loops may not be
+    /// structured. It does nothing without the `verify` feature.
+    pub fn set_goto(&mut self) {
+        #[cfg(feature = "verify")]
+        {
+            self.ir.verifier.goto = true;
+        }
     }
 
     pub fn emit_inst(&mut self, i: InterInst, pos: Option<usize>) {
-        self.code.push(i);
-        self.pos.push(pos);
+        self.ir.emit_inst(i, pos);
     }
 }
@@ -4,10 +4,10 @@
 
 use super::branch::BranchHelper;
 use super::codegen::*;
-use super::consts::*;
+use super::consts::Consts;
 use super::context::*;
 use super::package::PkgHelper;
-use super::types::{TypeCache, TypeLookup};
+use super::types::TypeCache;
 use go_parser::ast::Ident;
 use go_parser::{AstObjects, ErrorList, FileSet, IdentKey, Map};
 use go_types::{
@@ -19,11 +19,37 @@ use go_vm::*;
 use std::path::Path;
 use std::vec;
 
+/// The function generated to evaluate an expression
+const EVAL_FUNC: &str = "__goscript_eval__";
+
 pub fn parse_check_gen<S: SourceRead>(
     path: &Path,
     tconfig: &TraceConfig,
     reader: &S,
     debug_info: bool,
+) -> Result<Bytecode, ErrorList> {
+    parse_check_gen_impl(path, tconfig, reader, debug_info, None)
+}
+
+/// Compiles `expr` in the scope of the package at `path`, the entry function
+/// of the Bytecode initializes the package and returns the value of `expr`
+/// as an `interface{}`, instead of calling main.
+pub fn parse_check_gen_eval<S: SourceRead>(
+    path: &Path,
+    tconfig: &TraceConfig,
+    reader: &S,
+    expr: &str,
+    debug_info: bool,
+) -> Result<Bytecode, ErrorList> {
+    parse_check_gen_impl(path, tconfig, reader, debug_info, Some(expr))
+}
+
+fn parse_check_gen_impl<S: SourceRead>(
+    path: &Path,
+    tconfig: &TraceConfig,
+    reader: &S,
+    debug_info: bool,
+    eval: Option<&str>,
 ) -> Result<Bytecode, ErrorList> {
     let mut fset = FileSet::new();
 
@@ -33,28 +59,54 @@ pub fn parse_check_gen<S: SourceRead>(
     let pkgs = &mut Map::new();
     let el = ErrorList::new();
 
-    let importer = &mut Importer::new(
+    let mut importer = Importer::new(
         &tconfig, reader, &mut fset, pkgs, results, ast_objs, tc_objs, &el, 0,
     );
+    if let Some(expr) = eval {
+        let body = format!(
+            "func {}() interface{{}} {{\n\treturn {}\n}}\n",
+            EVAL_FUNC, expr
+        );
+        importer = importer.with_generated_file("<eval>".to_owned(), body);
+    }
     let key = ImportKey::new(
         path.to_str().unwrap(),
         reader.working_dir().to_str().unwrap(),
     );
     let main_pkg = importer.import(&key);
+    if el.len() == 0 {
+        // packages imported from export data cannot be compiled
+        let unsafe_pkg = *tc_objs.universe().unsafe_pkg();
+        for i in 0..tc_objs.pkgs.vec().len() {
+            let pkey = TCPackageKey::from(i);
+            if pkey != unsafe_pkg && !results.contains_key(&pkey) {
+                let path = tc_objs.pkgs[pkey].path();
+                let msg = format!("package {} has no source code to compile", path);
+                el.add(None, msg, false, false);
+            }
+        }
+    }
+    // this also refuses partial ASTs, the parser reports every Bad node
+    // it leaves in place of the code it couldn't parse
     if el.len() > 0 {
         Err(el)
     } else {
         let blank_ident = ast_objs.idents.insert(Ident::blank(0));
-        let main_ident = ast_objs.idents.insert(Ident::with_str(0, "main"));
-        Ok(gen_byte_code(
+        let entry_func = eval.map_or("main", |_| EVAL_FUNC);
+        gen_byte_code(
             ast_objs,
             tc_objs,
             results,
             main_pkg.unwrap(),
-            main_ident,
+            entry_func,
+            eval.is_some(),
             blank_ident,
             debug_info.then_some(fset),
-        ))
+        )
+        .map_err(|e| {
+            el.add(None, e.to_string(), false, false);
+            el
+        })
     }
 }
 
@@ -63,13 +115,13 @@ fn gen_byte_code(
     tc_objs: &TCObjects,
     checker_result: &Map<TCPackageKey, TypeInfo>,
     tc_main_pkg: TCPackageKey,
-    main_ident: IdentKey,
+    entry_func: &str,
+    main_returns: bool,
     blank_ident: IdentKey,
     fset: Option<FileSet>,
-) -> Bytecode {
-    let vm_objs = VMObjects::new();
-    let mut vmctx = CodeGenVMCtx::new(vm_objs);
-    let consts = Consts::new();
+) -> Result<Bytecode, BuildError> {
+    let mut prog = IrProgram::new();
+    let (vmctx, consts) = (&mut prog.vmctx, &prog.consts);
     let mut iface_selector = IfaceSelector::new();
     let mut struct_selector = StructSelector::new();
     let mut pkg_map = Map::new();
@@ -84,15 +136,15 @@ fn gen_byte_code(
     }
 
     let main_pkg = pkg_map[&tc_main_pkg];
-    let entry = gen_entry_func(&mut vmctx, &consts, main_pkg, main_ident);
+    let entry = gen_entry_func(vmctx, consts, main_pkg, entry_func, main_returns);
     let entry_key = entry.f_key;
     result_funcs.push(entry);
 
     for (tcpkg, ti) in checker_result.iter() {
         let mut pkg_helper = PkgHelper::new(ast_objs, tc_objs, &pkg_map);
         let cgen = CodeGen::new(
-            &mut vmctx,
-            &consts,
+            vmctx,
+            consts,
             ast_objs,
             tc_objs,
             &ti,
@@ -107,46 +159,43 @@ fn gen_byte_code(
         result_funcs.append(&mut cgen.gen_with_files(&ti.ast_files, *tcpkg));
     }
 
-    let (consts, cst_map) = consts.get_runtime_consts(&mut vmctx);
-    for f in result_funcs.into_iter() {
-        f.into_runtime_func(ast_objs, &mut vmctx, branch_helper.labels(), &cst_map);
+    let funcs: Vec<IrFunc> = result_funcs.into_iter().map(FuncCtx::into_ir).collect();
+    for f in funcs.into_iter() {
+        prog.add_func(f);
     }
-
-    let dummy_ti = TypeInfo::new();
-    let mut lookup = TypeLookup::new(tc_objs, &dummy_ti, &mut type_cache);
-    let iface_binding = iface_selector
-        .result()
-        .into_iter()
-        .map(|x| lookup.iface_binding_info(x, &mut vmctx))
-        .collect();
-
-    Bytecode::new(
-        vmctx.into_vmo(),
-        consts,
-        iface_binding,
-        struct_selector.result(),
+    prog.into_bytecode(
         entry_key,
         main_pkg,
+        iface_selector.result(),
+        struct_selector.result(),
         fset,
     )
 }
 
-// generate the entry function for Bytecode
+// generate the entry function for Bytecode, if `main_returns`, main returns
+// an interface{}, which the entry function returns too
 fn gen_entry_func<'a, 'c>(
     vmctx: &'a mut CodeGenVMCtx,
     consts: &'c Consts,
     pkg: PackageKey,
-    main_ident: IdentKey,
+    entry_func: &str,
+    main_returns: bool,
 ) -> FuncCtx<'c> {
-    let fmeta = vmctx.prim_meta().default_sig;
+    let fmeta = match main_returns {
+        true => {
+            let results = vec![vmctx.prim_meta().empty_iface];
+            Meta::new_sig(None, vec![], results, None, vmctx.metas_mut())
+        }
+        false => vmctx.prim_meta().default_sig,
+    };
     let fobj = vmctx.function_with_meta(None, fmeta.clone(), FuncFlag::Default);
     let fkey = *fobj.as_function();
     let mut fctx = FuncCtx::new(fkey, None, consts);
     fctx.emit_import(pkg, None);
     let pkg_addr = fctx.add_package(pkg);
-    let index = Addr::PkgMemberIndex(pkg, main_ident);
-    fctx.emit_load_pkg(Addr::Regsiter(0), pkg_addr, index, None);
-    fctx.emit_call(Addr::Regsiter(0), 0, CallStyle::Default, None);
+    let index = fctx.pkg_member_index(pkg, entry_func);
+    fctx.emit_load_pkg(Addr::Register(0), pkg_addr, index, None);
+    fctx.emit_call(Addr::Register(0), 0, false, CallStyle::Default, None);
     fctx.emit_return(None, None, vmctx.functions());
     fctx
 }
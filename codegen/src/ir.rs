@@ -0,0 +1,552 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The intermediate representation the typed AST is lowered to, before it's
+//! lowered to the instructions of the VM: instructions like the VM's whose
+//! operands are `Addr`s, some of them resolved only when the whole program
+//! is generated, see `Lowering`.
+//!
+//! It doesn't refer to the AST or to the type checker, a package member is
+//! named by its name and a label is a number of the function. The code
+//! generator emits the code of each function into an `IrFunc` and hands them
+//! to an `IrProgram`, which lowers them all to `Bytecode`, another frontend
+//! does the same without going through Go source. It refuses the functions
+//! `go_vm::BytecodeBuilder` refuses with the same `BuildError`s, the builder
+//! checks each function as it's finished, the IR only when it's lowered:
+//!
+//! ```
+//! use go_codegen::ir::*;
+//! use go_vm::types::*;
+//! use go_vm::*;
+//!
+//! let mut prog = IrProgram::new();
+//! let pkg = prog.new_package("main");
+//! let mint = prog.vm_ctx().prim_meta().mint;
+//! let sig = Meta::new_sig(None, vec![], vec![mint], None, prog.vm_ctx().metas_mut());
+//! let mut f = prog.new_function(Some(pkg), sig, FuncFlag::Default);
+//! let result = f.add_local(None);
+//! let (one, two) = (prog.add_const(1isize.into()), prog.add_const(2isize.into()));
+//! f.emit_inst(InterInst::with_op_t_index(Opcode::ADD, Some(ValueType::Int), None, result, one, two), None);
+//! f.emit_inst(InterInst::with_op_t(Opcode::RETURN, Some(ValueType::FlagA), None), None);
+//! let entry = prog.add_func(f);
+//! let code = prog.into_bytecode(entry, pkg, vec![], vec![], None).unwrap();
+//! let results = run_entry(&code, &FfiFactory::new(), &Limits::default(), &RunConfig::default())
+//!     .unwrap();
+//! assert_eq!(*results[0].as_int(), 3);
+//! ```
+
+use crate::consts::Consts;
+use go_parser::Map;
+use go_vm::types::*;
+use go_vm::*;
+use std::convert::TryFrom;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Addr {
+    Const(usize),
+    LocalVar(usize),
+    Register(usize),
+    Imm(OpIndex),
+    PkgMemberIndex(PackageKey, usize), // the name in `Lowering::names`, deferred resolve
+    Label(usize),                      // the label in `Lowering::labels`, deferred resolve
+    UntypedNil,                        // will be typed when assigned to var
+    Void,
+}
+
+impl Addr {
+    pub fn as_var_index(self) -> usize {
+        match self {
+            Self::LocalVar(i) => i,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn as_reg_index(self) -> usize {
+        match self {
+            Self::Register(i) => i,
+            _ => unreachable!(),
+        }
+    }
+
+    /// `base` is the index the VM jumps from, see `IrFunc::jump_bases`.
+    fn into_index(self, l: &Lowering, base: usize) -> Result<OpIndex, BuildError> {
+        // Zero values are the first batch of consts
+        Ok(match self {
+            Self::Const(i) => -(l.cst_map[&i] as OpIndex) - 1,
+            Self::LocalVar(i) => i as OpIndex,
+            Self::Register(i) => (l.reg_base + i) as OpIndex,
+            Self::PkgMemberIndex(key, name) => {
+                let name = &l.names[name];
+                match l.packages[key].member_index(name) {
+                    Some(i) => *i,
+                    None => return Err(BuildError::UnknownMember(l.func, name.clone())),
+                }
+            }
+            Self::Label(label) => match l.labels.get(label).copied().flatten() {
+                Some(offset) if offset < l.code_len => (offset as OpIndex) - (base as OpIndex),
+                _ => return Err(BuildError::UnboundLabel(l.func, label.into())),
+            },
+            Self::Imm(i) => i,
+            Self::UntypedNil => unreachable!(),
+            Self::Void => std::i32::MAX,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct InterInst {
+    pub op0: Opcode,
+    pub op1: Opcode,
+    pub t0: ValueType,
+    pub t1: ValueType,
+    pub d: Addr,
+    pub s0: Addr,
+    pub s1: Addr,
+}
+
+impl InterInst {
+    pub fn with_op(op: Opcode) -> Self {
+        InterInst {
+            op0: op,
+            op1: Opcode::VOID,
+            t0: ValueType::Void,
+            t1: ValueType::Void,
+            d: Addr::Void,
+            s0: Addr::Void,
+            s1: Addr::Void,
+        }
+    }
+
+    pub fn with_op_index(op: Opcode, d: Addr, s0: Addr, s1: Addr) -> Self {
+        Self::with_op_t_index(op, None, None, d, s0, s1)
+    }
+
+    pub fn with_op_t(op: Opcode, t0: Option<ValueType>, t1: Option<ValueType>) -> Self {
+        Self::with_op_t_index(op, t0, t1, Addr::Void, Addr::Void, Addr::Void)
+    }
+
+    pub fn with_op_t_index(
+        op: Opcode,
+        t0: Option<ValueType>,
+        t1: Option<ValueType>,
+        d: Addr,
+        s0: Addr,
+        s1: Addr,
+    ) -> Self {
+        Self {
+            op0: op,
+            op1: Opcode::VOID,
+            t0: t0.unwrap_or(ValueType::Void),
+            t1: t1.unwrap_or(ValueType::Void),
+            d,
+            s0,
+            s1,
+        }
+    }
+
+    pub fn set_op1_with_t(&mut self, t: ValueType) {
+        self.op1 = unsafe { std::mem::transmute(t) }
+    }
+
+    /// `base` is the index the labels the instruction jumps to are resolved
+    /// from.
+    pub fn into_runtime_inst(self, l: &Lowering, base: usize) -> Result<Instruction, BuildError> {
+        Ok(Instruction {
+            op0: self.op0,
+            op1: self.op1,
+            t0: self.t0,
+            t1: self.t1,
+            d: self.d.into_index(l, base)?,
+            s0: self.s0.into_index(l, base)?,
+            s1: self.s1.into_index(l, base)?,
+        })
+    }
+}
+
+/// What the addresses of a function are resolved with, once all the
+/// packages have their members and the constants are final.
+pub struct Lowering<'a> {
+    /// The function lowered, for the errors.
+    pub func: FunctionKey,
+    /// The number of instructions, the labels are bound before the end.
+    pub code_len: usize,
+    /// The registers are above the locals.
+    pub reg_base: usize,
+    pub packages: &'a PackageObjs,
+    /// The names of the package members the function refers to.
+    pub names: &'a [String],
+    /// The labels of the function, bound to the index of an instruction.
+    pub labels: &'a [Option<usize>],
+    /// The indices of the runtime constants, by the indices of `Consts`.
+    pub cst_map: &'a Map<usize, usize>,
+}
+
+/// The code of a function being generated, with the labels and the locals
+/// it refers to. The results, then the parameters, are its first locals.
+pub struct IrFunc {
+    pub f_key: FunctionKey,
+    code: Vec<InterInst>,
+    pos: Vec<Option<usize>>,
+    /// The upvalues, `Addr::Imm` of the index is the operand that refers to one.
+    pub up_ptrs: Vec<ValueDesc>,
+    local_zeros: Vec<GosValue>,
+    // the names of the package members `Addr::PkgMemberIndex` refers to
+    names: Vec<String>,
+    // the indices of the names in `names`
+    name_indices: Map<String, usize>,
+    // the labels `Addr::Label` refers to, bound to an instruction or not yet
+    labels: Vec<Option<usize>>,
+    local_alloc: usize,
+    #[cfg(feature = "verify")]
+    pub(crate) verifier: crate::verify::Verifier,
+}
+
+impl IrFunc {
+    pub fn new(f_key: FunctionKey) -> IrFunc {
+        IrFunc {
+            f_key,
+            code: vec![],
+            pos: vec![],
+            up_ptrs: vec![],
+            local_zeros: vec![],
+            names: vec![],
+            name_indices: Map::new(),
+            labels: vec![],
+            local_alloc: 0,
+            #[cfg(feature = "verify")]
+            verifier: Default::default(),
+        }
+    }
+
+    /// The number of instructions from the one at `loc` to the end.
+    pub fn offset(&self, loc: usize) -> OpIndex {
+        // todo: don't crash if OpIndex overflows
+        OpIndex::try_from((self.code.len() - loc) as isize).unwrap()
+    }
+
+    pub fn next_code_index(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn inst_mut(&mut self, i: usize) -> &mut InterInst {
+        self.code.get_mut(i).unwrap()
+    }
+
+    /// `pos` is the position of the source the instruction is generated for,
+    /// if any.
+    pub fn emit_inst(&mut self, i: InterInst, pos: Option<usize>) {
+        #[cfg(feature = "verify")]
+        self.verifier
+            .check(&i, self.code.len(), self.local_alloc, pos);
+        self.code.push(i);
+        self.pos.push(pos);
+    }
+
+    /// The address of the index of the member `name` of `pkg`, resolved when
+    /// the function is lowered, as the members are not all known before.
+    pub fn pkg_member_index(&mut self, pkg: PackageKey, name: &str) -> Addr {
+        let i = match self.name_indices.get(name) {
+            Some(i) => *i,
+            None => {
+                let i = self.names.len();
+                self.names.push(name.to_owned());
+                self.name_indices.insert(name.to_owned(), i);
+                i
+            }
+        };
+        Addr::PkgMemberIndex(pkg, i)
+    }
+
+    /// A new label, to jump to with `Addr::Label` before it's bound.
+    pub fn new_label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    /// Binds the label to the instruction at `offset`.
+    pub fn bind_label(&mut self, label: usize, offset: usize) {
+        debug_assert!(self.labels[label].is_none());
+        self.labels[label] = Some(offset);
+    }
+
+    /// The address to jump to the instruction at `offset` with.
+    pub fn label_at(&mut self, offset: usize) -> Addr {
+        let label = self.new_label();
+        self.bind_label(label, offset);
+        Addr::Label(label)
+    }
+
+    /// Adds a local, the zero value is the one it's set to when the function
+    /// is called, none for the results and the parameters.
+    pub fn add_local(&mut self, zero_val: Option<GosValue>) -> Addr {
+        let addr = Addr::LocalVar(self.local_alloc);
+        self.local_alloc += 1;
+        if let Some(zero) = zero_val {
+            self.local_zeros.push(zero);
+        }
+        addr
+    }
+
+    pub fn local_count(&self) -> usize {
+        self.local_alloc
+    }
+
+    /// The index the VM jumps from for every instruction, the next one, but
+    /// RANGE jumps from after its extra word, and SELECT and its entries from
+    /// the first block, after the entries.
+    fn jump_bases(code: &[InterInst]) -> Vec<usize> {
+        let mut bases: Vec<usize> = (1..=code.len()).collect();
+        for (i, inst) in code.iter().enumerate() {
+            match (inst.op0, inst.s0) {
+                (Opcode::RANGE, _) => bases[i] = i + 2,
+                (Opcode::SELECT, Addr::Imm(n)) => {
+                    let blocks = i + 1 + n as usize;
+                    bases[i..blocks].fill(blocks);
+                }
+                _ => {}
+            }
+        }
+        bases
+    }
+
+    /// Lowers the code to the instructions of the VM and puts it in the
+    /// function, fails if it jumps to a label that's not bound or refers to
+    /// a package member that's not added.
+    pub(crate) fn lower(
+        self,
+        vmctx: &mut CodeGenVMCtx,
+        cst_map: &Map<usize, usize>,
+    ) -> Result<(), BuildError> {
+        #[cfg(feature = "verify")]
+        self.verifier
+            .check_flow(&self.code, &self.labels, &self.pos);
+        let lowering = Lowering {
+            func: self.f_key,
+            code_len: self.code.len(),
+            reg_base: self.local_alloc,
+            packages: vmctx.packages(),
+            names: &self.names,
+            labels: &self.labels,
+            cst_map,
+        };
+        let bases = Self::jump_bases(&self.code);
+        let code: Vec<Instruction> = self
+            .code
+            .into_iter()
+            .zip(bases)
+            .map(|(x, base)| x.into_runtime_inst(&lowering, base))
+            .collect::<Result<_, _>>()?;
+        let func = &mut vmctx.functions_mut()[self.f_key];
+        func.pos = self
+            .pos
+            .into_iter()
+            .map(|x| {
+                x.map(|y| {
+                    assert!(y <= u32::MAX as usize);
+                    y as u32
+                })
+            })
+            .collect();
+        func.up_ptrs = self.up_ptrs;
+        func.max_write_index = Instruction::max_write_index(&code);
+        func.local_zeros = self.local_zeros;
+        func.code = code;
+        Ok(())
+    }
+}
+
+/// The packages, functions and constants of a program, the functions are
+/// lowered when they're all added, see `into_bytecode`.
+pub struct IrProgram {
+    pub(crate) vmctx: CodeGenVMCtx,
+    pub(crate) consts: Consts,
+    funcs: Vec<IrFunc>,
+}
+
+impl IrProgram {
+    pub fn new() -> IrProgram {
+        IrProgram {
+            vmctx: CodeGenVMCtx::new(VMObjects::new()),
+            consts: Consts::new(),
+            funcs: vec![],
+        }
+    }
+
+    /// The objects of the VM, to make metadata and add package members with.
+    pub fn vm_ctx(&mut self) -> &mut CodeGenVMCtx {
+        &mut self.vmctx
+    }
+
+    pub fn new_package(&mut self, name: &str) -> PackageKey {
+        self.vmctx
+            .packages_mut()
+            .insert(PackageObj::new(name.to_owned()))
+    }
+
+    pub fn new_function(&mut self, pkg: Option<PackageKey>, sig: Meta, flag: FuncFlag) -> IrFunc {
+        let f = self.vmctx.function_with_meta(pkg, sig, flag);
+        IrFunc::new(*f.as_function())
+    }
+
+    /// Adds a constant, which is nil or comparable.
+    pub fn add_const(&self, val: GosValue) -> Addr {
+        if val.is_nil() {
+            Addr::Const(self.consts.add_nil(val))
+        } else {
+            Addr::Const(self.consts.add_comparable(val))
+        }
+    }
+
+    /// Adds the function, to be lowered with the others.
+    pub fn add_func(&mut self, f: IrFunc) -> FunctionKey {
+        let key = f.f_key;
+        self.funcs.push(f);
+        key
+    }
+
+    /// Lowers the functions and returns the bytecode, which runs `entry` in
+    /// the context of `main_pkg`. `iface_links` and `indices` are the
+    /// interfaces and the embedded struct fields the code refers to by index.
+    /// Fails like `BytecodeBuilder::build` does, if a function jumps to a
+    /// label that's not bound or refers to a package member that's not
+    /// added, or if the bytecode doesn't link, see `Bytecode::link`.
+    pub fn into_bytecode(
+        mut self,
+        entry: FunctionKey,
+        main_pkg: PackageKey,
+        iface_links: Vec<(Meta, Meta)>,
+        indices: Vec<Vec<OpIndex>>,
+        file_set: Option<go_parser::FileSet>,
+    ) -> Result<Bytecode, BuildError> {
+        let (consts, cst_map) = self.consts.get_runtime_consts(&mut self.vmctx);
+        for f in self.funcs.into_iter() {
+            f.lower(&mut self.vmctx, &cst_map)?;
+        }
+        Bytecode::new(
+            self.vmctx.into_vmo(),
+            consts,
+            iface_links,
+            indices,
+            entry,
+            main_pkg,
+            file_set,
+        )
+        .map_err(BuildError::Link)
+    }
+}
+
+impl Default for IrProgram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // the labels are resolved to offsets from the jumps and the registers are
+    // put above the locals added after the code that uses them
+    #[test]
+    fn test_loop() {
+        let mut prog = IrProgram::new();
+        let pkg = prog.new_package("main");
+        let mint = prog.vm_ctx().prim_meta().mint;
+        let sig = Meta::new_sig(None, vec![], vec![mint], None, prog.vm_ctx().metas_mut());
+        let mut f = prog.new_function(Some(pkg), sig, FuncFlag::Default);
+        let (one, five) = (prog.add_const(1isize.into()), prog.add_const(5isize.into()));
+        let int = Some(ValueType::Int);
+        let sum = f.add_local(None);
+        let (top, end) = (f.new_label(), f.new_label());
+        let cond = Addr::Register(0);
+        f.bind_label(top, f.next_code_index());
+        let i = f.add_local(Some(0isize.into()));
+        let lss = InterInst::with_op_t_index(Opcode::LSS, int, None, cond, i, five);
+        f.emit_inst(lss, None);
+        let exit =
+            InterInst::with_op_index(Opcode::JUMP_IF_NOT, Addr::Label(end), cond, Addr::Void);
+        f.emit_inst(exit, None);
+        let add = InterInst::with_op_t_index(Opcode::ADD_ASSIGN, int, None, sum, i, Addr::Void);
+        f.emit_inst(add, None);
+        let inc = InterInst::with_op_t_index(Opcode::ADD_ASSIGN, int, None, i, one, Addr::Void);
+        f.emit_inst(inc, None);
+        let back = InterInst::with_op_index(Opcode::JUMP, Addr::Label(top), Addr::Void, Addr::Void);
+        f.emit_inst(back, None);
+        f.bind_label(end, f.next_code_index());
+        f.emit_inst(
+            InterInst::with_op_t(Opcode::RETURN, Some(ValueType::FlagA), None),
+            None,
+        );
+        let entry = prog.add_func(f);
+        let code = prog
+            .into_bytecode(entry, pkg, vec![], vec![], None)
+            .unwrap();
+        let func = &code.objects.functions[entry];
+        // the register is the first slot above the two locals
+        assert_eq!(func.code[0].d, 2);
+        assert_eq!(func.code[1].d, 3);
+        assert_eq!(func.code[4].d, -5);
+        let results = run_entry(
+            &code,
+            &FfiFactory::new(),
+            &Limits::default(),
+            &RunConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(*results[0].as_int(), 10);
+    }
+
+    // bad inputs are refused like BytecodeBuilder refuses them, the verifier
+    // panics on them first
+    #[test]
+    #[cfg(not(feature = "verify"))]
+    fn test_lowering_errors() {
+        let ret = InterInst::with_op_t(Opcode::RETURN, Some(ValueType::FlagA), None);
+        let build = |emit: &dyn Fn(&mut IrFunc, PackageKey)| {
+            let mut prog = IrProgram::new();
+            let pkg = prog.new_package("main");
+            let sig = Meta::new_sig(None, vec![], vec![], None, prog.vm_ctx().metas_mut());
+            let mut f = prog.new_function(Some(pkg), sig, FuncFlag::Default);
+            emit(&mut f, pkg);
+            f.emit_inst(ret, None);
+            let entry = prog.add_func(f);
+            (entry, prog.into_bytecode(entry, pkg, vec![], vec![], None))
+        };
+
+        let (entry, re) = build(&|f, _| {
+            let l = f.new_label();
+            let jump =
+                InterInst::with_op_index(Opcode::JUMP, Addr::Label(l), Addr::Void, Addr::Void);
+            f.emit_inst(jump, None);
+        });
+        assert_eq!(re.err(), Some(BuildError::UnboundLabel(entry, 0.into())));
+
+        let (entry, re) = build(&|f, pkg| {
+            let member = f.pkg_member_index(pkg, "missing");
+            let load =
+                InterInst::with_op_index(Opcode::LOAD_PKG, Addr::Register(0), member, Addr::Void);
+            f.emit_inst(load, None);
+        });
+        assert_eq!(
+            re.err(),
+            Some(BuildError::UnknownMember(entry, "missing".to_owned()))
+        );
+    }
+
+    // RANGE jumps from after its extra word, SELECT and its entries from
+    // after the entries
+    #[test]
+    fn test_jump_bases() {
+        let imm = |op, n| InterInst::with_op_index(op, Addr::Void, Addr::Imm(n), Addr::Void);
+        let code = [
+            imm(Opcode::RANGE, 0),
+            InterInst::with_op(Opcode::VOID),
+            imm(Opcode::SELECT, 2),
+            InterInst::with_op(Opcode::VOID),
+            InterInst::with_op(Opcode::VOID),
+            InterInst::with_op(Opcode::RETURN),
+        ];
+        assert_eq!(IrFunc::jump_bases(&code), vec![2, 2, 5, 5, 5, 6]);
+    }
+}
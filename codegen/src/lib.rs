@@ -7,16 +7,22 @@
 //! # Feature
 //! - `async`: Channel and goroutine support
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
+//! - `verify`: Check every instruction against the register depth as it's emitted, and the jumps of every function, see `verify.rs`
 
 mod branch;
 mod consts;
 mod context;
+pub mod ir;
 //mod emit;
 mod package;
 //mod selector;
 mod codegen;
 mod entry;
 mod types;
+#[cfg(feature = "verify")]
+mod cfg;
+#[cfg(feature = "verify")]
+mod verify;
 
-pub use entry::parse_check_gen;
+pub use entry::{parse_check_gen, parse_check_gen_eval};
 pub use go_types::{SourceRead, TraceConfig};
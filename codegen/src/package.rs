@@ -53,7 +53,7 @@ impl<'a> PkgHelper<'a> {
         let tc_pkg = self.tc_objs.lobjs[okey].pkg().unwrap();
         let pkg = self.get_runtime_key(tc_pkg);
         let pkg_addr = fctx.add_comparable(FfiCtx::new_package(pkg));
-        let index_addr = Addr::PkgMemberIndex(pkg, ident);
+        let index_addr = fctx.pkg_member_index(pkg, &self.ast_objs.idents[ident].name);
         VirtualAddr::PackageMember(pkg_addr, index_addr)
     }
 
@@ -63,11 +63,11 @@ impl<'a> PkgHelper<'a> {
         files: &Vec<File>,
         ti: &TypeInfo,
     ) -> (Vec<IdentKey>, Vec<Rc<ValueSpec>>) {
+        // keyed by objects rather than names, as there can be many blank vars
         let mut orders = Map::new();
         for (i, init) in ti.init_order.iter().enumerate() {
             for okey in init.lhs.iter() {
-                let name = self.tc_objs.lobjs[*okey].name();
-                orders.insert(name, i);
+                orders.insert(*okey, i);
             }
         }
 
@@ -83,8 +83,8 @@ impl<'a> PkgHelper<'a> {
                                 match spec {
                                     Spec::Value(v) => {
                                         names.extend(v.names.iter());
-                                        let name = &self.ast_objs.idents[v.names[0]].name;
-                                        if let Some(order) = orders.get(name) {
+                                        let okey = ti.defs[&v.names[0]];
+                                        if let Some(order) = okey.and_then(|x| orders.get(&x)) {
                                             decls.push((v.clone(), order));
                                         }
                                     }
@@ -5,9 +5,9 @@
 use go_parser::ast::{Expr, Node, NodeId};
 use go_parser::{IdentKey, Map};
 use go_types::{
-    check::TypeInfo, typ::BasicType, typ::ChanDir, typ::Type, ConstValue, EntityType,
-    ObjKey as TCObjKey, OperandMode, PackageKey as TCPackageKey, SelectionKind as TCSelectionKind,
-    TCObjects, TypeKey as TCTypeKey,
+    check::TypeInfo, typ, typ::BasicInfo, typ::BasicType, typ::ChanDir, typ::Type, ConstValue,
+    EntityType, ObjKey as TCObjKey, OperandMode, PackageKey as TCPackageKey,
+    SelectionKind as TCSelectionKind, TCObjects, TypeKey as TCTypeKey,
 };
 use go_vm::types::*;
 use go_vm::*;
@@ -20,6 +20,9 @@ pub enum SelectionType {
     NonMethod,
     MethodNonPtrRecv,
     MethodPtrRecv,
+    /// A method expression like `T.m`, with whether the method has a pointer
+    /// receiver.
+    MethodExpr(bool),
 }
 
 pub(crate) struct TypeLookup<'a> {
@@ -121,6 +124,24 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// Returns true if the identifier refers to the predeclared nil, rather than
+    /// anything that happens to be named "nil".
+    pub fn is_nil_ident(&self, ikey: IdentKey) -> bool {
+        self.ti
+            .uses
+            .get(&ikey)
+            .map_or(false, |x| self.tc_objs.lobjs[*x].entity_type().is_nil())
+    }
+
+    /// Returns true if the expression is the predeclared nil, possibly parenthesized.
+    pub fn is_nil_expr(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Ident(ikey) => self.is_nil_ident(*ikey),
+            Expr::Paren(p) => self.is_nil_expr(&p.expr),
+            _ => false,
+        }
+    }
+
     pub fn expr_value_type(&self, e: &Expr) -> ValueType {
         let tv = self.ti.types.get(&e.id()).unwrap();
         if tv.mode == OperandMode::TypeExpr {
@@ -137,7 +158,7 @@ impl<'a> TypeLookup<'a> {
         vmctx: &mut CodeGenVMCtx,
     ) -> (ValueType, TCTypeKey) {
         let tc_type = self.expr_tc_type(&e);
-        let typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        let typ = self.array_deref(self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type));
         let meta = self.tc_type_to_meta(typ, vmctx);
         let metas = &vmctx.metas();
         match &metas[meta.key] {
@@ -157,6 +178,14 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// The length of the array if e is an array or a pointer to one
+    pub fn expr_array_len(&self, e: &Expr) -> Option<u64> {
+        let typ = self.array_deref(self.underlying_tc(self.expr_tc_type(e)));
+        self.tc_objs.types[typ]
+            .try_as_array()
+            .and_then(|detail| detail.len())
+    }
+
     pub fn node_meta(&mut self, id: NodeId, vmctx: &mut CodeGenVMCtx) -> Meta {
         let tv = self.ti.types.get(&id).unwrap();
         let md = self.tc_type_to_meta(tv.typ, vmctx);
@@ -197,6 +226,11 @@ impl<'a> TypeLookup<'a> {
         self.obj_tc_type(self.object_def(ikey))
     }
 
+    #[inline]
+    pub fn obj_pos(&self, okey: TCObjKey) -> usize {
+        self.tc_objs.lobjs[okey].pos()
+    }
+
     #[inline]
     pub fn obj_tc_type(&self, okey: TCObjKey) -> TCTypeKey {
         let obj = &self.tc_objs.lobjs[okey];
@@ -232,17 +266,17 @@ impl<'a> TypeLookup<'a> {
         let sel = &self.ti.selections[&id];
         let recv_type = sel.recv().unwrap();
         let obj = &self.tc_objs.lobjs[sel.obj()];
-        let expr_type = obj.typ().unwrap();
-        let sel_typ = if self.tc_objs.types[expr_type].try_as_signature().is_some() {
-            match obj.entity_type() {
-                EntityType::Func(ptr_recv) => match ptr_recv {
-                    true => SelectionType::MethodPtrRecv,
-                    false => SelectionType::MethodNonPtrRecv,
-                },
-                _ => SelectionType::NonMethod,
+        let ptr_recv = matches!(obj.entity_type(), EntityType::Func(true));
+        let (expr_type, sel_typ) = match sel.kind() {
+            TCSelectionKind::FieldVal => (obj.typ().unwrap(), SelectionType::NonMethod),
+            TCSelectionKind::MethodVal if ptr_recv => {
+                (obj.typ().unwrap(), SelectionType::MethodPtrRecv)
+            }
+            TCSelectionKind::MethodVal => (obj.typ().unwrap(), SelectionType::MethodNonPtrRecv),
+            // the type of the expression has the receiver as the first parameter
+            TCSelectionKind::MethodExpr => {
+                (self.node_tc_type(id), SelectionType::MethodExpr(ptr_recv))
             }
-        } else {
-            SelectionType::NonMethod
         };
         (recv_type, expr_type, &sel.indices(), sel_typ)
     }
@@ -371,7 +405,7 @@ impl<'a> TypeLookup<'a> {
                 let (cr, ci, _) = val.to_complex().complex_as_complex128();
                 FfiCtx::new_complex128(cr.0, ci.0)
             }
-            ValueType::String => FfiCtx::new_string(&val.str_as_string()),
+            ValueType::String => FfiCtx::new_string_from_bytes(val.str_as_bytes()),
             ValueType::UnsafePtr => FfiCtx::new_nil(ValueType::UnsafePtr),
             _ => {
                 dbg!(typ);
@@ -385,7 +419,7 @@ impl<'a> TypeLookup<'a> {
         match &self.tc_objs.types[typ] {
             Type::Basic(_) => self.basic_type_meta(typ, vmctx.prim_meta()).unwrap(),
             Type::Array(detail) => {
-                let elem = self.tc_type_to_meta_impl(detail.elem(), vmctx);
+                let elem = self.tc_type_to_meta(detail.elem(), vmctx);
                 Meta::new_array(elem, detail.len().unwrap() as usize, vmctx.metas_mut())
             }
             Type::Slice(detail) => {
@@ -529,7 +563,7 @@ impl<'a> TypeLookup<'a> {
     }
 
     pub fn slice_elem_type(&self, typ: TCTypeKey) -> ValueType {
-        match &self.tc_objs.types[typ] {
+        match &self.tc_objs.types[self.underlying_tc(typ)] {
             Type::Slice(s) => self.tc_type_to_value_type(s.elem()),
             _ => unreachable!(),
         }
@@ -546,12 +580,35 @@ impl<'a> TypeLookup<'a> {
             && vt1 != ValueType::Void
     }
 
+    /// Whether an interface value assigned to another interface type needs
+    /// to be bound again, as the methods are called by their index in the
+    /// method set of the interface.
+    pub fn should_rebind_iface(&self, lhs: TCTypeKey, rhs: TCTypeKey) -> bool {
+        let (l, r) = (self.underlying_tc(lhs), self.underlying_tc(rhs));
+        match (&self.tc_objs.types[l], &self.tc_objs.types[r]) {
+            (Type::Interface(detail), Type::Interface(_)) => {
+                !detail.is_empty() && !typ::identical(l, r, self.tc_objs)
+            }
+            _ => false,
+        }
+    }
+
+    /// The array type if typ is a pointer to an array, typ itself otherwise
+    fn array_deref(&self, typ: TCTypeKey) -> TCTypeKey {
+        match self.tc_objs.types[typ].try_as_pointer() {
+            Some(p) => self.underlying_tc(p.base()),
+            None => typ,
+        }
+    }
+
     fn range_tc_types(&self, typ: TCTypeKey) -> [TCTypeKey; 3] {
         let t_int = self.tc_objs.universe().types()[&BasicType::Int];
-        let typ = self.tc_objs.types[typ].underlying().unwrap_or(typ);
+        let typ = self.array_deref(self.tc_objs.types[typ].underlying().unwrap_or(typ));
         match &self.tc_objs.types[typ] {
             Type::Basic(detail) => match detail.typ() {
                 BasicType::Str | BasicType::UntypedString => [typ, t_int, t_int],
+                // ranging over an integer, there's no value
+                _ if detail.info() == BasicInfo::IsInteger => [typ, typ, typ],
                 _ => unreachable!(),
             },
             Type::Slice(detail) => [typ, t_int, detail.elem()],
@@ -575,17 +632,6 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
-    pub fn iface_binding_info(
-        &mut self,
-        i_s: (TCTypeKey, TCTypeKey),
-        vmctx: &mut CodeGenVMCtx,
-    ) -> (Meta, Vec<IfaceBinding>) {
-        let iface = self.tc_type_to_meta(i_s.0, vmctx);
-        let named = self.tc_type_to_meta(i_s.1, vmctx);
-        let metas = vmctx.metas();
-        iface.bind_with_iface(named, metas)
-    }
-
     fn build_fields(
         &mut self,
         fields: &Vec<TCObjKey>,
@@ -607,6 +653,7 @@ impl<'a> TypeLookup<'a> {
                 meta,
                 name: field.name().clone(),
                 tag,
+                embedded,
                 embedded_indices: None,
             });
             if embedded {
@@ -627,6 +674,7 @@ impl<'a> TypeLookup<'a> {
                             meta: f.meta,
                             name: f.name.clone(),
                             tag: f.tag.clone(),
+                            embedded: f.embedded,
                             embedded_indices: Some(indices),
                         });
                     }
@@ -0,0 +1,163 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The verification mode of the code generator, with the `verify` feature.
+//!
+//! The registers of a function are allocated like a stack, `ExprCtx::cur_reg`
+//! is its depth. The depth is tracked as the code is generated, and every
+//! instruction is checked as it's emitted: the registers it uses must be
+//! below the depth, as the ones above may be taken by the next expression,
+//! and the locals it uses must be allocated. A mismatch panics right away,
+//! with the position of the AST node the instruction is generated for, to
+//! be looked up in the `FileSet`, instead of corrupting the registers of the
+//! VM at runtime.
+//!
+//! The jumps are checked when the function is done, on its control-flow
+//! graph, see `cfg.rs`: they must land on instructions, and a jump back must
+//! be to a block that dominates it, the head of a loop, unless the function
+//! has a goto, which may make a loop with more than one entry.
+
+use crate::cfg::Cfg;
+use crate::context::{Addr, InterInst};
+use go_parser::Pos;
+use go_vm::types::*;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Verifier {
+    /// The register depth of the expression being generated, the registers
+    /// are not checked when it's none.
+    pub depth: Option<usize>,
+    /// Whether the function has a goto.
+    pub goto: bool,
+}
+
+impl Verifier {
+    pub fn check(&self, inst: &InterInst, index: usize, locals: usize, pos: Option<Pos>) {
+        let operands = [("d", inst.d), ("s0", inst.s0), ("s1", inst.s1)];
+        for (name, addr) in operands {
+            let bad = match addr {
+                Addr::LocalVar(i) => {
+                    (i >= locals).then(|| format!("local {} is used with {} allocated", i, locals))
+                }
+                Addr::Register(i) => match self.depth {
+                    Some(depth) if i > depth || i == depth && !Self::bound(inst, name) => {
+                        Some(format!("register {} is used at depth {}", i, depth))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(msg) = bad {
+                let at = pos.map_or("unknown".to_owned(), |p| p.to_string());
+                panic!(
+                    "codegen verify: {} by {} of {} #{}, at pos {}",
+                    msg, name, inst.op0, index, at
+                );
+            }
+        }
+    }
+
+    pub fn check_flow(&self, code: &[InterInst], labels: &[Option<usize>], pos: &[Option<Pos>]) {
+        let fail = |msg: String, index: usize| {
+            let at = pos[index].map_or("unknown".to_owned(), |p| p.to_string());
+            panic!("codegen verify: {}, at pos {}", msg, at);
+        };
+        let cfg = match Cfg::new(code, labels) {
+            Ok(cfg) => cfg,
+            Err((index, msg)) => fail(msg, index),
+        };
+        if self.goto {
+            return;
+        }
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            if !cfg.reachable(b) {
+                continue;
+            }
+            for &s in block.succs.iter() {
+                if cfg.blocks[s].begin <= block.begin && !cfg.dominates(s, b) {
+                    let msg = format!(
+                        "#{} jumps back to #{}, which doesn't dominate it",
+                        block.begin, cfg.blocks[s].begin
+                    );
+                    fail(msg, block.begin);
+                }
+            }
+        }
+    }
+
+    // the operands that are the bound of a range of registers, which may be
+    // at the depth when the range is empty: the stack base of a call with no
+    // results and no arguments, the variadic arguments packed and the
+    // elements of a literal
+    fn bound(inst: &InterInst, name: &str) -> bool {
+        matches!(
+            (inst.op0, name),
+            (Opcode::CALL, "s0") | (Opcode::PACK_VARIADIC, _) | (Opcode::LITERAL, "s0")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn verifier(depth: usize) -> Verifier {
+        Verifier {
+            depth: Some(depth),
+            goto: false,
+        }
+    }
+
+    #[test]
+    fn test_check_depth() {
+        // reads the register below the depth, and writes the one at it
+        let inst = InterInst::with_op_index(
+            Opcode::ADD,
+            Addr::Register(0),
+            Addr::Register(0),
+            Addr::LocalVar(1),
+        );
+        verifier(1).check(&inst, 0, 2, None);
+        // a call with no arguments and no results has its stack base at it
+        let inst =
+            InterInst::with_op_index(Opcode::CALL, Addr::Void, Addr::Register(2), Addr::Void);
+        verifier(2).check(&inst, 0, 0, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "register 2 is used at depth 1 by s1 of ADD #3, at pos 42")]
+    fn test_check_depth_above() {
+        let inst = InterInst::with_op_index(
+            Opcode::ADD,
+            Addr::Register(0),
+            Addr::Register(0),
+            Addr::Register(2),
+        );
+        verifier(1).check(&inst, 3, 0, Some(42));
+    }
+
+    #[test]
+    #[should_panic(expected = "register 1 is used at depth 1 by s0 of ADD #0")]
+    fn test_check_depth_at() {
+        let inst = InterInst::with_op_index(
+            Opcode::ADD,
+            Addr::Register(0),
+            Addr::Register(1),
+            Addr::Register(0),
+        );
+        verifier(1).check(&inst, 0, 0, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "local 2 is used with 2 allocated")]
+    fn test_check_local() {
+        let inst = InterInst::with_op_index(
+            Opcode::DUPLICATE,
+            Addr::LocalVar(2),
+            Addr::Register(0),
+            Addr::Void,
+        );
+        verifier(1).check(&inst, 0, 2, None);
+    }
+}
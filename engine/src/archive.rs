@@ -0,0 +1,66 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Program archives, one file with what it takes to run a program, to deploy
+//! it instead of a tree of source directories: the bytecode of the main
+//! package and of all its dependencies, with the source map, and the export
+//! data of the dependencies, see `Engine::archive`.
+//!
+//! The bytecode format is not stable, an archive is only read by the version
+//! of the engine that wrote it.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::io;
+
+/// The extension of archive files, `goscript run` runs them.
+pub const ARCHIVE_EXT: &str = "gosa";
+
+const MAGIC: &[u8] = b"goscript-archive";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A program packed into one file, `to_bytes` and `from_bytes` write and
+/// read it, `Engine::run_bytecode` runs its bytecode.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ProgramArchive {
+    /// The path the main package was compiled from.
+    pub main: String,
+    pub bytecode: go_vm::Bytecode,
+    /// The export data of the packages the main package imports, directly or
+    /// not, sorted by import path, see `Engine::export`.
+    pub exports: Vec<(String, String)>,
+}
+
+impl ProgramArchive {
+    /// The export data of the package imported as `path`.
+    pub fn export_data(&self, path: &str) -> Option<&str> {
+        self.exports
+            .binary_search_by(|(x, _)| x.as_str().cmp(path))
+            .ok()
+            .map(|i| self.exports[i].1.as_str())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        VERSION.serialize(&mut data).unwrap();
+        self.serialize(&mut data).unwrap();
+        data
+    }
+
+    /// Reads an archive written by `to_bytes`, fails if it's not one, or if
+    /// it's written by another version.
+    pub fn from_bytes(data: &[u8]) -> io::Result<ProgramArchive> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("not a program archive".to_owned()))?;
+        let version = String::deserialize(&mut data)?;
+        if version != VERSION {
+            return Err(invalid(format!(
+                "the archive is of version {}, not {}",
+                version, VERSION
+            )));
+        }
+        ProgramArchive::try_from_slice(data)
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The command line runner.
+//!
+//! ```text
+//! goscript run [-std dir] [-cache dir] file.gos [--] [args...]
+//! goscript build [-std dir] [-o file.gosa] file.gos
+//! goscript doc [-std dir] [-json] package
+//! ```
+//!
+//! The script gets `args` in `os.Args` after its path, and the environment
+//! of the process. The exit code is the one given to `os.Exit`, 2 if the
+//! script panics and 1 if it doesn't compile.
+//!
+//! `build` packs the program into an archive, which `run` runs in place of
+//! the source, it needs the archive feature.
+//!
+//! `doc` prints the documentation of the package in a directory, or of a std
+//! package if there's no such directory, as Markdown or JSON.
+
+use go_engine::ffi::Bytecode;
+use go_engine::{Engine, SourceReader};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+const USAGE: &str = "usage: goscript run [-std dir] [-cache dir] file.gos [--] [args...]
+       goscript build [-std dir] [-o file.gosa] file.gos
+       goscript doc [-std dir] [-json] package
+
+  -std dir     the directory of the std packages, defaults to $GOSCRIPT_STD
+  -cache dir   caches the compiled scripts in dir, needs the cache feature
+  -o file      where to write the archive, defaults to the path with .gosa
+  -json        prints the documentation as JSON instead of Markdown";
+
+#[derive(PartialEq)]
+enum Command {
+    Run,
+    Build,
+    Doc,
+}
+
+struct Options {
+    command: Command,
+    std_dir: PathBuf,
+    cache_dir: Option<PathBuf>,
+    #[cfg(feature = "archive")]
+    out: Option<PathBuf>,
+    json: bool,
+    path: String,
+    args: Vec<String>,
+}
+
+fn main() {
+    let opts = parse_args(std::env::args().skip(1).collect()).unwrap_or_else(|msg| {
+        eprintln!("{}\n\n{}", msg, USAGE);
+        exit(2)
+    });
+    let code = match opts.command {
+        Command::Run => run(opts),
+        Command::Build => build(opts),
+        Command::Doc => doc(opts),
+    };
+    let _ = std::io::stdout().flush();
+    exit(code);
+}
+
+fn parse_args(mut args: Vec<String>) -> Result<Options, String> {
+    let command = match args.first().map(|x| x.as_str()) {
+        Some("run") => Command::Run,
+        Some("build") => Command::Build,
+        Some("doc") => Command::Doc,
+        _ => return Err("the commands are run, build and doc".to_owned()),
+    };
+    args.remove(0);
+    let mut std_dir = std::env::var_os("GOSCRIPT_STD").map(PathBuf::from);
+    let mut cache_dir = None;
+    #[cfg(feature = "archive")]
+    let mut out = None;
+    let mut json = false;
+    let mut iter = args.into_iter();
+    let path = loop {
+        let arg = iter.next().ok_or("no file to run")?;
+        match arg.as_str() {
+            "-std" => std_dir = Some(iter.next().ok_or("-std needs a dir")?.into()),
+            "-cache" if command == Command::Run => {
+                cache_dir = Some(iter.next().ok_or("-cache needs a dir")?.into())
+            }
+            #[cfg(feature = "archive")]
+            "-o" if command == Command::Build => {
+                out = Some(iter.next().ok_or("-o needs a file")?.into())
+            }
+            "-json" if command == Command::Doc => json = true,
+            "-h" | "-help" | "--help" => return Err("".to_owned()),
+            _ if arg.starts_with('-') => return Err(format!("unknown flag {}", arg)),
+            _ => break arg,
+        }
+    };
+    let mut args: Vec<String> = iter.collect();
+    if args.first().map(|x| x.as_str()) == Some("--") {
+        args.remove(0);
+    }
+    match command {
+        Command::Build if !args.is_empty() => return Err("build takes no args".to_owned()),
+        Command::Doc if !args.is_empty() => return Err("doc takes no args".to_owned()),
+        _ => {}
+    }
+    let std_dir = std_dir.unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../std/"));
+    Ok(Options {
+        command,
+        std_dir,
+        cache_dir,
+        #[cfg(feature = "archive")]
+        out,
+        json,
+        path,
+        args,
+    })
+}
+
+// imports of "./pkg" are resolved from the working directory
+fn source_path(path: &str) -> PathBuf {
+    match Path::new(path) {
+        p if p.is_absolute() || p.starts_with(".") || p.starts_with("..") => p.to_path_buf(),
+        p => Path::new(".").join(p),
+    }
+}
+
+fn run(opts: Options) -> i32 {
+    let path = source_path(&opts.path);
+    let sr = SourceReader::local_fs(opts.std_dir, PathBuf::from("./"));
+    let mut engine = Engine::new();
+    engine.set_args([opts.path].into_iter().chain(opts.args).collect());
+    engine.set_env(std::env::vars().collect());
+
+    #[cfg(feature = "archive")]
+    if path
+        .extension()
+        .map_or(false, |x| x == go_engine::ARCHIVE_EXT)
+    {
+        return match std::fs::read(&path).and_then(|x| go_engine::ProgramArchive::from_bytes(&x)) {
+            Ok(archive) => run_bytecode(&engine, &archive.bytecode),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                1
+            }
+        };
+    }
+
+    let result = match opts.cache_dir {
+        #[cfg(feature = "cache")]
+        Some(dir) => engine.compile_cached(&go_engine::CompileCache::new(dir), &sr, &path, true),
+        #[cfg(not(feature = "cache"))]
+        Some(_) => {
+            eprintln!("-cache needs the cache feature");
+            return 2;
+        }
+        None => engine.compile(&sr, &path, true, false, false),
+    };
+    let code = match result {
+        Ok(code) => code,
+        Err(el) => {
+            el.sort();
+            eprint!("{}", el);
+            return 1;
+        }
+    };
+    run_bytecode(&engine, &code)
+}
+
+fn run_bytecode(engine: &Engine, code: &Bytecode) -> i32 {
+    match engine.run_bytecode(code) {
+        None => 0,
+        Some(pdata) => match pdata.exit_code {
+            Some(code) => code,
+            None => {
+                let call_stack = go_engine::ffi::CallStackDisplay::new(&pdata, code);
+                let _ = std::io::stdout().flush();
+                eprintln!("panic: {}\n\n{}", pdata.msg, call_stack);
+                2
+            }
+        },
+    }
+}
+
+#[cfg(feature = "archive")]
+fn build(opts: Options) -> i32 {
+    let path = source_path(&opts.path);
+    let sr = SourceReader::local_fs(opts.std_dir, PathBuf::from("./"));
+    let archive = match Engine::new().archive(&sr, &path) {
+        Ok(archive) => archive,
+        Err(el) => {
+            el.sort();
+            eprint!("{}", el);
+            return 1;
+        }
+    };
+    let out = opts
+        .out
+        .unwrap_or_else(|| Path::new(&opts.path).with_extension(go_engine::ARCHIVE_EXT));
+    match std::fs::write(&out, archive.to_bytes()) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}: {}", out.display(), e);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "archive"))]
+fn build(_: Options) -> i32 {
+    eprintln!("build needs the archive feature");
+    2
+}
+
+fn doc(opts: Options) -> i32 {
+    // a path that isn't a directory is taken as the one of a std package
+    let path = match Path::new(&opts.path).is_dir() {
+        true => source_path(&opts.path),
+        false => PathBuf::from(&opts.path),
+    };
+    let sr = SourceReader::local_fs(opts.std_dir, PathBuf::from("./"));
+    match Engine::new().doc(&sr, &path, false, false) {
+        Ok(doc) if opts.json => {
+            println!("{}", doc.to_json());
+            0
+        }
+        Ok(doc) => {
+            print!("{}", doc.to_markdown());
+            0
+        }
+        Err(el) => {
+            el.sort();
+            eprint!("{}", el);
+            1
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! An on-disk cache of compiled bytecode and export data, so that running an
+//! unchanged script again skips parsing, type checking and code generation.
+//!
+//! An entry is keyed by the hash of the compiler version, the enabled
+//! features, the compile options and the entry path. It records everything
+//! the compiler read from the `SourceRead`: the files, the directory listings
+//! and the import resolutions, with the hashes of the results. A lookup
+//! replays them, the entry is used only if they all give the same results,
+//! so editing, adding or removing a file of any imported package invalidates
+//! it.
+
+use crate::engine::{BuildContext, ImportKey, SourceRead};
+use borsh::{BorshDeserialize, BorshSerialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const EXT: &str = "gosc";
+
+type Hash = [u8; 32];
+
+/// A directory of compiled scripts, it can be shared by processes, entries
+/// are replaced atomically.
+#[derive(Clone, Debug)]
+pub struct CompileCache {
+    dir: PathBuf,
+}
+
+impl CompileCache {
+    /// The directory is created when the first entry is stored.
+    pub fn new(dir: PathBuf) -> CompileCache {
+        CompileCache { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes all the entries.
+    pub fn clear(&self) -> io::Result<()> {
+        if !self.dir.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |x| x == EXT) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the key of compiling `path` with `reader`, `options` are the
+    /// other inputs that change the output.
+    pub(crate) fn key<S: SourceRead>(&self, reader: &S, path: &Path, options: &str) -> Hash {
+        let mut h = Sha256::new();
+        for part in [
+            VERSION,
+            &enabled_features(),
+            options,
+            &format!("{:?}", reader.build_context()),
+            &reader.working_dir().to_string_lossy(),
+            &reader
+                .base_dir()
+                .map(|x| x.to_string_lossy())
+                .unwrap_or_default(),
+            &path.to_string_lossy(),
+        ] {
+            h.update(part.as_bytes());
+            h.update([0]);
+        }
+        h.finalize().into()
+    }
+
+    /// Returns the payload stored with `key`, if what it was compiled from
+    /// is unchanged.
+    pub(crate) fn load<S: SourceRead>(&self, key: &Hash, reader: &S) -> Option<Vec<u8>> {
+        let data = fs::read(self.entry_path(key)).ok()?;
+        let entry = Entry::try_from_slice(&data).ok()?;
+        entry
+            .reads
+            .iter()
+            .all(|r| r.replay(reader) == r.result)
+            .then_some(entry.payload)
+    }
+
+    /// Stores `payload` with `key`, along with what `recorder` has read.
+    /// Failing to write is not an error, the next run compiles again.
+    pub(crate) fn store<S: SourceRead>(&self, key: &Hash, recorder: Recorder<S>, payload: Vec<u8>) {
+        let entry = Entry {
+            reads: recorder.reads.into_inner(),
+            payload,
+        };
+        let path = self.entry_path(key);
+        let temp = path.with_extension(format!("{}.{}", EXT, std::process::id()));
+        let _ = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temp, entry.try_to_vec()?))
+            .and_then(|_| fs::rename(&temp, &path))
+            .map_err(|_| fs::remove_file(&temp));
+    }
+
+    fn entry_path(&self, key: &Hash) -> PathBuf {
+        let name: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(name).with_extension(EXT)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct Entry {
+    reads: Vec<Read>,
+    payload: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+enum Op {
+    ReadFile(String),
+    ReadDir(String),
+    IsFile(String),
+    IsDir(String),
+    Canonicalize(String, String),
+}
+
+/// A call to the `SourceRead` and the hash of its result.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct Read {
+    op: Op,
+    result: Hash,
+}
+
+impl Read {
+    fn replay<S: SourceRead>(&self, reader: &S) -> Hash {
+        match &self.op {
+            Op::ReadFile(p) => hash_read_file(&reader.read_file(Path::new(p))),
+            Op::ReadDir(p) => hash_read_dir(&reader.read_dir(Path::new(p))),
+            Op::IsFile(p) => hash_bool(reader.is_file(Path::new(p))),
+            Op::IsDir(p) => hash_bool(reader.is_dir(Path::new(p))),
+            Op::Canonicalize(path, dir) => {
+                hash_canonicalize(&reader.canonicalize_import(&ImportKey::new(path, dir)))
+            }
+        }
+    }
+}
+
+/// Recorder is the `SourceRead` the compiler reads through when the result
+/// is to be cached.
+pub(crate) struct Recorder<'a, S: SourceRead> {
+    reader: &'a S,
+    reads: RefCell<Vec<Read>>,
+}
+
+impl<'a, S: SourceRead> Recorder<'a, S> {
+    pub(crate) fn new(reader: &'a S) -> Recorder<'a, S> {
+        Recorder {
+            reader,
+            reads: RefCell::new(vec![]),
+        }
+    }
+
+    fn record(&self, op: Op, result: Hash) {
+        self.reads.borrow_mut().push(Read { op, result });
+    }
+}
+
+impl<'a, S: SourceRead> SourceRead for Recorder<'a, S> {
+    fn working_dir(&self) -> &Path {
+        self.reader.working_dir()
+    }
+
+    fn base_dir(&self) -> Option<&Path> {
+        self.reader.base_dir()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        let result = self.reader.read_file(path);
+        self.record(path_op(Op::ReadFile, path), hash_read_file(&result));
+        result
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let result = self.reader.read_dir(path);
+        self.record(path_op(Op::ReadDir, path), hash_read_dir(&result));
+        result
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        let result = self.reader.is_file(path);
+        self.record(path_op(Op::IsFile, path), hash_bool(result));
+        result
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let result = self.reader.is_dir(path);
+        self.record(path_op(Op::IsDir, path), hash_bool(result));
+        result
+    }
+
+    fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)> {
+        let result = self.reader.canonicalize_import(key);
+        let op = Op::Canonicalize(key.path.clone(), key.dir.clone());
+        self.record(op, hash_canonicalize(&result));
+        result
+    }
+
+    fn build_context(&self) -> BuildContext {
+        self.reader.build_context()
+    }
+}
+
+fn path_op(f: fn(String) -> Op, path: &Path) -> Op {
+    f(path.to_string_lossy().into_owned())
+}
+
+fn hash_read_file(result: &io::Result<String>) -> Hash {
+    match result {
+        Ok(s) => Sha256::new().chain_update([1]).chain_update(s),
+        Err(_) => Sha256::new().chain_update([0]),
+    }
+    .finalize()
+    .into()
+}
+
+fn hash_read_dir(result: &io::Result<Vec<PathBuf>>) -> Hash {
+    let mut h = Sha256::new();
+    match result {
+        Ok(list) => {
+            h.update([1]);
+            for p in list.iter() {
+                h.update(p.to_string_lossy().as_bytes());
+                h.update([0]);
+            }
+        }
+        Err(_) => h.update([0]),
+    }
+    h.finalize().into()
+}
+
+fn hash_bool(b: bool) -> Hash {
+    Sha256::digest([b as u8]).into()
+}
+
+fn hash_canonicalize(result: &io::Result<(PathBuf, String)>) -> Hash {
+    match result {
+        Ok((p, s)) => Sha256::new()
+            .chain_update([1])
+            .chain_update(p.to_string_lossy().as_bytes())
+            .chain_update([0])
+            .chain_update(s),
+        Err(_) => Sha256::new().chain_update([0]),
+    }
+    .finalize()
+    .into()
+}
+
+// the features that change the bytecode or the std packages
+fn enabled_features() -> String {
+    [
+        ("async", cfg!(feature = "async")),
+        ("go_std", cfg!(feature = "go_std")),
+        ("instruction_pos", cfg!(feature = "instruction_pos")),
+        ("http", cfg!(feature = "http")),
+        ("yaml", cfg!(feature = "yaml")),
+        ("toml", cfg!(feature = "toml")),
+    ]
+    .iter()
+    .filter(|x| x.1)
+    .map(|x| x.0)
+    .collect::<Vec<_>>()
+    .join(",")
+}
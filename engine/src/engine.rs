@@ -3,15 +3,20 @@
 // license that can be found in the LICENSE file.
 
 use crate::ffi::Ffi;
+#[cfg(feature = "http")]
+use crate::std::http;
 #[cfg(feature = "go_std")]
-use crate::std::os;
+use crate::std::{log, os};
 #[cfg(feature = "serde_borsh")]
-use borsh::BorshSerialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use std::path::Path;
 use std::rc::Rc;
 
 #[cfg(feature = "codegen")]
-pub use {cg::SourceRead, types::ImportKey};
+pub use {
+    cg::SourceRead,
+    types::{BuildContext, DeclDoc, ImportKey, PackageDoc, Severity, DEFAULT_MAX_DEPTH},
+};
 #[cfg(feature = "codegen")]
 extern crate go_codegen as cg;
 #[cfg(feature = "codegen")]
@@ -32,10 +37,55 @@ pub struct Config {
     pub std_out: Option<Box<dyn std::io::Write + Sync + Send>>,
     /// custom std err
     pub std_err: Option<Box<dyn std::io::Write + Sync + Send>>,
+    /// max bytes the script can allocate in total, see `Engine::set_allocation_limit`
+    pub max_total_allocation: Option<usize>,
+    /// stops the script when interrupted, see `Engine::set_interrupt`
+    pub interrupt: Option<vm::Interrupt>,
+    /// `os.Args`, see `Engine::set_args`
+    pub args: Vec<String>,
+    /// the environment variables, see `Engine::set_env`
+    pub env: Vec<(String, String)>,
+    /// what `runtime/buildinfo` says about the application, see `Engine::set_app_info`
+    pub app_info: vm::AppInfo,
+    /// how unused imports are reported, see `Engine::set_unused`
+    #[cfg(feature = "codegen")]
+    pub unused_imports: Severity,
+    /// how unused variables are reported, see `Engine::set_unused`
+    #[cfg(feature = "codegen")]
+    pub unused_vars: Severity,
+    /// where the unused imports and variables reported as warnings go
+    #[cfg(feature = "codegen")]
+    pub warnings: parser::ErrorList,
+    /// how deep expressions and types can nest, see `Engine::set_max_check_depth`
+    #[cfg(feature = "codegen")]
+    pub max_check_depth: Option<usize>,
+    /// the directory compiled scripts are cached in, see `CompileCache`
+    #[cfg(feature = "cache")]
+    pub cache_dir: Option<std::path::PathBuf>,
+}
+
+/// The reasons `Engine::eval_expr` can fail.
+#[cfg(feature = "codegen")]
+#[derive(Debug)]
+pub enum EvalError {
+    /// the package or the expression doesn't compile
+    Compile(parser::ErrorList),
+    /// the package initialization or the expression panicked, with the
+    /// message and the call stack
+    Panic(String, String),
+    /// the run was stopped by `Engine::set_interrupt`'s handle, with the call
+    /// stack where it stopped
+    Interrupted(String),
 }
 
 pub struct Engine {
     ffi: vm::FfiFactory,
+    limits: vm::Limits,
+    run_config: vm::RunConfig,
+    #[cfg(feature = "codegen")]
+    check: types::TraceConfig,
+    #[cfg(feature = "codegen")]
+    strip_unused: bool,
 }
 
 impl Engine {
@@ -44,6 +94,12 @@ impl Engine {
         {
             Engine {
                 ffi: vm::FfiFactory::new(),
+                limits: vm::Limits::default(),
+                run_config: vm::RunConfig::default(),
+                #[cfg(feature = "codegen")]
+                check: types::TraceConfig::default(),
+                #[cfg(feature = "codegen")]
+                strip_unused: false,
             }
         }
 
@@ -51,6 +107,12 @@ impl Engine {
         {
             let mut e = Engine {
                 ffi: vm::FfiFactory::new(),
+                limits: vm::Limits::default(),
+                run_config: vm::RunConfig::default(),
+                #[cfg(feature = "codegen")]
+                check: types::TraceConfig::default(),
+                #[cfg(feature = "codegen")]
+                strip_unused: false,
             };
             crate::std::register(&mut e.ffi);
             e
@@ -62,6 +124,12 @@ impl Engine {
         {
             Engine {
                 ffi: vm::FfiFactory::with_user_data(data),
+                limits: vm::Limits::default(),
+                run_config: vm::RunConfig::default(),
+                #[cfg(feature = "codegen")]
+                check: types::TraceConfig::default(),
+                #[cfg(feature = "codegen")]
+                strip_unused: false,
             }
         }
 
@@ -69,6 +137,12 @@ impl Engine {
         {
             let mut e = Engine {
                 ffi: vm::FfiFactory::with_user_data(data),
+                limits: vm::Limits::default(),
+                run_config: vm::RunConfig::default(),
+                #[cfg(feature = "codegen")]
+                check: types::TraceConfig::default(),
+                #[cfg(feature = "codegen")]
+                strip_unused: false,
             };
             crate::std::register(&mut e.ffi);
             e
@@ -85,6 +159,123 @@ impl Engine {
         os::set_std_io(std_in, std_out, std_err);
     }
 
+    /// Sets the transport the std package `net/http` sends the requests of
+    /// the runs of this engine with, without one they fail with an error.
+    #[cfg(feature = "http")]
+    pub fn set_http_transport(
+        &mut self,
+        transport: Option<std::sync::Arc<dyn http::HttpTransport + Send + Sync>>,
+    ) {
+        self.ffi.set_host_data(transport);
+    }
+
+    /// Sets the sink the std packages `log` and `log/slog` send the records
+    /// of the runs of this engine to, without one they are written to stderr.
+    #[cfg(feature = "go_std")]
+    pub fn set_log_sink(&mut self, sink: Option<std::sync::Arc<dyn log::LogSink + Send + Sync>>) {
+        self.ffi.set_host_data(sink);
+    }
+
+    /// Limits the bytes a run can allocate in total. It's an allocation
+    /// budget rather than a cap on the memory in use, freed memory is not
+    /// subtracted, so a long run that keeps allocating and dropping values
+    /// exceeds it too. A `make` that would exceed it fails before allocating.
+    /// When the limit is exceeded, `on_exceeded` is called with the bytes
+    /// needed and can return a new limit to keep the run going, otherwise
+    /// the script panics with an allocation limit exceeded error.
+    pub fn set_allocation_limit(
+        &mut self,
+        bytes: Option<usize>,
+        on_exceeded: Option<Rc<dyn Fn(usize) -> Option<usize>>>,
+    ) {
+        self.limits.max_total_allocation = bytes;
+        self.limits.on_allocation_exceeded = on_exceeded;
+    }
+
+    /// Lets `interrupt` stop the runs of this engine from any thread, they
+    /// fail with a PanicData that is `interrupted`. The handle stays
+    /// interrupted until it's reset.
+    pub fn set_interrupt(&mut self, interrupt: Option<vm::Interrupt>) {
+        self.limits.interrupt = interrupt;
+    }
+
+    /// Counts the instructions executed by the runs of this engine into
+    /// `stats`, for finding what's worth optimizing.
+    #[cfg(feature = "opcode_stats")]
+    pub fn set_opcode_stats(&mut self, stats: Option<vm::OpcodeStats>) {
+        self.limits.opcode_stats = stats;
+    }
+
+    /// Sets how many stacks and frame lists of finished goroutines the runs
+    /// of this engine keep for the new ones, `set_opcode_stats` counts how
+    /// they are reused.
+    pub fn set_goroutine_pool(&mut self, caps: vm::PoolCaps) {
+        self.limits.pool = caps;
+    }
+
+    /// Lets the host set the package vars of the runs of this engine before
+    /// they start, and read them after they exit, see `vm::Globals`.
+    pub fn set_globals(&mut self, globals: Option<vm::Globals>) {
+        self.limits.globals = globals;
+    }
+
+    /// Lets the runs of this engine subscribe to the event sources the host
+    /// publishes to, with the std package `events`, see `vm::Events`.
+    pub fn set_events(&mut self, events: Option<vm::Events>) {
+        self.limits.events = events;
+    }
+
+    /// Sets how the checker reports the imports and the local variables that
+    /// are not used, they are errors by default as in Go. The ones reported
+    /// as warnings are added to `warnings` and don't fail the compile.
+    #[cfg(feature = "codegen")]
+    pub fn set_unused(&mut self, imports: Severity, vars: Severity, warnings: parser::ErrorList) {
+        self.check.unused_imports = imports;
+        self.check.unused_vars = vars;
+        self.check.warnings = warnings;
+    }
+
+    /// Sets how deep expressions and types can nest in the scripts,
+    /// `DEFAULT_MAX_DEPTH` if none. The checker descends into them recursively,
+    /// the deeper ones fail to compile rather than overflow the stack, which
+    /// may need a lower limit on a thread with a small one.
+    #[cfg(feature = "codegen")]
+    pub fn set_max_check_depth(&mut self, depth: Option<usize>) {
+        self.check.max_depth = depth;
+    }
+
+    /// Makes `compile` drop the functions and the constants the program can't
+    /// reach, see `vm::Bytecode::strip_unused`, to shrink the bytecode that's
+    /// serialized for deployment.
+    #[cfg(feature = "codegen")]
+    pub fn set_strip_unused(&mut self, strip: bool) {
+        self.strip_unused = strip;
+    }
+
+    /// Seeds the default source of `math/rand` in the runs of this engine,
+    /// see `vm::Limits::seed`.
+    pub fn set_seed(&mut self, seed: Option<i64>) {
+        self.limits.seed = seed;
+    }
+
+    /// Sets `os.Args` of the runs of this engine, the first one is the name of
+    /// the program.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.run_config.args = args;
+    }
+
+    /// Sets the environment variables the runs of this engine start with, the
+    /// ones of the host process are not visible to the scripts otherwise.
+    pub fn set_env(&mut self, env: Vec<(String, String)>) {
+        self.run_config.env = env;
+    }
+
+    /// Sets the version and the settings of the application the scripts of
+    /// this engine see in the std package `runtime/buildinfo`.
+    pub fn set_app_info(&mut self, info: vm::AppInfo) {
+        self.run_config.app_info = info;
+    }
+
     pub fn register_extension(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
         self.ffi.register(name, proto);
     }
@@ -101,8 +292,108 @@ impl Engine {
         let cfg = types::TraceConfig {
             trace_parser,
             trace_checker,
+            ..self.check.clone()
         };
-        cg::parse_check_gen(path, &cfg, reader, debug_info)
+        let mut code = cg::parse_check_gen(path, &cfg, reader, debug_info)?;
+        if self.strip_unused {
+            code.strip_unused();
+        }
+        Ok(code)
+    }
+
+    /// Type checks the package at `path` and returns its export data, which
+    /// can be saved as "<package>.gosx" and imported in place of the source.
+    #[cfg(feature = "codegen")]
+    pub fn export<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+        trace_parser: bool,
+        trace_checker: bool,
+    ) -> Result<String, parser::ErrorList> {
+        let cfg = types::TraceConfig {
+            trace_parser,
+            trace_checker,
+            ..self.check.clone()
+        };
+        types::check_export(path, &cfg, reader)
+    }
+
+    /// Type checks the package at `path` and returns its documentation, see
+    /// `PackageDoc::to_markdown` and `PackageDoc::to_json`.
+    #[cfg(feature = "codegen")]
+    pub fn doc<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+        trace_parser: bool,
+        trace_checker: bool,
+    ) -> Result<PackageDoc, parser::ErrorList> {
+        let cfg = types::TraceConfig {
+            trace_parser,
+            trace_checker,
+            ..self.check.clone()
+        };
+        types::check_doc(path, &cfg, reader)
+    }
+
+    /// Like `compile`, but returns the bytecode cached by a previous call
+    /// when none of the files it was compiled from has changed.
+    #[cfg(feature = "cache")]
+    pub fn compile_cached<S: SourceRead>(
+        &self,
+        cache: &crate::CompileCache,
+        reader: &S,
+        path: &Path,
+        debug_info: bool,
+    ) -> Result<vm::Bytecode, parser::ErrorList> {
+        let key = cache.key(reader, path, &self.cache_options("bytecode", debug_info));
+        if let Some(code) = cache
+            .load(&key, reader)
+            .and_then(|x| vm::Bytecode::try_from_slice(&x).ok())
+        {
+            return Ok(code);
+        }
+        let recorder = crate::cache::Recorder::new(reader);
+        let code = self.compile(&recorder, path, debug_info, false, false)?;
+        cache.store(&key, recorder, code.try_to_vec().unwrap());
+        Ok(code)
+    }
+
+    /// Like `export`, but returns the export data cached by a previous call
+    /// when none of the files it was generated from has changed.
+    #[cfg(feature = "cache")]
+    pub fn export_cached<S: SourceRead>(
+        &self,
+        cache: &crate::CompileCache,
+        reader: &S,
+        path: &Path,
+    ) -> Result<String, parser::ErrorList> {
+        let key = cache.key(reader, path, &self.cache_options("export", false));
+        if let Some(data) = cache
+            .load(&key, reader)
+            .and_then(|x| String::from_utf8(x).ok())
+        {
+            return Ok(data);
+        }
+        let recorder = crate::cache::Recorder::new(reader);
+        let data = self.export(&recorder, path, false, false)?;
+        cache.store(&key, recorder, data.clone().into_bytes());
+        Ok(data)
+    }
+
+    // the options that change the output of the compiler
+    #[cfg(feature = "cache")]
+    fn cache_options(&self, kind: &str, debug_info: bool) -> String {
+        format!(
+            "{} {} {:?} {:?} {:?} {}",
+            kind,
+            debug_info,
+            self.check.unused_imports,
+            self.check.unused_vars,
+            self.check.max_depth,
+            self.strip_unused
+        )
     }
 
     #[cfg(all(feature = "codegen", feature = "serde_borsh"))]
@@ -118,8 +409,112 @@ impl Engine {
             .map(|code| code.try_to_vec().unwrap())
     }
 
+    /// Like `compile_serialize`, but with the symbols stripped, see
+    /// `vm::Bytecode::strip_symbols`, returns the bytecode to ship and the
+    /// debug info to keep, which `vm::DebugInfo::try_from_slice` reads back.
+    #[cfg(all(feature = "codegen", feature = "serde_borsh"))]
+    pub fn compile_serialize_stripped<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+    ) -> Result<(Vec<u8>, Vec<u8>), parser::ErrorList> {
+        let mut code = self.compile(reader, path, true, false, false)?;
+        let info = code.strip_symbols();
+        Ok((code.try_to_vec().unwrap(), info.try_to_vec().unwrap()))
+    }
+
+    /// Compiles and serializes the program at `path` into a bundle signed with
+    /// `key`, along with the hashes of the sources of its packages, see
+    /// `Manifest`.
+    #[cfg(feature = "sign")]
+    pub fn compile_signed<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+        key: &crate::SigningKey,
+    ) -> Result<Vec<u8>, parser::ErrorList> {
+        let hasher = crate::sign::Hasher::new(reader);
+        let code = self.compile(&hasher, path, false, false, false)?;
+        let packages = hasher.packages(path);
+        Ok(crate::sign::seal(code.try_to_vec().unwrap(), packages, key))
+    }
+
+    /// Returns the bytecode of a bundle made by `compile_signed`, if it's
+    /// signed by `key`, unchanged, and `verify` accepts its manifest, e.g.
+    /// by checking the hashes of the packages against the ones it knows.
+    #[cfg(feature = "sign")]
+    pub fn load_signed(
+        &self,
+        bundle: &[u8],
+        key: &crate::VerifyingKey,
+        verify: impl FnOnce(&crate::Manifest) -> Result<(), String>,
+    ) -> Result<vm::Bytecode, crate::BundleError> {
+        let (manifest, code) = crate::sign::open(bundle, key)?;
+        verify(&manifest).map_err(crate::BundleError::Refused)?;
+        vm::Bytecode::try_from_slice(&code).map_err(|_| crate::BundleError::Malformed)
+    }
+
+    /// Compiles the program at `path` with the source map into an archive,
+    /// along with the export data of the packages it imports.
+    #[cfg(all(feature = "codegen", feature = "archive"))]
+    pub fn archive<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+    ) -> Result<crate::ProgramArchive, parser::ErrorList> {
+        let bytecode = self.compile(reader, path, true, false, false)?;
+        let exports = types::check_export_deps(path, &self.check, reader)?;
+        Ok(crate::ProgramArchive {
+            main: path.to_string_lossy().into_owned(),
+            bytecode,
+            exports,
+        })
+    }
+
+    /// Runs a new instance of the compiled program, compile once and call this
+    /// for as many instances as needed.
     pub fn run_bytecode(&self, bc: &vm::Bytecode) -> Option<vm::PanicData> {
-        vm::run(bc, &self.ffi)
+        vm::run_with_config(bc, &self.ffi, &self.limits, &self.run_config)
+    }
+
+    /// Evaluates `expr` with access to the members of the package at `path`,
+    /// and returns the value. With `set_globals`, the packages are as the last
+    /// run of this engine left them, e.g. the one of the program or an earlier
+    /// evaluation, otherwise a new instance is initialized for it.
+    /// Values of interface types are returned as their dynamic values. Imports
+    /// are per file in Go, so the imported packages are not in scope.
+    #[cfg(feature = "codegen")]
+    pub fn eval_expr<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+        expr: &str,
+    ) -> Result<vm::types::GosValue, EvalError> {
+        let cfg = types::TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+            ..self.check.clone()
+        };
+        let code =
+            cg::parse_check_gen_eval(path, &cfg, reader, expr, true).map_err(EvalError::Compile)?;
+        match vm::eval_entry(&code, &self.ffi, &self.limits, &self.run_config) {
+            Ok(mut results) => {
+                let val = results.pop().unwrap();
+                let underlying = val.as_interface().and_then(|x| x.underlying_value());
+                Ok(underlying.cloned().unwrap_or(val))
+            }
+            Err(pdata) if pdata.interrupted => {
+                let call_stack = vm::CallStackDisplay::new(&pdata, &code);
+                Err(EvalError::Interrupted(format!("{}", call_stack)))
+            }
+            Err(pdata) => {
+                let call_stack = vm::CallStackDisplay::new(&pdata, &code);
+                Err(EvalError::Panic(
+                    format!("{}", pdata.msg),
+                    format!("{}", call_stack),
+                ))
+            }
+        }
     }
 
     #[cfg(feature = "codegen")]
@@ -132,27 +527,33 @@ impl Engine {
         panic_handler: Option<Rc<dyn Fn(String, String)>>,
     ) -> Result<(), parser::ErrorList> {
         self.compile(reader, path, true, trace_parser, trace_checker)
-            .map(|code| {
-                // let mut decoded;
-                // #[cfg(feature = "serde_borsh")]
-                // {
-                //     let encoded = code.try_to_vec().unwrap();
-                //     decoded = go_vm::Bytecode::try_from_slice(&encoded).unwrap();
-                // }
-                // #[cfg(not(feature = "serde_borsh"))]
-                // {
-                //     decoded = code;
-                // }
-                let pdata = vm::run(&code, &self.ffi);
-                if let Some(pdata) = pdata {
-                    let call_stack = vm::CallStackDisplay::new(&pdata, &code);
-                    if let Some(handler) = panic_handler {
-                        handler(format!("{}", pdata.msg), format!("{}", call_stack));
-                    } else {
-                        eprintln!("{}\n", pdata.msg);
-                        eprintln!("{}\n", call_stack);
-                    }
-                }
-            })
+            .map(|code| self.run_code(&code, panic_handler))
+    }
+
+    /// Like `run_source`, but compiles with `compile_cached`.
+    #[cfg(feature = "cache")]
+    pub fn run_source_cached<S: SourceRead>(
+        &self,
+        cache: &crate::CompileCache,
+        reader: &S,
+        path: &Path,
+        panic_handler: Option<Rc<dyn Fn(String, String)>>,
+    ) -> Result<(), parser::ErrorList> {
+        self.compile_cached(cache, reader, path, true)
+            .map(|code| self.run_code(&code, panic_handler))
+    }
+
+    fn run_code(&self, code: &vm::Bytecode, panic_handler: Option<Rc<dyn Fn(String, String)>>) {
+        let pdata = vm::run_with_config(code, &self.ffi, &self.limits, &self.run_config);
+        // os.Exit is not a panic
+        if let Some(pdata) = pdata.filter(|x| x.exit_code.is_none()) {
+            let call_stack = vm::CallStackDisplay::new(&pdata, code);
+            if let Some(handler) = panic_handler {
+                handler(format!("{}", pdata.msg), format!("{}", call_stack));
+            } else {
+                eprintln!("{}\n", pdata.msg);
+                eprintln!("{}\n", call_stack);
+            }
+        }
     }
 }
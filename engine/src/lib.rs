@@ -34,9 +34,17 @@
 //! - `go_std`: Enable the Go standard library
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 //! - `codegen`: Enable codegen
+//! - `codegen_verify`: Check the register depth of every instruction as it is emitted, to debug codegen
 //! - `instruction_pos`: Add instruction position to bytecode for debugging
+//! - `opcode_stats`: Count the executed instructions by opcode, see `Engine::set_opcode_stats`
 //! - `serde_borsh`: Serde support for bytecode using Borsh
+//! - `cache`: Cache the compiled scripts on disk, see `CompileCache`
+//! - `archive`: Pack a program into one file to deploy, see `ProgramArchive`
+//! - `sign`: Sign the serialized bytecode and verify it on load, see `Engine::compile_signed`
 //! - `wasm`: Enable wasm support
+//! - `http`: The std package `net/http`, with the transport set by the host, see `Engine::set_http_transport`
+//! - `yaml`: The std package `encoding/yaml`, for scripts to read YAML config files
+//! - `toml`: The std package `encoding/toml`, for scripts to read TOML config files
 //!
 
 mod engine;
@@ -48,6 +56,15 @@ mod vfs;
 
 mod source;
 
+#[cfg(feature = "cache")]
+mod cache;
+
+#[cfg(feature = "sign")]
+mod sign;
+
+#[cfg(feature = "archive")]
+mod archive;
+
 #[macro_use]
 pub mod ffi;
 
@@ -59,6 +76,24 @@ pub use engine::*;
 pub use go_parser::{ErrorList, FileSet};
 pub use source::*;
 
+#[cfg(feature = "cache")]
+pub use cache::CompileCache;
+
+#[cfg(feature = "archive")]
+pub use archive::{ProgramArchive, ARCHIVE_EXT};
+
+#[cfg(feature = "sign")]
+pub use {
+    ed25519_dalek::{SigningKey, VerifyingKey},
+    sign::{BundleError, Manifest},
+};
+
+#[cfg(feature = "go_std")]
+pub use crate::std::log::{LogLevel, LogRecord, LogSink};
+
+#[cfg(feature = "http")]
+pub use crate::std::http::{HttpRequest, HttpResponse, HttpTransport};
+
 pub use crate::vfs::{compound::CompoundFs, vfs_map::VfsMap, VirtualFs};
 
 #[cfg(feature = "read_fs")]
@@ -0,0 +1,178 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Signed bytecode bundles, so that a host can refuse to run a script that
+//! was not built by a key it trusts, or was changed after it was.
+//!
+//! A bundle is the serialized bytecode along with a manifest signed with
+//! ed25519, see `Manifest`. `Engine::compile_signed` makes one and
+//! `Engine::load_signed` checks it before deserializing the bytecode.
+
+use crate::engine::{BuildContext, ImportKey, SourceRead};
+use borsh::{BorshDeserialize, BorshSerialize};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use go_parser::Map;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::io;
+use std::path::{Path, PathBuf};
+
+type Hash = [u8; 32];
+
+/// What's signed in a bundle.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    /// The SHA-256 hash of the serialized bytecode.
+    pub bytecode: Hash,
+    /// The packages the bytecode was compiled from and the SHA-256 hashes of
+    /// their source files, sorted by import path, the main package is named
+    /// by the path it was compiled from.
+    pub packages: Vec<(String, Hash)>,
+}
+
+/// The reasons `Engine::load_signed` can refuse a bundle.
+#[derive(Debug)]
+pub enum BundleError {
+    /// it's not a bundle, or a bundle of another version
+    Malformed,
+    /// the manifest is not signed by the key
+    BadSignature,
+    /// the bytecode is not the one in the manifest
+    Tampered,
+    /// the hook of the host refused the manifest, with its reason
+    Refused(String),
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct Bundle {
+    manifest: Vec<u8>,
+    signature: [u8; 64],
+    bytecode: Vec<u8>,
+}
+
+pub(crate) fn seal(bytecode: Vec<u8>, packages: Vec<(String, Hash)>, key: &SigningKey) -> Vec<u8> {
+    let manifest = Manifest {
+        bytecode: Sha256::digest(&bytecode).into(),
+        packages,
+    }
+    .try_to_vec()
+    .unwrap();
+    let signature = key.sign(&manifest).to_bytes();
+    Bundle {
+        manifest,
+        signature,
+        bytecode,
+    }
+    .try_to_vec()
+    .unwrap()
+}
+
+/// Returns the manifest and the bytecode of a bundle signed by `key`.
+pub(crate) fn open(bundle: &[u8], key: &VerifyingKey) -> Result<(Manifest, Vec<u8>), BundleError> {
+    let bundle = Bundle::try_from_slice(bundle).map_err(|_| BundleError::Malformed)?;
+    key.verify_strict(&bundle.manifest, &Signature::from_bytes(&bundle.signature))
+        .map_err(|_| BundleError::BadSignature)?;
+    let manifest =
+        Manifest::try_from_slice(&bundle.manifest).map_err(|_| BundleError::Malformed)?;
+    if manifest.bytecode != <Hash>::from(Sha256::digest(&bundle.bytecode)) {
+        return Err(BundleError::Tampered);
+    }
+    Ok((manifest, bundle.bytecode))
+}
+
+/// Hasher is the `SourceRead` the compiler reads through when the result is
+/// to be signed, it keeps the source files by package.
+pub(crate) struct Hasher<'a, S: SourceRead> {
+    reader: &'a S,
+    // the files read, by directory
+    files: RefCell<Map<PathBuf, Vec<(String, String)>>>,
+    // the import paths of the directories
+    imports: RefCell<Map<PathBuf, String>>,
+}
+
+impl<'a, S: SourceRead> Hasher<'a, S> {
+    pub(crate) fn new(reader: &'a S) -> Hasher<'a, S> {
+        Hasher {
+            reader,
+            files: RefCell::new(Map::new()),
+            imports: RefCell::new(Map::new()),
+        }
+    }
+
+    /// The packages read and the hashes of their files, `main` names the one
+    /// that's not imported.
+    pub(crate) fn packages(self, main: &Path) -> Vec<(String, Hash)> {
+        let imports = self.imports.into_inner();
+        let mut packages: Vec<(String, Hash)> = self
+            .files
+            .into_inner()
+            .into_iter()
+            .map(|(dir, mut files)| {
+                let name = imports
+                    .get(&dir)
+                    .cloned()
+                    .unwrap_or_else(|| main.to_string_lossy().into_owned());
+                files.sort();
+                let mut h = Sha256::new();
+                for (file, content) in files.iter() {
+                    h.update(file.as_bytes());
+                    h.update([0]);
+                    h.update(content.as_bytes());
+                    h.update([0]);
+                }
+                (name, h.finalize().into())
+            })
+            .collect();
+        packages.sort();
+        packages
+    }
+}
+
+impl<'a, S: SourceRead> SourceRead for Hasher<'a, S> {
+    fn working_dir(&self) -> &Path {
+        self.reader.working_dir()
+    }
+
+    fn base_dir(&self) -> Option<&Path> {
+        self.reader.base_dir()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        let result = self.reader.read_file(path);
+        if let Ok(content) = &result {
+            let dir = path.parent().unwrap_or(Path::new("")).to_owned();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let mut files = self.files.borrow_mut();
+            let files = files.entry(dir).or_default();
+            if files.iter().all(|(x, _)| *x != name) {
+                files.push((name.into_owned(), content.clone()));
+            }
+        }
+        result
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.reader.read_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.reader.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.reader.is_dir(path)
+    }
+
+    fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)> {
+        let result = self.reader.canonicalize_import(key);
+        if let Ok((dir, path)) = &result {
+            self.imports.borrow_mut().insert(dir.clone(), path.clone());
+        }
+        result
+    }
+
+    fn build_context(&self) -> BuildContext {
+        self.reader.build_context()
+    }
+}
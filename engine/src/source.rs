@@ -2,7 +2,7 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use crate::engine::{Config, Engine, ImportKey, SourceRead};
+use crate::engine::{BuildContext, Config, Engine, ImportKey, SourceRead};
 use crate::vfs::VirtualFs;
 use crate::ErrorList;
 use go_parser::Map;
@@ -16,9 +16,21 @@ pub fn run(
     path: &Path,
     panic_handler: Option<Rc<dyn Fn(String, String)>>,
 ) -> Result<(), ErrorList> {
-    let engine = Engine::new();
+    let mut engine = Engine::new();
+    engine.set_allocation_limit(config.max_total_allocation, None);
+    engine.set_interrupt(config.interrupt);
+    engine.set_args(config.args);
+    engine.set_env(config.env);
+    engine.set_app_info(config.app_info);
+    engine.set_unused(config.unused_imports, config.unused_vars, config.warnings);
+    engine.set_max_check_depth(config.max_check_depth);
     #[cfg(feature = "go_std")]
     engine.set_std_io(config.std_in, config.std_out, config.std_err);
+    #[cfg(feature = "cache")]
+    if let Some(dir) = config.cache_dir {
+        let cache = crate::CompileCache::new(dir);
+        return engine.run_source_cached(&cache, source, path, panic_handler);
+    }
     engine.run_source(
         config.trace_parser,
         config.trace_checker,
@@ -35,6 +47,8 @@ pub struct SourceReader {
     working_dir: PathBuf,
     /// The virtual file system from which to read files.
     vfs: Box<dyn VirtualFs>,
+    /// GOOS, GOARCH and build tags for choosing files of packages
+    build_context: BuildContext,
 }
 
 impl SourceReader {
@@ -47,9 +61,29 @@ impl SourceReader {
             base_dir,
             working_dir,
             vfs,
+            build_context: SourceReader::with_feature_tags(BuildContext::default()),
         }
     }
 
+    /// Sets the build context, which defaults to the host.
+    pub fn with_build_context(mut self, ctx: BuildContext) -> SourceReader {
+        self.build_context = SourceReader::with_feature_tags(ctx);
+        self
+    }
+
+    /// Adds the tags of the optional std packages that are enabled, the
+    /// packages that are not can't be imported.
+    #[allow(unused_mut)]
+    fn with_feature_tags(mut ctx: BuildContext) -> BuildContext {
+        #[cfg(feature = "http")]
+        ctx.tags.push("http".to_owned());
+        #[cfg(feature = "yaml")]
+        ctx.tags.push("yaml".to_owned());
+        #[cfg(feature = "toml")]
+        ctx.tags.push("toml".to_owned());
+        ctx
+    }
+
     /// Create a SourceReader that reads from local file system.
     #[cfg(feature = "read_fs")]
     pub fn local_fs(base_dir: PathBuf, working_dir: PathBuf) -> SourceReader {
@@ -174,6 +208,10 @@ impl SourceRead for SourceReader {
         self.vfs.is_dir(path)
     }
 
+    fn build_context(&self) -> BuildContext {
+        self.build_context.clone()
+    }
+
     fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)> {
         let mut import_path = key.path.clone();
         let path = if self.vfs.is_local(&key.path) {
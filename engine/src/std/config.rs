@@ -0,0 +1,682 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The native parts of the std packages `encoding/yaml` and `encoding/toml`,
+//! for scripts to read and write config files. A document is parsed into a
+//! `Node` tree, which is stored into the value a script passes in by walking
+//! its metadata, the same way `GosValueRef` finds the fields of script values.
+//! Marshaling walks the value the other way, into a tree that is written out.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+
+/// A value of a config file, both formats are read into it.
+enum Node {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<Node>),
+    Table(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn kind(&self) -> &'static str {
+        match self {
+            Node::Null => "null",
+            Node::Bool(_) => "bool",
+            Node::Int(_) => "integer",
+            Node::Float(_) => "float",
+            Node::Str(_) => "string",
+            Node::List(_) => "array",
+            Node::Table(_) => "table",
+        }
+    }
+}
+
+/// Stores `Node`s into script values, it's told the metas of the values that
+/// go into `interface{}`, which the package passes in as typed nils.
+struct Decoder<'a, 'c> {
+    ctx: &'a mut FfiCtx<'c>,
+    objs: &'c VMObjects,
+    // the key of the struct tags that rename the fields
+    tag: &'static str,
+    int_meta: Meta,
+    table_meta: Meta,
+    list_meta: Meta,
+}
+
+impl<'a, 'c> Decoder<'a, 'c> {
+    fn new(
+        ctx: &'a mut FfiCtx<'c>,
+        tag: &'static str,
+        int_meta: Meta,
+        table: &GosValue,
+        list: &GosValue,
+    ) -> Decoder<'a, 'c> {
+        let objs = ctx.vm_objs;
+        let meta_of = |x: &GosValue| match x.as_interface() {
+            Some(InterfaceObj::Gos(_, Some((m, _)))) => *m,
+            _ => unreachable!(),
+        };
+        Decoder {
+            ctx,
+            objs,
+            tag,
+            int_meta,
+            table_meta: meta_of(table),
+            list_meta: meta_of(list),
+        }
+    }
+
+    /// Stores the node into what `v` points to, the errors are Go's messages
+    /// without the name of the package.
+    fn unmarshal(&mut self, node: &Node, v: &GosValue) -> Result<(), String> {
+        let (ptr, meta) = match v.as_interface() {
+            Some(InterfaceObj::Gos(val, Some((m, _)))) => (val, *m),
+            _ => return Err("Unmarshal(nil)".to_owned()),
+        };
+        if meta.ptr_depth == 0 {
+            return Err(format!("Unmarshal(non-pointer {})", self.type_name(meta)));
+        }
+        match ptr.as_pointer() {
+            Some(p) => self.decode_pointee(node, p, meta.unptr_to(), ""),
+            None => Err(format!("Unmarshal(nil {})", self.type_name(meta))),
+        }
+    }
+
+    fn decode_pointee(
+        &mut self,
+        node: &Node,
+        p: &PointerObj,
+        meta: Meta,
+        path: &str,
+    ) -> Result<(), String> {
+        let old = p
+            .deref(self.ctx.stack, self.ctx.packages)
+            .map_err(|e| e.to_string())?;
+        let val = self.decode(node, meta, old, path)?;
+        // structs are decoded in place
+        if meta.value_type(&self.objs.metas) != ValueType::Struct {
+            p.set_pointee(&val, self.ctx.stack, self.ctx.packages, self.ctx.gcc)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the value of type `meta` the node decodes to, `old` is the
+    /// current one, the fields of a struct and the entries of a map that are
+    /// not in the node are kept.
+    fn decode(
+        &mut self,
+        node: &Node,
+        meta: Meta,
+        old: GosValue,
+        path: &str,
+    ) -> Result<GosValue, String> {
+        let objs = self.objs;
+        let metas = &objs.metas;
+        if let Node::Null = node {
+            return Ok(self.ctx.zero_val(&meta));
+        }
+        if meta.ptr_depth > 0 {
+            let pointee = meta.unptr_to();
+            return match old.as_pointer() {
+                Some(p) => {
+                    self.decode_pointee(node, p, pointee, path)?;
+                    Ok(old)
+                }
+                None => {
+                    let zero = self.ctx.zero_val(&pointee);
+                    let val = self.decode(node, pointee, zero, path)?;
+                    Ok(FfiCtx::new_pointer(val))
+                }
+            };
+        }
+        let mismatch = || {
+            format!(
+                "cannot unmarshal {} into {} of type {}",
+                node.kind(),
+                if path.is_empty() { "value" } else { path },
+                MetaView::new(meta, objs)
+            )
+        };
+        match (meta.mtype_unwraped(metas), node) {
+            (MetadataType::Struct(fields), Node::Table(entries)) => {
+                for (key, node) in entries.iter() {
+                    if let Some(indices) = self.field_indices(fields, key) {
+                        let (last, embedded) = indices.split_last().unwrap();
+                        let mut parent = old.clone();
+                        for i in embedded {
+                            let val = parent.as_struct().0.borrow_fields()[*i].clone();
+                            parent = val;
+                        }
+                        let s = &parent.as_struct().0;
+                        let field_meta = metas[meta.key].unwrap_named(metas).as_struct();
+                        let field_meta = field_meta.get(&indices, metas).meta;
+                        let field = s.borrow_fields()[*last].clone();
+                        let val = self.decode(node, field_meta, field, &join(path, key))?;
+                        s.borrow_fields_mut()[*last] = val;
+                    }
+                }
+                Ok(old)
+            }
+            (MetadataType::Map(kmeta, vmeta), Node::Table(entries)) => {
+                let map = match old.as_map() {
+                    Some(_) => old,
+                    None => self.ctx.new_map(Map::new()),
+                };
+                for (key, node) in entries.iter() {
+                    let k = self.map_key(key, *kmeta)?;
+                    let zero = self.ctx.zero_val(vmeta);
+                    let v = self.decode(node, *vmeta, zero, &join(path, key))?;
+                    map.as_map().unwrap().0.insert(k, v);
+                }
+                Ok(map)
+            }
+            (MetadataType::Slice(elem), Node::List(nodes)) => {
+                let vals = self.decode_elems(nodes, *elem, nodes.len(), path)?;
+                Ok(self.ctx.new_slice(vals, elem.value_type(metas)))
+            }
+            (MetadataType::Array(elem, size), Node::List(nodes)) => {
+                let vals = self.decode_elems(nodes, *elem, *size, path)?;
+                Ok(self.ctx.new_array(vals, elem.value_type(metas)))
+            }
+            (MetadataType::Interface(methods), _) if methods.infos().is_empty() => {
+                Ok(self.generic(node))
+            }
+            (_, Node::Bool(b)) if meta.value_type(metas) == ValueType::Bool => Ok((*b).into()),
+            (_, Node::Str(s)) if meta.value_type(metas) == ValueType::String => {
+                Ok(FfiCtx::new_string(s))
+            }
+            (_, Node::Int(i)) => int_value(*i, meta.value_type(metas)).ok_or_else(mismatch),
+            (_, Node::Float(f)) => match meta.value_type(metas) {
+                ValueType::Float32 => Ok((*f as f32).into()),
+                ValueType::Float64 => Ok((*f).into()),
+                _ => Err(mismatch()),
+            },
+            _ => Err(mismatch()),
+        }
+    }
+
+    fn decode_elems(
+        &mut self,
+        nodes: &[Node],
+        meta: Meta,
+        count: usize,
+        path: &str,
+    ) -> Result<Vec<GosValue>, String> {
+        (0..count)
+            .map(|i| {
+                let zero = self.ctx.zero_val(&meta);
+                match nodes.get(i) {
+                    Some(node) => self.decode(node, meta, zero, &format!("{}[{}]", path, i)),
+                    None => Ok(zero),
+                }
+            })
+            .collect()
+    }
+
+    /// Decodes what goes into an `interface{}`, a table is a
+    /// `map[string]interface{}` and an array is a `[]interface{}`.
+    fn generic(&mut self, node: &Node) -> GosValue {
+        let objs = self.objs;
+        let prim = &objs.prim_meta;
+        let (val, meta) = match node {
+            Node::Null => return self.ctx.zero_val(&prim.empty_iface),
+            Node::Bool(b) => ((*b).into(), prim.mbool),
+            Node::Int(i) => (
+                int_value(*i, self.int_meta.value_type(&objs.metas)).unwrap(),
+                self.int_meta,
+            ),
+            Node::Float(f) => ((*f).into(), prim.mfloat64),
+            Node::Str(s) => (FfiCtx::new_string(s), prim.mstr),
+            Node::List(nodes) => {
+                let vals = nodes.iter().map(|x| self.generic(x)).collect();
+                (
+                    self.ctx.new_slice(vals, ValueType::Interface),
+                    self.list_meta,
+                )
+            }
+            Node::Table(entries) => {
+                let map = entries
+                    .iter()
+                    .map(|(k, v)| (FfiCtx::new_string(k), self.generic(v)))
+                    .collect();
+                (self.ctx.new_map(map), self.table_meta)
+            }
+        };
+        self.ctx.new_empty_interface(val, meta)
+    }
+
+    /// Returns the indices of the exported field the key names, by the
+    /// struct tag or else by the name of the field ignoring case, the fields
+    /// of embedded structs are searched too.
+    fn field_indices(&self, fields: &Fields, key: &str) -> Option<Vec<usize>> {
+        let metas = &self.objs.metas;
+        let candidates = fields.infos().iter().filter(|f| {
+            let embedded_struct = f.embedded
+                && f.meta.ptr_depth == 0
+                && matches!(f.meta.mtype_unwraped(metas), MetadataType::Struct(_));
+            f.exported() && !embedded_struct
+        });
+        let tag_name = |f: &FieldInfo| {
+            f.lookup_tag(self.tag)
+                .map(|t| t.split(',').next().unwrap().to_owned())
+                .filter(|x| !x.is_empty())
+        };
+        let mut by_name = None;
+        for f in candidates {
+            match tag_name(f) {
+                Some(name) if name == key => return Some(fields.indices_by_name(&f.name)),
+                Some(_) => {}
+                None if by_name.is_none() && f.name.eq_ignore_ascii_case(key) => {
+                    by_name = Some(fields.indices_by_name(&f.name))
+                }
+                None => {}
+            }
+        }
+        by_name
+    }
+
+    fn map_key(&self, text: &str, meta: Meta) -> Result<GosValue, String> {
+        let typ = meta.value_type(&self.objs.metas);
+        let key = match typ {
+            ValueType::String => Some(FfiCtx::new_string(text)),
+            ValueType::Bool => text.parse::<bool>().ok().map(GosValue::from),
+            _ => text.parse::<i64>().ok().and_then(|i| int_value(i, typ)),
+        };
+        key.ok_or_else(|| {
+            format!(
+                "cannot unmarshal key {} into map key of type {}",
+                text,
+                MetaView::new(meta, self.objs)
+            )
+        })
+    }
+
+    fn type_name(&self, meta: Meta) -> String {
+        MetaView::new(meta, self.objs).to_string()
+    }
+}
+
+/// Reads script values into `Node`s, by walking their metadata like
+/// `Decoder` does.
+struct Encoder<'a, 'c> {
+    ctx: &'a FfiCtx<'c>,
+    // the key of the struct tags that rename the fields
+    tag: &'static str,
+}
+
+impl<'a, 'c> Encoder<'a, 'c> {
+    /// The node of what's in `v`, nil maps and slices are empty.
+    fn marshal(&self, v: &GosValue) -> Result<Node, String> {
+        match v.as_interface() {
+            Some(InterfaceObj::Gos(val, Some((m, _)))) => self.encode(val, *m),
+            Some(InterfaceObj::Ffi(_)) => Err("cannot marshal a native value".to_owned()),
+            _ => Ok(Node::Null),
+        }
+    }
+
+    fn encode(&self, val: &GosValue, meta: Meta) -> Result<Node, String> {
+        let objs = self.ctx.vm_objs;
+        let metas = &objs.metas;
+        if meta.ptr_depth > 0 {
+            return match val.as_pointer() {
+                Some(_) => {
+                    let pointee = self.ctx.deref_pointer(val).map_err(|e| e.to_string())?;
+                    self.encode(&pointee, meta.unptr_to())
+                }
+                None => Ok(Node::Null),
+            };
+        }
+        match meta.mtype_unwraped(metas) {
+            MetadataType::Struct(fields) => self.encode_struct(val, fields),
+            MetadataType::Map(kmeta, vmeta) => {
+                let mut entries: Vec<(GosValue, GosValue)> = match val.as_map() {
+                    Some((map, _)) => map
+                        .borrow_data()
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                    None => vec![],
+                };
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                let entries = entries
+                    .iter()
+                    .map(|(k, v)| Ok((self.map_key(k, *kmeta)?, self.encode(v, *vmeta)?)))
+                    .collect::<Result<_, String>>()?;
+                Ok(Node::Table(entries))
+            }
+            MetadataType::Slice(elem) => {
+                let vals = self.ctx.slice_elems(val).unwrap_or_default();
+                self.encode_elems(&vals, *elem)
+            }
+            MetadataType::Array(elem, _) => self.encode_elems(&self.ctx.array_elems(val), *elem),
+            MetadataType::Interface(_) => match val.as_interface() {
+                Some(InterfaceObj::Gos(val, Some((m, _)))) => self.encode(val, *m),
+                Some(_) => Err(format!("cannot marshal {}", MetaView::new(meta, objs))),
+                None => Ok(Node::Null),
+            },
+            _ => scalar(val)
+                .ok_or_else(|| format!("cannot marshal type {}", MetaView::new(meta, objs))),
+        }
+    }
+
+    /// A struct is a table of its exported fields, the fields of embedded
+    /// structs are inlined unless a field of the struct has the same name.
+    fn encode_struct(&self, val: &GosValue, fields: &Fields) -> Result<Node, String> {
+        let metas = &self.ctx.vm_objs.metas;
+        // the promoted fields are listed too, with the paths to them
+        let own = fields
+            .infos()
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.embedded_indices.is_none());
+        let embedded_struct = |f: &FieldInfo| {
+            f.embedded
+                && f.meta.ptr_depth == 0
+                && matches!(f.meta.mtype_unwraped(metas), MetadataType::Struct(_))
+        };
+        let names: Vec<String> = own
+            .clone()
+            .filter(|(_, f)| f.exported() && !embedded_struct(f))
+            .map(|(_, f)| self.key(f))
+            .collect();
+        let mut entries: Vec<(String, Node)> = vec![];
+        for (i, f) in own {
+            let field = val.as_struct().0.borrow_fields()[i].clone();
+            if embedded_struct(f) {
+                if let Node::Table(inlined) = self.encode(&field, f.meta)? {
+                    for (key, node) in inlined {
+                        if !names.contains(&key) && entries.iter().all(|(k, _)| *k != key) {
+                            entries.push((key, node));
+                        }
+                    }
+                }
+            } else if f.exported() {
+                entries.push((self.key(f), self.encode(&field, f.meta)?));
+            }
+        }
+        Ok(Node::Table(entries))
+    }
+
+    fn encode_elems(&self, vals: &[GosValue], meta: Meta) -> Result<Node, String> {
+        let nodes = vals
+            .iter()
+            .map(|x| self.encode(x, meta))
+            .collect::<Result<_, _>>()?;
+        Ok(Node::List(nodes))
+    }
+
+    /// The name in the struct tag, or else the name of the field.
+    fn key(&self, f: &FieldInfo) -> String {
+        f.lookup_tag(self.tag)
+            .map(|t| t.split(',').next().unwrap().to_owned())
+            .filter(|x| !x.is_empty())
+            .unwrap_or_else(|| f.name.clone())
+    }
+
+    fn map_key(&self, key: &GosValue, meta: Meta) -> Result<String, String> {
+        match scalar(key) {
+            Some(Node::Str(s)) => Ok(s),
+            Some(Node::Int(i)) => Ok(i.to_string()),
+            Some(Node::Bool(b)) => Ok(b.to_string()),
+            _ => Err(format!(
+                "cannot marshal map key of type {}",
+                MetaView::new(meta, self.ctx.vm_objs)
+            )),
+        }
+    }
+}
+
+/// The node of a bool, a number or a string, None for the other types and
+/// for the uint64s too big for an integer node.
+fn scalar(val: &GosValue) -> Option<Node> {
+    macro_rules! int {
+        ($v:expr) => {
+            i64::try_from(*$v).ok().map(Node::Int)
+        };
+    }
+    match val.typ() {
+        ValueType::Bool => Some(Node::Bool(*val.as_bool())),
+        ValueType::Int => int!(val.as_int()),
+        ValueType::Int8 => int!(val.as_int8()),
+        ValueType::Int16 => int!(val.as_int16()),
+        ValueType::Int32 => int!(val.as_int32()),
+        ValueType::Int64 => int!(val.as_int64()),
+        ValueType::Uint => int!(val.as_uint()),
+        ValueType::Uint8 => int!(val.as_uint8()),
+        ValueType::Uint16 => int!(val.as_uint16()),
+        ValueType::Uint32 => int!(val.as_uint32()),
+        ValueType::Uint64 => int!(val.as_uint64()),
+        // through the text, so that 0.1 stays 0.1 and not 0.10000000149011612
+        ValueType::Float32 => val.as_float32().to_string().parse().ok().map(Node::Float),
+        ValueType::Float64 => Some(Node::Float(val.as_float64().into_inner())),
+        ValueType::String => Some(Node::Str(val.as_string().as_str().to_string())),
+        _ => None,
+    }
+}
+
+/// The integer as a value of the type, None if it's not an integer type or
+/// the integer doesn't fit, a float can hold any integer.
+fn int_value(i: i64, typ: ValueType) -> Option<GosValue> {
+    macro_rules! fit {
+        ($t:ty) => {
+            <$t>::try_from(i).ok().map(GosValue::from)
+        };
+    }
+    match typ {
+        ValueType::Int => fit!(isize),
+        ValueType::Int8 => fit!(i8),
+        ValueType::Int16 => fit!(i16),
+        ValueType::Int32 => fit!(i32),
+        ValueType::Int64 => fit!(i64),
+        ValueType::Uint => fit!(usize),
+        ValueType::Uint8 => fit!(u8),
+        ValueType::Uint16 => fit!(u16),
+        ValueType::Uint32 => fit!(u32),
+        ValueType::Uint64 => fit!(u64),
+        ValueType::Float32 => Some((i as f32).into()),
+        ValueType::Float64 => Some((i as f64).into()),
+        _ => None,
+    }
+}
+
+/// The results of `marshal`, the text and the error message.
+fn marshal_results(result: Result<String, String>) -> (GosValue, GosValue) {
+    match result {
+        Ok(text) => (FfiCtx::new_string(&text), FfiCtx::new_string("")),
+        Err(msg) => (FfiCtx::new_string(""), FfiCtx::new_string(&msg)),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_owned(),
+        false => format!("{}.{}", path, key),
+    }
+}
+
+#[cfg(feature = "yaml")]
+#[derive(Ffi)]
+pub struct YamlFfi;
+
+#[cfg(feature = "yaml")]
+#[ffi_impl(rename = "encoding.yaml")]
+impl YamlFfi {
+    // returns the error message, empty if there's none
+    fn ffi_unmarshal(
+        ctx: &mut FfiCtx,
+        data: GosValue,
+        v: GosValue,
+        table: GosValue,
+        list: GosValue,
+    ) -> GosValue {
+        let docs = match yaml_rust::YamlLoader::load_from_str(&data.as_string().as_str()) {
+            Ok(docs) => docs,
+            Err(e) => return FfiCtx::new_string(&e.to_string()),
+        };
+        // only the first document is decoded, an empty one changes nothing
+        let result = docs.first().map_or(Ok(()), |doc| {
+            let node = YamlFfi::node(doc)?;
+            let int_meta = ctx.vm_objs.prim_meta.mint;
+            Decoder::new(ctx, "yaml", int_meta, &table, &list).unmarshal(&node, &v)
+        });
+        FfiCtx::new_string(&result.err().unwrap_or_default())
+    }
+
+    // returns the text and the error message, empty if there's none
+    fn ffi_marshal(ctx: &FfiCtx, v: GosValue) -> (GosValue, GosValue) {
+        let result = Encoder { ctx, tag: "yaml" }.marshal(&v).and_then(|node| {
+            let mut out = String::new();
+            yaml_rust::YamlEmitter::new(&mut out)
+                .dump(&YamlFfi::yaml(node))
+                .map_err(|e| format!("{:?}", e))?;
+            // without the document start the emitter always writes
+            Ok(format!("{}\n", out.trim_start_matches("---").trim_start()))
+        });
+        marshal_results(result)
+    }
+
+    fn yaml(node: Node) -> yaml_rust::Yaml {
+        use yaml_rust::Yaml;
+        match node {
+            Node::Null => Yaml::Null,
+            Node::Bool(b) => Yaml::Boolean(b),
+            Node::Int(i) => Yaml::Integer(i),
+            Node::Float(f) if f.is_nan() => Yaml::Real(".nan".to_owned()),
+            Node::Float(f) if f.is_infinite() => {
+                Yaml::Real(if f > 0.0 { ".inf" } else { "-.inf" }.to_owned())
+            }
+            // Debug keeps the point of the floats that are integers
+            Node::Float(f) => Yaml::Real(format!("{:?}", f)),
+            Node::Str(s) => Yaml::String(s),
+            Node::List(nodes) => Yaml::Array(nodes.into_iter().map(YamlFfi::yaml).collect()),
+            Node::Table(entries) => Yaml::Hash(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (Yaml::String(k), YamlFfi::yaml(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn node(y: &yaml_rust::Yaml) -> Result<Node, String> {
+        use yaml_rust::Yaml;
+        Ok(match y {
+            Yaml::Null => Node::Null,
+            Yaml::Boolean(b) => Node::Bool(*b),
+            Yaml::Integer(i) => Node::Int(*i),
+            Yaml::Real(_) => Node::Float(y.as_f64().ok_or("invalid float")?),
+            Yaml::String(s) => Node::Str(s.clone()),
+            Yaml::Array(a) => Node::List(a.iter().map(YamlFfi::node).collect::<Result<_, _>>()?),
+            Yaml::Hash(h) => Node::Table(
+                h.iter()
+                    .map(|(k, v)| {
+                        let key = match k {
+                            Yaml::String(s) | Yaml::Real(s) => s.clone(),
+                            Yaml::Integer(i) => i.to_string(),
+                            Yaml::Boolean(b) => b.to_string(),
+                            Yaml::Null => "null".to_owned(),
+                            _ => return Err("mapping keys must be scalars".to_owned()),
+                        };
+                        Ok((key, YamlFfi::node(v)?))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Yaml::Alias(_) => return Err("aliases are not supported".to_owned()),
+            Yaml::BadValue => return Err("invalid value".to_owned()),
+        })
+    }
+}
+
+#[cfg(feature = "toml")]
+#[derive(Ffi)]
+pub struct TomlFfi;
+
+#[cfg(feature = "toml")]
+#[ffi_impl(rename = "encoding.toml")]
+impl TomlFfi {
+    // returns the error message, empty if there's none
+    fn ffi_unmarshal(
+        ctx: &mut FfiCtx,
+        data: GosValue,
+        v: GosValue,
+        table: GosValue,
+        list: GosValue,
+    ) -> GosValue {
+        let doc = match data.as_string().as_str().parse::<toml::Value>() {
+            Ok(doc) => doc,
+            Err(e) => return FfiCtx::new_string(&e.to_string()),
+        };
+        let int_meta = ctx.vm_objs.prim_meta.mint64;
+        let result =
+            Decoder::new(ctx, "toml", int_meta, &table, &list).unmarshal(&TomlFfi::node(&doc), &v);
+        FfiCtx::new_string(&result.err().unwrap_or_default())
+    }
+
+    // returns the text and the error message, empty if there's none
+    fn ffi_marshal(ctx: &FfiCtx, v: GosValue) -> (GosValue, GosValue) {
+        let result = Encoder { ctx, tag: "toml" }
+            .marshal(&v)
+            .and_then(TomlFfi::toml)
+            .and_then(|doc| match doc {
+                Some(doc @ toml::Value::Table(_)) => {
+                    toml::to_string(&doc).map_err(|e| e.to_string())
+                }
+                _ => Err("top-level values must be maps or structs".to_owned()),
+            });
+        marshal_results(result)
+    }
+
+    /// None for a null, the entries of a table that are null are left out.
+    fn toml(node: Node) -> Result<Option<toml::Value>, String> {
+        use toml::Value;
+        Ok(Some(match node {
+            Node::Null => return Ok(None),
+            Node::Bool(b) => Value::Boolean(b),
+            Node::Int(i) => Value::Integer(i),
+            Node::Float(f) => Value::Float(f),
+            Node::Str(s) => Value::String(s),
+            Node::List(nodes) => Value::Array(
+                nodes
+                    .into_iter()
+                    .map(|x| {
+                        TomlFfi::toml(x)?.ok_or_else(|| "cannot marshal nil in an array".to_owned())
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Node::Table(entries) => {
+                let mut table = toml::map::Map::new();
+                for (k, v) in entries {
+                    if let Some(v) = TomlFfi::toml(v)? {
+                        table.insert(k, v);
+                    }
+                }
+                Value::Table(table)
+            }
+        }))
+    }
+
+    fn node(t: &toml::Value) -> Node {
+        use toml::Value;
+        match t {
+            Value::Boolean(b) => Node::Bool(*b),
+            Value::Integer(i) => Node::Int(*i),
+            Value::Float(f) => Node::Float(*f),
+            Value::String(s) => Node::Str(s.clone()),
+            // as the text of RFC 3339
+            Value::Datetime(d) => Node::Str(d.to_string()),
+            Value::Array(a) => Node::List(a.iter().map(TomlFfi::node).collect()),
+            Value::Table(t) => Node::Table(
+                t.iter()
+                    .map(|(k, v)| (k.clone(), TomlFfi::node(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
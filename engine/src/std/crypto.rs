@@ -0,0 +1,81 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Algorithms of the hashes, must match the Go side
+const SHA256: isize = 0;
+
+const MD5: isize = 1;
+
+#[derive(Ffi)]
+pub struct HashFfi;
+
+#[ffi_impl(rename = "crypto.hash")]
+impl HashFfi {
+    fn ffi_new(alg: isize) -> RuntimeResult<GosValue> {
+        let hasher = match alg {
+            SHA256 => Hasher::Sha256(RefCell::new(Sha256::new())),
+            MD5 => Hasher::Md5(RefCell::new(Md5::new())),
+            _ => return Err(format!("crypto: unknown hash algorithm {}", alg).into()),
+        };
+        Ok(FfiCtx::new_unsafe_ptr(Rc::new(hasher)))
+    }
+
+    fn ffi_write(p: GosValue, data: GosValue) -> RuntimeResult<()> {
+        let hasher = p.as_non_nil_unsafe_ptr()?.downcast_ref::<Hasher>()?;
+        let slice = &data.as_non_nil_slice::<Elem8>()?.0;
+        hasher.update(&slice.as_raw_slice());
+        Ok(())
+    }
+
+    // writes the checksum of the data so far to out, and keeps the state
+    fn ffi_sum(p: GosValue, out: GosValue) -> RuntimeResult<()> {
+        let hasher = p.as_non_nil_unsafe_ptr()?.downcast_ref::<Hasher>()?;
+        let slice = &out.as_non_nil_slice::<Elem8>()?.0;
+        hasher.sum(&mut slice.as_raw_slice_mut());
+        Ok(())
+    }
+
+    fn ffi_reset(p: GosValue) -> RuntimeResult<()> {
+        let hasher = p.as_non_nil_unsafe_ptr()?.downcast_ref::<Hasher>()?;
+        hasher.reset();
+        Ok(())
+    }
+}
+
+#[derive(UnsafePtr)]
+pub enum Hasher {
+    Sha256(RefCell<Sha256>),
+    Md5(RefCell<Md5>),
+}
+
+impl Hasher {
+    fn update(&self, data: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.borrow_mut().update(data),
+            Self::Md5(h) => h.borrow_mut().update(data),
+        }
+    }
+
+    fn sum(&self, out: &mut [u8]) {
+        match self {
+            Self::Sha256(h) => out.copy_from_slice(&h.borrow().clone().finalize()),
+            Self::Md5(h) => out.copy_from_slice(&h.borrow().clone().finalize()),
+        }
+    }
+
+    fn reset(&self) {
+        match self {
+            Self::Sha256(h) => h.borrow_mut().reset(),
+            Self::Md5(h) => h.borrow_mut().reset(),
+        }
+    }
+}
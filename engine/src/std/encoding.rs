@@ -0,0 +1,245 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The native parts of the std packages `encoding/hex` and `encoding/base64`,
+//! the decoders are ported from Go to report the same errors.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+// Results of decoding hex, other than the invalid byte
+const HEX_OK: isize = -1;
+const HEX_ODD_LENGTH: isize = -2;
+
+// the pad rune of the encodings without padding
+const NO_PADDING: i32 = -1;
+
+fn bytes_of(slice: &GosValue) -> Vec<u8> {
+    match slice.as_slice::<Elem8>() {
+        Some(s) => s.0.as_raw_slice::<u8>().to_vec(),
+        None => vec![],
+    }
+}
+
+// copies the decoded bytes to the slice dst, like Go, it panics if dst is too short
+fn write_to(dst: &GosValue, data: &[u8]) -> RuntimeResult<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let dst = &dst.as_non_nil_slice::<Elem8>()?.0;
+    let mut buf = dst.as_raw_slice_mut::<u8>();
+    if buf.len() < data.len() {
+        return Err(format!(
+            "index out of range [{}] with length {}",
+            buf.len(),
+            buf.len()
+        )
+        .into());
+    }
+    buf[..data.len()].copy_from_slice(data);
+    Ok(())
+}
+
+#[derive(Ffi)]
+pub struct HexFfi;
+
+#[ffi_impl(rename = "encoding.hex")]
+impl HexFfi {
+    fn ffi_encode(src: GosValue) -> GosValue {
+        let src = bytes_of(&src);
+        let mut dst = Vec::with_capacity(src.len() * 2);
+        for b in src.iter() {
+            dst.push(HEX_DIGITS[(b >> 4) as usize]);
+            dst.push(HEX_DIGITS[(b & 0x0f) as usize]);
+        }
+        FfiCtx::new_string_from_bytes(&dst)
+    }
+
+    // returns the number of bytes written to dst, and HEX_OK, HEX_ODD_LENGTH
+    // or the invalid byte
+    fn ffi_decode(dst: GosValue, src: GosValue) -> RuntimeResult<(isize, isize)> {
+        let src = src.as_string().as_str();
+        let mut decoded = Vec::with_capacity(src.len() / 2);
+        let mut result = HEX_OK;
+        for pair in src.as_bytes().chunks(2) {
+            let a = HexFfi::from_hex_char(pair[0]);
+            if a > 0x0f {
+                result = pair[0] as isize;
+                break;
+            }
+            if pair.len() == 1 {
+                result = HEX_ODD_LENGTH;
+                break;
+            }
+            let b = HexFfi::from_hex_char(pair[1]);
+            if b > 0x0f {
+                result = pair[1] as isize;
+                break;
+            }
+            decoded.push((a << 4) | b);
+        }
+        write_to(&dst, &decoded)?;
+        Ok((decoded.len() as isize, result))
+    }
+
+    fn from_hex_char(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => 0xff,
+        }
+    }
+}
+
+#[derive(Ffi)]
+pub struct Base64Ffi;
+
+#[ffi_impl(rename = "encoding.base64")]
+impl Base64Ffi {
+    fn ffi_encode(alphabet: GosValue, pad: i32, src: GosValue) -> GosValue {
+        let alphabet = alphabet.as_string().as_str();
+        let alphabet = alphabet.as_bytes();
+        let src = bytes_of(&src);
+        let mut dst = Vec::with_capacity((src.len() + 2) / 3 * 4);
+        for chunk in src.chunks(3) {
+            let val = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |v, (i, b)| v | (*b as u32) << (16 - i * 8));
+            let count = chunk.len() + 1;
+            for i in 0..count {
+                dst.push(alphabet[(val >> (18 - i * 6) & 0x3f) as usize]);
+            }
+            if pad != NO_PADDING {
+                for _ in count..4 {
+                    dst.push(pad as u8);
+                }
+            }
+        }
+        FfiCtx::new_string_from_bytes(&dst)
+    }
+
+    // returns the number of bytes written to dst, and the offset of the
+    // corrupt input, or -1
+    fn ffi_decode(
+        alphabet: GosValue,
+        pad: i32,
+        dst: GosValue,
+        src: GosValue,
+    ) -> RuntimeResult<(isize, isize)> {
+        let alphabet = alphabet.as_string().as_str();
+        let mut decode_map = [0xffu8; 256];
+        for (i, c) in alphabet.as_bytes().iter().enumerate() {
+            decode_map[*c as usize] = i as u8;
+        }
+        let src = src.as_string().as_str();
+        let decoder = Decoder {
+            decode_map,
+            pad,
+            src: src.as_bytes(),
+        };
+
+        let mut decoded = Vec::with_capacity(src.len() / 4 * 3 + 2);
+        let mut si = 0;
+        let mut corrupt = -1;
+        while si < decoder.src.len() {
+            let (nsi, bytes, n, err) = decoder.decode_quantum(si);
+            si = nsi;
+            decoded.extend_from_slice(&bytes[..n]);
+            if let Some(offset) = err {
+                corrupt = offset as isize;
+                break;
+            }
+        }
+        write_to(&dst, &decoded)?;
+        Ok((decoded.len() as isize, corrupt))
+    }
+}
+
+struct Decoder<'a> {
+    decode_map: [u8; 256],
+    pad: i32,
+    src: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn is_newline(&self, si: usize) -> bool {
+        self.src[si] == b'\n' || self.src[si] == b'\r'
+    }
+
+    fn skip_newlines(&self, mut si: usize) -> usize {
+        while si < self.src.len() && self.is_newline(si) {
+            si += 1;
+        }
+        si
+    }
+
+    /// Decodes up to 4 base64 chars starting at si, returns the index of the
+    /// next char, the decoded bytes and their count, and the offset of the
+    /// error.
+    fn decode_quantum(&self, mut si: usize) -> (usize, [u8; 3], usize, Option<usize>) {
+        let src = self.src;
+        let mut dbuf = [0u8; 4];
+        let mut dlen = 4;
+        let mut err = None;
+        let mut j = 0;
+        while j < 4 {
+            if src.len() == si {
+                if j == 0 {
+                    return (si, [0; 3], 0, None);
+                } else if j == 1 || self.pad != NO_PADDING {
+                    return (si, [0; 3], 0, Some(si - j));
+                }
+                dlen = j;
+                break;
+            }
+            let c = src[si];
+            si += 1;
+            let out = self.decode_map[c as usize];
+            if out != 0xff {
+                dbuf[j] = out;
+                j += 1;
+                continue;
+            }
+            if c == b'\n' || c == b'\r' {
+                continue;
+            }
+            if c as i32 != self.pad {
+                return (si, [0; 3], 0, Some(si - 1));
+            }
+            // the end of the input, with padding
+            match j {
+                0 | 1 => return (si, [0; 3], 0, Some(si - 1)),
+                2 => {
+                    si = self.skip_newlines(si);
+                    if si == src.len() {
+                        return (si, [0; 3], 0, Some(src.len()));
+                    }
+                    if src[si] as i32 != self.pad {
+                        return (si, [0; 3], 0, Some(si - 1));
+                    }
+                    si += 1;
+                }
+                _ => {}
+            }
+            si = self.skip_newlines(si);
+            if si < src.len() {
+                err = Some(si);
+            }
+            dlen = j;
+            break;
+        }
+
+        let val = (dbuf[0] as u32) << 18
+            | (dbuf[1] as u32) << 12
+            | (dbuf[2] as u32) << 6
+            | dbuf[3] as u32;
+        let bytes = [(val >> 16) as u8, (val >> 8) as u8, val as u8];
+        (si, bytes, dlen - 1, err)
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::{GosValue, RuntimeResult, UnsafePtr};
+use std::any::Any;
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct EventsFfi;
+
+#[ffi_impl]
+impl EventsFfi {
+    fn ffi_subscribe(ctx: &FfiCtx, name: GosValue) -> RuntimeResult<GosValue> {
+        let events = ctx
+            .events()
+            .ok_or_else(|| "events: the host has no event sources".to_owned())?;
+        let sub = events.subscribe(&name.as_string().as_str())?;
+        Ok(FfiCtx::new_unsafe_ptr(Rc::new(SubscriptionPtr(sub))))
+    }
+
+    async fn ffi_async_next(sub: GosValue) -> RuntimeResult<Vec<GosValue>> {
+        let sub = FfiCtx::as_user_data::<SubscriptionPtr>(&sub)?.0.clone();
+        Ok(match sub.next().await {
+            Some(data) => vec![data.into(), true.into()],
+            None => vec!["".to_owned().into(), false.into()],
+        })
+    }
+}
+
+struct SubscriptionPtr(Subscription);
+
+impl UnsafePtr for SubscriptionPtr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
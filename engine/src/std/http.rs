@@ -0,0 +1,109 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The client of the std package `net/http` sends the requests with the
+//! transport the host sets, see `Engine::set_http_transport`, so that it
+//! decides what scripts can reach and how.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use futures_lite::future::Future;
+use go_vm::types::{Elem8, GosValue, RuntimeResult};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The transport of an engine, kept in the host data of its FfiFactory.
+pub(crate) type Transport = Arc<dyn HttpTransport + Send + Sync>;
+
+/// A request made by a script.
+#[derive(Clone, Debug, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    /// in the order they are set, a key can repeat
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The response to an `HttpRequest`.
+#[derive(Clone, Debug, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// HttpTransport makes the requests of the scripts, the returned futures run
+/// on the executor of the VM, so other goroutines keep running while they're
+/// pending. An error is returned to the script as the error of the request.
+pub trait HttpTransport {
+    fn round_trip(
+        &self,
+        req: HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<HttpResponse, String>>>>;
+}
+
+#[derive(Ffi)]
+pub struct HttpFfi;
+
+#[ffi_impl(rename = "net.http")]
+impl HttpFfi {
+    fn ffi_async_round_trip(
+        ctx: &mut FfiCtx,
+        method: GosValue,
+        url: GosValue,
+        header: GosValue,
+        body: GosValue,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+        let req = HttpRequest {
+            method: method.as_string().as_str().to_string(),
+            url: url.as_string().as_str().to_string(),
+            headers: HttpFfi::parse_header(&header.as_string().as_str()),
+            body: match body.as_slice::<Elem8>() {
+                Some(s) => s.0.as_raw_slice::<u8>().to_vec(),
+                None => vec![],
+            },
+        };
+        let transport = ctx.host_data::<Transport>().cloned();
+        Box::pin(async move {
+            let result = match transport {
+                Some(t) => t.round_trip(req).await,
+                None => Err("http: the host has no transport".to_owned()),
+            };
+            HttpFfi::response(result)
+        })
+    }
+
+    fn response(result: Result<HttpResponse, String>) -> RuntimeResult<Vec<GosValue>> {
+        Ok(match result {
+            Ok(resp) => vec![
+                (resp.status as isize).into(),
+                HttpFfi::format_header(&resp.headers).into(),
+                FfiCtx::new_string_from_bytes(&resp.body),
+                "".to_owned().into(),
+            ],
+            Err(e) => vec![
+                0isize.into(),
+                "".to_owned().into(),
+                "".to_owned().into(),
+                e.into(),
+            ],
+        })
+    }
+
+    // headers cross the FFI as "Key: Value\n" lines
+    fn parse_header(s: &str) -> Vec<(String, String)> {
+        s.lines()
+            .filter_map(|l| l.split_once(": "))
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    fn format_header(headers: &[(String, String)]) -> String {
+        headers
+            .iter()
+            .map(|(k, v)| format!("{}: {}\n", k, v))
+            .collect()
+    }
+}
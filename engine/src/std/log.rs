@@ -0,0 +1,85 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The std packages `log` and `log/slog` send the records of the scripts to
+//! the sink the host sets, see `Engine::set_log_sink`, so that they end up in
+//! the host's logging pipeline. Without one, they are written to stderr.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::{GosElem, GosValue, RuntimeResult};
+use std::sync::Arc;
+
+/// The sink of an engine, kept in the host data of its FfiFactory.
+pub(crate) type Sink = Arc<dyn LogSink + Send + Sync>;
+
+/// The level of a record, the ones of `log/slog`, a script can use any
+/// value in between too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LogLevel(pub isize);
+
+impl LogLevel {
+    pub const DEBUG: LogLevel = LogLevel(-4);
+    pub const INFO: LogLevel = LogLevel(0);
+    pub const WARN: LogLevel = LogLevel(4);
+    pub const ERROR: LogLevel = LogLevel(8);
+}
+
+/// A record logged by a script. The records of `log` have no attributes,
+/// and are `INFO`s but for the ones of `Fatal` and `Panic`, which are
+/// `ERROR`s.
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub message: String,
+    /// the values are `interface{}`s, in the order they are added
+    pub attrs: Vec<(String, GosValue)>,
+}
+
+/// LogSink receives the records of the scripts, on the thread running them.
+pub trait LogSink {
+    fn log(&self, record: LogRecord);
+}
+
+#[derive(Ffi)]
+pub struct LogFfi;
+
+#[ffi_impl(rename = "log")]
+impl LogFfi {
+    /// Returns false if there is no sink, for the package to write the
+    /// record to stderr.
+    fn ffi_log(
+        ctx: &FfiCtx,
+        level: isize,
+        msg: GosValue,
+        keys: GosValue,
+        values: GosValue,
+    ) -> RuntimeResult<bool> {
+        let sink = match ctx.host_data::<Sink>() {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+        // the records of log have no attributes
+        let attrs = if keys.is_nil() {
+            vec![]
+        } else {
+            let keys = FfiCtx::slice_as_rust_slice::<GosElem>(&keys)?;
+            let values = FfiCtx::slice_as_rust_slice::<GosElem>(&values)?;
+            keys.iter()
+                .zip(values.iter())
+                .map(|(k, v)| {
+                    (
+                        k.borrow().as_string().as_str().to_string(),
+                        v.borrow().clone(),
+                    )
+                })
+                .collect()
+        };
+        sink.log(LogRecord {
+            level: LogLevel(level),
+            message: msg.as_string().as_str().to_string(),
+            attrs,
+        });
+        Ok(true)
+    }
+}
@@ -5,10 +5,20 @@
 /// Go 1.12
 ///
 mod bits;
+#[cfg(any(feature = "yaml", feature = "toml"))]
+mod config;
+mod crypto;
+mod encoding;
+mod events;
 mod fmt2;
+#[cfg(feature = "http")]
+pub(crate) mod http;
 mod io;
+pub(crate) mod log;
 pub(crate) mod os;
+mod rand;
 mod reflect;
+mod runtime;
 #[cfg(feature = "async")]
 mod sync;
 #[cfg(target_arch = "wasm32")]
@@ -24,4 +34,19 @@ pub(crate) fn register(factory: &mut go_vm::FfiFactory) {
     reflect::ReflectFfi::register(factory);
     io::IoFfi::register(factory);
     os::FileFfi::register(factory);
+    os::ProcFfi::register(factory);
+    runtime::RuntimeFfi::register(factory);
+    runtime::BuildInfoFfi::register(factory);
+    events::EventsFfi::register(factory);
+    rand::RandFfi::register(factory);
+    crypto::HashFfi::register(factory);
+    encoding::HexFfi::register(factory);
+    encoding::Base64Ffi::register(factory);
+    log::LogFfi::register(factory);
+    #[cfg(feature = "http")]
+    http::HttpFfi::register(factory);
+    #[cfg(feature = "yaml")]
+    config::YamlFfi::register(factory);
+    #[cfg(feature = "toml")]
+    config::TomlFfi::register(factory);
 }
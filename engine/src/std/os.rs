@@ -125,6 +125,33 @@ impl FileFfi {
     }
 }
 
+#[derive(Ffi)]
+pub struct ProcFfi;
+
+#[ffi_impl(rename = "os.proc")]
+impl ProcFfi {
+    fn ffi_args_len(ctx: &FfiCtx) -> isize {
+        ctx.args().len() as isize
+    }
+
+    fn ffi_arg(ctx: &FfiCtx, i: isize) -> GosValue {
+        FfiCtx::new_string(&ctx.args()[i as usize])
+    }
+
+    fn ffi_env_len(ctx: &FfiCtx) -> isize {
+        ctx.env().len() as isize
+    }
+
+    fn ffi_env(ctx: &FfiCtx, i: isize) -> (GosValue, GosValue) {
+        let (k, v) = &ctx.env()[i as usize];
+        (FfiCtx::new_string(k), FfiCtx::new_string(v))
+    }
+
+    fn ffi_exit(ctx: &FfiCtx, code: isize) {
+        ctx.exit(code as i32);
+    }
+}
+
 pub enum StdIo {
     StdIn,
     StdOut,
@@ -0,0 +1,16 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+
+#[derive(Ffi)]
+pub struct RandFfi;
+
+#[ffi_impl]
+impl RandFfi {
+    fn ffi_seed(ctx: &FfiCtx) -> i64 {
+        ctx.seed()
+    }
+}
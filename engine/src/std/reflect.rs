@@ -5,6 +5,7 @@
 extern crate self as go_engine;
 use crate::ffi::*;
 use go_vm::types::*;
+use go_vm::MetaView;
 use std::any::Any;
 use std::cell::RefCell;
 use std::mem;
@@ -50,6 +51,11 @@ fn val_to_map_iter(val: &GosValue) -> RuntimeResult<&StdMapIter> {
     val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdMapIter>()
 }
 
+#[inline]
+fn val_to_std_type(val: &GosValue) -> RuntimeResult<&StdType> {
+    val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdType>()
+}
+
 #[inline]
 fn meta_objs(p: *const MetadataObjs) -> &'static MetadataObjs {
     unsafe { &*p }
@@ -99,6 +105,45 @@ impl ReflectFfi {
         Ok(StdType::type_of(v, ctx))
     }
 
+    fn ffi_type_string(ctx: &FfiCtx, t: GosValue) -> RuntimeResult<GosValue> {
+        let t = val_to_std_type(&t)?;
+        Ok(FfiCtx::new_string(&t.view(ctx).to_string()))
+    }
+
+    fn ffi_type_elem(ctx: &FfiCtx, t: GosValue) -> RuntimeResult<(GosValue, usize)> {
+        match val_to_std_type(&t)?.view(ctx).elem() {
+            Some(elem) => Ok(StdType::new_ptr(elem.meta(), ctx)),
+            None => err_wrong_type!(),
+        }
+    }
+
+    fn ffi_type_num_field(ctx: &FfiCtx, t: GosValue) -> RuntimeResult<isize> {
+        let t = val_to_std_type(&t)?.view(ctx);
+        match t.kind() {
+            ValueType::Struct => Ok(t.fields().len() as isize),
+            _ => err_wrong_type!(),
+        }
+    }
+
+    fn ffi_type_field(
+        ctx: &FfiCtx,
+        t: GosValue,
+        i: isize,
+    ) -> RuntimeResult<(GosValue, GosValue, bool, GosValue, usize)> {
+        let t = val_to_std_type(&t)?.view(ctx);
+        if t.kind() != ValueType::Struct {
+            return err_wrong_type!();
+        }
+        match t.fields().get(i as usize) {
+            Some(f) => {
+                let (typ, kind) = StdType::new_ptr(f.typ.meta(), ctx);
+                let tag = FfiCtx::new_string(f.tag.unwrap_or_default());
+                Ok((FfiCtx::new_string(f.name), tag, f.embedded, typ, kind))
+            }
+            None => err_index_oor!(),
+        }
+    }
+
     fn ffi_bool_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<bool> {
         val_to_std_val(&p)?.bool_val(ctx)
     }
@@ -119,6 +164,10 @@ impl ReflectFfi {
         val_to_std_val(&p)?.bytes_val(ctx)
     }
 
+    fn ffi_string_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val(&p)?.string_val(ctx)
+    }
+
     fn ffi_elem(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
         val_to_std_val(&p)?.elem(ctx)
     }
@@ -167,6 +216,14 @@ impl ReflectFfi {
         Ok(val_to_std_val(&p)?.can_set())
     }
 
+    fn ffi_can_interface(p: GosValue) -> RuntimeResult<bool> {
+        Ok(val_to_std_val(&p)?.can_interface())
+    }
+
+    fn ffi_interface_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val(&p)?.interface(ctx)
+    }
+
     fn ffi_set(ctx: &mut FfiCtx, p: GosValue, val: GosValue) -> RuntimeResult<()> {
         val_to_std_val(&p)?.set(ctx, val_to_std_val(&val)?.val(ctx)?)
     }
@@ -244,7 +301,7 @@ impl StdValue {
     fn val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
         match self {
             Self::Value(v, _) => Ok(v.clone()),
-            Self::Pointer(p, _, _) => p.deref(&ctx.stack, &ctx.vm_objs.packages),
+            Self::Pointer(p, _, _) => p.deref(&ctx.stack, ctx.packages),
         }
     }
 
@@ -313,6 +370,14 @@ impl StdValue {
         Ok(val)
     }
 
+    fn string_val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        let val = self.val(ctx)?;
+        match val.typ() {
+            ValueType::String => Ok(val),
+            _ => err_wrong_type!(),
+        }
+    }
+
     fn elem(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
         let val = self.val(ctx)?;
         match val.typ() {
@@ -331,7 +396,10 @@ impl StdValue {
         if val.typ() != ValueType::Struct {
             err_wrong_type!()
         } else {
-            Ok((val.as_struct().0.borrow_fields().len() as isize).into())
+            // the values also have slots for the fields promoted from the
+            // embedded ones, after the declared fields
+            let view = MetaView::new(self.meta().unwrap(), ctx.vm_objs);
+            Ok((view.fields().len() as isize).into())
         }
     }
 
@@ -404,6 +472,26 @@ impl StdValue {
         }
     }
 
+    fn can_interface(&self) -> bool {
+        match self {
+            Self::Pointer(p, _, exported) => match p as &PointerObj {
+                PointerObj::StructField(_, _) => exported.unwrap(),
+                _ => true,
+            },
+            Self::Value(_, _) => true,
+        }
+    }
+
+    fn interface(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        let val = self.val(ctx)?;
+        match self.meta() {
+            // the values of interface types are interfaces already
+            _ if val.typ() == ValueType::Interface => Ok(val),
+            Some(m) => Ok(ctx.new_empty_interface(val, *m)),
+            None => err_wrong_type!(),
+        }
+    }
+
     fn can_set(&self) -> bool {
         match self {
             Self::Value(_, _) => false,
@@ -421,9 +509,7 @@ impl StdValue {
             return Err("reflect: value is not settable".to_owned().into());
         }
         match self {
-            Self::Pointer(p, _, _) => {
-                p.set_pointee(&val, ctx.stack, &ctx.vm_objs.packages, &ctx.gcc)
-            }
+            Self::Pointer(p, _, _) => p.set_pointee(&val, ctx.stack, ctx.packages, &ctx.gcc),
             _ => unreachable!(),
         }
     }
@@ -543,12 +629,14 @@ impl StdType {
     }
 
     fn type_of(val: &StdValue, ctx: &FfiCtx) -> (GosValue, usize) {
-        let m = val.meta().unwrap().clone();
+        StdType::new_ptr(val.meta().unwrap(), ctx)
+    }
+
+    /// Returns the type as a pointer for the Go side, with its kind.
+    fn new_ptr(m: Meta, ctx: &FfiCtx) -> (GosValue, usize) {
         let typ = StdType::new(m, &ctx.vm_objs.metas);
-        let kind = match m
-            .underlying(&ctx.vm_objs.metas)
-            .value_type(&ctx.vm_objs.metas)
-        {
+        // a pointer to a named type is a pointer, not of the named type's kind
+        let kind = match m.value_type(&ctx.vm_objs.metas) {
             ValueType::Bool => GosKind::Bool,
             ValueType::Int => GosKind::Int,
             ValueType::Int8 => GosKind::Int8,
@@ -579,6 +667,10 @@ impl StdType {
         };
         (FfiCtx::new_unsafe_ptr(Rc::new(typ)), (kind as usize))
     }
+
+    fn view<'a>(&self, ctx: &'a FfiCtx) -> MetaView<'a> {
+        MetaView::new(self.meta, ctx.vm_objs)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -591,7 +683,7 @@ struct StdMapIterInner {
 struct StdMapIter {
     inner: RefCell<StdMapIterInner>,
     key_meta: Meta,
-    //val_meta: Meta,
+    val_meta: Meta,
 }
 
 impl UnsafePtr for StdMapIter {
@@ -607,10 +699,11 @@ impl StdMapIter {
         let iter: GosMapIter<'static> = unsafe { mem::transmute(mref.iter()) };
         let metas = &ctx.vm_objs.metas;
         let map_meta = metas[v.meta().unwrap().underlying(metas).key].as_map();
-        let (key_meta, _) = (map_meta.0.clone(), map_meta.1.clone());
+        let (key_meta, val_meta) = (map_meta.0.clone(), map_meta.1.clone());
         let smi = StdMapIter {
             inner: RefCell::new(StdMapIterInner { iter, item: None }),
             key_meta,
+            val_meta,
         };
         Ok(FfiCtx::new_unsafe_ptr(Rc::new(smi)))
     }
@@ -638,6 +731,6 @@ impl StdMapIter {
                 .to_owned()
                 .into()),
         }
-        .map(|x| wrap_std_val(x, Some(self.key_meta)))
+        .map(|x| wrap_std_val(x, Some(self.val_meta)))
     }
 }
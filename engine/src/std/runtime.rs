@@ -0,0 +1,71 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use futures_lite::future;
+use go_vm::types::{GosValue, RuntimeResult};
+
+#[derive(Ffi)]
+pub struct RuntimeFfi;
+
+#[ffi_impl]
+impl RuntimeFfi {
+    // without the async feature there are no other goroutines to yield to
+    fn ffi_can_yield() -> bool {
+        cfg!(feature = "async")
+    }
+
+    async fn ffi_async_gosched() -> RuntimeResult<Vec<GosValue>> {
+        future::yield_now().await;
+        Ok(vec![])
+    }
+
+    fn ffi_num_goroutine(ctx: &FfiCtx) -> isize {
+        ctx.goroutine_count() as isize
+    }
+
+    fn ffi_gc(ctx: &FfiCtx) {
+        ctx.collect_garbage();
+    }
+}
+
+#[derive(Ffi)]
+pub struct BuildInfoFfi;
+
+#[ffi_impl(rename = "runtime.buildinfo")]
+impl BuildInfoFfi {
+    fn ffi_version() -> GosValue {
+        FfiCtx::new_string(go_vm::VERSION)
+    }
+
+    // the features of the engine that make a difference to scripts
+    fn ffi_features() -> GosValue {
+        let features = [
+            ("async", cfg!(feature = "async")),
+            ("btree_map", cfg!(feature = "btree_map")),
+            ("instruction_pos", cfg!(feature = "instruction_pos")),
+            ("opcode_stats", cfg!(feature = "opcode_stats")),
+            ("http", cfg!(feature = "http")),
+            ("yaml", cfg!(feature = "yaml")),
+            ("toml", cfg!(feature = "toml")),
+            ("wasm", cfg!(feature = "wasm")),
+        ];
+        let names: Vec<&str> = features.iter().filter(|x| x.1).map(|x| x.0).collect();
+        FfiCtx::new_string(&names.join(","))
+    }
+
+    fn ffi_app_version(ctx: &FfiCtx) -> GosValue {
+        FfiCtx::new_string(&ctx.app_info().version)
+    }
+
+    fn ffi_settings_len(ctx: &FfiCtx) -> isize {
+        ctx.app_info().settings.len() as isize
+    }
+
+    fn ffi_setting(ctx: &FfiCtx, i: isize) -> (GosValue, GosValue) {
+        let (k, v) = &ctx.app_info().settings[i as usize];
+        (FfiCtx::new_string(k), FfiCtx::new_string(v))
+    }
+}
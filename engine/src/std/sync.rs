@@ -19,11 +19,11 @@ use std::vec;
 macro_rules! create_mutex {
     ($arg0:expr, $ctx:expr, $typ:tt) => {{
         let pp = $arg0.as_pointer().unwrap();
-        let p = pp.deref(&$ctx.stack, &$ctx.vm_objs.packages)?;
+        let p = pp.deref(&$ctx.stack, $ctx.packages)?;
         if p.is_nil() {
             let inner = $typ::new();
             let p = FfiCtx::new_unsafe_ptr(Rc::new(inner.clone()));
-            pp.set_pointee(&p, $ctx.stack, &$ctx.vm_objs.packages, &$ctx.gcc)?;
+            pp.set_pointee(&p, $ctx.stack, $ctx.packages, &$ctx.gcc)?;
             Ok(inner)
         } else {
             Ok(p.as_unsafe_ptr()
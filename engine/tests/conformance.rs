@@ -0,0 +1,151 @@
+//! Runs the Go programs in `tests/conformance` and compares what they print
+//! to the standard output, the panic that stops them and the errors that
+//! keep them from compiling with what Go gives, checked in next to them:
+//! - `NAME.out`, the standard output;
+//! - `NAME.err`, the panic as Go prints its first line, `panic: ...`, or the
+//!   compile errors, one `line: message` per line, absent if there's neither.
+//!
+//! `golden.sh` regenerates them with the go command. The programs goscript
+//! doesn't agree with Go on yet are listed in `known_failures.txt`, they fail
+//! the test when they start to agree so the list doesn't go stale.
+//!
+//! Run with `cargo test -p go-engine --features conformance --test conformance`,
+//! set `CONFORMANCE` to run only the programs whose names contain it.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+extern crate go_engine as engine;
+
+const DIR: &str = "./tests/conformance";
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    fn take_string(&self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut *self.0.lock().unwrap())).into_owned()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    out: String,
+    err: String,
+}
+
+fn expected(path: &Path) -> Outcome {
+    let read = |ext: &str| fs::read_to_string(path.with_extension(ext)).unwrap_or_default();
+    Outcome {
+        out: read("out"),
+        err: read("err"),
+    }
+}
+
+fn run_goscript(path: &Path) -> Outcome {
+    let stdout = SharedBuf::default();
+    let mut cfg = engine::Config::default();
+    cfg.std_out = Some(Box::new(stdout.clone()));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let panicked = Rc::new(RefCell::new(None));
+    let p = panicked.clone();
+    let ph: Option<Rc<dyn Fn(String, String)>> = Some(Rc::new(move |msg: String, _| {
+        *p.borrow_mut() = Some(format!("panic: {}\n", msg));
+    }));
+    let err = match engine::run(cfg, &sr, path, ph) {
+        Ok(_) => panicked.borrow_mut().take().unwrap_or_default(),
+        Err(el) => {
+            el.sort();
+            el.borrow()
+                .iter()
+                .map(|e| format!("{}: {}\n", e.pos.line, e.msg))
+                .collect()
+        }
+    };
+    Outcome {
+        out: stdout.take_string(),
+        err,
+    }
+}
+
+fn known_failures() -> BTreeSet<String> {
+    fs::read_to_string(Path::new(DIR).join("known_failures.txt"))
+        .unwrap()
+        .lines()
+        .map(|x| x.split('#').next().unwrap().trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_owned())
+        .collect()
+}
+
+fn describe(name: &str, want: &Outcome, got: &Outcome) -> String {
+    let mut s = format!("{}:\n", name);
+    for (what, w, g) in [
+        ("stdout", &want.out, &got.out),
+        ("err", &want.err, &got.err),
+    ] {
+        if w != g {
+            s.push_str(&format!(
+                "--- {} of go\n{}--- {} of goscript\n{}",
+                what, w, what, g
+            ));
+        }
+    }
+    s
+}
+
+#[test]
+fn conformance() {
+    let filter = std::env::var("CONFORMANCE").unwrap_or_default();
+    let mut known = known_failures();
+    let mut paths: Vec<PathBuf> = fs::read_dir(DIR)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .filter(|x| x.extension().map_or(false, |x| x == "go"))
+        .collect();
+    paths.sort();
+
+    let mut failures = vec![];
+    for path in paths.iter() {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let is_known = known.remove(name);
+        if !name.contains(&filter) {
+            continue;
+        }
+        let want = expected(path);
+        let got = run_goscript(path);
+        match (want == got, is_known) {
+            (true, true) => failures.push(format!(
+                "{}: agrees with go now, remove it from known_failures.txt\n",
+                name
+            )),
+            (false, false) => failures.push(describe(name, &want, &got)),
+            _ => {}
+        }
+    }
+    for name in known.iter() {
+        failures.push(format!("{}: in known_failures.txt but not found\n", name));
+    }
+    assert!(
+        failures.is_empty(),
+        "not conforming, {} of {} programs:\n\n{}",
+        failures.len(),
+        paths.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,28 @@
+package main
+
+import "fmt"
+
+type Point struct {
+	X, Y int
+}
+
+type Celsius float64
+
+func (c Celsius) String() string { return fmt.Sprintf("%.1f°C", float64(c)) }
+
+func main() {
+	fmt.Println("hello", 42, -7, 3.5, true, nil)
+	fmt.Println([]int{1, 2, 3}, [2]string{"a", "b"}, []string{})
+	fmt.Println(Point{1, 2}, &Point{3, 4})
+	fmt.Printf("%v %+v %d %s %q\n", Point{1, 2}, Point{1, 2}, 255, "str", "quoted")
+	fmt.Printf("%x %X %o %b %c %U\n", 255, 255, 8, 5, 'A', 'A')
+	fmt.Printf("%5d|%-5d|%05d\n", 42, 42, 42)
+	fmt.Printf("%.2f %8.3f %e %g\n", 3.14159, 2.5, 1234.5678, 0.000012)
+	fmt.Printf("%t %v\n", false, 'x')
+	fmt.Printf("%T %T %T %T\n", 1, "s", 2.0, Point{})
+	fmt.Println(Celsius(21.5))
+	fmt.Print("a", "b", 1, 2, "c\n")
+	s := fmt.Sprint(1, 2, "x", 3)
+	fmt.Println(s, len(s))
+	fmt.Println(1e6, 1e21, 100000000.0, 1.0/3.0, float32(0.1))
+}
@@ -0,0 +1,16 @@
+package main
+
+func f() int {
+	for {
+		break
+	}
+}
+
+func main() {
+	a := 1
+	b := "x"
+	_ = a + b
+	var s string = 2
+	_ = s
+	undefinedFn()
+}
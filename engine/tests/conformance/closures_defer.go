@@ -0,0 +1,58 @@
+package main
+
+import "fmt"
+
+func counter() func() int {
+	n := 0
+	return func() int {
+		n++
+		return n
+	}
+}
+
+func deferred() (result int) {
+	defer func() { result *= 2 }()
+	for i := 0; i < 3; i++ {
+		defer fmt.Println("defer", i)
+	}
+	return 5
+}
+
+func safeDiv(a, b int) (q int, err error) {
+	defer func() {
+		if r := recover(); r != nil {
+			err = fmt.Errorf("recovered: %v", r)
+		}
+	}()
+	return a / b, nil
+}
+
+func try(f func()) (msg string) {
+	defer func() { msg = fmt.Sprint(recover()) }()
+	f()
+	return "no panic"
+}
+
+func main() {
+	c1, c2 := counter(), counter()
+	fmt.Println(c1(), c1(), c1(), c2())
+	fmt.Println(deferred())
+	fmt.Println(safeDiv(7, 2))
+	fmt.Println(safeDiv(1, 0))
+
+	fmt.Println(try(func() {}))
+	fmt.Println(try(func() { panic("boom") }))
+	fmt.Println(try(func() {
+		var m map[string]int
+		m["a"] = 1
+	}))
+	fmt.Println(try(func() {
+		var a []int
+		i := 3
+		_ = a[i]
+	}))
+	fmt.Println(try(func() {
+		var p *struct{ x int }
+		_ = p.x
+	}))
+}
@@ -0,0 +1,38 @@
+package main
+
+import (
+	"fmt"
+	"math"
+)
+
+type Weekday int
+
+const (
+	Sunday Weekday = iota
+	Monday
+	Tuesday
+)
+
+const (
+	_  = iota
+	KB = 1 << (10 * iota)
+	MB
+)
+
+func main() {
+	fmt.Println(Sunday, Tuesday, KB, MB)
+	var u8 uint8 = 250
+	u8 += 10
+	var i8 int8 = 127
+	i8++
+	fmt.Println(u8, i8)
+	fmt.Println(7/2, -7/2, 7%3, -7%3, 7.0/2)
+	fmt.Println(1<<3|1, 6&3, 6^3, 6&^3, ^0)
+	fmt.Println(math.MaxInt64, math.MinInt64, math.MaxUint32)
+	fmt.Println(math.Sqrt(2), math.Floor(-2.5), math.Inf(1), -math.Inf(1), math.NaN())
+	x := 3.9
+	fmt.Println(int(x), int(-x), float64(7)/2, uint32(1<<32-1))
+	var r rune = 'é'
+	fmt.Println(r, string(r), byte('a'))
+	fmt.Printf("%v %d %T\n", Monday, Monday, Monday)
+}
@@ -0,0 +1,57 @@
+package main
+
+import "fmt"
+
+func classify(n int) string {
+	switch {
+	case n < 0:
+		return "neg"
+	case n == 0:
+		return "zero"
+	case n%2 == 0:
+		return "even"
+	}
+	return "odd"
+}
+
+func main() {
+	for _, n := range []int{-1, 0, 3, 4} {
+		fmt.Print(classify(n), " ")
+	}
+	fmt.Println()
+
+outer:
+	for i := 0; i < 3; i++ {
+		for j := 0; j < 3; j++ {
+			if j == 2 {
+				continue outer
+			}
+			if i == 2 {
+				break outer
+			}
+			fmt.Print(i, j, ";")
+		}
+	}
+	fmt.Println()
+
+	switch x := 2; x {
+	case 1:
+		fmt.Println("one")
+	case 2:
+		fmt.Println("two")
+		fallthrough
+	case 3:
+		fmt.Println("three")
+	default:
+		fmt.Println("other")
+	}
+
+	i := 0
+loop:
+	if i < 3 {
+		fmt.Print(i)
+		i++
+		goto loop
+	}
+	fmt.Println()
+}
@@ -0,0 +1,24 @@
+#!/bin/sh
+# Regenerates the golden files of the conformance corpus with the go command
+# on PATH, for each NAME.go:
+# - NAME.out, what it prints to the standard output;
+# - NAME.err, the first line of the panic that stops it, or its compile
+#   errors as "line: message", removed if there's neither.
+# Only the programs given as arguments are run if there are any.
+set -u
+cd "$(dirname "$0")"
+[ $# -eq 0 ] && set -- *.go
+tmp=$(mktemp)
+trap 'rm -f "$tmp"' EXIT
+for src in "$@"; do
+	name=${src%.go}
+	go run "$src" >"$name.out" 2>"$tmp"
+	if grep -q '^panic: ' "$tmp"; then
+		grep -m1 '^panic: ' "$tmp" >"$name.err"
+	elif grep -q '^\./[^:]*\.go:[0-9]*:[0-9]*: ' "$tmp"; then
+		grep '^\./[^:]*\.go:[0-9]*:[0-9]*: ' "$tmp" |
+			sed 's|^\./[^:]*\.go:\([0-9]*\):[0-9]*: |\1: |' >"$name.err"
+	else
+		rm -f "$name.err"
+	fi
+done
@@ -0,0 +1,81 @@
+package main
+
+import (
+	"fmt"
+	"sync"
+)
+
+func worker(jobs <-chan int, results chan<- int, wg *sync.WaitGroup) {
+	defer wg.Done()
+	for {
+		j, ok := <-jobs
+		if !ok {
+			return
+		}
+		results <- j * j
+	}
+}
+
+func main() {
+	jobs := make(chan int, 10)
+	results := make(chan int, 10)
+	var wg sync.WaitGroup
+	for w := 0; w < 3; w++ {
+		wg.Add(1)
+		go worker(jobs, results, &wg)
+	}
+	for i := 1; i <= 9; i++ {
+		jobs <- i
+	}
+	close(jobs)
+	wg.Wait()
+	close(results)
+	sum := 0
+	for {
+		r, ok := <-results
+		if !ok {
+			break
+		}
+		sum += r
+	}
+	fmt.Println("sum", sum)
+
+	ping := make(chan string)
+	done := make(chan bool)
+	go func() {
+		for i := 0; i < 3; i++ {
+			fmt.Println("got", <-ping)
+		}
+		done <- true
+	}()
+	for _, m := range []string{"a", "b", "c"} {
+		ping <- m
+	}
+	<-done
+
+	var mu sync.Mutex
+	total := 0
+	var wg2 sync.WaitGroup
+	for i := 0; i < 10; i++ {
+		wg2.Add(1)
+		go func(n int) {
+			defer wg2.Done()
+			mu.Lock()
+			total += n
+			mu.Unlock()
+		}(i)
+	}
+	wg2.Wait()
+	fmt.Println("total", total)
+
+	c := make(chan int, 1)
+	for i := 0; i < 2; i++ {
+		select {
+		case v := <-c:
+			fmt.Println("recv", v)
+		default:
+			fmt.Println("empty")
+			c <- 7
+		}
+	}
+}
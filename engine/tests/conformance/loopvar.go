@@ -0,0 +1,15 @@
+package main
+
+import "fmt"
+
+func main() {
+	// each iteration has its own i since Go 1.22
+	var fs []func() int
+	for i := 0; i < 3; i++ {
+		fs = append(fs, func() int { return i })
+	}
+	for _, f := range fs {
+		fmt.Print(f(), " ")
+	}
+	fmt.Println()
+}
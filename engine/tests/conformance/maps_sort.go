@@ -0,0 +1,47 @@
+package main
+
+import (
+	"fmt"
+	"sort"
+)
+
+type byLen []string
+
+func (s byLen) Len() int           { return len(s) }
+func (s byLen) Less(i, j int) bool { return len(s[i]) < len(s[j]) }
+func (s byLen) Swap(i, j int)      { s[i], s[j] = s[j], s[i] }
+
+func main() {
+	counts := map[string]int{}
+	for _, w := range []string{"b", "a", "c", "a", "b", "a"} {
+		counts[w]++
+	}
+	fmt.Println(counts, len(counts))
+	keys := make([]string, 0, len(counts))
+	for k := range counts {
+		keys = append(keys, k)
+	}
+	sort.Strings(keys)
+	for _, k := range keys {
+		fmt.Printf("%s=%d ", k, counts[k])
+	}
+	fmt.Println()
+	delete(counts, "b")
+	v, ok := counts["b"]
+	fmt.Println(v, ok, counts)
+
+	nums := []int{5, 2, 8, 1, 9, 3}
+	sort.Ints(nums)
+	fmt.Println(nums, sort.SearchInts(nums, 8))
+	people := []struct {
+		Name string
+		Age  int
+	}{{"Al", 30}, {"Bo", 25}, {"Cy", 35}}
+	sort.Slice(people, func(i, j int) bool { return people[i].Age < people[j].Age })
+	fmt.Println(people)
+	words := byLen{"banana", "kiwi", "apple", "fig"}
+	sort.Sort(words)
+	fmt.Println(words, sort.IsSorted(words))
+	m := map[int][]string{2: {"x"}, 1: {"y", "z"}}
+	fmt.Println(m)
+}
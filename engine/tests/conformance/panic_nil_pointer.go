@@ -0,0 +1,11 @@
+package main
+
+import "fmt"
+
+type T struct{ x int }
+
+func main() {
+	var p *T
+	fmt.Println(p == nil)
+	fmt.Println(p.x)
+}
@@ -0,0 +1,7 @@
+package main
+
+func main() {
+	s := make([]int, 3)
+	j := 5
+	_ = s[1:j]
+}
@@ -0,0 +1,33 @@
+package main
+
+import "fmt"
+
+func main() {
+	s := make([]int, 3, 5)
+	fmt.Println(s, len(s), cap(s))
+	s = append(s, 1, 2)
+	t := s[1:4]
+	t[0] = 9
+	fmt.Println(s, t, len(t), cap(t))
+	s = append(s, 3)
+	s[0] = 100
+	fmt.Println(t[0], s[0])
+
+	a := [3]int{1, 2, 3}
+	b := a
+	b[0] = 9
+	fmt.Println(a, b, a == [3]int{1, 2, 3})
+	n := copy(s, []int{7, 8})
+	fmt.Println(n, s[:3])
+
+	var nilS []int
+	fmt.Println(nilS == nil, len(nilS), nilS)
+	grid := [2][3]int{}
+	grid[1][2] = 5
+	fmt.Println(grid)
+	full := s[1:2:3]
+	fmt.Println(len(full), cap(full))
+	bs := []byte("abc")
+	bs[0] = 'X'
+	fmt.Println(string(bs), bs)
+}
@@ -0,0 +1,41 @@
+package main
+
+import (
+	"fmt"
+	"strconv"
+	"strings"
+)
+
+func main() {
+	s := "The quick brown fox"
+	fmt.Println(strings.ToUpper(s), strings.ToLower(s))
+	fmt.Println(strings.Fields(s), len(strings.Fields(s)))
+	fmt.Println(strings.Split("a,b,,c", ","), len(strings.Split("a,b,,c", ",")))
+	fmt.Println(strings.Join([]string{"x", "y", "z"}, "-"))
+	fmt.Println(strings.Contains(s, "quick"), strings.HasPrefix(s, "The"), strings.HasSuffix(s, "cat"))
+	fmt.Println(strings.Index(s, "brown"), strings.LastIndex("go gopher", "go"), strings.Index(s, "zzz"))
+	fmt.Println(strings.Repeat("ab", 3), strings.Replace("oink oink oink", "k", "ky", 2), strings.ReplaceAll("oink oink", "oink", "moo"))
+	fmt.Println(strings.TrimSpace("  pad  "), strings.Trim("xxhixx", "x"), strings.TrimPrefix("prefix-body", "prefix-"))
+
+	n, err := strconv.Atoi("123")
+	fmt.Println(n+1, err)
+	_, err = strconv.Atoi("12a")
+	fmt.Println(err)
+	f, _ := strconv.ParseFloat("2.5", 64)
+	fmt.Println(f*2, strconv.Itoa(-45), strconv.Quote("hi\n"), strconv.FormatInt(255, 16))
+	b, err := strconv.ParseBool("maybe")
+	fmt.Println(b, err)
+
+	var sb strings.Builder
+	for i := 0; i < 3; i++ {
+		sb.WriteString(strconv.Itoa(i))
+		sb.WriteByte(',')
+	}
+	fmt.Println(sb.String(), sb.Len())
+
+	for i, r := range "héllo" {
+		fmt.Print(i, ":", string(r), " ")
+	}
+	fmt.Println()
+	fmt.Println(len("héllo"), []byte("hé"), []rune("hé"))
+}
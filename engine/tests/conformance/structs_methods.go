@@ -0,0 +1,67 @@
+package main
+
+import (
+	"errors"
+	"fmt"
+)
+
+type Shape interface {
+	Area() float64
+	Perimeter() float64
+}
+
+type Rect struct{ W, H float64 }
+
+func (r Rect) Area() float64      { return r.W * r.H }
+func (r Rect) Perimeter() float64 { return 2 * (r.W + r.H) }
+
+type Square struct {
+	Rect
+	Name string
+}
+
+type Counter struct{ n int }
+
+func (c *Counter) Inc() { c.n++ }
+
+type NotFound struct{ Key string }
+
+func (e *NotFound) Error() string { return "not found: " + e.Key }
+
+func find(key string) error {
+	if key == "ok" {
+		return nil
+	}
+	return &NotFound{key}
+}
+
+func main() {
+	shapes := []Shape{Rect{2, 3}, Square{Rect{4, 4}, "sq"}}
+	for _, s := range shapes {
+		fmt.Println(s.Area(), s.Perimeter())
+	}
+	sq := Square{Rect{1, 2}, "s"}
+	fmt.Printf("%v %+v\n", sq, sq)
+
+	var c Counter
+	c.Inc()
+	c.Inc()
+	p := &c
+	p.Inc()
+	fmt.Println(c.n, *p)
+
+	err := find("k")
+	fmt.Println(err, find("ok") == nil)
+	wrapped := fmt.Errorf("lookup: %w", err)
+	fmt.Println(wrapped, errors.Unwrap(wrapped) == err)
+
+	if _, ok := shapes[1].(Square); ok {
+		fmt.Println("square")
+	}
+	switch v := shapes[0].(type) {
+	case Square:
+		fmt.Println("square", v.Name)
+	case Rect:
+		fmt.Println("rect", v.W)
+	}
+}
@@ -66,6 +66,218 @@ fn run_path(path: &str, trace: bool, fail_on_panic: bool) -> Result<(), engine::
     result
 }
 
+#[cfg(feature = "go_std")]
+fn run_build(path: &str, ctx: engine::BuildContext) -> Result<(), engine::ErrorList> {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"))
+        .with_build_context(ctx);
+    let ph: Option<Rc<dyn Fn(String, String)>> =
+        Some(Rc::new(move |msg: String, stack: String| {
+            eprintln!("{}\n", msg);
+            eprintln!("{}\n", stack);
+            panic!("test panicked");
+        }));
+    let result = engine::run(engine::Config::default(), &sr, Path::new(path), ph);
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    result
+}
+
+mod host {
+    use go_engine::ffi::*;
+    use std::any::Any;
+    use std::rc::Rc;
+
+    struct Handle {
+        id: isize,
+    }
+
+    impl UnsafePtr for Handle {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn display(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "handle#{}", self.id)
+        }
+    }
+
+    #[derive(Ffi)]
+    pub struct HostFfi;
+
+    #[ffi_impl(rename = "test.host")]
+    impl HostFfi {
+        fn ffi_new_handle(ctx: &mut FfiCtx, id: isize) -> GosValue {
+            ctx.new_user_data(Rc::new(Handle { id }))
+        }
+
+        fn ffi_new_typed_handle(
+            ctx: &mut FfiCtx,
+            typ: GosValue,
+            id: isize,
+        ) -> RuntimeResult<GosValue> {
+            let typ = ctx.named_type(&typ.as_string().as_str())?;
+            ctx.new_typed_user_data(Rc::new(Handle { id }), typ)
+        }
+
+        fn ffi_handle_id(h: GosValue) -> RuntimeResult<isize> {
+            Ok(FfiCtx::as_user_data::<Handle>(&h)?.id)
+        }
+
+        fn ffi_describe(h: GosValue) -> String {
+            h.to_string()
+        }
+
+        fn ffi_format(ctx: &mut FfiCtx, verb: GosValue, v: GosValue) -> RuntimeResult<String> {
+            let verb = match verb.as_string().as_str().as_ref() {
+                "v" => Verb::V,
+                "+v" => Verb::PlusV,
+                "#v" => Verb::SharpV,
+                s => return Err(format!("unknown verb {}", s).into()),
+            };
+            Ok(ctx.format_value(&v, None, verb))
+        }
+
+        fn ffi_format_depth(ctx: &mut FfiCtx, v: GosValue, depth: isize) -> String {
+            ValueFmt::new(&v, Verb::V)
+                .with_type(None, ctx.vm_objs)
+                .with_max_depth(depth as usize)
+                .to_string()
+        }
+
+        fn ffi_goroutine_id(ctx: &mut FfiCtx) -> isize {
+            ctx.goroutine_id() as isize
+        }
+
+        fn ffi_set_tag(ctx: &mut FfiCtx, tag: isize) {
+            ctx.set_goroutine_local(Some(Rc::new(tag)));
+        }
+
+        fn ffi_tag(ctx: &mut FfiCtx) -> isize {
+            ctx.goroutine_local()
+                .and_then(|x| x.downcast_ref::<isize>().copied())
+                .unwrap_or(-1)
+        }
+
+        fn ffi_parse_int(s: GosValue) -> Result<isize, std::num::ParseIntError> {
+            s.as_string().as_str().parse::<isize>()
+        }
+
+        fn ffi_split(s: GosValue) -> Result<(String, String), String> {
+            let s = s.as_string().as_str().to_string();
+            s.split_once('=')
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .ok_or_else(|| format!("no '=' in {:?}", s))
+        }
+
+        fn ffi_check(ok: bool) -> std::result::Result<(), String> {
+            ok.then(|| ()).ok_or_else(|| "check failed".to_owned())
+        }
+
+        fn ffi_digits(ctx: &mut FfiCtx, n: isize) -> Result<GosValue, &'static str> {
+            if n < 0 {
+                return Err("negative");
+            }
+            let digits = n.to_string();
+            let digits = digits.bytes().map(|b| ((b - b'0') as isize).into());
+            Ok(ctx.new_slice(digits.collect(), ValueType::Int))
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+mod events {
+    use go_engine::ffi::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::pin::Pin;
+
+    thread_local! {
+        static HANDLERS: RefCell<HashMap<String, Callback>> = RefCell::new(HashMap::new());
+    }
+
+    fn handler(name: &GosValue) -> RuntimeResult<Callback> {
+        let name = name.as_string().as_str().to_string();
+        HANDLERS
+            .with(|h| h.borrow().get(&name).cloned())
+            .ok_or_else(|| format!("no handler for {}", name).into())
+    }
+
+    #[derive(Ffi)]
+    pub struct EventsFfi;
+
+    #[ffi_impl(rename = "test.events")]
+    impl EventsFfi {
+        fn ffi_on(ctx: &mut FfiCtx, name: GosValue, f: GosValue) -> RuntimeResult<()> {
+            let cb = ctx.new_callback(&f)?;
+            let name = name.as_string().as_str().to_string();
+            HANDLERS.with(|h| h.borrow_mut().insert(name, cb));
+            Ok(())
+        }
+
+        fn ffi_emit(name: GosValue, v: isize) -> RuntimeResult<()> {
+            handler(&name)?.call(vec![v.into()]).map(|_| ())
+        }
+
+        fn ffi_async_emit_wait(
+            _ctx: &mut FfiCtx,
+            name: GosValue,
+            v: isize,
+        ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+            let call = handler(&name).and_then(|cb| cb.call(vec![v.into()]));
+            Box::pin(async move {
+                match call?.await {
+                    Ok(results) => Ok(results),
+                    Err(_) => Ok(vec![(-1 as isize).into()]),
+                }
+            })
+        }
+
+        fn ffi_async_emit_blocking(
+            ctx: &mut FfiCtx,
+            name: GosValue,
+            v: isize,
+        ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+            let call = handler(&name).and_then(|cb| cb.call_blocking(ctx, vec![v.into()]));
+            Box::pin(async move {
+                match call?.await {
+                    Ok(results) => Ok(results),
+                    Err(_) => Ok(vec![(-1 as isize).into()]),
+                }
+            })
+        }
+    }
+}
+
+#[cfg(feature = "go_std")]
+fn run_with_host(path: &str) -> Result<(), engine::ErrorList> {
+    let mut engine = engine::Engine::new();
+    engine.register_extension(
+        host::HostFfi::auto_gen_ffi_id(),
+        host::HostFfi::auto_gen_ffi_new(),
+    );
+    #[cfg(feature = "async")]
+    engine.register_extension(
+        events::EventsFfi::auto_gen_ffi_id(),
+        events::EventsFfi::auto_gen_ffi_new(),
+    );
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let ph: Option<Rc<dyn Fn(String, String)>> =
+        Some(Rc::new(move |msg: String, stack: String| {
+            eprintln!("{}\n", msg);
+            eprintln!("{}\n", stack);
+            panic!("test panicked");
+        }));
+    let result = engine.run_source(false, false, &sr, Path::new(path), ph);
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    result
+}
+
 #[cfg(not(feature = "go_std"))]
 fn run_path(_path: &str, _trace: bool, fail_on_panic: bool) -> Result<(), engine::ErrorList> {
     unimplemented!()
@@ -174,135 +386,1490 @@ fn test_source() {
 }
 
 #[test]
-#[cfg(feature = "read_zip")]
-fn test_zip() {
-    let source = r#"
-    package main
-    import (
-        "fmt"
-    )
-    func main() {
-        fmt.Println("hello from raw zip lalala")
-    }
-    "#;
-    let result = run_zip_and_string("./tests/std.zip", Cow::Borrowed(source), false);
+#[cfg(feature = "read_zip")]
+fn test_zip() {
+    let source = r#"
+    package main
+    import (
+        "fmt"
+    )
+    func main() {
+        fmt.Println("hello from raw zip lalala")
+    }
+    "#;
+    let result = run_zip_and_string("./tests/std.zip", Cow::Borrowed(source), false);
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(all(feature = "read_zip", feature = "go_std"))]
+fn test_zip_g2case0() {
+    let result = run_zip("./tests/std.zip", "./tests/group2/case0.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g2case0() {
+    let result = run("./tests/group2/case0.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g2case1() {
+    let result = run("./tests/group2/case1.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g2case2() {
+    let result = run_path("./tests/group2/case2.gos", true, false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g2case3() {
+    let result = run("./tests/group2/case3.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g2nil() {
+    let result = run("./tests/group2/nil.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g2display() {
+    let result = run("./tests/group2/display.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_unicode0() {
+    time_test!();
+
+    let result = run("./tests/group2/unicode0.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_sync_mutex() {
+    let result = run("./tests/group2/sync_mutex.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_sync_rwmutex() {
+    let result = run("./tests/group2/sync_rwmutex.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_local() {
+    let result = run("./tests/group2/local.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_imports() {
+    let result = run("./tests/group2/imports.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_ifaces() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group2/ifaces.gos");
+    let engine = engine::Engine::new();
+    let mut bc = engine.compile(&sr, path, false, false, false).unwrap();
+    // one binding of *T to Namer for the conversions in both packages
+    assert_eq!(bc.ifaces.len(), 1);
+    assert!(engine.run_bytecode(&bc).is_none());
+
+    // only the links are serialized, the bindings are made again
+    #[cfg(feature = "serde_borsh")]
+    {
+        use borsh::{BorshDeserialize, BorshSerialize};
+        let data = bc.try_to_vec().unwrap();
+        let decoded = engine::ffi::Bytecode::try_from_slice(&data).unwrap();
+        assert_eq!(decoded.iface_links, bc.iface_links);
+        assert_eq!(decoded.ifaces.len(), 1);
+        assert!(engine.run_bytecode(&decoded).is_none());
+    }
+
+    let (namer, _) = bc.iface_links[0];
+    let int = bc.objects.prim_meta.mint;
+    bc.iface_links.push((namer, int));
+    assert_eq!(bc.link().unwrap_err(), "link error: missing method Name");
+}
+
+#[test]
+fn test_strip_unused() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/strip_unused.gos");
+    let engine = engine::Engine::new();
+    let mut bc = engine.compile(&sr, path, false, false, false).unwrap();
+    #[cfg(feature = "serde_borsh")]
+    let full_len = borsh::BorshSerialize::try_to_vec(&bc).unwrap().len();
+    let stripped = bc.strip_unused();
+    assert!(stripped.functions > 0 && stripped.consts > 0);
+    assert_eq!(bc.strip_unused(), engine::ffi::Stripped::default());
+
+    let has_code = |name: &str| {
+        let pkg = &bc.objects.packages[bc.main_pkg];
+        let member = pkg.member(pkg.member_indices()[name]);
+        let (cls, _) = member.as_closure().unwrap();
+        match &cls {
+            engine::ffi::ClosureObj::Gos(cls) => !bc.objects.functions[cls.func].code.is_empty(),
+            _ => unreachable!(),
+        }
+    };
+    assert!(!has_code("unused") && !has_code("helper"));
+    assert!(has_code("main") && has_code("Exported"));
+    assert!(engine.run_bytecode(&bc).is_none());
+
+    #[cfg(feature = "serde_borsh")]
+    {
+        use borsh::{BorshDeserialize, BorshSerialize};
+        let data = bc.try_to_vec().unwrap();
+        assert!(data.len() < full_len);
+        let decoded = engine::ffi::Bytecode::try_from_slice(&data).unwrap();
+        assert!(engine.run_bytecode(&decoded).is_none());
+    }
+}
+
+#[test]
+fn test_line_directive_stack() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/line_directive.gos");
+    let engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, true, false, false).unwrap();
+    let panic = engine.run_bytecode(&bc).unwrap();
+    let stack = engine::ffi::CallStackDisplay::new(&panic, &bc).to_string();
+    // the directive gives no column
+    assert!(stack.contains("\ntests/group1/template.tmpl:102\n"));
+}
+
+#[test]
+fn test_strip_symbols() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/strip_symbols.gos");
+    let engine = engine::Engine::new();
+    let mut bc = engine.compile(&sr, path, true, false, false).unwrap();
+    let info = bc.strip_symbols();
+    assert!(bc.file_set.is_none() && bc.source_map.is_none());
+    let main = &bc.objects.packages[bc.main_pkg];
+    assert!(main.member_indices().get("Count").is_none());
+    assert!(main.member_indices().get("describe").is_none());
+    assert_eq!(bc.types("main")[0].name(), Some("point"));
+
+    let panic = engine.run_bytecode(&bc).unwrap();
+    let stack = engine::ffi::CallStackDisplay::new(&panic, &bc).to_string();
+    assert!(!stack.contains("strip_symbols.gos"));
+    bc.attach_debug_info(info).unwrap();
+    assert!(bc.objects.packages[bc.main_pkg]
+        .member_indices()
+        .get("Count")
+        .is_some());
+    let stack = engine::ffi::CallStackDisplay::new(&panic, &bc).to_string();
+    assert!(stack.starts_with("tests/group1/strip_symbols.gos:"));
+
+    let other = engine.compile(
+        &sr,
+        Path::new("./tests/group1/strip_unused.gos"),
+        true,
+        false,
+        false,
+    );
+    let info = other.unwrap().strip_symbols();
+    assert!(bc.attach_debug_info(info).is_err());
+
+    #[cfg(feature = "serde_borsh")]
+    {
+        use borsh::BorshDeserialize;
+        let (data, info) = engine.compile_serialize_stripped(&sr, path).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("describe"));
+        let mut decoded = engine::ffi::Bytecode::try_from_slice(&data).unwrap();
+        let panic = engine.run_bytecode(&decoded).unwrap();
+        let info = engine::ffi::DebugInfo::try_from_slice(&info).unwrap();
+        decoded.attach_debug_info(info).unwrap();
+        let stack = engine::ffi::CallStackDisplay::new(&panic, &decoded).to_string();
+        assert!(stack.starts_with("tests/group1/strip_symbols.gos:"));
+    }
+}
+
+#[test]
+fn test_bytecode_builder() {
+    use engine::ffi::*;
+    let int = |x: isize| GosValue::from(x);
+    let typed = |inst: Inst| inst.with_types(ValueType::Int, ValueType::Void);
+    let mut b = BytecodeBuilder::new();
+    let pkg = b.new_package("main");
+    let mint = b.prim_meta().mint;
+
+    // sum(n int) int adds up 1 to n
+    let sig = b.new_sig(vec![mint], vec![mint]);
+    let mut f = b.new_function(Some(pkg), sig, FuncFlag::Default);
+    let one = b.add_const(int(1));
+    let (head, end) = (f.new_label(), f.new_label());
+    let i = f.add_local(int(0));
+    let (n, sum, cond) = (f.param(0), f.result(0), Operand::Reg(0));
+    f.emit(Inst::new(Opcode::DUPLICATE, i, one, Operand::Void));
+    f.bind(head);
+    f.emit(typed(Inst::new(Opcode::LEQ, cond, i, n)));
+    f.jump_if_not(cond, end);
+    f.emit(typed(Inst::new(Opcode::ADD, sum, sum, i)));
+    f.emit(typed(Inst::new(Opcode::ADD, i, i, one)));
+    f.jump(head);
+    f.bind(end);
+    f.ret();
+    let func = b.finish_function(f).unwrap();
+    let func = b.function_value(func);
+    b.add_member(pkg, "sum", func.clone());
+    let func = b.add_const(func);
+
+    // the entry returns sum(10)
+    let sig = b.new_sig(vec![], vec![mint]);
+    let mut e = b.new_function(None, sig, FuncFlag::Default);
+    let ten = b.add_const(int(10));
+    e.emit(Inst::new(
+        Opcode::DUPLICATE,
+        Operand::Reg(1),
+        ten,
+        Operand::Void,
+    ));
+    e.call(func, 0);
+    e.emit(Inst::new(
+        Opcode::DUPLICATE,
+        e.result(0),
+        Operand::Reg(0),
+        Operand::Void,
+    ));
+    e.ret();
+    let entry = b.finish_function(e).unwrap();
+    let code = b.build(entry, pkg).unwrap();
+    let results = run_entry(
+        &code,
+        &FfiFactory::new(),
+        &Limits::default(),
+        &RunConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(*results[0].as_int(), 55);
+
+    let mut b = BytecodeBuilder::new();
+    let sig = b.prim_meta().default_sig;
+    let mut f = b.new_function(None, sig, FuncFlag::Default);
+    let (key, label) = (f.key(), f.new_label());
+    f.jump(label);
+    assert_eq!(
+        b.finish_function(f),
+        Err(BuildError::UnboundLabel(key, label))
+    );
+    let mut f = b.new_function(None, sig, FuncFlag::Default);
+    let key = f.key();
+    let bad = Operand::Local(0);
+    f.emit(Inst::new(
+        Opcode::DUPLICATE,
+        bad,
+        Operand::Reg(0),
+        Operand::Void,
+    ));
+    assert_eq!(b.finish_function(f), Err(BuildError::NoReturn(key)));
+    let mut f = b.new_function(None, sig, FuncFlag::Default);
+    let key = f.key();
+    f.emit(Inst::new(
+        Opcode::DUPLICATE,
+        bad,
+        Operand::Reg(0),
+        Operand::Void,
+    ));
+    f.ret();
+    assert_eq!(
+        b.finish_function(f),
+        Err(BuildError::BadOperand(key, 0, bad))
+    );
+    assert_eq!(
+        b.build(key, PackageKey::null()).err(),
+        Some(BuildError::NoEntry(key))
+    );
+}
+
+#[test]
+#[cfg(feature = "sign")]
+fn test_signed_bundle() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/strip_unused.gos");
+    let engine = engine::Engine::new();
+    let key = engine::SigningKey::from_bytes(&[7; 32]);
+    let trusted = key.verifying_key();
+    let mut bundle = engine.compile_signed(&sr, path, &key).unwrap();
+
+    let mut packages = vec![];
+    let bc = engine
+        .load_signed(&bundle, &trusted, |m| {
+            packages = m.packages.iter().map(|(name, _)| name.clone()).collect();
+            Ok(())
+        })
+        .unwrap();
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert!(packages.contains(&"strings".to_owned()));
+    assert!(packages.contains(&path.to_string_lossy().into_owned()));
+
+    let refuse = |_: &engine::Manifest| Err("unknown package".to_owned());
+    let accept = |_: &engine::Manifest| Ok(());
+    assert!(matches!(
+        engine.load_signed(&bundle, &trusted, refuse),
+        Err(engine::BundleError::Refused(x)) if x == "unknown package"
+    ));
+    let other = engine::SigningKey::from_bytes(&[8; 32]).verifying_key();
+    assert!(matches!(
+        engine.load_signed(&bundle, &other, accept),
+        Err(engine::BundleError::BadSignature)
+    ));
+    assert!(matches!(
+        engine.load_signed(&bundle[1..], &trusted, accept),
+        Err(engine::BundleError::Malformed)
+    ));
+    // the bytecode is at the end
+    *bundle.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        engine.load_signed(&bundle, &trusted, accept),
+        Err(engine::BundleError::Tampered)
+    ));
+}
+
+#[test]
+fn test_import_cycle() {
+    let result = run("./tests/group2/import_cycle.gos", false);
+    let msg = "import cycle not allowed: package a imports b imports a";
+    assert!(result.unwrap_err().to_string().contains(msg));
+
+    // packages only available as export data
+    let result = run("./tests/group2/import_cycle_export.gos", false);
+    let msg = "import cycle not allowed: package x imports y imports x";
+    assert!(result.unwrap_err().to_string().contains(msg));
+}
+
+#[test]
+fn test_syntax_errors() {
+    let result = run("./tests/group2/syntax_errors.gos", false);
+    let msg = result.unwrap_err().to_string();
+    assert!(msg.contains("syntax_errors.gos:6:1  expected operand, found }"));
+    assert!(msg.contains("syntax_errors.gos:11:1  expected '}', found func"));
+    // the declarations after the errors are still checked
+    assert!(msg.contains("syntax_errors.gos:12:9  cannot convert \"s\""));
+    assert!(!msg.contains("declared but not used"));
+}
+
+#[test]
+fn test_multifile1() {
+    let result = run("./tests/group2/multifile1", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_multifile2() {
+    let result = run("./tests/group2/multifile2", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_multifile3() {
+    let result = run("./tests/group2/multifile3", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_build_tags() {
+    let result = run("./tests/group2/buildtags", false);
+    assert!(result.is_ok());
+    let ctx = engine::BuildContext::new("js", "wasm", vec!["debug".to_owned()]);
+    let result = run_build("./tests/group2/buildtags", ctx);
+    assert!(result.is_ok());
+    let ctx = engine::BuildContext::new("windows", "arm64", vec![]);
+    let result = run_build("./tests/group2/buildtags", ctx);
+    assert!(result.is_err());
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_source_map() {
+    let engine = engine::Engine::new();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/source_map.gos");
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    assert!(bc.source_map.is_none());
+
+    let bc = engine.compile(&sr, path, true, false, false).unwrap();
+    let sm = bc.source_map.as_ref().unwrap();
+    let mut lines = vec![];
+    for (i, func) in bc.objects.functions.iter().enumerate() {
+        let positions = sm.function(engine::ffi::FunctionKey::from(i));
+        assert_eq!(positions.len(), func.code.len());
+        for pc in 0..positions.len() {
+            if let Some((file, pos)) = sm.lookup(i.into(), pc) {
+                if file.ends_with("source_map.gos") {
+                    lines.push(pos.line);
+                }
+            }
+        }
+    }
+    assert!(lines.contains(&4));
+    assert!(lines.contains(&8));
+
+    #[cfg(feature = "serde_borsh")]
+    {
+        use borsh::{BorshDeserialize, BorshSerialize};
+        let data = bc.try_to_vec().unwrap();
+        let decoded = engine::ffi::Bytecode::try_from_slice(&data).unwrap();
+        let decoded_sm = decoded.source_map.as_ref().unwrap();
+        assert_eq!(decoded_sm.files(), sm.files());
+        let entry = sm.function(bc.entry);
+        assert_eq!(decoded_sm.function(decoded.entry), entry);
+
+        // a source map whose positions index missing files resolves nothing
+        type Raw = (Vec<String>, Vec<Vec<Option<engine::ffi::SourcePos>>>);
+        let (_, funcs) = Raw::try_from_slice(&sm.try_to_vec().unwrap()).unwrap();
+        let data = (Vec::<String>::new(), funcs).try_to_vec().unwrap();
+        let tampered = engine::ffi::SourceMap::try_from_slice(&data).unwrap();
+        assert!((0..entry.len()).all(|pc| tampered.lookup(bc.entry, pc).is_none()));
+    }
+}
+
+#[test]
+fn test_unused() {
+    use engine::Severity;
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group2/unused.gos");
+    let mut engine = engine::Engine::new();
+    let el = engine
+        .compile(&sr, path, false, false, false)
+        .err()
+        .unwrap();
+    el.sort();
+    let msgs: Vec<String> = el.borrow().iter().map(|x| x.msg.clone()).collect();
+    assert_eq!(
+        msgs,
+        [
+            "math imported but not used as m",
+            "strings imported but not used",
+            "x declared but not used",
+            "v declared but not used",
+        ]
+    );
+
+    let warnings = engine::ErrorList::new();
+    engine.set_unused(Severity::Warning, Severity::Error, warnings.clone());
+    let el = engine
+        .compile(&sr, path, false, false, false)
+        .err()
+        .unwrap();
+    assert_eq!(el.len(), 2);
+    assert_eq!(warnings.len(), 2);
+
+    let warnings = engine::ErrorList::new();
+    engine.set_unused(Severity::Ignore, Severity::Warning, warnings.clone());
+    assert!(engine.compile(&sr, path, false, false, false).is_ok());
+    assert_eq!(warnings.len(), 2);
+
+    let warnings = engine::ErrorList::new();
+    engine.set_unused(Severity::Ignore, Severity::Ignore, warnings.clone());
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(warnings.len(), 0);
+}
+
+#[test]
+#[cfg(feature = "cache")]
+fn test_compile_cache() {
+    use engine::Severity;
+    let dir = std::env::temp_dir().join(format!("goscript_cache_{}", std::process::id()));
+    let write = |name: &str, src: &str| {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, src).unwrap();
+    };
+    write(
+        "main.gos",
+        "package main\n\nimport \"./lib\"\n\nfunc main() {\n\tx := 1\n\tpanic(lib.Name)\n}\n",
+    );
+    write("lib/lib.gos", "package lib\n\nconst Name = \"v1\"\n");
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), dir.clone());
+    let cache = engine::CompileCache::new(dir.join("cache"));
+    // warnings are only reported when the script is actually compiled
+    let warnings = engine::ErrorList::new();
+    let mut engine = engine::Engine::new();
+    engine.set_unused(Severity::Error, Severity::Warning, warnings.clone());
+    let run = |engine: &engine::Engine| {
+        let bc = engine
+            .compile_cached(&cache, &sr, Path::new("./main.gos"), true)
+            .unwrap();
+        format!("{}", engine.run_bytecode(&bc).unwrap().msg)
+    };
+
+    assert!(run(&engine).contains("v1"));
+    assert_eq!(warnings.len(), 1);
+    assert!(run(&engine).contains("v1"));
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 1);
+
+    // changing a file of an imported package
+    write("lib/lib.gos", "package lib\n\nconst Name = \"v2\"\n");
+    assert!(run(&engine).contains("v2"));
+    assert_eq!(warnings.len(), 2);
+    assert!(run(&engine).contains("v2"));
+    assert_eq!(warnings.len(), 2);
+
+    // adding a file to it
+    write("lib/more.gos", "package lib\n\nconst More = 1\n");
+    assert!(run(&engine).contains("v2"));
+    assert_eq!(warnings.len(), 3);
+
+    // other options are other entries
+    engine.set_unused(Severity::Ignore, Severity::Warning, warnings.clone());
+    assert!(run(&engine).contains("v2"));
+    assert_eq!(warnings.len(), 4);
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 2);
+
+    cache.clear().unwrap();
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(all(feature = "read_fs", feature = "go_std", feature = "async"))]
+fn test_cli() {
+    let run = |arg: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_goscript"))
+            .args(["run", "./tests/group2/cli.gos", "--", arg, "x"])
+            .env("GOSCRIPT_CLI_TEST", "a=b")
+            .output()
+            .unwrap()
+    };
+    let out = run("env");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "env x\ndeferred calls are not run by os.Exit\n"
+    );
+    let out = run("exit");
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "exit x\n");
+    let out = run("panic");
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("panic: boom x\n"));
+}
+
+#[test]
+#[cfg(all(feature = "read_fs", feature = "go_std"))]
+fn test_cli_doc() {
+    let doc = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_goscript"))
+            .arg("doc")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let out = doc(&["../types/tests/data/doc/lib"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("# package lib\n"));
+    // a std package, as JSON
+    let out = doc(&["-json", "strings"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("{\"name\":\"strings\","));
+    let out = doc(&["./tests/group1/nope"]);
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn test_archive() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group2/imports.gos");
+    let engine = engine::Engine::new();
+    let data = engine.archive(&sr, path).unwrap().to_bytes();
+    let archive = engine::ProgramArchive::from_bytes(&data).unwrap();
+    assert_eq!(archive.main, "./tests/group2/imports.gos");
+    assert!(archive.bytecode.source_map.is_some());
+    assert!(archive
+        .export_data("strings")
+        .unwrap()
+        .contains("HasPrefix"));
+    assert!(archive.export_data("./plugin").is_some());
+    assert!(archive.export_data("main").is_none());
+    assert!(engine.run_bytecode(&archive.bytecode).is_none());
+    assert!(engine::ProgramArchive::from_bytes(&data[1..]).is_err());
+
+    let dir = std::env::temp_dir().join(format!("goscript-archive-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("imports.gosa");
+    let cli = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_goscript"))
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let built = cli(&[
+        "build",
+        "-o",
+        out.to_str().unwrap(),
+        "./tests/group2/imports.gos",
+    ]);
+    assert_eq!(built.status.code(), Some(0));
+    assert_eq!(cli(&["run", out.to_str().unwrap()]).status.code(), Some(0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_ffi_error() {
+    let result = run_with_host("./tests/group1/ffi_error.gos");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_host_types() {
+    let err = run_with_host("./tests/group1/host_types.gos");
+    assert!(err.is_ok());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_goroutine_local() {
+    let result = run_with_host("./tests/group1/goroutine_local.gos");
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_channel_deadlock() {
+    // the blocked goroutines are not polled, so the run ends
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/channel_deadlock.gos");
+    let engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let msg = format!("{}", engine.run_bytecode(&bc).unwrap().msg);
+    assert!(
+        msg.contains("all goroutines are asleep - deadlock!"),
+        "{}",
+        msg
+    );
+}
+
+#[test]
+fn test_instances() {
+    let engine = engine::Engine::new();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/instances.gos");
+    let bc = engine.compile(&sr, path, true, false, false).unwrap();
+    for _ in 0..3 {
+        let pdata = engine.run_bytecode(&bc);
+        if let Some(pdata) = &pdata {
+            eprintln!(
+                "{}\n{}",
+                pdata.msg,
+                engine::ffi::CallStackDisplay::new(pdata, &bc)
+            );
+        }
+        assert!(pdata.is_none());
+    }
+}
+
+#[test]
+fn test_allocation_limit() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/allocation_limit.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let run = |engine: &engine::Engine| engine.run_bytecode(&bc).map(|p| format!("{}", p.msg));
+
+    assert_eq!(run(&engine), None);
+
+    engine.set_allocation_limit(Some(8 << 20), None);
+    assert_eq!(run(&engine), None);
+
+    engine.set_allocation_limit(Some(1 << 20), None);
+    let msg = run(&engine).unwrap();
+    assert!(msg.contains("allocation limit exceeded"), "{}", msg);
+
+    let calls = Rc::new(std::cell::Cell::new(0));
+    let calls2 = calls.clone();
+    let raise = Rc::new(move |allocated: usize| {
+        calls2.set(calls2.get() + 1);
+        Some(allocated * 2)
+    });
+    engine.set_allocation_limit(Some(1 << 20), Some(raise));
+    assert_eq!(run(&engine), None);
+    assert!(calls.get() >= 2);
+}
+
+#[test]
+fn test_allocation_limit_make() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/allocation_make.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let run = |engine: &engine::Engine| engine.run_bytecode(&bc).map(|p| format!("{}", p.msg));
+
+    engine.set_allocation_limit(Some(16 << 20), None);
+    assert_eq!(run(&engine), None);
+
+    // the 8MB backing array is checked before it's allocated
+    engine.set_allocation_limit(Some(4 << 20), None);
+    let msg = run(&engine).unwrap();
+    assert!(
+        msg.contains("allocation limit exceeded") && msg.contains("8388608 more requested"),
+        "{}",
+        msg
+    );
+
+    let needed = Rc::new(std::cell::Cell::new(0));
+    let needed2 = needed.clone();
+    let raise = Rc::new(move |bytes: usize| {
+        needed2.set(bytes);
+        Some(bytes)
+    });
+    engine.set_allocation_limit(Some(4 << 20), Some(raise));
+    assert_eq!(run(&engine), None);
+    assert!(needed.get() >= 8 << 20);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_allocation_limit_chan() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/allocation_chan.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let run = |engine: &engine::Engine| engine.run_bytecode(&bc).map(|p| format!("{}", p.msg));
+
+    assert_eq!(run(&engine), None);
+
+    // the buffer of a million values is over 1MB
+    engine.set_allocation_limit(Some(1 << 20), None);
+    let msg = run(&engine).unwrap();
+    assert!(
+        msg.contains("allocation limit exceeded") && msg.contains("more requested"),
+        "{}",
+        msg
+    );
+}
+
+#[test]
+fn test_allocation_limit_string() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/allocation_string.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let run = |engine: &engine::Engine| engine.run_bytecode(&bc).map(|p| format!("{}", p.msg));
+
+    assert_eq!(run(&engine), None);
+
+    // the concatenations build about 10MB of strings, the conversions 2MB
+    engine.set_allocation_limit(Some(1 << 20), None);
+    let msg = run(&engine).unwrap();
+    assert!(msg.contains("allocation limit exceeded"), "{}", msg);
+
+    engine.set_allocation_limit(Some(11 << 20), None);
+    let msg = run(&engine).unwrap();
+    assert!(msg.contains("allocation limit exceeded"), "{}", msg);
+
+    engine.set_allocation_limit(Some(16 << 20), None);
+    assert_eq!(run(&engine), None);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_interrupt() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/interrupt.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, true, false, false).unwrap();
+    let interrupt = engine::ffi::Interrupt::new();
+    engine.set_interrupt(Some(interrupt.clone()));
+
+    let handle = interrupt.clone();
+    let timer = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        handle.interrupt();
+    });
+    let pdata = engine.run_bytecode(&bc).unwrap();
+    timer.join().unwrap();
+    assert!(pdata.interrupted);
+    assert_eq!(format!("{}", pdata.msg), "interrupted");
+    // spin and the func literal
+    assert_eq!(pdata.call_stack.len(), 2);
+
+    // stays interrupted until reset
+    let pdata = engine.run_bytecode(&bc).unwrap();
+    assert!(pdata.interrupted);
+    interrupt.reset();
+    assert!(!interrupt.is_interrupted());
+}
+
+#[test]
+#[cfg(feature = "opcode_stats")]
+fn test_opcode_stats() {
+    use engine::ffi::{Opcode, OpcodeStats};
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/for.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let stats = OpcodeStats::new();
+    engine.set_opcode_stats(Some(stats.clone()));
+    assert!(engine.run_bytecode(&bc).is_none());
+
+    let total = stats.total();
+    let ops = stats.opcodes();
+    assert_eq!(ops.iter().map(|x| x.1).sum::<u64>(), total);
+    assert!(ops.windows(2).all(|x| x[0].1 >= x[1].1));
+    assert!(ops.iter().any(|x| x.0 == Opcode::JUMP));
+    assert!(stats.pairs()[0].1 > 0);
+    assert!(format!("{}", stats).starts_with(&format!("{} instructions executed", total)));
+
+    // the counts of the runs add up
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(stats.total(), total * 2);
+    stats.reset();
+    assert_eq!(stats.total(), 0);
+}
+
+#[test]
+#[cfg(all(feature = "async", feature = "opcode_stats"))]
+fn test_goroutine_pool() {
+    use engine::ffi::{OpcodeStats, PoolCaps};
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/goroutine_pool.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let stats = OpcodeStats::new();
+    engine.set_opcode_stats(Some(stats.clone()));
+    engine.set_goroutine_pool(PoolCaps::default());
+    assert!(engine.run_bytecode(&bc).is_none());
+
+    let c = stats.pool();
+    // the entry fiber and the 152 goroutines
+    assert_eq!(c.stacks_reused + c.stacks_allocated, 153);
+    assert!(c.stacks_allocated <= 51);
+    assert!(c.stacks_freed >= 1);
+    assert_eq!(c.frames_reused + c.frames_allocated, 153);
+    assert!(format!("{}", stats).contains("goroutine stacks: "));
+
+    // without pooling every stack is allocated
+    stats.reset();
+    let caps = PoolCaps {
+        stacks_per_class: 0,
+        frame_lists: 0,
+        ..PoolCaps::default()
+    };
+    engine.set_goroutine_pool(caps);
+    assert!(engine.run_bytecode(&bc).is_none());
+    let c = stats.pool();
+    assert_eq!(c.stacks_allocated, 153);
+    assert_eq!(c.stacks_reused + c.stacks_returned + c.frames_reused, 0);
+}
+
+#[test]
+fn test_meta_view() {
+    use engine::ffi::ValueType;
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/meta_view.gos");
+    let engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let types = bc.types("main");
+    let names: Vec<&str> = types.iter().map(|x| x.name().unwrap()).collect();
+    assert_eq!(names, vec!["Celsius", "Point", "Rect", "Shape"]);
+    let (celsius, point, rect, shape) = (types[0], types[1], types[2], types[3]);
+
+    assert_eq!(celsius.kind(), ValueType::Float64);
+    assert_eq!(celsius.package(), Some("main"));
+    assert!(celsius.methods().is_empty());
+
+    let fields = point.fields();
+    assert_eq!(fields.len(), 3);
+    assert_eq!(fields[1].name, "Y");
+    assert_eq!(fields[1].tag, Some("json:\"x\""));
+    assert_eq!(fields[2].typ.kind(), ValueType::String);
+
+    assert_eq!(rect.kind(), ValueType::Struct);
+    let fields = rect.fields();
+    assert!(fields[0].embedded);
+    assert_eq!(fields[0].typ.name(), Some("Point"));
+    assert_eq!(fields[1].typ.kind(), ValueType::Pointer);
+    assert_eq!(fields[1].typ.elem().unwrap().name(), Some("Point"));
+    assert_eq!(fields[2].typ.key().unwrap().kind(), ValueType::String);
+    assert_eq!(fields[2].typ.to_string(), "map[string][]int");
+    assert_eq!(fields[3].typ.to_string(), "<-chan bool");
+    assert_eq!(fields.len(), 4);
+    let methods = rect.methods();
+    let names: Vec<(&str, bool)> = methods.iter().map(|x| (x.name, x.pointer_recv)).collect();
+    assert_eq!(names, vec![("Area", false), ("Scale", true)]);
+    let scale = methods[1].typ.unwrap();
+    assert_eq!(
+        scale.to_string(),
+        "func(float64, ...int) (main.Shape, error)"
+    );
+    assert!(scale.is_variadic());
+    assert_eq!(scale.params().len(), 2);
+    assert_eq!(scale.results()[0].name(), Some("Shape"));
+
+    assert_eq!(shape.kind(), ValueType::Interface);
+    let methods = shape.methods();
+    assert_eq!(methods[0].name, "Area");
+    assert_eq!(methods[0].typ.unwrap().to_string(), "func() float64");
+}
+
+#[test]
+fn test_globals() {
+    use engine::ffi::Globals;
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/globals.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let globals = Globals::new();
+    engine.set_globals(Some(globals.clone()));
+    let get = |name: &str| globals.get(&bc, "main", name).unwrap();
+
+    assert!(globals.get(&bc, "main", "Count").is_err());
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(
+        get("Greeting").as_string().as_str().to_string(),
+        "hello nobody"
+    );
+    assert_eq!(*get("Count").as_int(), 12);
+
+    // set before the init functions run
+    let prim = &bc.objects.prim_meta;
+    globals
+        .set(&bc, "main", "Name", "gopher".to_owned().into(), prim.mstr)
+        .unwrap();
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(get("Name").as_string().as_str().to_string(), "gopher");
+    assert_eq!(*get("Count").as_int(), 12);
+
+    let point = globals.typ(&bc, "main", "Origin").unwrap();
+    assert_eq!(point.to_string(), "main.Point");
+    globals
+        .set(&bc, "main", "Origin", get("Origin"), point.meta())
+        .unwrap();
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(*get("Count").as_int(), 12);
+
+    let err = globals
+        .set(&bc, "main", "Count", "12".to_owned().into(), prim.mstr)
+        .unwrap_err();
+    assert_eq!(
+        err.as_str(),
+        "cannot use string value as int value in assignment to main.Count"
+    );
+    // the types must be identical, not only of the same kind
+    let other = globals.typ(&bc, "main", "Corner").unwrap();
+    let err = globals
+        .set(&bc, "main", "Origin", get("Origin"), other.meta())
+        .unwrap_err();
+    assert_eq!(
+        err.as_str(),
+        "cannot use main.Other value as main.Point value in assignment to main.Origin"
+    );
+    let err = globals
+        .set(&bc, "main", "Count", 1i64.into(), prim.mint64)
+        .unwrap_err();
+    assert_eq!(
+        err.as_str(),
+        "cannot use int64 value as int value in assignment to main.Count"
+    );
+    let one = || 1isize.into();
+    assert!(globals.set(&bc, "main", "main", one(), prim.mint).is_err());
+    assert!(globals
+        .set(&bc, "nopkg", "Count", one(), prim.mint)
+        .is_err());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_events() {
+    use engine::ffi::Events;
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/events.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let events = Events::new();
+    events.declare("tick");
+    engine.set_events(Some(events.clone()));
+    assert!(events.publish("tock", "0").is_err());
+
+    let publisher = events.clone();
+    let handle = std::thread::spawn(move || {
+        // the events published before the script subscribes are not received
+        while publisher.publish("tick", "0").unwrap() == 0 {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        publisher.publish("tick", "1").unwrap();
+        publisher.publish("tick", "2").unwrap();
+        publisher.close("tick");
+    });
+    assert!(engine.run_bytecode(&bc).is_none());
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_value_fmt() {
+    let result = run_with_host("./tests/group1/value_fmt.gos");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_host_value() {
+    let result = run_with_host("./tests/group1/host_value.gos");
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_callback() {
+    let result = run_with_host("./tests/group1/callback.gos");
+    assert!(result.is_ok());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_callback_blocking() {
+    let result = run_with_host("./tests/group1/callback_blocking.gos");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_g1case1() {
+    let result = run("./tests/group1/case1.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_closure1() {
+    let result = run("./tests/group1/closure1.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_closure2() {
+    let result = run("./tests/group1/closure2.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_closure3() {
+    let result = run("./tests/group1/closure3.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_closure4() {
+    let result = run("./tests/group1/closure4.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_func1() {
+    let result = run("./tests/group1/func1.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_blankid() {
+    let result = run("./tests/group1/blankid.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_declare() {
+    let result = run("./tests/group1/declare.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_iota() {
+    let result = run("./tests/group1/iota.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_number_literals() {
+    let result = run("./tests/group1/number_literals.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_string_literals() {
+    let result = run("./tests/group1/string_literals.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_embed_pointer() {
+    let result = run("./tests/group1/embed_pointer.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_iface_assert() {
+    let result = run("./tests/group1/iface_assert.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_iface_compare() {
+    let result = run("./tests/group1/iface_compare.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_array_value() {
+    let result = run("./tests/group1/array_value.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_struct_value() {
+    let result = run("./tests/group1/struct_value.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-#[cfg(all(feature = "read_zip", feature = "go_std"))]
-fn test_zip_g2case0() {
-    let result = run_zip("./tests/std.zip", "./tests/group2/case0.gos", false);
+fn test_make() {
+    let result = run("./tests/group1/make.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g2case0() {
-    let result = run("./tests/group2/case0.gos", false);
+fn test_new() {
+    let result = run("./tests/group1/new.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g2case1() {
-    let result = run("./tests/group2/case1.gos", true);
+fn test_array_len() {
+    let result = run("./tests/group1/array_len.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g2case2() {
-    let result = run_path("./tests/group2/case2.gos", true, false);
+fn test_array_pointer() {
+    let result = run("./tests/group1/array_pointer.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g2case3() {
-    let result = run("./tests/group2/case3.gos", true);
+fn test_string_concat() {
+    let result = run("./tests/group1/string_concat.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g2nil() {
-    let result = run("./tests/group2/nil.gos", true);
+fn test_typeswitch_nil() {
+    let result = run("./tests/group1/typeswitch_nil.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g2display() {
-    let result = run("./tests/group2/display.gos", true);
+fn test_typeswitch_scope() {
+    let result = run("./tests/group1/typeswitch_scope.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_unicode0() {
-    time_test!();
+fn test_iface_memo() {
+    let result = run("./tests/group1/iface_memo.gos", true);
+    assert!(result.is_ok());
+}
 
-    let result = run("./tests/group2/unicode0.gos", true);
+#[test]
+fn test_func_nil() {
+    let result = run("./tests/group1/func_nil.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_sync_mutex() {
-    let result = run("./tests/group2/sync_mutex.gos", true);
+fn test_variadic_value() {
+    let result = run("./tests/group1/variadic_value.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_sync_rwmutex() {
-    let result = run("./tests/group2/sync_rwmutex.gos", true);
+fn test_variadic_none() {
+    let result = run("./tests/group1/variadic_none.gos", false);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_local() {
-    let result = run("./tests/group2/local.gos", false);
+fn test_recover_scope() {
+    let result = run("./tests/group1/recover_scope.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_g1case1() {
-    let result = run("./tests/group1/case1.gos", true);
+fn test_panic_value() {
+    let result = run("./tests/group1/panic_value.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_closure1() {
-    let result = run("./tests/group1/closure1.gos", true);
+fn test_runtime() {
+    let result = run("./tests/group1/runtime.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_closure2() {
-    let result = run("./tests/group1/closure2.gos", true);
+fn test_math_rand() {
+    let result = run("./tests/group1/math_rand.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_closure3() {
-    let result = run("./tests/group1/closure3.gos", true);
+fn test_math_rand_seed() {
+    use engine::ffi::Globals;
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/math_rand.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let globals = Globals::new();
+    engine.set_globals(Some(globals.clone()));
+    let first = || *globals.get(&bc, "main", "First").unwrap().as_int64();
+
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(first(), 5577006791947779410);
+
+    engine.set_seed(Some(7));
+    let int64 = bc.objects.prim_meta.mint64;
+    globals
+        .set(&bc, "main", "Seed", 7i64.into(), int64)
+        .unwrap();
+    assert!(engine.run_bytecode(&bc).is_none());
+    let seven = first();
+    assert_ne!(seven, 5577006791947779410);
+    // replaying with the same seed draws the same numbers
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(first(), seven);
+}
+
+#[test]
+fn test_crypto() {
+    let result = run("./tests/group1/crypto.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_closure4() {
-    let result = run("./tests/group1/closure4.gos", true);
+fn test_encoding() {
+    let result = run("./tests/group1/encoding.gos", true);
     assert!(result.is_ok());
 }
 
+#[cfg(feature = "http")]
+struct EchoTransport;
+
+#[cfg(feature = "http")]
+impl engine::HttpTransport for EchoTransport {
+    fn round_trip(
+        &self,
+        req: engine::HttpRequest,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<engine::HttpResponse, String>>>>
+    {
+        Box::pin(async move {
+            // pending for a while, like a real request
+            for _ in 0..3 {
+                futures_lite::future::yield_now().await;
+            }
+            if req.url.starts_with("http://down/") {
+                return Err("connection refused".to_owned());
+            }
+            if req.url.ends_with("/missing") {
+                return Ok(engine::HttpResponse {
+                    status: 404,
+                    headers: vec![],
+                    body: b"not found".to_vec(),
+                });
+            }
+            let mut headers = vec![("X-Method".to_owned(), req.method)];
+            for (k, v) in req.headers.into_iter() {
+                headers.push((format!("X-Echo-{}", k), v));
+            }
+            Ok(engine::HttpResponse {
+                status: 200,
+                headers,
+                body: req.body,
+            })
+        })
+    }
+}
+
+/// Answers like EchoTransport, after being woken from another thread, the
+/// way the futures of real network I/O are.
+#[cfg(feature = "http")]
+struct ThreadTransport;
+
+#[cfg(feature = "http")]
+struct ThreadWake {
+    state: std::sync::Arc<std::sync::Mutex<(bool, Option<std::task::Waker>)>>,
+    started: bool,
+}
+
+#[cfg(feature = "http")]
+impl std::future::Future for ThreadWake {
+    type Output = ();
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.0 {
+            return std::task::Poll::Ready(());
+        }
+        state.1 = Some(cx.waker().clone());
+        drop(state);
+        if !self.started {
+            self.started = true;
+            let state = self.state.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(30));
+                let mut state = state.lock().unwrap();
+                state.0 = true;
+                if let Some(w) = state.1.take() {
+                    w.wake();
+                }
+            });
+        }
+        std::task::Poll::Pending
+    }
+}
+
+#[cfg(feature = "http")]
+impl engine::HttpTransport for ThreadTransport {
+    fn round_trip(
+        &self,
+        req: engine::HttpRequest,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<engine::HttpResponse, String>>>>
+    {
+        Box::pin(async move {
+            ThreadWake {
+                state: Default::default(),
+                started: false,
+            }
+            .await;
+            EchoTransport.round_trip(req).await
+        })
+    }
+}
+
 #[test]
-fn test_func1() {
-    let result = run("./tests/group1/func1.gos", true);
+#[cfg(feature = "http")]
+fn test_http() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/http.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    engine.set_http_transport(Some(std::sync::Arc::new(EchoTransport)));
+    assert!(engine.run_bytecode(&bc).is_none());
+    // the transport is the engine's, the requests of another one fail
+    assert!(engine::Engine::new().run_bytecode(&bc).is_some());
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_http_thread_wake() {
+    // nothing can run while the requests are pending, it's not a deadlock
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/http.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    engine.set_http_transport(Some(std::sync::Arc::new(ThreadTransport)));
+    assert!(engine.run_bytecode(&bc).is_none());
+}
+
+#[test]
+#[cfg(not(feature = "http"))]
+fn test_http_disabled() {
+    // without the feature, net/http has no files to build
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/http.gos");
+    let engine = engine::Engine::new();
+    assert!(engine.compile(&sr, path, false, false, false).is_err());
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn test_yaml() {
+    let result = run("./tests/group1/yaml.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_blankid() {
-    let result = run("./tests/group1/blankid.gos", true);
+#[cfg(feature = "toml")]
+fn test_toml() {
+    let result = run("./tests/group1/toml.gos", true);
     assert!(result.is_ok());
 }
 
 #[test]
-fn test_declare() {
-    let result = run("./tests/group1/declare.gos", true);
+fn test_buildinfo() {
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/buildinfo.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    engine.set_app_info(engine::ffi::AppInfo {
+        version: "2.1.0".to_owned(),
+        settings: vec![
+            ("beta".to_owned(), "off".to_owned()),
+            ("region".to_owned(), "eu".to_owned()),
+            ("beta".to_owned(), "on".to_owned()),
+        ],
+    });
+    assert!(engine.run_bytecode(&bc).is_none());
+}
+
+struct CollectSink(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl engine::LogSink for CollectSink {
+    fn log(&self, record: engine::LogRecord) {
+        let mut s = format!("{} {}", record.level.0, record.message);
+        for (k, v) in record.attrs.iter() {
+            s.push_str(&format!(" {}={}", k, v));
+        }
+        self.0.lock().unwrap().push(s);
+    }
+}
+
+#[test]
+fn test_log() {
+    let records = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/log.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    engine.set_log_sink(Some(std::sync::Arc::new(CollectSink(records.clone()))));
+    assert!(engine.run_bytecode(&bc).is_none());
+    assert_eq!(
+        *records.lock().unwrap(),
+        vec![
+            "0 hello 42",
+            "0 app: 3 items",
+            "8 bad 1",
+            "0 started port=8080 debug=true",
+            "-4 kept",
+            "4 slow service=api ms=1500 !BADKEY=orphan",
+            "8 failed service=api !BADKEY=3.5",
+            "6 custom",
+        ]
+    );
+
+    // the sink belongs to the engine, the records of another one go to stderr
+    let other = engine::Engine::new();
+    assert!(other.run_bytecode(&bc).is_none());
+    assert_eq!(records.lock().unwrap().len(), 8);
+}
+
+#[test]
+fn test_zero_values() {
+    let result = run("./tests/group1/zero_values.gos", true);
     assert!(result.is_ok());
 }
 
@@ -324,6 +1891,12 @@ fn test_pkg() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_pkg_alias() {
+    let result = run("./tests/group1/pkg_alias.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_type1() {
     let result = run("./tests/group1/type1.gos", true);
@@ -342,6 +1915,42 @@ fn test_operations() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_paren() {
+    let result = run("./tests/group1/paren.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_tuple_assign() {
+    let result = run("./tests/group1/tuple_assign.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_compound_assign() {
+    let result = run("./tests/group1/compound_assign.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_short_circuit() {
+    let result = run("./tests/group1/short_circuit.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_named_iface() {
+    let result = run("./tests/group1/named_iface.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_float_special() {
+    let result = run("./tests/group1/float_special.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_if() {
     let result = run("./tests/group1/if.gos", true);
@@ -372,6 +1981,12 @@ fn test_initorder() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_stringer() {
+    let result = run("./tests/group1/stringer.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_switch() {
     let result = run("./tests/group1/switch.gos", true);
@@ -420,6 +2035,12 @@ fn test_channel() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_channel_ring() {
+    let result = run("./tests/group1/channel_ring.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_defer() {
     let result = run("./tests/group1/defer.gos", true);
@@ -438,6 +2059,18 @@ fn test_recover() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_index_panic() {
+    let result = run("./tests/group1/index_panic.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_nil_panic() {
+    let result = run("./tests/group1/nil_panic.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_select() {
     let result = run("./tests/group1/select.gos", true);
@@ -456,6 +2089,30 @@ fn test_complex() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_minmax() {
+    let result = run("./tests/group1/minmax.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_clear() {
+    let result = run("./tests/group1/clear.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_range_int() {
+    let result = run("./tests/group1/range_int.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_range_func() {
+    let result = run("./tests/group1/range_func.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_linked() {
     let result = run("./tests/demo/linked.gos", true);
@@ -610,3 +2267,264 @@ fn test_std_temp() {
     let result = run("./tests/std/temp.gos", false);
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_eval_expr() {
+    let engine = engine::Engine::new();
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/eval.gos");
+    let eval = |expr: &str| engine.eval_expr(&sr, path, expr);
+
+    let val = eval("next() * 2").unwrap();
+    assert_eq!(*val.as_int(), 84);
+    // every evaluation gets a new instance of the package
+    assert_eq!(*eval("next()").unwrap().as_int(), 42);
+    let val = eval("point{counter, 2}.String()").unwrap();
+    assert_eq!(&*val.as_string().as_str(), "(41, 2)");
+    assert_eq!(
+        eval("origin").unwrap().as_struct().0.borrow_fields().len(),
+        2
+    );
+
+    assert!(matches!(
+        eval("counter + \"1\""),
+        Err(engine::EvalError::Compile(_))
+    ));
+    // imports are per file
+    assert!(matches!(
+        eval("fmt.Sprint(counter)"),
+        Err(engine::EvalError::Compile(_))
+    ));
+    match eval("[]int{}[counter]") {
+        Err(engine::EvalError::Panic(msg, _)) => {
+            assert_eq!(msg, "runtime error: index out of range [41] with length 0")
+        }
+        _ => panic!("should panic"),
+    }
+}
+
+#[test]
+fn test_eval_expr_globals() {
+    use engine::ffi::Globals;
+    let mut engine = engine::Engine::new();
+    engine.set_globals(Some(Globals::new()));
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/eval.gos");
+    let eval = |expr: &str| *engine.eval_expr(&sr, path, expr).unwrap().as_int();
+
+    // the first evaluation initializes the package, the next ones see the
+    // vars as it left them, without running init again
+    assert_eq!(eval("next()"), 42);
+    assert_eq!(eval("counter"), 42);
+    assert_eq!(eval("next() + origin.x"), 43);
+
+    // the vars of the program, as its last run left them
+    let code = engine.compile(&sr, path, false, false, false).unwrap();
+    let pdata = engine.run_bytecode(&code).unwrap();
+    assert_eq!(pdata.msg.to_string(), "main is not called when evaluating");
+    assert_eq!(eval("counter"), 41);
+}
+
+#[test]
+fn test_value_path() {
+    use engine::ffi::Globals;
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let path = Path::new("./tests/group1/value_path.gos");
+    let mut engine = engine::Engine::new();
+    let bc = engine.compile(&sr, path, false, false, false).unwrap();
+    let globals = Globals::new();
+    engine.set_globals(Some(globals.clone()));
+    assert!(engine.run_bytecode(&bc).is_none());
+
+    globals
+        .with_ref(&bc, "main", "Data", |data| {
+            let int = |path: &str| *data.field_by_path(path).unwrap().value().as_int();
+            let string = |path: &str| {
+                let r = data.field_by_path(path).unwrap();
+                let s = r.value().as_string().as_str().to_string();
+                s
+            };
+            assert_eq!(int("ID"), 7);
+            assert_eq!(int("Base.ID"), 7);
+            assert_eq!(string("Title"), "demo");
+            assert_eq!(int("Items[0].C"), 1);
+            assert_eq!(string("Items[2].Tags[1]"), "b");
+            assert_eq!(int(r#"ByName["x y"].C"#), 10);
+            assert_eq!(int("Counts[-1]"), 5);
+            assert_eq!(int("Any.C"), 42);
+            assert_eq!(string("Names[1]"), "bob");
+            assert_eq!(int("Self.Self.Items[2].C"), 3);
+            assert_eq!(
+                data.field_by_path("Items[2]").unwrap().typ().to_string(),
+                "*main.Inner"
+            );
+
+            // Items[1] points to a package var
+            let prim = &bc.objects.prim_meta;
+            data.set_by_path("Items[1].C", 20isize.into(), prim.mint)
+                .unwrap();
+            data.set_by_path("Items[2].Tags[0]", "z".to_owned().into(), prim.mstr)
+                .unwrap();
+            let entry = data.field_by_path(r#"ByName["x y"]"#).unwrap();
+            let meta = entry.typ().meta();
+            data.set_by_path("ByName[new]", entry.into_value(), meta)
+                .unwrap();
+            data.set_by_path("ID", 8isize.into(), prim.mint).unwrap();
+            assert_eq!(string("Items[2].Tags[0]"), "z");
+            assert_eq!(int("ByName[new].C"), 10);
+            assert_eq!(int("Self.Base.ID"), 8);
+
+            let err = |path: &str| data.field_by_path(path).err().unwrap();
+            assert_eq!(
+                err("Items[3]").as_str(),
+                "runtime error: index out of range [3] with length 3"
+            );
+            assert_eq!(err("Nope").as_str(), "main.App has no field Nope");
+            assert_eq!(err("Counts[x]").as_str(), "invalid int key x");
+            assert_eq!(err("ByName[y].C").as_str(), "key y not in map");
+            assert_eq!(err("Title[0]").as_str(), "cannot index string");
+            assert_eq!(err("Items.[0]").as_str(), "invalid path Items.[0]");
+            let err = data
+                .set_by_path("Title", 1isize.into(), prim.mint)
+                .unwrap_err();
+            assert_eq!(
+                err.as_str(),
+                "cannot use int value as string value in assignment to Title"
+            );
+            // the types must be identical, not only of the same kind
+            let item = data.field_by_path("Items[0]").unwrap();
+            let err = data
+                .set_by_path("Base", item.value().clone(), item.typ().meta())
+                .unwrap_err();
+            assert_eq!(
+                err.as_str(),
+                "cannot use *main.Inner value as *main.Base value in assignment to Base"
+            );
+        })
+        .unwrap();
+    globals
+        .with_ref(&bc, "main", "Settings", |s| {
+            assert_eq!(*s.field_by_path("C").unwrap().value().as_int(), 20);
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_nil_kinds() {
+    let result = run("./tests/group1/nil_kinds.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_shift_untyped() {
+    let result = run("./tests/group1/shift_untyped.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_method_expr() {
+    let result = run("./tests/group1/method_expr.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_array_lit_zero() {
+    let result = run("./tests/group1/array_lit_zero.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_nil_slice_string() {
+    let result = run("./tests/group1/nil_slice_string.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_slice_expr() {
+    let result = run("./tests/group1/slice_expr.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_slice_iface() {
+    let result = run("./tests/group1/slice_iface.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_map_shared() {
+    let result = run("./tests/group1/map_shared.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_nil_conversion() {
+    let result = run("./tests/group1/nil_conversion.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_return_call() {
+    let result = run("./tests/group1/return_call.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_range_break() {
+    let result = run("./tests/group1/range_break.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_channel_close() {
+    let result = run("./tests/group1/channel_close.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_map_unhashable() {
+    let result = run("./tests/group1/map_unhashable.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_map_delete() {
+    let result = run("./tests/group1/map_delete.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_init_deps() {
+    let result = run("./tests/group1/init_deps.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_init_vars() {
+    let result = run("./tests/group1/init_vars.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_assign_call() {
+    let result = run("./tests/group1/assign_call.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_iface_rebind() {
+    let result = run("./tests/group1/iface_rebind.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_conversion_named() {
+    let result = run("./tests/group1/conversion_named.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_fmt_struct() {
+    let result = run("./tests/group1/fmt_struct.gos", true);
+    assert!(result.is_ok());
+}
@@ -486,6 +486,7 @@ impl Node for Decl {
 
 #[derive(Debug)]
 pub struct File {
+    pub doc: Option<Rc<CommentGroup>>,
     pub package: position::Pos,
     pub name: IdentKey,
     pub decls: Vec<Decl>,
@@ -545,8 +546,10 @@ impl IdentEntity {
     }
 }
 
+/// Reports whether a name starts with an upper case letter, which Go's spec
+/// defines as one of Unicode category Lu.
 pub fn is_exported(s: &str) -> bool {
-    s.chars().next().unwrap().is_uppercase()
+    s.chars().next().is_some_and(char::is_uppercase)
 }
 
 // An Ident node represents an identifier.
@@ -834,6 +837,7 @@ pub struct ImportSpec {
 // (ConstSpec or VarSpec production).
 #[derive(Debug)]
 pub struct ValueSpec {
+    pub doc: Option<Rc<CommentGroup>>,
     pub names: Vec<IdentKey>,
     pub typ: Option<Expr>,
     pub values: Vec<Expr>,
@@ -842,6 +846,7 @@ pub struct ValueSpec {
 // A TypeSpec node represents a type declaration (TypeSpec production).
 #[derive(Debug)]
 pub struct TypeSpec {
+    pub doc: Option<Rc<CommentGroup>>,
     pub name: IdentKey,
     pub assign: position::Pos,
     pub typ: Expr,
@@ -865,6 +870,7 @@ pub struct BadDecl {
 //	Token::VAR     ValueSpec
 #[derive(Debug)]
 pub struct GenDecl {
+    pub doc: Option<Rc<CommentGroup>>,
     pub token_pos: position::Pos,
     pub token: token::Token,
     pub l_paran: Option<position::Pos>,
@@ -875,6 +881,7 @@ pub struct GenDecl {
 // A FuncDecl node represents a function declaration.
 #[derive(Debug)]
 pub struct FuncDecl {
+    pub doc: Option<Rc<CommentGroup>>,
     pub recv: Option<FieldList>,
     pub name: IdentKey,
     pub typ: FuncTypeKey,
@@ -1098,6 +1105,7 @@ pub struct RangeStmt {
 
 #[derive(Debug)]
 pub struct Field {
+    pub doc: Option<Rc<CommentGroup>>,
     pub names: Vec<IdentKey>,
     pub typ: Expr,
     pub tag: Option<Expr>,
@@ -1160,3 +1168,68 @@ impl FieldList {
         }
     }
 }
+
+// A Comment node represents a single //-style or /*-style comment,
+// the text of a //-style comment doesn't include the newline.
+#[derive(Debug)]
+pub struct Comment {
+    pub slash: position::Pos,
+    pub text: String,
+}
+
+// A CommentGroup represents a sequence of comments with no other tokens
+// and no empty lines between, the ones parsed are the doc comments.
+#[derive(Debug)]
+pub struct CommentGroup {
+    pub list: Vec<Comment>,
+}
+
+impl CommentGroup {
+    /// Returns the text of the comment without the comment markers, the
+    /// leading and trailing empty lines and the directives like "//go:",
+    /// runs of empty lines are reduced to one.
+    pub fn text(&self) -> String {
+        let mut lines: Vec<&str> = vec![];
+        for c in self.list.iter() {
+            let text = if let Some(t) = c.text.strip_prefix("//") {
+                match t.strip_prefix(' ') {
+                    Some(t) => t,
+                    None if CommentGroup::is_directive(t) => continue,
+                    None => t,
+                }
+            } else {
+                &c.text[2..c.text.len() - 2]
+            };
+            lines.extend(text.split('\n').map(|x| x.trim_end()));
+        }
+        let mut result = String::new();
+        let mut blank = false;
+        for line in lines {
+            if line.is_empty() {
+                blank = !result.is_empty();
+                continue;
+            }
+            if blank {
+                result.push('\n');
+                blank = false;
+            }
+            result.push_str(line);
+            result.push('\n');
+        }
+        result
+    }
+
+    // like "//go:generate" or "//line", they are for the tools
+    fn is_directive(text: &str) -> bool {
+        if text.starts_with("line ") {
+            return true;
+        }
+        match text.find(':') {
+            Some(colon) if colon > 0 && colon + 1 < text.len() => text.as_bytes()[..colon + 2]
+                .iter()
+                .enumerate()
+                .all(|(i, b)| i == colon || b.is_ascii_lowercase() || b.is_ascii_digit()),
+            _ => false,
+        }
+    }
+}
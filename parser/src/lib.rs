@@ -18,6 +18,9 @@
 //! }
 //! ```
 //!
+//! For a very large source, like a generated one, `parse_reader` reads it
+//! from an `io::Read` in chunks, as the parser goes, instead.
+//!
 //! # Feature
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 //!
@@ -29,6 +32,7 @@ mod parser;
 mod position;
 mod scanner;
 mod token;
+mod unicode;
 
 pub mod ast;
 pub mod scope;
@@ -54,3 +58,22 @@ pub fn parse_file<'a>(
     let file = p.parse_file();
     (p, file)
 }
+
+/// Parses the source read from `src` in chunks, as the parser goes, so that
+/// only a small part of it is held in memory at a time, unlike `parse_file`
+/// which takes it in whole. The AST it produces is held in whole all the same.
+///
+/// Invalid UTF-8 in the source and read failures are reported as errors.
+pub fn parse_reader<'a, R: std::io::Read + 'a>(
+    o: &'a mut AstObjects,
+    fs: &'a mut FileSet,
+    el: &'a ErrorList,
+    name: &str,
+    src: R,
+    trace: bool,
+) -> (parser::Parser<'a>, Option<ast::File>) {
+    let f = fs.add_file(name.to_string(), None, 0);
+    let mut p = parser::Parser::from_reader(o, f, el, Box::new(src), trace);
+    let file = p.parse_file();
+    (p, file)
+}
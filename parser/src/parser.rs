@@ -16,6 +16,7 @@ use super::position;
 use super::scanner;
 use super::scope::*;
 use super::token::{Token, LOWEST_PREC};
+use std::io;
 use std::rc::Rc;
 
 macro_rules! new_scope {
@@ -36,7 +37,11 @@ macro_rules! new_ident {
 
 macro_rules! new_field {
     ($owner:ident, $names:expr, $typ:expr, $tag:expr) => {
+        new_field!($owner, $names, $typ, $tag, None)
+    };
+    ($owner:ident, $names:expr, $typ:expr, $tag:expr, $doc:expr) => {
         $owner.objects.fields.insert(Field {
+            doc: $doc,
             names: $names,
             typ: $typ,
             tag: $tag,
@@ -44,6 +49,10 @@ macro_rules! new_field {
     };
 }
 
+// How deep the parse functions can nest, the recursion would overflow the
+// stack past it. A pair of parentheses takes about five levels, a block three.
+const MAX_NEST_LEV: isize = 1000;
+
 // Parsing modes for parseSimpleStmt.
 #[derive(PartialEq, Eq)]
 enum ParseSimpleMode {
@@ -58,13 +67,22 @@ pub struct Parser<'a> {
     errors: &'a ErrorList,
 
     trace: bool,
+    // how deep the parse functions nest, see MAX_NEST_LEV
     indent: isize,
+    // set when the nesting is too deep, the rest of the file is skipped
+    bailed_out: bool,
 
     pos: position::Pos,
     token: Token,
+    // whether the doc comments are kept, see `with_docs`
+    docs: bool,
+    // the comment group that ends on the line before the current token
+    lead_comment: Option<Rc<CommentGroup>>,
 
     sync_pos: position::Pos,
     sync_count: isize,
+    // the count of errors when the function body being parsed began
+    body_errors: usize,
 
     expr_level: isize,
     in_rhs: bool,
@@ -87,16 +105,58 @@ impl<'a> Parser<'a> {
         trace: bool,
     ) -> Parser<'a> {
         let s = scanner::Scanner::new(file, src, el);
+        Parser::with_scanner(objs, s, el, trace, false)
+    }
+
+    /// Same as `new`, but the comment groups right above the declarations,
+    /// the fields and the package clause are kept in their `doc`, which
+    /// costs the line of every comment.
+    pub fn with_docs(
+        objs: &'a mut AstObjects,
+        file: &'a mut position::File,
+        el: &'a ErrorList,
+        src: &'a str,
+        trace: bool,
+    ) -> Parser<'a> {
+        let s = scanner::Scanner::new(file, src, el);
+        Parser::with_scanner(objs, s, el, trace, true)
+    }
+
+    /// Creates a parser reading the source from `src` as it goes, instead of
+    /// taking it in whole. `file` grows with the source read, so its size can
+    /// be anything to begin with.
+    pub fn from_reader(
+        objs: &'a mut AstObjects,
+        file: &'a mut position::File,
+        el: &'a ErrorList,
+        src: Box<dyn io::Read + 'a>,
+        trace: bool,
+    ) -> Parser<'a> {
+        let s = scanner::Scanner::from_reader(file, src, el);
+        Parser::with_scanner(objs, s, el, trace, false)
+    }
+
+    fn with_scanner(
+        objs: &'a mut AstObjects,
+        s: scanner::Scanner<'a>,
+        el: &'a ErrorList,
+        trace: bool,
+        docs: bool,
+    ) -> Parser<'a> {
         let mut p = Parser {
             objects: objs,
             scanner: s,
             errors: el,
             trace: trace,
             indent: 0,
+            bailed_out: false,
             pos: 0,
             token: Token::NONE,
+            docs,
+            lead_comment: None,
             sync_pos: 0,
             sync_count: 0,
+            body_errors: 0,
             expr_level: 0,
             in_rhs: false,
             pkg_scope: None,
@@ -332,6 +392,19 @@ impl<'a> Parser<'a> {
         trace_str.push('(');
         self.print_trace(self.pos, &trace_str);
         self.indent += 1;
+        if self.indent > MAX_NEST_LEV && !self.bailed_out {
+            self.bail_out();
+        }
+    }
+
+    // Gives up on a file nested too deep like Go's parser: the rest is
+    // skipped and no other error is reported.
+    fn bail_out(&mut self) {
+        self.error_str(self.pos, "exceeded max nesting depth");
+        self.bailed_out = true;
+        while self.token != Token::EOF {
+            self.next();
+        }
     }
 
     fn trace_end(&mut self) {
@@ -339,31 +412,110 @@ impl<'a> Parser<'a> {
         self.print_trace(self.pos, ")");
     }
 
+    fn next0(&mut self) {
+        let (token, pos) = self.scanner.scan();
+        match token {
+            Token::COMMENT(_) => self.print_trace(pos, &format!("{}", token)),
+            _ => self.print_trace(pos, &format!("next: {}", token)),
+        }
+        self.token = token;
+        self.pos = pos;
+    }
+
+    fn line(&self, pos: position::Pos) -> usize {
+        self.file().position(pos).line
+    }
+
+    // A 'func' in the first column is taken as the start of the next
+    // function declaration, even inside a body, which is then missing
+    // its closing '}'. Only a body that is wrong already is cut short: a
+    // syntax error has been reported in it, or the 'func' is followed by
+    // a name, which a function literal doesn't have.
+    fn at_func_decl(&mut self) -> bool {
+        self.token == Token::FUNC
+            && self.file().position_for(self.pos, false).column == 1
+            && (self.errors.len() > self.body_errors || self.scanner.name_follows())
+    }
+
+    // Consumes a comment and returns the line it ends on.
+    fn consume_comment(&mut self, list: &mut Vec<Comment>) -> usize {
+        let text = match &self.token {
+            Token::COMMENT(lit) => lit.as_str().trim_end_matches('\n').to_owned(),
+            _ => unreachable!(),
+        };
+        let line = self.line(self.pos) + text.matches('\n').count();
+        list.push(Comment {
+            slash: self.pos,
+            text,
+        });
+        self.next0();
+        line
+    }
+
+    // Consumes a group of adjacent comments, n is the number of lines
+    // allowed between two comments of the group. Returns the group and the
+    // line it ends on.
+    fn consume_comment_group(&mut self, n: usize) -> (Rc<CommentGroup>, usize) {
+        let mut list = vec![];
+        let mut end = self.line(self.pos);
+        while let Token::COMMENT(_) = self.token {
+            if self.line(self.pos) > end + n {
+                break;
+            }
+            end = self.consume_comment(&mut list);
+        }
+        (Rc::new(CommentGroup { list }), end)
+    }
+
+    // Get next token and skip comments, keeping the comment group right
+    // above the token as its lead comment, e.g. the doc of a declaration,
+    // if the docs are kept
     fn next(&mut self) {
-        // Get next token and skip comments
-        loop {
-            let (token, pos) = self.scanner.scan();
-            match token {
-                Token::COMMENT(_) => {
-                    // Skip comment
-                    self.print_trace(pos, &format!("{}", token));
+        if !self.docs {
+            loop {
+                self.next0();
+                if !matches!(self.token, Token::COMMENT(_)) {
+                    return;
                 }
-                _ => {
-                    self.print_trace(pos, &format!("next: {}", token));
-                    self.token = token;
-                    self.pos = pos;
-                    break;
+            }
+        }
+        self.lead_comment = None;
+        let first = self.token == Token::NONE;
+        let prev_pos = self.pos;
+        self.next0();
+        if let Token::COMMENT(_) = self.token {
+            if !first && self.line(self.pos) == self.line(prev_pos) {
+                // a comment on the line of the previous token is not a lead comment
+                self.consume_comment_group(0);
+            }
+            let mut group = None;
+            while let Token::COMMENT(_) = self.token {
+                let (comment, end) = self.consume_comment_group(1);
+                group = Some((comment, end));
+            }
+            if let Some((comment, end)) = group {
+                if self.token != Token::EOF && end + 1 == self.line(self.pos) {
+                    self.lead_comment = Some(comment);
                 }
             }
         }
     }
 
     fn error_str(&self, pos: position::Pos, s: &str) {
-        FilePosErrors::new(self.file(), self.errors).parser_add_str(pos, s);
+        if !self.bailed_out {
+            FilePosErrors::new(self.file(), self.errors).parser_add_str(pos, s);
+        }
     }
 
     fn error(&self, pos: position::Pos, msg: String) {
-        FilePosErrors::new(self.file(), self.errors).parser_add(pos, msg);
+        if !self.bailed_out {
+            FilePosErrors::new(self.file(), self.errors).parser_add(pos, msg);
+        }
+    }
+
+    fn reported_at(&self, pos: position::Pos) -> bool {
+        let offset = self.file().position(pos).offset;
+        matches!(self.errors.borrow().last(), Some(e) if e.by_parser && e.pos.offset == offset)
     }
 
     fn error_expected(&self, pos: position::Pos, msg: &str) {
@@ -389,6 +541,10 @@ impl<'a> Parser<'a> {
         let pos = self.pos;
         if self.token != *token {
             self.error_expected(pos, &format!("'{}'", token));
+            // leave the '}' to the block it closes
+            if self.at_boundary(false) && self.sync() {
+                return pos;
+            }
         }
         self.next();
         pos
@@ -407,10 +563,26 @@ impl<'a> Parser<'a> {
         self.expect(token)
     }
 
+    // Same as expect('}') but leaves a declaration the block stopped at
+    // to be parsed, the error is reported once for all unclosed blocks.
+    fn expect_rbrace(&mut self) -> position::Pos {
+        let pos = self.pos;
+        if !self.at_func_decl() {
+            self.expect(&Token::RBRACE)
+        } else {
+            if !self.reported_at(pos) {
+                self.error_expected(pos, "'}'");
+            }
+            pos
+        }
+    }
+
     fn expect_semi(&mut self) {
         // semicolon is optional before a closing ')' or '}'
         match self.token {
             Token::RPAREN | Token::RBRACE => {}
+            // the unclosed body before a declaration has been reported
+            Token::FUNC if self.reported_at(self.pos) => {}
             Token::SEMICOLON(_) => {
                 self.next();
             }
@@ -446,35 +618,75 @@ impl<'a> Parser<'a> {
 
     // advance consumes tokens until the current token p.tok
     // is in the 'to' set, or token.EOF. For error recovery.
+    //
+    // It also stops, without consuming it, at a token outside of any
+    // bracket it skipped that ends the enclosing construct: the '}' of the
+    // block being parsed inside a function body, or a 'func' starting a line
+    // at the top level, so that an error doesn't swallow the statements or
+    // declarations after it.
     fn advance(&mut self, to: fn(&Token) -> bool) {
+        let mut depth = 0;
+        let mut line_start = true;
         while self.token != Token::EOF {
+            if depth == 0 && self.at_boundary(line_start) && self.sync() {
+                break;
+            }
+            match self.token {
+                Token::LPAREN | Token::LBRACK | Token::LBRACE => depth += 1,
+                Token::RPAREN | Token::RBRACK | Token::RBRACE if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            line_start = matches!(self.token, Token::SEMICOLON(_));
             self.next();
-            if to(&self.token) {
-                // Return only if parser made some progress since last
-                // sync or if it has not reached 10 advance calls without
-                // progress. Otherwise consume at least one token to
-                // avoid an endless parser loop (it is possible that
-                // both parseOperand and parseStmt call advance and
-                // correctly do not advance, thus the need for the
-                // invocation limit p.syncCnt).
-                if self.pos == self.sync_pos && self.sync_count < 10 {
-                    self.sync_count += 1;
-                    break;
-                }
-                if self.pos > self.sync_pos {
-                    self.sync_pos = self.pos;
-                    self.sync_count = 0;
-                    break;
-                }
-                // Reaching here indicates a parser bug, likely an
-                // incorrect token list in this function, but it only
-                // leads to skipping of possibly correct code if a
-                // previous error is present, and thus is preferred
-                // over a non-terminating parse.
+            if to(&self.token) && self.sync() {
+                break;
             }
         }
     }
 
+    // skip_bad consumes the token a type was expected at, unless it ends
+    // the expression, in which case the enclosing list or block goes on
+    // from it.
+    fn skip_bad(&mut self) {
+        if !(self.token.is_expr_end() && self.sync()) {
+            self.next();
+        }
+    }
+
+    fn at_boundary(&self, line_start: bool) -> bool {
+        match self.token {
+            Token::RBRACE => self.label_scope.is_some(),
+            Token::FUNC => self.label_scope.is_none() && line_start,
+            _ => false,
+        }
+    }
+
+    // sync reports whether advance may stop at the current token.
+    fn sync(&mut self) -> bool {
+        // Return only if parser made some progress since last
+        // sync or if it has not reached 10 advance calls without
+        // progress. Otherwise consume at least one token to
+        // avoid an endless parser loop (it is possible that
+        // both parseOperand and parseStmt call advance and
+        // correctly do not advance, thus the need for the
+        // invocation limit p.syncCnt).
+        if self.pos == self.sync_pos && self.sync_count < 10 {
+            self.sync_count += 1;
+            return true;
+        }
+        if self.pos > self.sync_pos {
+            self.sync_pos = self.pos;
+            self.sync_count = 0;
+            return true;
+        }
+        // Reaching here indicates a parser bug, likely an
+        // incorrect token list in this function, but it only
+        // leads to skipping of possibly correct code if a
+        // previous error is present, and thus is preferred
+        // over a non-terminating parse.
+        false
+    }
+
     // safe_pos returns a valid file position for a given position: If pos
     // is valid to begin with, safe_pos returns pos. If pos is out-of-range,
     // safe_pos returns the EOF position.
@@ -588,7 +800,7 @@ impl<'a> Parser<'a> {
         let ret = if typ.is_none() {
             let pos = self.pos;
             self.error_expected(pos, "type");
-            self.next();
+            self.skip_bad();
             Expr::new_bad(pos, self.pos)
         } else {
             typ.unwrap()
@@ -654,7 +866,7 @@ impl<'a> Parser<'a> {
                     Expr::Ident(ident) => *ident,
                     _ => {
                         let pos = x.pos(&self.objects);
-                        if let Expr::Bad(_) = x {
+                        if !x.is_bad() {
                             // only report error if it's a new one
                             self.error_expected(pos, "identifier")
                         }
@@ -668,6 +880,7 @@ impl<'a> Parser<'a> {
     fn parse_field_decl(&mut self, scope: ScopeKey) -> FieldKey {
         self.trace_begin("FieldDecl");
 
+        let doc = self.lead_comment.take();
         // 1st FieldDecl
         // A type name used as an anonymous field looks like a field identifier.
         let mut list = vec![];
@@ -716,7 +929,7 @@ impl<'a> Parser<'a> {
         self.expect_semi();
 
         let to_resolve = typ.clone_ident();
-        let field = new_field!(self, idents, typ, tag);
+        let field = new_field!(self, idents, typ, tag, doc);
         self.declare(
             DeclObj::Field(field),
             EntityData::NoData,
@@ -796,7 +1009,7 @@ impl<'a> Parser<'a> {
             None => {
                 let pos = self.pos;
                 self.error_expected(pos, "type");
-                self.next();
+                self.skip_bad();
                 Expr::new_bad(pos, self.pos)
             }
         }
@@ -931,6 +1144,7 @@ impl<'a> Parser<'a> {
     fn parse_method_spec(&mut self, scope: ScopeKey) -> FieldKey {
         self.trace_begin("MethodSpec");
 
+        let doc = self.lead_comment.take();
         let mut idents = vec![];
         let mut typ = self.parse_type_name();
         let ident = typ.try_as_ident();
@@ -944,7 +1158,7 @@ impl<'a> Parser<'a> {
             self.resolve(&typ);
         }
         self.expect_semi();
-        let field = new_field!(self, idents, typ, None);
+        let field = new_field!(self, idents, typ, None, doc);
         self.declare(
             DeclObj::Field(field),
             EntityData::NoData,
@@ -1086,6 +1300,9 @@ impl<'a> Parser<'a> {
                 }
                 _ => {}
             };
+            if self.at_func_decl() {
+                break;
+            }
             list.push(self.parse_stmt());
         }
 
@@ -1097,12 +1314,14 @@ impl<'a> Parser<'a> {
         self.trace_begin("Body");
 
         let lbrace = self.expect(&Token::LBRACE);
+        let outer_errors = std::mem::replace(&mut self.body_errors, self.errors.len());
         self.top_scope = Some(scope); // open function scope
         self.open_label_scope();
         let list = self.parse_stmt_list();
         self.close_label_scope();
         self.close_scope();
-        let rbrace = self.expect(&Token::RBRACE);
+        let rbrace = self.expect_rbrace();
+        self.body_errors = outer_errors;
 
         self.trace_end();
         BlockStmt::new(lbrace, list, rbrace)
@@ -1115,7 +1334,7 @@ impl<'a> Parser<'a> {
         self.open_scope();
         let list = self.parse_stmt_list();
         self.close_scope();
-        let rbrace = self.expect(&Token::RBRACE);
+        let rbrace = self.expect_rbrace();
 
         self.trace_end();
         BlockStmt::new(lbrace, list, rbrace)
@@ -2188,7 +2407,7 @@ impl<'a> Parser<'a> {
             let clause = self.parse_case_clause(type_switch);
             list.push(Stmt::Case(Rc::new(clause)));
         }
-        let rbrace = self.expect(&Token::RBRACE);
+        let rbrace = self.expect_rbrace();
         self.expect_semi();
         let body = BlockStmt {
             l_brace: lbrace,
@@ -2289,7 +2508,7 @@ impl<'a> Parser<'a> {
         while self.token == Token::CASE || self.token == Token::DEFAULT {
             list.push(Stmt::Comm(Rc::new(self.parse_comm_clause())));
         }
-        let rbrace = self.expect(&Token::RBRACE);
+        let rbrace = self.expect_rbrace();
         self.expect_semi();
         let body = BlockStmt {
             l_brace: lbrace,
@@ -2531,6 +2750,7 @@ impl<'a> Parser<'a> {
     ) -> SpecKey {
         self_.trace_begin(&format!("{}{}", keyword.text(), "Spec"));
 
+        let doc = self_.lead_comment.take();
         let pos = self_.pos;
         let idents = self_.parse_ident_list();
         let typ = self_.try_type();
@@ -2562,6 +2782,7 @@ impl<'a> Parser<'a> {
         // the end of the innermost containing block.
         // (Global identifiers are resolved in a separate phase after parsing.)
         let spec = self_.objects.specs.insert(Spec::Value(Rc::new(ValueSpec {
+            doc,
             names: idents,
             typ,
             values,
@@ -2585,6 +2806,7 @@ impl<'a> Parser<'a> {
     fn parse_type_spec(&mut self, _: &Token, _: isize) -> SpecKey {
         self.trace_begin("TypeSpec");
 
+        let doc = self.lead_comment.take();
         let ident = self.parse_ident();
         // Go spec: The scope of a type identifier declared inside a function begins
         // at the identifier in the TypeSpec and ends at the end of the innermost
@@ -2592,6 +2814,7 @@ impl<'a> Parser<'a> {
         // (Global identifiers are resolved in a separate phase after parsing.)
         let placeholder = Expr::new_bad(0, 0);
         let spec_val = Spec::Type(Rc::new(TypeSpec {
+            doc,
             name: ident,
             assign: 0,
             typ: placeholder,
@@ -2631,6 +2854,7 @@ impl<'a> Parser<'a> {
     ) -> Decl {
         self.trace_begin(&format!("GenDecl({})", keyword.text()));
 
+        let doc = self.lead_comment.take();
         let pos = self.expect(keyword);
         let (lparen, rparen, list) = if self.token == Token::LPAREN {
             let lp = self.pos;
@@ -2650,6 +2874,7 @@ impl<'a> Parser<'a> {
 
         self.trace_end();
         Decl::Gen(Rc::new(GenDecl {
+            doc,
             token_pos: pos,
             token: keyword.clone(),
             l_paran: lparen,
@@ -2661,6 +2886,7 @@ impl<'a> Parser<'a> {
     fn parse_func_decl(&mut self) -> Decl {
         self.trace_begin("FunctionDecl");
 
+        let doc = self.lead_comment.take();
         let pos = self.expect(&Token::FUNC);
         let scope = new_scope!(self, self.top_scope);
         let recv = if self.token == Token::LPAREN {
@@ -2684,6 +2910,7 @@ impl<'a> Parser<'a> {
             results: results,
         });
         let decl = self.objects.fdecls.insert(FuncDecl {
+            doc,
             recv: recv,
             name: ident,
             typ: typ,
@@ -2749,6 +2976,7 @@ impl<'a> Parser<'a> {
         self.trace_begin("File");
 
         let err_count = self.errors.len();
+        let doc = self.lead_comment.take();
         let pos = self.expect(&Token::PACKAGE);
         // Go spec: The package clause is not a declaration;
         // the package name does not appear in any scope.
@@ -2800,6 +3028,7 @@ impl<'a> Parser<'a> {
 
         self.trace_end();
         Some(File {
+            doc,
             package: pos,
             name: ident,
             decls: decls,
@@ -63,12 +63,23 @@ impl fmt::Display for FilePos {
     }
 }
 
+/// An alternative position from a line directive like `//line a.go:10`, for
+/// the source from `offset` on.
+#[derive(Clone, Debug)]
+struct LineInfo {
+    offset: usize,
+    filename: Rc<String>,
+    line: usize,
+    column: usize,
+}
+
 #[derive(Debug)]
 pub struct File {
     name: Rc<String>,
     base: usize,
     size: usize,
     lines: Vec<usize>,
+    infos: Vec<LineInfo>,
 }
 
 impl File {
@@ -78,6 +89,7 @@ impl File {
             base: 0,
             size: 0,
             lines: vec![0],
+            infos: vec![],
         }
     }
 
@@ -97,6 +109,11 @@ impl File {
         self.lines.len()
     }
 
+    /// Grows the file to `size`, for a source that is read as it's scanned.
+    pub fn grow(&mut self, size: usize) {
+        self.size = self.size.max(size);
+    }
+
     pub fn add_line(&mut self, offset: usize) {
         let i = self.line_count();
         if (i == 0 || self.lines[i - 1] < offset) && offset < self.size {
@@ -167,30 +184,69 @@ impl File {
         self.base() + offset
     }
 
+    /// Adds the alternative position of a line directive, for the source from
+    /// `offset` on. The offsets must be added in order, a column of zero
+    /// means it's unknown.
+    pub fn add_line_column_info(
+        &mut self,
+        offset: usize,
+        filename: String,
+        line: usize,
+        column: usize,
+    ) {
+        let i = self.infos.len();
+        if (i == 0 || self.infos[i - 1].offset < offset) && offset < self.size {
+            self.infos.push(LineInfo {
+                offset,
+                filename: Rc::new(filename),
+                line,
+                column,
+            });
+        }
+    }
+
+    /// The position of `p`, as the line directives give it.
     pub fn position(&self, p: Pos) -> FilePos {
+        self.position_for(p, true)
+    }
+
+    /// The position of `p`, as the line directives give it if `adjusted`, or
+    /// where it is in the file.
+    pub fn position_for(&self, p: Pos, adjusted: bool) -> FilePos {
         if p < self.base || p > self.base + self.size {
             panic!("illegal Pos value");
         }
 
-        let line_count = self.line_count();
         let offset = p - self.base;
-        let line = match self
-            .lines
-            .iter()
-            .enumerate()
-            .find(|&(_, &line)| line > offset)
-        {
-            Some((i, _)) => i,
-            None => line_count,
-        };
-        let column = offset - self.lines[line - 1] + 1;
-
-        FilePos {
+        let index = self.line_index(offset);
+        let mut pos = FilePos {
             filename: self.name.clone(),
-            line: line,
+            line: index + 1,
             offset: offset,
-            column: column,
+            column: offset - self.lines[index] + 1,
+        };
+        if !adjusted {
+            return pos;
+        }
+        let info = match self.infos.partition_point(|x| x.offset <= offset) {
+            0 => return pos,
+            i => &self.infos[i - 1],
+        };
+        // the lines after the directive go on from the line it gives
+        let distance = index - self.line_index(info.offset);
+        pos.filename = info.filename.clone();
+        pos.line = info.line + distance;
+        if info.column == 0 {
+            pos.column = 0;
+        } else if distance == 0 {
+            pos.column = info.column + offset - info.offset;
         }
+        pos
+    }
+
+    // The index of the line that `offset` is on.
+    fn line_index(&self, offset: usize) -> usize {
+        self.lines.partition_point(|&x| x <= offset).max(1) - 1
     }
 }
 
@@ -202,7 +258,33 @@ impl BorshSerialize for File {
         name_str.serialize(writer)?;
         self.base.serialize(writer)?;
         self.size.serialize(writer)?;
-        self.lines.serialize(writer)
+        self.lines.serialize(writer)?;
+        self.infos.serialize(writer)
+    }
+}
+
+#[cfg(feature = "serde_borsh")]
+impl BorshSerialize for LineInfo {
+    #[inline]
+    fn serialize<W: BorshWrite>(&self, writer: &mut W) -> BorshResult<()> {
+        self.offset.serialize(writer)?;
+        let name_str: &str = &*self.filename;
+        name_str.serialize(writer)?;
+        self.line.serialize(writer)?;
+        self.column.serialize(writer)
+    }
+}
+
+#[cfg(feature = "serde_borsh")]
+impl BorshDeserialize for LineInfo {
+    #[inline]
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> BorshResult<Self> {
+        Ok(LineInfo {
+            offset: usize::deserialize_reader(reader)?,
+            filename: Rc::new(String::deserialize_reader(reader)?),
+            line: usize::deserialize_reader(reader)?,
+            column: usize::deserialize_reader(reader)?,
+        })
     }
 }
 
@@ -214,11 +296,13 @@ impl BorshDeserialize for File {
         let base = usize::deserialize_reader(reader)?;
         let size = usize::deserialize_reader(reader)?;
         let lines = Vec::<usize>::deserialize_reader(reader)?;
+        let infos = Vec::<LineInfo>::deserialize_reader(reader)?;
         Ok(File {
             name: Rc::new(name),
             base,
             size,
             lines,
+            infos,
         })
     }
 }
@@ -239,7 +323,12 @@ impl FileSet {
     }
 
     pub fn base(&self) -> usize {
-        self.base
+        // the recent file may have grown since it was added
+        self.recent_file_end().max(self.base)
+    }
+
+    fn recent_file_end(&self) -> usize {
+        self.files.last().map_or(0, |f| f.base + f.size + 1)
     }
 
     pub fn iter(&self) -> FileSetIter {
@@ -277,16 +366,17 @@ impl FileSet {
     }
 
     pub fn add_file(&mut self, name: String, base: Option<usize>, size: usize) -> &mut File {
-        let real_base = if let Some(b) = base { b } else { self.base };
-        if real_base < self.base {
+        let cur_base = self.base();
+        let real_base = if let Some(b) = base { b } else { cur_base };
+        if real_base < cur_base {
             panic!("illegal base");
         }
 
         let mut f = File::new(name);
         f.base = real_base;
         f.size = size;
-        let set_base = self.base + size + 1; // +1 because EOF also has a position
-        if set_base < self.base {
+        let set_base = cur_base + size + 1; // +1 because EOF also has a position
+        if set_base < cur_base {
             panic!("token.Pos offset overflow (> 2G of source code in file set)");
         }
         self.base = set_base;
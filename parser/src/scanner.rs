@@ -12,12 +12,15 @@
 use super::errors;
 use super::position;
 use super::token::Token;
+use super::unicode;
+use std::collections::VecDeque;
+use std::io;
 use std::iter::Peekable;
 use std::str::Chars;
 
 pub struct Scanner<'a> {
     file: &'a mut position::File, // source file handle
-    src: Peekable<Chars<'a>>,     // source
+    src: Source<'a>,              // source
     errors: &'a errors::ErrorList,
 
     offset: usize,      // character offset
@@ -31,10 +34,28 @@ impl<'a> Scanner<'a> {
         file: &'a mut position::File,
         src: &'a str,
         err: &'a errors::ErrorList,
+    ) -> Scanner<'a> {
+        Scanner::with_source(file, Source::Str(src.chars().peekable()), err)
+    }
+
+    /// Creates a scanner reading the source from `src` in chunks, `file`
+    /// grows with the source read, so its size can be anything to begin with.
+    pub fn from_reader(
+        file: &'a mut position::File,
+        src: Box<dyn io::Read + 'a>,
+        err: &'a errors::ErrorList,
+    ) -> Scanner<'a> {
+        Scanner::with_source(file, Source::Stream(Stream::new(src)), err)
+    }
+
+    fn with_source(
+        file: &'a mut position::File,
+        src: Source<'a>,
+        err: &'a errors::ErrorList,
     ) -> Scanner<'a> {
         Scanner {
             file: file,
-            src: src.chars().peekable(),
+            src: src,
             errors: err,
             offset: 0,
             line_offset: 0,
@@ -44,7 +65,12 @@ impl<'a> Scanner<'a> {
     }
 
     fn error(&self, msg: &str) {
-        errors::FilePosErrors::new(self.file, self.errors).add_str(self.offset, msg, false);
+        self.error_at(self.offset, msg);
+    }
+
+    fn error_at(&self, offset: usize, msg: &str) {
+        let pos = self.file.pos(offset);
+        errors::FilePosErrors::new(self.file, self.errors).add_str(pos, msg, false);
     }
 
     // Read the next Unicode char
@@ -201,6 +227,7 @@ impl<'a> Scanner<'a> {
                     self.semi1 = false;
                     Token::SEMICOLON(false.into())
                 } else {
+                    self.report_source_errors();
                     Token::EOF
                 }
             }
@@ -212,7 +239,7 @@ impl<'a> Scanner<'a> {
         let mut s = String::new();
         loop {
             match self.peek_char() {
-                Some(&ch) if is_letter(ch) || is_decimal(ch) => {
+                Some(&ch) if is_letter(ch) || is_digit(ch) => {
                     self.advance_and_push(&mut s, ch);
                 }
                 _ => break,
@@ -237,69 +264,102 @@ impl<'a> Scanner<'a> {
         tok
     }
 
+    /// Scans an int or a float literal the way Go's scanner does: the digits
+    /// of octal and binary literals are validated after the fact, as `09.5`
+    /// and `09i` are valid decimal literals.
     fn scan_number_without_i(&mut self, ch: char) -> Token {
-        let mut literal = String::new();
-        if ch == '.' {
-            //.34
-            self.scan_fraction_and_finish(literal)
-        } else if ch == '0' {
-            self.advance_and_push(&mut literal, '0');
-            match self.peek_char() {
-                // hexadecimal int
-                Some('x') | Some('X') => self.prefixed_int(literal, IntPrefix::Hex),
-                // octal int (explicit)
-                Some('o') | Some('O') => self.prefixed_int(literal, IntPrefix::Octal(false)),
-                // binary int
-                Some('b') | Some('B') => self.prefixed_int(literal, IntPrefix::Binary),
-                // octal int (bare)
-                Some(ch) if is_decimal(*ch) => self.prefixed_int(literal, IntPrefix::Octal(true)),
-                _ => self.decimal_int_or_float(literal),
+        let mut lit = String::new();
+        let mut prefix = IntPrefix::Decimal;
+        let mut digits = 0;
+        if ch != '.' {
+            if ch == '0' {
+                self.advance_and_push(&mut lit, '0');
+                digits = 1;
+                prefix = match self.peek_char() {
+                    Some('x') | Some('X') => IntPrefix::Hex,
+                    Some('o') | Some('O') => IntPrefix::Octal(false),
+                    Some('b') | Some('B') => IntPrefix::Binary,
+                    _ => IntPrefix::Octal(true),
+                };
+                if !prefix.is_bare() {
+                    let c = *self.peek_char().unwrap();
+                    self.advance_and_push(&mut lit, c);
+                    digits = 0;
+                }
+            }
+            match self.scan_digits(&mut lit, prefix.scanned(), true) {
+                Ok(count) => digits += count,
+                Err(e) => return self.illegal_number(lit, e),
             }
-        } else {
-            self.decimal_int_or_float(literal)
         }
-    }
 
-    fn prefixed_int(&mut self, mut literal: String, prefix: IntPrefix) -> Token {
-        if prefix.is_bare() {
-            literal.push(prefix.char());
-        } else {
-            self.advance_and_push(&mut literal, prefix.char());
+        let mut is_float = false;
+        if self.peek_char() == Some(&'.') {
+            if matches!(prefix, IntPrefix::Binary | IntPrefix::Octal(false)) {
+                let msg = format!("invalid radix point in {} literal", prefix.name());
+                return self.illegal_number(lit, &msg);
+            }
+            is_float = true;
+            self.advance_and_push(&mut lit, '.');
+            match self.scan_digits(&mut lit, prefix.scanned(), false) {
+                Ok(count) => digits += count,
+                Err(e) => return self.illegal_number(lit, e),
+            }
+        }
+        if digits == 0 {
+            let msg = format!("{} literal has no digits", prefix.name());
+            return self.illegal_number(lit, &msg);
         }
 
-        let result = self.scan_digits(&mut literal, prefix.is_valid(), true);
-        match result {
-            Ok(count) => {
-                if count == 0 {
-                    self.error(prefix.err_msg());
-                    return Token::ILLEGAL(literal.into());
+        match self.peek_char() {
+            Some(&e) if e == 'e' || e == 'E' || e == 'p' || e == 'P' => {
+                if (e == 'e' || e == 'E') && !prefix.is_decimal() {
+                    let msg = format!("'{}' exponent requires decimal mantissa", e);
+                    return self.illegal_number(lit, &msg);
+                }
+                if (e == 'p' || e == 'P') && !matches!(prefix, IntPrefix::Hex) {
+                    let msg = format!("'{}' exponent requires hexadecimal mantissa", e);
+                    return self.illegal_number(lit, &msg);
+                }
+                is_float = true;
+                self.advance_and_push(&mut lit, e);
+                if let Some(&sign) = self.peek_char() {
+                    if sign == '+' || sign == '-' {
+                        self.advance_and_push(&mut lit, sign);
+                    }
+                }
+                match self.scan_digits(&mut lit, is_decimal, false) {
+                    Ok(0) => return self.illegal_number(lit, "exponent has no digits"),
+                    Ok(_) => {}
+                    Err(e) => return self.illegal_number(lit, e),
                 }
             }
-            Err(e) => {
-                self.error(e);
-                return Token::ILLEGAL(literal.into());
-            }
-        }
-        if let Some(c) = self.peek_char() {
-            if matches!(prefix, IntPrefix::Binary | IntPrefix::Octal(_)) && is_decimal(*c) {
-                self.error(prefix.err_msg());
-                return Token::ILLEGAL(literal.into());
+            _ if is_float && matches!(prefix, IntPrefix::Hex) => {
+                return self.illegal_number(lit, "hexadecimal mantissa requires a 'p' exponent");
             }
+            _ => {}
         }
-        Token::INT(literal.into())
-    }
 
-    fn decimal_int_or_float(&mut self, mut literal: String) -> Token {
-        // decimal int or float 3 / 3.14
-        if let Err(e) = self.scan_digits(&mut literal, is_decimal, true) {
-            self.error(e);
-            return Token::ILLEGAL(literal.into());
+        if is_float {
+            return Token::FLOAT(lit.into());
         }
-        match self.peek_char() {
-            Some('e') | Some('E') => self.scan_exponent_and_finish(literal),
-            Some('.') => self.scan_fraction_and_finish(literal),
-            _ => Token::INT(literal.into()),
+        // 08i is a decimal imaginary literal
+        let check = !(prefix.is_bare() && self.peek_char() == Some(&'i'));
+        let skip = if prefix.is_decimal() { 0 } else { 2 };
+        let invalid = lit
+            .chars()
+            .skip(skip)
+            .find(|c| *c != '_' && !prefix.is_valid()(*c));
+        if let Some(c) = invalid.filter(|_| check) {
+            let msg = format!("invalid digit '{}' in {} literal", c, prefix.name());
+            return self.illegal_number(lit, &msg);
         }
+        Token::INT(lit.into())
+    }
+
+    fn illegal_number(&self, lit: String, msg: &str) -> Token {
+        self.error(msg);
+        Token::ILLEGAL(lit.into())
     }
 
     fn scan_token(&mut self, t: Token, semi: bool) -> Token {
@@ -310,19 +370,34 @@ impl<'a> Scanner<'a> {
 
     fn scan_char(&mut self) -> Token {
         let mut lit = String::new();
-        if let Some(unquoted) = self.scan_string_char_lit(&mut lit, '\'') {
-            Token::CHAR((lit, unquoted.chars().next().unwrap()).into())
-        } else {
-            Token::ILLEGAL(lit.into())
+        match self.scan_string_char_lit(&mut lit, '\'') {
+            Some((unquoted, count, valid)) => {
+                if valid && count != 1 {
+                    self.error(match count {
+                        0 => "empty rune literal or unescaped ' in rune literal",
+                        _ => "more than one character in rune literal",
+                    });
+                }
+                // a single byte is either ASCII or a byte escape like '\xff',
+                // which is the rune of the same value
+                let ch = match unquoted[..] {
+                    [b] => char::from(b),
+                    _ => String::from_utf8_lossy(&unquoted)
+                        .chars()
+                        .next()
+                        .unwrap_or('\0'),
+                };
+                Token::CHAR((lit, ch).into())
+            }
+            None => Token::ILLEGAL(lit.into()),
         }
     }
 
     fn scan_string(&mut self) -> Token {
         let mut lit = String::new();
-        if let Some(unquoted) = self.scan_string_char_lit(&mut lit, '"') {
-            Token::STRING((lit, unquoted).into())
-        } else {
-            Token::ILLEGAL(lit.into())
+        match self.scan_string_char_lit(&mut lit, '"') {
+            Some((unquoted, _, _)) => Token::STRING((lit, unquoted).into()),
+            None => Token::ILLEGAL(lit.into()),
         }
     }
 
@@ -349,10 +424,13 @@ impl<'a> Scanner<'a> {
                 }
             };
         }
-        Token::STRING((lit, unquoted).into())
+        Token::STRING((lit, unquoted.into_bytes()).into())
     }
 
     fn scan_comment(&mut self, ch: char) -> Token {
+        let offs = self.offset;
+        // a //line directive must start at the beginning of the line
+        let directive = ch == '*' || offs == self.line_offset;
         let mut lit = String::new();
         lit.push(self.read_char().unwrap());
         lit.push(self.read_char().unwrap());
@@ -369,6 +447,9 @@ impl<'a> Scanner<'a> {
                 }
             }
             lit.push('\n');
+            if directive && lit[2..].starts_with("line ") {
+                self.update_line_info(self.offset, offs, &lit);
+            }
             Token::COMMENT(lit.into())
         } else {
             // /*
@@ -389,17 +470,86 @@ impl<'a> Scanner<'a> {
                     }
                     None => {
                         self.error("comment not terminated");
-                        break;
+                        return Token::COMMENT(lit.into());
                     }
                 }
             }
+            if lit[2..].starts_with("line ") {
+                self.update_line_info(self.offset, offs, &lit);
+            }
             Token::COMMENT(lit.into())
         }
     }
 
-    fn scan_string_char_lit(&mut self, lit: &mut String, quote: char) -> Option<String> {
+    /// Records the position a line directive `//line filename:line:col`,
+    /// `//line filename:line` or the same in a `/*line` comment gives to the
+    /// source at `next`, right after the comment at `offs`.
+    fn update_line_info(&mut self, next: usize, offs: usize, lit: &str) {
+        let text = match lit.starts_with("/*") {
+            true => &lit[..lit.len() - 2],
+            false => lit.trim_end_matches('\n'),
+        };
+        // lop off the leading "//line " or "/*line "
+        let text = &text[7..];
+        let offs = offs + 7;
+        let offset_of = |i: usize| offs + text[..i].chars().count();
+
+        let (i, n, ok) = match trailing_digits(text) {
+            Some(x) => x,
+            None => return, // not a line directive
+        };
+        if !ok {
+            let msg = format!("invalid line number: {}", &text[i..]);
+            self.error_at(offset_of(i), &msg);
+            return;
+        }
+        // a cap on the line and column numbers
+        const MAX_LINE_COL: usize = 1 << 30;
+        let (i, line, column, text) = match trailing_digits(&text[..i - 1]) {
+            Some((i2, n2, true)) => {
+                if n == 0 || n > MAX_LINE_COL {
+                    let msg = format!("invalid column number: {}", &text[i..]);
+                    self.error_at(offset_of(i), &msg);
+                    return;
+                }
+                (i2, n2, n, &text[..i - 1])
+            }
+            _ => (i, n, 0, text),
+        };
+        if line == 0 || line > MAX_LINE_COL {
+            let msg = format!("invalid line number: {}", &text[i..]);
+            self.error_at(offset_of(i), &msg);
+            return;
+        }
+
+        // with a column, an empty filename is the one of the line before,
+        // a relative one is in the directory of the file
+        let name = &text[..i - 1];
+        let filename = if name.is_empty() && column > 0 {
+            String::clone(&self.file.position(self.file.pos(offs)).filename)
+        } else {
+            let path = std::path::Path::new(name);
+            match std::path::Path::new(self.file.name()).parent() {
+                Some(dir) if !name.is_empty() && path.is_relative() => {
+                    dir.join(path).to_string_lossy().into_owned()
+                }
+                _ => name.to_owned(),
+            }
+        };
+        self.file.add_line_column_info(next, filename, line, column);
+    }
+
+    /// Returns the bytes the literal stands for, the number of chars and
+    /// escapes in it, and whether all of its escapes are valid.
+    fn scan_string_char_lit(
+        &mut self,
+        lit: &mut String,
+        quote: char,
+    ) -> Option<(Vec<u8>, usize, bool)> {
         lit.push(self.read_char().unwrap());
-        let mut unquoted = String::with_capacity(lit.len());
+        let mut unquoted = Vec::with_capacity(lit.len());
+        let mut count = 0;
+        let mut valid = true;
         loop {
             match self.peek_char() {
                 Some(&ch) if ch == quote => {
@@ -407,98 +557,86 @@ impl<'a> Scanner<'a> {
                     break;
                 }
                 Some('\n') | None => {
-                    self.error("string/char literal not terminated");
+                    self.error(match quote {
+                        '"' => "string literal not terminated",
+                        _ => "rune literal not terminated",
+                    });
                     return None;
                 }
-                Some('\\') => {
-                    let result = self.scan_escape(lit, quote);
-                    if result.is_none() {
-                        return None;
-                    } else {
-                        unquoted.push(result.unwrap());
+                Some('\\') => match self.scan_escape(lit, quote) {
+                    // \x and octal escapes are bytes, the others are chars
+                    Some((x, true)) => unquoted.push(x as u8),
+                    Some((x, false)) => {
+                        let ch = std::char::from_u32(x).unwrap();
+                        unquoted.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
                     }
-                }
+                    None => valid = false,
+                },
                 Some(&ch) => {
                     self.advance_and_push(lit, ch);
-                    unquoted.push(ch);
+                    unquoted.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
                 }
             }
+            count += 1;
         }
-        Some(unquoted)
+        Some((unquoted, count, valid))
     }
 
-    fn scan_escape(&mut self, lit: &mut String, quote: char) -> Option<char> {
+    /// Returns the value of the escape and whether it's a byte value.
+    fn scan_escape(&mut self, lit: &mut String, quote: char) -> Option<(u32, bool)> {
         lit.push(self.read_char().unwrap());
 
         let mut n: isize;
         let base: u32;
         let max: u32;
         match self.peek_char() {
-            Some(&ch) => match ch {
-                'a' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\u{0007}');
-                }
-                'b' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\u{0008}');
-                }
-                'f' => {
+            Some(&ch) => {
+                let simple = match ch {
+                    'a' => Some('\u{0007}'),
+                    'b' => Some('\u{0008}'),
+                    'f' => Some('\u{000c}'),
+                    'n' => Some('\n'),
+                    'r' => Some('\r'),
+                    't' => Some('\t'),
+                    'v' => Some('\u{000b}'),
+                    '\\' => Some('\\'),
+                    c if c == quote => Some(c),
+                    _ => None,
+                };
+                if let Some(c) = simple {
                     self.advance_and_push(lit, ch);
-                    return Some('\u{000c}');
+                    return Some((c as u32, false));
                 }
-                'n' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\n');
-                }
-                'r' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\r');
-                }
-                't' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\t');
-                }
-                'v' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\u{000b}');
-                }
-                '\\' => {
-                    self.advance_and_push(lit, ch);
-                    return Some('\u{005c}');
-                }
-                c if c == quote => {
-                    self.advance_and_push(lit, c);
-                    return Some(c);
-                }
-                '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' => {
-                    n = 3;
-                    base = 8;
-                    max = 255;
-                }
-                'x' => {
-                    self.advance_and_push(lit, 'x');
-                    n = 2;
-                    base = 16;
-                    max = 255;
-                }
-                'u' => {
-                    self.advance_and_push(lit, 'u');
-                    n = 4;
-                    base = 16;
-                    max = std::char::MAX as u32;
-                }
-                'U' => {
-                    self.advance_and_push(lit, 'U');
-                    n = 8;
-                    base = 16;
-                    max = std::char::MAX as u32;
-                }
-                _ => {
-                    self.error("unknown escape sequence");
-                    return None;
+                match ch {
+                    '0'..='7' => {
+                        n = 3;
+                        base = 8;
+                        max = 255;
+                    }
+                    'x' => {
+                        self.advance_and_push(lit, 'x');
+                        n = 2;
+                        base = 16;
+                        max = 255;
+                    }
+                    'u' => {
+                        self.advance_and_push(lit, 'u');
+                        n = 4;
+                        base = 16;
+                        max = std::char::MAX as u32;
+                    }
+                    'U' => {
+                        self.advance_and_push(lit, 'U');
+                        n = 8;
+                        base = 16;
+                        max = std::char::MAX as u32;
+                    }
+                    _ => {
+                        self.error("unknown escape sequence");
+                        return None;
+                    }
                 }
-            },
+            }
             None => {
                 self.error("escape sequence not terminated");
                 return None;
@@ -511,7 +649,10 @@ impl<'a> Scanner<'a> {
                 Some(&ch) => {
                     let d = digit_val(ch);
                     if d >= base {
-                        self.error("illegal character in escape sequence");
+                        self.error(&format!(
+                            "illegal character U+{:04X} '{}' in escape sequence",
+                            ch as u32, ch
+                        ));
                         return None;
                     }
                     self.advance_and_push(lit, ch);
@@ -524,15 +665,11 @@ impl<'a> Scanner<'a> {
             }
             n -= 1;
         }
-        if x <= max {
-            let result = std::char::from_u32(x);
-            if result.is_none() {
-                self.error("escape sequence is invalid Unicode code point");
-            }
-            result
-        } else {
-            None
+        if x > max || (0xd800..0xe000).contains(&x) {
+            self.error("escape sequence is invalid Unicode code point");
+            return None;
         }
+        Some((x, max == 255))
     }
 
     fn scan_switch2<'b>(&mut self, t1: &'b Token, t2: &'b Token) -> &'b Token {
@@ -595,42 +732,6 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn scan_fraction_and_finish(&mut self, mut lit: String) -> Token {
-        self.advance_and_push(&mut lit, '.');
-        if let Err(e) = self.scan_digits(&mut lit, is_decimal, false) {
-            self.error(e);
-            return Token::ILLEGAL(lit.into());
-        }
-        match self.peek_char() {
-            Some('e') | Some('E') => self.scan_exponent_and_finish(lit),
-            _ => Token::FLOAT(lit.into()),
-        }
-    }
-
-    fn scan_exponent_and_finish(&mut self, mut lit: String) -> Token {
-        self.advance_and_push(&mut lit, 'e');
-        match self.peek_char() {
-            Some(&ch) if ch == '+' || ch == '-' => {
-                self.advance_and_push(&mut lit, ch);
-            }
-            _ => {}
-        }
-        match self.scan_digits(&mut lit, is_decimal, false) {
-            Ok(count) => {
-                if count == 0 {
-                    self.error("illegal floating-point exponent");
-                    Token::ILLEGAL(lit.into())
-                } else {
-                    Token::FLOAT(lit.into())
-                }
-            }
-            Err(e) => {
-                self.error(e);
-                Token::ILLEGAL(lit.into())
-            }
-        }
-    }
-
     fn scan_digits<'s, 'r>(
         &'s mut self,
         digits: &mut String,
@@ -686,32 +787,49 @@ impl<'a> Scanner<'a> {
 
     fn read_char(&mut self) -> Option<char> {
         let next = self.src.next();
+        if let Source::Stream(stream) = &mut self.src {
+            // count the char after this one in too, a new line only
+            // starts after a '\n' if there is one
+            stream.fill(0);
+            self.file.grow(stream.decoded);
+        }
         match next {
             Some(ch) => {
                 if ch == '\n' {
-                    self.line_offset = self.offset;
+                    self.line_offset = self.offset + 1;
                     self.file.add_line(self.offset + 1);
                 }
                 self.offset += 1;
             }
             None => {}
         }
+        self.report_source_errors();
         next
     }
 
+    // Reports the errors in reading the source up to the current offset.
+    fn report_source_errors(&mut self) {
+        if let Source::Stream(stream) = &mut self.src {
+            while let Some((offset, msg)) = stream.take_error(self.offset) {
+                let pos = self.file.pos(offset);
+                errors::FilePosErrors::new(self.file, self.errors).add(pos, msg, false);
+            }
+        }
+    }
+
     fn peek_char(&mut self) -> Option<&char> {
         self.src.peek()
     }
 
     fn get_char2nd(&mut self) -> Option<char> {
-        let mut iter = self.src.clone();
+        let mut iter = self.src.ahead();
         iter.next();
         iter.next()
     }
 
     // returns true if line ends with comment:
-    fn comment_to_end(&self) -> bool {
-        let mut iter = self.src.clone(); // don't touch the main iter
+    fn comment_to_end(&mut self) -> bool {
+        let mut iter = self.src.ahead().peekable(); // don't touch the main iter
         iter.next(); // eat the first '/'
         match iter.next() {
             // //-style comment always ends a line
@@ -760,6 +878,170 @@ impl<'a> Scanner<'a> {
     pub fn file(&self) -> &position::File {
         self.file
     }
+
+    // Reports whether a name comes next on the line, without scanning it.
+    pub(crate) fn name_follows(&mut self) -> bool {
+        self.src
+            .ahead()
+            .find(|&ch| ch != ' ' && ch != '\t')
+            .is_some_and(is_letter)
+    }
+}
+
+// The chars of a source, held in whole or read from a stream on demand.
+enum Source<'a> {
+    Str(Peekable<Chars<'a>>),
+    Stream(Stream<'a>),
+}
+
+impl<'a> Source<'a> {
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Source::Str(chars) => chars.next(),
+            Source::Stream(stream) => {
+                stream.fill(0);
+                stream.ahead.pop_front()
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        match self {
+            Source::Str(chars) => chars.peek(),
+            Source::Stream(stream) => {
+                stream.fill(0);
+                stream.ahead.front()
+            }
+        }
+    }
+
+    // Returns an iterator over the chars to come, which doesn't consume them.
+    fn ahead(&mut self) -> Ahead<'_, 'a> {
+        match self {
+            Source::Str(chars) => Ahead::Str(chars.clone()),
+            Source::Stream(stream) => Ahead::Stream(stream, 0),
+        }
+    }
+}
+
+enum Ahead<'s, 'a> {
+    Str(Peekable<Chars<'a>>),
+    Stream(&'s mut Stream<'a>, usize),
+}
+
+impl<'s, 'a> Iterator for Ahead<'s, 'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Ahead::Str(chars) => chars.next(),
+            Ahead::Stream(stream, i) => {
+                stream.fill(*i);
+                let ch = stream.ahead.get(*i).copied();
+                *i += 1;
+                ch
+            }
+        }
+    }
+}
+
+const STREAM_CHUNK_SIZE: usize = 16 * 1024;
+
+// A source decoded from UTF-8 a chunk at a time, only the chars looked
+// ahead are held, which is never more than the rest of a line.
+struct Stream<'a> {
+    reader: Box<dyn io::Read + 'a>,
+    pending: Vec<u8>,                  // undecoded bytes, at most an incomplete char
+    ahead: VecDeque<char>,             // decoded chars not read yet
+    decoded: usize,                    // count of chars decoded
+    eof: bool,                         // the reader is exhausted or failed
+    errors: VecDeque<(usize, String)>, // errors by the offset they are at
+}
+
+impl<'a> Stream<'a> {
+    fn new(reader: Box<dyn io::Read + 'a>) -> Stream<'a> {
+        Stream {
+            reader,
+            pending: vec![],
+            ahead: VecDeque::new(),
+            decoded: 0,
+            eof: false,
+            errors: VecDeque::new(),
+        }
+    }
+
+    // Reads on until the char at index `i` of the ones ahead is decoded,
+    // or the end of the source.
+    fn fill(&mut self, i: usize) {
+        if self.ahead.len() > i || self.eof {
+            return;
+        }
+        let mut chunk = [0; STREAM_CHUNK_SIZE];
+        while self.ahead.len() <= i && !self.eof {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    if !self.pending.is_empty() {
+                        self.pending.clear();
+                        self.push_illegal();
+                    }
+                }
+                Ok(n) => {
+                    self.pending.extend_from_slice(&chunk[..n]);
+                    self.decode();
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.eof = true;
+                    let msg = format!("failed to read source: {}", e);
+                    self.errors.push_back((self.decoded, msg));
+                }
+            }
+        }
+    }
+
+    fn decode(&mut self) {
+        let mut start = 0;
+        loop {
+            let (valid, invalid) = match std::str::from_utf8(&self.pending[start..]) {
+                Ok(s) => (s, None),
+                Err(e) => {
+                    let valid = &self.pending[start..start + e.valid_up_to()];
+                    (std::str::from_utf8(valid).unwrap(), Some(e.error_len()))
+                }
+            };
+            let count = valid.len();
+            for ch in valid.chars() {
+                self.ahead.push_back(ch);
+                self.decoded += 1;
+            }
+            start += count;
+            match invalid {
+                Some(Some(len)) => {
+                    start += len;
+                    self.push_illegal();
+                }
+                // an incomplete char at the end waits for the next chunk
+                Some(None) | None => break,
+            }
+        }
+        self.pending.drain(..start);
+    }
+
+    fn push_illegal(&mut self) {
+        let msg = "illegal UTF-8 encoding".to_owned();
+        self.errors.push_back((self.decoded, msg));
+        self.ahead.push_back(char::REPLACEMENT_CHARACTER);
+        self.decoded += 1;
+    }
+
+    // Takes the first error if it's at or before `offset`.
+    fn take_error(&mut self, offset: usize) -> Option<(usize, String)> {
+        match self.errors.front() {
+            Some((at, _)) if *at <= offset => self.errors.pop_front(),
+            _ => None,
+        }
+    }
 }
 
 fn digit_val(ch: char) -> u32 {
@@ -772,7 +1054,14 @@ fn digit_val(ch: char) -> u32 {
 }
 
 fn is_letter(ch: char) -> bool {
-    ch.is_alphabetic() || ch == '_'
+    match ch {
+        'a'..='z' | 'A'..='Z' | '_' => true,
+        _ => !ch.is_ascii() && unicode::is_letter(ch),
+    }
+}
+
+fn is_digit(ch: char) -> bool {
+    is_decimal(ch) || (!ch.is_ascii() && unicode::is_digit(ch))
 }
 
 fn is_decimal(ch: char) -> bool {
@@ -792,7 +1081,9 @@ fn is_hex(ch: char) -> bool {
 }
 
 enum IntPrefix {
+    Decimal,
     Binary,
+    /// true for the legacy octal literals without 'o'
     Octal(bool),
     Hex,
 }
@@ -800,17 +1091,18 @@ enum IntPrefix {
 impl IntPrefix {
     fn is_valid(&self) -> fn(char) -> bool {
         match self {
+            Self::Decimal => is_decimal,
             Self::Binary => is_binary,
             Self::Octal(_) => is_octal,
             Self::Hex => is_hex,
         }
     }
 
-    fn char(&self) -> char {
+    /// The digits scanned before being validated.
+    fn scanned(&self) -> fn(char) -> bool {
         match self {
-            Self::Binary => 'b',
-            Self::Octal(_) => 'o',
-            Self::Hex => 'x',
+            Self::Hex => is_hex,
+            _ => is_decimal,
         }
     }
 
@@ -818,15 +1110,31 @@ impl IntPrefix {
         matches!(self, Self::Octal(true))
     }
 
-    fn err_msg(&self) -> &str {
+    /// Legacy octal mantissas are decimal ones for floats.
+    fn is_decimal(&self) -> bool {
+        matches!(self, Self::Decimal | Self::Octal(true))
+    }
+
+    fn name(&self) -> &str {
         match self {
-            Self::Binary => "illegal binary number",
-            Self::Octal(_) => "illegal octal number",
-            Self::Hex => "illegal hexadecimal number",
+            Self::Decimal => "decimal",
+            Self::Binary => "binary",
+            Self::Octal(_) => "octal",
+            Self::Hex => "hexadecimal",
         }
     }
 }
 
+/// The index after the last ':' in `text` and the number after it, if it's
+/// one, as a filename may have ':'s as well.
+fn trailing_digits(text: &str) -> Option<(usize, usize, bool)> {
+    let i = text.rfind(':')? + 1;
+    match text[i..].parse::<usize>() {
+        Ok(n) if !text[i..].starts_with('+') => Some((i, n, true)),
+        _ => Some((i, 0, false)),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::position::FileSet;
@@ -877,4 +1185,226 @@ mod test {
         }
         print!("\n<- {} ->\n", err);
     }
+
+    #[test]
+    fn test_scan_numbers() {
+        let scan = |src: &str| {
+            let mut fs = FileSet::new();
+            let f = fs.add_file("numbers.gs".to_owned(), None, 1000);
+            let err = errors::ErrorList::new();
+            let mut scanner = Scanner::new(f, src, &err);
+            let (tok, _) = scanner.scan();
+            let msg = err.borrow().first().map(|e| e.msg.clone());
+            (tok, msg)
+        };
+        let ok = |src: &str, tok: Token| assert_eq!(scan(src), (tok, None), "{}", src);
+        ok("0b1011", Token::INT("0b1011".to_owned().into()));
+        ok("0O17", Token::INT("0O17".to_owned().into()));
+        ok("017", Token::INT("017".to_owned().into()));
+        ok("0x_FF_ff", Token::INT("0x_FF_ff".to_owned().into()));
+        ok("1_000_000", Token::INT("1_000_000".to_owned().into()));
+        ok("0x1.8p3", Token::FLOAT("0x1.8p3".to_owned().into()));
+        ok("0X.8P-1", Token::FLOAT("0X.8P-1".to_owned().into()));
+        ok("09.5", Token::FLOAT("09.5".to_owned().into()));
+        ok("1_0.2_5e1_0", Token::FLOAT("1_0.2_5e1_0".to_owned().into()));
+        ok(".25", Token::FLOAT(".25".to_owned().into()));
+        ok("3i", Token::IMAG("3i".to_owned().into()));
+        ok("09i", Token::IMAG("09i".to_owned().into()));
+        ok("0x1p-2i", Token::IMAG("0x1p-2i".to_owned().into()));
+
+        let err = |src: &str, msg: &str| {
+            assert_eq!(scan(src).1.as_deref(), Some(msg), "{}", src);
+        };
+        err("08", "invalid digit '8' in octal literal");
+        err("0b102", "invalid digit '2' in binary literal");
+        err("0o18", "invalid digit '8' in octal literal");
+        err("0x", "hexadecimal literal has no digits");
+        err("0x1.8", "hexadecimal mantissa requires a 'p' exponent");
+        err("0b1.0", "invalid radix point in binary literal");
+        err("0o7e1", "'e' exponent requires decimal mantissa");
+        err("1p3", "'p' exponent requires hexadecimal mantissa");
+        err("1e+", "exponent has no digits");
+        err("1__0", "_ must separate successive digits");
+    }
+
+    #[test]
+    fn test_scan_strings() {
+        let scan = |src: &str| {
+            let mut fs = FileSet::new();
+            let f = fs.add_file("strings.gs".to_owned(), None, 1000);
+            let err = errors::ErrorList::new();
+            let mut scanner = Scanner::new(f, src, &err);
+            let (tok, _) = scanner.scan();
+            let msg = err.borrow().first().map(|e| e.msg.clone());
+            (tok, msg)
+        };
+        let bytes = |src: &str, b: &[u8]| match scan(src) {
+            (Token::STRING(data), None) => assert_eq!(&data.as_str_bytes().1[..], b, "{}", src),
+            x => panic!("{}: {:?}", src, x),
+        };
+        bytes(r#""\xff\x00""#, &[0xff, 0]);
+        bytes(r#""\377\101""#, &[0xff, b'A']);
+        bytes(r#""世""#, "世".as_bytes());
+        bytes(r#""\U0001F600""#, "😀".as_bytes());
+        bytes(r#""\a\b\f\n\r\t\v\\\"""#, b"\x07\x08\x0c\n\r\t\x0b\\\"");
+        bytes("`a\\n\r\nb`", b"a\\n\nb");
+
+        let char = |src: &str, c: char| match scan(src) {
+            (Token::CHAR(data), None) => assert_eq!(*data.as_str_char().1, c, "{}", src),
+            x => panic!("{}: {:?}", src, x),
+        };
+        char(r"'\xff'", '\u{ff}');
+        char(r"'\377'", '\u{ff}');
+        char(r"'\''", '\'');
+        char("'世'", '世');
+
+        let err = |src: &str, msg: &str| {
+            assert_eq!(scan(src).1.as_deref(), Some(msg), "{}", src);
+        };
+        err(r#""\q""#, "unknown escape sequence");
+        err(r#""\'""#, "unknown escape sequence");
+        err(r#""\400""#, "escape sequence is invalid Unicode code point");
+        err(
+            r#""\ud800""#,
+            "escape sequence is invalid Unicode code point",
+        );
+        err(
+            r#""\U00110000""#,
+            "escape sequence is invalid Unicode code point",
+        );
+        err(
+            r#""\xg0""#,
+            "illegal character U+0067 'g' in escape sequence",
+        );
+        err("''", "empty rune literal or unescaped ' in rune literal");
+        err("'ab'", "more than one character in rune literal");
+        err("\"abc", "string literal not terminated");
+        err("`abc", "raw string literal not terminated");
+    }
+
+    #[test]
+    fn test_scan_line_directives() {
+        let src = "package p\n//line a.go:10\nx\n/*line b.go:20:5*/y\n//line :30:2\nz\n  //line c.go:1\nw\n//line d.go:x\nv";
+        let mut fs = FileSet::new();
+        let f = fs.add_file("lines.gs".to_owned(), None, src.chars().count());
+        let err = errors::ErrorList::new();
+        let mut scanner = Scanner::new(f, src, &err);
+        let mut idents = vec![];
+        loop {
+            match scanner.scan() {
+                (Token::EOF, _) => break,
+                (Token::IDENT(name), pos) => {
+                    let p = scanner.file().position(pos);
+                    idents.push(format!("{} {}", name.as_str(), p));
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(
+            idents,
+            vec![
+                "p lines.gs:1:9",
+                "x a.go:10",
+                "y b.go:20:5",
+                "z b.go:30:2",
+                "w b.go:32:1",
+                "v b.go:34:1",
+            ]
+        );
+        let e = &err.borrow()[0];
+        assert_eq!(e.msg, "invalid line number: x");
+        assert_eq!(e.pos.to_string(), "b.go:33:13");
+
+        let f = scanner.file();
+        assert_eq!(
+            f.position_for(f.pos(src.len() - 1), false).to_string(),
+            "lines.gs:10:1"
+        );
+    }
+
+    // Reads at most 3 bytes at a time, to split chars and lookaheads.
+    struct Trickle<'b>(&'b [u8]);
+
+    impl<'b> io::Read for Trickle<'b> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_scan_reader() {
+        let tokens = |scanner: &mut Scanner| {
+            let mut tokens = vec![];
+            loop {
+                let (tok, pos) = scanner.scan();
+                let p = scanner.file().position(pos);
+                tokens.push(format!("{:?} {}", tok, p));
+                if tok == Token::EOF {
+                    break tokens;
+                }
+            }
+        };
+        let src = "package 世界\n/* a\n */ x := 'é' /* b */\n\ty...\n//line a.go:7\nz\n";
+        let mut fs = FileSet::new();
+        let f = fs.add_file("a.gs".to_owned(), None, src.chars().count());
+        let err = errors::ErrorList::new();
+        let expected = tokens(&mut Scanner::new(f, src, &err));
+        let f = fs.add_file("a.gs".to_owned(), None, 0);
+        let base = f.base();
+        let reader = Box::new(Trickle(src.as_bytes()));
+        {
+            let mut scanner = Scanner::from_reader(f, reader, &err);
+            assert_eq!(tokens(&mut scanner), expected);
+        }
+        // the file has grown to the size of the source
+        assert_eq!(fs.base(), base + src.chars().count() + 1);
+        assert_eq!(err.len(), 0);
+
+        let f = fs.add_file("b.gs".to_owned(), None, 0);
+        let reader = Box::new(Trickle(b"x \xff y \xe4\xb8"));
+        let mut scanner = Scanner::from_reader(f, reader, &err);
+        let got = tokens(&mut scanner);
+        assert_eq!(got[1], "ILLEGAL b.gs:1:3");
+        assert_eq!(got[3], "ILLEGAL b.gs:1:7");
+        let errors: Vec<String> = err.borrow().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "[TC] b.gs:1:3  illegal UTF-8 encoding\n",
+                "[TC] b.gs:1:7  illegal UTF-8 encoding\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_idents() {
+        let src = "变量 π ǅ x١ _ä Ⅰ a½";
+        let mut fs = FileSet::new();
+        let f = fs.add_file("a.gs".to_owned(), None, src.chars().count());
+        let err = errors::ErrorList::new();
+        let mut scanner = Scanner::new(f, src, &err);
+        let mut tokens = vec![];
+        loop {
+            let (tok, _) = scanner.scan();
+            if tok == Token::EOF {
+                break;
+            }
+            tokens.push(format!("{:?}", tok));
+        }
+        let expected = [
+            "IDENT 变量",
+            "IDENT π",
+            "IDENT ǅ",
+            "IDENT x١",
+            "IDENT _ä",
+            "ILLEGAL",
+            "IDENT a",
+            "ILLEGAL",
+            "\";(inserted)\"",
+        ];
+        assert_eq!(tokens, expected);
+    }
 }
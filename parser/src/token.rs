@@ -383,7 +383,7 @@ impl fmt::Debug for Token {
 enum RawTokenData {
     Bool(bool),
     Str(String),
-    StrStr(String, String),
+    StrBytes(String, Vec<u8>),
     StrChar(String, char),
 }
 
@@ -402,9 +402,9 @@ impl From<String> for TokenData {
     }
 }
 
-impl From<(String, String)> for TokenData {
-    fn from(ss: (String, String)) -> Self {
-        TokenData(Box::new(RawTokenData::StrStr(ss.0, ss.1)))
+impl From<(String, Vec<u8>)> for TokenData {
+    fn from(sb: (String, Vec<u8>)) -> Self {
+        TokenData(Box::new(RawTokenData::StrBytes(sb.0, sb.1)))
     }
 }
 
@@ -443,7 +443,7 @@ impl TokenData {
     pub fn as_str(&self) -> &String {
         match self.0.as_ref() {
             RawTokenData::Str(s) => s,
-            RawTokenData::StrStr(s, _) => s,
+            RawTokenData::StrBytes(s, _) => s,
             RawTokenData::StrChar(s, _) => s,
             _ => unreachable!(),
         }
@@ -452,15 +452,17 @@ impl TokenData {
     pub fn as_str_mut(&mut self) -> &mut String {
         match self.0.as_mut() {
             RawTokenData::Str(s) => s,
-            RawTokenData::StrStr(s, _) => s,
+            RawTokenData::StrBytes(s, _) => s,
             RawTokenData::StrChar(s, _) => s,
             _ => unreachable!(),
         }
     }
 
-    pub fn as_str_str(&self) -> (&String, &String) {
+    /// The literal of a string and the bytes it stands for, which are not
+    /// always valid UTF-8, e.g. "\xff".
+    pub fn as_str_bytes(&self) -> (&String, &Vec<u8>) {
         match self.0.as_ref() {
-            RawTokenData::StrStr(s1, s2) => (s1, s2),
+            RawTokenData::StrBytes(s, b) => (s, b),
             _ => unreachable!(),
         }
     }
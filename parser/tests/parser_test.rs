@@ -57,3 +57,169 @@ fn test_issue3() {
     let (p, _) = fe::parse_file(o, &mut fs, el, "/a", "`", false);
     print!("{}", p.get_errors());
 }
+
+#[test]
+fn test_recover() {
+    let src = r#"package main
+
+func a() {
+	x := 1 +
+}
+
+func b() {
+	if true {
+	}
+func c() {
+	x := T{a: }
+	y := 2
+}
+
+var d = )
+func e() {}
+
+func f() {
+func() { println("hi") }()
+	defer
+func() {}()
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (_, f) = fe::parse_file(o, &mut fs, el, "/a", src, false);
+    let names: Vec<&str> = f
+        .unwrap()
+        .decls
+        .iter()
+        .map(|d| match d {
+            fe::ast::Decl::Func(f) => o.idents[o.fdecls[*f].name].name.as_str(),
+            fe::ast::Decl::Gen(_) => "var",
+            fe::ast::Decl::Bad(_) => "bad",
+        })
+        .collect();
+    assert_eq!(names, ["a", "b", "c", "var", "e", "f"]);
+    let errors: Vec<String> = el.borrow().iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        [
+            "[Parser] /a:5:1  expected operand, found }\n",
+            "[Parser] /a:10:1  expected '}', found func\n",
+            "[Parser] /a:11:12  expected operand, found }\n",
+            "[Parser] /a:15:9  expected operand, found )\n",
+            "[Parser] /a:16:1  expected ';', found func\n",
+        ]
+    );
+}
+
+#[test]
+fn test_nest_limit() {
+    let n = 20000;
+    let src = format!(
+        "package main\n\nvar a = {}1{}\nvar b = [][][]int{{}}\n",
+        "(".repeat(n),
+        ")".repeat(n)
+    );
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    fe::parse_file(o, &mut fs, el, "/a", &src, false);
+    let errors: Vec<String> = el.borrow().iter().map(|e| e.to_string()).collect();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("exceeded max nesting depth"));
+
+    let src = format!(
+        "package main\n\nvar a = {}1{}\n",
+        "(".repeat(150),
+        ")".repeat(150)
+    );
+    let el = &mut fe::ErrorList::new();
+    fe::parse_file(o, &mut fs, el, "/b", &src, false);
+    assert_eq!(el.len(), 0);
+}
+
+// Generates a source of `count` functions as it's read, without holding it.
+struct Generated {
+    next: usize,
+    count: usize,
+    buf: Vec<u8>,
+}
+
+impl std::io::Read for Generated {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.buf.is_empty() {
+            if self.next == 0 {
+                self.buf.extend_from_slice(b"package gen\n");
+            } else if self.next <= self.count {
+                let f = format!("func f{}() int {{ return {} }}\n", self.next, self.next);
+                self.buf.extend_from_slice(f.as_bytes());
+            } else {
+                return Ok(0);
+            }
+            self.next += 1;
+        }
+        let n = out.len().min(self.buf.len());
+        out[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_parse_reader() {
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let src = Generated {
+        next: 0,
+        count: 20000,
+        buf: vec![],
+    };
+    let (_, f) = fe::parse_reader(o, &mut fs, el, "/gen", src, false);
+    assert_eq!(el.len(), 0);
+    let f = f.unwrap();
+    assert_eq!(f.decls.len(), 20000);
+    let last = match f.decls.last().unwrap() {
+        fe::ast::Decl::Func(f) => o.fdecls[*f].name,
+        _ => unreachable!(),
+    };
+    let pos = o.idents[last].pos;
+    assert_eq!(fs.position(pos).unwrap().to_string(), "/gen:20001:6");
+
+    // files added after it come after all of its source
+    let (_, f) = fe::parse_file(o, &mut fs, el, "/b", "package b", false);
+    assert_eq!(
+        fs.position(f.unwrap().package).unwrap().to_string(),
+        "/b:1:1"
+    );
+}
+
+#[test]
+fn test_docs() {
+    let src = "// Package a is documented.\npackage a\n\n// F is documented too.\nfunc F() {} // not a doc\n";
+    let doc = |with_docs: bool| {
+        let mut fs = fe::FileSet::new();
+        let o = &mut fe::AstObjects::new();
+        let el = &mut fe::ErrorList::new();
+        let f = fs.add_file("/a".to_owned(), None, src.chars().count());
+        let mut p = match with_docs {
+            true => fe::Parser::with_docs(o, f, el, src, false),
+            false => fe::Parser::new(o, f, el, src, false),
+        };
+        let file = p.parse_file().unwrap();
+        drop(p);
+        let func = match &file.decls[0] {
+            fe::ast::Decl::Func(f) => o.fdecls[*f].doc.as_ref().map(|x| x.text()),
+            _ => unreachable!(),
+        };
+        (file.doc.map(|x| x.text()), func)
+    };
+    assert_eq!(
+        doc(true),
+        (
+            Some("Package a is documented.\n".to_owned()),
+            Some("F is documented too.\n".to_owned())
+        )
+    );
+    // the comments are skipped without looking at their lines
+    assert_eq!(doc(false), (None, None));
+}
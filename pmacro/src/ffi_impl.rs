@@ -32,6 +32,8 @@ enum FfiReturnType {
     MultipleVal(Vec<bool>),
     Vec,
     AlreadyBoxed,
+    // a Result<T, E: Display> is T's values followed by an error
+    GoError(Box<FfiReturnType>),
 }
 
 pub fn ffi_impl_implement(
@@ -320,6 +322,29 @@ fn gen_wrapper_block(
                     Ok(vec![#ret])
                 }}
             }
+            (false, false, FfiReturnType::GoError(inner)) => {
+                let nil_err: Expr = parse_quote! {
+                    FfiCtx::new_nil(go_vm::types::ValueType::Interface)
+                };
+                let ok: Expr = match *inner {
+                    FfiReturnType::ZeroVal => parse_quote! {vec![#nil_err]},
+                    FfiReturnType::OneVal(primitive) => {
+                        let ret = one_return_value(primitive);
+                        parse_quote! {vec![#ret, #nil_err]}
+                    }
+                    FfiReturnType::MultipleVal(types) => {
+                        let ret = multiple_return_values(types);
+                        parse_quote! {vec![#ret #nil_err]}
+                    }
+                    _ => unreachable!(),
+                };
+                parse_quote! {{
+                    match #self_ty::#callee(#args) {
+                        Ok(input) => Ok(#ok),
+                        Err(e) => ctx.error_results(&e.to_string()),
+                    }
+                }}
+            }
             (false, _, FfiReturnType::Vec) => panic!("non-async func cannot return a vec"),
             (false, _, _) => panic!("unsupported return type"),
             (true, _, _) => unreachable!(),
@@ -360,6 +385,27 @@ fn get_return_type_attributes(rt: &ReturnType) -> (bool, FfiReturnType) {
                             _ => return_type_panic!(),
                         }
                     }
+                    "Result" if is_std_result(&tp.path) => {
+                        let inner = match get_type_arg_types(&seg.arguments).first() {
+                            Some(Type::Path(itp)) => {
+                                let name = itp.path.segments.last().unwrap().ident.to_string();
+                                match name.as_str() {
+                                    "GosValue" => FfiReturnType::OneVal(false),
+                                    _ if is_primitive(&name) => FfiReturnType::OneVal(true),
+                                    _ => return_type_panic!(),
+                                }
+                            }
+                            Some(Type::Tuple(tt)) => {
+                                if tt.elems.is_empty() {
+                                    FfiReturnType::ZeroVal
+                                } else {
+                                    FfiReturnType::MultipleVal(are_primitives(tt))
+                                }
+                            }
+                            _ => return_type_panic!(),
+                        };
+                        (false, FfiReturnType::GoError(Box::new(inner)))
+                    }
                     _ => return_type_panic!(),
                 }
             }
@@ -410,6 +456,32 @@ fn get_type_arg_type(args: &PathArguments) -> Type {
     }
 }
 
+fn get_type_arg_types(args: &PathArguments) -> Vec<Type> {
+    match args {
+        PathArguments::AngleBracketed(aargs) => aargs
+            .args
+            .iter()
+            .map(|x| match x {
+                GenericArgument::Type(t) => t.clone(),
+                _ => return_type_panic!(),
+            })
+            .collect(),
+        _ => return_type_panic!(),
+    }
+}
+
+/// Only `Result`, `std::result::Result` and `core::result::Result` are taken
+/// for the std one, other types named `Result` like `io::Result` have other
+/// type args.
+fn is_std_result(path: &syn::Path) -> bool {
+    let names: Vec<String> = path.segments.iter().map(|x| x.ident.to_string()).collect();
+    match names.as_slice() {
+        [r] => r == "Result",
+        [c, m, r] => (c == "std" || c == "core") && m == "result" && r == "Result",
+        _ => false,
+    }
+}
+
 fn is_primitive(name: &str) -> bool {
     match name {
         "bool" | "isize" | "i8" | "i16" | "i32" | "i64" | "usize" | "u8" | "u16" | "u32"
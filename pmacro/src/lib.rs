@@ -17,6 +17,14 @@ pub fn derive_ffi(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     ffi::derive_ffi_implement(input)
 }
 
+/// Implements the FFI with the associated functions named `ffi_*` of the impl
+/// block, `ffi_async_*` for the async ones, which are called by the methods of
+/// the FFI interface without the prefix.
+///
+/// A function returning `RuntimeResult<T>` panics in the script on errors, one
+/// returning `Result<T, E>` with `E: Display` returns T's values followed by an
+/// `error` instead, the method must be declared like `f() (T, error)`. On
+/// errors the other results are zero values, see `FfiCtx::error_results`.
 #[proc_macro_attribute]
 pub fn ffi_impl(
     args: proc_macro::TokenStream,
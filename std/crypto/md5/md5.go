@@ -0,0 +1,73 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Package md5 implements the MD5 hash algorithm, natively.
+//
+// MD5 is cryptographically broken and should not be used for secure
+// applications.
+package md5
+
+import (
+	"hash"
+	"unsafe"
+)
+
+// The size of a MD5 checksum in bytes.
+const Size = 16
+
+// The blocksize of MD5 in bytes.
+const BlockSize = 64
+
+// the algorithm of the native hasher
+const alg = 1
+
+var native ffiHash
+
+func init() {
+	native = ffi(ffiHash, "crypto.hash")
+}
+
+type ffiHash interface {
+	new(alg int) unsafe.Pointer
+	write(p unsafe.Pointer, b []byte)
+	sum(p unsafe.Pointer, out []byte)
+	reset(p unsafe.Pointer)
+}
+
+type digest struct {
+	ptr unsafe.Pointer
+}
+
+// New returns a new hash.Hash computing the MD5 checksum.
+func New() hash.Hash {
+	return &digest{native.new(alg)}
+}
+
+func (d *digest) Write(p []byte) (int, error) {
+	if len(p) > 0 {
+		native.write(d.ptr, p)
+	}
+	return len(p), nil
+}
+
+func (d *digest) Sum(b []byte) []byte {
+	out := make([]byte, Size)
+	native.sum(d.ptr, out)
+	return append(b, out...)
+}
+
+func (d *digest) Reset() { native.reset(d.ptr) }
+
+func (d *digest) Size() int { return Size }
+
+func (d *digest) BlockSize() int { return BlockSize }
+
+// Sum returns the MD5 checksum of the data.
+func Sum(data []byte) [Size]byte {
+	d := New()
+	d.Write(data)
+	var sum [Size]byte
+	copy(sum[:], d.Sum(nil))
+	return sum
+}
@@ -0,0 +1,71 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Package sha256 implements the SHA256 hash algorithm as defined in FIPS 180-4,
+// natively.
+package sha256
+
+import (
+	"hash"
+	"unsafe"
+)
+
+// The size of a SHA256 checksum in bytes.
+const Size = 32
+
+// The blocksize of SHA256 in bytes.
+const BlockSize = 64
+
+// the algorithm of the native hasher
+const alg = 0
+
+var native ffiHash
+
+func init() {
+	native = ffi(ffiHash, "crypto.hash")
+}
+
+type ffiHash interface {
+	new(alg int) unsafe.Pointer
+	write(p unsafe.Pointer, b []byte)
+	sum(p unsafe.Pointer, out []byte)
+	reset(p unsafe.Pointer)
+}
+
+type digest struct {
+	ptr unsafe.Pointer
+}
+
+// New returns a new hash.Hash computing the SHA256 checksum.
+func New() hash.Hash {
+	return &digest{native.new(alg)}
+}
+
+func (d *digest) Write(p []byte) (int, error) {
+	if len(p) > 0 {
+		native.write(d.ptr, p)
+	}
+	return len(p), nil
+}
+
+func (d *digest) Sum(b []byte) []byte {
+	out := make([]byte, Size)
+	native.sum(d.ptr, out)
+	return append(b, out...)
+}
+
+func (d *digest) Reset() { native.reset(d.ptr) }
+
+func (d *digest) Size() int { return Size }
+
+func (d *digest) BlockSize() int { return BlockSize }
+
+// Sum256 returns the SHA256 checksum of the data.
+func Sum256(data []byte) [Size]byte {
+	d := New()
+	d.Write(data)
+	var sum [Size]byte
+	copy(sum[:], d.Sum(nil))
+	return sum
+}
@@ -0,0 +1,53 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//go:build toml
+
+// Package toml decodes TOML documents into Go values and encodes Go values
+// as TOML, for scripts to read and write their config files.
+//
+// Tables are decoded into structs and maps, arrays into slices and arrays.
+// A struct field is matched by the name in its `toml:"name"` tag, or else by
+// its own name ignoring case, embedded structs are inlined. Into an
+// interface{}, tables are decoded as map[string]interface{}, arrays as
+// []interface{} and integers as int64. Datetimes are decoded as strings.
+package toml
+
+import "errors"
+
+var native ffiToml
+
+func init() {
+	native = ffi(ffiToml, "encoding.toml")
+}
+
+type ffiToml interface {
+	// table and list are typed nils, of the types tables and arrays are
+	// decoded as into an interface{}
+	unmarshal(data string, v interface{}, table interface{}, list interface{}) string
+	marshal(v interface{}) (string, string)
+}
+
+// Unmarshal decodes the document in data and stores the result in the value
+// pointed to by v, which must be a non-nil pointer. The fields of a struct
+// and the entries of a map that are not in the document are kept.
+func Unmarshal(data []byte, v interface{}) error {
+	if msg := native.unmarshal(string(data), v, map[string]interface{}(nil), []interface{}(nil)); msg != "" {
+		return errors.New("toml: " + msg)
+	}
+	return nil
+}
+
+// Marshal returns the TOML encoding of v, which must be a struct or a map.
+// Structs and maps are encoded as tables, with their keys sorted, slices and
+// arrays as arrays. A struct field is written with the name in its
+// `toml:"name"` tag, or else its own name, embedded structs are inlined. Nil
+// values are left out of tables, as TOML has no null.
+func Marshal(v interface{}) ([]byte, error) {
+	out, msg := native.marshal(v)
+	if msg != "" {
+		return nil, errors.New("toml: " + msg)
+	}
+	return []byte(out), nil
+}
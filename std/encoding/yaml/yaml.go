@@ -0,0 +1,53 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//go:build yaml
+
+// Package yaml decodes YAML documents into Go values and encodes Go values
+// as YAML, for scripts to read and write their config files.
+//
+// Mappings are decoded into structs and maps, sequences into slices and
+// arrays. A struct field is matched by the name in its `yaml:"name"` tag, or
+// else by its own name ignoring case, embedded structs are inlined. Into an
+// interface{}, mappings are decoded as map[string]interface{}, sequences as
+// []interface{} and integers as int. Only the first document of a stream is
+// decoded, and aliases are not supported.
+package yaml
+
+import "errors"
+
+var native ffiYaml
+
+func init() {
+	native = ffi(ffiYaml, "encoding.yaml")
+}
+
+type ffiYaml interface {
+	// table and list are typed nils, of the types mappings and sequences
+	// are decoded as into an interface{}
+	unmarshal(data string, v interface{}, table interface{}, list interface{}) string
+	marshal(v interface{}) (string, string)
+}
+
+// Unmarshal decodes the first document in data and stores the result in the
+// value pointed to by v, which must be a non-nil pointer. The fields of a
+// struct and the entries of a map that are not in the document are kept.
+func Unmarshal(data []byte, v interface{}) error {
+	if msg := native.unmarshal(string(data), v, map[string]interface{}(nil), []interface{}(nil)); msg != "" {
+		return errors.New("yaml: " + msg)
+	}
+	return nil
+}
+
+// Marshal returns the YAML encoding of v. Structs and maps are encoded as
+// mappings, with the keys of maps sorted, slices and arrays as sequences. A
+// struct field is written with the name in its `yaml:"name"` tag, or else
+// its own name, embedded structs are inlined.
+func Marshal(v interface{}) ([]byte, error) {
+	out, msg := native.marshal(v)
+	if msg != "" {
+		return nil, errors.New("yaml: " + msg)
+	}
+	return []byte(out), nil
+}
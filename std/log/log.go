@@ -0,0 +1,211 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Package log implements a subset of Go's log package.
+//
+// The output of the standard logger and of the Loggers created without a
+// writer goes to the sink the host sets, so it ends up in the host's logging
+// pipeline, or to os.Stderr if there's none. The flags are kept for
+// compatibility, but no timestamps or file names are written, the host stamps
+// the records.
+package log
+
+import (
+	"fmt"
+	"io"
+	"os"
+)
+
+var native ffiLog
+
+func init() {
+	native = ffi(ffiLog, "log")
+}
+
+type ffiLog interface {
+	log(level int, msg string, keys []string, values []interface{}) bool
+}
+
+// the levels of the records sent to the sink, as in log/slog
+const (
+	levelInfo  = 0
+	levelError = 8
+)
+
+// These flags are the ones of Go, they are kept but have no effect.
+const (
+	Ldate         = 1 << iota     // the date in the local time zone: 2009/01/23
+	Ltime                         // the time in the local time zone: 01:23:23
+	Lmicroseconds                 // microsecond resolution: 01:23:23.123123.  assumes Ltime.
+	Llongfile                     // full file name and line number: /a/b/c/d.go:23
+	Lshortfile                    // final file name element and line number: d.go:23. overrides Llongfile
+	LUTC                          // if Ldate or Ltime is set, use UTC rather than the local time zone
+	Lmsgprefix                    // move the "prefix" from the beginning of the line to before the message
+	LstdFlags     = Ldate | Ltime // initial values for the standard logger
+)
+
+// A Logger writes lines of output to an io.Writer, or to the host's sink if
+// it has none.
+type Logger struct {
+	prefix string
+	flag   int
+	out    io.Writer
+}
+
+// New creates a new Logger writing to out, or to the host's sink if out is
+// nil. The prefix appears at the beginning of each line.
+func New(out io.Writer, prefix string, flag int) *Logger {
+	return &Logger{prefix: prefix, flag: flag, out: out}
+}
+
+var std = New(nil, "", LstdFlags)
+
+// Default returns the standard logger used by the package-level output functions.
+func Default() *Logger { return std }
+
+// Output writes the output for a logging event, a newline is appended if
+// s doesn't end with one.
+func (l *Logger) Output(calldepth int, s string) error {
+	return l.output(levelInfo, s)
+}
+
+func (l *Logger) output(level int, s string) error {
+	if len(s) > 0 && s[len(s)-1] == '\n' {
+		s = s[:len(s)-1]
+	}
+	s = l.prefix + s
+	if l.out == nil {
+		if native.log(level, s, nil, nil) {
+			return nil
+		}
+		_, err := io.WriteString(os.Stderr, s+"\n")
+		return err
+	}
+	_, err := io.WriteString(l.out, s+"\n")
+	return err
+}
+
+// Print calls l.Output to print to the logger.
+// Arguments are handled in the manner of fmt.Print.
+func (l *Logger) Print(v ...interface{}) { l.output(levelInfo, fmt.Sprint(v...)) }
+
+// Printf calls l.Output to print to the logger.
+// Arguments are handled in the manner of fmt.Printf.
+func (l *Logger) Printf(format string, v ...interface{}) {
+	l.output(levelInfo, fmt.Sprintf(format, v...))
+}
+
+// Println calls l.Output to print to the logger.
+// Arguments are handled in the manner of fmt.Println.
+func (l *Logger) Println(v ...interface{}) { l.output(levelInfo, fmt.Sprintln(v...)) }
+
+// Fatal is equivalent to l.Print() followed by a call to os.Exit(1).
+func (l *Logger) Fatal(v ...interface{}) {
+	l.output(levelError, fmt.Sprint(v...))
+	os.Exit(1)
+}
+
+// Fatalf is equivalent to l.Printf() followed by a call to os.Exit(1).
+func (l *Logger) Fatalf(format string, v ...interface{}) {
+	l.output(levelError, fmt.Sprintf(format, v...))
+	os.Exit(1)
+}
+
+// Fatalln is equivalent to l.Println() followed by a call to os.Exit(1).
+func (l *Logger) Fatalln(v ...interface{}) {
+	l.output(levelError, fmt.Sprintln(v...))
+	os.Exit(1)
+}
+
+// Panic is equivalent to l.Print() followed by a call to panic().
+func (l *Logger) Panic(v ...interface{}) {
+	s := fmt.Sprint(v...)
+	l.output(levelError, s)
+	panic(s)
+}
+
+// Panicf is equivalent to l.Printf() followed by a call to panic().
+func (l *Logger) Panicf(format string, v ...interface{}) {
+	s := fmt.Sprintf(format, v...)
+	l.output(levelError, s)
+	panic(s)
+}
+
+// Panicln is equivalent to l.Println() followed by a call to panic().
+func (l *Logger) Panicln(v ...interface{}) {
+	s := fmt.Sprintln(v...)
+	l.output(levelError, s)
+	panic(s)
+}
+
+// Flags returns the output flags for the logger.
+func (l *Logger) Flags() int { return l.flag }
+
+// SetFlags sets the output flags for the logger.
+func (l *Logger) SetFlags(flag int) { l.flag = flag }
+
+// Prefix returns the output prefix for the logger.
+func (l *Logger) Prefix() string { return l.prefix }
+
+// SetPrefix sets the output prefix for the logger.
+func (l *Logger) SetPrefix(prefix string) { l.prefix = prefix }
+
+// Writer returns the output destination for the logger, nil for the host's sink.
+func (l *Logger) Writer() io.Writer { return l.out }
+
+// SetOutput sets the output destination for the logger, nil for the host's sink.
+func (l *Logger) SetOutput(w io.Writer) { l.out = w }
+
+// SetOutput sets the output destination for the standard logger.
+func SetOutput(w io.Writer) { std.SetOutput(w) }
+
+// Flags returns the output flags for the standard logger.
+func Flags() int { return std.Flags() }
+
+// SetFlags sets the output flags for the standard logger.
+func SetFlags(flag int) { std.SetFlags(flag) }
+
+// Prefix returns the output prefix for the standard logger.
+func Prefix() string { return std.Prefix() }
+
+// SetPrefix sets the output prefix for the standard logger.
+func SetPrefix(prefix string) { std.SetPrefix(prefix) }
+
+// Writer returns the output destination for the standard logger.
+func Writer() io.Writer { return std.Writer() }
+
+// Print calls Output to print to the standard logger.
+// Arguments are handled in the manner of fmt.Print.
+func Print(v ...interface{}) { std.Print(v...) }
+
+// Printf calls Output to print to the standard logger.
+// Arguments are handled in the manner of fmt.Printf.
+func Printf(format string, v ...interface{}) { std.Printf(format, v...) }
+
+// Println calls Output to print to the standard logger.
+// Arguments are handled in the manner of fmt.Println.
+func Println(v ...interface{}) { std.Println(v...) }
+
+// Fatal is equivalent to Print() followed by a call to os.Exit(1).
+func Fatal(v ...interface{}) { std.Fatal(v...) }
+
+// Fatalf is equivalent to Printf() followed by a call to os.Exit(1).
+func Fatalf(format string, v ...interface{}) { std.Fatalf(format, v...) }
+
+// Fatalln is equivalent to Println() followed by a call to os.Exit(1).
+func Fatalln(v ...interface{}) { std.Fatalln(v...) }
+
+// Panic is equivalent to Print() followed by a call to panic().
+func Panic(v ...interface{}) { std.Panic(v...) }
+
+// Panicf is equivalent to Printf() followed by a call to panic().
+func Panicf(format string, v ...interface{}) { std.Panicf(format, v...) }
+
+// Panicln is equivalent to Println() followed by a call to panic().
+func Panicln(v ...interface{}) { std.Panicln(v...) }
+
+// Output writes the output for a logging event.
+func Output(calldepth int, s string) error {
+	return std.Output(calldepth+1, s)
+}
@@ -0,0 +1,216 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Package slog implements a subset of Go's log/slog package.
+//
+// The records go to the sink the host sets, with their level, message and
+// attributes, so they end up in the host's logging pipeline. If there's
+// none, they are written to os.Stderr as lines of text. There are no
+// Handlers, Groups or contexts, and the value of an Attr is an interface{}.
+package slog
+
+import (
+	"fmt"
+	"io"
+	"os"
+	"strconv"
+)
+
+var native ffiLog
+
+func init() {
+	native = ffi(ffiLog, "log")
+}
+
+type ffiLog interface {
+	log(level int, msg string, keys []string, values []interface{}) bool
+}
+
+// A Level is the importance or severity of a log event.
+// The higher the level, the more important or severe the event.
+type Level int
+
+// Names for common levels.
+const (
+	LevelDebug Level = -4
+	LevelInfo  Level = 0
+	LevelWarn  Level = 4
+	LevelError Level = 8
+)
+
+// String returns a name for the level. If the level has a name, then that
+// name in uppercase is returned. If the level is between named values, then
+// an integer is appended to the uppercased name, e.g. "WARN-2".
+func (l Level) String() string {
+	str := func(base string, val Level) string {
+		switch {
+		case val == 0:
+			return base
+		case val > 0:
+			return base + "+" + strconv.Itoa(int(val))
+		default:
+			return base + strconv.Itoa(int(val))
+		}
+	}
+	switch {
+	case l < LevelInfo:
+		return str("DEBUG", l-LevelDebug)
+	case l < LevelWarn:
+		return str("INFO", l-LevelInfo)
+	case l < LevelError:
+		return str("WARN", l-LevelWarn)
+	default:
+		return str("ERROR", l-LevelError)
+	}
+}
+
+// An Attr is a key-value pair.
+type Attr struct {
+	Key   string
+	Value interface{}
+}
+
+// String returns an Attr for a string value.
+func String(key, value string) Attr { return Attr{key, value} }
+
+// Int64 returns an Attr for an int64.
+func Int64(key string, value int64) Attr { return Attr{key, value} }
+
+// Int converts an int to an int64 and returns an Attr with that value.
+func Int(key string, value int) Attr { return Int64(key, int64(value)) }
+
+// Uint64 returns an Attr for a uint64.
+func Uint64(key string, v uint64) Attr { return Attr{key, v} }
+
+// Float64 returns an Attr for a floating-point number.
+func Float64(key string, v float64) Attr { return Attr{key, v} }
+
+// Bool returns an Attr for a bool.
+func Bool(key string, v bool) Attr { return Attr{key, v} }
+
+// Any returns an Attr for the supplied value.
+func Any(key string, value interface{}) Attr { return Attr{key, value} }
+
+// String returns a string of the form "key=value".
+func (a Attr) String() string {
+	return fmt.Sprintf("%s=%v", a.Key, a.Value)
+}
+
+const badKey = "!BADKEY"
+
+// argsToAttrs turns the arguments of the output methods into Attrs, an
+// argument is either an Attr, or a string key followed by its value.
+func argsToAttrs(args []interface{}) []Attr {
+	var attrs []Attr
+	for len(args) > 0 {
+		switch x := args[0].(type) {
+		case string:
+			if len(args) == 1 {
+				attrs = append(attrs, String(badKey, x))
+				args = nil
+			} else {
+				attrs = append(attrs, Any(x, args[1]))
+				args = args[2:]
+			}
+		case Attr:
+			attrs = append(attrs, x)
+			args = args[1:]
+		default:
+			attrs = append(attrs, Any(badKey, x))
+			args = args[1:]
+		}
+	}
+	return attrs
+}
+
+// A Logger records the messages of a program with its attributes.
+type Logger struct {
+	attrs []Attr
+}
+
+var defaultLogger = &Logger{}
+
+// the minimum level of the records that are output
+var logLevel = LevelInfo
+
+// Default returns the default Logger.
+func Default() *Logger { return defaultLogger }
+
+// SetDefault makes l the default Logger.
+func SetDefault(l *Logger) { defaultLogger = l }
+
+// SetLogLoggerLevel sets the minimum level of the records that are output,
+// LevelInfo by default, and returns the previous one.
+func SetLogLoggerLevel(level Level) (oldLevel Level) {
+	oldLevel = logLevel
+	logLevel = level
+	return
+}
+
+// With returns a Logger that includes the given attributes in each output
+// operation. Arguments are converted to attributes as if by Logger.Log.
+func (l *Logger) With(args ...interface{}) *Logger {
+	attrs := make([]Attr, 0, len(l.attrs)+len(args))
+	attrs = append(attrs, l.attrs...)
+	return &Logger{append(attrs, argsToAttrs(args)...)}
+}
+
+// Enabled reports whether l emits log records at the given level.
+func (l *Logger) Enabled(level Level) bool {
+	return level >= logLevel
+}
+
+// Log emits a log record with the given level and message. The args are
+// either Attrs, or string keys followed by their values, an argument that is
+// neither gets the key "!BADKEY".
+func (l *Logger) Log(level Level, msg string, args ...interface{}) {
+	if !l.Enabled(level) {
+		return
+	}
+	attrs := append(append([]Attr{}, l.attrs...), argsToAttrs(args)...)
+	keys := make([]string, len(attrs))
+	values := make([]interface{}, len(attrs))
+	for i, a := range attrs {
+		keys[i] = a.Key
+		values[i] = a.Value
+	}
+	if native.log(int(level), msg, keys, values) {
+		return
+	}
+	s := level.String() + " " + msg
+	for _, a := range attrs {
+		s += " " + a.String()
+	}
+	io.WriteString(os.Stderr, s+"\n")
+}
+
+// Debug logs at LevelDebug.
+func (l *Logger) Debug(msg string, args ...interface{}) { l.Log(LevelDebug, msg, args...) }
+
+// Info logs at LevelInfo.
+func (l *Logger) Info(msg string, args ...interface{}) { l.Log(LevelInfo, msg, args...) }
+
+// Warn logs at LevelWarn.
+func (l *Logger) Warn(msg string, args ...interface{}) { l.Log(LevelWarn, msg, args...) }
+
+// Error logs at LevelError.
+func (l *Logger) Error(msg string, args ...interface{}) { l.Log(LevelError, msg, args...) }
+
+// With calls Logger.With on the default logger.
+func With(args ...interface{}) *Logger { return Default().With(args...) }
+
+// Log calls Logger.Log on the default logger.
+func Log(level Level, msg string, args ...interface{}) { Default().Log(level, msg, args...) }
+
+// Debug calls Logger.Debug on the default logger.
+func Debug(msg string, args ...interface{}) { Default().Debug(msg, args...) }
+
+// Info calls Logger.Info on the default logger.
+func Info(msg string, args ...interface{}) { Default().Info(msg, args...) }
+
+// Warn calls Logger.Warn on the default logger.
+func Warn(msg string, args ...interface{}) { Default().Warn(msg, args...) }
+
+// Error calls Logger.Error on the default logger.
+func Error(msg string, args ...interface{}) { Default().Error(msg, args...) }
@@ -285,7 +285,15 @@ func read(p []byte, int63 func() int64, readVal *int64, readPos *int8) (n int, e
  * Top-level convenience functions
  */
 
-var globalRand = New(&lockedSource{src: NewSource(1).(Source64)})
+var native = ffi(ffiRand, "rand")
+
+type ffiRand interface {
+	seed() int64
+}
+
+// The default Source is seeded by the host, so that runs given the same seed
+// get the same numbers.
+var globalRand = New(&lockedSource{src: NewSource(native.seed()).(Source64)})
 
 // Seed uses the provided seed value to initialize the default Source to a
 // deterministic state. If Seed is not called, the generator behaves as
@@ -67,7 +67,9 @@ func Sinh(x float64) float64 {
 //	Cosh(±0) = 1
 //	Cosh(±Inf) = +Inf
 //	Cosh(NaN) = NaN
-func Cosh(x float64) float64
+func Cosh(x float64) float64 {
+	return cosh(x)
+}
 
 func cosh(x float64) float64 {
 	x = Abs(x)
@@ -0,0 +1,267 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//go:build http
+
+// Package http provides a subset of the HTTP client of Go's net/http.
+//
+// The requests are sent by the transport set by the host, which decides what
+// can be reached and how. Other goroutines keep running while a request is
+// pending. Without a transport, the requests fail with an error.
+//
+// Unlike Go, the URL of a Request is a string, as there is no net/url.
+package http
+
+import (
+	"bytes"
+	"errors"
+	"io"
+	"sort"
+	"strconv"
+	"strings"
+)
+
+var native ffiHttp
+
+func init() {
+	native = ffi(ffiHttp, "net.http")
+}
+
+type ffiHttp interface {
+	async_round_trip(method string, url string, header string, body []byte) (status int, respHeader string, respBody string, err string)
+}
+
+// Common HTTP methods.
+const (
+	MethodGet     = "GET"
+	MethodHead    = "HEAD"
+	MethodPost    = "POST"
+	MethodPut     = "PUT"
+	MethodPatch   = "PATCH"
+	MethodDelete  = "DELETE"
+	MethodConnect = "CONNECT"
+	MethodOptions = "OPTIONS"
+	MethodTrace   = "TRACE"
+)
+
+// A Request represents an HTTP request to be sent by a client.
+type Request struct {
+	// Method specifies the HTTP method (GET, POST, PUT, etc.).
+	// An empty string means GET.
+	Method string
+
+	// URL specifies the URI being requested.
+	URL string
+
+	// Header contains the request header fields.
+	Header Header
+
+	// Body is the request's body, nil means no body.
+	Body io.ReadCloser
+
+	// ContentLength records the length of the body, if known.
+	ContentLength int64
+}
+
+// NewRequest returns a new Request given a method, URL, and optional body.
+//
+// If the provided body is also an io.Closer, the Client closes it after
+// the request is sent.
+func NewRequest(method, url string, body io.Reader) (*Request, error) {
+	if method == "" {
+		method = MethodGet
+	}
+	if !isToken(method) {
+		return nil, errors.New("net/http: invalid method " + strconv.Quote(method))
+	}
+	req := &Request{
+		Method: method,
+		URL:    url,
+		Header: make(Header),
+	}
+	if body != nil {
+		rc, ok := body.(io.ReadCloser)
+		if !ok {
+			rc = nopCloser{body}
+		}
+		req.Body = rc
+		switch v := body.(type) {
+		case *bytes.Buffer:
+			req.ContentLength = int64(v.Len())
+		case *bytes.Reader:
+			req.ContentLength = int64(v.Len())
+		case *strings.Reader:
+			req.ContentLength = int64(v.Len())
+		}
+	}
+	return req, nil
+}
+
+// isToken reports whether s is a token, as methods and header field names are
+func isToken(s string) bool {
+	for i := 0; i < len(s); i++ {
+		if !validHeaderFieldByte(s[i]) {
+			return false
+		}
+	}
+	return len(s) > 0
+}
+
+// Response represents the response from an HTTP request.
+type Response struct {
+	Status     string // e.g. "200 OK"
+	StatusCode int    // e.g. 200
+
+	// Header maps header keys to values.
+	Header Header
+
+	// Body represents the response body, it is never nil.
+	Body io.ReadCloser
+
+	// ContentLength records the length of the body.
+	ContentLength int64
+
+	// Request is the request that was sent to obtain this Response.
+	Request *Request
+}
+
+// A Client is an HTTP client. Its zero value (DefaultClient) is a usable
+// client that uses the transport of the host.
+type Client struct{}
+
+// DefaultClient is the default Client and is used by Get and Post.
+var DefaultClient = &Client{}
+
+// urlError reports an error and the operation and URL that caused it, like
+// url.Error in Go.
+type urlError struct {
+	Op  string
+	URL string
+	Err error
+}
+
+func (e *urlError) Unwrap() error { return e.Err }
+func (e *urlError) Error() string { return e.Op + " " + strconv.Quote(e.URL) + ": " + e.Err.Error() }
+
+// Do sends an HTTP request and returns an HTTP response.
+//
+// An error is returned if the transport fails to make the request, a non-2xx
+// status code doesn't cause an error. The request Body, if non-nil, is
+// closed.
+func (c *Client) Do(req *Request) (*Response, error) {
+	method := req.Method
+	if method == "" {
+		method = MethodGet
+	}
+	op := method[:1] + strings.ToLower(method[1:])
+	uerr := func(err error) (*Response, error) {
+		return nil, &urlError{Op: op, URL: req.URL, Err: err}
+	}
+
+	var body []byte
+	if req.Body != nil {
+		var buf bytes.Buffer
+		_, err := buf.ReadFrom(req.Body)
+		req.Body.Close()
+		if err != nil {
+			return uerr(err)
+		}
+		body = buf.Bytes()
+	}
+	if !strings.HasPrefix(req.URL, "http://") && !strings.HasPrefix(req.URL, "https://") {
+		scheme := ""
+		if i := strings.Index(req.URL, "://"); i >= 0 {
+			scheme = req.URL[:i]
+		}
+		return uerr(errors.New("unsupported protocol scheme " + strconv.Quote(scheme)))
+	}
+	header, err := formatHeader(req.Header)
+	if err != nil {
+		return uerr(err)
+	}
+
+	status, respHeader, respBody, msg := native.async_round_trip(method, req.URL, header, body)
+	if msg != "" {
+		return uerr(errors.New(msg))
+	}
+	resp := &Response{
+		Status:        strconv.Itoa(status) + " " + StatusText(status),
+		StatusCode:    status,
+		Header:        parseHeader(respHeader),
+		Body:          nopCloser{strings.NewReader(respBody)},
+		ContentLength: int64(len(respBody)),
+		Request:       req,
+	}
+	return resp, nil
+}
+
+type nopCloser struct {
+	io.Reader
+}
+
+func (nopCloser) Close() error { return nil }
+
+// Get issues a GET to the specified URL.
+func (c *Client) Get(url string) (*Response, error) {
+	req, err := NewRequest(MethodGet, url, nil)
+	if err != nil {
+		return nil, err
+	}
+	return c.Do(req)
+}
+
+// Post issues a POST to the specified URL.
+//
+// If the provided body is an io.Closer, it is closed after the
+// request.
+func (c *Client) Post(url, contentType string, body io.Reader) (*Response, error) {
+	req, err := NewRequest(MethodPost, url, body)
+	if err != nil {
+		return nil, err
+	}
+	req.Header.Set("Content-Type", contentType)
+	return c.Do(req)
+}
+
+// Get issues a GET to the specified URL with the DefaultClient.
+func Get(url string) (*Response, error) {
+	return DefaultClient.Get(url)
+}
+
+// Post issues a POST to the specified URL with the DefaultClient.
+func Post(url, contentType string, body io.Reader) (*Response, error) {
+	return DefaultClient.Post(url, contentType, body)
+}
+
+// the header crosses the FFI as "Key: Value\n" lines, in the order of the keys
+func formatHeader(h Header) (string, error) {
+	keys := make([]string, 0, len(h))
+	for k := range h {
+		keys = append(keys, k)
+	}
+	sort.Strings(keys)
+	var b strings.Builder
+	for _, k := range keys {
+		if !isToken(k) {
+			return "", errors.New("net/http: invalid header field name " + strconv.Quote(k))
+		}
+		for _, v := range h[k] {
+			if strings.ContainsAny(v, "\r\n") {
+				return "", errors.New("net/http: invalid header field value for " + strconv.Quote(k))
+			}
+			b.WriteString(k + ": " + v + "\n")
+		}
+	}
+	return b.String(), nil
+}
+
+func parseHeader(s string) Header {
+	h := make(Header)
+	for _, line := range strings.Split(s, "\n") {
+		if i := strings.Index(line, ": "); i > 0 {
+			h.Add(line[:i], line[i+2:])
+		}
+	}
+	return h
+}
@@ -381,7 +381,7 @@ func (t reflectType) Size() uintptr {
 }
 
 func (t reflectType) String() string {
-	return t.kind.String()
+	return native.type_string(t.typePtr)
 }
 
 func (t reflectType) Kind() Kind {
@@ -417,11 +417,19 @@ func (t reflectType) IsVariadic() bool {
 }
 
 func (t reflectType) Elem() Type {
-	panic("not implemented")
+	p, k := native.type_elem(t.typePtr)
+	return reflectType{p, Kind(k)}
 }
 
 func (t reflectType) Field(i int) StructField {
-	panic("not implemented")
+	name, tag, embedded, p, k := native.type_field(t.typePtr, i)
+	return StructField{
+		Name:      name,
+		Type:      reflectType{p, Kind(k)},
+		Tag:       StructTag(tag),
+		Index:     []int{i},
+		Anonymous: embedded,
+	}
 }
 
 func (t reflectType) FieldByIndex(index []int) StructField {
@@ -449,7 +457,7 @@ func (t reflectType) Len() int {
 }
 
 func (t reflectType) NumField() int {
-	panic("not implemented")
+	return native.type_num_field(t.typePtr)
 }
 
 func (t reflectType) NumIn() int {
@@ -17,12 +17,17 @@ func init() {
 type ffiReflect interface {
 	value_of(i interface{}) unsafe.Pointer
 	type_of(p unsafe.Pointer) (unsafe.Pointer, uint)
+	type_string(t unsafe.Pointer) string
+	type_elem(t unsafe.Pointer) (unsafe.Pointer, uint)
+	type_num_field(t unsafe.Pointer) int
+	type_field(t unsafe.Pointer, i int) (string, string, bool, unsafe.Pointer, uint)
 
 	bool_val(p unsafe.Pointer) bool
 	int_val(p unsafe.Pointer) int64
 	uint_val(p unsafe.Pointer) uint64
 	float_val(p unsafe.Pointer) float64
 	bytes_val(p unsafe.Pointer) []byte
+	string_val(p unsafe.Pointer) string
 	elem(p unsafe.Pointer) unsafe.Pointer
 	num_field(p unsafe.Pointer) int
 	field(p unsafe.Pointer, i int) unsafe.Pointer
@@ -37,6 +42,8 @@ type ffiReflect interface {
 
 	can_addr(p unsafe.Pointer) bool
 	can_set(p unsafe.Pointer) bool
+	can_interface(p unsafe.Pointer) bool
+	interface_val(p unsafe.Pointer) interface{}
 	set(p unsafe.Pointer, v unsafe.Pointer)
 	set_bool(p unsafe.Pointer, b bool)
 	set_bytes(p unsafe.Pointer, x []byte)
@@ -216,7 +223,7 @@ func (v Value) Int() int64 {
 
 // CanInterface reports whether Interface can be used without panicking.
 func (v Value) CanInterface() bool {
-	return false
+	return native.can_interface(v.ptr)
 }
 
 // Interface returns v's current value as an interface{}.
@@ -225,7 +232,10 @@ func (v Value) CanInterface() bool {
 // It panics if the Value was obtained by accessing
 // unexported struct fields.
 func (v Value) Interface() (i interface{}) {
-	panic("not implemented")
+	if !v.CanInterface() {
+		panic("reflect.Value.Interface: cannot return value obtained from unexported field or method")
+	}
+	return native.interface_val(v.ptr)
 }
 
 // InterfaceData returns the interface v's value as a uintptr pair.
@@ -382,6 +392,9 @@ func (v Value) OverflowUint(x uint64) bool {
 // Unlike in Go, there is nothing much you can do with uintptr
 // other than using it as a handle or printing it out
 func (v Value) Pointer() uintptr {
+	if v.IsNil() {
+		return 0
+	}
 	return uintptr(v.ptr)
 }
 
@@ -501,7 +514,10 @@ func (v Value) Slice3(i, j, k int) Value {
 // The fmt package treats Values specially. It does not call their String
 // method implicitly but instead prints the concrete values they hold.
 func (v Value) String() string {
-	panic("not implemented")
+	if v.Kind() != String {
+		return "<" + v.Type().String() + " Value>"
+	}
+	return native.string_val(v.ptr)
 }
 
 // TryRecv attempts to receive a value from the channel v but will not block.
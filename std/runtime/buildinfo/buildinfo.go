@@ -0,0 +1,78 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Package buildinfo tells scripts what runs them, the version and the
+// features of goscript and what the application embedding it says about
+// itself, for them to detect the features they can use.
+package buildinfo
+
+import "strings"
+
+var native ffiBuildInfo
+
+type ffiBuildInfo interface {
+	version() string
+	features() string
+	app_version() string
+	settings_len() int
+	setting(i int) (string, string)
+}
+
+// Version is the version of the goscript VM.
+var Version string
+
+// Features are the cargo features goscript was built with that make a
+// difference to scripts, e.g. "async" or "http".
+var Features []string
+
+// AppVersion is the version of the application, as set by it.
+var AppVersion string
+
+// A Setting is a key-value pair the application sets, e.g. one of its
+// feature flags.
+type Setting struct {
+	Key   string
+	Value string
+}
+
+// Settings are the ones of the application, in the order it sets them.
+var Settings []Setting
+
+func init() {
+	native = ffi(ffiBuildInfo, "runtime.buildinfo")
+
+	Version = native.version()
+	if f := native.features(); f != "" {
+		Features = strings.Split(f, ",")
+	}
+	AppVersion = native.app_version()
+	n := native.settings_len()
+	if n > 0 {
+		Settings = make([]Setting, n)
+		for i := range Settings {
+			Settings[i].Key, Settings[i].Value = native.setting(i)
+		}
+	}
+}
+
+// HasFeature reports whether goscript was built with the named feature.
+func HasFeature(name string) bool {
+	for _, f := range Features {
+		if f == name {
+			return true
+		}
+	}
+	return false
+}
+
+// Lookup returns the value of the last setting of the application with the
+// key, and whether there is one.
+func Lookup(key string) (value string, ok bool) {
+	for _, s := range Settings {
+		if s.Key == key {
+			value, ok = s.Value, true
+		}
+	}
+	return
+}
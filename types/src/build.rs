@@ -0,0 +1,304 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+//
+//
+// This code is adapted from the offical Go code written in Go
+// with license as follows:
+// Copyright 2011 The Go Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Conditional compilation: the files of a package directory can be excluded
+//! by `_GOOS`/`_GOARCH` suffixes in their names (e.g. `shim_wasm.gos`,
+//! `shim_linux_amd64.gos`), or by a `//go:build` line before the package clause.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+const KNOWN_OS: &[&str] = &[
+    "aix", "android", "darwin", "dragonfly", "freebsd", "hurd", "illumos", "ios", "js", "linux",
+    "nacl", "netbsd", "openbsd", "plan9", "solaris", "wasip1", "windows", "zos",
+];
+
+const UNIX_OS: &[&str] = &[
+    "aix", "android", "darwin", "dragonfly", "freebsd", "hurd", "illumos", "ios", "linux",
+    "netbsd", "openbsd", "solaris",
+];
+
+const KNOWN_ARCH: &[&str] = &[
+    "386", "amd64", "arm", "arm64", "loong64", "mips", "mipsle", "mips64", "mips64le", "ppc64",
+    "ppc64le", "riscv64", "s390x", "wasm",
+];
+
+/// BuildContext decides which files of a package directory are loaded.
+#[derive(Clone, Debug)]
+pub struct BuildContext {
+    pub goos: String,
+    pub goarch: String,
+    /// additional tags satisfied by `//go:build` lines
+    pub tags: Vec<String>,
+}
+
+impl Default for BuildContext {
+    /// The context of the host, in Go's naming.
+    fn default() -> BuildContext {
+        let goos = match std::env::consts::OS {
+            "macos" => "darwin",
+            "unknown" | "" if std::env::consts::ARCH == "wasm32" => "js",
+            os => os,
+        };
+        let goarch = match std::env::consts::ARCH {
+            "x86" => "386",
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "wasm32" => "wasm",
+            "powerpc64" => "ppc64",
+            "loongarch64" => "loong64",
+            arch => arch,
+        };
+        BuildContext {
+            goos: goos.to_owned(),
+            goarch: goarch.to_owned(),
+            tags: vec![],
+        }
+    }
+}
+
+impl BuildContext {
+    pub fn new(goos: &str, goarch: &str, tags: Vec<String>) -> BuildContext {
+        BuildContext {
+            goos: goos.to_owned(),
+            goarch: goarch.to_owned(),
+            tags,
+        }
+    }
+
+    /// Reports whether the tag is satisfied, "goscript" always is.
+    pub fn match_tag(&self, tag: &str) -> bool {
+        tag == "goscript"
+            || tag == self.goos
+            || tag == self.goarch
+            || self.tags.iter().any(|x| x == tag)
+            || (tag == "unix" && UNIX_OS.contains(&self.goos.as_str()))
+            || (tag == "linux" && self.goos == "android")
+            || (tag == "darwin" && self.goos == "ios")
+    }
+
+    /// Reports whether a file with the given name should be loaded according
+    /// to its `_GOOS`, `_GOARCH` or `_GOOS_GOARCH` suffix.
+    pub fn match_file_name(&self, name: &str) -> bool {
+        let name = name.split('.').next().unwrap();
+        let name = match name.find('_') {
+            Some(i) => &name[i..],
+            None => return true,
+        };
+        let mut parts: Vec<&str> = name.split('_').collect();
+        if parts.last() == Some(&"test") {
+            parts.pop();
+        }
+        let n = parts.len();
+        if n >= 2 && KNOWN_OS.contains(&parts[n - 2]) && KNOWN_ARCH.contains(&parts[n - 1]) {
+            return self.match_tag(parts[n - 2]) && self.match_tag(parts[n - 1]);
+        }
+        if n >= 1 && (KNOWN_OS.contains(&parts[n - 1]) || KNOWN_ARCH.contains(&parts[n - 1])) {
+            return self.match_tag(parts[n - 1]);
+        }
+        true
+    }
+
+    /// Reports whether the `//go:build` line of the file, if any, is satisfied.
+    /// Only the comments before the package clause are looked at.
+    pub fn match_content(&self, content: &str) -> Result<bool, String> {
+        let mut in_comment = false;
+        for line in content.lines() {
+            let line = line.trim();
+            if in_comment {
+                if let Some(i) = line.find("*/") {
+                    in_comment = false;
+                    if !line[i + 2..].trim().is_empty() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            } else if let Some(expr) = line.strip_prefix("//go:build") {
+                if !expr.is_empty() && !expr.starts_with(char::is_whitespace) {
+                    continue;
+                }
+                return ConstraintParser::new(expr).parse().map(|x| x.eval(self));
+            } else if line.starts_with("//") {
+                continue;
+            } else if let Some(rest) = line.strip_prefix("/*") {
+                if !rest.contains("*/") {
+                    in_comment = true;
+                }
+                continue;
+            }
+            break;
+        }
+        Ok(true)
+    }
+}
+
+/// A parsed `//go:build` expression.
+#[derive(Debug)]
+enum Constraint {
+    Tag(String),
+    Not(Box<Constraint>),
+    And(Box<Constraint>, Box<Constraint>),
+    Or(Box<Constraint>, Box<Constraint>),
+}
+
+impl Constraint {
+    fn eval(&self, ctx: &BuildContext) -> bool {
+        match self {
+            Constraint::Tag(t) => ctx.match_tag(t),
+            Constraint::Not(x) => !x.eval(ctx),
+            Constraint::And(x, y) => x.eval(ctx) && y.eval(ctx),
+            Constraint::Or(x, y) => x.eval(ctx) || y.eval(ctx),
+        }
+    }
+}
+
+struct ConstraintParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> ConstraintParser<'a> {
+    fn new(s: &'a str) -> ConstraintParser<'a> {
+        ConstraintParser {
+            chars: s.chars().peekable(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Constraint, String> {
+        let x = self.or()?;
+        match self.peek() {
+            None => Ok(x),
+            Some(c) => Err(format!("unexpected '{}' in //go:build line", c)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Constraint, String> {
+        let mut x = self.and()?;
+        while self.eat("||")? {
+            x = Constraint::Or(Box::new(x), Box::new(self.and()?));
+        }
+        Ok(x)
+    }
+
+    fn and(&mut self) -> Result<Constraint, String> {
+        let mut x = self.not()?;
+        while self.eat("&&")? {
+            x = Constraint::And(Box::new(x), Box::new(self.not()?));
+        }
+        Ok(x)
+    }
+
+    fn not(&mut self) -> Result<Constraint, String> {
+        match self.peek() {
+            Some('!') => {
+                self.chars.next();
+                Ok(Constraint::Not(Box::new(self.not()?)))
+            }
+            Some('(') => {
+                self.chars.next();
+                let x = self.or()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(x)
+                    }
+                    _ => Err("missing ) in //go:build line".to_owned()),
+                }
+            }
+            _ => self.tag(),
+        }
+    }
+
+    fn tag(&mut self) -> Result<Constraint, String> {
+        let mut tag = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' || c == '.' {
+                tag.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        if tag.is_empty() {
+            Err("missing build tag in //go:build line".to_owned())
+        } else {
+            Ok(Constraint::Tag(tag))
+        }
+    }
+
+    /// Consumes the two-char operator `op` if it's next.
+    fn eat(&mut self, op: &str) -> Result<bool, String> {
+        let first = op.chars().next();
+        if self.peek() != first {
+            return Ok(false);
+        }
+        self.chars.next();
+        match self.chars.next() {
+            Some(c) if Some(c) == op.chars().nth(1) => Ok(true),
+            _ => Err(format!("invalid operator in //go:build line, expecting {}", op)),
+        }
+    }
+
+    /// Skips spaces and returns the next char.
+    fn peek(&mut self) -> Option<char> {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+        self.chars.peek().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_match_file_name() {
+        let ctx = BuildContext::new("linux", "amd64", vec![]);
+        assert!(ctx.match_file_name("shim.gos"));
+        assert!(ctx.match_file_name("shim_native.gos"));
+        assert!(ctx.match_file_name("shim_linux.gos"));
+        assert!(ctx.match_file_name("shim_amd64.gos"));
+        assert!(ctx.match_file_name("shim_linux_amd64.gos"));
+        assert!(!ctx.match_file_name("shim_wasm.gos"));
+        assert!(!ctx.match_file_name("shim_js_wasm.gos"));
+        assert!(!ctx.match_file_name("shim_linux_arm64.gos"));
+        assert!(!ctx.match_file_name("shim_windows_test.gos"));
+        assert!(!ctx.match_file_name("linux_windows.gos"));
+        assert!(ctx.match_file_name("windows.gos"));
+    }
+
+    #[test]
+    fn test_match_content() {
+        let ctx = BuildContext::new("js", "wasm", vec!["debug".to_owned()]);
+        let src = |c: &str| format!("// Copyright\n\n{}\n\npackage foo\n", c);
+        assert_eq!(ctx.match_content(&src("//go:build wasm")), Ok(true));
+        assert_eq!(ctx.match_content(&src("//go:build !wasm")), Ok(false));
+        assert_eq!(ctx.match_content(&src("//go:build linux || js")), Ok(true));
+        assert_eq!(ctx.match_content(&src("//go:build js && !debug")), Ok(false));
+        assert_eq!(
+            ctx.match_content(&src("//go:build (linux || js) && (debug || release)")),
+            Ok(true)
+        );
+        assert_eq!(ctx.match_content(&src("//go:build goscript")), Ok(true));
+        assert_eq!(ctx.match_content(&src("//go:buildx linux")), Ok(true));
+        assert_eq!(ctx.match_content(&src("/* a\nb */\n//go:build linux")), Ok(false));
+        assert_eq!(
+            ctx.match_content("package foo\n\n//go:build linux\n"),
+            Ok(true)
+        );
+        assert!(ctx.match_content(&src("//go:build linux &")).is_err());
+        assert!(ctx.match_content(&src("//go:build (linux")).is_err());
+        assert!(ctx.match_content(&src("//go:build")).is_err());
+    }
+}
@@ -293,7 +293,17 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         };
 
         match result {
-            UnpackResult::Error => invalidate_lhs(),
+            UnpackResult::Error => {
+                invalidate_lhs();
+                // the error is reported already, likely a syntax error in
+                // rhs, avoid follow-on "declared but not used" errors
+                for okey in lhs.iter() {
+                    self.lobj_mut(*okey)
+                        .entity_type_mut()
+                        .var_property_mut()
+                        .used = true;
+                }
+            }
             UnpackResult::Tuple(_, _, _)
             | UnpackResult::CommaOk(_, _)
             | UnpackResult::Mutliple(_, _)
@@ -203,7 +203,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                         if detail.info() == BasicInfo::IsString {
                             if let OperandMode::Constant(v) = &x.mode {
                                 OperandMode::Constant(Value::with_u64(
-                                    v.str_as_string().len() as u64
+                                    v.str_as_bytes().len() as u64
                                 ))
                             } else {
                                 OperandMode::Value
@@ -253,6 +253,25 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     _ => record(self, x.typ, &vec![ty], false),
                 }
             }
+            Builtin::Clear => {
+                // clear(m)
+                // clear(s)
+                let tkey = x.typ.unwrap();
+                match self.otype(tkey).underlying_val(self.tc_objs) {
+                    Type::Map(_) | Type::Slice(_) => {
+                        x.mode = OperandMode::NoValue;
+                        record(self, None, &vec![tkey], false);
+                    }
+                    _ => {
+                        let xd = self.new_dis(x);
+                        self.invalid_arg(
+                            xd.pos(),
+                            &format!("cannot clear {}: argument must be a map or a slice", xd),
+                        );
+                        return false;
+                    }
+                }
+            }
             Builtin::Close => {
                 // close(c)
                 let tkey = typ::underlying_type(x.typ.unwrap(), self.tc_objs);
@@ -582,24 +601,22 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     return false;
                 }
 
-                // constant integer arguments, if any
-                let sizes: Vec<u64> = call.args[1..]
+                // the size arguments, with the values of the constant ones
+                let sizes: Vec<Option<u64>> = call.args[1..]
                     .iter()
-                    .filter_map(|x| {
-                        if let Ok(i) = self.index(x, None, fctx) {
-                            return i;
-                        }
-                        None
-                    })
+                    .map(|x| self.index(x, None, fctx).ok().flatten())
                     .collect();
-                if sizes.len() == 2 && sizes[0] > sizes[1] {
-                    let pos = call.args[1].pos(self.ast_objs);
-                    self.invalid_arg(pos, "length and capacity swapped");
-                    // safe to continue
+                if let [Some(len), Some(cap)] = sizes[..] {
+                    if len > cap {
+                        let pos = call.args[1].pos(self.ast_objs);
+                        self.invalid_arg(pos, "length and capacity swapped");
+                        // safe to continue
+                    }
                 }
                 x.mode = OperandMode::Value;
                 x.typ = Some(arg0t);
 
+                // all the size arguments are passed on, constant or not
                 let int_type = self.basic_type(BasicType::Int);
                 record(
                     self,
@@ -608,6 +625,84 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     false,
                 );
             }
+            Builtin::Max | Builtin::Min => {
+                // max(x, ...)
+                // min(x, ...)
+                let op = if id == Builtin::Min {
+                    Token::LSS
+                } else {
+                    Token::GTR
+                };
+                let not_ordered = |c: &Checker<S>, a: &Operand| {
+                    let ad = c.new_dis(a);
+                    c.invalid_arg(ad.pos(), &format!("{} cannot be ordered", ad));
+                    false
+                };
+                if !typ::is_ordered(x.typ.unwrap(), self.tc_objs) {
+                    return not_ordered(self, x);
+                }
+                for i in 1..nargs {
+                    let mut a = Operand::new();
+                    unpack_result.as_ref().unwrap().get(self, &mut a, i, fctx);
+                    if a.invalid() {
+                        return false;
+                    }
+                    if !typ::is_ordered(a.typ.unwrap(), self.tc_objs) {
+                        return not_ordered(self, &a);
+                    }
+
+                    // match the types as in a binary operation
+                    self.convert_untyped(x, a.typ.unwrap(), fctx);
+                    if x.invalid() {
+                        return false;
+                    }
+                    self.convert_untyped(&mut a, x.typ.unwrap(), fctx);
+                    if a.invalid() {
+                        return false;
+                    }
+                    if !typ::identical_o(x.typ, a.typ, self.tc_objs) {
+                        self.invalid_arg(
+                            a.pos(self.ast_objs),
+                            &format!(
+                                "mismatched types {} (previous argument) and {} (type of {})",
+                                self.new_dis(x.typ.as_ref().unwrap()),
+                                self.new_dis(a.typ.as_ref().unwrap()),
+                                self.new_dis(&a)
+                            ),
+                        );
+                        return false;
+                    }
+
+                    match (&x.mode, &a.mode) {
+                        (OperandMode::Constant(vx), OperandMode::Constant(va)) => {
+                            if Value::compare(va, &op, vx) {
+                                *x = a;
+                            }
+                        }
+                        _ => x.mode = OperandMode::Value,
+                    }
+                }
+
+                // the result of a non-constant call is typed, as with a single
+                // non-constant argument
+                if x.mode.constant_val().is_none() {
+                    x.mode = OperandMode::Value;
+                    let msg = format!("argument to {}", binfo.name);
+                    self.assignment(x, None, &msg, fctx);
+                    if x.invalid() {
+                        return false;
+                    }
+                }
+
+                // all the arguments have the final type
+                let t = x.typ.unwrap();
+                for arg in call.args.iter() {
+                    self.update_expr_type(arg, t, true, fctx);
+                }
+                if x.mode.constant_val().is_none() {
+                    record(self, x.typ, &vec![t; nargs], false);
+                }
+            }
             Builtin::New => {
                 // new(T)
                 // (no argument evaluated yet)
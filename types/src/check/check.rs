@@ -11,7 +11,9 @@
 
 #![allow(dead_code)]
 use super::super::constant::Value;
-use super::super::importer::{ImportKey, Importer, SourceRead, TraceConfig};
+use super::super::importer::{
+    ImportKey, Importer, Severity, SourceRead, TraceConfig, DEFAULT_MAX_DEPTH,
+};
 use super::super::objects::{DeclInfoKey, ObjKey, PackageKey, ScopeKey, TCObjects, TypeKey};
 use super::super::operand::OperandMode;
 use super::super::selection::Selection;
@@ -124,6 +126,10 @@ pub struct TypeInfo {
     /// in source order. Variables without an initialization expression do not
     /// appear in this list.
     pub init_order: Vec<Initializer>,
+    /// 'spans' holds the start and end positions of the expressions in 'types',
+    /// sorted by start, the outer expressions first. It's only filled with
+    /// `TraceConfig::record_spans`, see `type_at`.
+    pub spans: Vec<(Pos, Pos, NodeId)>,
     /// oxfeeefeee: parse result of the package, to be used by code gen
     pub ast_files: Vec<ast::File>,
 }
@@ -138,9 +144,51 @@ impl TypeInfo {
             selections: Map::new(),
             scopes: Map::new(),
             init_order: Vec::new(),
+            spans: Vec::new(),
             ast_files: Vec::new(),
         }
     }
+
+    /// Returns the innermost expression containing `pos` that has a type,
+    /// with its type and value. Expressions nest, so it's the last one
+    /// starting at or before `pos` that hasn't ended.
+    pub fn type_at(&self, pos: Pos) -> Option<(NodeId, &TypeAndValue)> {
+        let i = self.spans.partition_point(|(start, _, _)| *start <= pos);
+        self.spans[..i]
+            .iter()
+            .rev()
+            .filter(|(_, end, _)| pos < *end)
+            .find_map(|(_, _, id)| self.types.get(id).map(|tv| (id.clone(), tv)))
+    }
+
+    /// Returns the identifier at `pos` if it declares something, with the
+    /// object it declares, see 'defs'.
+    pub fn def_at(&self, pos: Pos, objs: &AstObjects) -> Option<(IdentKey, Option<ObjKey>)> {
+        self.defs
+            .iter()
+            .find(|(ikey, _)| {
+                let ident = &objs.idents[**ikey];
+                ident.pos <= pos && pos < ident.end()
+            })
+            .map(|(ikey, okey)| (*ikey, *okey))
+    }
+
+    /// Returns the identifier at `pos` if it refers to an object, with the
+    /// object, see 'uses'.
+    pub fn use_at(&self, pos: Pos, objs: &AstObjects) -> Option<(IdentKey, ObjKey)> {
+        self.uses
+            .iter()
+            .find(|(ikey, _)| {
+                let ident = &objs.idents[**ikey];
+                ident.pos <= pos && pos < ident.end()
+            })
+            .map(|(ikey, okey)| (*ikey, *okey))
+    }
+
+    /// Returns the object implicitly declared by `node`, see 'implicits'.
+    pub fn implicit_for(&self, node: &impl Node) -> Option<ObjKey> {
+        self.implicits.get(&node.id()).copied()
+    }
 }
 
 /// ExprInfo stores information about an untyped expression.
@@ -228,6 +276,12 @@ pub struct Checker<'a, S: SourceRead> {
     pub result: TypeInfo,
     // for debug
     pub indent: Rc<RefCell<usize>>,
+    // packages being checked that led to this one, ending with this package
+    pub import_chain: Vec<PackageKey>,
+    // how deep the expression or the type being checked is nested
+    pub depth: usize,
+    // whether the one being checked is reported as too deep
+    too_deep: bool,
 }
 
 impl ObjContext {
@@ -327,6 +381,10 @@ impl TypeInfo {
         self.record_type_and_value_with_id(e.id(), mode, typ);
     }
 
+    pub fn record_span(&mut self, e: &Expr, objs: &AstObjects) {
+        self.spans.push((e.pos(objs), e.end(objs), e.id()));
+    }
+
     pub fn record_type_and_value_with_id(&mut self, id: NodeId, mode: OperandMode, typ: TypeKey) {
         if let OperandMode::Invalid = mode {
             return;
@@ -423,6 +481,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             reader: reader,
             result: TypeInfo::new(),
             indent: Rc::new(RefCell::new(0)),
+            import_chain: vec![pkg],
+            depth: 0,
+            too_deep: false,
         }
     }
 
@@ -435,6 +496,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         self.init_order();
         self.unused_imports(fctx);
         self.record_untyped(fctx);
+        // an expression may be checked more than once
+        let spans = &mut self.result.spans;
+        spans.sort_by_key(|(start, end, _)| (*start, std::cmp::Reverse(*end)));
+        spans.dedup_by(|a, b| a.2 == b.2);
 
         std::mem::swap(&mut self.result.ast_files, &mut files);
         self.all_results.insert(self.pkg, self.result);
@@ -463,6 +528,39 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         self.trace_config.trace_checker
     }
 
+    /// Reports whether the expression or the type being checked is nested
+    /// deeper than `TraceConfig::max_depth`, with the error `msg` at `pos`
+    /// once for the outermost one.
+    pub fn too_deep(&mut self, pos: Pos, msg: &str) -> bool {
+        let max = self.trace_config.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if self.depth <= max {
+            return false;
+        }
+        if !self.too_deep {
+            self.too_deep = true;
+            self.error_str(pos, msg);
+        }
+        true
+    }
+
+    #[inline]
+    pub fn records_spans(&self) -> bool {
+        self.trace_config.record_spans
+    }
+
+    /// Records the span of `e` for `TypeInfo::type_at` if asked to.
+    pub fn record_span(&mut self, e: &Expr) {
+        if self.records_spans() {
+            self.result.record_span(e, self.ast_objs);
+        }
+    }
+
+    /// Leaves a nested expression or type, see `too_deep`.
+    pub fn leave_nested(&mut self) {
+        self.depth -= 1;
+        self.too_deep &= self.depth > 0;
+    }
+
     pub fn new_importer(&mut self, pos: Pos) -> Importer<S> {
         Importer::new(
             self.trace_config,
@@ -475,6 +573,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             self.errors,
             pos,
         )
+        .with_import_chain(self.import_chain.clone())
     }
 
     /// check files' package name
@@ -521,6 +620,25 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         self.error_impl(pos, err.to_string(), true);
     }
 
+    pub fn unused_import_error(&self, pos: Pos, err: String) {
+        self.unused_error_impl(self.trace_config.unused_imports, pos, err);
+    }
+
+    pub fn unused_var_error(&self, pos: Pos, err: String) {
+        self.unused_error_impl(self.trace_config.unused_vars, pos, err);
+    }
+
+    fn unused_error_impl(&self, severity: Severity, pos: Pos, err: String) {
+        match severity {
+            Severity::Error => self.error_impl(pos, err, true),
+            Severity::Warning => {
+                let file = self.fset.file(pos).unwrap();
+                FilePosErrors::new(file, &self.trace_config.warnings).add(pos, err, true);
+            }
+            Severity::Ignore => {}
+        }
+    }
+
     fn error_impl(&self, pos: Pos, err: String, soft: bool) {
         let file = self.fset.file(pos).unwrap();
         FilePosErrors::new(file, self.errors).add(pos, err, soft);
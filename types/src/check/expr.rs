@@ -600,24 +600,30 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             return;
         }
 
-        // spec: "The right operand in a shift expression must have unsigned
-        // integer type or be an untyped constant representable by a value of
-        // type uint."
+        // spec: "The right operand in a shift expression must have integer
+        // type or be an untyped constant representable by a value of type
+        // uint."
+        if let OperandMode::Constant(v) = &y.mode {
+            // a good error message for negative shift counts, consider -1
+            // and -1.0, but not -1.1
+            let yval = v.to_int();
+            if yval.is_int() && yval.sign() < 0 {
+                let yd = self.new_dis(y);
+                self.invalid_op(yd.pos(), &format!("negative shift count {}", yd));
+                x.mode = OperandMode::Invalid;
+                return;
+            }
+        }
         let ytval = self.otype(y.typ.unwrap());
-        if ytval.is_unsigned(o) {
-            //ok
-        } else if ytval.is_untyped(o) {
+        if ytval.is_untyped(o) {
             self.convert_untyped(y, self.basic_type(BasicType::Uint), fctx);
             if y.invalid() {
                 x.mode = OperandMode::Invalid;
                 return;
             }
-        } else {
+        } else if !ytval.is_integer(o) {
             let yd = self.new_dis(y);
-            self.error(
-                yd.pos(),
-                format!("shift count {} must be unsigned integer", yd),
-            );
+            self.error(yd.pos(), format!("shift count {} must be integer", yd));
             x.mode = OperandMode::Invalid;
             return;
         }
@@ -628,10 +634,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 let yval = yv.to_int();
                 if !yval.is_int() {
                     let yd = self.new_dis(y);
-                    self.invalid_op(
-                        yd.pos(),
-                        &format!("shift count {} must be unsigned integer", yd),
-                    );
+                    self.invalid_op(yd.pos(), &format!("shift count {} must be integer", yd));
                     x.mode = OperandMode::Invalid;
                     return;
                 }
@@ -693,17 +696,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             }
         }
 
-        // constant rhs must be >= 0
-        if let OperandMode::Constant(v) = &y.mode {
-            if v.sign() < 0 {
-                let yd = self.new_dis(y);
-                self.invalid_op(
-                    yd.pos(),
-                    &format!("shift count {} must not be negative", yd),
-                );
-            }
-        }
-
         if !typ::is_integer(x.typ.unwrap(), self.tc_objs) {
             let xd = self.new_dis(x);
             self.invalid_op(xd.pos(), &format!("shifted operand {} must be integer", xd));
@@ -956,7 +948,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             self.trace_begin(ed.pos(), &format!("{}", ed));
         }
 
+        self.depth += 1;
         let kind = self.raw_internal(x, e, hint, fctx);
+        self.leave_nested();
 
         let ty = match &x.mode {
             OperandMode::Invalid => self.invalid_type(),
@@ -964,6 +958,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             _ => x.typ.unwrap(),
         };
 
+        self.record_span(e);
         if typ::is_untyped(ty, self.tc_objs) {
             // delay type and value recording until we know the type
             // or until the end of type checking
@@ -1006,6 +1001,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         };
 
         let epos = e.pos(self.ast_objs);
+        if self.too_deep(epos, "expression too deep") {
+            return on_err(x);
+        }
         match e {
             Expr::Bad(_) => return on_err(x),
             Expr::Ident(i) => self.ident(x, *i, None, false, fctx),
@@ -1194,6 +1192,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             // that case there is nothing to record (there is no type in
                             // the source at that point).
                             if let Some(te) = &cl.typ {
+                                self.record_span(te);
                                 self.result.record_type_and_value(
                                     te,
                                     OperandMode::TypeExpr,
@@ -1312,7 +1311,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     Type::Basic(detail) => {
                         if detail.info() == typ::BasicInfo::IsString {
                             let len = if let OperandMode::Constant(v) = &x.mode {
-                                Some(v.str_as_string().len() as u64)
+                                Some(v.str_as_bytes().len() as u64)
                             } else {
                                 None
                             };
@@ -1395,7 +1394,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                 return on_err(x);
                             }
                             let len = if let OperandMode::Constant(v) = &x.mode {
-                                Some(v.str_as_string().len() as u64)
+                                Some(v.str_as_bytes().len() as u64)
                             } else {
                                 None
                             };
@@ -1547,7 +1546,11 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 }
             }
             Expr::Binary(be) => {
+                // the operands are not counted as nested, so that a long chain
+                // like a + b + c + ... is not too deep
+                self.depth -= 1;
                 self.binary(x, Some(e), &be.expr_a, &be.expr_b, &be.op, fctx);
+                self.depth += 1;
                 if x.invalid() {
                     return on_err(x);
                 }
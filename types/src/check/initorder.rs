@@ -164,30 +164,32 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         // which are permitted. Yet such cycles may incorrectly inflate the dependency
         // count for variables which in turn may not get scheduled for initialization
         // in correct order.)
-        let mut nodes: Vec<GraphNode> = map
-            .iter()
-            .filter_map(|(o, node)| {
-                if self.lobj(*o).entity_type().is_func() {
-                    for p in node.pred.borrow().iter() {
-                        if p != o {
-                            for s in node.succ.borrow().iter() {
-                                if s != o {
-                                    map[p].succ.borrow_mut().insert(*s);
-                                    map[s].pred.borrow_mut().insert(*p);
-                                    map[s].pred.borrow_mut().remove(o);
-                                }
+        for (o, node) in map.iter() {
+            if self.lobj(*o).entity_type().is_func() {
+                for p in node.pred.borrow().iter() {
+                    if p != o {
+                        for s in node.succ.borrow().iter() {
+                            if s != o {
+                                map[p].succ.borrow_mut().insert(*s);
+                                map[s].pred.borrow_mut().insert(*p);
+                                map[s].pred.borrow_mut().remove(o);
                             }
-                            map[p].succ.borrow_mut().remove(o);
                         }
+                        map[p].succ.borrow_mut().remove(o);
                     }
-                    None
-                } else {
-                    Some(GraphNode {
-                        obj: *o,
-                        ndeps: node.succ.borrow().len(),
-                        pos: self.lobj(*o).pos(),
-                    })
                 }
+            }
+        }
+
+        // count the dependencies only after all the function nodes are removed,
+        // removing one changes the edges of the nodes before it in the map
+        let mut nodes: Vec<GraphNode> = map
+            .iter()
+            .filter(|(o, _)| !self.lobj(**o).entity_type().is_func())
+            .map(|(o, node)| GraphNode {
+                obj: *o,
+                ndeps: node.succ.borrow().len(),
+                pos: self.lobj(*o).pos(),
             })
             .collect();
 
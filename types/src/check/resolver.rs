@@ -552,14 +552,14 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                         if !*used {
                             let (path, base) = self.pkg_path_and_name(*pkey);
                             if obj_val.name() == base {
-                                self.soft_error(
+                                self.unused_import_error(
                                     obj_val.pos(),
                                     format!("{} imported but not used", path),
                                 );
                             } else {
-                                self.soft_error(
+                                self.unused_import_error(
                                     obj_val.pos(),
-                                    format!("{} imported but not used as {}", path, base),
+                                    format!("{} imported but not used as {}", path, obj_val.name()),
                                 );
                             }
                         }
@@ -571,7 +571,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         // check use of dot-imported packages
         for (_, imports) in fctx.unused_dot_imports.iter() {
             for (pkey, pos) in imports.iter() {
-                self.soft_error(
+                self.unused_import_error(
                     *pos,
                     format!("{} imported but not used", self.package(*pkey).path()),
                 );
@@ -747,7 +747,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 name = &name[0..name.len() - 1];
             }
             if let Some(i) = name.rfind('/') {
-                name = &name[i..name.len()]
+                name = &name[i + 1..name.len()]
             }
             let pkg = self.tc_objs.new_package(path.clone());
             self.package_mut(pkg).mark_fake_with_name(name.to_owned());
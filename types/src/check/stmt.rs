@@ -35,6 +35,7 @@ struct StmtContext {
     continue_ok: bool,
     fallthrough_ok: bool,
     final_switch_case: bool,
+    in_type_switch: bool,
 }
 
 impl StmtContext {
@@ -44,6 +45,7 @@ impl StmtContext {
             continue_ok: false,
             fallthrough_ok: false,
             final_switch_case: false,
+            in_type_switch: false,
         }
     }
 }
@@ -53,7 +55,7 @@ enum GoVal {
     Int64(i64),
     Uint64(u64),
     Float64(F64),
-    Str(String),
+    Str(Vec<u8>),
     Invalid,
 }
 
@@ -71,7 +73,7 @@ impl GoVal {
                 (f, true) => GoVal::Float64(f),
                 _ => GoVal::Invalid,
             },
-            Value::Str(_) => GoVal::Str(v.str_as_string()),
+            Value::Str(_) => GoVal::Str(v.str_as_bytes().to_vec()),
             _ => GoVal::Invalid,
         }
     }
@@ -179,7 +181,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         unused.sort_by(|a, b| a.pos().cmp(&b.pos()));
 
         for lo in unused.iter() {
-            self.soft_error(lo.pos(), format!("{} declared but not used", lo.name()));
+            self.unused_var_error(lo.pos(), format!("{} declared but not used", lo.name()));
         }
         for skey in sval.children().iter() {
             // Don't go inside function literal scopes a second time;
@@ -396,6 +398,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         let mut inner_ctx = ctx.clone();
         inner_ctx.fallthrough_ok = false;
         inner_ctx.final_switch_case = false;
+        inner_ctx.in_type_switch = false;
         match stmt {
             Stmt::Bad(_) | Stmt::Empty(_) => {} //ignore
             Stmt::Decl(d) => self.decl_stmt((**d).clone(), fctx),
@@ -600,6 +603,8 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                         if !ctx.fallthrough_ok {
                             let msg = if ctx.final_switch_case {
                                 "cannot fallthrough final case in switch"
+                            } else if ctx.in_type_switch {
+                                "cannot fallthrough in type switch"
                             } else {
                                 "fallthrough statement out of place"
                             };
@@ -693,6 +698,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             }
             Stmt::TypeSwitch(tss) => {
                 inner_ctx.break_ok = true;
+                inner_ctx.in_type_switch = true;
                 self.open_scope(stmt, "type switch".to_owned());
 
                 self.simple_stmt(tss.init.as_ref(), fctx);
@@ -821,7 +827,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     if !used {
                         let ident = self.ast_ident(lhs.unwrap());
                         let (pos, name) = (ident.pos, &ident.name);
-                        self.soft_error(pos, format!("{} declared but not used", name));
+                        self.unused_var_error(pos, format!("{} declared but not used", name));
                     }
                 }
 
@@ -921,6 +927,8 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 self.expr(x, &rs.expr, fctx);
 
                 // determine key/value types
+                let mut cause = None;
+                let mut range_over_int = false;
                 let (key, val) = if x.invalid() {
                     (None, None)
                 } else {
@@ -929,6 +937,20 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             Some(self.basic_type(BasicType::Int)),
                             Some(*self.tc_objs.universe().rune()),
                         ),
+                        Type::Basic(detail) if detail.info() == BasicInfo::IsInteger => {
+                            if let Some(v) = &rs.val {
+                                self.error(
+                                    v.pos(self.ast_objs),
+                                    format!(
+                                        "range over {} permits only one iteration variable",
+                                        self.new_dis(x)
+                                    ),
+                                );
+                                // ok to continue
+                            }
+                            range_over_int = true;
+                            (x.typ, Some(self.invalid_type()))
+                        }
                         Type::Array(detail) => {
                             (Some(self.basic_type(BasicType::Int)), Some(detail.elem()))
                         }
@@ -968,13 +990,44 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             }
                             (Some(detail.elem()), Some(self.invalid_type()))
                         }
+                        Type::Signature(_) => match self.range_func_yield_params(x.typ.unwrap()) {
+                            Ok(params) => {
+                                let lhs = [&rs.key, &rs.val];
+                                if let Some(e) = lhs[params.len()..].iter().find_map(|x| x.as_ref())
+                                {
+                                    self.error(
+                                        e.pos(self.ast_objs),
+                                        format!(
+                                            "range over {} permits {} iteration variable{}",
+                                            self.new_dis(x),
+                                            ["no", "only one"][params.len()],
+                                            ["s", ""][params.len()],
+                                        ),
+                                    );
+                                    // ok to continue
+                                }
+                                let invalid = self.invalid_type();
+                                (
+                                    Some(params.first().copied().unwrap_or(invalid)),
+                                    Some(params.get(1).copied().unwrap_or(invalid)),
+                                )
+                            }
+                            Err(c) => {
+                                cause = Some(c);
+                                (None, None)
+                            }
+                        },
                         _ => (None, None),
                     }
                 };
 
                 if key.is_none() {
                     let xd = self.new_dis(x);
-                    self.error(xd.pos(), format!("cannot range over {}", xd));
+                    let msg = match cause {
+                        Some(c) => format!("cannot range over {}: {}", xd, c),
+                        None => format!("cannot range over {}", xd),
+                    };
+                    self.error(xd.pos(), msg);
                     // ok to continue
                 }
 
@@ -1011,7 +1064,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             }
                         };
                         // initialize lhs variable
-                        if rhs[i].is_some() {
+                        if range_over_int && i == 0 {
+                            // an untyped constant gets the type of the variable
+                            self.init_var(okey, x, "range clause", fctx);
+                        } else if rhs[i].is_some() {
                             x.mode = OperandMode::Value;
                             x.expr = lhs.map(|x| x.clone());
                             x.typ = rhs[i];
@@ -1045,7 +1101,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 } else {
                     // ordinary assignment
                     for (i, lhs) in lhs.iter().enumerate() {
-                        if lhs.is_some() && rhs[i].is_some() {
+                        if range_over_int && i == 0 && lhs.is_some() {
+                            self.assign_var(lhs.unwrap(), x, fctx);
+                        } else if lhs.is_some() && rhs[i].is_some() {
                             x.mode = OperandMode::Value;
                             x.expr = lhs.map(|x| x.clone());
                             x.typ = rhs[i];
@@ -1054,6 +1112,11 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     }
                 }
 
+                if range_over_int && rs.key.is_none() {
+                    // the value of an untyped constant must fit in its default type
+                    self.assignment(x, None, "range clause", fctx);
+                }
+
                 self.stmt(&Stmt::Block(rs.body.clone()), &inner_ctx, fctx);
 
                 self.close_scope()
@@ -1061,4 +1124,47 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             _ => self.error_str(stmt.pos(self.ast_objs), "invalid statement"),
         }
     }
+
+    /// Returns the parameter types of the yield function of a range over
+    /// `func(yield func(K, V) bool)`, or why it can't be ranged over.
+    pub fn range_func_yield_params(&self, t: TypeKey) -> Result<Vec<TypeKey>, &'static str> {
+        let objs = &*self.tc_objs;
+        let tuple = |t: TypeKey| objs.types[t].try_as_tuple().unwrap().vars();
+        let var_types = |t: TypeKey| -> Vec<TypeKey> {
+            tuple(t)
+                .iter()
+                .map(|x| objs.lobjs[*x].typ().unwrap())
+                .collect()
+        };
+        let sig = match self.otype(t).underlying_val(objs).try_as_signature() {
+            Some(sig) => sig,
+            None => return Err("not a function"),
+        };
+        let params = var_types(sig.params());
+        if params.len() != 1 {
+            return Err("func must be func(yield func(...) bool): wrong argument count");
+        }
+        if !tuple(sig.results()).is_empty() {
+            return Err("func must be func(yield func(...) bool): unexpected results");
+        }
+        let yield_sig = match self
+            .otype(params[0])
+            .underlying_val(objs)
+            .try_as_signature()
+        {
+            Some(sig) => sig,
+            None => return Err("func must be func(yield func(...) bool): argument is not func"),
+        };
+        let yield_params = var_types(yield_sig.params());
+        let yield_results = var_types(yield_sig.results());
+        if yield_params.len() > 2 || yield_sig.variadic() {
+            return Err(
+                "func must be func(yield func(...) bool): yield func has too many parameters",
+            );
+        }
+        if yield_results.len() != 1 || !typ::is_boolean(yield_results[0], objs) {
+            return Err("func must be func(yield func(...) bool): yield func does not return bool");
+        }
+        Ok(yield_params)
+    }
 }
@@ -157,8 +157,11 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             let ed = self.new_dis(e);
             self.trace_begin(ed.pos(), &format!("{}", ed));
         }
+        self.depth += 1;
         let t = self.type_internal(e, def, fctx);
+        self.leave_nested();
         debug_assert!(typ::is_typed(t, self.tc_objs));
+        self.record_span(e);
         self.result
             .record_type_and_value(e, OperandMode::TypeExpr, t);
         if self.trace() {
@@ -283,6 +286,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         };
         let pos = e.pos(self.ast_objs);
         let result_t: Option<TypeKey> = match e {
+            _ if self.too_deep(pos, "type too deep") => None,
             Expr::Bad(_) => None,
             Expr::Ident(i) => {
                 let mut x = Operand::new();
@@ -535,6 +539,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 let t = self.tc_objs.new_t_slice(self.lobj(last).typ().unwrap());
                 self.lobj_mut(last).set_type(Some(t));
                 let e = &self.ast_objs.fields[l.list[l.list.len() - 1]].typ;
+                if self.records_spans() {
+                    self.result.record_span(e, self.ast_objs);
+                }
                 self.result
                     .record_type_and_value(e, OperandMode::TypeExpr, t);
             }
@@ -764,7 +771,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         if let Some(e) = t {
             if let Expr::BasicLit(bl) = e {
                 if let Token::STRING(data) = &bl.token {
-                    return Some(data.as_str_str().1.clone());
+                    return Some(String::from_utf8_lossy(data.as_str_bytes().1).into_owned());
                 }
                 self.invalid_ast(
                     e.pos(self.ast_objs),
@@ -799,7 +806,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         pos: Pos,
     ) {
         if tag.is_some() && tags.is_none() {
-            *tags = Some(vec![]);
+            *tags = Some(vec![None; fields.len()]);
         }
         if tags.is_some() {
             tags.as_mut().unwrap().push(tag);
@@ -43,7 +43,8 @@ type F64 = ordered_float::OrderedFloat<f64>;
 pub enum Value {
     Unknown,
     Bool(bool),
-    Str(String),
+    // not always valid UTF-8, e.g. "\xff"
+    Str(Vec<u8>),
     Int(BigInt),
     Rat(BigRational),
     Float(F64),
@@ -71,11 +72,14 @@ impl fmt::Display for Value {
             }
             Value::Rat(r) => {
                 //f.write_str("rat: ")?;
-                r.fmt(f)
+                match rat_to_f64(r).filter(|x| x.is_finite()) {
+                    Some(x) => f.write_str(&float_approx_str(x, r.is_integer())),
+                    None => r.fmt(f),
+                }
             }
             Value::Float(s) => {
                 //f.write_str("float: ")?;
-                s.fmt(f)
+                f.write_str(&float_approx_str(**s, s.fract() == 0.0))
             }
             Value::Complex(r, i) => {
                 //f.write_str("complex: ")?;
@@ -91,7 +95,11 @@ impl Value {
     }
 
     pub fn with_str(s: String) -> Value {
-        Value::Str(s)
+        Value::Str(s.into_bytes())
+    }
+
+    pub fn with_bytes(b: Vec<u8>) -> Value {
+        Value::Str(b)
     }
 
     pub fn with_i64(i: i64) -> Value {
@@ -112,7 +120,7 @@ impl Value {
             Token::FLOAT(flit) => float_from_literal(flit.as_str()),
             Token::IMAG(imlit) => {
                 let s = imlit.as_str();
-                let v = float_from_literal(&s[..(s.len() - 1)]);
+                let v = imag_from_literal(&s[..(s.len() - 1)]);
                 if let Value::Float(_) = &v {
                     Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(v))
                 } else {
@@ -124,8 +132,8 @@ impl Value {
                 Value::with_i64(*ch as i64)
             }
             Token::STRING(slit) => {
-                let (_, s) = slit.as_str_str();
-                Value::with_str(s.clone())
+                let (_, b) = slit.as_str_bytes();
+                Value::with_bytes(b.clone())
             }
             _ => Value::Unknown,
         }
@@ -152,8 +160,8 @@ impl Value {
                         BasicType::Float32 => {
                             let f32_ = *f as f32;
                             let ok = !f32_.is_infinite();
-                            if let Some(r) = rounded {
-                                *r = Value::Float(((*f as f32) as f64).into());
+                            if let Some(r) = rounded.filter(|_| ok) {
+                                *r = Value::Float((f32_ as f64).into());
                             }
                             ok
                         }
@@ -167,10 +175,7 @@ impl Value {
         match base.info() {
             BasicInfo::IsInteger => match self.to_int().borrow() {
                 Value::Int(ival) => {
-                    if let Some(r) = rounded {
-                        *r = Value::Int(ival.clone())
-                    }
-                    match base.typ() {
+                    let ok = match base.typ() {
                         BasicType::Int => ival.to_isize().is_some(),
                         BasicType::Int8 => ival.to_i8().is_some(),
                         BasicType::Int16 => ival.to_i16().is_some(),
@@ -183,7 +188,11 @@ impl Value {
                         BasicType::Uint64 => ival.to_u64().is_some(),
                         BasicType::UntypedInt => true,
                         _ => unreachable!(),
+                    };
+                    if let Some(r) = rounded.filter(|_| ok) {
+                        *r = Value::Int(ival.clone())
                     }
+                    ok
                 }
                 _ => false,
             },
@@ -197,24 +206,14 @@ impl Value {
                 };
                 match self.to_complex() {
                     Value::Complex(r, i) => {
-                        let (rrounded, irounded): (Option<&mut Value>, Option<&mut Value>) =
-                            match rounded {
-                                Some(val) => {
-                                    *val = Value::Complex(
-                                        Box::new(Value::with_f64(0.0)),
-                                        Box::new(Value::with_f64(0.0)),
-                                    );
-                                    if let Value::Complex(r, i) = &mut *val {
-                                        (Some(r.as_mut()), Some(i.as_mut()))
-                                    } else {
-                                        unreachable!()
-                                    }
-                                }
-                                None => (None, None),
-                            };
-                        let rok = float_representable(&r, ty, rrounded);
-                        let iok = float_representable(&i, ty, irounded);
-                        rok && iok
+                        let (mut rrounded, mut irounded) = (r.as_ref().clone(), i.as_ref().clone());
+                        let rok = float_representable(&r, ty, Some(&mut rrounded));
+                        let iok = float_representable(&i, ty, Some(&mut irounded));
+                        let ok = rok && iok;
+                        if let Some(val) = rounded.filter(|_| ok) {
+                            *val = Value::Complex(Box::new(rrounded), Box::new(irounded));
+                        }
+                        ok
                     }
                     _ => false,
                 }
@@ -449,7 +448,7 @@ impl Value {
                 _ => unreachable!(),
             },
             (Value::Str(a), Value::Str(b)) => match op {
-                Token::ADD => Value::Str(format!("{}{}", a, b)),
+                Token::ADD => Value::Str([&a[..], &b[..]].concat()),
                 _ => unreachable!(),
             },
             _ => unreachable!(),
@@ -582,10 +581,15 @@ impl Value {
         }
     }
 
+    /// Invalid UTF-8 is replaced, use str_as_bytes for the exact value.
     pub fn str_as_string(&self) -> String {
+        String::from_utf8_lossy(self.str_as_bytes()).into_owned()
+    }
+
+    pub fn str_as_bytes(&self) -> &[u8] {
         match self {
-            Value::Str(s) => s.to_string(), //quote_str(s)
-            Value::Unknown => "".to_owned(),
+            Value::Str(s) => s,
+            Value::Unknown => &[],
             _ => panic!("not a string"),
         }
     }
@@ -784,20 +788,29 @@ impl Value {
 // ----------------------------------------------------------------------------
 // utilities
 
-fn short_quote_str(s: &str, max: usize) -> String {
-    let result = s.escape_default().collect();
+fn short_quote_str(s: &[u8], max: usize) -> String {
+    let mut result = String::new();
+    for chunk in s.utf8_chunks() {
+        result.extend(chunk.valid().escape_default());
+        for b in chunk.invalid() {
+            result.push_str(&format!("\\x{:02x}", b));
+        }
+    }
     shorten_with_ellipsis(result, max)
 }
 
 fn int_from_literal(lit: &str) -> Value {
-    let result = if lit.starts_with("0x") {
-        BigInt::from_str_radix(&lit[2..], 16)
-    } else if lit.starts_with("0o") {
-        BigInt::from_str_radix(&lit[2..], 8)
-    } else if lit.starts_with("0b") {
-        BigInt::from_str_radix(&lit[2..], 2)
+    let lit = lit.replace('_', "").to_ascii_lowercase();
+    let result = if let Some(digits) = lit.strip_prefix("0x") {
+        BigInt::from_str_radix(digits, 16)
+    } else if let Some(digits) = lit.strip_prefix("0o") {
+        BigInt::from_str_radix(digits, 8)
+    } else if let Some(digits) = lit.strip_prefix("0b") {
+        BigInt::from_str_radix(digits, 2)
+    } else if lit.len() > 1 && lit.starts_with('0') {
+        BigInt::from_str_radix(&lit[1..], 8)
     } else {
-        BigInt::from_str_radix(lit, 10)
+        BigInt::from_str_radix(&lit, 10)
     };
     match result {
         Ok(i) => Value::Int(i),
@@ -806,12 +819,49 @@ fn int_from_literal(lit: &str) -> Value {
 }
 
 fn float_from_literal(lit: &str) -> Value {
+    let lit = lit.replace('_', "").to_ascii_lowercase();
+    if let Some(hex) = lit.strip_prefix("0x") {
+        return hex_float_from_literal(hex);
+    }
     match lit.parse::<f64>() {
         Ok(f) => Value::with_f64(f),
         Err(_) => Value::Unknown,
     }
 }
 
+/// Parses the part after "0x" of a hexadecimal float, e.g. "1.8p3".
+fn hex_float_from_literal(lit: &str) -> Value {
+    let (mantissa, exp) = match lit.split_once('p') {
+        Some(x) => x,
+        None => return Value::Unknown,
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", int, frac);
+    match (BigInt::from_str_radix(&digits, 16), exp.parse::<i32>()) {
+        (Ok(m), Ok(exp)) => {
+            let exp = exp - 4 * frac.len() as i32;
+            Value::with_f64(m.to_f64().unwrap_or(f64::INFINITY) * 2f64.powi(exp))
+        }
+        _ => Value::Unknown,
+    }
+}
+
+/// Parses the mantissa of an imaginary literal, ints with a prefix are in
+/// their base, others are decimal, e.g. "017" in "017i".
+fn imag_from_literal(lit: &str) -> Value {
+    let lower = lit.to_ascii_lowercase();
+    let is_prefixed_int = (lower.starts_with("0x") && !lower.contains('p'))
+        || lower.starts_with("0o")
+        || lower.starts_with("0b");
+    match is_prefixed_int {
+        true => match int_from_literal(lit) {
+            Value::Int(i) => Value::with_f64(i.to_f64().unwrap_or(f64::INFINITY)),
+            _ => Value::Unknown,
+        },
+        false => float_from_literal(lit),
+    }
+}
+
 fn shorten_with_ellipsis(s: String, max: usize) -> String {
     if s.len() <= max {
         s
@@ -823,6 +873,43 @@ fn shorten_with_ellipsis(s: String, max: usize) -> String {
     }
 }
 
+/// float_approx_str formats a float the way Go's constant package does: "%.6g",
+/// or "%g" if the value is not an integer but "%.6g" makes it look like one.
+fn float_approx_str(x: f64, is_int: bool) -> String {
+    if !x.is_finite() {
+        return x.to_string();
+    }
+    // "%.6g"
+    let sci = format!("{:.5e}", x);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let s = if !(-4..6).contains(&exp) {
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        let (sign, exp) = if exp < 0 { ('-', -exp) } else { ('+', exp) };
+        format!("{}e{}{:02}", mantissa, sign, exp)
+    } else {
+        let fixed = format!("{:.*}", (5 - exp) as usize, x);
+        match fixed.contains('.') {
+            true => fixed.trim_end_matches('0').trim_end_matches('.').to_owned(),
+            false => fixed,
+        }
+    };
+    if !is_int && !s.contains('.') {
+        // "%g", the shortest representation
+        let sci = format!("{:e}", x);
+        let (mantissa, exp) = sci.split_once('e').unwrap();
+        let exp: i32 = exp.parse().unwrap();
+        if (-4..6).contains(&exp) {
+            x.to_string()
+        } else {
+            let (sign, exp) = if exp < 0 { ('-', -exp) } else { ('+', exp) };
+            format!("{}e{}{:02}", mantissa, sign, exp)
+        }
+    } else {
+        s
+    }
+}
+
 fn rat_to_f64(r: &BigRational) -> Option<f64> {
     match (r.numer().to_f64(), r.denom().to_f64()) {
         (Some(n), Some(d)) => Some(n / d),
@@ -832,6 +919,60 @@ fn rat_to_f64(r: &BigRational) -> Option<f64> {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    #[test]
+    fn test_float_approx_str() {
+        assert_eq!(float_approx_str(1.5, false), "1.5");
+        assert_eq!(float_approx_str(1e300, true), "1e+300");
+        assert_eq!(float_approx_str(123456789.0, true), "1.23457e+08");
+        assert_eq!(float_approx_str(0.1, false), "0.1");
+        assert_eq!(float_approx_str(100.0, true), "100");
+        assert_eq!(float_approx_str(1.5e-7, false), "1.5e-07");
+        assert_eq!(float_approx_str(1e-7, false), "1e-07");
+        assert_eq!(float_approx_str(1.0000001, false), "1.0000001");
+        assert_eq!(float_approx_str(100000.5, false), "100000.5");
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let int = |s: &str| match int_from_literal(s) {
+            Value::Int(i) => i.to_i64().unwrap(),
+            v => panic!("{}: {:?}", s, v),
+        };
+        assert_eq!(int("0b1011"), 11);
+        assert_eq!(int("0B11"), 3);
+        assert_eq!(int("0o17"), 15);
+        assert_eq!(int("017"), 15);
+        assert_eq!(int("0X_ff_FF"), 65535);
+        assert_eq!(int("1_000_000"), 1000000);
+        assert_eq!(int("0"), 0);
+        let float = |v: Value| match v {
+            Value::Float(f) => f.into_inner(),
+            v => panic!("{:?}", v),
+        };
+        assert_eq!(float(float_from_literal("0x1.8p3")), 12.0);
+        assert_eq!(float(float_from_literal("0X.8P-1")), 0.25);
+        assert_eq!(float(float_from_literal("0x1_0p0")), 16.0);
+        assert_eq!(float(float_from_literal("1_0.2_5")), 10.25);
+        assert_eq!(float(float_from_literal("09.5")), 9.5);
+        assert_eq!(float(imag_from_literal("017")), 17.0);
+        assert_eq!(float(imag_from_literal("0o17")), 15.0);
+        assert_eq!(float(imag_from_literal("0x10")), 16.0);
+        assert_eq!(float(imag_from_literal("0x1p4")), 16.0);
+    }
+
+    #[test]
+    fn test_str_bytes() {
+        let a = Value::with_bytes(vec![0xff]);
+        let b = Value::with_str("世".to_owned());
+        let ab = Value::binary_op(&a, &Token::ADD, &b);
+        assert_eq!(ab.str_as_bytes(), b"\xff\xe4\xb8\x96");
+        assert_eq!(ab.str_as_string(), "\u{fffd}世");
+        assert_eq!(ab.to_string(), "\\xff\\u{4e16}");
+        assert!(Value::compare(&a, &Token::GTR, &b));
+    }
+
     #[test]
     fn test_str_unquote() {
         let s = "\\111";
@@ -0,0 +1,449 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Documentation of the public API of a package, made of the exported
+//! declarations with their signatures and the comments right above them.
+//!
+//! Names of the documented package are not qualified in the signatures, names
+//! of the other packages are qualified with the package name, like `io.Reader`.
+
+use super::check::TypeInfo;
+use super::display::Displayer;
+use super::importer::{ImportKey, Importer, SourceRead, TraceConfig};
+use super::obj::{type_name_is_alias, EntityType};
+use super::objects::{ObjKey, PackageKey, TCObjects, TypeKey};
+use super::typ::{self, Type};
+use go_parser::ast::{CommentGroup, Decl, Expr, Field, Spec};
+use go_parser::{AstObjects, ErrorList, FileSet, IdentKey, Map};
+use std::fmt::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+/// The documentation of a package.
+#[derive(Clone, Debug, Default)]
+pub struct PackageDoc {
+    pub name: String,
+    pub path: String,
+    pub doc: String,
+    pub consts: Vec<DeclDoc>,
+    pub vars: Vec<DeclDoc>,
+    pub funcs: Vec<DeclDoc>,
+    pub types: Vec<DeclDoc>,
+}
+
+/// The documentation of a declaration, `fields` are the exported fields of
+/// struct types and `methods` are the exported methods of named types, or the
+/// methods and embedded interfaces of interface types.
+#[derive(Clone, Debug, Default)]
+pub struct DeclDoc {
+    pub name: String,
+    pub signature: String,
+    pub doc: String,
+    pub fields: Vec<DeclDoc>,
+    pub methods: Vec<DeclDoc>,
+}
+
+/// Type checks the package at `path` and returns its documentation.
+pub fn check_doc<S: SourceRead>(
+    path: &Path,
+    config: &TraceConfig,
+    reader: &S,
+) -> Result<PackageDoc, ErrorList> {
+    let config = &TraceConfig {
+        parse_docs: true,
+        ..config.clone()
+    };
+    let mut fset = FileSet::new();
+    let ast_objs = &mut AstObjects::new();
+    let tc_objs = &mut TCObjects::new();
+    let results = &mut Map::new();
+    let pkgs = &mut Map::new();
+    let el = ErrorList::new();
+
+    let importer = &mut Importer::new(
+        config, reader, &mut fset, pkgs, results, ast_objs, tc_objs, &el, 0,
+    );
+    let key = ImportKey::new(
+        path.to_str().unwrap(),
+        reader.working_dir().to_str().unwrap(),
+    );
+    let pkg = importer.import(&key);
+    if el.len() > 0 {
+        return Err(el);
+    }
+    let pkg = pkg.unwrap();
+    Ok(package_doc(ast_objs, tc_objs, &results[&pkg], pkg))
+}
+
+/// Returns the documentation of a checked package, `ti` being its result.
+/// The `fmt_qualifier` of `tc_objs` is swapped while the signatures are
+/// formatted, and restored before it returns.
+pub fn package_doc(
+    ast_objs: &AstObjects,
+    tc_objs: &mut TCObjects,
+    ti: &TypeInfo,
+    pkg: PackageKey,
+) -> PackageDoc {
+    let path = tc_objs.pkgs[pkg].path().clone();
+    let qualifier = std::mem::replace(
+        &mut tc_objs.fmt_qualifier,
+        Box::new(move |p| {
+            if *p.path() == path {
+                "".into()
+            } else {
+                p.name().clone().unwrap_or_default().into()
+            }
+        }),
+    );
+    let docs = collect_docs(ast_objs, ti);
+    let w = Writer {
+        objs: tc_objs,
+        docs,
+    };
+
+    let pkg_val = &tc_objs.pkgs[pkg];
+    let mut result = PackageDoc {
+        name: pkg_val.name().clone().unwrap_or_default(),
+        path: pkg_val.path().clone(),
+        doc: ti
+            .ast_files
+            .iter()
+            .find_map(|f| f.doc.as_ref().map(|x| x.text()))
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let scope = &tc_objs.scopes[*pkg_val.scope()];
+    let mut elems: Vec<(&String, &ObjKey)> = scope.elems().iter().collect();
+    elems.sort_by(|a, b| a.0.cmp(b.0));
+    for (_, okey) in elems.into_iter() {
+        let lobj = &tc_objs.lobjs[*okey];
+        if !lobj.exported() {
+            continue;
+        }
+        match lobj.entity_type() {
+            EntityType::Const(_) => result.consts.push(w.const_doc(*okey)),
+            EntityType::Var(_) => result.vars.push(w.var_doc(*okey)),
+            EntityType::Func(_) => result.funcs.push(w.func_doc(*okey)),
+            EntityType::TypeName => result.types.push(w.type_doc(*okey)),
+            _ => {}
+        }
+    }
+    tc_objs.fmt_qualifier = qualifier;
+    result
+}
+
+impl PackageDoc {
+    /// Returns the documentation as a Markdown document.
+    pub fn to_markdown(&self) -> String {
+        let mut s = String::new();
+        writeln!(s, "# package {}\n", self.name).unwrap();
+        writeln!(s, "`import \"{}\"`\n", self.path).unwrap();
+        if !self.doc.is_empty() {
+            writeln!(s, "{}", self.doc).unwrap();
+        }
+        let sections = [
+            ("Constants", &self.consts),
+            ("Variables", &self.vars),
+            ("Functions", &self.funcs),
+            ("Types", &self.types),
+        ];
+        for (title, decls) in sections.iter() {
+            if decls.is_empty() {
+                continue;
+            }
+            writeln!(s, "## {}\n", title).unwrap();
+            for d in decls.iter() {
+                d.write_markdown(&mut s, 3);
+            }
+        }
+        s
+    }
+
+    /// Returns the documentation as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut s = String::new();
+        s.push_str("{\"name\":");
+        write_json_str(&mut s, &self.name);
+        s.push_str(",\"path\":");
+        write_json_str(&mut s, &self.path);
+        s.push_str(",\"doc\":");
+        write_json_str(&mut s, &self.doc);
+        s.push_str(",\"consts\":");
+        write_json_list(&mut s, &self.consts);
+        s.push_str(",\"vars\":");
+        write_json_list(&mut s, &self.vars);
+        s.push_str(",\"funcs\":");
+        write_json_list(&mut s, &self.funcs);
+        s.push_str(",\"types\":");
+        write_json_list(&mut s, &self.types);
+        s.push('}');
+        s
+    }
+}
+
+impl DeclDoc {
+    /// Returns the documentation as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut s = String::new();
+        self.write_json(&mut s);
+        s
+    }
+
+    fn write_markdown(&self, s: &mut String, level: usize) {
+        writeln!(s, "{} {}\n", "#".repeat(level), self.name).unwrap();
+        writeln!(s, "```go\n{}\n```\n", self.signature).unwrap();
+        if !self.doc.is_empty() {
+            writeln!(s, "{}", self.doc).unwrap();
+        }
+        if !self.fields.is_empty() {
+            for f in self.fields.iter() {
+                write!(s, "- `{}`", f.signature).unwrap();
+                match f.doc.lines().next() {
+                    Some(line) => writeln!(s, ": {}", line).unwrap(),
+                    None => s.push('\n'),
+                }
+            }
+            s.push('\n');
+        }
+        for m in self.methods.iter() {
+            m.write_markdown(s, level + 1);
+        }
+    }
+
+    fn write_json(&self, s: &mut String) {
+        s.push_str("{\"name\":");
+        write_json_str(s, &self.name);
+        s.push_str(",\"signature\":");
+        write_json_str(s, &self.signature);
+        s.push_str(",\"doc\":");
+        write_json_str(s, &self.doc);
+        s.push_str(",\"fields\":");
+        write_json_list(s, &self.fields);
+        s.push_str(",\"methods\":");
+        write_json_list(s, &self.methods);
+        s.push('}');
+    }
+}
+
+fn write_json_list(s: &mut String, list: &[DeclDoc]) {
+    s.push('[');
+    for (i, d) in list.iter().enumerate() {
+        if i > 0 {
+            s.push(',');
+        }
+        d.write_json(s);
+    }
+    s.push(']');
+}
+
+fn write_json_str(s: &mut String, v: &str) {
+    s.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+/// Maps the objects declared at package level, and the fields and methods of
+/// the types declared there, to their doc comments.
+fn collect_docs(ast_objs: &AstObjects, ti: &TypeInfo) -> Map<ObjKey, String> {
+    let mut docs = Map::new();
+    let mut add = |ikey: IdentKey, doc: &Option<Rc<CommentGroup>>| {
+        if let (Some(Some(okey)), Some(doc)) = (ti.defs.get(&ikey), doc) {
+            docs.insert(*okey, doc.text());
+        }
+    };
+    for file in ti.ast_files.iter() {
+        for decl in file.decls.iter() {
+            match decl {
+                Decl::Func(fkey) => {
+                    let fdecl = &ast_objs.fdecls[*fkey];
+                    add(fdecl.name, &fdecl.doc);
+                }
+                Decl::Gen(gdecl) => {
+                    for skey in gdecl.specs.iter() {
+                        match &ast_objs.specs[*skey] {
+                            Spec::Value(vs) => {
+                                let doc = vs.doc.as_ref().or(gdecl.doc.as_ref()).cloned();
+                                for name in vs.names.iter() {
+                                    add(*name, &doc);
+                                }
+                            }
+                            Spec::Type(ts) => {
+                                let doc = ts.doc.as_ref().or(gdecl.doc.as_ref()).cloned();
+                                add(ts.name, &doc);
+                                let list = match &ts.typ {
+                                    Expr::Struct(st) => &st.fields.list,
+                                    Expr::Interface(it) => &it.methods.list,
+                                    _ => continue,
+                                };
+                                for fkey in list.iter() {
+                                    let field = &ast_objs.fields[*fkey];
+                                    for name in field_idents(field) {
+                                        add(name, &field.doc);
+                                    }
+                                }
+                            }
+                            Spec::Import(_) => {}
+                        }
+                    }
+                }
+                Decl::Bad(_) => {}
+            }
+        }
+    }
+    docs
+}
+
+// the names of a field, or the name of the type of an embedded field
+fn field_idents(field: &Field) -> Vec<IdentKey> {
+    if !field.names.is_empty() {
+        return field.names.clone();
+    }
+    let mut e = &field.typ;
+    if let Expr::Star(s) = e {
+        e = &s.expr;
+    }
+    match e {
+        Expr::Ident(i) => vec![*i],
+        Expr::Selector(s) => vec![s.sel],
+        _ => vec![],
+    }
+}
+
+struct Writer<'a> {
+    objs: &'a TCObjects,
+    docs: Map<ObjKey, String>,
+}
+
+impl<'a> Writer<'a> {
+    fn decl(&self, okey: ObjKey, signature: String) -> DeclDoc {
+        DeclDoc {
+            name: self.objs.lobjs[okey].name().clone(),
+            signature,
+            doc: self.docs.get(&okey).cloned().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn type_str(&self, t: TypeKey) -> String {
+        Displayer::new(&t, None, Some(self.objs)).to_string()
+    }
+
+    fn sig_str(&self, t: TypeKey) -> String {
+        struct Sig<'a>(TypeKey, &'a TCObjects);
+        impl<'a> std::fmt::Display for Sig<'a> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                typ::fmt_signature(self.0, f, self.1)
+            }
+        }
+        Sig(t, self.objs).to_string()
+    }
+
+    fn const_doc(&self, okey: ObjKey) -> DeclDoc {
+        let lobj = &self.objs.lobjs[okey];
+        let t = lobj.typ().unwrap();
+        let sig = if typ::is_untyped(t, self.objs) {
+            format!("const {} = {}", lobj.name(), lobj.const_val())
+        } else {
+            format!(
+                "const {} {} = {}",
+                lobj.name(),
+                self.type_str(t),
+                lobj.const_val()
+            )
+        };
+        self.decl(okey, sig)
+    }
+
+    fn var_doc(&self, okey: ObjKey) -> DeclDoc {
+        let lobj = &self.objs.lobjs[okey];
+        let sig = format!("var {} {}", lobj.name(), self.type_str(lobj.typ().unwrap()));
+        self.decl(okey, sig)
+    }
+
+    fn func_doc(&self, okey: ObjKey) -> DeclDoc {
+        let lobj = &self.objs.lobjs[okey];
+        let t = lobj.typ().unwrap();
+        let recv = match self.objs.types[t].try_as_signature().unwrap().recv() {
+            Some(r) => format!("({}) ", self.type_str(self.objs.lobjs[*r].typ().unwrap())),
+            None => "".to_owned(),
+        };
+        let sig = format!("func {}{}{}", recv, lobj.name(), self.sig_str(t));
+        self.decl(okey, sig)
+    }
+
+    fn type_doc(&self, okey: ObjKey) -> DeclDoc {
+        let lobj = &self.objs.lobjs[okey];
+        let t = lobj.typ().unwrap();
+        if type_name_is_alias(okey, self.objs) {
+            let sig = format!("type {} = {}", lobj.name(), self.type_str(t));
+            return self.decl(okey, sig);
+        }
+        let underlying = typ::underlying_type(t, self.objs);
+        let mut fields = vec![];
+        let mut methods = vec![];
+        let kind = match &self.objs.types[underlying] {
+            Type::Struct(s) => {
+                for f in s
+                    .fields()
+                    .iter()
+                    .filter(|x| self.objs.lobjs[**x].exported())
+                {
+                    let fobj = &self.objs.lobjs[*f];
+                    let ftype = self.type_str(fobj.typ().unwrap());
+                    let sig = if fobj.var_embedded() {
+                        ftype
+                    } else {
+                        format!("{} {}", fobj.name(), ftype)
+                    };
+                    fields.push(self.decl(*f, sig));
+                }
+                "struct".to_owned()
+            }
+            Type::Interface(i) => {
+                for e in i.embeddeds().iter() {
+                    let name = self.type_str(*e);
+                    methods.push(DeclDoc {
+                        name: name.clone(),
+                        signature: name,
+                        ..Default::default()
+                    });
+                }
+                for m in i
+                    .methods()
+                    .iter()
+                    .filter(|x| self.objs.lobjs[**x].exported())
+                {
+                    let mobj = &self.objs.lobjs[*m];
+                    let sig = format!("{}{}", mobj.name(), self.sig_str(mobj.typ().unwrap()));
+                    methods.push(self.decl(*m, sig));
+                }
+                "interface".to_owned()
+            }
+            _ => self.type_str(underlying),
+        };
+        if let Some(named) = self.objs.types[t].try_as_named() {
+            let mut list: Vec<&ObjKey> = named
+                .methods()
+                .iter()
+                .filter(|x| self.objs.lobjs[**x].exported())
+                .collect();
+            list.sort_by_key(|x| self.objs.lobjs[**x].name());
+            methods.extend(list.into_iter().map(|m| self.func_doc(*m)));
+        }
+        let mut result = self.decl(okey, format!("type {} {}", lobj.name(), kind));
+        result.fields = fields;
+        result.methods = methods;
+        result
+    }
+}
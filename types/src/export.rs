@@ -0,0 +1,859 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Export data describes the public API of a checked package, so that other
+//! packages can be type checked against it without its source code.
+//!
+//! It's a text file made of s-expressions:
+//!
+//! ```text
+//! (goscript-export 1 "name" "path")
+//! (deps "strings" "./other")
+//! (const "Max" (basic "int") (int "10"))
+//! (var "Default" (ptr (named 0 "Config")))
+//! (func "New" (func (tuple) (tuple (var "" (ptr (named 0 "Config")))) false))
+//! (type "Config" (struct (field "Name" 0 (basic "string") false _))
+//!     (method "String" 0 false (func (tuple) (tuple (var "" (basic "string"))) false)))
+//! (alias "Cfg" (named 0 "Config"))
+//! ```
+//!
+//! Named types are referred to by a package index and a name, 0 being the
+//! exported package itself and `i` being the i-th package listed in `deps`.
+//! Named types that are reachable from the exported objects are written out
+//! even if they are not exported themselves.
+
+use super::check::TypeInfo;
+use super::constant::Value;
+use super::importer::{ImportKey, Importer, SourceRead, TraceConfig};
+use super::obj::EntityType;
+use super::objects::{ObjKey, PackageKey, ScopeKey, TCObjects, TypeKey};
+use super::scope::Scope;
+use super::typ::{ChanDir, Type};
+use go_parser::{AstObjects, ErrorList, FileSet, Map};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The extension of export data files, an import of "./lib" is resolved to
+/// "./lib.gosx" when the source code of the package is not found.
+pub const EXPORT_DATA_EXT: &str = "gosx";
+
+const MAGIC: &str = "goscript-export";
+const VERSION: &str = "1";
+
+/// Type checks the package at `path` and returns its export data.
+pub fn check_export<S: SourceRead>(
+    path: &Path,
+    config: &TraceConfig,
+    reader: &S,
+) -> Result<String, ErrorList> {
+    check(path, config, reader, |tc_objs, pkg, _| {
+        write_export_data(tc_objs, pkg)
+    })
+}
+
+/// Type checks the package at `path` and returns the export data of the
+/// packages it imports, directly or not, sorted by import path.
+pub fn check_export_deps<S: SourceRead>(
+    path: &Path,
+    config: &TraceConfig,
+    reader: &S,
+) -> Result<Vec<(String, String)>, ErrorList> {
+    check(path, config, reader, |tc_objs, pkg, results| {
+        let mut deps: Vec<(String, String)> = results
+            .keys()
+            .filter(|x| **x != pkg)
+            .map(|x| {
+                (
+                    tc_objs.pkgs[*x].path().clone(),
+                    write_export_data(tc_objs, *x),
+                )
+            })
+            .collect();
+        deps.sort();
+        deps
+    })
+}
+
+fn check<S: SourceRead, T>(
+    path: &Path,
+    config: &TraceConfig,
+    reader: &S,
+    f: impl FnOnce(&TCObjects, PackageKey, &Map<PackageKey, TypeInfo>) -> T,
+) -> Result<T, ErrorList> {
+    let mut fset = FileSet::new();
+    let ast_objs = &mut AstObjects::new();
+    let tc_objs = &mut TCObjects::new();
+    let results = &mut Map::new();
+    let pkgs = &mut Map::new();
+    let el = ErrorList::new();
+
+    let importer = &mut Importer::new(
+        config, reader, &mut fset, pkgs, results, ast_objs, tc_objs, &el, 0,
+    );
+    let key = ImportKey::new(
+        path.to_str().unwrap(),
+        reader.working_dir().to_str().unwrap(),
+    );
+    let pkg = importer.import(&key);
+    if el.len() > 0 {
+        Err(el)
+    } else {
+        Ok(f(tc_objs, pkg.unwrap(), results))
+    }
+}
+
+/// Returns the export data of a checked package.
+pub fn write_export_data(objs: &TCObjects, pkg: PackageKey) -> String {
+    let mut w = Writer {
+        objs,
+        pkg,
+        deps: vec![],
+        pending: vec![],
+        written: Map::new(),
+    };
+    let mut body = String::new();
+    w.write_objects(&mut body).unwrap();
+
+    let pkg_val = &objs.pkgs[pkg];
+    let mut header = String::new();
+    writeln!(
+        header,
+        "({} {} {:?} {:?})",
+        MAGIC,
+        VERSION,
+        pkg_val.name().as_deref().unwrap_or(""),
+        pkg_val.path()
+    )
+    .unwrap();
+    header.push_str("(deps");
+    for dep in w.deps.iter() {
+        write!(header, " {:?}", objs.pkgs[*dep].path()).unwrap();
+    }
+    header.push_str(")\n");
+    header.push_str(&body);
+    header
+}
+
+struct Writer<'a> {
+    objs: &'a TCObjects,
+    pkg: PackageKey,
+    // packages other than pkg that provide named types
+    deps: Vec<PackageKey>,
+    // named types of pkg to be written
+    pending: Vec<TypeKey>,
+    written: Map<TypeKey, ()>,
+}
+
+impl<'a> Writer<'a> {
+    fn write_objects(&mut self, w: &mut String) -> std::fmt::Result {
+        let scope = &self.objs.scopes[*self.objs.pkgs[self.pkg].scope()];
+        let mut elems: Vec<(&String, &ObjKey)> = scope.elems().iter().collect();
+        elems.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, okey) in elems.into_iter() {
+            let lobj = &self.objs.lobjs[*okey];
+            if !lobj.exported() {
+                continue;
+            }
+            let typ = lobj.typ().unwrap();
+            match lobj.entity_type() {
+                EntityType::TypeName => {
+                    match self.objs.types[typ].try_as_named() {
+                        Some(n) if *n.obj() == Some(*okey) => self.pending.push(typ),
+                        _ => {
+                            write!(w, "(alias {:?} ", name)?;
+                            self.write_type(w, typ)?;
+                            w.push_str(")\n");
+                        }
+                    }
+                    continue;
+                }
+                EntityType::Const(val) => {
+                    write!(w, "(const {:?} ", name)?;
+                    self.write_type(w, typ)?;
+                    w.push(' ');
+                    write_value(w, val)?;
+                }
+                EntityType::Var(_) => {
+                    write!(w, "(var {:?} ", name)?;
+                    self.write_type(w, typ)?;
+                }
+                EntityType::Func(_) => {
+                    write!(w, "(func {:?} ", name)?;
+                    self.write_type(w, typ)?;
+                }
+                _ => continue,
+            }
+            w.push_str(")\n");
+        }
+        while let Some(t) = self.pending.pop() {
+            if self.written.insert(t, ()).is_none() {
+                self.write_named_decl(w, t)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_named_decl(&mut self, w: &mut String, t: TypeKey) -> std::fmt::Result {
+        let named = self.objs.types[t].try_as_named().unwrap();
+        let name = self.objs.lobjs[named.obj().unwrap()].name();
+        write!(w, "(type {:?} ", name)?;
+        self.write_type(w, named.underlying())?;
+        for m in named.methods().iter() {
+            let mobj = &self.objs.lobjs[*m];
+            write!(w, "\n    (method {:?} ", mobj.name())?;
+            self.write_pkg_ref(w, mobj.pkg())?;
+            write!(w, " {} ", mobj.entity_type().func_has_ptr_recv())?;
+            self.write_type(w, mobj.typ().unwrap())?;
+            w.push(')');
+        }
+        w.push_str(")\n");
+        Ok(())
+    }
+
+    fn write_type(&mut self, w: &mut String, t: TypeKey) -> std::fmt::Result {
+        match &self.objs.types[t] {
+            Type::Basic(b) => write!(w, "(basic {:?})", b.name()),
+            Type::Array(a) => {
+                write!(w, "(array {} ", a.len().unwrap_or(0))?;
+                self.write_type(w, a.elem())?;
+                w.write_char(')')
+            }
+            Type::Slice(s) => {
+                w.push_str("(slice ");
+                self.write_type(w, s.elem())?;
+                w.write_char(')')
+            }
+            Type::Struct(s) => {
+                w.push_str("(struct");
+                for (i, f) in s.fields().iter().enumerate() {
+                    let fobj = &self.objs.lobjs[*f];
+                    write!(w, " (field {:?} ", fobj.name())?;
+                    self.write_pkg_ref(w, fobj.pkg())?;
+                    w.push(' ');
+                    self.write_type(w, fobj.typ().unwrap())?;
+                    write!(w, " {} ", fobj.var_embedded())?;
+                    match s.tag(i) {
+                        Some(tag) => write!(w, "{:?})", tag)?,
+                        None => w.push_str("_)"),
+                    }
+                }
+                w.write_char(')')
+            }
+            Type::Pointer(p) => {
+                w.push_str("(ptr ");
+                self.write_type(w, p.base())?;
+                w.write_char(')')
+            }
+            Type::Tuple(tuple) => {
+                w.push_str("(tuple");
+                for v in tuple.vars().iter() {
+                    let vobj = &self.objs.lobjs[*v];
+                    write!(w, " (var {:?} ", vobj.name())?;
+                    self.write_type(w, vobj.typ().unwrap())?;
+                    w.push(')');
+                }
+                w.write_char(')')
+            }
+            Type::Signature(sig) => {
+                w.push_str("(func ");
+                self.write_type(w, sig.params())?;
+                w.push(' ');
+                self.write_type(w, sig.results())?;
+                write!(w, " {})", sig.variadic())
+            }
+            Type::Interface(iface) => {
+                // embedded interfaces are flattened
+                iface.complete(self.objs);
+                let methods = iface.all_methods().as_ref().unwrap().clone();
+                w.push_str("(interface");
+                for m in methods.iter() {
+                    let mobj = &self.objs.lobjs[*m];
+                    write!(w, " (method {:?} ", mobj.name())?;
+                    self.write_pkg_ref(w, mobj.pkg())?;
+                    w.push(' ');
+                    self.write_type(w, mobj.typ().unwrap())?;
+                    w.push(')');
+                }
+                w.write_char(')')
+            }
+            Type::Map(m) => {
+                w.push_str("(map ");
+                self.write_type(w, m.key())?;
+                w.push(' ');
+                self.write_type(w, m.elem())?;
+                w.write_char(')')
+            }
+            Type::Chan(c) => {
+                let dir = match c.dir() {
+                    ChanDir::SendRecv => "both",
+                    ChanDir::SendOnly => "send",
+                    ChanDir::RecvOnly => "recv",
+                };
+                write!(w, "(chan {} ", dir)?;
+                self.write_type(w, c.elem())?;
+                w.write_char(')')
+            }
+            Type::Named(n) => {
+                let lobj = &self.objs.lobjs[n.obj().unwrap()];
+                match lobj.pkg() {
+                    None => write!(w, "(universe {:?})", lobj.name()),
+                    Some(p) => {
+                        if p == self.pkg {
+                            self.pending.push(t);
+                        }
+                        w.push_str("(named ");
+                        self.write_pkg_ref(w, Some(p))?;
+                        write!(w, " {:?})", lobj.name())
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_pkg_ref(&mut self, w: &mut String, pkg: Option<PackageKey>) -> std::fmt::Result {
+        match pkg {
+            None => w.write_char('_'),
+            Some(p) if p == self.pkg => w.write_char('0'),
+            Some(p) => {
+                let index = match self.deps.iter().position(|x| *x == p) {
+                    Some(i) => i,
+                    None => {
+                        self.deps.push(p);
+                        self.deps.len() - 1
+                    }
+                };
+                write!(w, "{}", index + 1)
+            }
+        }
+    }
+}
+
+fn write_value(w: &mut String, val: &Value) -> std::fmt::Result {
+    match val {
+        Value::Unknown => w.write_str("(unknown)"),
+        Value::Bool(b) => write!(w, "(bool {})", b),
+        Value::Str(s) => match std::str::from_utf8(s) {
+            Ok(s) => write!(w, "(string {:?})", s),
+            // invalid UTF-8, e.g. "\xff", is kept as hex
+            Err(_) => {
+                let hex: String = s.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(w, "(bytes \"{}\")", hex)
+            }
+        },
+        Value::Int(i) => write!(w, "(int \"{}\")", i),
+        Value::Rat(r) => write!(w, "(rat \"{}\" \"{}\")", r.numer(), r.denom()),
+        Value::Float(f) => write!(w, "(float \"{:?}\")", f.into_inner()),
+        Value::Complex(r, i) => {
+            w.write_str("(complex ")?;
+            write_value(w, r)?;
+            w.write_char(' ')?;
+            write_value(w, i)?;
+            w.write_char(')')
+        }
+    }
+}
+
+/// Parsed export data, the dependencies have to be imported before the
+/// package can be built with `build_package`.
+pub(crate) struct ExportData {
+    pub name: String,
+    pub deps: Vec<String>,
+    decls: Vec<Sexp>,
+}
+
+pub(crate) fn parse_export_data(data: &str) -> Result<ExportData, String> {
+    let mut sexps = SexpParser::new(data).parse_all()?.into_iter();
+    let header = sexps.next().ok_or("empty export data")?;
+    let header = header.list()?;
+    if header.len() != 4 || header[0].atom()? != MAGIC {
+        return Err("not goscript export data".to_owned());
+    }
+    if header[1].atom()? != VERSION {
+        return Err(format!("unsupported version {}", header[1].atom()?));
+    }
+    let name = header[2].string()?.to_owned();
+    let deps = sexps.next().ok_or("missing deps")?;
+    let deps = deps.list()?;
+    if deps.is_empty() || deps[0].atom()? != "deps" {
+        return Err("missing deps".to_owned());
+    }
+    let deps = deps[1..]
+        .iter()
+        .map(|x| x.string().map(|s| s.to_owned()))
+        .collect::<Result<Vec<String>, String>>()?;
+    Ok(ExportData {
+        name,
+        deps,
+        decls: sexps.collect(),
+    })
+}
+
+/// Declares the objects of the export data in the scope of `pkg`, `deps` are
+/// the packages listed in the export data.
+pub(crate) fn build_package(
+    objs: &mut TCObjects,
+    pkg: PackageKey,
+    data: ExportData,
+    deps: Vec<PackageKey>,
+) -> Result<(), String> {
+    objs.pkgs[pkg].set_name(data.name);
+    for dep in deps.iter() {
+        objs.pkgs[pkg].add_import(*dep);
+    }
+    let mut r = Reader {
+        objs,
+        pkg,
+        deps,
+        named: Map::new(),
+        ifaces: vec![],
+    };
+    // declare all named types first, they can be referred to before being declared
+    for decl in data.decls.iter() {
+        let list = decl.list()?;
+        if list.first().map(|x| x.atom()) == Some(Ok("type")) {
+            r.declare_named(list.get(1).ok_or("missing type name")?.string()?);
+        }
+    }
+    for decl in data.decls.iter() {
+        r.read_decl(decl.list()?)?;
+    }
+    for t in r.ifaces.iter() {
+        let objs = &*r.objs;
+        objs.types[*t].try_as_interface().unwrap().complete(objs);
+    }
+    r.objs.pkgs[pkg].mark_complete();
+    Ok(())
+}
+
+struct Reader<'a> {
+    objs: &'a mut TCObjects,
+    pkg: PackageKey,
+    deps: Vec<PackageKey>,
+    // named types of pkg
+    named: Map<String, TypeKey>,
+    // interfaces to be completed when all types are set up
+    ifaces: Vec<TypeKey>,
+}
+
+impl<'a> Reader<'a> {
+    fn declare_named(&mut self, name: &str) {
+        let t = self.objs.new_t_named(None, None, vec![]);
+        let okey = self
+            .objs
+            .new_type_name(0, Some(self.pkg), name.to_owned(), Some(t));
+        self.objs.types[t].try_as_named_mut().unwrap().set_obj(okey);
+        self.named.insert(name.to_owned(), t);
+        self.declare(okey);
+    }
+
+    fn declare(&mut self, okey: ObjKey) {
+        let scope = *self.objs.pkgs[self.pkg].scope();
+        Scope::insert(scope, okey, self.objs);
+    }
+
+    fn read_decl(&mut self, list: &[Sexp]) -> Result<(), String> {
+        let kind = list.first().ok_or("empty declaration")?.atom()?;
+        let name = list.get(1).ok_or("missing name")?.string()?.to_owned();
+        let arg = |i: usize| list.get(i).ok_or(format!("invalid declaration of {}", name));
+        match kind {
+            "type" => {
+                let t = self.named[&name];
+                let underlying = self.read_type(arg(2)?)?;
+                self.objs.types[t]
+                    .try_as_named_mut()
+                    .unwrap()
+                    .set_underlying(underlying);
+                for m in list[3..].iter() {
+                    let m = m.list()?;
+                    if m.len() != 5 || m[0].atom()? != "method" {
+                        return Err(format!("invalid method of {}", name));
+                    }
+                    let ptr = m[3].bool()?;
+                    let recv_type = match ptr {
+                        true => self.objs.new_t_pointer(t),
+                        false => t,
+                    };
+                    let recv =
+                        self.objs
+                            .new_param_var(0, Some(self.pkg), "".to_owned(), Some(recv_type));
+                    let sig = self.read_type(&m[4])?;
+                    self.objs.types[sig]
+                        .try_as_signature_mut()
+                        .ok_or(format!("invalid method of {}", name))?
+                        .set_recv(Some(recv));
+                    let pkg = self.read_pkg_ref(&m[2])?;
+                    let func = self
+                        .objs
+                        .new_func(0, pkg, m[1].string()?.to_owned(), Some(sig));
+                    self.objs.lobjs[func]
+                        .entity_type_mut()
+                        .func_set_has_ptr_recv(ptr);
+                    self.objs.types[t]
+                        .try_as_named_mut()
+                        .unwrap()
+                        .methods_mut()
+                        .push(func);
+                }
+            }
+            "alias" => {
+                let t = self.read_type(arg(2)?)?;
+                let okey = self.objs.new_type_name(0, Some(self.pkg), name, Some(t));
+                self.declare(okey);
+            }
+            "const" => {
+                let t = self.read_type(arg(2)?)?;
+                let val = read_value(arg(3)?)?;
+                let okey = self.objs.new_const(0, Some(self.pkg), name, Some(t), val);
+                self.declare(okey);
+            }
+            "var" => {
+                let t = self.read_type(arg(2)?)?;
+                let okey = self.objs.new_var(0, Some(self.pkg), name, Some(t));
+                self.declare(okey);
+            }
+            "func" => {
+                let t = self.read_type(arg(2)?)?;
+                let okey = self.objs.new_func(0, Some(self.pkg), name, Some(t));
+                self.declare(okey);
+            }
+            _ => return Err(format!("unknown declaration {}", kind)),
+        }
+        Ok(())
+    }
+
+    fn read_type(&mut self, sexp: &Sexp) -> Result<TypeKey, String> {
+        let list = sexp.list()?;
+        let kind = list.first().ok_or("empty type")?.atom()?;
+        let arg = |i: usize| list.get(i).ok_or(format!("invalid {} type", kind));
+        let t = match kind {
+            "basic" => {
+                let name = arg(1)?.string()?;
+                let univ = self.objs.universe();
+                univ.types()
+                    .values()
+                    .chain([*univ.byte(), *univ.rune()].iter())
+                    .find(|x| self.objs.types[**x].try_as_basic().unwrap().name() == name)
+                    .copied()
+                    .ok_or(format!("unknown basic type {}", name))?
+            }
+            "array" => {
+                let len = u64::from_str(arg(1)?.atom()?).map_err(|e| e.to_string())?;
+                let elem = self.read_type(arg(2)?)?;
+                self.objs.new_t_array(elem, Some(len))
+            }
+            "slice" => {
+                let elem = self.read_type(arg(1)?)?;
+                self.objs.new_t_slice(elem)
+            }
+            "struct" => {
+                let mut fields = vec![];
+                let mut tags = vec![];
+                for f in list[1..].iter() {
+                    let f = f.list()?;
+                    if f.len() != 6 || f[0].atom()? != "field" {
+                        return Err("invalid struct field".to_owned());
+                    }
+                    let pkg = self.read_pkg_ref(&f[2])?;
+                    let typ = self.read_type(&f[3])?;
+                    let name = f[1].string()?.to_owned();
+                    fields.push(self.objs.new_field(0, pkg, name, Some(typ), f[4].bool()?));
+                    tags.push(match &f[5] {
+                        Sexp::Str(s) => Some(s.clone()),
+                        _ => None,
+                    });
+                }
+                let tags = tags.iter().any(|x| x.is_some()).then_some(tags);
+                self.objs.new_t_struct(fields, tags)
+            }
+            "ptr" => {
+                let base = self.read_type(arg(1)?)?;
+                self.objs.new_t_pointer(base)
+            }
+            "tuple" => {
+                let mut vars = vec![];
+                for v in list[1..].iter() {
+                    let v = v.list()?;
+                    if v.len() != 3 || v[0].atom()? != "var" {
+                        return Err("invalid tuple".to_owned());
+                    }
+                    let typ = self.read_type(&v[2])?;
+                    let name = v[1].string()?.to_owned();
+                    vars.push(self.objs.new_param_var(0, Some(self.pkg), name, Some(typ)));
+                }
+                self.objs.new_t_tuple(vars)
+            }
+            "func" => {
+                let params = self.read_type(arg(1)?)?;
+                let results = self.read_type(arg(2)?)?;
+                let variadic = arg(3)?.bool()?;
+                self.objs
+                    .new_t_signature(None, None, params, results, variadic)
+            }
+            "interface" => {
+                let mut methods = vec![];
+                for m in list[1..].iter() {
+                    let m = m.list()?;
+                    if m.len() != 4 || m[0].atom()? != "method" {
+                        return Err("invalid interface method".to_owned());
+                    }
+                    let pkg = self.read_pkg_ref(&m[2])?;
+                    let sig = self.read_type(&m[3])?;
+                    let name = m[1].string()?.to_owned();
+                    methods.push(self.objs.new_func(0, pkg, name, Some(sig)));
+                }
+                let t = self.objs.new_t_interface(methods, vec![]);
+                self.ifaces.push(t);
+                t
+            }
+            "map" => {
+                let key = self.read_type(arg(1)?)?;
+                let elem = self.read_type(arg(2)?)?;
+                self.objs.new_t_map(key, elem)
+            }
+            "chan" => {
+                let dir = match arg(1)?.atom()? {
+                    "both" => ChanDir::SendRecv,
+                    "send" => ChanDir::SendOnly,
+                    "recv" => ChanDir::RecvOnly,
+                    s => return Err(format!("invalid channel direction {}", s)),
+                };
+                let elem = self.read_type(arg(2)?)?;
+                self.objs.new_t_chan(dir, elem)
+            }
+            "named" => {
+                let name = arg(2)?.string()?;
+                match self.read_pkg_ref(arg(1)?)? {
+                    Some(p) if p == self.pkg => *self
+                        .named
+                        .get(name)
+                        .ok_or(format!("undeclared type {}", name))?,
+                    Some(p) => self.lookup_type(*self.objs.pkgs[p].scope(), name)?,
+                    None => return Err(format!("invalid package of type {}", name)),
+                }
+            }
+            "universe" => {
+                let scope = *self.objs.universe().scope();
+                self.lookup_type(scope, arg(1)?.string()?)?
+            }
+            _ => return Err(format!("unknown type {}", kind)),
+        };
+        Ok(t)
+    }
+
+    fn lookup_type(&self, scope: ScopeKey, name: &str) -> Result<TypeKey, String> {
+        self.objs.scopes[scope]
+            .lookup(name)
+            .map(|x| &self.objs.lobjs[*x])
+            .filter(|x| x.entity_type().is_type_name())
+            .and_then(|x| x.typ())
+            .ok_or(format!("type {} not found", name))
+    }
+
+    fn read_pkg_ref(&self, sexp: &Sexp) -> Result<Option<PackageKey>, String> {
+        match sexp.atom()? {
+            "_" => Ok(None),
+            "0" => Ok(Some(self.pkg)),
+            s => {
+                let i = usize::from_str(s).map_err(|e| e.to_string())?;
+                self.deps
+                    .get(i.wrapping_sub(1))
+                    .map(|x| Some(*x))
+                    .ok_or(format!("invalid package index {}", i))
+            }
+        }
+    }
+}
+
+fn read_value(sexp: &Sexp) -> Result<Value, String> {
+    let list = sexp.list()?;
+    let kind = list.first().ok_or("empty value")?.atom()?;
+    let arg = |i: usize| list.get(i).ok_or(format!("invalid {} value", kind));
+    let big_int = |s: &str| BigInt::from_str(s).map_err(|e| e.to_string());
+    let val = match kind {
+        "unknown" => Value::Unknown,
+        "bool" => Value::Bool(arg(1)?.bool()?),
+        "string" => Value::with_str(arg(1)?.string()?.to_owned()),
+        "bytes" => {
+            let hex = arg(1)?.string()?;
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| {
+                    hex.get(i..i + 2)
+                        .and_then(|x| u8::from_str_radix(x, 16).ok())
+                })
+                .collect::<Option<Vec<u8>>>()
+                .ok_or("invalid bytes value")?;
+            Value::with_bytes(bytes)
+        }
+        "int" => Value::Int(big_int(arg(1)?.string()?)?),
+        "rat" => Value::Rat(BigRational::new(
+            big_int(arg(1)?.string()?)?,
+            big_int(arg(2)?.string()?)?,
+        )),
+        "float" => {
+            let f = f64::from_str(arg(1)?.string()?).map_err(|e| e.to_string())?;
+            Value::Float(f.into())
+        }
+        "complex" => Value::Complex(
+            Box::new(read_value(arg(1)?)?),
+            Box::new(read_value(arg(2)?)?),
+        ),
+        _ => return Err(format!("unknown value {}", kind)),
+    };
+    Ok(val)
+}
+
+#[derive(Debug)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn list(&self) -> Result<&[Sexp], String> {
+        match self {
+            Sexp::List(l) => Ok(l),
+            _ => Err(format!("list expected, found {:?}", self)),
+        }
+    }
+
+    fn atom(&self) -> Result<&str, String> {
+        match self {
+            Sexp::Atom(a) => Ok(a),
+            _ => Err(format!("atom expected, found {:?}", self)),
+        }
+    }
+
+    fn string(&self) -> Result<&str, String> {
+        match self {
+            Sexp::Str(s) => Ok(s),
+            _ => Err(format!("string expected, found {:?}", self)),
+        }
+    }
+
+    fn bool(&self) -> Result<bool, String> {
+        match self.atom()? {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            s => Err(format!("bool expected, found {}", s)),
+        }
+    }
+}
+
+struct SexpParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> SexpParser<'a> {
+    fn new(s: &'a str) -> SexpParser<'a> {
+        SexpParser {
+            chars: s.chars().peekable(),
+        }
+    }
+
+    fn parse_all(&mut self) -> Result<Vec<Sexp>, String> {
+        let mut result = vec![];
+        loop {
+            self.skip_spaces();
+            match self.chars.peek() {
+                None => return Ok(result),
+                Some(_) => result.push(self.parse()?),
+            }
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn parse(&mut self) -> Result<Sexp, String> {
+        self.skip_spaces();
+        match self.chars.next() {
+            Some('(') => {
+                let mut list = vec![];
+                loop {
+                    self.skip_spaces();
+                    match self.chars.peek() {
+                        Some(')') => {
+                            self.chars.next();
+                            return Ok(Sexp::List(list));
+                        }
+                        Some(_) => list.push(self.parse()?),
+                        None => return Err("unexpected end of export data".to_owned()),
+                    }
+                }
+            }
+            Some('"') => self.parse_string().map(Sexp::Str),
+            Some(c) if c != ')' => {
+                let mut atom = c.to_string();
+                while let Some(c) = self.chars.peek() {
+                    if c.is_whitespace() || *c == '(' || *c == ')' || *c == '"' {
+                        break;
+                    }
+                    atom.push(*c);
+                    self.chars.next();
+                }
+                Ok(Sexp::Atom(atom))
+            }
+            _ => Err("unexpected ')' in export data".to_owned()),
+        }
+    }
+
+    /// Parses a string written with Rust's Debug formatting.
+    fn parse_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('0') => s.push('\0'),
+                    Some('u') => {
+                        let mut hex = String::new();
+                        if self.chars.next() != Some('{') {
+                            return Err("invalid unicode escape".to_owned());
+                        }
+                        loop {
+                            match self.chars.next() {
+                                Some('}') => break,
+                                Some(c) => hex.push(c),
+                                None => return Err("invalid unicode escape".to_owned()),
+                            }
+                        }
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or("invalid unicode escape")?;
+                        s.push(c);
+                    }
+                    Some(c) => s.push(c),
+                    None => break,
+                },
+                Some(c) => s.push(c),
+                None => break,
+            }
+        }
+        Err("unterminated string in export data".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sexp_string() {
+        let s = "a\"b\\c\n\t\u{7f}é";
+        let sexps = SexpParser::new(&format!("(x {:?})", s)).parse_all().unwrap();
+        assert_eq!(sexps[0].list().unwrap()[1].string().unwrap(), s);
+    }
+}
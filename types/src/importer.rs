@@ -9,18 +9,51 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use super::build::BuildContext;
 use super::check::{Checker, TypeInfo};
+use super::export;
 use super::objects::{PackageKey, TCObjects};
 use go_parser::ast;
 use go_parser::{AstObjects, ErrorList, FileSet, Map, Parser, Pos};
 use std::io;
 use std::path::{Path, PathBuf};
 
+#[derive(Clone, Debug, Default)]
 pub struct TraceConfig {
     //print debug info in parser
     pub trace_parser: bool,
     // print debug info in checker
     pub trace_checker: bool,
+    // how imports that are not used are reported
+    pub unused_imports: Severity,
+    // how local variables that are not used are reported
+    pub unused_vars: Severity,
+    // where the reports with Severity::Warning go
+    pub warnings: ErrorList,
+    // how deep expressions and types can nest, DEFAULT_MAX_DEPTH if none
+    pub max_depth: Option<usize>,
+    // whether TypeInfo::spans is filled, for TypeInfo::type_at
+    pub record_spans: bool,
+    // whether the parser keeps the doc comments, for package_doc
+    pub parse_docs: bool,
+}
+
+/// How deep expressions and types can nest by default. The checker descends
+/// into them recursively, so the deeper ones are rejected rather than let
+/// overflow the stack.
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+/// How the checker reports a problem that doesn't stop the program from
+/// compiling, like a variable that is declared but not used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Severity {
+    /// reported as an error, as the Go compiler does
+    #[default]
+    Error,
+    /// reported to `TraceConfig::warnings`, the check doesn't fail
+    Warning,
+    /// not reported
+    Ignore,
 }
 
 pub trait SourceRead {
@@ -37,6 +70,11 @@ pub trait SourceRead {
     fn is_dir(&self, path: &Path) -> bool;
 
     fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)>;
+
+    /// Decides which files of a package directory are loaded, defaults to the host.
+    fn build_context(&self) -> BuildContext {
+        BuildContext::default()
+    }
 }
 
 /// ImportKey identifies an imported package by import path and source directory
@@ -70,6 +108,11 @@ pub struct Importer<'a, S: SourceRead> {
     tc_objs: &'a mut TCObjects,
     errors: &'a ErrorList,
     pos: Pos,
+    // packages being checked when this import is requested, for cycle detection
+    import_chain: Vec<PackageKey>,
+    // name and content without the package clause of a file to add to the
+    // next package parsed
+    generated_file: Option<(String, String)>,
 }
 
 impl<'a, S: SourceRead> Importer<'a, S> {
@@ -94,22 +137,42 @@ impl<'a, S: SourceRead> Importer<'a, S> {
             tc_objs: tc_objs,
             errors: errors,
             pos: pos,
+            import_chain: vec![],
+            generated_file: None,
         }
     }
 
-    pub fn import(&mut self, key: &'a ImportKey) -> Result<PackageKey, ()> {
+    pub fn with_import_chain(mut self, chain: Vec<PackageKey>) -> Importer<'a, S> {
+        self.import_chain = chain;
+        self
+    }
+
+    /// Adds a file to the package imported first, e.g. to check an expression
+    /// in the scope of that package. `body` is the content of the file after
+    /// the package clause.
+    pub fn with_generated_file(mut self, name: String, body: String) -> Importer<'a, S> {
+        self.generated_file = Some((name, body));
+        self
+    }
+
+    pub fn import(&mut self, key: &ImportKey) -> Result<PackageKey, ()> {
         if key.path == "unsafe" {
             return Ok(*self.tc_objs.universe().unsafe_pkg());
         }
 
         match self.reader.canonicalize_import(key) {
-            Ok((path, import_path)) => match self.pkgs.get(&import_path) {
-                Some(key) => Ok(*key),
+            // a local package can be reached by different relative import paths,
+            // so packages are identified by their canonicalized paths
+            Ok((path, import_path)) => match self.pkgs.get(&*path.to_string_lossy()) {
+                Some(key) => match self.import_chain.iter().position(|x| x == key) {
+                    Some(i) => self.cycle_error(&self.import_chain[i..]),
+                    None => Ok(*key),
+                },
                 None => {
-                    let pkg = self.tc_objs.new_package(import_path.clone());
-                    self.pkgs.insert(import_path, pkg);
+                    let pkg = self.tc_objs.new_package(import_path);
+                    self.pkgs.insert(path.to_string_lossy().to_string(), pkg);
                     let files = self.parse_path(&path)?;
-                    Checker::new(
+                    let mut checker = Checker::new(
                         self.tc_objs,
                         self.ast_objs,
                         self.fset,
@@ -119,11 +182,72 @@ impl<'a, S: SourceRead> Importer<'a, S> {
                         pkg,
                         self.trace_config,
                         self.reader,
-                    )
-                    .check(files)
+                    );
+                    checker.import_chain = self.import_chain.clone();
+                    checker.import_chain.push(pkg);
+                    checker.check(files)
                 }
             },
-            Err(e) => self.error(format!("canonicalize import error: {}", e)),
+            Err(e) => {
+                // fall back to export data if there is no source code
+                let export_key = ImportKey::new(
+                    &format!("{}.{}", key.path, export::EXPORT_DATA_EXT),
+                    &key.dir,
+                );
+                match self.reader.canonicalize_import(&export_key) {
+                    Ok((path, import_path)) => self.import_export_data(&path, import_path, key),
+                    Err(_) => self.error(format!("canonicalize import error: {}", e)),
+                }
+            }
+        }
+    }
+
+    fn import_export_data(
+        &mut self,
+        path: &Path,
+        import_path: String,
+        key: &ImportKey,
+    ) -> Result<PackageKey, ()> {
+        if let Some(pkg) = self.pkgs.get(&*path.to_string_lossy()) {
+            return match self.import_chain.iter().position(|x| x == pkg) {
+                Some(i) => self.cycle_error(&self.import_chain[i..]),
+                None => Ok(*pkg),
+            };
+        }
+        let data = match self.reader.read_file(path) {
+            Ok(content) => export::parse_export_data(&content),
+            Err(e) => return self.error(format!("failed to read {}: {}", path.display(), e)),
+        };
+        let data = match data {
+            Ok(d) => d,
+            Err(e) => return self.error(format!("invalid export data {}: {}", path.display(), e)),
+        };
+        let import_path = import_path
+            .strip_suffix(&format!(".{}", export::EXPORT_DATA_EXT))
+            .map(|x| x.to_owned())
+            .unwrap_or(import_path);
+        let pkg = self.tc_objs.new_package(import_path);
+        // named before the deps are imported, for reporting cycles
+        self.tc_objs.pkgs[pkg].set_name(data.name.clone());
+        self.pkgs.insert(path.to_string_lossy().to_string(), pkg);
+        let dir = path
+            .parent()
+            .map_or(key.dir.clone(), |x| x.to_string_lossy().to_string());
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        self.import_chain.push(pkg);
+        let deps = data
+            .deps
+            .iter()
+            .map(|dep| {
+                let dep = local_export_dep(&stem, dep).unwrap_or(dep.clone());
+                self.import(&ImportKey::new(&dep, &dir))
+            })
+            .collect::<Result<Vec<_>, ()>>();
+        self.import_chain.pop();
+        let deps = deps?;
+        match export::build_package(self.tc_objs, pkg, data, deps) {
+            Ok(()) => Ok(pkg),
+            Err(e) => self.error(format!("invalid export data {}: {}", path.display(), e)),
         }
     }
 
@@ -135,26 +259,12 @@ impl<'a, S: SourceRead> Importer<'a, S> {
                 } else {
                     let mut afiles = vec![];
                     for (full_name, content) in contents.into_iter() {
-                        let mut pfile = self.fset.add_file(
-                            full_name,
-                            Some(self.fset.base()),
-                            content.chars().count(),
-                        );
-                        let afile = Parser::new(
-                            self.ast_objs,
-                            &mut pfile,
-                            self.errors,
-                            &content,
-                            self.trace_config.trace_parser,
-                        )
-                        .parse_file();
-                        if afile.is_none() {
-                            // parse error, the details should be in the errorlist already.
-                            // give up
-                            return Err(());
-                        } else {
-                            afiles.push(afile.unwrap());
-                        }
+                        afiles.push(self.parse_file(full_name, &content)?);
+                    }
+                    if let Some((name, body)) = self.generated_file.take() {
+                        let pkg_name = &self.ast_objs.idents[afiles[0].name].name;
+                        let content = format!("package {}\n\n{}", pkg_name, body);
+                        afiles.push(self.parse_file(name, &content)?);
                     }
                     Ok(afiles)
                 }
@@ -167,6 +277,33 @@ impl<'a, S: SourceRead> Importer<'a, S> {
         }
     }
 
+    fn parse_file(&mut self, name: String, content: &str) -> Result<ast::File, ()> {
+        let pfile = self
+            .fset
+            .add_file(name, Some(self.fset.base()), content.chars().count());
+        let trace = self.trace_config.trace_parser;
+        let mut parser = match self.trace_config.parse_docs {
+            true => Parser::with_docs(self.ast_objs, pfile, self.errors, content, trace),
+            false => Parser::new(self.ast_objs, pfile, self.errors, content, trace),
+        };
+        // on parse error, the details should be in the errorlist already
+        parser.parse_file().ok_or(())
+    }
+
+    /// Reports an import cycle, `cycle` starts with the package that is imported
+    /// again and ends with the package importing it.
+    fn cycle_error<T>(&self, cycle: &[PackageKey]) -> Result<T, ()> {
+        let names: Vec<&str> = cycle
+            .iter()
+            .chain(cycle.first())
+            .map(|x| self.tc_objs.pkgs[*x].name().as_deref().unwrap_or("_"))
+            .collect();
+        self.error(format!(
+            "import cycle not allowed: package {}",
+            names.join(" imports ")
+        ))
+    }
+
     fn error<T>(&self, err: String) -> Result<T, ()> {
         self.errors
             .add(self.fset.position(self.pos), err, false, false);
@@ -174,17 +311,57 @@ impl<'a, S: SourceRead> Importer<'a, S> {
     }
 }
 
+/// Local dependencies in export data are relative to the source directory of
+/// the package, which export data file "lib.gosx" stands for "lib", returns
+/// the path relative to the directory of the export data file.
+fn local_export_dep(stem: &str, dep: &str) -> Option<String> {
+    if !(dep == "." || dep == ".." || dep.starts_with("./") || dep.starts_with("../")) {
+        return None;
+    }
+    let mut parts = vec![stem];
+    for c in dep.split('/') {
+        match c {
+            "" | "." => {}
+            ".." => match parts.last() {
+                Some(&p) if p != ".." => {
+                    parts.pop();
+                }
+                _ => parts.push(".."),
+            },
+            _ => parts.push(c),
+        }
+    }
+    match parts.first() {
+        Some(&"..") => Some(parts.join("/")),
+        _ => Some(format!("./{}", parts.join("/"))),
+    }
+}
+
 fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, String)>> {
     let working_dir = reader.working_dir().canonicalize().ok();
+    let build_ctx = reader.build_context();
     let mut result = vec![];
-    let mut read = |path: PathBuf| -> io::Result<()> {
+    // build constraints only apply to files found in a directory
+    let mut read = |path: PathBuf, constrained: bool| -> io::Result<()> {
         if let Some(ext) = path.extension() {
             if ext == "gos" || ext == "go" || ext == "src" {
                 if let Some(fs) = path.file_stem() {
-                    let s = fs.to_str();
-                    if s.is_some() && !s.unwrap().ends_with("_test") {
+                    if let Some(s) = fs.to_str().filter(|x| !x.ends_with("_test")) {
+                        if constrained && !build_ctx.match_file_name(s) {
+                            return Ok(());
+                        }
                         let p = path.as_path();
                         let content = reader.read_file(p)?;
+                        if constrained {
+                            match build_ctx.match_content(&content) {
+                                Ok(true) => {}
+                                Ok(false) => return Ok(()),
+                                Err(e) => {
+                                    let msg = format!("{}: {}", p.display(), e);
+                                    return Err(io::Error::other(msg));
+                                }
+                            }
+                        }
                         // try get short display name for the file
                         let full_name = match &working_dir {
                             Some(wd) => p.strip_prefix(wd).unwrap_or(p),
@@ -204,10 +381,10 @@ fn read_content(p: &Path, reader: &dyn SourceRead) -> io::Result<Vec<(String, St
         let mut paths = reader.read_dir(p)?;
         paths.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
         for p in paths.into_iter() {
-            read(p)?;
+            read(p, true)?;
         }
     } else if reader.is_file(p) {
-        read(p.to_path_buf())?;
+        read(p.to_path_buf(), false)?;
     }
     if result.len() == 0 {
         return Err(io::Error::new(io::ErrorKind::Other, "no file/dir found"));
@@ -16,7 +16,10 @@ mod package;
 mod scope;
 #[macro_use]
 mod objects;
+mod build;
 mod display;
+mod doc;
+mod export;
 mod importer;
 mod lookup;
 mod operand;
@@ -27,8 +30,11 @@ mod universe;
 pub mod typ;
 pub mod check;
 
+pub use build::BuildContext;
 pub use constant::Value as ConstValue;
 pub use display::Displayer;
+pub use doc::{check_doc, package_doc, DeclDoc, PackageDoc};
+pub use export::{check_export, check_export_deps, write_export_data, EXPORT_DATA_EXT};
 pub use importer::*;
 pub use obj::*;
 pub use objects::*;
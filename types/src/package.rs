@@ -105,7 +105,11 @@ impl Package {
         f: &mut fmt::Formatter<'_>,
         qf: &dyn Fn(&Package) -> Cow<str>,
     ) -> fmt::Result {
-        write!(f, "{}.", qf(self))
+        let q = qf(self);
+        if q.is_empty() {
+            return Ok(());
+        }
+        write!(f, "{}.", q)
     }
 }
 
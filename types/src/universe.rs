@@ -33,6 +33,7 @@ pub enum ExprKind {
 pub enum Builtin {
     Append,
     Cap,
+    Clear,
     Close,
     Complex,
     Copy,
@@ -40,6 +41,8 @@ pub enum Builtin {
     Imag,
     Len,
     Make,
+    Max,
+    Min,
     New,
     Panic,
     Print,
@@ -342,6 +345,7 @@ impl Universe {
             // use vec becasue array doesn't have into_iter()!
             (Builtin::Append, "append", 1, true, ExprKind::Expression),
             (Builtin::Cap, "cap", 1, false, ExprKind::Expression),
+            (Builtin::Clear, "clear", 1, false, ExprKind::Statement),
             (Builtin::Close, "close", 1, false, ExprKind::Statement),
             (Builtin::Complex, "complex", 2, false, ExprKind::Expression),
             (Builtin::Copy, "copy", 2, false, ExprKind::Statement),
@@ -349,6 +353,8 @@ impl Universe {
             (Builtin::Imag, "imag", 1, false, ExprKind::Expression),
             (Builtin::Len, "len", 1, false, ExprKind::Expression),
             (Builtin::Make, "make", 1, true, ExprKind::Expression),
+            (Builtin::Max, "max", 1, true, ExprKind::Expression),
+            (Builtin::Min, "min", 1, true, ExprKind::Expression),
             (Builtin::New, "new", 1, false, ExprKind::Expression),
             (Builtin::Panic, "panic", 1, false, ExprKind::Statement),
             (Builtin::Print, "print", 0, true, ExprKind::Statement),
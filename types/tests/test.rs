@@ -169,12 +169,18 @@ fn parse_error(s: &str, line: usize) -> io::Result<Vec<ErrInfo>> {
 }
 
 fn test_file(path: &str, trace: bool) {
-    dbg!(path);
-    let pkgs = &mut Map::new();
     let config = types::TraceConfig {
         trace_parser: trace,
         trace_checker: trace,
+        ..Default::default()
     };
+    test_file_with(path, &config);
+}
+
+fn test_file_with(path: &str, config: &types::TraceConfig) {
+    dbg!(path);
+    let trace = config.trace_checker;
+    let pkgs = &mut Map::new();
     let reader = FsReader::new(None, None);
     let fs = &mut fe::FileSet::new();
     let asto = &mut fe::AstObjects::new();
@@ -198,7 +204,8 @@ fn test_file(path: &str, trace: bool) {
         if e.msg.starts_with('\t') || e.by_parser {
             continue;
         }
-        if let Some(errs) = expected_errs.get_mut(&e.pos.line) {
+        let key = (file_name(Path::new(e.pos.filename.as_str())), e.pos.line);
+        if let Some(errs) = expected_errs.get_mut(&key) {
             let mut found = false;
             for info in errs.iter_mut() {
                 if !info.checked {
@@ -229,17 +236,22 @@ fn test_file(path: &str, trace: bool) {
     }
 }
 
-fn parse_comment_errors<P>(path: P) -> io::Result<Map<usize, Vec<ErrInfo>>>
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+/// The expected errors by the names of the files and the lines they are on.
+fn parse_comment_errors<P>(path: P) -> io::Result<Map<(String, usize), Vec<ErrInfo>>>
 where
     P: AsRef<Path>,
 {
     let mut result = Map::new();
-    let mut parse_file = |lines: io::Lines<io::BufReader<File>>| -> io::Result<()> {
-        for (i, x) in lines.enumerate() {
+    let mut parse_file = |path: &Path| -> io::Result<()> {
+        for (i, x) in read_lines(path)?.enumerate() {
             let t = x?;
             let mut errors = parse_error(&t, i + 1)?;
             if !errors.is_empty() {
-                let entry = result.entry(i + 1).or_insert(vec![]);
+                let entry = result.entry((file_name(path), i + 1)).or_insert(vec![]);
                 entry.append(&mut errors);
             }
         }
@@ -247,15 +259,13 @@ where
     };
 
     if path.as_ref().is_file() {
-        let lines = read_lines(path)?;
-        parse_file(lines)?;
+        parse_file(path.as_ref())?;
     } else if path.as_ref().is_dir() {
         for entry in fs::read_dir(path)? {
             let entry = entry?;
             let path = entry.path();
             if !path.is_dir() {
-                let lines = read_lines(path)?;
-                parse_file(lines)?;
+                parse_file(&path)?;
             }
         }
     }
@@ -272,6 +282,7 @@ fn test_auto() {
     test_file("./tests/data/constdecl.gos", trace);
     test_file("./tests/data/conversions.gos", trace);
     test_file("./tests/data/conversions2.gos", trace);
+    test_file("./tests/data/overflow.gos", trace);
     test_file("./tests/data/cycles.gos", trace);
     test_file("./tests/data/cycles1.gos", trace);
     test_file("./tests/data/cycles2.gos", trace);
@@ -305,7 +316,141 @@ fn test_auto() {
     test_file("./tests/data/vardecl.src", trace);
 }
 
+#[test]
+fn test_export_data() {
+    let config = types::TraceConfig {
+        trace_parser: false,
+        trace_checker: false,
+        ..Default::default()
+    };
+    let reader = FsReader::new(None, None);
+    let data = types::check_export(Path::new("./tests/data/export/lib_src"), &config, &reader)
+        .unwrap();
+    if std::env::var("GOSCRIPT_UPDATE_EXPORT").is_ok() {
+        fs::write("./tests/data/export/lib.gosx", &data).unwrap();
+    }
+    // main.gos imports "./lib", which only exists as export data
+    assert_eq!(
+        data,
+        fs::read_to_string("./tests/data/export/lib.gosx").unwrap()
+    );
+    test_file("./tests/data/export/main.gos", false);
+}
+
+#[test]
+fn test_doc() {
+    let config = types::TraceConfig::default();
+    let reader = FsReader::new(None, None);
+    let doc = types::check_doc(Path::new("./tests/data/doc/lib"), &config, &reader).unwrap();
+    let (md, json) = (doc.to_markdown(), doc.to_json());
+    if std::env::var("GOSCRIPT_UPDATE_DOC").is_ok() {
+        fs::write("./tests/data/doc/lib.md", &md).unwrap();
+        fs::write("./tests/data/doc/lib.json", &json).unwrap();
+    }
+    assert_eq!(md, fs::read_to_string("./tests/data/doc/lib.md").unwrap());
+    assert_eq!(json, fs::read_to_string("./tests/data/doc/lib.json").unwrap());
+}
+
+#[test]
+fn test_type_info_query() {
+    let path = "./tests/data/query.gos";
+    let src = fs::read_to_string(path).unwrap();
+    let config = types::TraceConfig {
+        record_spans: true,
+        ..Default::default()
+    };
+    let reader = FsReader::new(None, None);
+    let fs = &mut fe::FileSet::new();
+    let asto = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let tco = &mut types::TCObjects::new();
+    let results = &mut Map::new();
+    let pkgs = &mut Map::new();
+    let importer = &mut types::Importer::new(&config, &reader, fs, pkgs, results, asto, tco, el, 0);
+    let pkg = importer.import(&types::ImportKey::new(path, "./")).unwrap();
+    assert_eq!(el.len(), 0);
+    let info = &results[&pkg];
+    let base = fs.iter().next().unwrap().base();
+    let pos = |s: &str| base + src.find(s).unwrap();
+    let type_name = |t: types::TypeKey| format!("{}", types::Displayer::new(&t, None, Some(tco)));
+
+    // the innermost expression: t in t.n, then t.n, then t.n + 1
+    let named_t = info.def_at(pos("T struct"), asto).unwrap().1.unwrap();
+    let (_, tv) = info.type_at(pos("t.n")).unwrap();
+    assert_eq!(Some(tv.typ), tco.lobjs[named_t].typ());
+    let (_, tv) = info.type_at(pos("n + 1")).unwrap();
+    assert_eq!(type_name(tv.typ), "int");
+    let (_, tv) = info.type_at(pos("+ 1")).unwrap();
+    assert_eq!(type_name(tv.typ), "int");
+    let (_, tv) = info.type_at(pos("1\n")).unwrap();
+    assert!(tv.get_const_val().is_some());
+    assert!(info.type_at(pos("package")).is_none());
+
+    let (_, obj) = info.def_at(pos("x :="), asto).unwrap();
+    assert_eq!(tco.lobjs[obj.unwrap()].name(), "x");
+    assert_eq!(tco.lobjs[named_t].name(), "T");
+    assert!(info.def_at(pos("t.n"), asto).is_none());
+    let (_, obj) = info.use_at(pos("t.n"), asto).unwrap();
+    let t = info.def_at(pos("t T"), asto).unwrap().1.unwrap();
+    assert_eq!(obj, t);
+
+    // the case clause of a type switch implicitly declares v
+    let fdecl = match &info.ast_files[0].decls[1] {
+        fe::ast::Decl::Func(f) => &asto.fdecls[*f],
+        _ => unreachable!(),
+    };
+    let case = match &fdecl.body.as_ref().unwrap().list[1] {
+        fe::ast::Stmt::TypeSwitch(ts) => &ts.body.list[0],
+        _ => unreachable!(),
+    };
+    let v = info.implicit_for(case).unwrap();
+    assert_eq!(tco.lobjs[v].name(), "v");
+    assert_eq!(type_name(tco.lobjs[v].typ().unwrap()), "int");
+}
+
+#[test]
+fn test_struct_tags() {
+    let path = "./tests/data/tags.gos";
+    let config = types::TraceConfig::default();
+    let reader = FsReader::new(None, None);
+    let fs = &mut fe::FileSet::new();
+    let asto = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let tco = &mut types::TCObjects::new();
+    let results = &mut Map::new();
+    let pkgs = &mut Map::new();
+    let importer = &mut types::Importer::new(&config, &reader, fs, pkgs, results, asto, tco, el, 0);
+    let pkg = importer.import(&types::ImportKey::new(path, "./")).unwrap();
+    assert_eq!(el.len(), 0);
+    let scope = &tco.scopes[*tco.pkgs[pkg].scope()];
+    let named = tco.lobjs[*scope.lookup("T").unwrap()].typ().unwrap();
+    let underlying = tco.types[named].underlying().unwrap();
+    let detail = tco.types[underlying].try_as_struct().unwrap();
+    // the fields before the first tag have no tags
+    let tags: Vec<_> = (0..4).map(|i| detail.tag(i).map(|x| x.as_str())).collect();
+    assert_eq!(tags, vec![None, None, Some("k:\"c\""), None]);
+}
+
 #[test]
 fn test_temp() {
     test_file("./tests/data/temp.gos", true);
 }
+
+#[test]
+fn test_nil() {
+    test_file("./tests/data/nil.gos", false);
+}
+
+#[test]
+fn test_shifts() {
+    test_file("./tests/data/shifts.src", false);
+}
+
+#[test]
+fn test_deep() {
+    let config = types::TraceConfig {
+        max_depth: Some(10),
+        ..Default::default()
+    };
+    test_file_with("./tests/data/deep.src", &config);
+}
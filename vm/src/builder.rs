@@ -0,0 +1,416 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Building bytecode instruction by instruction, for the tools that target
+//! the VM without going through Go source, e.g. macro systems or the
+//! frontends of other languages.
+//!
+//! A function is built with a `FuncBuilder`, its operands are `Operand`s,
+//! which are resolved to the indices the VM reads when it's finished:
+//! the registers are put above the locals, which may be added after the
+//! code that uses them, and the jumps are patched to the labels they go to.
+//! `BytecodeBuilder::finish_function` checks the function before it's added
+//! and `BytecodeBuilder::build` returns the `Bytecode`, to run with `run`.
+//!
+//! ```
+//! use go_vm::types::*;
+//! use go_vm::*;
+//!
+//! let mut b = BytecodeBuilder::new();
+//! let pkg = b.new_package("main");
+//! let mint = b.prim_meta().mint;
+//! let sig = b.new_sig(vec![], vec![mint]);
+//! let mut f = b.new_function(Some(pkg), sig, FuncFlag::Default);
+//! let (one, two) = (b.add_const(1isize.into()), b.add_const(2isize.into()));
+//! f.emit(Inst::new(Opcode::ADD, f.result(0), one, two).with_types(ValueType::Int, ValueType::Void));
+//! f.ret();
+//! let entry = b.finish_function(f).unwrap();
+//! let code = b.build(entry, pkg).unwrap();
+//! let results = run_entry(&code, &FfiFactory::new(), &Limits::default(), &RunConfig::default())
+//!     .unwrap();
+//! assert_eq!(*results[0].as_int(), 3);
+//! ```
+
+use crate::ffi::CodeGenVMCtx;
+use crate::value::*;
+use std::fmt;
+
+/// A position in the code of a function, to jump to, see `FuncBuilder::bind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Label(usize);
+
+impl From<usize> for Label {
+    /// The label numbered `i` in its function, for the frontends that number
+    /// their labels themselves, like `go_codegen::ir`.
+    fn from(i: usize) -> Label {
+        Label(i)
+    }
+}
+
+/// An operand of an instruction being built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// The i-th of the results, then of the parameters, then of the locals
+    /// added with `FuncBuilder::add_local`.
+    Local(usize),
+    /// The i-th register, the registers are above the locals.
+    Reg(usize),
+    /// A constant, see `BytecodeBuilder::add_const`.
+    Const(usize),
+    /// An immediate value, e.g. the index of a package member.
+    Imm(OpIndex),
+    /// The offset of a jump to the label.
+    Label(Label),
+    Void,
+}
+
+/// An instruction being built, the same as `Instruction` with `Operand`s.
+#[derive(Clone, Copy, Debug)]
+pub struct Inst {
+    pub op0: Opcode,
+    pub op1: Opcode,
+    pub t0: ValueType,
+    pub t1: ValueType,
+    pub d: Operand,
+    pub s0: Operand,
+    pub s1: Operand,
+}
+
+impl Inst {
+    pub fn new(op: Opcode, d: Operand, s0: Operand, s1: Operand) -> Inst {
+        Inst {
+            op0: op,
+            op1: Opcode::VOID,
+            t0: ValueType::Void,
+            t1: ValueType::Void,
+            d,
+            s0,
+            s1,
+        }
+    }
+
+    pub fn with_types(mut self, t0: ValueType, t1: ValueType) -> Inst {
+        self.t0 = t0;
+        self.t1 = t1;
+        self
+    }
+
+    /// Sets `op1`, which some opcodes read as a third type.
+    pub fn with_op1_type(mut self, t: ValueType) -> Inst {
+        self.op1 = unsafe { std::mem::transmute::<ValueType, Opcode>(t) };
+        self
+    }
+}
+
+/// Why `BytecodeBuilder` refused a function or the bytecode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// the function has no code, or its last instruction is neither a
+    /// return nor a jump
+    NoReturn(FunctionKey),
+    /// a label is jumped to and never bound, or bound at the end of the code
+    UnboundLabel(FunctionKey, Label),
+    /// a label is bound twice
+    Rebound(FunctionKey, Label),
+    /// the operand of the instruction at the index is a local that's not
+    /// added, or a constant that's not
+    BadOperand(FunctionKey, usize, Operand),
+    /// the entry function is not finished
+    NoEntry(FunctionKey),
+    /// a package member is referred to by a name the package doesn't have
+    UnknownMember(FunctionKey, String),
+    /// the bytecode failed to link, see `Bytecode::link`
+    Link(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoReturn(func) => write!(f, "{:?} does not end with a return", func),
+            Self::UnboundLabel(func, l) => write!(f, "{:?} jumps to the unbound {:?}", func, l),
+            Self::Rebound(func, l) => write!(f, "{:?} binds {:?} twice", func, l),
+            Self::BadOperand(func, i, op) => {
+                write!(f, "{:?} uses the invalid {:?} at {}", func, op, i)
+            }
+            Self::NoEntry(func) => write!(f, "the entry {:?} is not finished", func),
+            Self::UnknownMember(func, n) => write!(f, "{:?} uses the unknown member {}", func, n),
+            Self::Link(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builds the code of a function, see the module docs.
+pub struct FuncBuilder {
+    key: FunctionKey,
+    flag: FuncFlag,
+    // the results and the parameters
+    fixed: usize,
+    ret_count: usize,
+    local_zeros: Vec<GosValue>,
+    code: Vec<Inst>,
+    labels: Vec<Option<usize>>,
+    rebound: Option<Label>,
+    // the package of a constructor, which its return marks initialized
+    pkg: Option<Operand>,
+}
+
+impl FuncBuilder {
+    pub fn key(&self) -> FunctionKey {
+        self.key
+    }
+
+    pub fn result(&self, i: usize) -> Operand {
+        assert!(i < self.ret_count, "no result {}", i);
+        Operand::Local(i)
+    }
+
+    pub fn param(&self, i: usize) -> Operand {
+        assert!(self.ret_count + i < self.fixed, "no parameter {}", i);
+        Operand::Local(self.ret_count + i)
+    }
+
+    /// Adds a local, set to `zero` whenever the function is called.
+    pub fn add_local(&mut self, zero: GosValue) -> Operand {
+        self.local_zeros.push(zero);
+        Operand::Local(self.fixed + self.local_zeros.len() - 1)
+    }
+
+    /// Emits an instruction and returns its index.
+    pub fn emit(&mut self, inst: Inst) -> usize {
+        self.code.push(inst);
+        self.code.len() - 1
+    }
+
+    /// The instruction at the index, to patch it.
+    pub fn inst_mut(&mut self, i: usize) -> &mut Inst {
+        &mut self.code[i]
+    }
+
+    pub fn next_index(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds the label to the next instruction emitted.
+    pub fn bind(&mut self, label: Label) {
+        // the labels made with `Label::from` are not all added yet
+        if label.0 >= self.labels.len() {
+            self.labels.resize(label.0 + 1, None);
+        }
+        let target = &mut self.labels[label.0];
+        if target.is_some() {
+            self.rebound.get_or_insert(label);
+        }
+        *target = Some(self.code.len());
+    }
+
+    pub fn jump(&mut self, label: Label) -> usize {
+        self.emit(Inst::new(
+            Opcode::JUMP,
+            Operand::Label(label),
+            Operand::Void,
+            Operand::Void,
+        ))
+    }
+
+    /// Jumps to the label if `cond`, a bool, is true.
+    pub fn jump_if(&mut self, cond: Operand, label: Label) -> usize {
+        self.emit(Inst::new(
+            Opcode::JUMP_IF,
+            Operand::Label(label),
+            cond,
+            Operand::Void,
+        ))
+    }
+
+    /// Jumps to the label if `cond`, a bool, is false.
+    pub fn jump_if_not(&mut self, cond: Operand, label: Label) -> usize {
+        self.emit(Inst::new(
+            Opcode::JUMP_IF_NOT,
+            Operand::Label(label),
+            cond,
+            Operand::Void,
+        ))
+    }
+
+    /// Calls the closure `cls`, the results and then the arguments are in
+    /// the registers from `stack_base` on.
+    pub fn call(&mut self, cls: Operand, stack_base: usize) -> usize {
+        let inst = Inst::new(Opcode::CALL, cls, Operand::Reg(stack_base), Operand::Void);
+        self.emit(inst.with_types(ValueType::FlagA, ValueType::Void))
+    }
+
+    /// Returns, with the values of the results.
+    pub fn ret(&mut self) -> usize {
+        let (flag, d) = match self.flag {
+            FuncFlag::Default => (ValueType::FlagA, Operand::Void),
+            FuncFlag::PkgCtor => (ValueType::FlagB, self.pkg.unwrap()),
+            FuncFlag::HasDefer => (ValueType::FlagC, Operand::Void),
+        };
+        let inst = Inst::new(Opcode::RETURN, d, Operand::Void, Operand::Void);
+        self.emit(inst.with_types(flag, ValueType::Void))
+    }
+
+    fn resolve(&self, op: Operand, index: usize, consts: usize) -> Result<OpIndex, BuildError> {
+        let locals = self.fixed + self.local_zeros.len();
+        let bad = || BuildError::BadOperand(self.key, index, op);
+        match op {
+            Operand::Local(i) if i < locals => Ok(i as OpIndex),
+            Operand::Reg(i) => Ok((locals + i) as OpIndex),
+            Operand::Const(i) if i < consts => Ok(-(i as OpIndex) - 1),
+            Operand::Imm(i) => Ok(i),
+            Operand::Label(l) => match self.labels.get(l.0).copied().flatten() {
+                Some(target) if target < self.code.len() => {
+                    Ok(target as OpIndex - index as OpIndex - 1)
+                }
+                _ => Err(BuildError::UnboundLabel(self.key, l)),
+            },
+            Operand::Void => Ok(OpIndex::MAX),
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// Builds bytecode out of functions built with `FuncBuilder`s.
+pub struct BytecodeBuilder {
+    vmctx: CodeGenVMCtx,
+    consts: Vec<GosValue>,
+}
+
+impl BytecodeBuilder {
+    pub fn new() -> BytecodeBuilder {
+        BytecodeBuilder {
+            vmctx: CodeGenVMCtx::new(VMObjects::new()),
+            consts: vec![],
+        }
+    }
+
+    pub fn prim_meta(&self) -> &PrimitiveMeta {
+        self.vmctx.prim_meta()
+    }
+
+    /// The objects, to make the metadata of other types, e.g. with
+    /// `Meta::new_slice`.
+    pub fn objects_mut(&mut self) -> &mut VMObjects {
+        self.vmctx.objects_mut()
+    }
+
+    pub fn new_sig(&mut self, params: Vec<Meta>, results: Vec<Meta>) -> Meta {
+        Meta::new_sig(None, params, results, None, self.vmctx.metas_mut())
+    }
+
+    pub fn new_package(&mut self, name: &str) -> PackageKey {
+        self.vmctx
+            .packages_mut()
+            .insert(PackageObj::new(name.to_owned()))
+    }
+
+    /// Adds a member to the package, for `LOAD_PKG` and `STORE_PKG` to use
+    /// with its index, the constructor of a package is its member 0.
+    pub fn add_member(&mut self, pkg: PackageKey, name: &str, val: GosValue) -> OpIndex {
+        self.vmctx.packages_mut()[pkg].add_member(name.to_owned(), val)
+    }
+
+    pub fn add_const(&mut self, val: GosValue) -> Operand {
+        self.consts.push(val);
+        Operand::Const(self.consts.len() - 1)
+    }
+
+    /// The value of a function with no upvalues, to call it as a constant or
+    /// as a package member.
+    pub fn function_value(&self, func: FunctionKey) -> GosValue {
+        let meta = self.vmctx.functions()[func].meta;
+        GosValue::new_closure_static(func, None, meta)
+    }
+
+    pub fn new_function(
+        &mut self,
+        pkg: Option<PackageKey>,
+        sig: Meta,
+        flag: FuncFlag,
+    ) -> FuncBuilder {
+        let key = *self.vmctx.function_with_meta(pkg, sig, flag).as_function();
+        let func = &self.vmctx.functions()[key];
+        let ret_count = func.ret_count() as usize;
+        let fixed = ret_count + func.param_count() as usize;
+        let pkg = match (flag, pkg) {
+            (FuncFlag::PkgCtor, Some(p)) => Some(self.add_const(GosValue::new_package(p))),
+            _ => None,
+        };
+        FuncBuilder {
+            key,
+            flag,
+            fixed,
+            ret_count,
+            local_zeros: vec![],
+            code: vec![],
+            labels: vec![],
+            rebound: None,
+            pkg,
+        }
+    }
+
+    /// Checks the function and puts its code in the bytecode being built.
+    pub fn finish_function(&mut self, f: FuncBuilder) -> Result<FunctionKey, BuildError> {
+        if let Some(l) = f.rebound {
+            return Err(BuildError::Rebound(f.key, l));
+        }
+        match f.code.last().map(|x| x.op0) {
+            Some(Opcode::RETURN) | Some(Opcode::JUMP) => {}
+            _ => return Err(BuildError::NoReturn(f.key)),
+        }
+        let consts = self.consts.len();
+        let code = f
+            .code
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                Ok(Instruction {
+                    op0: x.op0,
+                    op1: x.op1,
+                    t0: x.t0,
+                    t1: x.t1,
+                    d: f.resolve(x.d, i, consts)?,
+                    s0: f.resolve(x.s0, i, consts)?,
+                    s1: f.resolve(x.s1, i, consts)?,
+                })
+            })
+            .collect::<Result<Vec<_>, BuildError>>()?;
+        let func = &mut self.vmctx.functions_mut()[f.key];
+        func.pos = vec![None; code.len()];
+        func.max_write_index = Instruction::max_write_index(&code);
+        func.local_zeros = f.local_zeros;
+        func.code = code;
+        Ok(f.key)
+    }
+
+    /// Returns the bytecode, which runs `entry` in the context of `main_pkg`.
+    pub fn build(self, entry: FunctionKey, main_pkg: PackageKey) -> Result<Bytecode, BuildError> {
+        if self.vmctx.functions()[entry].code.is_empty() {
+            return Err(BuildError::NoEntry(entry));
+        }
+        Bytecode::new(
+            self.vmctx.into_vmo(),
+            self.consts,
+            vec![],
+            vec![],
+            entry,
+            main_pkg,
+            None,
+        )
+        .map_err(BuildError::Link)
+    }
+}
+
+impl Default for BytecodeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,12 +2,13 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::meta_view::MetaView;
 use crate::value::*;
 #[cfg(feature = "serde_borsh")]
 use borsh::{maybestd::io::Result, maybestd::io::Write, BorshDeserialize, BorshSerialize};
-#[cfg(feature = "serde_borsh")]
 use go_parser::PiggyVecKey;
-use go_parser::{piggy_key_type, PiggyVec};
+use go_parser::{piggy_key_type, FileSet, Map, PiggyVec};
+use std::rc::Rc;
 
 #[cfg(feature = "serde_borsh")]
 macro_rules! impl_borsh_for_key {
@@ -101,46 +102,67 @@ impl BorshDeserialize for VMObjects {
     }
 }
 
-#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[cfg_attr(feature = "serde_borsh", derive(BorshSerialize))]
 pub struct Bytecode {
     pub objects: VMObjects,
     pub consts: Vec<GosValue>,
-    /// For calling method via interfaces
+    /// For calling method via interfaces, indexed by the operand of the casts
+    /// to interfaces. An entry is the meta of the converted value and, for
+    /// each method of the interface in the order of its meta, where the
+    /// value has it:
+    /// - `Binding4Runtime::Struct(func, pointer_recv, indices)`, a method of
+    ///   a named type, promoted through the embedded fields at `indices`,
+    ///   the outermost last, if any;
+    /// - `Binding4Runtime::Iface(i, indices)`, the i-th method of an embedded
+    ///   interface field.
+    ///
+    /// A value of another interface type has no bindings and the meta is the
+    /// interface's, the dynamic type is bound to it at runtime.
+    ///
+    /// Not serialized, it's built by `link` from `iface_links`.
+    #[cfg_attr(feature = "serde_borsh", borsh_skip)]
     pub ifaces: Vec<(Meta, Vec<Binding4Runtime>)>,
+    /// The interface and the type of the value of each entry of `ifaces`.
+    /// This is what's serialized, the methods are looked up by name when
+    /// linking, so the function keys and the indices the bindings are made
+    /// of never outlive the objects they index.
+    pub iface_links: Vec<(Meta, Meta)>,
     /// For embedded fields of structs
     pub indices: Vec<Vec<OpIndex>>,
     pub entry: FunctionKey,
     pub main_pkg: PackageKey,
     /// Optional, for debug info
     pub file_set: Option<go_parser::FileSet>,
+    /// Optional, positions of instructions, available when file_set is
+    pub source_map: Option<SourceMap>,
 }
 
 impl Bytecode {
     pub fn new(
         objects: VMObjects,
         consts: Vec<GosValue>,
-        ifaces: Vec<(Meta, Vec<IfaceBinding>)>,
+        iface_links: Vec<(Meta, Meta)>,
         indices: Vec<Vec<OpIndex>>,
         entry: FunctionKey,
         main_pkg: PackageKey,
         file_set: Option<go_parser::FileSet>,
-    ) -> Bytecode {
-        let ifaces = ifaces
-            .into_iter()
-            .map(|(ms, binding)| {
-                let binding = binding.into_iter().map(|x| x.into()).collect();
-                (ms, binding)
-            })
-            .collect();
-        Bytecode {
+    ) -> std::result::Result<Bytecode, String> {
+        let source_map = file_set
+            .as_ref()
+            .map(|fs| SourceMap::new(&objects.functions, fs));
+        let mut bc = Bytecode {
             objects,
             consts,
-            ifaces,
+            ifaces: vec![],
+            iface_links,
             indices,
             entry,
             main_pkg,
             file_set,
-        }
+            source_map,
+        };
+        bc.link()?;
+        Ok(bc)
     }
 
     pub fn with_components(
@@ -148,21 +170,160 @@ impl Bytecode {
         functions: FunctionObjs,
         packages: PackageObjs,
         consts: Vec<GosValue>,
-        ifaces: Vec<(Meta, Vec<Binding4Runtime>)>,
+        iface_links: Vec<(Meta, Meta)>,
         indices: Vec<Vec<OpIndex>>,
         entry: FunctionKey,
         main_pkg: PackageKey,
         file_set: Option<go_parser::FileSet>,
-    ) -> Bytecode {
+    ) -> std::result::Result<Bytecode, String> {
         let objects = VMObjects::with_components(metas, functions, packages);
-        Bytecode {
+        Bytecode::new(
             objects,
             consts,
-            ifaces,
+            iface_links,
             indices,
             entry,
             main_pkg,
             file_set,
+        )
+    }
+
+    /// Builds `ifaces` from `iface_links`, looking the methods of the
+    /// interfaces up by name in the method sets of the types. Fails with the
+    /// name of a method a type doesn't have.
+    pub fn link(&mut self) -> std::result::Result<(), String> {
+        let metas = &self.objects.metas;
+        self.ifaces = self
+            .iface_links
+            .iter()
+            .map(|(iface, val)| {
+                if val.is_interface(metas) {
+                    return Ok((*iface, vec![]));
+                }
+                let (meta, bindings) = iface
+                    .try_bind_with_iface(*val, metas)
+                    .map_err(|name| format!("link error: missing method {}", name))?;
+                Ok((meta, bindings.into_iter().map(|x| x.into()).collect()))
+            })
+            .collect::<std::result::Result<_, String>>()?;
+        Ok(())
+    }
+
+    /// The types declared at package level by the packages named `package`,
+    /// sorted by name, see `MetaView::name`.
+    pub fn types(&self, package: &str) -> Vec<MetaView<'_>> {
+        let mut types: Vec<MetaView> = self
+            .objects
+            .packages
+            .iter()
+            .filter(|pkg| pkg.name() == package)
+            .flat_map(|pkg| {
+                pkg.member_indices().iter().filter_map(|(name, i)| {
+                    let member = pkg.member(*i);
+                    if member.typ() != ValueType::Metadata {
+                        return None;
+                    }
+                    let view = MetaView::new(*member.as_metadata(), &self.objects);
+                    // aliases are members too
+                    (view.name() == Some(name.as_str())).then_some(view)
+                })
+            })
+            .collect();
+        types.sort_by_key(|x| x.name());
+        types
+    }
+}
+
+#[cfg(feature = "serde_borsh")]
+impl BorshDeserialize for Bytecode {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+        let mut bc = Bytecode {
+            objects: VMObjects::deserialize_reader(reader)?,
+            consts: Vec::deserialize_reader(reader)?,
+            ifaces: vec![],
+            iface_links: Vec::deserialize_reader(reader)?,
+            indices: Vec::deserialize_reader(reader)?,
+            entry: FunctionKey::deserialize_reader(reader)?,
+            main_pkg: PackageKey::deserialize_reader(reader)?,
+            file_set: Option::deserialize_reader(reader)?,
+            source_map: Option::deserialize_reader(reader)?,
+        };
+        bc.link()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(bc)
+    }
+}
+
+/// A position in the source code, `file` indexes `SourceMap::files`.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourcePos {
+    pub file: u32,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// SourceMap maps the instructions of every function to their positions in the
+/// source code, so that tools like debuggers and profilers can resolve them
+/// without the AST or the FileSet.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<String>,
+    // indexed by FunctionKey and then by instruction index
+    funcs: Vec<Vec<Option<SourcePos>>>,
+}
+
+impl SourceMap {
+    pub fn new(functions: &FunctionObjs, fset: &FileSet) -> SourceMap {
+        let mut files = vec![];
+        let mut file_indices: Map<Rc<String>, u32> = Map::new();
+        let funcs = functions
+            .iter()
+            .map(|func| {
+                func.pos
+                    .iter()
+                    .map(|p| {
+                        let fp = fset.position((*p)? as usize)?;
+                        let file = *file_indices.entry(fp.filename.clone()).or_insert_with(|| {
+                            files.push(fp.filename.to_string());
+                            files.len() as u32 - 1
+                        });
+                        Some(SourcePos {
+                            file,
+                            line: fp.line as u32,
+                            column: fp.column as u32,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        SourceMap { files, funcs }
+    }
+
+    /// Names of the source files.
+    #[inline]
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The positions of the instructions of a function, indexed by instruction.
+    #[inline]
+    pub fn function(&self, func: FunctionKey) -> &[Option<SourcePos>] {
+        self.funcs
+            .get(func.as_usize())
+            .map_or(&[], |x| x.as_slice())
+    }
+
+    pub(crate) fn clear_function(&mut self, func: FunctionKey) {
+        if let Some(x) = self.funcs.get_mut(func.as_usize()) {
+            x.clear();
         }
     }
+
+    /// Returns the file name and the position of an instruction.
+    pub fn lookup(&self, func: FunctionKey, pc: usize) -> Option<(&str, SourcePos)> {
+        let pos = (*self.function(func).get(pc)?)?;
+        Some((self.files.get(pos.file as usize)?, pos))
+    }
 }
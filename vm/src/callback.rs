@@ -0,0 +1,207 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Callbacks let the host keep closures handed over by the script and call them
+//! later, e.g. when an event happens.
+//!
+//! Calls are queued and then run by the VM's scheduler, as new goroutines with the
+//! `async` feature, or the next time the running code yields without it.
+//! A call doesn't outlive the VM, calling a callback after the VM has exited
+//! returns an error.
+//!
+//! A call runs the deferred calls of the closure when it returns, or when it
+//! panics, and a panic it doesn't recover from only ends the call, it's returned
+//! to the host as the result. There are two ways to call:
+//! - `call_in_new_goroutine` starts the call as a new goroutine, once the calling
+//!   one yields, the host can wait for it in an async FFI function or not at all.
+//! - `call_blocking` is for async FFI functions that wait for it, the call runs
+//!   as the goroutine calling the function, with its ID and goroutine local
+//!   value, so it's as if the script called the closure there. It's started
+//!   before the other queued calls.
+
+#[cfg(feature = "async")]
+use crate::ffi::FfiCtx;
+use crate::value::*;
+use crate::vm::PanicData;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// The return values of a callback, or the panic it didn't recover from.
+pub type CallbackResult = std::result::Result<Vec<GosValue>, PanicData>;
+
+/// A call waiting to be run by the VM.
+pub(crate) struct QueuedCall {
+    pub(crate) closure: ClosureObj,
+    pub(crate) args: Vec<GosValue>,
+    pub(crate) call: CallbackCall,
+    /// the ID and the goroutine local value of the goroutine a blocking call
+    /// runs as
+    pub(crate) caller: Option<(usize, Option<Rc<dyn Any>>)>,
+}
+
+/// The calls waiting to be run by the VM.
+#[derive(Default)]
+pub(crate) struct CallbackQueue {
+    calls: RefCell<VecDeque<QueuedCall>>,
+}
+
+impl CallbackQueue {
+    pub(crate) fn pop(&self) -> Option<QueuedCall> {
+        self.calls.borrow_mut().pop_front()
+    }
+}
+
+/// Callback is a script closure kept by the host, see `FfiCtx::new_callback`.
+#[derive(Clone, Debug)]
+pub struct Callback {
+    closure: ClosureObj,
+    params: Vec<ValueType>,
+    queue: Weak<CallbackQueue>,
+}
+
+impl Callback {
+    pub(crate) fn new(
+        closure: ClosureObj,
+        params: Vec<ValueType>,
+        queue: Weak<CallbackQueue>,
+    ) -> Callback {
+        Callback {
+            closure,
+            params,
+            queue,
+        }
+    }
+
+    /// Same as `call_in_new_goroutine`.
+    pub fn call(&self, args: Vec<GosValue>) -> RuntimeResult<CallbackCall> {
+        self.call_in_new_goroutine(args)
+    }
+
+    /// Schedules a call with the given arguments as a new goroutine, variadic
+    /// arguments are passed in a slice as the last argument.
+    pub fn call_in_new_goroutine(&self, args: Vec<GosValue>) -> RuntimeResult<CallbackCall> {
+        self.schedule(args, None)
+    }
+
+    /// Schedules a call with the given arguments that runs as the goroutine
+    /// calling the FFI function `ctx` is for, which is to wait for it.
+    #[cfg(feature = "async")]
+    pub fn call_blocking(&self, ctx: &FfiCtx, args: Vec<GosValue>) -> RuntimeResult<CallbackCall> {
+        self.schedule(args, Some((ctx.goroutine_id(), ctx.goroutine_local())))
+    }
+
+    fn schedule(
+        &self,
+        args: Vec<GosValue>,
+        caller: Option<(usize, Option<Rc<dyn Any>>)>,
+    ) -> RuntimeResult<CallbackCall> {
+        if args.len() != self.params.len() {
+            return Err(format!(
+                "callback expects {} arguments, got {}",
+                self.params.len(),
+                args.len()
+            )
+            .into());
+        }
+        for (i, (arg, t)) in args.iter().zip(self.params.iter()).enumerate() {
+            if arg.typ() != *t {
+                return Err(format!(
+                    "callback argument {} should be {:?}, got {:?}",
+                    i,
+                    t,
+                    arg.typ()
+                )
+                .into());
+            }
+        }
+        match self.queue.upgrade() {
+            Some(queue) => {
+                let call = CallbackCall::new();
+                let blocking = caller.is_some();
+                let entry = QueuedCall {
+                    closure: self.closure.clone(),
+                    args,
+                    call: call.clone(),
+                    caller,
+                };
+                // the caller of a blocking call is waiting for it
+                let mut calls = queue.calls.borrow_mut();
+                if blocking {
+                    calls.push_front(entry);
+                } else {
+                    calls.push_back(entry);
+                }
+                Ok(call)
+            }
+            None => Err("callback called after the VM has exited".to_owned().into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum CallState {
+    Pending(Option<Waker>),
+    Done(CallbackResult),
+    Taken,
+}
+
+/// CallbackCall is a scheduled call of a Callback, it can be polled by
+/// `take_result`, or awaited in async FFI functions.
+#[derive(Clone, Debug)]
+pub struct CallbackCall {
+    state: Rc<RefCell<CallState>>,
+}
+
+impl CallbackCall {
+    pub(crate) fn new() -> CallbackCall {
+        CallbackCall {
+            state: Rc::new(RefCell::new(CallState::Pending(None))),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        !matches!(&*self.state.borrow(), CallState::Pending(_))
+    }
+
+    /// Returns the result once the call is done, only the first time.
+    pub fn take_result(&self) -> Option<CallbackResult> {
+        let mut state = self.state.borrow_mut();
+        match &*state {
+            CallState::Done(_) => match std::mem::replace(&mut *state, CallState::Taken) {
+                CallState::Done(r) => Some(r),
+                _ => unreachable!(),
+            },
+            _ => None,
+        }
+    }
+
+    pub(crate) fn finish(&self, result: CallbackResult) {
+        let prev = self.state.replace(CallState::Done(result));
+        if let CallState::Pending(Some(waker)) = prev {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for CallbackCall {
+    type Output = CallbackResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(result) = self.take_result() {
+            return Poll::Ready(result);
+        }
+        match &mut *self.state.borrow_mut() {
+            CallState::Pending(waker) => {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            _ => panic!("callback result already taken"),
+        }
+    }
+}
@@ -2,147 +2,350 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+//! Channels are a ring buffer of `cap` slots and a queue of the values
+//! offered by the blocked senders, like Go's.
+//!
+//! A sender that finds the buffer full, which an unbuffered channel always
+//! is, offers its value and waits for a receiver to take it. A receiver takes
+//! the head of the buffer and moves the oldest offer into the freed slot, or
+//! with an empty buffer takes the oldest offer directly, so the value is
+//! handed over without a hop through the channel. Nothing is allocated per
+//! send, the buffer is allocated by make and the queue only grows to the most
+//! senders blocked at once.
+//!
+//! Wakeup ordering:
+//! - values are received in the order they are sent, and blocked senders
+//!   complete in the order they blocked;
+//! - blocked receivers are not queued, they are all woken by a send and the
+//!   first one the executor polls gets the value;
+//! - a select blocked on several sends offers on all of them, the first
+//!   offer taken completes it and the others are withdrawn before anyone
+//!   else can take them;
+//! - a select with a default case sends on an unbuffered channel only if
+//!   some goroutine is blocked receiving from it, outside a select.
+//!
+//! A blocked goroutine leaves its waker on the channels it waits for and is
+//! not polled again until one of them changes, so a goroutine blocked for
+//! good costs nothing, and when they all are, the run ends as deadlocked.
+
 use super::instruction::*;
 use super::value::*;
 use futures_lite::future;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::task::{Poll, Waker};
 
-#[derive(Clone, Debug)]
-pub enum RendezvousState {
-    NotReady,
-    Ready,
-    InPlace(GosValue),
+const CLOSED_MSG: &str = "channel closed!";
+
+#[derive(Debug)]
+pub enum TrySendError {
+    Full(GosValue),
+    Closed(GosValue),
+}
+
+#[derive(Debug)]
+pub enum TryRecvError {
+    Empty,
     Closed,
 }
 
-#[derive(Clone, Debug)]
-pub enum Channel {
-    Bounded(
-        async_channel::Sender<GosValue>,
-        async_channel::Receiver<GosValue>,
-    ),
-    // Cloning Channel needs to return the same channel, hence the Rc
-    Rendezvous(Rc<RefCell<RendezvousState>>),
+thread_local! {
+    static NEXT_TICKET: Cell<u64> = const { Cell::new(0) };
 }
 
-impl Channel {
-    pub fn new(cap: usize) -> Channel {
-        if cap == 0 {
-            Channel::Rendezvous(Rc::new(RefCell::new(RendezvousState::NotReady)))
-        } else {
-            let (s, r) = async_channel::bounded(cap);
-            Channel::Bounded(s, r)
+fn new_ticket() -> u64 {
+    NEXT_TICKET.with(|x| {
+        let t = x.get();
+        x.set(t + 1);
+        t
+    })
+}
+
+/// Set when one of the offers of a select is taken, so that no other is.
+type Group = Rc<Cell<bool>>;
+
+#[derive(Debug)]
+struct Offer {
+    ticket: u64,
+    val: GosValue,
+    group: Option<Group>,
+    // made for a blocked receiver by a sender that didn't wait, the send is
+    // complete even if the channel is closed before it's taken
+    committed: bool,
+}
+
+impl Offer {
+    fn is_of(&self, group: Option<&Group>) -> bool {
+        matches!((&self.group, group), (Some(a), Some(b)) if Rc::ptr_eq(a, b))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    buf: Box<[GosValue]>,
+    head: usize,
+    len: usize,
+    offers: VecDeque<Offer>,
+    closed: bool,
+    // receivers blocked outside a select, which will take an offer for sure
+    blocked_receivers: usize,
+    // the goroutines blocked on the channel, woken when it changes
+    waiters: Vec<Waker>,
+}
+
+impl State {
+    fn add_waiter(&mut self, waker: &Waker) {
+        if !self.waiters.iter().any(|x| x.will_wake(waker)) {
+            self.waiters.push(waker.clone());
         }
     }
 
-    #[inline]
-    pub fn len(&self) -> usize {
-        match self {
-            Channel::Bounded(s, _) => s.len(),
-            Channel::Rendezvous(_) => 0,
+    fn wake_all(&mut self) {
+        for w in self.waiters.drain(..) {
+            w.wake();
         }
     }
 
-    #[inline]
-    pub fn cap(&self) -> usize {
-        match self {
-            Channel::Bounded(s, _) => s.capacity().unwrap(),
-            Channel::Rendezvous(_) => 0,
+    fn push(&mut self, val: GosValue) {
+        let i = (self.head + self.len) % self.buf.len();
+        self.buf[i] = val;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> GosValue {
+        let val = std::mem::replace(&mut self.buf[self.head], GosValue::new_nil(ValueType::Void));
+        self.head = (self.head + 1) % self.buf.len();
+        self.len -= 1;
+        val
+    }
+
+    fn try_send(&mut self, val: GosValue) -> Result<(), TrySendError> {
+        if self.closed {
+            Err(TrySendError::Closed(val))
+        } else if self.len < self.buf.len() && self.offers.is_empty() {
+            self.push(val);
+            self.wake_all();
+            Ok(())
+        } else if self.buf.is_empty() && self.offers.len() < self.blocked_receivers {
+            self.offers.push_back(Offer {
+                ticket: new_ticket(),
+                val,
+                group: None,
+                committed: true,
+            });
+            self.wake_all();
+            Ok(())
+        } else {
+            Err(TrySendError::Full(val))
         }
     }
 
-    #[inline]
-    pub fn close(&self) {
-        match self {
-            Channel::Bounded(s, _) => {
-                s.close();
-            }
-            Channel::Rendezvous(state) => *state.borrow_mut() = RendezvousState::Closed,
-        }
-    }
-
-    pub fn try_send(&self, v: GosValue) -> Result<(), async_channel::TrySendError<GosValue>> {
-        match self {
-            Channel::Bounded(s, _) => s.try_send(v),
-            Channel::Rendezvous(state) => {
-                let mut state_ref = state.borrow_mut();
-                let s: &RendezvousState = &state_ref;
-                match s {
-                    RendezvousState::NotReady => Err(async_channel::TrySendError::Full(v)),
-                    RendezvousState::Ready => {
-                        *state_ref = RendezvousState::InPlace(v);
-                        Ok(())
-                    }
-                    RendezvousState::InPlace(_) => Err(async_channel::TrySendError::Full(v)),
-                    RendezvousState::Closed => Err(async_channel::TrySendError::Closed(v)),
+    fn offer(&mut self, val: GosValue, group: Option<Group>) -> u64 {
+        let ticket = new_ticket();
+        self.offers.push_back(Offer {
+            ticket,
+            val,
+            group,
+            committed: false,
+        });
+        self.wake_all();
+        ticket
+    }
+
+    fn is_offered(&self, ticket: u64) -> bool {
+        self.offers.iter().any(|x| x.ticket == ticket)
+    }
+
+    fn withdraw(&mut self, ticket: u64) {
+        if let Some(i) = self.offers.iter().position(|x| x.ticket == ticket) {
+            self.offers.remove(i);
+            self.wake_all();
+        }
+    }
+
+    /// Takes the oldest offer not made by `own`, dropping the ones of the
+    /// selects already completed. Once closed, only the committed ones are
+    /// taken, the senders waiting for the others fail.
+    fn take_offer(&mut self, own: Option<&Group>) -> Option<GosValue> {
+        let mut i = 0;
+        while i < self.offers.len() {
+            let offer = &self.offers[i];
+            if offer.is_of(own) || (self.closed && !offer.committed) {
+                i += 1;
+            } else if offer.group.as_ref().is_some_and(|g| g.get()) {
+                self.offers.remove(i);
+            } else {
+                let offer = self.offers.remove(i).unwrap();
+                if let Some(g) = &offer.group {
+                    g.set(true);
                 }
+                // the sender completes
+                self.wake_all();
+                return Some(offer.val);
             }
         }
+        None
     }
 
-    pub fn try_recv(&self) -> Result<GosValue, async_channel::TryRecvError> {
-        match self {
-            Channel::Bounded(_, r) => r.try_recv(),
-            Channel::Rendezvous(state) => {
-                let mut state_ref = state.borrow_mut();
-                let s: &RendezvousState = &state_ref;
-                match s {
-                    RendezvousState::NotReady => {
-                        *state_ref = RendezvousState::Ready;
-                        Err(async_channel::TryRecvError::Empty)
-                    }
-                    RendezvousState::Ready => Err(async_channel::TryRecvError::Empty),
-                    RendezvousState::InPlace(_) => {
-                        drop(state_ref);
-                        if let RendezvousState::InPlace(v) =
-                            state.replace(RendezvousState::NotReady)
-                        {
-                            Ok(v)
-                        } else {
-                            unreachable!()
-                        }
-                    }
-                    RendezvousState::Closed => Err(async_channel::TryRecvError::Closed),
-                }
+    fn try_recv(&mut self, own: Option<&Group>) -> Result<GosValue, TryRecvError> {
+        if self.len > 0 {
+            let val = self.pop();
+            if let Some(v) = self.take_offer(own) {
+                self.push(v);
             }
+            // a select with a default case may send now
+            self.wake_all();
+            Ok(val)
+        } else if let Some(v) = self.take_offer(own) {
+            Ok(v)
+        } else if self.closed {
+            Err(TryRecvError::Closed)
+        } else {
+            Err(TryRecvError::Empty)
         }
     }
+}
+
+// Cloning Channel needs to return the same channel, hence the Rc
+#[derive(Clone, Debug)]
+pub struct Channel(Rc<RefCell<State>>);
+
+impl Channel {
+    pub fn new(cap: usize) -> Channel {
+        Channel(Rc::new(RefCell::new(State {
+            buf: vec![GosValue::new_nil(ValueType::Void); cap].into_boxed_slice(),
+            head: 0,
+            len: 0,
+            offers: VecDeque::new(),
+            closed: false,
+            blocked_receivers: 0,
+            waiters: vec![],
+        })))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.borrow().len
+    }
+
+    #[inline]
+    pub fn cap(&self) -> usize {
+        self.0.borrow().buf.len()
+    }
+
+    /// The blocked senders fail, the buffered values can still be received.
+    #[inline]
+    pub fn close(&self) {
+        let mut state = self.0.borrow_mut();
+        state.closed = true;
+        state.wake_all();
+    }
+
+    pub fn try_send(&self, v: GosValue) -> Result<(), TrySendError> {
+        self.0.borrow_mut().try_send(v)
+    }
+
+    pub fn try_recv(&self) -> Result<GosValue, TryRecvError> {
+        self.0.borrow_mut().try_recv(None)
+    }
 
     pub async fn send(&self, v: &GosValue) -> RuntimeResult<()> {
-        let mut val = Some(v.clone());
-        loop {
-            match self.try_send(val.take().unwrap()) {
+        let ticket = {
+            let mut state = self.0.borrow_mut();
+            match state.try_send(v.clone()) {
                 Ok(()) => return Ok(()),
-                Err(e) => match e {
-                    async_channel::TrySendError::Full(v) => {
-                        val = Some(v);
-                        future::yield_now().await;
-                    }
-                    async_channel::TrySendError::Closed(_) => {
-                        return Err("channel closed!".to_owned().into());
-                    }
-                },
+                Err(TrySendError::Closed(_)) => return Err(CLOSED_MSG.to_owned().into()),
+                Err(TrySendError::Full(v)) => state.offer(v, None),
+            }
+        };
+        let _pending = Pending::new(self, ticket);
+        loop {
+            changed(&[self]).await;
+            let state = self.0.borrow();
+            if !state.is_offered(ticket) {
+                return Ok(());
+            } else if state.closed {
+                return Err(CLOSED_MSG.to_owned().into());
             }
         }
     }
 
     pub async fn recv(&self) -> Option<GosValue> {
-        //dbg!(self);
+        let mut _blocked = None;
         loop {
-            match self.try_recv() {
+            let result = self.0.borrow_mut().try_recv(None);
+            match result {
                 Ok(v) => return Some(v),
-                Err(e) => match e {
-                    async_channel::TryRecvError::Empty => {
-                        future::yield_now().await;
-                    }
-                    async_channel::TryRecvError::Closed => return None,
-                },
+                Err(TryRecvError::Closed) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if _blocked.is_none() {
+                _blocked = Some(Blocked::new(self));
             }
+            changed(&[self]).await;
         }
     }
 }
 
+/// Waits for one of the channels to change, or for a spurious wakeup, the
+/// caller checks what it waits for again either way.
+async fn changed(chans: &[&Channel]) {
+    let mut waiting = false;
+    future::poll_fn(|cx| {
+        if waiting {
+            return Poll::Ready(());
+        }
+        waiting = true;
+        for c in chans.iter() {
+            c.0.borrow_mut().add_waiter(cx.waker());
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Withdraws an offer when dropped, if it's not taken yet, i.e. when the
+/// sender is interrupted, fails or completes another case of a select.
+struct Pending<'a> {
+    chan: &'a Channel,
+    ticket: u64,
+}
+
+impl<'a> Pending<'a> {
+    fn new(chan: &'a Channel, ticket: u64) -> Pending<'a> {
+        Pending { chan, ticket }
+    }
+
+    fn is_taken(&self) -> bool {
+        !self.chan.0.borrow().is_offered(self.ticket)
+    }
+}
+
+impl<'a> Drop for Pending<'a> {
+    fn drop(&mut self) {
+        self.chan.0.borrow_mut().withdraw(self.ticket);
+    }
+}
+
+/// Counts a receiver as blocked as long as it's alive.
+struct Blocked<'a> {
+    chan: &'a Channel,
+}
+
+impl<'a> Blocked<'a> {
+    fn new(chan: &'a Channel) -> Blocked<'a> {
+        chan.0.borrow_mut().blocked_receivers += 1;
+        Blocked { chan }
+    }
+}
+
+impl<'a> Drop for Blocked<'a> {
+    fn drop(&mut self) {
+        self.chan.0.borrow_mut().blocked_receivers -= 1;
+    }
+}
+
 pub enum SelectCommType {
     Send(GosValue),
     Recv(ValueType, OpIndex),
@@ -168,40 +371,72 @@ impl Selector {
     }
 
     pub async fn select(&self) -> RuntimeResult<(usize, Option<GosValue>)> {
-        let count = self.comms.len();
-        let rand_start = fastrand::usize(0..count);
+        if let Some(re) = self.try_comms(None)? {
+            return Ok(re);
+        }
+        if self.default_offset.is_some() {
+            return Ok((self.comms.len(), None));
+        }
+
+        // blocked, offer the values to send and wait for one to be taken or
+        // for a receive to be ready
+        let group: Group = Rc::new(Cell::new(false));
+        let mut pending = Vec::new();
+        for (i, entry) in self.comms.iter().enumerate() {
+            if let (SelectCommType::Send(val), Some(c)) = (&entry.typ, entry.chan.as_channel()) {
+                let chan = &c.chan;
+                let ticket = chan.0.borrow_mut().offer(val.clone(), Some(group.clone()));
+                pending.push((i, Pending::new(chan, ticket)));
+            }
+        }
+        let chans: Vec<&Channel> = self
+            .comms
+            .iter()
+            .filter_map(|x| x.chan.as_channel().map(|c| &c.chan))
+            .collect();
         loop {
-            for i in 0..count {
-                let index = (i + rand_start) % count;
-                let entry = &self.comms[index];
-                match &entry.typ {
-                    SelectCommType::Send(val) => {
-                        match entry.chan.as_non_nil_channel()?.chan.try_send(val.clone()) {
-                            Ok(_) => return Ok((index, None)),
-                            Err(e) => match e {
-                                async_channel::TrySendError::Full(_) => {}
-                                async_channel::TrySendError::Closed(_) => {
-                                    return Err("channel closed!".to_owned().into());
-                                }
-                            },
-                        }
-                    }
-                    SelectCommType::Recv(_, _) => {
-                        match entry.chan.as_non_nil_channel()?.chan.try_recv() {
-                            Ok(v) => return Ok((index, Some(v))),
-                            Err(e) => match e {
-                                async_channel::TryRecvError::Empty => {}
-                                async_channel::TryRecvError::Closed => return Ok((index, None)),
-                            },
-                        }
-                    }
+            changed(&chans).await;
+            for (i, p) in pending.iter() {
+                if p.is_taken() {
+                    return Ok((*i, None));
+                } else if p.chan.0.borrow().closed {
+                    return Err(CLOSED_MSG.to_owned().into());
                 }
             }
+            if let Some(re) = self.try_comms(Some(&group))? {
+                group.set(true);
+                return Ok(re);
+            }
+        }
+    }
 
-            if let Some(_) = self.default_offset {
-                return Ok((self.comms.len(), None));
+    /// Tries the receives, and the sends unless `own` has offered them, in a
+    /// random order.
+    fn try_comms(&self, own: Option<&Group>) -> RuntimeResult<Option<(usize, Option<GosValue>)>> {
+        let count = self.comms.len();
+        let rand_start = fastrand::usize(0..count.max(1));
+        for i in 0..count {
+            let index = (i + rand_start) % count;
+            let entry = &self.comms[index];
+            // communication on nil channels never proceeds
+            let chan = match entry.chan.as_channel() {
+                Some(c) => &c.chan,
+                None => continue,
+            };
+            match &entry.typ {
+                SelectCommType::Send(_) if own.is_some() => {}
+                SelectCommType::Send(val) => match chan.try_send(val.clone()) {
+                    Ok(_) => return Ok(Some((index, None))),
+                    Err(TrySendError::Full(_)) => {}
+                    Err(TrySendError::Closed(_)) => return Err(CLOSED_MSG.to_owned().into()),
+                },
+                SelectCommType::Recv(_, _) => match chan.0.borrow_mut().try_recv(own) {
+                    Ok(v) => return Ok(Some((index, Some(v)))),
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Closed) => return Ok(Some((index, None))),
+                },
             }
-            future::yield_now().await;
         }
+        Ok(None)
     }
 }
@@ -10,6 +10,9 @@ use std::hash::{Hash, Hasher};
 pub(crate) trait Dispatcher {
     fn typ(&self) -> ValueType;
 
+    /// The bytes an element takes in the underlying vector.
+    fn elem_size(&self) -> usize;
+
     fn array_with_size(
         &self,
         size: usize,
@@ -29,12 +32,18 @@ pub(crate) trait Dispatcher {
 
     fn array_eq(&self, a: &ValueData, b: &ValueData) -> bool;
 
+    fn array_go_eq(&self, a: &ValueData, b: &ValueData) -> bool;
+
     fn array_cmp(&self, a: &ValueData, b: &ValueData) -> std::cmp::Ordering;
 
     fn array_get_vec(&self, val: &GosValue) -> Vec<GosValue>;
 
     fn slice_get_vec(&self, val: &GosValue) -> Option<Vec<GosValue>>;
 
+    /// The address of the first element, slices that share it see the same
+    /// elements, at least the first one.
+    fn slice_addr(&self, val: &GosValue) -> Option<*const usize>;
+
     fn array_len(&self, val: &GosValue) -> usize;
 
     fn slice_slice(
@@ -102,6 +111,11 @@ macro_rules! define_dispatcher {
                 self.typ
             }
 
+            #[inline]
+            fn elem_size(&self) -> usize {
+                std::mem::size_of::<$elem>()
+            }
+
             fn array_with_size(
                 &self,
                 size: usize,
@@ -144,6 +158,31 @@ macro_rules! define_dispatcher {
                 a.as_array::<$elem>().0 == b.as_array::<$elem>().0
             }
 
+            #[inline]
+            fn array_go_eq(&self, a: &ValueData, b: &ValueData) -> bool {
+                let (a, b) = (&a.as_array::<$elem>().0, &b.as_array::<$elem>().0);
+                match self.typ {
+                    // the elements may hold floats, NaN is not equal to itself
+                    ValueType::Float32
+                    | ValueType::Float64
+                    | ValueType::Complex64
+                    | ValueType::Complex128
+                    | ValueType::Array
+                    | ValueType::Struct
+                    | ValueType::Interface => {
+                        a.len() == b.len()
+                            && a.as_rust_slice().iter().zip(b.as_rust_slice().iter()).all(
+                                |(x, y)| {
+                                    x.clone()
+                                        .into_value(self.typ)
+                                        .go_eq(&y.clone().into_value(self.typ))
+                                },
+                            )
+                    }
+                    _ => a == b,
+                }
+            }
+
             #[inline]
             fn array_cmp(&self, a: &ValueData, b: &ValueData) -> std::cmp::Ordering {
                 a.as_array::<$elem>().0.cmp(&b.as_array::<$elem>().0)
@@ -167,6 +206,11 @@ macro_rules! define_dispatcher {
                 })
             }
 
+            fn slice_addr(&self, val: &GosValue) -> Option<*const usize> {
+                val.as_slice::<$elem>()
+                    .map(|x| x.0.as_rust_slice().as_ptr() as *const usize)
+            }
+
             #[inline]
             fn array_len(&self, val: &GosValue) -> usize {
                 val.as_array::<$elem>().0.len()
@@ -180,13 +224,17 @@ macro_rules! define_dispatcher {
                 end: isize,
                 max: isize,
             ) -> RuntimeResult<GosValue> {
-                Ok(GosValue::new_slice(
-                    slice
-                        .as_non_nil_slice::<$elem>()?
-                        .0
-                        .slice(begin, end, max)?,
-                    slice.t_elem,
-                ))
+                match slice.as_slice::<$elem>() {
+                    Some(s) => Ok(GosValue::new_slice(
+                        s.0.slice(begin, end, max)?,
+                        slice.t_elem,
+                    )),
+                    // a nil slice can be sliced to itself, like s[0:0]
+                    None => match [begin, end, max].into_iter().find(|&i| i > 0) {
+                        Some(i) => Err(format!("index {} out of range", i).into()),
+                        None => Ok(slice.clone()),
+                    },
+                }
             }
 
             #[inline]
@@ -215,7 +263,8 @@ macro_rules! define_dispatcher {
                     Some(y) => match a {
                         Some(x) => {
                             let mut to = x.0.clone();
-                            to.append(&y.0);
+                            let grown = to.append(&y.0);
+                            gcc.add_allocated(grown * std::mem::size_of::<$elem>());
                             Ok(GosValue::new_slice(to, other.t_elem()))
                         }
                         None => {
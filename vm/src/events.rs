@@ -0,0 +1,144 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Events let the host publish to named sources that scripts subscribe to,
+//! e.g. for signals, timers or UI events, the std package `events` delivers
+//! them to channels.
+//!
+//! Unlike callbacks, publishing doesn't need the VM, it can be done from any
+//! thread, so the data of an event is a string.
+
+use crate::value::RuntimeResult;
+use go_parser::Map;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// Events is shared by the host and the runs it's given to, see
+/// `Limits::events`.
+#[derive(Clone, Debug, Default)]
+pub struct Events {
+    sources: Arc<Mutex<Map<String, Vec<Weak<Mutex<Queue>>>>>>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    events: VecDeque<String>,
+    closed: bool,
+    // the subscriber waiting for the next event
+    waker: Option<Waker>,
+}
+
+impl Queue {
+    fn wake(&mut self) {
+        if let Some(w) = self.waker.take() {
+            w.wake();
+        }
+    }
+}
+
+impl Events {
+    pub fn new() -> Events {
+        Events::default()
+    }
+
+    /// Declares the source `name`, scripts can only subscribe to declared
+    /// sources. Declaring a source again keeps its subscriptions.
+    pub fn declare(&self, name: &str) {
+        let mut sources = self.sources.lock().unwrap();
+        sources.entry(name.to_owned()).or_default();
+    }
+
+    /// Queues `data` for the subscriptions to the source `name`, and returns
+    /// how many there are.
+    pub fn publish(&self, name: &str, data: &str) -> RuntimeResult<usize> {
+        let mut sources = self.sources.lock().unwrap();
+        let subs = sources
+            .get_mut(name)
+            .ok_or_else(|| format!("events: unknown source {}", name))?;
+        // the runs that subscribed may have exited
+        subs.retain(|x| x.strong_count() > 0);
+        for sub in subs.iter().filter_map(|x| x.upgrade()) {
+            let mut queue = sub.lock().unwrap();
+            queue.events.push_back(data.to_owned());
+            queue.wake();
+        }
+        Ok(subs.len())
+    }
+
+    /// Removes the source `name`, its subscriptions end after the events
+    /// already queued.
+    pub fn close(&self, name: &str) {
+        let subs = self.sources.lock().unwrap().remove(name);
+        for sub in subs.iter().flatten().filter_map(|x| x.upgrade()) {
+            let mut queue = sub.lock().unwrap();
+            queue.closed = true;
+            queue.wake();
+        }
+    }
+
+    /// Returns a subscription receiving the events published to the source
+    /// `name` from now on.
+    pub fn subscribe(&self, name: &str) -> RuntimeResult<Subscription> {
+        let mut sources = self.sources.lock().unwrap();
+        let subs = sources
+            .get_mut(name)
+            .ok_or_else(|| format!("events: unknown source {}", name))?;
+        let queue = Arc::new(Mutex::new(Queue::default()));
+        subs.push(Arc::downgrade(&queue));
+        Ok(Subscription(queue))
+    }
+}
+
+/// Subscription is the queue of the events published to a source for one
+/// subscriber.
+#[derive(Clone, Debug)]
+pub struct Subscription(Arc<Mutex<Queue>>);
+
+impl Subscription {
+    /// Takes the next event, `Ready(None)` means the source is closed and
+    /// there are no more. If there is none yet, `cx` is woken up when there
+    /// is.
+    pub fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        let mut queue = self.0.lock().unwrap();
+        match queue.events.pop_front() {
+            Some(data) => Poll::Ready(Some(data)),
+            None if queue.closed => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Waits for the next event, None means the source is closed and there
+    /// are no more.
+    pub async fn next(&self) -> Option<String> {
+        std::future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod test {
+    use super::*;
+    use futures_lite::future;
+
+    #[test]
+    fn test_subscription_wakes() {
+        let events = Events::new();
+        events.declare("a");
+        let sub = events.subscribe("a").unwrap();
+        let publisher = events.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            publisher.publish("a", "x").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            publisher.close("a");
+        });
+        // block_on parks the thread, only the waker gets it going again
+        assert_eq!(future::block_on(sub.next()), Some("x".to_owned()));
+        assert_eq!(future::block_on(sub.next()), None);
+        handle.join().unwrap();
+    }
+}
@@ -3,26 +3,48 @@
 // license that can be found in the LICENSE file.
 
 use crate::bytecode::*;
+use crate::callback::{Callback, CallbackQueue};
 use crate::dispatcher::ArrCaller;
+use crate::events::Events;
 use crate::gc::GcContainer;
 use crate::stack::Stack;
 use crate::value::*;
 use crate::value::{GosValue, RuntimeResult};
+use crate::value_fmt::{ValueFmt, Verb};
+use crate::value_ref::GosValueRef;
+use crate::vm::AppInfo;
 #[cfg(feature = "async")]
 use futures_lite::future::Future;
 use go_parser::Map;
-use std::cell::Ref;
+use std::any::{Any, TypeId};
+use std::cell::{Cell, Ref, RefCell};
 #[cfg(feature = "async")]
 use std::pin::Pin;
 use std::rc::Rc;
 
 pub struct FfiCtx<'a> {
     pub func_name: &'a str,
+    /// The signature of the called method, as declared in the FFI interface.
+    pub func_meta: Meta,
     pub vm_objs: &'a VMObjects,
+    /// The packages of the running instance, use these instead of the ones
+    /// in `vm_objs` to access package vars.
+    pub packages: &'a PackageObjs,
     pub user_data: Option<usize>,
     pub stack: &'a mut Stack,
     pub gcc: &'a GcContainer,
     pub(crate) array_slice_caller: &'a ArrCaller,
+    pub(crate) callbacks: Option<&'a Rc<CallbackQueue>>,
+    pub(crate) goroutine_id: usize,
+    pub(crate) goroutine_count: usize,
+    pub(crate) events: Option<&'a Events>,
+    pub(crate) seed: i64,
+    pub(crate) args: &'a [String],
+    pub(crate) env: &'a [(String, String)],
+    pub(crate) app_info: &'a AppInfo,
+    pub(crate) exit_code: &'a Cell<Option<i32>>,
+    pub(crate) goroutine_local: &'a RefCell<Option<Rc<dyn Any>>>,
+    pub(crate) host_data: &'a Map<TypeId, Rc<dyn Any>>,
 }
 
 impl<'a> FfiCtx<'a> {
@@ -71,6 +93,12 @@ impl<'a> FfiCtx<'a> {
         GosValue::with_str(s)
     }
 
+    /// A string of any bytes, including invalid UTF-8.
+    #[inline]
+    pub fn new_string_from_bytes(b: &[u8]) -> GosValue {
+        GosValue::with_bytes(b)
+    }
+
     #[inline]
     pub fn new_unsafe_ptr(p: Rc<dyn UnsafePtr>) -> GosValue {
         GosValue::new_unsafe_ptr(p)
@@ -86,6 +114,23 @@ impl<'a> FfiCtx<'a> {
         GosValue::array_with_data(member, self.array_slice_caller.get(t_elem), self.gcc)
     }
 
+    #[inline]
+    pub fn new_slice(&self, member: Vec<GosValue>, t_elem: ValueType) -> GosValue {
+        GosValue::slice_with_data(member, self.array_slice_caller.get(t_elem), self.gcc)
+    }
+
+    /// The elements of an array as values.
+    #[inline]
+    pub fn array_elems(&self, val: &GosValue) -> Vec<GosValue> {
+        val.caller(self.array_slice_caller).array_get_vec(val)
+    }
+
+    /// The elements of a slice as values, None if it's nil.
+    #[inline]
+    pub fn slice_elems(&self, val: &GosValue) -> Option<Vec<GosValue>> {
+        val.caller(self.array_slice_caller).slice_get_vec(val)
+    }
+
     #[inline]
     pub fn new_primitive_array<T>(&self, member: Vec<T>, t_elem: ValueType) -> GosValue
     where
@@ -133,10 +178,213 @@ impl<'a> FfiCtx<'a> {
         GosValue::new_interface(InterfaceObj::with_value(underlying, Some((meta, vec![]))))
     }
 
+    /// Creates an `error` that formats as `msg`, a `*errors.errorString` like
+    /// the ones `errors.New` returns, so the package `errors` must be in the
+    /// program.
+    pub fn new_error(&self, msg: &str) -> RuntimeResult<GosValue> {
+        let typ = self.named_type("errors.errorString").map_err(|_| {
+            format!(
+                "FFI: cannot create the error '{}' without the package errors",
+                msg
+            )
+        })?;
+        let metas = &self.vm_objs.metas;
+        let val = self.zero_val(&typ.into_value_category());
+        val.as_struct().0.borrow_fields_mut()[0] = FfiCtx::new_string(msg);
+        let meta = typ.into_value_category().ptr_to();
+        let binding = meta.get_iface_binding(&"Error".to_owned(), metas).unwrap();
+        Ok(GosValue::new_interface(InterfaceObj::with_value(
+            FfiCtx::new_pointer(val),
+            Some((meta, vec![binding.into()])),
+        )))
+    }
+
+    /// The results of a call to a method whose last result is an `error`
+    /// that failed with `msg`, the other results are zero values.
+    pub fn error_results(&self, msg: &str) -> RuntimeResult<Vec<GosValue>> {
+        let sig = self.vm_objs.metas[self.func_meta.key].as_signature();
+        let (err, others) = sig
+            .results
+            .split_last()
+            .ok_or_else(|| format!("FFI: '{}' has no error result for: {}", self.func_name, msg))?;
+        let mut results: Vec<GosValue> = others.iter().map(|m| self.zero_val(m)).collect();
+        if err.value_type(&self.vm_objs.metas) != ValueType::Interface {
+            return Err(format!(
+                "FFI: the last result of '{}' is not an error, for: {}",
+                self.func_name, msg
+            )
+            .into());
+        }
+        results.push(self.new_error(msg)?);
+        Ok(results)
+    }
+
+    /// Wraps a host value as an opaque `interface{}`, which the script can pass
+    /// around, compare and print, and which comes back intact in `as_user_data`.
+    #[inline]
+    pub fn new_user_data(&self, p: Rc<dyn UnsafePtr>) -> GosValue {
+        let meta = self.vm_objs.prim_meta.unsafe_ptr;
+        self.new_empty_interface(GosValue::new_unsafe_ptr(p), meta)
+    }
+
+    /// Same as `new_user_data` but the interface holds a value of the named
+    /// type `typ`, which must be declared as `type T unsafe.Pointer`. Scripts
+    /// can then tell host values apart with type switches and assertions.
+    pub fn new_typed_user_data(&self, p: Rc<dyn UnsafePtr>, typ: Meta) -> RuntimeResult<GosValue> {
+        let metas = &self.vm_objs.metas;
+        if typ.underlying(metas).key != self.vm_objs.prim_meta.unsafe_ptr.key {
+            return Err("the type of a host value must be based on unsafe.Pointer"
+                .to_owned()
+                .into());
+        }
+        let meta = typ.into_value_category();
+        Ok(self.new_empty_interface(GosValue::new_unsafe_ptr(p), meta))
+    }
+
+    /// Looks up a type declared at package level by its qualified name,
+    /// e.g. "os.File", for `new_typed_user_data`.
+    pub fn named_type(&self, name: &str) -> RuntimeResult<Meta> {
+        let not_found = || format!("type '{}' not found", name);
+        let (pkg_name, type_name) = name.split_once('.').ok_or_else(not_found)?;
+        self.packages
+            .iter()
+            .filter(|pkg| pkg.name() == pkg_name)
+            .find_map(|pkg| {
+                let member = pkg.member(*pkg.member_index(type_name)?);
+                (member.typ() == ValueType::Metadata).then(|| *member.as_metadata())
+            })
+            .ok_or_else(|| not_found().into())
+    }
+
+    /// Keeps a closure passed in by the script, so that the host can call it
+    /// later, see `Callback`.
+    pub fn new_callback(&self, closure: &GosValue) -> RuntimeResult<Callback> {
+        let queue = self
+            .callbacks
+            .ok_or_else(|| "callbacks are not available".to_owned())?;
+        let cls = match closure.as_closure() {
+            Some((cls @ ClosureObj::Gos(_), _)) => cls.clone(),
+            Some(_) => return Err("callback must be a Goscript function".to_owned().into()),
+            None => return Err("callback is nil".to_owned().into()),
+        };
+        let sig = self.vm_objs.metas[cls.as_gos().meta.key].as_signature();
+        Ok(Callback::new(
+            cls,
+            sig.params_type.clone(),
+            Rc::downgrade(queue),
+        ))
+    }
+
+    /// The ID of the calling goroutine, unique within a run, the goroutine
+    /// running `main` is 0. A call made with `Callback::call_blocking` has the
+    /// ID of the goroutine waiting for it.
+    #[inline]
+    pub fn goroutine_id(&self) -> usize {
+        self.goroutine_id
+    }
+
+    /// The number of goroutines that have started and not finished yet,
+    /// including the calling one.
+    #[inline]
+    pub fn goroutine_count(&self) -> usize {
+        self.goroutine_count
+    }
+
+    /// The event sources the run was given, see `Limits::events`.
+    #[inline]
+    pub fn events(&self) -> Option<&Events> {
+        self.events
+    }
+
+    /// The seed of the run, see `Limits::seed`.
+    #[inline]
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// The command line arguments of the run, see `RunConfig::args`.
+    #[inline]
+    pub fn args(&self) -> &[String] {
+        self.args
+    }
+
+    /// The environment variables of the run, see `RunConfig::env`.
+    #[inline]
+    pub fn env(&self) -> &[(String, String)] {
+        self.env
+    }
+
+    /// What the host tells the scripts about itself, see `RunConfig::app_info`.
+    #[inline]
+    pub fn app_info(&self) -> &AppInfo {
+        self.app_info
+    }
+
+    /// The value of type `T` the host set for the engine, see
+    /// `FfiFactory::set_host_data`.
+    #[inline]
+    pub fn host_data<T: Any>(&self) -> Option<&T> {
+        self.host_data
+            .get(&TypeId::of::<T>())
+            .and_then(|x| x.downcast_ref::<T>())
+    }
+
+    /// Stops the run with `code` as soon as the FFI call returns, like Go's
+    /// `os.Exit`, the deferred calls are not run. The run fails with a
+    /// PanicData whose `exit_code` is `code`.
+    pub fn exit(&self, code: i32) {
+        self.exit_code.set(Some(code));
+    }
+
+    /// Frees the objects only kept alive by reference cycles right away,
+    /// instead of waiting for a goroutine to finish.
+    pub fn collect_garbage(&self) {
+        crate::gc::collect(self.gcc);
+    }
+
+    /// The value last set by `set_goroutine_local` in the calling goroutine,
+    /// a goroutine starts with the value of the one that started it.
+    pub fn goroutine_local(&self) -> Option<Rc<dyn Any>> {
+        self.goroutine_local.borrow().clone()
+    }
+
+    /// Keeps a host value for the calling goroutine, e.g. the request it serves,
+    /// so that later FFI calls from it, or from goroutines it starts, can find it.
+    pub fn set_goroutine_local(&self, val: Option<Rc<dyn Any>>) {
+        *self.goroutine_local.borrow_mut() = val;
+    }
+
+    /// Returns the host value in an `interface{}` or an `unsafe.Pointer`.
+    pub fn as_user_data<T: Any>(val: &GosValue) -> RuntimeResult<&T> {
+        let ptr = match val.typ() {
+            ValueType::Interface => val
+                .as_interface()
+                .and_then(|x| x.underlying_value())
+                .filter(|x| x.typ() == ValueType::UnsafePtr)
+                .ok_or_else(|| "interface does not hold a host value".to_owned())?,
+            _ => val,
+        };
+        ptr.as_non_nil_unsafe_ptr()?.downcast_ref::<T>()
+    }
+
     #[inline]
     pub fn deref_pointer(&self, ptr: &GosValue) -> RuntimeResult<GosValue> {
-        ptr.as_non_nil_pointer()?
-            .deref(&self.stack, &self.vm_objs.packages)
+        ptr.as_non_nil_pointer()?.deref(self.stack, self.packages)
+    }
+
+    /// Formats the value like Go's fmt does with the verb, `meta` is the type
+    /// of the value if it's known, values in interfaces carry their types.
+    pub fn format_value(&self, val: &GosValue, meta: Option<Meta>, verb: Verb) -> String {
+        ValueFmt::new(val, verb)
+            .with_type(meta, self.vm_objs)
+            .with_pointees(self.packages, Some(self.stack))
+            .to_string()
+    }
+
+    /// Returns the value with its type, to look into it by paths, see
+    /// `GosValueRef::field_by_path`.
+    pub fn value_ref(&self, val: GosValue, meta: Meta) -> GosValueRef<'_> {
+        GosValueRef::new(val, meta, self.vm_objs, self.packages, Some(self.stack))
     }
 
     #[inline]
@@ -202,6 +450,8 @@ pub struct FfiFactory {
     /// Down-casting only works for 'static types,
     /// so we just use the good old pointers
     user_data: Option<usize>,
+    /// the values the FFIs of this factory share, one per type
+    host_data: Map<TypeId, Rc<dyn Any>>,
 }
 
 impl FfiFactory {
@@ -209,6 +459,7 @@ impl FfiFactory {
         FfiFactory {
             registry: Map::new(),
             user_data: None,
+            host_data: Map::new(),
         }
     }
 
@@ -216,9 +467,20 @@ impl FfiFactory {
         FfiFactory {
             registry: Map::new(),
             user_data: Some(ptr),
+            host_data: Map::new(),
         }
     }
 
+    /// Sets the value of type `T` the FFIs get with `FfiCtx::host_data`,
+    /// so that what they are configured with belongs to an engine rather than
+    /// to the process. None removes it.
+    pub fn set_host_data<T: Any>(&mut self, data: Option<T>) {
+        match data {
+            Some(d) => self.host_data.insert(TypeId::of::<T>(), Rc::new(d)),
+            None => self.host_data.remove(&TypeId::of::<T>()),
+        };
+    }
+
     pub fn register(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
         assert!(self.registry.insert(name, proto).is_none());
     }
@@ -227,6 +489,11 @@ impl FfiFactory {
         self.user_data
     }
 
+    #[inline]
+    pub(crate) fn host_data(&self) -> &Map<TypeId, Rc<dyn Any>> {
+        &self.host_data
+    }
+
     pub(crate) fn create(&self, name: &str) -> RuntimeResult<Rc<dyn Ffi>> {
         match self.registry.get(name) {
             Some(proto) => Ok(proto.clone()),
@@ -247,6 +514,10 @@ pub struct CodeGenVMCtx {
     dummy_func_name: &'static str,
     dummy_stack: Stack,
     dummy_gcc: GcContainer,
+    dummy_local: RefCell<Option<Rc<dyn Any>>>,
+    dummy_exit_code: Cell<Option<i32>>,
+    dummy_app_info: AppInfo,
+    dummy_host_data: Map<TypeId, Rc<dyn Any>>,
     caller: ArrCaller,
 }
 
@@ -257,6 +528,10 @@ impl CodeGenVMCtx {
             dummy_func_name: "dummy_name",
             dummy_stack: Stack::new(),
             dummy_gcc: GcContainer::new(),
+            dummy_local: RefCell::new(None),
+            dummy_exit_code: Cell::new(None),
+            dummy_app_info: AppInfo::default(),
+            dummy_host_data: Map::new(),
             caller: ArrCaller::new(),
         }
     }
@@ -264,11 +539,24 @@ impl CodeGenVMCtx {
     pub fn ffi_ctx(&mut self) -> FfiCtx {
         FfiCtx {
             func_name: self.dummy_func_name,
+            func_meta: self.vm_objs.prim_meta.default_sig,
             vm_objs: &self.vm_objs,
+            packages: &self.vm_objs.packages,
             user_data: None,
             stack: &mut self.dummy_stack,
             gcc: &&self.dummy_gcc,
             array_slice_caller: &self.caller,
+            callbacks: None,
+            goroutine_id: 0,
+            goroutine_count: 1,
+            events: None,
+            seed: 1,
+            args: &[],
+            env: &[],
+            app_info: &self.dummy_app_info,
+            exit_code: &self.dummy_exit_code,
+            goroutine_local: &self.dummy_local,
+            host_data: &self.dummy_host_data,
         }
     }
 
@@ -0,0 +1,147 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Float formatting compatible with Go's `strconv.FormatFloat(f, 'g', -1, bitSize)`,
+//! which is also what `%v` uses in Go's fmt package.
+//!
+//! The shortest decimal digits that round trip are produced by Rust's own float
+//! formatting (Grisu with a Dragon4 fallback), only the layout is done here.
+
+use std::fmt::{self, Write};
+
+/// Same as strconv's %g: use %e if the exponent is less than -4 or
+/// greater than or equal to the precision, which is 6 for the shortest form.
+const EXP_PRECISION: i32 = 6;
+
+/// Formats a float64 the way Go does.
+pub fn format_f64(f: f64) -> String {
+    let mut s = String::new();
+    write_f64(&mut s, f, false).unwrap();
+    s
+}
+
+/// Formats a float32 the way Go does, using the shortest representation
+/// that round trips as a float32.
+pub fn format_f32(f: f32) -> String {
+    let mut s = String::new();
+    write_f32(&mut s, f, false).unwrap();
+    s
+}
+
+/// Writes a float64 in Go's shortest form, with a leading '+' for non-negative
+/// numbers if `plus` is set, like the imaginary part of a complex number.
+pub fn write_f64<W: Write>(w: &mut W, f: f64, plus: bool) -> fmt::Result {
+    if f.is_nan() {
+        return w.write_str(if plus { "+NaN" } else { "NaN" });
+    }
+    if f.is_infinite() {
+        return w.write_str(if f.is_sign_negative() { "-Inf" } else { "+Inf" });
+    }
+    write_finite(w, &format!("{:e}", f.abs()), f.is_sign_negative(), plus)
+}
+
+/// Writes a float32 in Go's shortest form, see `write_f64`.
+pub fn write_f32<W: Write>(w: &mut W, f: f32, plus: bool) -> fmt::Result {
+    if f.is_nan() {
+        return w.write_str(if plus { "+NaN" } else { "NaN" });
+    }
+    if f.is_infinite() {
+        return w.write_str(if f.is_sign_negative() { "-Inf" } else { "+Inf" });
+    }
+    write_finite(w, &format!("{:e}", f.abs()), f.is_sign_negative(), plus)
+}
+
+/// `sci` is the shortest scientific form of the absolute value produced by Rust,
+/// i.e. "1.2345e-7" or "5e0".
+fn write_finite<W: Write>(w: &mut W, sci: &str, neg: bool, plus: bool) -> fmt::Result {
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let digits: Vec<u8> = mantissa.bytes().filter(|&b| b != b'.').collect();
+    // digits are d[0].d[1]d[2]... * 10^exp
+    let nd = digits.len() as i32;
+
+    if neg {
+        w.write_char('-')?;
+    } else if plus {
+        w.write_char('+')?;
+    }
+
+    if !(-4..EXP_PRECISION).contains(&exp) {
+        // %e
+        w.write_char(digits[0] as char)?;
+        if nd > 1 {
+            w.write_char('.')?;
+            write_digits(w, &digits[1..])?;
+        }
+        let (sign, exp) = if exp < 0 { ('-', -exp) } else { ('+', exp) };
+        write!(w, "e{}{:02}", sign, exp)
+    } else {
+        // %f, dp is the position of the decimal point
+        let dp = exp + 1;
+        if dp <= 0 {
+            w.write_str("0.")?;
+            for _ in 0..-dp {
+                w.write_char('0')?;
+            }
+            write_digits(w, &digits)
+        } else if dp >= nd {
+            write_digits(w, &digits)?;
+            for _ in 0..dp - nd {
+                w.write_char('0')?;
+            }
+            Ok(())
+        } else {
+            write_digits(w, &digits[..dp as usize])?;
+            w.write_char('.')?;
+            write_digits(w, &digits[dp as usize..])
+        }
+    }
+}
+
+#[inline]
+fn write_digits<W: Write>(w: &mut W, digits: &[u8]) -> fmt::Result {
+    for d in digits.iter() {
+        w.write_char(*d as char)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_f64() {
+        let cases: &[(f64, &str)] = &[
+            (0.0, "0"),
+            (-0.0, "-0"),
+            (1.0, "1"),
+            (0.1, "0.1"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (-2.5, "-2.5"),
+            (123456.0, "123456"),
+            (1234567.0, "1.234567e+06"),
+            (100000000.0, "1e+08"),
+            (0.0001, "0.0001"),
+            (0.00001, "1e-05"),
+            (1e21, "1e+21"),
+            (1.5e-300, "1.5e-300"),
+            (std::f64::consts::PI, "3.141592653589793"),
+            (f64::MAX, "1.7976931348623157e+308"),
+            (f64::INFINITY, "+Inf"),
+            (f64::NEG_INFINITY, "-Inf"),
+            (f64::NAN, "NaN"),
+        ];
+        for (f, s) in cases.iter() {
+            assert_eq!(format_f64(*f), *s);
+        }
+    }
+
+    #[test]
+    fn test_format_f32() {
+        assert_eq!(format_f32(0.1), "0.1");
+        assert_eq!(format_f32(1.0 / 3.0), "0.33333334");
+        assert_eq!(format_f32(16777216.0), "1.6777216e+07");
+    }
+}
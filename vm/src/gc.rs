@@ -6,21 +6,54 @@ use super::instruction::ValueType;
 use super::objects::*;
 use super::value::{GosValue, RCQueue, RCount, IRC};
 use std::cell::Ref;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert::TryFrom;
 use std::rc::{Rc, Weak};
 
 pub struct GcContainer {
     inner: Rc<RefCell<Vec<GcWeak>>>,
+    allocated: Cell<usize>,
+    allocation_limit: Cell<usize>,
 }
 
 impl GcContainer {
     pub fn new() -> GcContainer {
         GcContainer {
             inner: Rc::new(RefCell::new(Vec::new())),
+            allocated: Cell::new(0),
+            allocation_limit: Cell::new(usize::MAX),
         }
     }
 
+    /// Total bytes allocated for arrays, slices, maps, structs, closures,
+    /// channel buffers and the strings built at runtime, freed memory is not
+    /// subtracted.
+    #[inline]
+    pub fn allocated(&self) -> usize {
+        self.allocated.get()
+    }
+
+    #[inline]
+    pub fn add_allocated(&self, bytes: usize) {
+        self.allocated
+            .set(self.allocated.get().saturating_add(bytes));
+    }
+
+    #[inline]
+    pub fn allocation_limit(&self) -> usize {
+        self.allocation_limit.get()
+    }
+
+    #[inline]
+    pub fn set_allocation_limit(&self, bytes: usize) {
+        self.allocation_limit.set(bytes);
+    }
+
+    #[inline]
+    pub(crate) fn exceeds_allocation_limit(&self, bytes: usize) -> bool {
+        self.allocated.get().saturating_add(bytes) > self.allocation_limit.get()
+    }
+
     pub fn add_array(&self, arr: &Rc<(GosArrayObj, RCount)>) {
         self.add_weak(GcWeak::new_array(arr))
     }
@@ -175,25 +208,14 @@ fn break_cycle(val: &GosValue) {
 
 /// put the non-zero-rc on the left, and the others on the right
 fn partition_to_scan(to_scan: &mut Vec<GosValue>) -> usize {
-    let len = to_scan.len();
-    if len == 0 {
-        return 0;
-    }
-    let mut p0 = 0;
-    let mut p1 = len - 1;
-    loop {
-        while p0 < len - 1 && to_scan[p0].rc() > 0 {
-            p0 += 1;
-        }
-        while p1 > 1 && to_scan[p1].rc() <= 0 {
-            p1 -= 1;
-        }
-        if p0 >= p1 {
-            break;
+    let mut boundary = 0;
+    for i in 0..to_scan.len() {
+        if to_scan[i].rc() > 0 {
+            to_scan.swap(boundary, i);
+            boundary += 1;
         }
-        to_scan.swap(p0, p1);
     }
-    p0
+    boundary
 }
 
 pub(crate) fn collect(objs: &GcContainer) {
@@ -240,3 +262,37 @@ pub(crate) fn collect(objs: &GcContainer) {
         .collect();
     //print!("objs left after GC: {}\n", result.len());
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::value::ArrCaller;
+
+    #[test]
+    fn test_partition_to_scan() {
+        let gcc = &GcContainer::new();
+        let caller = ArrCaller::get_slow(ValueType::Int);
+        for rcs in [
+            vec![1],
+            vec![0],
+            vec![1, 0],
+            vec![0, 1],
+            vec![0, 0, 1],
+            vec![0, 1, 0, 1],
+            vec![1, 1, 0, 0],
+        ] {
+            let mut vals: Vec<GosValue> = rcs
+                .iter()
+                .map(|rc| {
+                    let v = GosValue::array_with_data(vec![], &caller, gcc);
+                    v.set_rc(*rc);
+                    v
+                })
+                .collect();
+            let boundary = partition_to_scan(&mut vals);
+            assert_eq!(boundary, rcs.iter().filter(|x| **x > 0).count());
+            assert!(vals[..boundary].iter().all(|x| x.rc() > 0));
+            assert!(vals[boundary..].iter().all(|x| x.rc() <= 0));
+        }
+    }
+}
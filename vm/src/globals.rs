@@ -0,0 +1,192 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Globals let the host set the package vars of a program before it runs and
+//! read them after, by name, e.g. to inject configuration and fetch results
+//! without declaring FFI functions for them.
+
+use crate::gc::GcContainer;
+use crate::meta_view::MetaView;
+use crate::value::*;
+use crate::value_ref::GosValueRef;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Globals is shared by the host and the runs it's given to, see
+/// `Limits::globals`.
+#[derive(Clone, Default)]
+pub struct Globals {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    // set by the host, for every run
+    values: Vec<(PackageKey, OpIndex, GosValue)>,
+    // the packages of the last run, after it exited
+    packages: Option<PackageObjs>,
+}
+
+impl Globals {
+    pub fn new() -> Globals {
+        Globals::default()
+    }
+
+    /// Sets the var `name` of the package `pkg` in the runs of `code`. The value
+    /// replaces the initial value of the var, before the init functions of the
+    /// package run. `meta` is the type of the value, it must be identical to
+    /// the type of the var, see `typ`.
+    pub fn set(
+        &self,
+        code: &Bytecode,
+        pkg: &str,
+        name: &str,
+        val: GosValue,
+        meta: Meta,
+    ) -> RuntimeResult<()> {
+        let (pkey, index, var_meta) = lookup(code, pkg, name)?;
+        check_assign(
+            &val,
+            meta,
+            var_meta,
+            &code.objects,
+            &format!("{}.{}", pkg, name),
+        )?;
+        let mut state = self.state.borrow_mut();
+        state.values.retain(|x| (x.0, x.1) != (pkey, index));
+        state.values.push((pkey, index, val));
+        Ok(())
+    }
+
+    /// The type of the var `name` of the package `pkg`.
+    pub fn typ<'a>(
+        &self,
+        code: &'a Bytecode,
+        pkg: &str,
+        name: &str,
+    ) -> RuntimeResult<MetaView<'a>> {
+        let (_, _, meta) = lookup(code, pkg, name)?;
+        Ok(MetaView::new(meta, &code.objects))
+    }
+
+    /// Returns the value the var `name` of the package `pkg` had when the
+    /// last run of `code` exited.
+    pub fn get(&self, code: &Bytecode, pkg: &str, name: &str) -> RuntimeResult<GosValue> {
+        let (pkey, index, _) = lookup(code, pkg, name)?;
+        match &self.state.borrow().packages {
+            Some(pkgs) => Ok(pkgs[pkey].member(index).clone()),
+            None => Err("no run has exited yet".to_owned().into()),
+        }
+    }
+
+    /// Calls `f` with the value the var `name` of the package `pkg` had when
+    /// the last run of `code` exited, to look into it by paths, see
+    /// `GosValueRef::field_by_path`.
+    pub fn with_ref<R>(
+        &self,
+        code: &Bytecode,
+        pkg: &str,
+        name: &str,
+        f: impl FnOnce(GosValueRef) -> R,
+    ) -> RuntimeResult<R> {
+        let (pkey, index, meta) = lookup(code, pkg, name)?;
+        match &self.state.borrow().packages {
+            Some(pkgs) => {
+                let val = pkgs[pkey].member(index).clone();
+                Ok(f(GosValueRef::new(val, meta, &code.objects, pkgs, None)))
+            }
+            None => Err("no run has exited yet".to_owned().into()),
+        }
+    }
+
+    /// Sets the vars of `packages` to the values the vars of the same names had
+    /// when the last run exited, and marks the packages as initialized, so
+    /// that they are not initialized again. A package is left alone unless
+    /// all of its vars are found with values of the same types, e.g. the
+    /// names were stripped or the package has changed since.
+    pub(crate) fn restore(&self, packages: &PackageObjs, gcc: &GcContainer) {
+        let state = self.state.borrow();
+        let last = match &state.packages {
+            Some(pkgs) => pkgs,
+            None => return,
+        };
+        for pkg in packages.iter() {
+            let from = match last.iter().find(|x| x.name() == pkg.name() && x.inited()) {
+                Some(p) => p,
+                None => continue,
+            };
+            let vars: Option<Vec<(OpIndex, GosValue)>> = pkg
+                .member_indices()
+                .iter()
+                .filter(|(_, i)| pkg.var_meta(**i).is_some())
+                .map(|(name, i)| {
+                    let j = from
+                        .member_index(name)
+                        .filter(|j| from.var_meta(**j).is_some())?;
+                    let val = from.member(*j);
+                    (val.typ() == pkg.member(*i).typ()).then(|| (*i, val.copy_semantic(gcc)))
+                })
+                .collect();
+            if let Some(vars) = vars {
+                for (i, val) in vars.into_iter() {
+                    *pkg.member_mut(i) = val;
+                }
+                pkg.set_inited();
+            }
+        }
+    }
+
+    /// Called when the vars of a package are initialized.
+    pub(crate) fn apply(&self, pkey: PackageKey, pkg: &PackageObj, gcc: &GcContainer) {
+        for (key, index, val) in self.state.borrow().values.iter() {
+            if *key == pkey {
+                *pkg.member_mut(*index) = val.copy_semantic(gcc);
+            }
+        }
+    }
+
+    /// Called when a run exits.
+    pub(crate) fn keep(&self, packages: PackageObjs) {
+        self.state.borrow_mut().packages = Some(packages);
+    }
+}
+
+/// Checks that a value of type `meta` can be assigned to `target` of type
+/// `target_meta`, the types must be identical.
+pub(crate) fn check_assign(
+    val: &GosValue,
+    meta: Meta,
+    target_meta: Meta,
+    objs: &VMObjects,
+    target: &str,
+) -> RuntimeResult<()> {
+    let (meta, target_meta) = (
+        meta.into_value_category(),
+        target_meta.into_value_category(),
+    );
+    if val.typ() != meta.value_type(&objs.metas) || !meta.identical(&target_meta, &objs.metas) {
+        return Err(format!(
+            "cannot use {} value as {} value in assignment to {}",
+            MetaView::new(meta, objs),
+            MetaView::new(target_meta, objs),
+            target
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn lookup(code: &Bytecode, pkg: &str, name: &str) -> RuntimeResult<(PackageKey, OpIndex, Meta)> {
+    let (pkey, pobj) = code
+        .objects
+        .packages
+        .iter()
+        .enumerate()
+        .find(|(_, x)| x.name() == pkg)
+        .map(|(i, x)| (PackageKey::from(i), x))
+        .ok_or_else(|| format!("package {} not found", pkg))?;
+    pobj.member_index(name)
+        .and_then(|i| pobj.var_meta(*i).map(|m| (pkey, *i, *m)))
+        .ok_or_else(|| format!("{}.{} is not a var", pkg, name).into())
+}
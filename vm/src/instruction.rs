@@ -14,6 +14,14 @@ use std::fmt::Debug;
 
 pub type OpIndex = i32;
 
+/// The hash SWITCH_TABLE uses to pick the bucket of a string case, FNV-1a.
+#[inline]
+pub fn switch_hash(s: &[u8]) -> usize {
+    s.iter().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    }) as usize
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Opcode {
@@ -36,6 +44,7 @@ pub enum Opcode {
     STORE_POINTER,
     LOAD_UP_VALUE,
     STORE_UP_VALUE,
+    CLOSE_UP_VALUES,
 
     // arithmetic, logical, ref, arrow
     ADD,            // +
@@ -90,6 +99,7 @@ pub enum Opcode {
     JUMP_IF,
     JUMP_IF_NOT,
     SWITCH,
+    SWITCH_TABLE,
     SELECT,
     RANGE_INIT,
     RANGE,
@@ -114,9 +124,12 @@ pub enum Opcode {
     IMAG,    // for built-in function imag
     LEN,     // for built-in function len
     CAP,     // for built-in function cap
+    MIN,     // for built-in function min
+    MAX,     // for built-in function max
     APPEND,  // for built-in function append
     COPY,    // for built-in function copy
     DELETE,  // for built-in function delete
+    CLEAR,   // for built-in function clear
     CLOSE,   // for built-in function close
     PANIC,   // for built-in function panic
     RECOVER, // for built-in function recover
@@ -280,6 +293,7 @@ impl Instruction {
                 Opcode::STORE_POINTER => 0,
                 Opcode::LOAD_UP_VALUE => cur.d,
                 Opcode::STORE_UP_VALUE => 0,
+                Opcode::CLOSE_UP_VALUES => 0,
                 Opcode::ADD => cur.d,
                 Opcode::SUB => cur.d,
                 Opcode::MUL => cur.d,
@@ -331,6 +345,10 @@ impl Instruction {
                 Opcode::JUMP_IF => 0,
                 Opcode::JUMP_IF_NOT => 0,
                 Opcode::SWITCH => 0,
+                Opcode::SWITCH_TABLE => {
+                    i += cur.d as usize;
+                    0
+                }
                 Opcode::SELECT => {
                     let begin = i + 1;
                     i += cur.s0 as usize;
@@ -388,10 +406,13 @@ impl Instruction {
                 Opcode::APPEND => cur.d,
                 Opcode::COPY => cur.d,
                 Opcode::DELETE => 0,
+                Opcode::CLEAR => 0,
                 Opcode::CLOSE => 0,
                 Opcode::PANIC => 0,
                 Opcode::RECOVER => cur.d,
                 Opcode::ASSERT => 0,
+                Opcode::MIN => cur.d,
+                Opcode::MAX => cur.d,
                 Opcode::FFI => cur.d,
             };
             result = std::cmp::max(result, index);
@@ -8,6 +8,7 @@
 //! - `async`: Channel and goroutine support
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 //! - `instruction_pos`: Add instruction position to bytecode for debugging
+//! - `opcode_stats`: Count the executed instructions by opcode, see `OpcodeStats`
 //! - `serde_borsh`: Serde support for bytecode using Borsh
 
 mod instruction;
@@ -18,12 +19,24 @@ mod channel;
 mod objects;
 #[macro_use]
 mod dispatcher;
+mod builder;
 mod bytecode;
+mod callback;
+mod events;
 mod ffi;
+mod globals;
+mod meta_view;
+mod pool;
 mod stack;
+mod strip;
+#[cfg(feature = "opcode_stats")]
+mod stats;
 mod value;
+mod value_fmt;
+mod value_ref;
 mod vm;
 
+pub mod ftoa;
 pub mod gc;
 pub mod types {
     pub use crate::value::*;
@@ -34,14 +47,32 @@ pub mod parser {
 }
 
 pub use {
+    builder::{BuildError, BytecodeBuilder, FuncBuilder, Inst, Label, Operand},
+    callback::{Callback, CallbackCall, CallbackResult},
+    events::{Events, Subscription},
     ffi::*,
+    globals::Globals,
     go_parser::{Map, MapIter},
     go_pmacro::{ffi_impl, Ffi, UnsafePtr},
+    meta_view::{FieldView, MetaView, MethodView},
+    pool::{PoolCaps, PoolCounts},
+    strip::{DebugInfo, Stripped},
     value::Bytecode,
-    vm::run,
+    value_fmt::{ValueFmt, Verb, DEFAULT_MAX_DEPTH},
+    value_ref::GosValueRef,
     vm::PanicData,
+    vm::{
+        eval_entry, run, run_entry, run_with_config, run_with_limits, AppInfo, Interrupt, Limits,
+        RunConfig,
+    },
 };
 
+#[cfg(feature = "opcode_stats")]
+pub use stats::OpcodeStats;
+
+/// The version of the VM, scripts see it as `runtime/buildinfo.Version`.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 pub struct CallStackDisplay<'a> {
     panic_data: &'a PanicData,
     bc: &'a Bytecode,
@@ -56,21 +87,13 @@ impl<'a> CallStackDisplay<'a> {
 impl<'a> std::fmt::Display for CallStackDisplay<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for (fkey, pc) in self.panic_data.call_stack.iter() {
-            let func = &self.bc.objects.functions[*fkey];
-            if let Some(p) = func.pos[*pc as usize] {
-                if let Some(fs) = &self.bc.file_set {
-                    writeln!(
-                        f,
-                        "{}",
-                        fs.position(p as usize)
-                            .unwrap_or(go_parser::FilePos::null())
-                    )?;
-                } else {
-                    writeln!(f, "fileset not available, pos:{}", p)?;
-                }
-            } else {
-                f.write_str("<no debug info available for current frame>\n")?;
-            };
+            let sm = self.bc.source_map.as_ref();
+            match sm.and_then(|x| x.lookup(*fkey, *pc as usize)) {
+                // like FilePos, an unknown column is left out
+                Some((file, pos)) if pos.column == 0 => writeln!(f, "{}:{}", file, pos.line)?,
+                Some((file, pos)) => writeln!(f, "{}:{}:{}", file, pos.line, pos.column)?,
+                None => f.write_str("<no debug info available for current frame>\n")?,
+            }
         }
         Ok(())
     }
@@ -0,0 +1,331 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A read-only view over the metadata of a program, so that hosts can look
+//! into the types a script declares, e.g. to generate bindings or UIs, without
+//! parsing the source.
+
+use crate::value::*;
+use std::fmt;
+
+/// MetaView is a type of the program, see `Bytecode::types` for the types a
+/// package declares.
+#[derive(Clone, Copy)]
+pub struct MetaView<'a> {
+    meta: Meta,
+    objs: &'a VMObjects,
+}
+
+/// A field of a struct type.
+#[derive(Clone, Copy)]
+pub struct FieldView<'a> {
+    pub name: &'a str,
+    pub tag: Option<&'a str>,
+    pub embedded: bool,
+    pub typ: MetaView<'a>,
+}
+
+/// A method of a named type or an interface.
+#[derive(Clone, Copy)]
+pub struct MethodView<'a> {
+    pub name: &'a str,
+    /// the method is declared on the pointer type, always false for interfaces
+    pub pointer_recv: bool,
+    /// the signature without the receiver, None if the method has no body
+    pub typ: Option<MetaView<'a>>,
+}
+
+impl<'a> MetaView<'a> {
+    pub fn new(meta: Meta, objs: &'a VMObjects) -> MetaView<'a> {
+        MetaView {
+            meta: meta.into_value_category(),
+            objs,
+        }
+    }
+
+    pub fn meta(&self) -> Meta {
+        self.meta
+    }
+
+    /// The kind of the type, named types have the kind of their underlying
+    /// types, functions are `Closure`.
+    pub fn kind(&self) -> ValueType {
+        self.meta.value_type(&self.objs.metas)
+    }
+
+    /// The name of a named type declared at package level, None for other types.
+    /// If the type has aliases, it's the first of the names declared.
+    pub fn name(&self) -> Option<&'a str> {
+        self.declared().map(|(_, name)| name)
+    }
+
+    /// The name of the package that declares the named type.
+    pub fn package(&self) -> Option<&'a str> {
+        self.declared().map(|(pkg, _)| pkg)
+    }
+
+    pub fn is_named(&self) -> bool {
+        self.meta.ptr_depth == 0 && matches!(self.mtype(), MetadataType::Named(..))
+    }
+
+    /// The type a pointer points to, or the element type of an array, slice,
+    /// map or channel.
+    pub fn elem(&self) -> Option<MetaView<'a>> {
+        if self.meta.ptr_depth > 0 {
+            return Some(self.view(self.meta.unptr_to()));
+        }
+        match self.underlying_mtype() {
+            MetadataType::Array(m, _)
+            | MetadataType::Slice(m)
+            | MetadataType::Map(_, m)
+            | MetadataType::Channel(_, m) => Some(self.view(*m)),
+            _ => None,
+        }
+    }
+
+    /// The key type of a map.
+    pub fn key(&self) -> Option<MetaView<'a>> {
+        match self.underlying_mtype() {
+            MetadataType::Map(k, _) if self.meta.ptr_depth == 0 => Some(self.view(*k)),
+            _ => None,
+        }
+    }
+
+    /// The fields declared by a struct, without the ones promoted from its
+    /// embedded fields.
+    pub fn fields(&self) -> Vec<FieldView<'a>> {
+        match self.underlying_mtype() {
+            MetadataType::Struct(f) if self.meta.ptr_depth == 0 => f
+                .infos()
+                .iter()
+                .filter(|x| x.embedded_indices.is_none())
+                .map(|x| FieldView {
+                    name: &x.name,
+                    tag: x.tag.as_deref(),
+                    embedded: x.embedded,
+                    typ: self.view(x.meta),
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The methods of an interface, or the methods declared on a named type
+    /// or on the pointer to it, in the order they are declared.
+    pub fn methods(&self) -> Vec<MethodView<'a>> {
+        match (self.mtype(), self.underlying_mtype()) {
+            (_, MetadataType::Interface(f)) if self.meta.ptr_depth == 0 => f
+                .infos()
+                .iter()
+                .map(|x| MethodView {
+                    name: &x.name,
+                    pointer_recv: false,
+                    typ: Some(self.view(x.meta)),
+                })
+                .collect(),
+            (MetadataType::Named(methods, _), _) if self.meta.ptr_depth <= 1 => {
+                let mut names: Vec<(&String, &OpIndex)> = methods.mapping.iter().collect();
+                names.sort_by_key(|(_, i)| **i);
+                names
+                    .into_iter()
+                    .map(|(name, i)| {
+                        let desc = *methods.members[*i as usize].borrow();
+                        MethodView {
+                            name,
+                            pointer_recv: desc.pointer_recv,
+                            typ: desc.func.map(|f| self.view(self.objs.functions[f].meta)),
+                        }
+                    })
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The parameters of a function type, the last one is a slice if it's
+    /// variadic.
+    pub fn params(&self) -> Vec<MetaView<'a>> {
+        match self.sig() {
+            Some(s) => s.params.iter().map(|x| self.view(*x)).collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn results(&self) -> Vec<MetaView<'a>> {
+        match self.sig() {
+            Some(s) => s.results.iter().map(|x| self.view(*x)).collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        matches!(self.sig(), Some(s) if s.variadic.is_some())
+    }
+
+    /// Reports whether it looks like the predeclared error type.
+    fn is_error(&self) -> bool {
+        let methods = self.methods();
+        methods.len() == 1
+            && methods[0].name == "Error"
+            && matches!(methods[0].typ, Some(t) if t.params().is_empty()
+                && matches!(t.results()[..], [r] if r.kind() == ValueType::String))
+    }
+
+    fn view(&self, meta: Meta) -> MetaView<'a> {
+        MetaView::new(meta, self.objs)
+    }
+
+    fn mtype(&self) -> &'a MetadataType {
+        &self.objs.metas[self.meta.key]
+    }
+
+    fn underlying_mtype(&self) -> &'a MetadataType {
+        self.meta.mtype_unwraped(&self.objs.metas)
+    }
+
+    fn sig(&self) -> Option<&'a SigMetadata> {
+        match self.underlying_mtype() {
+            MetadataType::Signature(s) if self.meta.ptr_depth == 0 => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Looks for the package member the type is declared as. Aliases are
+    /// members too, the first declared name wins.
+    fn declared(&self) -> Option<(&'a str, &'a str)> {
+        if !self.is_named() {
+            return None;
+        }
+        self.objs.packages.iter().find_map(|pkg| {
+            pkg.member_indices()
+                .iter()
+                .filter(|(_, i)| {
+                    let member = pkg.member(**i);
+                    member.typ() == ValueType::Metadata && member.as_metadata().key == self.meta.key
+                })
+                .min_by_key(|(_, i)| **i)
+                .map(|(name, _)| (pkg.name(), name.as_str()))
+        })
+    }
+}
+
+impl<'a> fmt::Display for MetaView<'a> {
+    /// Formats the type the way Go spells it, the names of the types declared
+    /// in functions are unknown and shown as "?".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for _ in 0..self.meta.ptr_depth {
+            f.write_str("*")?;
+        }
+        let meta = Meta::new(self.meta.key, 0, false);
+        let view = self.view(meta);
+        if view.is_named() {
+            // the predeclared error and the types declared in functions are
+            // not package members
+            return match view.declared() {
+                Some((pkg, name)) => write!(f, "{}.{}", pkg, name),
+                None if view.is_error() => f.write_str("error"),
+                None => f.write_str("?"),
+            };
+        }
+        let list = |f: &mut fmt::Formatter<'_>, metas: &[Meta]| -> fmt::Result {
+            for (i, m) in metas.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", self.view(*m))?;
+            }
+            Ok(())
+        };
+        match view.underlying_mtype() {
+            MetadataType::Bool => f.write_str("bool"),
+            MetadataType::Int => f.write_str("int"),
+            MetadataType::Int8 => f.write_str("int8"),
+            MetadataType::Int16 => f.write_str("int16"),
+            MetadataType::Int32 => f.write_str("int32"),
+            MetadataType::Int64 => f.write_str("int64"),
+            MetadataType::Uint => f.write_str("uint"),
+            MetadataType::UintPtr => f.write_str("uintptr"),
+            MetadataType::Uint8 => f.write_str("uint8"),
+            MetadataType::Uint16 => f.write_str("uint16"),
+            MetadataType::Uint32 => f.write_str("uint32"),
+            MetadataType::Uint64 => f.write_str("uint64"),
+            MetadataType::Float32 => f.write_str("float32"),
+            MetadataType::Float64 => f.write_str("float64"),
+            MetadataType::Complex64 => f.write_str("complex64"),
+            MetadataType::Complex128 => f.write_str("complex128"),
+            MetadataType::UnsafePtr => f.write_str("unsafe.Pointer"),
+            MetadataType::Str => f.write_str("string"),
+            MetadataType::Array(m, size) => write!(f, "[{}]{}", size, self.view(*m)),
+            MetadataType::Slice(m) => write!(f, "[]{}", self.view(*m)),
+            MetadataType::Map(k, v) => write!(f, "map[{}]{}", self.view(*k), self.view(*v)),
+            MetadataType::Channel(t, m) => match t {
+                ChannelType::Send => write!(f, "chan<- {}", self.view(*m)),
+                ChannelType::Recv => write!(f, "<-chan {}", self.view(*m)),
+                ChannelType::SendRecv => write!(f, "chan {}", self.view(*m)),
+            },
+            MetadataType::Struct(_) => {
+                f.write_str("struct{")?;
+                for (i, x) in view.fields().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    match x.embedded {
+                        true => write!(f, "{}", x.typ)?,
+                        false => write!(f, "{} {}", x.name, x.typ)?,
+                    }
+                }
+                f.write_str("}")
+            }
+            MetadataType::Interface(fields) => {
+                f.write_str("interface{")?;
+                for (i, x) in fields.infos().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    f.write_str(&x.name)?;
+                    fmt_sig(f, self.view(x.meta), &list)?;
+                }
+                f.write_str("}")
+            }
+            MetadataType::Signature(_) => {
+                f.write_str("func")?;
+                fmt_sig(f, view, &list)
+            }
+            MetadataType::Named(..) | MetadataType::None => f.write_str("?"),
+        }
+    }
+}
+
+/// Formats the parameters and the results of a function type.
+fn fmt_sig(
+    f: &mut fmt::Formatter<'_>,
+    sig: MetaView,
+    list: &dyn Fn(&mut fmt::Formatter<'_>, &[Meta]) -> fmt::Result,
+) -> fmt::Result {
+    let s = match sig.sig() {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    f.write_str("(")?;
+    match &s.variadic {
+        Some((_, elem)) => {
+            list(f, &s.params[..s.params.len() - 1])?;
+            if s.params.len() > 1 {
+                f.write_str(", ")?;
+            }
+            write!(f, "...{}", sig.view(*elem))?;
+        }
+        None => list(f, &s.params)?,
+    }
+    f.write_str(")")?;
+    match s.results.len() {
+        0 => Ok(()),
+        1 => write!(f, " {}", sig.view(s.results[0])),
+        _ => {
+            f.write_str(" (")?;
+            list(f, &s.results)?;
+            f.write_str(")")
+        }
+    }
+}
@@ -5,7 +5,7 @@
 use crate::bytecode::{FunctionKey, MetadataKey, MetadataObjs, VMObjects};
 use crate::gc::GcContainer;
 use crate::instruction::{OpIndex, ValueType};
-use crate::objects::{IfaceBinding, StructObj};
+use crate::objects::{Binding4Runtime, IfaceBinding, StructObj};
 use crate::value::ArrCaller;
 use crate::value::GosValue;
 #[cfg(feature = "serde_borsh")]
@@ -231,6 +231,8 @@ impl Meta {
         }
     }
 
+    /// The zero value of the type. The arrays and structs nested in it are
+    /// all distinct values, setting an element of one leaves the others alone.
     #[inline]
     pub(crate) fn zero(&self, mobjs: &MetadataObjs, gcc: &GcContainer) -> GosValue {
         match self.ptr_depth {
@@ -326,7 +328,11 @@ impl Meta {
     }
 
     /// Depth-first search for method by name
-    fn get_iface_binding(&self, name: &String, metas: &MetadataObjs) -> Option<IfaceBinding> {
+    pub(crate) fn get_iface_binding(
+        &self,
+        name: &String,
+        metas: &MetadataObjs,
+    ) -> Option<IfaceBinding> {
         match &metas[self.key] {
             MetadataType::Named(m, underlying) => match m.mapping.get(name) {
                 Some(&i) => Some(IfaceBinding::Struct(m.members[i as usize].clone(), None)),
@@ -336,7 +342,9 @@ impl Meta {
                 .try_index_by_name(name)
                 .map(|x| IfaceBinding::Iface(x, None)),
             MetadataType::Struct(fields) => {
-                for (i, f) in fields.fields.iter().enumerate() {
+                // only the methods of embedded fields are promoted
+                let embedded = fields.fields.iter().enumerate().filter(|(_, f)| f.embedded);
+                for (i, f) in embedded {
                     if let Some(mut re) = f.meta.get_iface_binding(name, metas) {
                         let indices = match &mut re {
                             IfaceBinding::Struct(_, indices) | IfaceBinding::Iface(_, indices) => {
@@ -368,7 +376,112 @@ impl Meta {
     }
 
     pub fn identical(&self, other: &Self, metas: &MetadataObjs) -> bool {
-        (self.key == other.key) || metas[self.key].identical(&metas[other.key], metas)
+        self.ptr_depth == other.ptr_depth
+            && ((self.key == other.key) || metas[self.key].identical(&metas[other.key], metas))
+    }
+
+    #[inline]
+    pub fn is_interface(&self, metas: &MetadataObjs) -> bool {
+        self.ptr_depth == 0
+            && matches!(&metas[self.underlying(metas).key], MetadataType::Interface(_))
+    }
+
+    /// Reports whether values of the type can be compared with ==, slices,
+    /// maps, functions and the structs and arrays of them cannot.
+    pub fn comparable(&self, metas: &MetadataObjs) -> bool {
+        if self.ptr_depth > 0 {
+            return true;
+        }
+        match &metas[self.key] {
+            MetadataType::Named(_, u) => u.comparable(metas),
+            MetadataType::Slice(_) | MetadataType::Map(_, _) | MetadataType::Signature(_) => false,
+            MetadataType::Array(m, _) => m.comparable(metas),
+            MetadataType::Struct(f) => f
+                .infos()
+                .iter()
+                .filter(|x| x.embedded_indices.is_none())
+                .all(|x| x.meta.comparable(metas)),
+            _ => true,
+        }
+    }
+
+    /// Returns the bindings to be used at runtime if a value of this type implements
+    /// the interface `iface`, which is looked up in the method set by name and then
+    /// checked against the signatures.
+    ///
+    /// Unlike `bind_with_iface`, which is used when linking after the checker has done its job,
+    /// this is for type assertions and type switches where the type is only known at runtime.
+    pub fn implements(&self, iface: &Meta, objs: &VMObjects) -> Option<Vec<Binding4Runtime>> {
+        self.bind_iface_methods(iface, objs).ok()
+    }
+
+    /// The name of the first method of the interface that the type doesn't
+    /// have, None if it implements the interface.
+    pub fn missing_method<'a>(&self, iface: &Meta, objs: &'a VMObjects) -> Option<&'a str> {
+        self.bind_iface_methods(iface, objs).err()
+    }
+
+    fn bind_iface_methods<'a>(
+        &self,
+        iface: &Meta,
+        objs: &'a VMObjects,
+    ) -> Result<Vec<Binding4Runtime>, &'a str> {
+        let metas = &objs.metas;
+        let methods = match &metas[iface.underlying(metas).key] {
+            MetadataType::Interface(m) => m.infos(),
+            _ => unreachable!(),
+        };
+        let mut result = Vec::with_capacity(methods.len());
+        for m in methods.iter() {
+            let binding = self.get_iface_binding(&m.name, metas).ok_or(m.name.as_str())?;
+            let sig = match &binding {
+                IfaceBinding::Struct(desc, indices) => {
+                    let desc = desc.borrow();
+                    // methods with pointer receivers are not in the method set of a value
+                    if desc.pointer_recv && !self.reaches_by_pointer(indices, metas) {
+                        return Err(&m.name);
+                    }
+                    desc.func.map(|f| objs.functions[f].meta)
+                }
+                IfaceBinding::Iface(i, indices) => {
+                    let mut embedded = self.underlying(metas);
+                    if let Some(indices) = indices {
+                        for i in indices.iter().rev() {
+                            let fields = metas[embedded.key].unwrap_named(metas).as_struct();
+                            embedded = fields.get_non_embedded(*i as usize).meta;
+                        }
+                    }
+                    let embedded = embedded.underlying(metas);
+                    Some(metas[embedded.key].as_interface().infos()[*i].meta)
+                }
+            };
+            if let Some(sig) = sig {
+                let (a, b) = (metas[sig.key].as_signature(), metas[m.meta.key].as_signature());
+                if !a.identical_ignore_recv(b, metas) {
+                    return Err(&m.name);
+                }
+            }
+            result.push(binding.into());
+        }
+        Ok(result)
+    }
+
+    /// Reports whether the value or any of the embedded fields on the path to
+    /// a promoted method is a pointer, which puts the methods with pointer
+    /// receivers in the method set.
+    fn reaches_by_pointer(&self, indices: &Option<Vec<OpIndex>>, metas: &MetadataObjs) -> bool {
+        if self.ptr_depth > 0 {
+            return true;
+        }
+        let mut cur = *self;
+        for i in indices.iter().flatten().rev() {
+            let fields = metas[cur.key].unwrap_named(metas).as_struct();
+            cur = fields.get_non_embedded(*i as usize).meta;
+            if cur.ptr_depth > 0 {
+                return true;
+            }
+        }
+        false
     }
 
     pub fn bind_with_iface(
@@ -376,17 +489,25 @@ impl Meta {
         value_meta: Self,
         metas: &MetadataObjs,
     ) -> (Meta, Vec<IfaceBinding>) {
+        self.try_bind_with_iface(value_meta, metas).unwrap()
+    }
+
+    /// Same as `bind_with_iface`, but returns the name of the first method
+    /// `value_meta` doesn't have instead of panicking.
+    pub fn try_bind_with_iface<'a>(
+        &self,
+        value_meta: Self,
+        metas: &'a MetadataObjs,
+    ) -> Result<(Meta, Vec<IfaceBinding>), &'a str> {
         let fields: Vec<&String> = match &metas[self.underlying(metas).key] {
             MetadataType::Interface(m) => m.infos().iter().map(|x| &x.name).collect(),
             _ => unreachable!(),
         };
-        (
-            value_meta,
-            fields
-                .iter()
-                .map(|x| value_meta.get_iface_binding(x, metas).unwrap())
-                .collect(),
-        )
+        let bindings = fields
+            .iter()
+            .map(|x| value_meta.get_iface_binding(x, metas).ok_or(x.as_str()))
+            .collect::<Result<_, _>>()?;
+        Ok((value_meta, bindings))
     }
 }
 
@@ -397,6 +518,9 @@ pub struct FieldInfo {
     pub name: String,
     pub tag: Option<String>,
     //pub exported: bool,
+    /// the field is declared without a name, by its type
+    pub embedded: bool,
+    /// Some for the fields promoted from embedded fields, the path to it
     pub embedded_indices: Option<Vec<usize>>,
 }
 
@@ -542,6 +666,10 @@ pub struct MethodDesc {
     pub func: Option<FunctionKey>,
 }
 
+/// The method table of a named type, the methods in the order they are
+/// added and their indices by name. Only the code generator uses the
+/// indices, to resolve the static calls into functions, the bindings to
+/// interfaces are linked by name, see `Bytecode::iface_links`.
 #[derive(Debug, Clone)]
 pub struct Methods {
     pub members: Vec<Rc<RefCell<MethodDesc>>>,
@@ -617,6 +745,10 @@ impl SigMetadata {
         } {
             return false;
         }
+        self.identical_ignore_recv(other, metas)
+    }
+
+    pub fn identical_ignore_recv(&self, other: &Self, metas: &MetadataObjs) -> bool {
         if self.params.len() != other.params.len() {
             return false;
         }
@@ -787,7 +919,8 @@ impl MetadataType {
             (Self::Channel(at, avt), Self::Channel(bt, bvt)) => {
                 at == bt && avt.identical(bvt, metas)
             }
-            (Self::Named(_, a), Self::Named(_, b)) => a.identical(b, metas),
+            // named types are only identical to themselves, which `Meta::identical`
+            // checks by key
             _ => false,
         }
     }
@@ -12,6 +12,7 @@ use crate::instruction::{Instruction, OpIndex, ValueType};
 use crate::metadata::*;
 use crate::stack::Stack;
 use crate::value::*;
+use crate::value_fmt::{Printer, Verb};
 
 #[cfg(feature = "serde_borsh")]
 use borsh::{
@@ -23,12 +24,13 @@ use std::any::Any;
 use std::borrow::Cow;
 use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::cmp::Ordering;
-use std::fmt::{self, Debug, Display, Write};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Debug, Display};
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::rc::{Rc, Weak};
-use std::{panic, ptr, str};
+use std::{ptr, str};
 
 // ----------------------------------------------------------------------------
 // MapObj
@@ -43,6 +45,9 @@ pub struct MapObj {
 }
 
 impl MapObj {
+    /// Estimated bytes per entry, for memory accounting
+    pub(crate) const ENTRY_SIZE: usize = 2 * std::mem::size_of::<GosValue>();
+
     #[inline]
     pub fn new() -> MapObj {
         Self::with_data(Map::new())
@@ -55,13 +60,22 @@ impl MapObj {
         }
     }
 
+    /// A key holding a NaN is never found again, so every insert of one adds
+    /// a new entry, as in Go.
     #[inline]
     pub fn insert(&self, key: GosValue, val: GosValue) -> Option<GosValue> {
+        let key = match key.has_nan() {
+            true => key.with_unique_nan(),
+            false => key,
+        };
         self.borrow_data_mut().insert(key, val)
     }
 
     #[inline]
     pub fn get(&self, key: &GosValue) -> Option<GosValue> {
+        if key.has_nan() {
+            return None;
+        }
         let borrow = self.borrow_data();
         let val = borrow.get(key);
         match val {
@@ -72,10 +86,19 @@ impl MapObj {
 
     #[inline]
     pub fn delete(&self, key: &GosValue) {
+        if key.has_nan() {
+            return;
+        }
         let mut mref = self.borrow_data_mut();
         mref.remove(key);
     }
 
+    #[inline]
+    pub fn clear(&self) {
+        let mut mref = self.borrow_data_mut();
+        mref.clear();
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.borrow_data().len()
@@ -115,15 +138,8 @@ impl Eq for MapObj {}
 
 impl Display for MapObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("map[")?;
-        for (i, kv) in self.map.borrow().iter().enumerate() {
-            if i > 0 {
-                f.write_char(' ')?;
-            }
-            let v: &GosValue = &kv.1;
-            write!(f, "{}:{}", kv.0, v)?
-        }
-        f.write_char(']')
+        let addr = self as *const MapObj as *const usize;
+        Printer::untyped(Verb::V).map(f, addr, self, None, 0)
     }
 }
 
@@ -377,6 +393,11 @@ where
         self.borrow_data().len()
     }
 
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.borrow_data().capacity()
+    }
+
     #[inline(always)]
     pub fn borrow_data_mut(&self) -> std::cell::RefMut<Vec<T>> {
         self.vec.borrow_mut()
@@ -405,7 +426,7 @@ where
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
         if i >= self.len() {
-            return Err(format!("index {} out of range", i).to_owned().into());
+            return Err(RuntimeError::index_out_of_range(i, self.len()));
         }
         Ok(self.borrow_data()[i].clone().into_value(t))
     }
@@ -413,7 +434,7 @@ where
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
         if i >= self.len() {
-            return Err(format!("index {} out of range", i).to_owned().into());
+            return Err(RuntimeError::index_out_of_range(i, self.len()));
         }
         Ok(self.borrow_data()[i].set_value(&val))
     }
@@ -613,12 +634,18 @@ where
 
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
+        if i >= self.len() {
+            return Err(RuntimeError::index_out_of_range(i, self.len()));
+        }
         self.array_obj().get(self.begin() + i, t)
     }
 
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
-        self.array_obj().set(i, val)
+        if i >= self.len() {
+            return Err(RuntimeError::index_out_of_range(i, self.len()));
+        }
+        self.array_obj().set(self.begin() + i, val)
     }
 
     #[inline]
@@ -636,9 +663,12 @@ where
         }
     }
 
+    /// Appends `other` and returns how many elements the capacity of the
+    /// underlying array grew by.
     #[inline]
-    pub fn append(&mut self, other: &SliceObj<T>) {
+    pub fn append(&mut self, other: &SliceObj<T>) -> usize {
         let mut data = self.borrow_all_data_mut();
+        let old_cap = data.capacity();
         let new_end = self.end() + other.len();
         let after_end_len = data.len() - self.end();
         let sharing = self.sharing_with(other);
@@ -657,11 +687,13 @@ where
                 T::copy_or_clone_slice(&mut data[self.end()..new_end], &cloned);
             }
         }
+        let grown = data.capacity() - old_cap;
         drop(data);
         *self.end.get_mut() = new_end;
         if self.cap_end.get() < self.end.get() {
             *self.cap_end.get_mut() = self.end.get();
         }
+        grown
     }
 
     #[inline]
@@ -709,9 +741,9 @@ where
     pub fn swap(&self, i: usize, j: usize) -> RuntimeResult<()> {
         let len = self.len();
         if i >= len {
-            Err(format!("index {} out of range", i).into())
+            Err(RuntimeError::index_out_of_range(i, len))
         } else if j >= len {
-            Err(format!("index {} out of range", j).into())
+            Err(RuntimeError::index_out_of_range(j, len))
         } else {
             self.borrow_all_data_mut()
                 .swap(i + self.begin(), j + self.begin());
@@ -754,7 +786,7 @@ where
         };
 
         let ei = if end < 0 {
-            this_len
+            this_begin + this_len
         } else {
             let val = this_begin + end as usize;
             if val < bi || val > cap {
@@ -797,16 +829,55 @@ pub type SliceEnumIter<'a, T> = std::iter::Enumerate<SliceIter<'a, T>>;
 // ----------------------------------------------------------------------------
 // StringObj
 
-pub type StringIter<'a> = std::str::Chars<'a>;
+/// Iterates over the runes of a string the way Go's range does: with their
+/// byte offsets, and U+FFFD for each byte that's not valid UTF-8.
+#[derive(Clone, Debug)]
+pub struct StringIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
 
-pub type StringEnumIter<'a> = std::iter::Enumerate<StringIter<'a>>;
+impl<'a> StringIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> StringIter<'a> {
+        StringIter { bytes, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for StringIter<'a> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let rest = &self.bytes[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let head = &rest[..rest.len().min(4)];
+        let valid = match std::str::from_utf8(head) {
+            Ok(s) => s,
+            Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap(),
+        };
+        let (ch, len) = match valid.chars().next() {
+            Some(ch) => (ch, ch.len_utf8()),
+            None => (char::REPLACEMENT_CHARACTER, 1),
+        };
+        let offset = self.offset;
+        self.offset += len;
+        Some((offset, ch))
+    }
+}
 
 pub type StringObj = SliceObj<Elem8>;
 
 impl StringObj {
     #[inline]
     pub fn with_str(s: &str) -> StringObj {
-        let buf: Vec<Elem8> = unsafe { std::mem::transmute(s.as_bytes().to_vec()) };
+        Self::with_bytes(s.as_bytes())
+    }
+
+    /// The bytes don't have to be valid UTF-8, like in Go.
+    #[inline]
+    pub fn with_bytes(b: &[u8]) -> StringObj {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(b.to_vec()) };
         Self::with_buf(buf)
     }
 
@@ -824,6 +895,13 @@ impl StringObj {
         unsafe { std::mem::transmute(self.as_rust_slice()) }
     }
 
+    /// The bytes of the string, which are not always valid UTF-8 like
+    /// `as_str` assumes, e.g. after string([]byte{0xff}).
+    #[inline]
+    pub fn as_bytes(&self) -> Ref<'_, [u8]> {
+        unsafe { std::mem::transmute(self.as_rust_slice()) }
+    }
+
     #[inline]
     pub fn index(&self, i: usize) -> RuntimeResult<GosValue> {
         self.get(i, ValueType::Uint8)
@@ -834,11 +912,30 @@ impl StringObj {
         self.index_elem(i).into_inner()
     }
 
-    #[inline]
+    /// When self ends where its buffer does, other is appended to the buffer
+    /// in place, which keeps `s += x` in a loop amortized linear. The bytes
+    /// of the strings already sharing the buffer are left untouched.
     pub fn add(&self, other: &StringObj) -> StringObj {
-        let mut buf = self.as_rust_slice().to_vec();
-        buf.extend_from_slice(&other.as_rust_slice());
-        Self::with_buf(buf)
+        if self.len() == 0 {
+            return other.clone();
+        } else if other.len() == 0 {
+            return self.clone();
+        }
+        let mut data = self.borrow_all_data_mut();
+        if self.end() != data.len() {
+            drop(data);
+            let mut buf = self.as_rust_slice().to_vec();
+            buf.extend_from_slice(&other.as_rust_slice());
+            return Self::with_buf(buf);
+        }
+        if self.sharing_with(other) {
+            data.extend_from_within(other.range());
+        } else {
+            data.extend_from_slice(&other.as_rust_slice());
+        }
+        let end = data.len();
+        drop(data);
+        SliceObj::with_array(self.array.clone(), self.begin() as isize, end as isize).unwrap()
     }
 }
 
@@ -915,14 +1012,8 @@ impl Hash for StructObj {
 
 impl Display for StructObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_char('{')?;
-        for (i, fld) in self.borrow_fields().iter().enumerate() {
-            if i > 0 {
-                f.write_char(' ')?;
-            }
-            write!(f, "{}", fld)?
-        }
-        f.write_char('}')
+        let fields = self.borrow_fields().clone();
+        Printer::untyped(Verb::V).fields(f, &fields, None, 0)
     }
 }
 
@@ -1182,7 +1273,7 @@ impl PointerObj {
             ValueType::Array => PointerObj::new_array_member_internal(val, i, caller),
             ValueType::Slice => match val.is_nil() {
                 false => Ok(PointerObj::SliceMember(val, i)),
-                true => Err("access nil value".to_owned().into()),
+                true => Err(RuntimeError::index_out_of_range(i as usize, 0)),
             },
             _ => unreachable!(),
         }
@@ -1356,12 +1447,26 @@ impl Display for PointerObj {
 // ----------------------------------------------------------------------------
 // UnsafePtrObj
 
+/// UnsafePtr is how host values are embedded in the script, as `unsafe.Pointer`s
+/// or as opaque `interface{}`s. A host value is always equal to itself, i.e.
+/// copies of the same `Rc`.
 pub trait UnsafePtr {
     /// For downcasting
     fn as_any(&self) -> &dyn Any;
 
+    /// Implement this if different host values can be equal.
     fn eq(&self, _: &dyn UnsafePtr) -> bool {
-        panic!("implement your own eq for your type");
+        false
+    }
+
+    /// For using it as a map key, must agree with eq.
+    fn hash(&self) -> u64 {
+        self as *const Self as *const u8 as usize as u64
+    }
+
+    /// For printing it in the script, e.g. with fmt.Println.
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", self as *const Self as *const u8)
     }
 
     /// for gc
@@ -1405,6 +1510,12 @@ impl UnsafePtr for PointerHandle {
         }
     }
 
+    fn hash(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.ptr.hash(&mut state);
+        state.finish()
+    }
+
     /// for gc
     fn ref_sub_one(&self) {
         self.ptr.ref_sub_one()
@@ -1465,13 +1576,43 @@ impl Eq for UnsafePtrObj {}
 impl PartialEq for UnsafePtrObj {
     #[inline]
     fn eq(&self, other: &UnsafePtrObj) -> bool {
-        self.ptr.eq(other.ptr())
+        let identical = Rc::as_ptr(&self.ptr) as *const u8 == Rc::as_ptr(&other.ptr) as *const u8;
+        identical || self.ptr.eq(other.ptr())
+    }
+}
+
+impl Hash for UnsafePtrObj {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ptr.hash().hash(state)
+    }
+}
+
+impl PartialOrd for UnsafePtrObj {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UnsafePtrObj {
+    /// Equal host values are ordered as equal, the others by their hash and
+    /// then by address, for using them as keys of BTreeMaps.
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        let addr = |p: &UnsafePtrObj| Rc::as_ptr(&p.ptr) as *const u8 as usize;
+        self.ptr
+            .hash()
+            .cmp(&other.ptr.hash())
+            .then(addr(self).cmp(&addr(other)))
     }
 }
 
 impl Display for UnsafePtrObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:p}", &*self.ptr as *const dyn UnsafePtr)
+        self.ptr.display(f)
     }
 }
 // ----------------------------------------------------------------------------
@@ -1861,64 +2002,110 @@ impl ClosureObj {
 // ----------------------------------------------------------------------------
 // PackageObj
 
-/// PackageObj is part of the generated Bytecode, it stores imports, consts,
-/// vars, funcs declared in a package
+/// The parts of a package that don't change at runtime, shared by all the
+/// instances of a program.
 #[derive(Clone, Debug)]
-pub struct PackageObj {
+struct PackageInfo {
     name: String,
-    members: Vec<RefCell<GosValue>>, // imports, const, var, func are all stored here
     member_indices: Map<String, OpIndex>,
     init_funcs: Vec<GosValue>,
-    // maps func_member_index of the constructor to pkg_member_index
-    var_mapping: RefCell<Option<Map<OpIndex, OpIndex>>>,
+    // the types of the vars, by pkg_member_index
+    var_metas: Map<OpIndex, Meta>,
+}
+
+/// PackageObj is part of the generated Bytecode, it stores imports, consts,
+/// vars, funcs declared in a package.
+#[derive(Clone, Debug)]
+pub struct PackageObj {
+    info: Rc<PackageInfo>,
+    members: Vec<RefCell<GosValue>>, // imports, const, var, func are all stored here
+    inited: Cell<bool>,
 }
 
 impl PackageObj {
     pub fn new(name: String) -> PackageObj {
         PackageObj {
-            name,
+            info: Rc::new(PackageInfo {
+                name,
+                member_indices: Map::new(),
+                init_funcs: vec![],
+                var_metas: Map::new(),
+            }),
             members: vec![],
-            member_indices: Map::new(),
-            init_funcs: vec![],
-            var_mapping: RefCell::new(Some(Map::new())),
+            inited: Cell::new(false),
+        }
+    }
+
+    /// Returns a copy for a new run of the program, only the member values
+    /// are copied, the rest is shared with `self`.
+    pub fn instance(&self, gcc: &GcContainer) -> PackageObj {
+        PackageObj {
+            info: self.info.clone(),
+            members: self
+                .members
+                .iter()
+                .map(|x| RefCell::new(x.borrow().copy_semantic(gcc)))
+                .collect(),
+            inited: self.inited.clone(),
         }
     }
 
     pub fn name(&self) -> &str {
-        &self.name
+        &self.info.name
     }
 
     pub fn add_member(&mut self, name: String, val: GosValue) -> OpIndex {
         self.members.push(RefCell::new(val));
         let index = (self.members.len() - 1) as OpIndex;
-        self.member_indices.insert(name, index);
+        Rc::make_mut(&mut self.info)
+            .member_indices
+            .insert(name, index);
         index as OpIndex
     }
 
-    pub fn add_var_mapping(&mut self, name: String, fn_index: OpIndex) -> OpIndex {
-        let index = *self.member_index(&name).unwrap();
-        self.var_mapping
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .insert(fn_index, index);
+    pub fn add_var(&mut self, name: String, val: GosValue, meta: Meta) -> OpIndex {
+        let index = self.add_member(name, val);
+        Rc::make_mut(&mut self.info).var_metas.insert(index, meta);
         index
     }
 
+    /// Takes the names of the members out, but the ones of the types, which
+    /// are how types are named at runtime, see `Bytecode::strip_symbols`.
+    pub(crate) fn take_member_names(&mut self) -> Map<String, OpIndex> {
+        let members = &self.members;
+        let info = Rc::make_mut(&mut self.info);
+        let (keep, names) = std::mem::take(&mut info.member_indices)
+            .into_iter()
+            .partition(|(name, i)| {
+                name.is_empty() || members[*i as usize].borrow().typ() == ValueType::Metadata
+            });
+        info.member_indices = keep;
+        names
+    }
+
+    pub(crate) fn put_member_names(&mut self, names: Map<String, OpIndex>) {
+        Rc::make_mut(&mut self.info).member_indices.extend(names);
+    }
+
     pub fn add_init_func(&mut self, func: GosValue) {
-        self.init_funcs.push(func);
+        Rc::make_mut(&mut self.info).init_funcs.push(func);
     }
 
     pub fn member_indices(&self) -> &Map<String, OpIndex> {
-        &self.member_indices
+        &self.info.member_indices
     }
 
     pub fn member_index(&self, name: &str) -> Option<&OpIndex> {
-        self.member_indices.get(name)
+        self.info.member_indices.get(name)
+    }
+
+    /// The type of the member at `i` if it's a var.
+    pub fn var_meta(&self, i: OpIndex) -> Option<&Meta> {
+        self.info.var_metas.get(&i)
     }
 
     pub fn inited(&self) -> bool {
-        self.var_mapping.borrow().is_none()
+        self.inited.get()
     }
 
     #[inline]
@@ -1933,34 +2120,31 @@ impl PackageObj {
 
     #[inline]
     pub fn init_func(&self, i: OpIndex) -> Option<&GosValue> {
-        self.init_funcs.get(i as usize)
+        self.info.init_funcs.get(i as usize)
     }
 
+    /// Marks the vars as initialized, they are set by the constructor of the
+    /// package as it runs.
     #[inline]
-    pub fn init_vars(&self, vals: Vec<GosValue>) {
-        let mut borrow = self.var_mapping.borrow_mut();
-        let mapping = borrow.as_ref().unwrap();
-        for (i, v) in vals.into_iter().enumerate() {
-            let vi = mapping[&(i as OpIndex)];
-            *self.member_mut(vi) = v;
-        }
-        *borrow = None;
+    pub fn set_inited(&self) {
+        self.inited.set(true);
     }
 }
 
 #[cfg(feature = "serde_borsh")]
 impl BorshSerialize for PackageObj {
     fn serialize<W: BorshWrite>(&self, writer: &mut W) -> BorshResult<()> {
-        self.name.serialize(writer)?;
+        self.info.name.serialize(writer)?;
         let members: Vec<GosValue> = self
             .members
             .iter()
             .map(|x| x.clone().into_inner())
             .collect();
         members.serialize(writer)?;
-        self.member_indices.serialize(writer)?;
-        self.init_funcs.serialize(writer)?;
-        self.var_mapping.borrow().serialize(writer)
+        self.info.member_indices.serialize(writer)?;
+        self.info.init_funcs.serialize(writer)?;
+        self.inited().serialize(writer)?;
+        self.info.var_metas.serialize(writer)
     }
 }
 
@@ -1974,14 +2158,17 @@ impl BorshDeserialize for PackageObj {
             .collect();
         let member_indices = Map::<String, OpIndex>::deserialize_reader(reader)?;
         let init_funcs = Vec::<GosValue>::deserialize_reader(reader)?;
-        let var_mapping =
-            RefCell::new(Option::<Map<OpIndex, OpIndex>>::deserialize_reader(reader)?);
+        let inited = bool::deserialize_reader(reader)?;
+        let var_metas = Map::<OpIndex, Meta>::deserialize_reader(reader)?;
         Ok(PackageObj {
-            name,
+            inited: Cell::new(inited),
+            info: Rc::new(PackageInfo {
+                name,
+                member_indices,
+                init_funcs,
+                var_metas,
+            }),
             members,
-            member_indices,
-            init_funcs,
-            var_mapping,
         })
     }
 }
@@ -0,0 +1,151 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Reuse of the stacks and the frame lists of finished goroutines, so that
+//! `go f()` in a loop doesn't allocate them anew every time.
+//!
+//! The stacks are pooled by size class, i.e. by the capacity they grew to,
+//! from `DEFAULT_CAPACITY` doubling up to `PoolCaps::max_stack`, bigger ones
+//! are freed so that a single deep goroutine doesn't pin its memory for the
+//! rest of the run. A new goroutine takes the smallest pooled stack.
+
+use crate::stack::DEFAULT_CAPACITY;
+#[cfg(feature = "opcode_stats")]
+use crate::stats::OpcodeStats;
+use crate::value::GosValue;
+
+/// How much the pools of a run keep, see `Limits::pool`.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolCaps {
+    /// The most stacks kept in each size class, 0 disables the pooling.
+    pub stacks_per_class: usize,
+    /// The largest capacity, in values, of the stacks kept.
+    pub max_stack: usize,
+    /// The most frame lists kept.
+    pub frame_lists: usize,
+}
+
+impl Default for PoolCaps {
+    fn default() -> Self {
+        PoolCaps {
+            stacks_per_class: 64,
+            max_stack: 16 * DEFAULT_CAPACITY,
+            frame_lists: 256,
+        }
+    }
+}
+
+/// How the pools of the runs are used, see `OpcodeStats::pool`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolCounts {
+    /// Stacks taken from the pool.
+    pub stacks_reused: u64,
+    /// Stacks allocated because the pool had none.
+    pub stacks_allocated: u64,
+    /// Stacks of finished goroutines put in the pool.
+    pub stacks_returned: u64,
+    /// Stacks of finished goroutines freed, their class was full or they
+    /// were too big.
+    pub stacks_freed: u64,
+    /// Frame lists taken from the pool.
+    pub frames_reused: u64,
+    /// Frame lists allocated because the pool had none.
+    pub frames_allocated: u64,
+}
+
+/// The pools of a run, `F` is the call frame of the VM.
+pub(crate) struct FiberPool<F> {
+    // by size class, the smallest first
+    stacks: Vec<Vec<Vec<GosValue>>>,
+    frames: Vec<Vec<F>>,
+    caps: PoolCaps,
+    #[cfg(feature = "opcode_stats")]
+    stats: Option<OpcodeStats>,
+}
+
+impl<F> FiberPool<F> {
+    pub(crate) fn new(
+        caps: PoolCaps,
+        #[cfg(feature = "opcode_stats")] stats: Option<OpcodeStats>,
+    ) -> FiberPool<F> {
+        let classes = if caps.stacks_per_class == 0 {
+            0
+        } else {
+            size_class(caps.max_stack.max(DEFAULT_CAPACITY)) + 1
+        };
+        FiberPool {
+            stacks: (0..classes).map(|_| vec![]).collect(),
+            frames: vec![],
+            caps,
+            #[cfg(feature = "opcode_stats")]
+            stats,
+        }
+    }
+
+    /// Returns an empty vec for a new stack.
+    pub(crate) fn take_stack(&mut self) -> Vec<GosValue> {
+        match self.stacks.iter_mut().find_map(|x| x.pop()) {
+            Some(v) => {
+                self.count(|c| c.stacks_reused += 1);
+                v
+            }
+            None => {
+                self.count(|c| c.stacks_allocated += 1);
+                Vec::with_capacity(DEFAULT_CAPACITY)
+            }
+        }
+    }
+
+    /// Takes back the vec of a finished stack, emptied by the caller so that
+    /// no value is dropped while the pool is borrowed.
+    pub(crate) fn put_stack(&mut self, vec: Vec<GosValue>) {
+        debug_assert!(vec.is_empty());
+        let cap = vec.capacity();
+        let class = match self.stacks.get_mut(size_class(cap)) {
+            Some(c) if cap <= self.caps.max_stack && c.len() < self.caps.stacks_per_class => c,
+            _ => {
+                self.count(|c| c.stacks_freed += 1);
+                return;
+            }
+        };
+        class.push(vec);
+        self.count(|c| c.stacks_returned += 1);
+    }
+
+    /// Returns an empty vec for the frames of a new fiber.
+    pub(crate) fn take_frames(&mut self) -> Vec<F> {
+        match self.frames.pop() {
+            Some(v) => {
+                self.count(|c| c.frames_reused += 1);
+                v
+            }
+            None => {
+                self.count(|c| c.frames_allocated += 1);
+                Vec::with_capacity(4)
+            }
+        }
+    }
+
+    /// Takes back the emptied frame list of a finished fiber.
+    pub(crate) fn put_frames(&mut self, frames: Vec<F>) {
+        debug_assert!(frames.is_empty());
+        if self.frames.len() < self.caps.frame_lists {
+            self.frames.push(frames);
+        }
+    }
+
+    #[inline]
+    fn count(&self, _f: impl FnOnce(&mut PoolCounts)) {
+        #[cfg(feature = "opcode_stats")]
+        if let Some(s) = &self.stats {
+            _f(&mut s.counts().borrow_mut().pool);
+        }
+    }
+}
+
+/// 0 for capacities up to 2 * DEFAULT_CAPACITY - 1, 1 up to
+/// 4 * DEFAULT_CAPACITY - 1, etc.
+fn size_class(cap: usize) -> usize {
+    (cap / DEFAULT_CAPACITY).max(1).ilog2() as usize
+}
@@ -2,9 +2,10 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::gc::GcContainer;
 use crate::value::*;
 
-const DEFAULT_CAPACITY: usize = 256;
+pub(crate) const DEFAULT_CAPACITY: usize = 256;
 
 pub struct Stack {
     vec: Vec<GosValue>,
@@ -55,10 +56,12 @@ impl Stack {
         lhs: &ValueData,
         t: ValueType,
         op: Opcode,
+        rhs_t: ValueType,
         rhs: OpIndex,
         sb: OpIndex,
         consts: &[GosValue],
-    ) -> GosValue {
+        gcc: &GcContainer,
+    ) -> RuntimeResult<GosValue> {
         let d = match op {
             Opcode::INC => lhs.inc(t),
             Opcode::DEC => lhs.dec(t),
@@ -71,14 +74,25 @@ impl Stack {
             Opcode::OR => lhs.binary_op_or(self.read(rhs, sb, consts).data(), t),
             Opcode::XOR => lhs.binary_op_xor(self.read(rhs, sb, consts).data(), t),
             Opcode::AND_NOT => lhs.binary_op_and_not(self.read(rhs, sb, consts).data(), t),
-            Opcode::SHL => lhs.binary_op_shl(self.read(rhs, sb, consts).data().as_uint32(), t),
-            Opcode::SHR => lhs.binary_op_shr(self.read(rhs, sb, consts).data().as_uint32(), t),
+            Opcode::SHL => {
+                let count = self.read(rhs, sb, consts).data().as_shift_count(rhs_t)?;
+                lhs.binary_op_shl(&count, t)
+            }
+            Opcode::SHR => {
+                let count = self.read(rhs, sb, consts).data().as_shift_count(rhs_t)?;
+                lhs.binary_op_shr(&count, t)
+            }
             _ => {
                 dbg!(op);
                 unreachable!();
             }
         };
-        GosValue::new(t, d)
+        let val = GosValue::new(t, d);
+        // a string concatenation, checked against the limit at the next time slice
+        if t == ValueType::String {
+            gcc.add_allocated(val.as_string().len());
+        }
+        Ok(val)
     }
 
     #[inline]
@@ -96,6 +110,14 @@ impl Stack {
         self.vec[begin..new_len].swap_with_slice(&mut vec);
     }
 
+    /// Moves the values in [begin, end) up by one slot.
+    #[inline]
+    pub fn shift_up(&mut self, begin: OpIndex, end: OpIndex) {
+        let (b, e) = (begin as usize, end as usize);
+        self.set_min_size(e + 1);
+        self.vec[b..=e].rotate_right(1);
+    }
+
     #[inline]
     pub fn move_vec(&mut self, begin: OpIndex, end: OpIndex) -> Vec<GosValue> {
         let b = begin as usize;
@@ -105,6 +127,24 @@ impl Stack {
         defaults
     }
 
+    /// Moves the values in [begin, end) to the end of `dst`.
+    #[cfg(feature = "async")]
+    #[inline]
+    pub(crate) fn move_to(&mut self, begin: OpIndex, end: OpIndex, dst: &mut Vec<GosValue>) {
+        let (b, e) = (begin as usize, end as usize);
+        dst.extend(
+            self.vec[b..e]
+                .iter_mut()
+                .map(|x| std::mem::replace(x, GosValue::new_nil(ValueType::Void))),
+        );
+    }
+
+    /// Takes the values out, leaving the stack empty.
+    #[inline]
+    pub(crate) fn take_vec(&mut self) -> Vec<GosValue> {
+        std::mem::take(&mut self.vec)
+    }
+
     #[inline]
     pub fn get_bool(&mut self, index: OpIndex) -> bool {
         *self.get_data(index).as_bool()
@@ -144,18 +184,25 @@ impl std::fmt::Debug for Stack {
 
 /// store iterators for Opcode::RANGE
 pub struct RangeStack {
-    maps: Vec<GosMapIter<'static>>,
-    slices: Vec<SliceEnumIter<'static, AnyElem>>,
-    strings: Vec<StringEnumIter<'static>>,
+    // every iter is keyed by the height of the frame it's in, and the number
+    // of range loops around its loop in the function, the value ranged over
+    // is kept after it, as it may be a temporary
+    iters: Vec<((usize, OpIndex), RangeIter, GosValue)>,
+}
+
+enum RangeIter {
+    // None for a nil map, which is ranged over zero times
+    Map(Option<GosMapIter<'static>>),
+    // an array is ranged over by a copy, the flag tells which
+    Slice(SliceEnumIter<'static, AnyElem>, bool),
+    String(StringIter<'static>),
+    // the next value and the count of an integer of the type
+    Int(u64, u64, ValueType),
 }
 
 impl RangeStack {
     pub fn new() -> RangeStack {
-        RangeStack {
-            maps: vec![],
-            slices: vec![],
-            strings: vec![],
-        }
+        RangeStack { iters: vec![] }
     }
 
     /// range_init creates iters and transmute them to 'static, then save them on stacks.
@@ -167,75 +214,125 @@ impl RangeStack {
     /// the borrow checker. Which is not safe to Rust, but it's exactly what Go does.
     pub(crate) fn range_init(
         &mut self,
+        key: (usize, OpIndex),
         target: &GosValue,
         typ: ValueType,
         arr_caller: &Box<dyn Dispatcher>,
+        gcc: &GcContainer,
     ) -> RuntimeResult<()> {
-        match typ {
-            ValueType::Map => {
-                let map = target.as_non_nil_map()?.0.borrow_data();
-                let iter = unsafe { std::mem::transmute(map.iter()) };
-                self.maps.push(iter);
+        // the iters of this loop or the ones in it that were left by break,
+        // return or a panic, and the ones of the frames that have returned
+        self.pop_above(key, true);
+        let target = match typ {
+            ValueType::Array => target.copy_semantic(gcc),
+            _ => target.clone(),
+        };
+        let iter = match typ {
+            ValueType::Map => RangeIter::Map(target.as_map().map(|m| {
+                let map = m.0.borrow_data();
+                unsafe { std::mem::transmute(map.iter()) }
+            })),
+            ValueType::Array => RangeIter::Slice(arr_caller.array_slice_iter(&target)?, true),
+            ValueType::Slice => {
+                let iter = if target.is_nil() {
+                    let empty: &'static [AnyElem] = &[];
+                    empty.iter().enumerate()
+                } else {
+                    arr_caller.array_slice_iter(&target)?
+                };
+                RangeIter::Slice(iter, false)
             }
-            ValueType::Array | ValueType::Slice => {
-                let iter = arr_caller.array_slice_iter(&target)?;
-                self.slices.push(iter);
+            ValueType::String => RangeIter::String(unsafe {
+                std::mem::transmute::<StringIter, StringIter<'static>>(StringIter::new(
+                    &target.as_string().as_bytes(),
+                ))
+            }),
+            ValueType::Uint
+            | ValueType::UintPtr
+            | ValueType::Uint8
+            | ValueType::Uint16
+            | ValueType::Uint32
+            | ValueType::Uint64 => {
+                let n = *target.cast_copyable(typ, ValueType::Uint64).as_uint64();
+                RangeIter::Int(0, n, typ)
             }
-            ValueType::String => {
-                let iter =
-                    unsafe { std::mem::transmute(target.as_string().as_str().chars().enumerate()) };
-                self.strings.push(iter);
+            ValueType::Int
+            | ValueType::Int8
+            | ValueType::Int16
+            | ValueType::Int32
+            | ValueType::Int64 => {
+                let n = *target.cast_copyable(typ, ValueType::Int64).as_int64();
+                RangeIter::Int(0, n.max(0) as u64, typ)
             }
             _ => unreachable!(),
-        }
+        };
+        self.iters.push((key, iter, target));
         Ok(())
     }
 
     pub(crate) fn range_body(
         &mut self,
-        typ: ValueType,
+        key: (usize, OpIndex),
         arr_caller: &Box<dyn Dispatcher>,
         stack: &mut Stack,
         index_key: OpIndex,
         index_val: OpIndex,
+        gcc: &GcContainer,
     ) -> bool {
-        match typ {
-            ValueType::Map => match self.maps.last_mut().unwrap().next() {
+        // the loops in this one may have been left by break
+        self.pop_above(key, false);
+        let done = match &mut self.iters.last_mut().unwrap().1 {
+            RangeIter::Map(iter) => match iter.as_mut().and_then(|x| x.next()) {
                 Some((k, v)) => {
-                    stack.set(index_key, k.clone());
-                    stack.set(index_val, v.clone());
+                    stack.set(index_key, k.copy_semantic(gcc));
+                    stack.set(index_val, v.copy_semantic(gcc));
                     false
                 }
-                None => {
-                    self.maps.pop();
-                    true
-                }
+                None => true,
             },
-            ValueType::Array | ValueType::Slice => {
-                match arr_caller.array_slice_next(self.slices.last_mut().unwrap()) {
-                    Some((k, v)) => {
-                        stack.set(index_key, (k as isize).into());
-                        stack.set(index_val, v);
-                        false
-                    }
-                    None => {
-                        self.slices.pop();
-                        true
+            RangeIter::Slice(iter, copied) => match arr_caller.array_slice_next(iter) {
+                Some((k, v)) => {
+                    stack.set(index_key, (k as isize).into());
+                    // the elements of an array copy are not read again
+                    match copied {
+                        true => stack.set(index_val, v),
+                        false => stack.set(index_val, v.copy_semantic(gcc)),
                     }
+                    false
                 }
-            }
-            ValueType::String => match self.strings.last_mut().unwrap().next() {
+                None => true,
+            },
+            RangeIter::String(iter) => match iter.next() {
                 Some((k, v)) => {
                     stack.set(index_key, (k as isize).into());
                     stack.set(index_val, (v as isize).into());
                     false
                 }
-                None => {
-                    self.strings.pop();
-                    true
+                None => true,
+            },
+            RangeIter::Int(next, n, typ) => match *next < *n {
+                true => {
+                    let k = GosValue::from(*next).cast_copyable(ValueType::Uint64, *typ);
+                    stack.set(index_key, k);
+                    *next += 1;
+                    false
                 }
+                false => true,
             },
-            _ => unreachable!(),
+        };
+        if done {
+            self.iters.pop();
+        }
+        done
+    }
+
+    fn pop_above(&mut self, key: (usize, OpIndex), inclusive: bool) {
+        while let Some((k, _, _)) = self.iters.last() {
+            if *k > key || (inclusive && *k == key) {
+                self.iters.pop();
+            } else {
+                break;
+            }
         }
     }
 }
@@ -0,0 +1,152 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Counts of the instructions a run executes, by opcode and by pairs of
+//! consecutive opcodes, to find out which patterns are worth optimizing in
+//! the emitter and the peephole optimizer, and of how the goroutine pools
+//! are used.
+//!
+//! Only built with the `opcode_stats` feature, and only collected for the
+//! runs given an `OpcodeStats` in their `Limits`.
+
+use crate::instruction::Opcode;
+use crate::pool::PoolCounts;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+const OPCODE_COUNT: usize = Opcode::FFI as usize + 1;
+
+/// How many of the most executed pairs the report shows.
+const REPORT_PAIRS: usize = 20;
+
+pub(crate) struct Counts {
+    ops: Vec<u64>,
+    // indexed by prev * OPCODE_COUNT + op
+    pairs: Vec<u64>,
+    prev: Opcode,
+    pub(crate) pool: PoolCounts,
+}
+
+impl Counts {
+    fn new() -> Counts {
+        Counts {
+            ops: vec![0; OPCODE_COUNT],
+            pairs: vec![0; OPCODE_COUNT * OPCODE_COUNT],
+            prev: Opcode::VOID,
+            pool: PoolCounts::default(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn add(&mut self, op: Opcode) {
+        self.ops[op as usize] += 1;
+        if self.prev != Opcode::VOID {
+            self.pairs[self.prev as usize * OPCODE_COUNT + op as usize] += 1;
+        }
+        self.prev = op;
+    }
+
+    /// Ends a sequence, so that it doesn't pair up with what the next
+    /// goroutine runs.
+    pub(crate) fn cut(&mut self) {
+        self.prev = Opcode::VOID;
+    }
+}
+
+/// OpcodeStats is shared by the runs it's given to, their counts add up.
+#[derive(Clone)]
+pub struct OpcodeStats {
+    counts: Rc<RefCell<Counts>>,
+}
+
+impl OpcodeStats {
+    pub fn new() -> OpcodeStats {
+        OpcodeStats {
+            counts: Rc::new(RefCell::new(Counts::new())),
+        }
+    }
+
+    pub(crate) fn counts(&self) -> &RefCell<Counts> {
+        &self.counts
+    }
+
+    /// The number of executed instructions.
+    pub fn total(&self) -> u64 {
+        self.counts.borrow().ops.iter().sum()
+    }
+
+    /// The executed opcodes and their counts, the most executed first.
+    pub fn opcodes(&self) -> Vec<(Opcode, u64)> {
+        let counts = self.counts.borrow();
+        sorted(counts.ops.iter().enumerate().map(|(i, n)| (opcode(i), *n)))
+    }
+
+    /// The opcodes executed one right after the other and their counts, the
+    /// most executed first.
+    pub fn pairs(&self) -> Vec<((Opcode, Opcode), u64)> {
+        let counts = self.counts.borrow();
+        sorted(
+            counts
+                .pairs
+                .iter()
+                .enumerate()
+                .map(|(i, n)| ((opcode(i / OPCODE_COUNT), opcode(i % OPCODE_COUNT)), *n)),
+        )
+    }
+
+    /// How the stacks and the frame lists of the goroutines were reused.
+    pub fn pool(&self) -> PoolCounts {
+        self.counts.borrow().pool
+    }
+
+    pub fn reset(&self) {
+        *self.counts.borrow_mut() = Counts::new();
+    }
+}
+
+impl Default for OpcodeStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for OpcodeStats {
+    /// A report of all the executed opcodes, the most executed pairs and the
+    /// use of the goroutine pools.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        let percent = |n: u64| n as f64 * 100.0 / total.max(1) as f64;
+        writeln!(f, "{} instructions executed", total)?;
+        for (op, n) in self.opcodes() {
+            writeln!(f, "{:>16} {:>12} {:>6.2}%", op, n, percent(n))?;
+        }
+        writeln!(f, "most executed pairs:")?;
+        for ((a, b), n) in self.pairs().into_iter().take(REPORT_PAIRS) {
+            writeln!(f, "{:>16} {:<16} {:>12} {:>6.2}%", a, b, n, percent(n))?;
+        }
+        let c = self.pool();
+        writeln!(
+            f,
+            "goroutine stacks: {} reused, {} allocated, {} returned, {} freed",
+            c.stacks_reused, c.stacks_allocated, c.stacks_returned, c.stacks_freed
+        )?;
+        writeln!(
+            f,
+            "goroutine frame lists: {} reused, {} allocated",
+            c.frames_reused, c.frames_allocated
+        )
+    }
+}
+
+fn opcode(i: usize) -> Opcode {
+    debug_assert!(i < OPCODE_COUNT);
+    unsafe { std::mem::transmute(i as u8) }
+}
+
+fn sorted<T>(iter: impl Iterator<Item = (T, u64)>) -> Vec<(T, u64)> {
+    let mut v: Vec<(T, u64)> = iter.filter(|(_, n)| *n > 0).collect();
+    v.sort_by_key(|x| std::cmp::Reverse(x.1));
+    v
+}
@@ -0,0 +1,245 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Stripping what shipped bytecode doesn't need: the code a whole program
+//! can't reach, see `Bytecode::strip_unused`, and the names and positions
+//! that tell about the source, see `Bytecode::strip_symbols`.
+
+use crate::value::*;
+#[cfg(feature = "serde_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
+use go_parser::{FileSet, Map, PiggyVecKey};
+
+/// What `Bytecode::strip_unused` dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stripped {
+    pub functions: usize,
+    pub consts: usize,
+}
+
+/// The names and the positions `Bytecode::strip_symbols` takes out of the
+/// bytecode, to be kept apart from it and put back with `attach_debug_info`,
+/// e.g. to see where a shipped program panicked with `CallStackDisplay`.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Debug, Default)]
+pub struct DebugInfo {
+    pub file_set: Option<FileSet>,
+    pub source_map: Option<SourceMap>,
+    /// The positions of the instructions, by function.
+    pub positions: Vec<Vec<Option<u32>>>,
+    /// The names of the package members that are not types, by package.
+    pub member_names: Vec<Map<String, OpIndex>>,
+}
+
+impl Bytecode {
+    /// Drops the code of the functions that can't be reached from the entry
+    /// function, or from the exported functions of the main package, and the
+    /// constants only they use, to shrink the serialized bytecode. It's for
+    /// bytecode that runs as a whole program, not for `eval`.
+    ///
+    /// The keys and indices stay valid, a stripped function is left with no
+    /// code and a stripped constant is replaced with `false`. Every method of
+    /// the named types of the packages reached is kept, it may be called
+    /// through an interface only known at runtime, and so is the metadata,
+    /// the interfaces are linked to the methods by name.
+    pub fn strip_unused(&mut self) -> Stripped {
+        let (funcs, consts) = Reach::new(self).run();
+        let mut stripped = Stripped::default();
+        for (i, reached) in funcs.into_iter().enumerate() {
+            let func = &mut self.objects.functions[FunctionKey::from(i)];
+            if reached || func.code.is_empty() {
+                continue;
+            }
+            func.code = vec![];
+            func.pos = vec![];
+            func.up_ptrs = vec![];
+            func.local_zeros = vec![];
+            func.max_write_index = 0;
+            if let Some(map) = &mut self.source_map {
+                map.clear_function(FunctionKey::from(i));
+            }
+            stripped.functions += 1;
+        }
+        for (i, reached) in consts.into_iter().enumerate() {
+            if !reached && self.consts[i].typ() != ValueType::Bool {
+                self.consts[i] = false.into();
+                stripped.consts += 1;
+            }
+        }
+        stripped
+    }
+
+    /// Takes the source positions, the file names and the names of the
+    /// package members out of the bytecode, so that what's shipped doesn't
+    /// tell how the source is laid out. Local variables are never named in
+    /// bytecode. The names of the types, of the struct fields and of the
+    /// methods are kept, the program needs them to print values, to encode
+    /// them and to link interfaces, and so is the package constructor.
+    ///
+    /// The vars stripped of their names can't be found by `Globals` and the
+    /// exported functions can't be kept by `strip_unused`, strip them first.
+    pub fn strip_symbols(&mut self) -> DebugInfo {
+        let funcs = &mut self.objects.functions;
+        let positions = (0..funcs.vec().len())
+            .map(|i| std::mem::take(&mut funcs[FunctionKey::from(i)].pos))
+            .collect();
+        let pkgs = &mut self.objects.packages;
+        let member_names = (0..pkgs.vec().len())
+            .map(|i| pkgs[PackageKey::from(i)].take_member_names())
+            .collect();
+        DebugInfo {
+            file_set: self.file_set.take(),
+            source_map: self.source_map.take(),
+            positions,
+            member_names,
+        }
+    }
+
+    /// Puts back what `strip_symbols` took out, fails if it was taken out of
+    /// other bytecode.
+    pub fn attach_debug_info(&mut self, info: DebugInfo) -> std::result::Result<(), String> {
+        let funcs = &mut self.objects.functions;
+        let pkgs = &mut self.objects.packages;
+        if info.positions.len() != funcs.vec().len()
+            || info.member_names.len() != pkgs.vec().len()
+            || info.positions.iter().enumerate().any(|(i, pos)| {
+                !pos.is_empty() && pos.len() != funcs[FunctionKey::from(i)].code.len()
+            })
+        {
+            return Err("the debug info is not of this bytecode".to_owned());
+        }
+        for (i, pos) in info.positions.into_iter().enumerate() {
+            funcs[FunctionKey::from(i)].pos = pos;
+        }
+        for (i, names) in info.member_names.into_iter().enumerate() {
+            pkgs[PackageKey::from(i)].put_member_names(names);
+        }
+        self.file_set = info.file_set;
+        self.source_map = info.source_map;
+        Ok(())
+    }
+}
+
+// The functions and constants reachable from the roots, the packages are
+// reached along with their functions and constants.
+struct Reach<'a> {
+    code: &'a Bytecode,
+    funcs: Vec<bool>,
+    consts: Vec<bool>,
+    pkgs: Vec<bool>,
+    queue: Vec<FunctionKey>,
+}
+
+impl<'a> Reach<'a> {
+    fn new(code: &'a Bytecode) -> Reach<'a> {
+        Reach {
+            code,
+            funcs: vec![false; code.objects.functions.vec().len()],
+            consts: vec![false; code.consts.len()],
+            pkgs: vec![false; code.objects.packages.vec().len()],
+            queue: vec![],
+        }
+    }
+
+    fn run(mut self) -> (Vec<bool>, Vec<bool>) {
+        self.func(self.code.entry);
+        let code = self.code;
+        let main = &code.objects.packages[code.main_pkg];
+        for (name, i) in main.member_indices().iter() {
+            if name.starts_with(|c: char| c.is_uppercase()) {
+                self.value(&main.member(*i));
+            }
+        }
+        while let Some(f) = self.queue.pop() {
+            let func = &code.objects.functions[f];
+            self.package(func.package);
+            for inst in func.code.iter() {
+                for i in [inst.d, inst.s0, inst.s1] {
+                    self.operand(i);
+                }
+                if matches!(inst.op0, Opcode::LOAD_PKG | Opcode::REF_PKG_MEMBER) {
+                    self.member(inst.s0, inst.s1);
+                }
+            }
+        }
+        (self.funcs, self.consts)
+    }
+
+    fn func(&mut self, f: FunctionKey) {
+        let reached = &mut self.funcs[f.as_usize()];
+        if !*reached {
+            *reached = true;
+            self.queue.push(f);
+        }
+    }
+
+    fn value(&mut self, val: &GosValue) {
+        match val.typ() {
+            ValueType::Function => self.func(*val.as_function()),
+            ValueType::Closure => {
+                if let Some((ClosureObj::Gos(cls), _)) = val.as_closure() {
+                    self.func(cls.func);
+                }
+            }
+            ValueType::Package => self.package(*val.as_package()),
+            _ => {}
+        }
+    }
+
+    // an operand that's negative may be a constant, or not, e.g. the offset
+    // of a jump, which is harmless as far as what's kept goes
+    fn operand(&mut self, i: OpIndex) {
+        if i >= 0 {
+            return;
+        }
+        let index = (-i - 1) as usize;
+        if index < self.consts.len() && !self.consts[index] {
+            self.consts[index] = true;
+            let code = self.code;
+            self.value(&code.consts[index]);
+        }
+    }
+
+    fn member(&mut self, pkg: OpIndex, index: OpIndex) {
+        let code = self.code;
+        let pkg = match pkg < 0 {
+            true => &code.consts[(-pkg - 1) as usize],
+            false => return,
+        };
+        if pkg.typ() == ValueType::Package {
+            let pkg = &code.objects.packages[*pkg.as_package()];
+            self.value(&pkg.member(index));
+        }
+    }
+
+    // the constructor, the init functions and the methods of a package, the
+    // entry function is in none
+    fn package(&mut self, key: PackageKey) {
+        match self.pkgs.get_mut(key.as_usize()) {
+            Some(reached) if !*reached => *reached = true,
+            _ => return,
+        }
+        let code = self.code;
+        let objs = &code.objects;
+        let pkg = &objs.packages[key];
+        if let Some(ctor) = pkg.member_indices().get("") {
+            self.value(&pkg.member(*ctor));
+        }
+        let mut i = 0;
+        while let Some(f) = pkg.init_func(i) {
+            self.value(f);
+            i += 1;
+        }
+        for meta in objs.metas.vec().iter() {
+            if let MetadataType::Named(methods, _) = meta {
+                for m in methods.members.iter() {
+                    match m.borrow().func {
+                        Some(f) if objs.functions[f].package == key => self.func(f),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
@@ -9,6 +9,7 @@ pub use crate::bytecode::*;
 use crate::channel::Channel;
 pub(crate) use crate::dispatcher::*;
 use crate::gc::GcContainer;
+use crate::value_fmt::{ValueFmt, Verb};
 pub use crate::instruction::*;
 pub use crate::metadata::*;
 pub use crate::objects::*;
@@ -31,6 +32,7 @@ use std::num::Wrapping;
 use std::ptr;
 use std::rc::Rc;
 use std::result;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
 pub type F32 = ordered_float::OrderedFloat<f32>;
 pub type F64 = ordered_float::OrderedFloat<f64>;
@@ -38,6 +40,9 @@ pub type IRC = i32;
 pub type RCount = Cell<IRC>;
 pub type RCQueue = VecDeque<IRC>;
 
+// counts the NaNs given out as map keys, see `GosValue::with_unique_nan`
+static NAN_PAYLOAD: AtomicU64 = AtomicU64::new(1);
+
 #[inline]
 pub(crate) fn rcount_mark_and_queue(rc: &RCount, queue: &mut RCQueue) {
     let i = rc.get();
@@ -57,7 +62,9 @@ fn ref_ptr_eq<T>(x: Option<&T>, y: Option<&T>) -> bool {
 
 macro_rules! nil_err_str {
     () => {
-        "access nil value".to_owned().into()
+        "runtime error: invalid memory address or nil pointer dereference"
+            .to_owned()
+            .into()
     };
 }
 
@@ -85,12 +92,61 @@ macro_rules! union_shift {
     };
 }
 
+// a signed value shifted right by its width or more has only sign bits left
+macro_rules! union_shr_signed {
+    ($a:ident, $b:ident, $name:tt, $t:ty) => {
+        ValueData {
+            $name: $a.$name >> (*$b).min(<$t>::BITS - 1),
+        }
+    };
+}
+
 macro_rules! union_cmp {
     ($a:ident, $b:ident, $name:tt, $op:tt) => {
         $a.$name $op $b.$name
     };
 }
 
+// F32 and F64 order NaN as a number, Go compares floats with IEEE semantics.
+macro_rules! union_cmp_float {
+    ($a:ident, $b:ident, $name:tt, $op:tt) => {
+        $a.$name.0 $op $b.$name.0
+    };
+}
+
+// The total equality of Eq, Ord and Hash, as F32 and F64 but NaNs are only
+// equal with the same bits, which keeps the NaN map keys apart, see
+// `GosValue::with_unique_nan`.
+macro_rules! float_key_eq {
+    ($a:expr, $b:expr) => {
+        if $a.is_nan() && $b.is_nan() {
+            $a.to_bits() == $b.to_bits()
+        } else {
+            $a == $b
+        }
+    };
+}
+
+macro_rules! float_key_cmp {
+    ($a:expr, $b:expr) => {
+        if $a.is_nan() && $b.is_nan() {
+            $a.to_bits().cmp(&$b.to_bits())
+        } else {
+            $a.cmp($b)
+        }
+    };
+}
+
+macro_rules! float_key_hash {
+    ($a:expr, $state:ident) => {
+        if $a.is_nan() {
+            $a.to_bits().hash($state)
+        } else {
+            $a.hash($state)
+        }
+    };
+}
+
 macro_rules! binary_op_int_float_str {
     ($t:ident, $a:ident, $b:ident, $op:tt) => {
         match $t {
@@ -147,8 +203,8 @@ macro_rules! cmp_bool_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            ValueType::Float32 => union_cmp_float!($a, $b, float32, $op),
+            ValueType::Float64 => union_cmp_float!($a, $b, float64, $op),
             _ => unreachable!(),
         }
     };
@@ -168,8 +224,8 @@ macro_rules! cmp_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            ValueType::Float32 => union_cmp_float!($a, $b, float32, $op),
+            ValueType::Float64 => union_cmp_float!($a, $b, float64, $op),
             _ => unreachable!(),
         }
     };
@@ -194,6 +250,19 @@ macro_rules! shift_int {
     };
 }
 
+macro_rules! shr_int {
+    ($t:ident, $a:ident, $b:ident) => {
+        match $t {
+            ValueType::Int => union_shr_signed!($a, $b, int, isize),
+            ValueType::Int8 => union_shr_signed!($a, $b, int8, i8),
+            ValueType::Int16 => union_shr_signed!($a, $b, int16, i16),
+            ValueType::Int32 => union_shr_signed!($a, $b, int32, i32),
+            ValueType::Int64 => union_shr_signed!($a, $b, int64, i64),
+            _ => shift_int!($t, $a, $b, checked_shr),
+        }
+    };
+}
+
 macro_rules! convert_to_int {
     ($val:expr, $vt:expr, $d_type:tt, $typ:tt) => {{
         unsafe {
@@ -251,6 +320,18 @@ impl RuntimeError {
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    /// The Go runtime error for indexing out of `[0, len)`, negative indices
+    /// come in wrapped around by `as_index`.
+    pub(crate) fn index_out_of_range(index: usize, len: usize) -> RuntimeError {
+        match index as isize {
+            i if i < 0 => RuntimeError(format!("runtime error: index out of range [{}]", i)),
+            i => RuntimeError(format!(
+                "runtime error: index out of range [{}] with length {}",
+                i, len
+            )),
+        }
+    }
 }
 
 impl std::fmt::Display for RuntimeError {
@@ -820,7 +901,29 @@ impl ValueData {
 
     #[inline]
     pub(crate) fn binary_op_shr(&self, b: &u32, t: ValueType) -> ValueData {
-        unsafe { shift_int!(t, self, b, checked_shr) }
+        unsafe { shr_int!(t, self, b) }
+    }
+
+    /// The count of a shift, of the type `t`, the counts too big to matter
+    /// are capped. A negative count is a runtime error.
+    #[inline]
+    pub(crate) fn as_shift_count(&self, t: ValueType) -> RuntimeResult<u32> {
+        let count = match t {
+            ValueType::Uint
+            | ValueType::UintPtr
+            | ValueType::Uint8
+            | ValueType::Uint16
+            | ValueType::Uint32
+            | ValueType::Uint64 => *self.cast_copyable(t, ValueType::Uint64).as_uint64(),
+            _ => match *self.cast_copyable(t, ValueType::Int64).as_int64() {
+                c if c < 0 => {
+                    let msg = "runtime error: negative shift amount".to_owned();
+                    return Err(RuntimeError::new(msg));
+                }
+                c => c as u64,
+            },
+        };
+        Ok(count.min(u32::MAX as u64) as u32)
     }
 
     #[inline]
@@ -1017,6 +1120,7 @@ impl ValueData {
     where
         T: Element,
     {
+        gcc.add_allocated(arr.capacity() * std::mem::size_of::<T>());
         let rc = Rc::new((arr, Cell::new(0)));
         if T::need_gc() {
             gcc.add_array(&ValueData::from_array(rc.clone()).into_array::<GosElem>());
@@ -1036,6 +1140,7 @@ impl ValueData {
 
     #[inline]
     fn new_struct(obj: StructObj, gcc: &GcContainer) -> ValueData {
+        gcc.add_allocated(obj.borrow_fields().len() * std::mem::size_of::<GosValue>());
         let s = Rc::new((obj, Cell::new(0)));
         gcc.add_struct(&s);
         ValueData::from_struct(s)
@@ -1053,6 +1158,7 @@ impl ValueData {
 
     #[inline]
     fn new_closure(obj: ClosureObj, gcc: &GcContainer) -> ValueData {
+        gcc.add_allocated(std::mem::size_of::<ClosureObj>());
         let cls = Rc::new((obj, Cell::new(0)));
         gcc.add_closure(&cls);
         ValueData::from_closure(Some(cls))
@@ -1076,6 +1182,7 @@ impl ValueData {
 
     #[inline]
     fn new_map(obj: MapObj, gcc: &GcContainer) -> ValueData {
+        gcc.add_allocated(std::mem::size_of::<MapObj>() + obj.len() * MapObj::ENTRY_SIZE);
         let m = Rc::new((obj, Cell::new(0)));
         gcc.add_map(&m);
         ValueData::from_map(Some(m))
@@ -1218,20 +1325,25 @@ impl ValueData {
     #[inline]
     fn copy_semantic(&self, t: ValueType, t_elem: ValueType, gcc: &GcContainer) -> ValueData {
         match t {
-            _ if t != ValueType::Array
-                && t != ValueType::Struct
-                && t != ValueType::Slice
-                && t != ValueType::Map =>
-            {
+            // maps are references, they are shared like pointers
+            _ if t != ValueType::Array && t != ValueType::Struct && t != ValueType::Slice => {
                 self.clone(t)
             }
-            ValueType::Array => ArrCaller::get_slow(t_elem).array_copy_semantic(self, gcc),
-            ValueType::Struct => ValueData::new_struct(StructObj::clone(&self.as_struct().0), gcc),
-            ValueType::Slice => ArrCaller::get_slow(t_elem).slice_copy_semantic(self),
-            ValueType::Map => match self.as_map() {
-                Some(m) => ValueData::new_map(m.0.clone(), gcc),
-                None => ValueData::new_nil(t),
+            // arrays and structs in them are values too, copied along
+            ValueType::Array => match t_elem {
+                ValueType::Array | ValueType::Struct => {
+                    let data = self.as_array::<GosElem>().0.borrow_data();
+                    let copied = data.iter().map(|x| x.borrow().copy_semantic(gcc)).collect();
+                    ValueData::new_array(ArrayObj::<GosElem>::with_data(copied), gcc)
+                }
+                _ => ArrCaller::get_slow(t_elem).array_copy_semantic(self, gcc),
             },
+            ValueType::Struct => {
+                let fields = self.as_struct().0.borrow_fields();
+                let copied = fields.iter().map(|x| x.copy_semantic(gcc)).collect();
+                ValueData::new_struct(StructObj::new(copied), gcc)
+            }
+            ValueType::Slice => ArrCaller::get_slow(t_elem).slice_copy_semantic(self),
             _ => unreachable!(),
         }
     }
@@ -1428,6 +1540,11 @@ impl GosValue {
         GosValue::new_string(StringObj::with_str(s))
     }
 
+    #[inline]
+    pub(crate) fn with_bytes(b: &[u8]) -> GosValue {
+        GosValue::new_string(StringObj::with_bytes(b))
+    }
+
     #[inline]
     pub(crate) fn new_array<T>(obj: ArrayObj<T>, t_elem: ValueType, gcc: &GcContainer) -> GosValue
     where
@@ -1527,6 +1644,8 @@ impl GosValue {
         caller.array_with_data(data, gcc)
     }
 
+    /// The slice's array holds `cap` elements, the ones past `size` can be
+    /// sliced up to later.
     #[inline]
     pub(crate) fn slice_with_size(
         size: usize,
@@ -1535,7 +1654,7 @@ impl GosValue {
         caller: &Box<dyn Dispatcher>,
         gcc: &GcContainer,
     ) -> GosValue {
-        let arr = GosValue::array_with_size(size, cap, val, caller, gcc);
+        let arr = GosValue::array_with_size(cap, cap, val, caller, gcc);
         GosValue::slice_array(arr, 0, size as isize, caller).unwrap()
     }
 
@@ -1976,10 +2095,11 @@ impl GosValue {
 
     #[inline]
     pub(crate) fn slice_array_equivalent(&self, index: usize) -> RuntimeResult<(&GosValue, usize)> {
-        Ok(self
-            .as_non_nil_slice::<AnyElem>()?
-            .0
-            .get_array_equivalent(index))
+        match self.as_slice::<AnyElem>() {
+            Some((s, _)) if index < s.len() => Ok(s.get_array_equivalent(index)),
+            Some((s, _)) => Err(RuntimeError::index_out_of_range(index, s.len())),
+            None => Err(RuntimeError::index_out_of_range(index, 0)),
+        }
     }
 
     pub fn slice_swap(&self, i: usize, j: usize) -> RuntimeResult<()> {
@@ -2007,6 +2127,110 @@ impl GosValue {
         }
     }
 
+    /// Go's ==, unlike `eq`, which is the total equality of map keys, floats
+    /// are compared with IEEE semantics, inside arrays, structs and
+    /// interfaces too: NaN is not equal to itself.
+    pub fn go_eq(&self, b: &GosValue) -> bool {
+        match (self.typ, b.typ) {
+            (_, ValueType::Void) | (ValueType::Void, _) => self.eq(b),
+            (ValueType::Float32, ValueType::Float32) => self.as_float32().0 == b.as_float32().0,
+            (ValueType::Float64, ValueType::Float64) => self.as_float64().0 == b.as_float64().0,
+            (ValueType::Complex64, ValueType::Complex64) => {
+                let x = self.as_complex64();
+                let y = b.as_complex64();
+                x.r.0 == y.r.0 && x.i.0 == y.i.0
+            }
+            (ValueType::Complex128, ValueType::Complex128) => {
+                let x = self.as_complex128();
+                let y = b.as_complex128();
+                x.r.0 == y.r.0 && x.i.0 == y.i.0
+            }
+            (ValueType::Array, ValueType::Array) => {
+                self.caller_slow().array_go_eq(self.data(), b.data())
+            }
+            (ValueType::Struct, ValueType::Struct) => {
+                let x = self.as_struct().0.borrow_fields();
+                let y = b.as_struct().0.borrow_fields();
+                x.iter().zip(y.iter()).all(|(x, y)| x.go_eq(y))
+            }
+            (ValueType::Interface, ValueType::Interface) => {
+                match (self.as_interface(), b.as_interface()) {
+                    (Some(InterfaceObj::Gos(x, _)), Some(InterfaceObj::Gos(y, _))) => x.go_eq(y),
+                    _ => self.eq(b),
+                }
+            }
+            (ValueType::Interface, _) => match self.as_interface() {
+                Some(InterfaceObj::Gos(x, _)) => x.go_eq(b),
+                _ => self.eq(b),
+            },
+            (_, ValueType::Interface) => b.go_eq(self),
+            _ => self.eq(b),
+        }
+    }
+
+    /// Whether the value holds a NaN, which makes it not equal to itself.
+    pub(crate) fn has_nan(&self) -> bool {
+        match self.typ {
+            ValueType::Float32 => self.as_float32().is_nan(),
+            ValueType::Float64 => self.as_float64().is_nan(),
+            ValueType::Complex64 => {
+                let c = self.as_complex64();
+                c.r.is_nan() || c.i.is_nan()
+            }
+            ValueType::Complex128 => {
+                let c = self.as_complex128();
+                c.r.is_nan() || c.i.is_nan()
+            }
+            ValueType::Array | ValueType::Struct => !self.go_eq(self),
+            ValueType::Interface => match self.as_interface() {
+                Some(InterfaceObj::Gos(v, _)) => v.has_nan(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Replaces the NaN with one of bits no other has had, so that it is a
+    /// new map key like in Go, where a NaN key can be added many times.
+    /// The signaling NaNs are used, which arithmetic doesn't produce.
+    /// Arrays and structs holding NaNs are left as they are.
+    pub(crate) fn with_unique_nan(self) -> GosValue {
+        fn nan32() -> F32 {
+            let n = NAN_PAYLOAD.fetch_add(1, AtomicOrdering::Relaxed) as u32;
+            f32::from_bits(0x7fa0_0000 | (n & 0x001f_ffff)).into()
+        }
+        fn nan64() -> F64 {
+            let n = NAN_PAYLOAD.fetch_add(1, AtomicOrdering::Relaxed);
+            f64::from_bits(0x7ff4_0000_0000_0000 | (n & 0x0003_ffff_ffff_ffff)).into()
+        }
+        match self.typ {
+            ValueType::Float32 => GosValue::from(nan32().0),
+            ValueType::Float64 => GosValue::from(nan64().0),
+            ValueType::Complex64 => {
+                let c = *self.as_complex64();
+                match c.r.is_nan() {
+                    true => GosValue::new_complex64(nan32(), c.i),
+                    false => GosValue::new_complex64(c.r, nan32()),
+                }
+            }
+            ValueType::Complex128 => {
+                let c = *self.as_complex128();
+                match c.r.is_nan() {
+                    true => GosValue::new_complex128(nan64(), c.i),
+                    false => GosValue::new_complex128(c.r, nan64()),
+                }
+            }
+            ValueType::Interface => match self.as_interface() {
+                Some(InterfaceObj::Gos(v, meta)) => GosValue::new_interface(InterfaceObj::Gos(
+                    v.clone().with_unique_nan(),
+                    meta.clone(),
+                )),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+
     #[inline]
     pub(crate) fn copy_semantic(&self, gcc: &GcContainer) -> GosValue {
         if self.copyable() {
@@ -2161,6 +2385,10 @@ impl GosValue {
 
     #[inline]
     pub(crate) fn cast(&self, new_type: ValueType) -> GosValue {
+        // a nil slice has no StringObj to share, e.g. an empty strings.Builder
+        if new_type == ValueType::String && self.typ == ValueType::Slice && self.is_nil() {
+            return GosValue::with_str("");
+        }
         GosValue::new(new_type, self.data.clone(self.typ))
     }
 
@@ -2218,12 +2446,16 @@ impl PartialEq for GosValue {
             (ValueType::Uint16, ValueType::Uint16) => self.as_uint16().eq(b.as_uint16()),
             (ValueType::Uint32, ValueType::Uint32) => self.as_uint32().eq(b.as_uint32()),
             (ValueType::Uint64, ValueType::Uint64) => self.as_uint64().eq(b.as_uint64()),
-            (ValueType::Float32, ValueType::Float32) => self.as_float32().eq(b.as_float32()),
-            (ValueType::Float64, ValueType::Float64) => self.as_float64().eq(b.as_float64()),
+            (ValueType::Float32, ValueType::Float32) => {
+                float_key_eq!(self.as_float32(), b.as_float32())
+            }
+            (ValueType::Float64, ValueType::Float64) => {
+                float_key_eq!(self.as_float64(), b.as_float64())
+            }
             (ValueType::Complex64, ValueType::Complex64) => {
                 let x = self.as_complex64();
                 let y = b.as_complex64();
-                x.r == y.r && x.i == y.i
+                float_key_eq!(x.r, y.r) && float_key_eq!(x.i, y.i)
             }
             (ValueType::Function, ValueType::Function) => self.as_function().eq(b.as_function()),
             (ValueType::Package, ValueType::Package) => self.as_package().eq(b.as_package()),
@@ -2231,10 +2463,10 @@ impl PartialEq for GosValue {
             (ValueType::Complex128, ValueType::Complex128) => {
                 let x = self.as_complex128();
                 let y = b.as_complex128();
-                x.r == y.r && x.i == y.i
+                float_key_eq!(x.r, y.r) && float_key_eq!(x.i, y.i)
             }
             (ValueType::String, ValueType::String) => {
-                *self.as_string().as_str() == *b.as_string().as_str()
+                *self.as_string().as_bytes() == *b.as_string().as_bytes()
             }
             (ValueType::Array, ValueType::Array) => {
                 self.caller_slow().array_eq(self.data(), b.data())
@@ -2288,18 +2520,22 @@ impl Hash for GosValue {
             ValueType::Uint16 => self.as_uint16().hash(state),
             ValueType::Uint32 => self.as_uint32().hash(state),
             ValueType::Uint64 => self.as_uint64().hash(state),
-            ValueType::Float32 => self.as_float32().hash(state),
-            ValueType::Float64 => self.as_float64().hash(state),
-            ValueType::Complex64 => self.as_uint64().hash(state),
+            ValueType::Float32 => float_key_hash!(self.as_float32(), state),
+            ValueType::Float64 => float_key_hash!(self.as_float64(), state),
+            ValueType::Complex64 => {
+                let c = self.as_complex64();
+                float_key_hash!(c.r, state);
+                float_key_hash!(c.i, state);
+            }
             ValueType::Function => self.as_function().hash(state),
             ValueType::Package => self.as_package().hash(state),
             ValueType::Metadata => self.as_metadata().hash(state),
-            ValueType::String => self.as_string().as_str().hash(state),
+            ValueType::String => self.as_string().as_bytes().hash(state),
             ValueType::Array => self.caller_slow().array_hash(self, state),
             ValueType::Complex128 => {
                 let c = self.as_complex128();
-                c.r.hash(state);
-                c.i.hash(state);
+                float_key_hash!(c.r, state);
+                float_key_hash!(c.i, state);
             }
             ValueType::Struct => {
                 self.as_struct().0.hash(state);
@@ -2308,6 +2544,10 @@ impl Hash for GosValue {
                 Some(p) => PointerObj::hash(&p, state),
                 None => 0.hash(state),
             },
+            ValueType::UnsafePtr => match self.as_unsafe_ptr() {
+                Some(p) => p.hash(state),
+                None => 0.hash(state),
+            },
             ValueType::Interface => match self.as_interface() {
                 Some(iface) => iface.hash(state),
                 None => 0.hash(state),
@@ -2342,26 +2582,30 @@ impl Ord for GosValue {
             (ValueType::Uint16, ValueType::Uint16) => self.as_uint16().cmp(b.as_uint16()),
             (ValueType::Uint32, ValueType::Uint32) => self.as_uint32().cmp(b.as_uint32()),
             (ValueType::Uint64, ValueType::Uint64) => self.as_uint64().cmp(b.as_uint64()),
-            (ValueType::Float32, ValueType::Float32) => self.as_float32().cmp(b.as_float32()),
-            (ValueType::Float64, ValueType::Float64) => self.as_float64().cmp(b.as_float64()),
+            (ValueType::Float32, ValueType::Float32) => {
+                float_key_cmp!(self.as_float32(), b.as_float32())
+            }
+            (ValueType::Float64, ValueType::Float64) => {
+                float_key_cmp!(self.as_float64(), b.as_float64())
+            }
             (ValueType::Complex128, ValueType::Complex128) => {
                 let left = self.as_complex128();
-                let right = self.as_complex128();
-                left.r.cmp(&right.r).then(left.i.cmp(&right.i))
+                let right = b.as_complex128();
+                float_key_cmp!(left.r, &right.r).then(float_key_cmp!(left.i, &right.i))
             }
             (ValueType::Function, ValueType::Function) => self.as_uint64().cmp(b.as_uint64()),
             (ValueType::Package, ValueType::Package) => self.as_uint64().cmp(b.as_uint64()),
             (ValueType::Metadata, ValueType::Metadata) => self.as_metadata().cmp(b.as_metadata()),
             (ValueType::String, ValueType::String) => {
-                self.as_string().as_str().cmp(&b.as_string().as_str())
+                self.as_string().as_bytes().cmp(&b.as_string().as_bytes())
             }
             (ValueType::Array, ValueType::Array) => {
                 self.caller_slow().array_cmp(self.data(), b.data())
             }
             (ValueType::Complex64, ValueType::Complex64) => {
                 let left = self.as_complex64();
-                let right = self.as_complex64();
-                left.r.cmp(&right.r).then(left.i.cmp(&right.i))
+                let right = b.as_complex64();
+                float_key_cmp!(left.r, &right.r).then(float_key_cmp!(left.i, &right.i))
             }
             (ValueType::Struct, ValueType::Struct) => self.as_struct().0.cmp(&b.as_struct().0),
             (ValueType::Pointer, ValueType::Pointer) => match (self.as_pointer(), b.as_pointer()) {
@@ -2370,6 +2614,14 @@ impl Ord for GosValue {
                 (Some(_), None) => Ordering::Greater,
                 (None, Some(_)) => Ordering::Less,
             },
+            (ValueType::UnsafePtr, ValueType::UnsafePtr) => {
+                match (self.as_unsafe_ptr(), b.as_unsafe_ptr()) {
+                    (Some(a), Some(b)) => a.cmp(b),
+                    (None, None) => Ordering::Equal,
+                    (Some(_), None) => Ordering::Greater,
+                    (None, Some(_)) => Ordering::Less,
+                }
+            }
             (ValueType::Interface, ValueType::Interface) => {
                 match (self.as_interface(), b.as_interface()) {
                     (Some(a), Some(b)) => a.cmp(b),
@@ -2534,7 +2786,7 @@ impl GosValue {
             ValueType::Function => self.as_function().serialize(writer),
             ValueType::Package => self.as_package().serialize(writer),
             ValueType::Metadata => self.as_metadata().serialize(writer),
-            ValueType::String => self.as_string().as_str().serialize(writer),
+            ValueType::String => self.as_string().as_bytes().serialize(writer),
             ValueType::Array => {
                 let vec = self.caller_slow().array_get_vec(self);
                 GosValue::too_large_check(vec.len())?;
@@ -2625,7 +2877,7 @@ impl GosValue {
             ValueType::Function => GosValue::new_function(FunctionKey::deserialize_reader(reader)?),
             ValueType::Package => GosValue::new_package(PackageKey::deserialize_reader(reader)?),
             ValueType::Metadata => GosValue::new_metadata(Meta::deserialize_reader(reader)?),
-            ValueType::String => GosValue::with_str(&String::deserialize_reader(reader)?),
+            ValueType::String => GosValue::with_bytes(&Vec::<u8>::deserialize_reader(reader)?),
             ValueType::Array => {
                 let elem_read = tr.t_elem_read()?;
                 let t_elem = elem_read.deserialize_reader(reader)?;
@@ -2729,67 +2981,7 @@ impl BorshDeserialize for GosValue {
 
 impl Display for GosValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.typ {
-            ValueType::Bool => write!(f, "{}", self.as_bool()),
-            ValueType::Int => write!(f, "{}", self.as_int()),
-            ValueType::Int8 => write!(f, "{}", self.as_int8()),
-            ValueType::Int16 => write!(f, "{}", self.as_int16()),
-            ValueType::Int32 => write!(f, "{}", self.as_int32()),
-            ValueType::Int64 => write!(f, "{}", self.as_int64()),
-            ValueType::Uint => write!(f, "{}", self.as_uint()),
-            ValueType::UintPtr => write!(f, "{}", self.as_uint_ptr()),
-            ValueType::Uint8 => write!(f, "{}", self.as_uint8()),
-            ValueType::Uint16 => write!(f, "{}", self.as_uint16()),
-            ValueType::Uint32 => write!(f, "{}", self.as_uint32()),
-            ValueType::Uint64 => write!(f, "{}", self.as_uint64()),
-            ValueType::Float32 => write!(f, "{}", self.as_float32()),
-            ValueType::Float64 => write!(f, "{}", self.as_float64()),
-            ValueType::Complex64 => {
-                let c = self.as_complex64();
-                write!(f, "({}, {})", c.r, c.i)
-            }
-            ValueType::Function => f.write_str("<function>"),
-            ValueType::Package => f.write_str("<package>"),
-            ValueType::Metadata => f.write_str("<metadata>"),
-            ValueType::Complex128 => {
-                let c = self.as_complex128();
-                write!(f, "({}, {})", c.r, c.i)
-            }
-            ValueType::String => f.write_str(&self.as_string().as_str()),
-            ValueType::Array => display_vec(&self.caller_slow().array_get_vec(self), f),
-            ValueType::Struct => write!(f, "{}", self.as_struct().0),
-            ValueType::Pointer => match self.as_pointer() {
-                Some(p) => std::fmt::Display::fmt(p, f),
-                None => f.write_str("<nil(pointer)>"),
-            },
-            ValueType::UnsafePtr => match self.as_unsafe_ptr() {
-                Some(p) => std::fmt::Display::fmt(p, f),
-                None => f.write_str("<nil(unsafe pointer)>"),
-            },
-            ValueType::Closure => match self.as_closure() {
-                Some(_) => f.write_str("<closure>"),
-                None => f.write_str("<nil(closure)>"),
-            },
-            ValueType::Slice => match self.caller_slow().slice_get_vec(self) {
-                Some(v) => display_vec(&v, f),
-                None => f.write_str("<nil(slice)>"),
-            },
-            ValueType::Map => match self.as_map() {
-                Some(m) => write!(f, "{}", m.0),
-                None => f.write_str("<nil(map)>"),
-            },
-            ValueType::Interface => match self.as_interface() {
-                Some(i) => write!(f, "{}", i),
-                None => f.write_str("<nil(interface)>"),
-            },
-            #[cfg(feature = "async")]
-            ValueType::Channel => match self.as_channel() {
-                Some(_) => f.write_str("<channel>"),
-                None => f.write_str("<nil(channel)>"),
-            },
-            ValueType::Void => f.write_str("<nil(untyped)>"),
-            _ => unreachable!(),
-        }
+        fmt::Display::fmt(&ValueFmt::new(self, Verb::V), f)
     }
 }
 
@@ -2817,11 +3009,16 @@ impl fmt::Debug for GosValue {
             ValueType::Metadata => write!(f, "Type: {:?}, Data: {:#?}", t, self.as_metadata()),
             ValueType::Complex128 => write!(f, "Type: {:?}, Data: {:#?}", t, self.as_complex128()),
             ValueType::String => {
-                write!(f, "Type: {:?}, Data: {:?}", t, &self.as_string().as_str(),)
+                write!(
+                    f,
+                    "Type: {:?}, Data: {:?}",
+                    t,
+                    String::from_utf8_lossy(&self.as_string().as_bytes())
+                )
             }
             ValueType::Array => match self.t_elem {
                 ValueType::Void => write!(f, "Type: {:?}, Data: {:?}", t, "unknown"),
-                _ => display_vec(&self.caller_slow().array_get_vec(self), f),
+                _ => Display::fmt(self, f),
             },
             ValueType::Struct => write!(f, "Type: {:?}, Data: {:#?}", t, self.as_struct()),
             ValueType::Pointer => write!(f, "Type: {:?}, Data: {:#?}", t, self.as_pointer()),
@@ -2850,17 +3047,6 @@ impl fmt::Debug for GosValue {
     }
 }
 
-fn display_vec(vec: &Vec<GosValue>, f: &mut fmt::Formatter) -> fmt::Result {
-    f.write_char('[')?;
-    for (i, v) in vec.iter().enumerate() {
-        if i > 0 {
-            f.write_char(' ')?;
-        }
-        write!(f, "{}", v)?
-    }
-    f.write_char(']')
-}
-
 pub fn debug_vec(vec: &Vec<GosValue>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     f.write_char('[')?;
     for (i, v) in vec.iter().enumerate() {
@@ -3065,7 +3251,7 @@ impl From<String> for GosValue {
 impl AsPrimitive<String> for GosValue {
     #[inline]
     fn as_(&self) -> String {
-        self.as_string().as_str().to_owned()
+        String::from_utf8_lossy(&self.as_string().as_bytes()).into_owned()
     }
 }
 
@@ -3128,4 +3314,22 @@ mod test {
         //let s2 = s.clone().into_string();
         //dbg!(s2);
     }
+    #[test]
+    fn test_nan_eq() {
+        let nan: GosValue = f64::NAN.into();
+        assert!(nan == nan && nan.cmp(&nan) == Ordering::Equal);
+        assert!(!nan.go_eq(&nan));
+        let zero: GosValue = 0.0f64.into();
+        let neg: GosValue = (-0.0f64).into();
+        assert!(zero == neg && zero.go_eq(&neg) && zero.cmp(&neg) == Ordering::Equal);
+
+        let m = MapObj::new();
+        m.insert(nan.clone(), 1.into());
+        m.insert(nan.clone(), 2.into());
+        assert_eq!(m.len(), 2);
+        assert!(m.get(&nan).is_none());
+        let keys: Vec<GosValue> = m.borrow_data().keys().cloned().collect();
+        assert!(keys[0] != keys[1] && keys[0].cmp(&keys[1]) != Ordering::Equal);
+        assert!(keys.iter().all(|k| k == k && !k.go_eq(k)));
+    }
 }
@@ -0,0 +1,449 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Formatting of script values the way Go's fmt prints them with `%v`, `%+v`
+//! and `%#v`. The Display impls of values, the fmt packages and hosts that
+//! show values to users, e.g. debuggers, all print values with it.
+//!
+//! Without the types only the kinds of the values are known: the fields of
+//! structs are printed without names and `%#v` can't spell the types. The
+//! dynamic types of the values in interfaces are known whenever the objects
+//! are given. A slice or map that contains itself prints `<cycle>` where it
+//! repeats, values nested deeper than the depth limit print `...`.
+
+use crate::ftoa;
+use crate::meta_view::MetaView;
+use crate::stack::Stack;
+use crate::value::*;
+use std::fmt::{self, Write};
+
+/// How deep values are printed by default, a struct or a container is one level.
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verb {
+    /// `%v`, the default format.
+    V,
+    /// `%+v`, like `%v` with the names of the struct fields.
+    PlusV,
+    /// `%#v`, the Go syntax of the value.
+    SharpV,
+}
+
+/// ValueFmt is a value that displays with a verb, see `FfiCtx::format_value`.
+#[derive(Clone, Copy)]
+pub struct ValueFmt<'a> {
+    val: &'a GosValue,
+    verb: Verb,
+    meta: Option<Meta>,
+    objs: Option<&'a VMObjects>,
+    packages: Option<&'a PackageObjs>,
+    stack: Option<&'a Stack>,
+    max_depth: usize,
+}
+
+impl<'a> ValueFmt<'a> {
+    pub fn new(val: &'a GosValue, verb: Verb) -> ValueFmt<'a> {
+        ValueFmt {
+            val,
+            verb,
+            meta: None,
+            objs: None,
+            packages: None,
+            stack: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Gives the type of the value, if it's known, and the objects to look up
+    /// the types of the values in interfaces.
+    pub fn with_type(mut self, meta: Option<Meta>, objs: &'a VMObjects) -> ValueFmt<'a> {
+        self.meta = meta;
+        self.objs = Some(objs);
+        self
+    }
+
+    /// Lets it print what pointers to package vars and to the locals of
+    /// running functions point to, like Go does for `&T{...}`.
+    pub fn with_pointees(
+        mut self,
+        packages: &'a PackageObjs,
+        stack: Option<&'a Stack>,
+    ) -> ValueFmt<'a> {
+        self.packages = Some(packages);
+        self.stack = stack;
+        self
+    }
+
+    pub fn with_max_depth(mut self, depth: usize) -> ValueFmt<'a> {
+        self.max_depth = depth;
+        self
+    }
+}
+
+impl<'a> fmt::Display for ValueFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let typ = self.objs.zip(self.meta).map(|(o, m)| MetaView::new(m, o));
+        let mut p = Printer {
+            verb: self.verb,
+            objs: self.objs,
+            packages: self.packages,
+            stack: self.stack,
+            max_depth: self.max_depth,
+            path: vec![],
+        };
+        p.value(f, self.val, typ, 0)
+    }
+}
+
+/// Printer walks a value, the slices and maps being printed are kept to
+/// detect cycles.
+pub(crate) struct Printer<'a> {
+    verb: Verb,
+    objs: Option<&'a VMObjects>,
+    packages: Option<&'a PackageObjs>,
+    stack: Option<&'a Stack>,
+    max_depth: usize,
+    path: Vec<*const usize>,
+}
+
+impl<'a> Printer<'a> {
+    /// A printer without types, for the Display impls.
+    pub(crate) fn untyped(verb: Verb) -> Printer<'a> {
+        Printer {
+            verb,
+            objs: None,
+            packages: None,
+            stack: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            path: vec![],
+        }
+    }
+
+    fn value(
+        &mut self,
+        f: &mut fmt::Formatter,
+        val: &GosValue,
+        typ: Option<MetaView<'a>>,
+        depth: usize,
+    ) -> fmt::Result {
+        let sharp = self.verb == Verb::SharpV;
+        macro_rules! uint {
+            ($v:expr) => {
+                match sharp {
+                    true => write!(f, "{:#x}", $v),
+                    false => write!(f, "{}", $v),
+                }
+            };
+        }
+        match val.typ() {
+            ValueType::Bool => write!(f, "{}", val.as_bool()),
+            ValueType::Int => write!(f, "{}", val.as_int()),
+            ValueType::Int8 => write!(f, "{}", val.as_int8()),
+            ValueType::Int16 => write!(f, "{}", val.as_int16()),
+            ValueType::Int32 => write!(f, "{}", val.as_int32()),
+            ValueType::Int64 => write!(f, "{}", val.as_int64()),
+            ValueType::Uint => uint!(val.as_uint()),
+            ValueType::UintPtr => uint!(val.as_uint_ptr()),
+            ValueType::Uint8 => uint!(val.as_uint8()),
+            ValueType::Uint16 => uint!(val.as_uint16()),
+            ValueType::Uint32 => uint!(val.as_uint32()),
+            ValueType::Uint64 => uint!(val.as_uint64()),
+            ValueType::Float32 => ftoa::write_f32(f, val.as_float32().into_inner(), false),
+            ValueType::Float64 => ftoa::write_f64(f, val.as_float64().into_inner(), false),
+            ValueType::Complex64 => {
+                let c = val.as_complex64();
+                f.write_char('(')?;
+                ftoa::write_f32(f, c.r.into_inner(), false)?;
+                ftoa::write_f32(f, c.i.into_inner(), true)?;
+                f.write_str("i)")
+            }
+            ValueType::Complex128 => {
+                let c = val.as_complex128();
+                f.write_char('(')?;
+                ftoa::write_f64(f, c.r.into_inner(), false)?;
+                ftoa::write_f64(f, c.i.into_inner(), true)?;
+                f.write_str("i)")
+            }
+            ValueType::String => {
+                let s = String::from_utf8_lossy(&val.as_string().as_bytes()).into_owned();
+                match sharp {
+                    true => write_quoted(f, &s),
+                    false => f.write_str(&s),
+                }
+            }
+            ValueType::Function => f.write_str("<function>"),
+            ValueType::Package => f.write_str("<package>"),
+            ValueType::Metadata => f.write_str("<metadata>"),
+            ValueType::Array => {
+                let elems = val.caller_slow().array_get_vec(val);
+                self.elems(f, val.as_addr(), &elems, typ, depth)
+            }
+            ValueType::Slice => match val.caller_slow().slice_get_vec(val) {
+                Some(elems) => {
+                    let addr = val.caller_slow().slice_addr(val).unwrap();
+                    self.elems(f, addr, &elems, typ, depth)
+                }
+                None => self.nil(f, typ, "[]"),
+            },
+            ValueType::Map => match val.as_map() {
+                Some((m, _)) => self.map(f, val.as_addr(), m, typ, depth),
+                None => self.nil(f, typ, "map[]"),
+            },
+            ValueType::Struct => {
+                let fields = val.as_struct().0.borrow_fields().clone();
+                self.fields(f, &fields, typ, depth)
+            }
+            ValueType::Pointer => match val.as_pointer() {
+                Some(p) => self.pointer(f, p, typ, depth),
+                None => self.nil(f, typ, "<nil>"),
+            },
+            ValueType::UnsafePtr => match val.as_unsafe_ptr() {
+                Some(p) => fmt::Display::fmt(p, f),
+                None => self.nil(f, typ, "<nil>"),
+            },
+            ValueType::Closure => match val.as_closure() {
+                Some(_) => self.address(f, typ, val.as_addr()),
+                None => self.nil(f, typ, "<nil>"),
+            },
+            #[cfg(feature = "async")]
+            ValueType::Channel => match val.as_channel() {
+                Some(_) => self.address(f, typ, val.as_addr()),
+                None => self.nil(f, typ, "<nil>"),
+            },
+            // the value is printed with its dynamic type, at the same depth
+            // so that a pointer in an interface is looked into like Go does
+            ValueType::Interface => match val.as_interface() {
+                Some(InterfaceObj::Gos(v, info)) => {
+                    let dynamic = self
+                        .objs
+                        .zip(info.as_ref())
+                        .map(|(o, (m, _))| MetaView::new(*m, o));
+                    self.value(f, v, dynamic, depth)
+                }
+                Some(InterfaceObj::Ffi(ffi)) => write!(f, "<ffi>{:?}", ffi.ffi_obj),
+                None => self.nil(f, typ, "<nil>"),
+            },
+            ValueType::Void => f.write_str("<nil>"),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Prints the elements of an array or a slice.
+    fn elems(
+        &mut self,
+        f: &mut fmt::Formatter,
+        addr: *const usize,
+        elems: &[GosValue],
+        typ: Option<MetaView<'a>>,
+        depth: usize,
+    ) -> fmt::Result {
+        let elem = typ.and_then(|t| t.elem());
+        self.nested(f, Some(addr), depth, |p, f| {
+            p.open(f, typ, '[')?;
+            for (i, v) in elems.iter().enumerate() {
+                p.separator(f, i)?;
+                p.value(f, v, elem, depth + 1)?;
+            }
+            p.close(f, ']')
+        })
+    }
+
+    pub(crate) fn map(
+        &mut self,
+        f: &mut fmt::Formatter,
+        addr: *const usize,
+        m: &MapObj,
+        typ: Option<MetaView<'a>>,
+        depth: usize,
+    ) -> fmt::Result {
+        let (key, elem) = match typ {
+            Some(t) => (t.key(), t.elem()),
+            None => (None, None),
+        };
+        // sorted like Go does, and copied so that no borrow is held while
+        // the entries are printed
+        let mut entries: Vec<(GosValue, GosValue)> = m
+            .borrow_data()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        self.nested(f, Some(addr), depth, |p, f| {
+            match (p.verb, typ) {
+                (Verb::SharpV, Some(t)) => write!(f, "{}{{", t)?,
+                (Verb::SharpV, None) => f.write_char('{')?,
+                _ => f.write_str("map[")?,
+            }
+            for (i, (k, v)) in entries.iter().enumerate() {
+                p.separator(f, i)?;
+                p.value(f, k, key, depth + 1)?;
+                f.write_char(':')?;
+                p.value(f, v, elem, depth + 1)?;
+            }
+            p.close(f, ']')
+        })
+    }
+
+    pub(crate) fn fields(
+        &mut self,
+        f: &mut fmt::Formatter,
+        fields: &[GosValue],
+        typ: Option<MetaView<'a>>,
+        depth: usize,
+    ) -> fmt::Result {
+        // the values of a struct with embedded fields also have slots for
+        // the promoted ones, after the declared ones
+        let views = typ.map(|t| t.fields()).filter(|x| x.len() <= fields.len());
+        let fields = match &views {
+            Some(x) => &fields[..x.len()],
+            None => fields,
+        };
+        self.nested(f, None, depth, |p, f| {
+            p.open(f, typ, '{')?;
+            for (i, v) in fields.iter().enumerate() {
+                p.separator(f, i)?;
+                let view = views.as_ref().map(|x| x[i]);
+                if let (Some(view), Verb::PlusV | Verb::SharpV) = (view, p.verb) {
+                    write!(f, "{}:", view.name)?;
+                }
+                p.value(f, v, view.map(|x| x.typ), depth + 1)?;
+            }
+            p.close(f, '}')
+        })
+    }
+
+    /// Like Go, only the pointers given to be printed are looked into, the
+    /// ones in other values are printed as addresses.
+    fn pointer(
+        &mut self,
+        f: &mut fmt::Formatter,
+        p: &PointerObj,
+        typ: Option<MetaView<'a>>,
+        depth: usize,
+    ) -> fmt::Result {
+        if depth == 0 {
+            if let Some(val) = self.pointee(p) {
+                if matches!(
+                    val.typ(),
+                    ValueType::Struct | ValueType::Array | ValueType::Slice | ValueType::Map
+                ) {
+                    f.write_char('&')?;
+                    return self.value(f, &val, typ.and_then(|t| t.elem()), depth + 1);
+                }
+            }
+        }
+        match (self.verb, typ) {
+            (Verb::SharpV, Some(t)) => write!(f, "({})({})", t, p),
+            _ => write!(f, "{}", p),
+        }
+    }
+
+    fn pointee(&self, p: &PointerObj) -> Option<GosValue> {
+        match p {
+            PointerObj::UpVal(uv) => match (&*uv.inner.borrow(), self.stack) {
+                (UpValueState::Closed(val), _) => Some(val.clone()),
+                (UpValueState::Open(_), Some(stack)) => Some(uv.value(stack).into_owned()),
+                (UpValueState::Open(_), None) => None,
+            },
+            PointerObj::SliceMember(s, i) => s.caller_slow().slice_get(s, *i as usize).ok(),
+            PointerObj::StructField(s, i) => {
+                Some(s.as_struct().0.borrow_fields()[*i as usize].clone())
+            }
+            PointerObj::PkgMember(pkg, i) => self.packages.map(|x| x[*pkg].member(*i).clone()),
+        }
+    }
+
+    /// Prints a container, unless it's too deep or already being printed.
+    fn nested(
+        &mut self,
+        f: &mut fmt::Formatter,
+        addr: Option<*const usize>,
+        depth: usize,
+        body: impl FnOnce(&mut Printer<'a>, &mut fmt::Formatter) -> fmt::Result,
+    ) -> fmt::Result {
+        if depth >= self.max_depth {
+            return f.write_str("...");
+        }
+        if let Some(addr) = addr {
+            if self.path.contains(&addr) {
+                return f.write_str("<cycle>");
+            }
+            self.path.push(addr);
+        }
+        let result = body(self, f);
+        if addr.is_some() {
+            self.path.pop();
+        }
+        result
+    }
+
+    fn open(&self, f: &mut fmt::Formatter, typ: Option<MetaView>, c: char) -> fmt::Result {
+        match (self.verb, typ) {
+            (Verb::SharpV, Some(t)) => write!(f, "{}{{", t),
+            (Verb::SharpV, None) => f.write_char('{'),
+            _ => f.write_char(c),
+        }
+    }
+
+    fn close(&self, f: &mut fmt::Formatter, c: char) -> fmt::Result {
+        match self.verb {
+            Verb::SharpV => f.write_char('}'),
+            _ => f.write_char(c),
+        }
+    }
+
+    fn separator(&self, f: &mut fmt::Formatter, i: usize) -> fmt::Result {
+        match (i, self.verb) {
+            (0, _) => Ok(()),
+            (_, Verb::SharpV) => f.write_str(", "),
+            _ => f.write_char(' '),
+        }
+    }
+
+    fn nil(&self, f: &mut fmt::Formatter, typ: Option<MetaView>, s: &str) -> fmt::Result {
+        match (self.verb, typ) {
+            (Verb::SharpV, Some(t))
+                if matches!(
+                    t.kind(),
+                    ValueType::Pointer | ValueType::Closure | ValueType::Channel
+                ) =>
+            {
+                write!(f, "({})(nil)", t)
+            }
+            (Verb::SharpV, Some(t)) => write!(f, "{}(nil)", t),
+            _ => f.write_str(s),
+        }
+    }
+
+    fn address(
+        &self,
+        f: &mut fmt::Formatter,
+        typ: Option<MetaView>,
+        addr: *const usize,
+    ) -> fmt::Result {
+        match (self.verb, typ) {
+            (Verb::SharpV, Some(t)) => write!(f, "({})({:p})", t, addr),
+            _ => write!(f, "{:p}", addr),
+        }
+    }
+}
+
+/// Writes a string the way Go quotes it.
+fn write_quoted(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 || c == '\x7f' => write!(f, "\\x{:02x}", c as u32)?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
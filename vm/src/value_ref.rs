@@ -0,0 +1,353 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Navigation into script data by paths like `a.b[2].c`, so that hosts can bind
+//! to the fields of the structs, slices and maps of a script, e.g. in UIs,
+//! without walking the values by hand.
+//!
+//! Pointers and interfaces are looked through, named types behave like their
+//! underlying types. The values found are the ones the script holds, not
+//! copies: setting a field of a struct found by a path changes the struct the
+//! script sees.
+
+use crate::globals::check_assign;
+use crate::meta_view::MetaView;
+use crate::stack::Stack;
+use crate::value::*;
+
+/// GosValueRef is a value of the script and its type, see
+/// `FfiCtx::value_ref` and `Globals::with_ref`.
+#[derive(Clone)]
+pub struct GosValueRef<'a> {
+    val: GosValue,
+    meta: Meta,
+    objs: &'a VMObjects,
+    packages: &'a PackageObjs,
+    // to deref the pointers to the locals of running functions
+    stack: Option<&'a Stack>,
+}
+
+#[derive(Debug)]
+enum Step<'p> {
+    Field(&'p str),
+    Index(String),
+}
+
+impl<'a> GosValueRef<'a> {
+    pub fn new(
+        val: GosValue,
+        meta: Meta,
+        objs: &'a VMObjects,
+        packages: &'a PackageObjs,
+        stack: Option<&'a Stack>,
+    ) -> GosValueRef<'a> {
+        GosValueRef {
+            val,
+            meta: meta.into_value_category(),
+            objs,
+            packages,
+            stack,
+        }
+    }
+
+    pub fn value(&self) -> &GosValue {
+        &self.val
+    }
+
+    pub fn into_value(self) -> GosValue {
+        self.val
+    }
+
+    pub fn typ(&self) -> MetaView<'a> {
+        MetaView::new(self.meta, self.objs)
+    }
+
+    /// Follows the path from the value, a path is a field name or an index
+    /// followed by any number of `.field` and `[index]`, e.g. `a.b[2].c`.
+    /// Map keys are written the way Go spells constants, strings can also be
+    /// unquoted, e.g. `m[key]`.
+    pub fn field_by_path(&self, path: &str) -> RuntimeResult<GosValueRef<'a>> {
+        let mut cur = self.clone();
+        for step in parse_path(path)? {
+            cur = cur.step(&step)?;
+        }
+        Ok(cur)
+    }
+
+    /// Sets what the path leads to. `meta` is the type of the value, it must
+    /// be identical to the type of the target. The value is stored as it is,
+    /// copy structs and arrays first if the host keeps using them.
+    pub fn set_by_path(&self, path: &str, val: GosValue, meta: Meta) -> RuntimeResult<()> {
+        let mut steps = parse_path(path)?;
+        let last = steps.pop().unwrap();
+        let mut cur = self.clone();
+        for step in steps {
+            cur = cur.step(&step)?;
+        }
+        let cur = cur.look_through()?;
+        let (target, target_meta) = match &last {
+            Step::Field(name) => {
+                let (parent, index) = cur.field_parent(name)?;
+                let meta = parent.struct_fields()?.get_non_embedded(index).meta;
+                (Target::Field(parent.val, index), meta)
+            }
+            Step::Index(text) => match cur.underlying_mtype() {
+                MetadataType::Array(m, _) | MetadataType::Slice(m) => {
+                    (Target::Elem(cur.val.clone(), parse_index(text)?), *m)
+                }
+                MetadataType::Map(k, v) => {
+                    (Target::Entry(cur.val.clone(), cur.parse_key(text, *k)?), *v)
+                }
+                _ => return Err(format!("cannot index {}", cur.typ()).into()),
+            },
+        };
+        check_assign(&val, meta, target_meta, self.objs, path)?;
+        match target {
+            Target::Field(s, index) => {
+                s.as_struct().0.borrow_fields_mut()[index] = val;
+            }
+            Target::Elem(s, index) => match s.typ() {
+                ValueType::Array => s.caller_slow().array_set(&s, &val, index)?,
+                _ => s.caller_slow().slice_set(&s, &val, index)?,
+            },
+            Target::Entry(m, key) => match m.as_map() {
+                Some((map, _)) => {
+                    map.insert(key, val);
+                }
+                None => return Err("assignment to entry in nil map".to_owned().into()),
+            },
+        }
+        Ok(())
+    }
+
+    fn step(&self, step: &Step) -> RuntimeResult<GosValueRef<'a>> {
+        let cur = self.look_through()?;
+        match step {
+            Step::Field(name) => {
+                let (parent, index) = cur.field_parent(name)?;
+                let meta = parent.struct_fields()?.get_non_embedded(index).meta;
+                let val = parent.val.as_struct().0.borrow_fields()[index].clone();
+                Ok(cur.with(val, meta))
+            }
+            Step::Index(text) => match cur.underlying_mtype() {
+                MetadataType::Array(m, _) => {
+                    let val = cur
+                        .val
+                        .caller_slow()
+                        .array_get(&cur.val, parse_index(text)?)?;
+                    Ok(cur.with(val, *m))
+                }
+                MetadataType::Slice(m) => {
+                    let val = cur
+                        .val
+                        .caller_slow()
+                        .slice_get(&cur.val, parse_index(text)?)?;
+                    Ok(cur.with(val, *m))
+                }
+                MetadataType::Map(k, v) => {
+                    let key = cur.parse_key(text, *k)?;
+                    match cur.val.as_map().and_then(|(map, _)| map.get(&key)) {
+                        Some(val) => Ok(cur.with(val, *v)),
+                        None => Err(format!("key {} not in map", text).into()),
+                    }
+                }
+                _ => Err(format!("cannot index {}", cur.typ()).into()),
+            },
+        }
+    }
+
+    /// Returns the struct that holds the field `name` and the index of the
+    /// field in it, which is an embedded struct for promoted fields.
+    fn field_parent(&self, name: &str) -> RuntimeResult<(GosValueRef<'a>, usize)> {
+        let fields = self.struct_fields()?;
+        if fields.try_index_by_name(name).is_none() {
+            return Err(format!("{} has no field {}", self.typ(), name).into());
+        }
+        let indices = fields.indices_by_name(name);
+        let (last, embedded) = indices.split_last().unwrap();
+        let mut cur = self.clone();
+        for i in embedded {
+            let meta = cur.struct_fields()?.get_non_embedded(*i).meta;
+            let val = cur.val.as_struct().0.borrow_fields()[*i].clone();
+            cur = cur.with(val, meta).look_through()?;
+        }
+        Ok((cur, *last))
+    }
+
+    /// Derefs pointers and unwraps interfaces, until it gets to a value.
+    fn look_through(&self) -> RuntimeResult<GosValueRef<'a>> {
+        let mut cur = self.clone();
+        loop {
+            let meta = cur.meta;
+            if meta.ptr_depth > 0 {
+                let val = cur.deref(cur.val.as_non_nil_pointer()?)?;
+                cur = cur.with(val, meta.unptr_to());
+                continue;
+            }
+            match &self.objs.metas[meta.key] {
+                MetadataType::Named(_, u) if u.ptr_depth > 0 => cur.meta = *u,
+                MetadataType::Interface(_) | MetadataType::Named(..)
+                    if meta.value_type(&self.objs.metas) == ValueType::Interface =>
+                {
+                    cur = match cur.val.as_interface() {
+                        Some(InterfaceObj::Gos(val, Some((m, _)))) => cur.with(val.clone(), *m),
+                        Some(InterfaceObj::Gos(_, None)) | Some(InterfaceObj::Ffi(_)) => {
+                            return Err(format!("cannot look into {}", cur.typ()).into())
+                        }
+                        None => return Err("access nil value".to_owned().into()),
+                    };
+                }
+                _ => return Ok(cur),
+            }
+        }
+    }
+
+    fn deref(&self, ptr: &PointerObj) -> RuntimeResult<GosValue> {
+        match (ptr, self.stack) {
+            (PointerObj::UpVal(uv), None) if uv.is_open() => {
+                Err("cannot deref a pointer to a local of a running function"
+                    .to_owned()
+                    .into())
+            }
+            (PointerObj::UpVal(uv), None) => match &*uv.inner.borrow() {
+                UpValueState::Closed(val) => Ok(val.clone()),
+                UpValueState::Open(_) => unreachable!(),
+            },
+            (PointerObj::UpVal(uv), Some(stack)) => Ok(uv.value(stack).into_owned()),
+            (PointerObj::SliceMember(s, i), _) => s.caller_slow().slice_get(s, *i as usize),
+            (PointerObj::StructField(s, i), _) => {
+                Ok(s.as_struct().0.borrow_fields()[*i as usize].clone())
+            }
+            (PointerObj::PkgMember(pkg, i), _) => Ok(self.packages[*pkg].member(*i).clone()),
+        }
+    }
+
+    fn parse_key(&self, text: &str, meta: Meta) -> RuntimeResult<GosValue> {
+        let invalid = || format!("invalid {} key {}", MetaView::new(meta, self.objs), text);
+        macro_rules! parse {
+            ($t:ty) => {
+                text.parse::<$t>()
+                    .map(GosValue::from)
+                    .map_err(|_| invalid())?
+            };
+        }
+        let key = match meta.value_type(&self.objs.metas) {
+            ValueType::String => match text.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+                Some(s) => GosValue::with_str(&unescape(s)),
+                None => GosValue::with_str(text),
+            },
+            ValueType::Bool => parse!(bool),
+            ValueType::Int => parse!(isize),
+            ValueType::Int8 => parse!(i8),
+            ValueType::Int16 => parse!(i16),
+            ValueType::Int32 => parse!(i32),
+            ValueType::Int64 => parse!(i64),
+            ValueType::Uint => parse!(usize),
+            ValueType::Uint8 => parse!(u8),
+            ValueType::Uint16 => parse!(u16),
+            ValueType::Uint32 => parse!(u32),
+            ValueType::Uint64 => parse!(u64),
+            ValueType::Float32 => parse!(f32),
+            ValueType::Float64 => parse!(f64),
+            _ => return Err(invalid().into()),
+        };
+        Ok(key)
+    }
+
+    fn struct_fields(&self) -> RuntimeResult<&'a Fields> {
+        match self.underlying_mtype() {
+            MetadataType::Struct(f) if self.meta.ptr_depth == 0 => Ok(f),
+            _ => Err(format!("{} is not a struct", self.typ()).into()),
+        }
+    }
+
+    fn underlying_mtype(&self) -> &'a MetadataType {
+        self.meta.mtype_unwraped(&self.objs.metas)
+    }
+
+    fn with(&self, val: GosValue, meta: Meta) -> GosValueRef<'a> {
+        GosValueRef::new(val, meta, self.objs, self.packages, self.stack)
+    }
+}
+
+/// Where `set_by_path` stores the value.
+enum Target {
+    Field(GosValue, usize),
+    Elem(GosValue, usize),
+    Entry(GosValue, GosValue),
+}
+
+fn parse_path(path: &str) -> RuntimeResult<Vec<Step<'_>>> {
+    let invalid = || format!("invalid path {}", path);
+    let mut steps = vec![];
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(s) = rest.strip_prefix('[') {
+            // a quoted key may contain ']'
+            let end = match s.starts_with('"') {
+                true => closing_quote(s).map(|i| i + 1),
+                false => s.find(']'),
+            };
+            match end.filter(|i| s[*i..].starts_with(']')) {
+                Some(i) => {
+                    steps.push(Step::Index(s[..i].trim().to_owned()));
+                    rest = &s[i + 1..];
+                }
+                None => return Err(invalid().into()),
+            }
+        } else {
+            let s = match rest.strip_prefix('.') {
+                Some(s) => s,
+                None if steps.is_empty() => rest,
+                None => return Err(invalid().into()),
+            };
+            let end = s.find(['.', '[']).unwrap_or(s.len());
+            if end == 0 {
+                return Err(invalid().into());
+            }
+            steps.push(Step::Field(&s[..end]));
+            rest = &s[end..];
+        }
+    }
+    match steps.is_empty() {
+        true => Err(invalid().into()),
+        false => Ok(steps),
+    }
+}
+
+fn parse_index(text: &str) -> RuntimeResult<usize> {
+    text.parse::<usize>()
+        .map_err(|_| format!("invalid index {}", text).into())
+}
+
+/// Returns the position of the quote that closes the one `s` starts with.
+fn closing_quote(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(c) => result.push(c),
+                None => {}
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
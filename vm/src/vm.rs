@@ -2,15 +2,26 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::callback::{CallbackCall, CallbackQueue, CallbackResult};
+use crate::events::Events;
 use crate::ffi::{FfiCtx, FfiFactory};
 use crate::gc::{collect, GcContainer};
+use crate::globals::Globals;
+use crate::meta_view::MetaView;
 use crate::objects::ClosureObj;
+use crate::pool::{FiberPool, PoolCaps};
 use crate::stack::{RangeStack, Stack};
+#[cfg(feature = "opcode_stats")]
+use crate::stats::OpcodeStats;
 use crate::value::*;
 use go_parser::Map;
+use std::any::Any;
 use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::rc::Rc;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
 
 #[cfg(feature = "async")]
 use crate::channel;
@@ -18,6 +29,14 @@ use crate::channel;
 use async_executor::LocalExecutor;
 #[cfg(feature = "async")]
 use futures_lite::future;
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::sync::atomic::AtomicUsize;
+#[cfg(feature = "async")]
+use std::task::{Context as TaskContext, Poll};
 
 // restore stack_ref after drop to allow code in block call yield
 macro_rules! restore_stack_ref {
@@ -27,6 +46,53 @@ macro_rules! restore_stack_ref {
     }};
 }
 
+/// Calls an FFI closure, the stack must not be borrowed by the caller, so that
+/// the FFI can yield and callbacks can access it through upvalues.
+macro_rules! call_ffi {
+    ($self_:ident, $ctx:ident, $ffic:expr, $params:expr) => {{
+        let ffic = $ffic;
+        let new_ctx = |stack| FfiCtx {
+            func_name: &ffic.func_name,
+            func_meta: ffic.meta,
+            vm_objs: &$ctx.code.objects,
+            packages: $ctx.packages,
+            user_data: $ctx.ffi_factory.user_data(),
+            stack,
+            gcc: $ctx.gcc,
+            array_slice_caller: &$ctx.code.objects.arr_slice_caller,
+            callbacks: Some(&$ctx.callbacks),
+            goroutine_id: $self_.id,
+            goroutine_count: $ctx.live_fibers.get(),
+            events: $ctx.limits.events.as_ref(),
+            seed: $ctx.limits.seed.unwrap_or(1),
+            args: &$ctx.config.args,
+            env: &$ctx.config.env,
+            app_info: &$ctx.config.app_info,
+            exit_code: &$ctx.exit_code,
+            goroutine_local: &$self_.local,
+            host_data: $ctx.ffi_factory.host_data(),
+        };
+        if !ffic.is_async {
+            ffic.ffi
+                .call(&mut new_ctx(&mut $self_.stack.borrow_mut()), $params)
+        } else {
+            #[cfg(not(feature = "async"))]
+            {
+                Err("Async features disabled".to_owned().into())
+            }
+            #[cfg(feature = "async")]
+            {
+                let fut = ffic
+                    .ffi
+                    .async_call(&mut new_ctx(&mut $self_.stack.borrow_mut()), $params);
+                let _pending = PendingFfi::new(&$ctx.pending_ffi);
+                // the caller stops when it's interrupted, the results are unused
+                $ctx.unless_interrupted(fut).await.unwrap_or(Ok(vec![]))
+            }
+        }
+    }};
+}
+
 macro_rules! go_panic {
     ($panic:ident, $msg:expr, $frame:ident, $code:ident) => {{
         let mut data = PanicData::new($msg);
@@ -44,6 +110,29 @@ macro_rules! go_panic_str {
     }};
 }
 
+// a defer in the body of a range over a function is for the function the loop
+// is in, inst.s1 yield functions up
+macro_rules! defer_call {
+    ($self_:ident, $deferred:expr, $inst:ident, $frame:ident, $panic:ident, $code:ident) => {{
+        if $inst.s1 == 0 {
+            $frame.defer_stack.get_or_insert(vec![]).push($deferred);
+        } else {
+            match range_owner_frame(&$self_.frames, &$self_.stack, $inst.s1) {
+                Some(i) => {
+                    let owner = &mut $self_.frames[i];
+                    owner.defer_stack.get_or_insert(vec![]).push($deferred);
+                    $frame = $self_.frames.last_mut().unwrap();
+                }
+                None => {
+                    $frame = $self_.frames.last_mut().unwrap();
+                    let msg = "defer in the body of a range over a function run outside the loop";
+                    go_panic_str!($panic, msg, $frame, $code);
+                }
+            }
+        }
+    }};
+}
+
 #[cfg(not(feature = "async"))]
 macro_rules! go_panic_no_async {
     ($panic:ident, $frame:ident, $code:ident) => {{
@@ -51,6 +140,14 @@ macro_rules! go_panic_no_async {
     }};
 }
 
+macro_rules! check_allocation {
+    ($ctx:ident, $panic:ident, $frame:ident, $code:ident) => {{
+        if let Err(e) = $ctx.check_allocation() {
+            go_panic_str!($panic, e.as_str(), $frame, $code);
+        }
+    }};
+}
+
 macro_rules! panic_if_err {
     ($result:expr, $panic:ident, $frame:ident, $code:ident) => {{
         if let Err(e) = $result {
@@ -93,13 +190,15 @@ macro_rules! shift_op {
         let right = $stack
             .read($inst.s1, $sb, $consts)
             .data()
-            .cast_copyable($inst.t1, ValueType::Uint32);
-        let vdata = $stack
-            .read($inst.s0, $sb, $consts)
-            .data()
-            .$op(right.as_uint32(), $inst.t0);
-        let val = GosValue::new($inst.t0, vdata);
-        $stack.set($inst.d + $sb, val);
+            .as_shift_count($inst.t1);
+        right.map(|right| {
+            let vdata = $stack
+                .read($inst.s0, $sb, $consts)
+                .data()
+                .$op(&right, $inst.t0);
+            let val = GosValue::new($inst.t0, vdata);
+            $stack.set($inst.d + $sb, val);
+        })
     }};
 }
 
@@ -108,9 +207,11 @@ macro_rules! shift_op_assign {
         let right = $stack
             .read($inst.s0, $sb, $consts)
             .data()
-            .cast_copyable($inst.t1, ValueType::Uint32);
-        let d = $stack.get_data_mut($inst.d + $sb);
-        *d = d.$op(right.as_uint32(), $inst.t0);
+            .as_shift_count($inst.t1);
+        right.map(|right| {
+            let d = $stack.get_data_mut($inst.d + $sb);
+            *d = d.$op(&right, $inst.t0);
+        })
     }};
 }
 
@@ -122,32 +223,284 @@ macro_rules! unary_op {
     }};
 }
 
+/// The instructions a goroutine runs before yielding to the others, so that a
+/// tight loop without channel ops can't starve them.
+const TIME_SLICE: usize = 1024;
+
+/// The most elements make allocates, Go's limit of 1<<48 bytes over the size of a value.
+const MAX_MAKE_SIZE: usize = (1 << 48) / std::mem::size_of::<GosValue>();
+
+/// Limits on the resources a run can use.
+#[derive(Clone, Default)]
+pub struct Limits {
+    /// Max bytes the run can allocate in total, see `GcContainer::allocated`.
+    /// It's a budget, not a cap on the memory in use: freed memory is not
+    /// given back. Exceeding it causes a runtime panic.
+    pub max_total_allocation: Option<usize>,
+    /// Called with the allocated bytes, plus the ones a `make` is about to
+    /// take, when `max_total_allocation` is exceeded, returns a new limit to
+    /// keep running, or None to let it panic.
+    pub on_allocation_exceeded: Option<Rc<dyn Fn(usize) -> Option<usize>>>,
+    /// Lets the host stop the run, see `Interrupt`.
+    pub interrupt: Option<Interrupt>,
+    /// Package vars set by the host before the run and read after it.
+    pub globals: Option<Globals>,
+    /// Named sources the host publishes events to, see `Events`.
+    pub events: Option<Events>,
+    /// Seeds the default source of the std package `math/rand`, so that runs
+    /// given the same seed get the same numbers. Like Go, it is 1 if not set.
+    pub seed: Option<i64>,
+    /// Counts the executed instructions and the use of `pool`, see
+    /// `OpcodeStats`.
+    #[cfg(feature = "opcode_stats")]
+    pub opcode_stats: Option<OpcodeStats>,
+    /// How many stacks and frame lists of finished goroutines are kept for
+    /// the new ones, see `PoolCaps`.
+    pub pool: PoolCaps,
+}
+
+/// What the host gives a run to read, unlike `Limits`, it doesn't change how
+/// the run goes but what the scripts see.
+#[derive(Clone, Debug, Default)]
+pub struct RunConfig {
+    /// The command line arguments of the run, `os.Args` in the std package
+    /// `os`, the first one being the name of the program.
+    pub args: Vec<String>,
+    /// The environment variables of the run, scripts see only these.
+    pub env: Vec<(String, String)>,
+    /// What the host tells the scripts about itself, see `AppInfo`.
+    pub app_info: AppInfo,
+}
+
+/// The version and the settings of the application running the scripts, the
+/// std package `runtime/buildinfo` has them for scripts to detect features.
+#[derive(Clone, Debug, Default)]
+pub struct AppInfo {
+    pub version: String,
+    /// e.g. the feature flags of the application, in the order they are set
+    pub settings: Vec<(String, String)>,
+}
+
+/// Interrupt stops a run from any thread, e.g. to cancel a runaway script.
+///
+/// Goroutines check it every time they yield, which is at least once every
+/// `TIME_SLICE` instructions, the blocked ones are woken up by it. They stop
+/// there without running their deferred calls.
+/// The run then fails with a PanicData that is `interrupted`.
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt(Arc<InterruptState>);
+
+#[derive(Debug, Default)]
+struct InterruptState {
+    interrupted: AtomicBool,
+    // the goroutines blocked until it's interrupted, by the id of their wait
+    wakers: Mutex<Vec<(usize, Waker)>>,
+    #[cfg(feature = "async")]
+    next_wait: AtomicUsize,
+}
+
+impl Interrupt {
+    pub fn new() -> Interrupt {
+        Interrupt::default()
+    }
+
+    pub fn interrupt(&self) {
+        self.0.interrupted.store(true, atomic::Ordering::Relaxed);
+        for (_, w) in self.0.wakers.lock().unwrap().drain(..) {
+            w.wake();
+        }
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.interrupted.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Clears the interruption so that the handle can be used for new runs.
+    pub fn reset(&self) {
+        self.0.interrupted.store(false, atomic::Ordering::Relaxed);
+    }
+
+    /// Resolves when it's interrupted.
+    #[cfg(feature = "async")]
+    fn wait(&self) -> InterruptWait<'_> {
+        InterruptWait {
+            interrupt: self,
+            id: self.0.next_wait.fetch_add(1, atomic::Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+struct InterruptWait<'a> {
+    interrupt: &'a Interrupt,
+    id: usize,
+}
+
+#[cfg(feature = "async")]
+impl Future for InterruptWait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<()> {
+        // checked with the lock held, so that interrupt() can't miss the waker
+        let mut wakers = self.interrupt.0.wakers.lock().unwrap();
+        if self.interrupt.is_interrupted() {
+            return Poll::Ready(());
+        }
+        match wakers.iter_mut().find(|(id, _)| *id == self.id) {
+            Some((_, w)) => w.clone_from(cx.waker()),
+            None => wakers.push((self.id, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for InterruptWait<'_> {
+    fn drop(&mut self) {
+        let mut wakers = self.interrupt.0.wakers.lock().unwrap();
+        wakers.retain(|(id, _)| *id != self.id);
+    }
+}
+
 /// Entry point
+///
+/// Every call runs a new instance of the program, with its own package vars,
+/// heap and goroutines, the Bytecode itself is only read, so the same one can
+/// be run any number of times.
 pub fn run(code: &Bytecode, ffi: &FfiFactory) -> Option<PanicData> {
+    run_with_limits(code, ffi, &Limits::default())
+}
+
+/// Same as `run` but with limits on the resources the run can use.
+pub fn run_with_limits(code: &Bytecode, ffi: &FfiFactory, limits: &Limits) -> Option<PanicData> {
+    run_with_config(code, ffi, limits, &RunConfig::default())
+}
+
+/// Same as `run_with_limits` but with what the scripts see of the host, see
+/// `RunConfig`.
+pub fn run_with_config(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    limits: &Limits,
+    config: &RunConfig,
+) -> Option<PanicData> {
+    run_entry(code, ffi, limits, config).err()
+}
+
+/// Same as `run_with_config` but returns the results of the entry function,
+/// which for Bytecode compiled from an expression is its value.
+pub fn run_entry(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    limits: &Limits,
+    config: &RunConfig,
+) -> CallbackResult {
+    run_entry_impl(code, ffi, limits, config, false)
+}
+
+/// Same as `run_entry`, but the packages the last run with `Limits::globals`
+/// left are taken up where it left them, instead of being initialized again,
+/// for Bytecode compiled from an expression to see them as they are.
+pub fn eval_entry(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    limits: &Limits,
+    config: &RunConfig,
+) -> CallbackResult {
+    run_entry_impl(code, ffi, limits, config, true)
+}
+
+fn run_entry_impl(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    limits: &Limits,
+    config: &RunConfig,
+    restore: bool,
+) -> CallbackResult {
     let gcc = GcContainer::new();
+    if let Some(bytes) = limits.max_total_allocation {
+        gcc.set_allocation_limit(bytes);
+    }
+    // package vars are the only part of the Bytecode that changes at runtime,
+    // each run gets its own copy
+    let packages: PackageObjs = code
+        .objects
+        .packages
+        .iter()
+        .map(|x| x.instance(&gcc))
+        .collect::<Vec<_>>()
+        .into();
+    if let Some(g) = limits.globals.as_ref().filter(|_| restore) {
+        g.restore(&packages, &gcc);
+    }
     let panic_data = Rc::new(RefCell::new(None));
+    let entry_call = CallbackCall::new();
 
     #[cfg(not(feature = "async"))]
     {
-        let ctx = Context::new(code, &gcc, ffi, panic_data.clone());
-        let first_frame = ctx.new_entry_frame(code.entry);
-        Fiber::new(ctx, Stack::new(), first_frame).main_loop();
+        let ctx = Context::new(
+            code,
+            &packages,
+            &gcc,
+            ffi,
+            limits,
+            config,
+            panic_data.clone(),
+        );
+        let entry = ctx.new_entry_closure(code.entry);
+        Fiber::with_call(ctx.clone(), entry, vec![], entry_call.clone()).main_loop();
+        while ctx.run_callbacks() {}
     }
     #[cfg(feature = "async")]
     {
         let exec = Rc::new(LocalExecutor::new());
-        let ctx = Context::new(exec.clone(), code, &gcc, ffi, panic_data.clone());
-        let entry = ctx.new_entry_frame(code.entry);
-        ctx.spawn_fiber(Stack::new(), entry);
+        let ctx = Context::new(
+            exec.clone(),
+            code,
+            &packages,
+            &gcc,
+            ffi,
+            limits,
+            config,
+            panic_data.clone(),
+        );
+        let entry = ctx.new_entry_closure(code.entry);
+        let mut f = Fiber::with_call(ctx.clone(), entry, vec![], entry_call.clone());
+        exec.spawn(async move { f.main_loop().await }).detach();
         future::block_on(async {
             loop {
-                if !exec.try_tick() {
+                let scheduled = ctx.run_callbacks();
+                if !exec.try_tick() && !scheduled {
+                    // it's a deadlock only if no host future is outstanding,
+                    // the hosts may wake them later, from other threads too
+                    if ctx.pending_ffi.get() == 0 {
+                        break;
+                    }
+                    exec.tick().await;
+                }
+                // after os.Exit, the goroutines left, blocked or not, don't run
+                if ctx.exit_code.get().is_some() && ctx.panic_data.borrow().is_some() {
                     break;
                 }
             }
         });
     }
-    panic_data.replace(None)
+    if let Some(g) = &limits.globals {
+        g.keep(packages);
+    }
+    match entry_call.take_result() {
+        Some(Ok(results)) => match panic_data.replace(None) {
+            Some(p) => Err(p),
+            None => Ok(results),
+        },
+        Some(Err(p)) => Err(p),
+        // the entry is blocked, but another goroutine may have been interrupted
+        None => Err(panic_data.replace(None).unwrap_or_else(|| {
+            PanicData::new(GosValue::empty_iface_with_val(GosValue::with_str(
+                "all goroutines are asleep - deadlock!",
+            )))
+        })),
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -166,6 +519,9 @@ struct CallFrame {
     referred_by: Option<Map<OpIndex, Referers>>,
 
     defer_stack: Option<Vec<DeferredCall>>,
+    // the panic a deferred call is run for, recover() called directly in it
+    // takes the panic and stops it
+    recoverable: Option<PanicData>,
 }
 
 impl CallFrame {
@@ -177,6 +533,7 @@ impl CallFrame {
             var_ptrs: None,
             referred_by: None,
             defer_stack: None,
+            recoverable: None,
         }
     }
 
@@ -203,6 +560,46 @@ impl CallFrame {
         }
     }
 
+    /// The upvalue already pointing to the local, which a new closure
+    /// capturing the same variable has to share.
+    fn referred_upvalue(&self, index: OpIndex) -> Option<UpValue> {
+        let referrers = self.referred_by.as_ref()?.get(&index)?;
+        referrers.weaks.iter().find_map(|x| x.upgrade())
+    }
+
+    /// Closes the upvalues pointing to the locals in the range, the variables
+    /// declared there next time are new ones.
+    fn close_up_values(
+        &mut self,
+        locals: std::ops::Range<OpIndex>,
+        stack: &Stack,
+        stack_rc: &Rc<RefCell<Stack>>,
+        func: &FunctionObj,
+    ) {
+        let referred = match &mut self.referred_by {
+            Some(r) => r,
+            None => return,
+        };
+        for ind in locals.clone() {
+            if let Some(referrers) = referred.remove(&ind) {
+                let val = stack.get(self.stack_base + ind);
+                for weak in referrers.weaks.iter() {
+                    if let Some(uv) = weak.upgrade() {
+                        uv.close(val.clone());
+                    }
+                }
+            }
+        }
+        // the pointers to the locals made with & need new upvalues too
+        for (i, p) in func.up_ptrs.iter().enumerate() {
+            if p.is_local && locals.contains(&p.index) {
+                let uv = UpValue::new(p.clone_with_stack(Rc::downgrade(stack_rc), self.stack_base));
+                self.add_referred_by(p.index, p.typ, &uv);
+                self.var_ptrs.as_mut().unwrap()[i] = uv;
+            }
+        }
+    }
+
     #[inline]
     fn func(&self) -> FunctionKey {
         self.closure.as_gos().func
@@ -232,6 +629,29 @@ impl CallFrame {
     }
 }
 
+/// The frame of the function that the body of a range over a function is in,
+/// `level` yield functions up from the last frame, each one refers to the flag
+/// of the function it's in with its first upvalue.
+fn range_owner_frame(
+    frames: &[CallFrame],
+    stack: &Rc<RefCell<Stack>>,
+    level: OpIndex,
+) -> Option<usize> {
+    let mut i = frames.len() - 1;
+    for _ in 0..level {
+        let uv = frames[i].var_ptrs.as_ref()?.first()?;
+        let desc = match &*uv.inner.borrow() {
+            UpValueState::Open(d) if d.stack.ptr_eq(&Rc::downgrade(stack)) => d.clone(),
+            _ => return None,
+        };
+        i = frames[..i].iter().rposition(|f| {
+            f.stack_base == desc.stack_base
+                && matches!(&f.closure, ClosureObj::Gos(c) if c.func == desc.func)
+        })?;
+    }
+    Some(i)
+}
+
 #[derive(Clone, Debug)]
 struct DeferredCall {
     frame: CallFrame,
@@ -244,10 +664,14 @@ enum Result {
     End,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PanicData {
     pub msg: GosValue,
     pub call_stack: Vec<(FunctionKey, OpIndex)>,
+    /// the run was stopped by `Interrupt` rather than by a panic
+    pub interrupted: bool,
+    /// the run was stopped by `FfiCtx::exit`, i.e. `os.Exit`, with the code
+    pub exit_code: Option<i32>,
 }
 
 impl PanicData {
@@ -255,48 +679,103 @@ impl PanicData {
         PanicData {
             msg: m,
             call_stack: vec![],
+            interrupted: false,
+            exit_code: None,
         }
     }
 }
 
+/// Counts an async FFI call as outstanding while it lives, the run doesn't
+/// end as deadlocked while any is.
+#[cfg(feature = "async")]
+struct PendingFfi(Rc<Cell<usize>>);
+
+#[cfg(feature = "async")]
+impl PendingFfi {
+    fn new(count: &Rc<Cell<usize>>) -> PendingFfi {
+        count.set(count.get() + 1);
+        PendingFfi(count.clone())
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for PendingFfi {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
 #[derive(Clone)]
 struct Context<'a> {
     #[cfg(feature = "async")]
     exec: Rc<LocalExecutor<'a>>,
     code: &'a Bytecode,
+    packages: &'a PackageObjs,
     gcc: &'a GcContainer,
     ffi_factory: &'a FfiFactory,
+    limits: &'a Limits,
+    config: &'a RunConfig,
     panic_data: Rc<RefCell<Option<PanicData>>>,
-    next_id: Cell<usize>,
+    callbacks: Rc<CallbackQueue>,
+    // shared by the clones so that fiber IDs are unique
+    next_id: Rc<Cell<usize>>,
+    // the fibers not finished yet, shared by the clones too
+    live_fibers: Rc<Cell<usize>>,
+    // set by os.Exit, it stops all the fibers like an interruption
+    exit_code: Rc<Cell<Option<i32>>>,
+    // the async FFI calls not finished yet, see PendingFfi
+    #[cfg(feature = "async")]
+    pending_ffi: Rc<Cell<usize>>,
+    // the stacks and frame lists of the finished fibers
+    pool: Rc<RefCell<FiberPool<CallFrame>>>,
+    // what the dynamic types implement, for assertions and type switches
+    iface_memo: Rc<IfaceMemo>,
 }
 
 impl<'a> Context<'a> {
     fn new(
         #[cfg(feature = "async")] exec: Rc<LocalExecutor<'a>>,
         code: &'a Bytecode,
+        packages: &'a PackageObjs,
         gcc: &'a GcContainer,
         ffi_factory: &'a FfiFactory,
+        limits: &'a Limits,
+        config: &'a RunConfig,
         panic_data: Rc<RefCell<Option<PanicData>>>,
     ) -> Context<'a> {
         Context {
             #[cfg(feature = "async")]
             exec,
             code,
+            packages,
             gcc,
             ffi_factory,
+            limits,
+            config,
             panic_data,
-            next_id: Cell::new(0),
+            callbacks: Rc::new(CallbackQueue::default()),
+            next_id: Rc::new(Cell::new(0)),
+            live_fibers: Rc::new(Cell::new(0)),
+            exit_code: Rc::new(Cell::new(None)),
+            #[cfg(feature = "async")]
+            pending_ffi: Rc::new(Cell::new(0)),
+            pool: Rc::new(RefCell::new(FiberPool::new(
+                limits.pool,
+                #[cfg(feature = "opcode_stats")]
+                limits.opcode_stats.clone(),
+            ))),
+            iface_memo: Rc::new(IfaceMemo::default()),
         }
     }
 
-    fn new_entry_frame(&self, entry: FunctionKey) -> CallFrame {
-        let cls = ClosureObj::gos_from_func(entry, &self.code.objects.functions, None);
-        CallFrame::with_closure(cls, 0)
+    fn new_entry_closure(&self, entry: FunctionKey) -> ClosureObj {
+        ClosureObj::gos_from_func(entry, &self.code.objects.functions, None)
     }
 
     #[cfg(feature = "async")]
-    fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame) {
+    fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame, local: Option<Rc<dyn Any>>) {
         let mut f = Fiber::new(self.clone(), stack, first_frame);
+        *f.local.get_mut() = local;
         self.exec
             .spawn(async move {
                 // let parent fiber go first
@@ -305,6 +784,82 @@ impl<'a> Context<'a> {
             })
             .detach();
     }
+
+    /// Returns an error if the allocation limit is exceeded and not raised by
+    /// `Limits::on_allocation_exceeded`.
+    fn check_allocation(&self) -> RuntimeResult<()> {
+        self.reserve_allocation(0)
+    }
+
+    /// Like `check_allocation`, but for `bytes` the run is about to allocate,
+    /// so that a big `make` fails before the memory is taken.
+    fn reserve_allocation(&self, bytes: usize) -> RuntimeResult<()> {
+        if !self.gcc.exceeds_allocation_limit(bytes) {
+            return Ok(());
+        }
+        let allocated = self.gcc.allocated();
+        if let Some(limit) = self
+            .limits
+            .on_allocation_exceeded
+            .as_ref()
+            .and_then(|f| f(allocated.saturating_add(bytes)))
+        {
+            self.gcc.set_allocation_limit(limit);
+            if !self.gcc.exceeds_allocation_limit(bytes) {
+                return Ok(());
+            }
+        }
+        let requested = match bytes {
+            0 => String::new(),
+            _ => format!(", {} more requested", bytes),
+        };
+        Err(format!(
+            "runtime error: allocation limit exceeded, {} bytes allocated in total{}, the limit is {}",
+            allocated,
+            requested,
+            self.gcc.allocation_limit()
+        )
+        .into())
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.exit_code.get().is_some()
+            || matches!(&self.limits.interrupt, Some(i) if i.is_interrupted())
+    }
+
+    /// Waits for `fut`, or returns None if the run is interrupted first, so that
+    /// blocked goroutines can stop too.
+    #[cfg(feature = "async")]
+    async fn unless_interrupted<T>(&self, fut: impl Future<Output = T>) -> Option<T> {
+        match &self.limits.interrupt {
+            Some(i) => {
+                future::or(async { Some(fut.await) }, async {
+                    i.wait().await;
+                    None
+                })
+                .await
+            }
+            None => Some(fut.await),
+        }
+    }
+
+    /// Starts the queued callback calls, returns false if there is none.
+    fn run_callbacks(&self) -> bool {
+        let mut any = false;
+        while let Some(qc) = self.callbacks.pop() {
+            any = true;
+            let mut f = Fiber::with_call(self.clone(), qc.closure, qc.args, qc.call);
+            if let Some((id, local)) = qc.caller {
+                f.id = id;
+                *f.local.borrow_mut() = local;
+            }
+            #[cfg(not(feature = "async"))]
+            f.main_loop();
+            #[cfg(feature = "async")]
+            self.exec.spawn(async move { f.main_loop().await }).detach();
+        }
+        any
+    }
 }
 
 struct Fiber<'a> {
@@ -312,24 +867,86 @@ struct Fiber<'a> {
     rstack: RangeStack,
     frames: Vec<CallFrame>,
     context: Context<'a>,
-    _id: usize,
+    // where the results go if it's running a callback
+    callback: Option<CallbackCall>,
+    id: usize,
+    // the host value kept for the goroutine, see `FfiCtx::goroutine_local`
+    local: RefCell<Option<Rc<dyn Any>>>,
 }
 
-impl<'a> Fiber<'a> {
-    pub fn _id(&self) -> usize {
-        self._id
+impl<'a> Drop for Fiber<'a> {
+    fn drop(&mut self) {
+        let live = &self.context.live_fibers;
+        live.set(live.get() - 1);
+
+        // the values are dropped before the pool is borrowed, the stack is
+        // only reused if no FfiCtx still holds it
+        let mut frames = std::mem::take(&mut self.frames);
+        frames.clear();
+        let vec = (Rc::strong_count(&self.stack) == 1).then(|| {
+            let mut v = self.stack.borrow_mut().take_vec();
+            v.clear();
+            v
+        });
+        let mut pool = self.context.pool.borrow_mut();
+        pool.put_frames(frames);
+        if let Some(v) = vec {
+            pool.put_stack(v);
+        }
     }
+}
 
+impl<'a> Fiber<'a> {
     fn new(context: Context<'a>, stack: Stack, first_frame: CallFrame) -> Fiber<'a> {
-        let _id = context.next_id.get();
-        context.next_id.set(_id + 1);
+        let id = context.next_id.get();
+        context.next_id.set(id + 1);
+        context.live_fibers.set(context.live_fibers.get() + 1);
+        let mut frames = context.pool.borrow_mut().take_frames();
+        frames.push(first_frame);
         Fiber {
             stack: Rc::new(RefCell::new(stack)),
             rstack: RangeStack::new(),
-            frames: vec![first_frame],
+            frames,
             context,
-            _id,
+            callback: None,
+            id,
+            local: RefCell::new(None),
+        }
+    }
+
+    fn with_call(
+        context: Context<'a>,
+        cls: ClosureObj,
+        args: Vec<GosValue>,
+        call: CallbackCall,
+    ) -> Fiber<'a> {
+        let gosc = cls.as_gos().clone();
+        let func = &context.code.objects.functions[gosc.func];
+        let mut vec = context.pool.borrow_mut().take_stack();
+        vec.extend_from_slice(&func.ret_zeros);
+        if let Some(r) = &gosc.recv {
+            vec.push(r.clone());
         }
+        vec.extend(args);
+        let frame = CallFrame::with_closure(cls, 0);
+        let mut fiber = Fiber::new(context, Stack::with_vec(vec), frame);
+        if let Some(uvs) = gosc.uvs {
+            let stack = Rc::downgrade(&fiber.stack);
+            let frame = &mut fiber.frames[0];
+            let mut ptrs: Vec<UpValue> = Vec::with_capacity(func.up_ptrs.len());
+            for (i, p) in func.up_ptrs.iter().enumerate() {
+                ptrs.push(if p.is_local {
+                    let uv = UpValue::new(p.clone_with_stack(stack.clone(), 0));
+                    frame.add_referred_by(p.index, p.typ, &uv);
+                    uv
+                } else {
+                    uvs[&i].clone()
+                });
+            }
+            frame.var_ptrs = Some(ptrs);
+        }
+        fiber.callback = Some(call);
+        fiber
     }
 
     #[cfg_attr(feature = "async", go_pmacro::async_fn)]
@@ -337,10 +954,12 @@ impl<'a> Fiber<'a> {
         let ctx = &self.context;
         let gcc = ctx.gcc;
         let objs: &VMObjects = &ctx.code.objects;
+        let pkgs: &PackageObjs = ctx.packages;
         let caller: &ArrCaller = &objs.arr_slice_caller;
         let consts = &ctx.code.consts;
         let prim_meta: &PrimitiveMeta = &objs.prim_meta;
         let ifaces = &ctx.code.ifaces;
+        let iface_memo: &IfaceMemo = &ctx.iface_memo;
         let indices = &ctx.code.indices;
         let mut frame_height = self.frames.len();
         let fr = self.frames.last().unwrap();
@@ -350,22 +969,57 @@ impl<'a> Fiber<'a> {
         let mut stack_mut_ref = self.stack.borrow_mut();
         let mut stack: &mut Stack = &mut stack_mut_ref;
         // allocate local variables
-        stack.set_vec(func.param_count(), func.local_zeros.clone());
+        stack.set_vec(
+            func.ret_count() + func.param_count(),
+            func.local_zeros.clone(),
+        );
 
         let mut code = &func.code;
 
-        let mut total_inst = 0;
-        //let mut stats: Map<Opcode, usize> = Map::new();
+        // the panic being unwound, it lives across time slices, and is held by
+        // the deferred call running for it until that returns
+        let mut panic: Option<PanicData> = None;
+        #[cfg(feature = "opcode_stats")]
+        let op_stats = ctx.limits.opcode_stats.as_ref().map(|x| x.counts());
         loop {
+            #[cfg(feature = "opcode_stats")]
+            if let Some(c) = op_stats {
+                c.borrow_mut().cut();
+            }
+            if ctx.is_interrupted() {
+                let exit_code = ctx.exit_code.get();
+                let msg = match exit_code {
+                    Some(code) => format!("exit status {}", code),
+                    None => "interrupted".to_owned(),
+                };
+                let mut data =
+                    PanicData::new(GosValue::empty_iface_with_val(GosValue::with_str(&msg)));
+                data.call_stack = self
+                    .frames
+                    .iter()
+                    .rev()
+                    .map(|f| (f.func(), f.pc.max(1) - 1))
+                    .collect();
+                data.interrupted = exit_code.is_none();
+                data.exit_code = exit_code;
+                match &self.callback {
+                    Some(call) => call.finish(Err(data)),
+                    None => *ctx.panic_data.borrow_mut() = Some(data),
+                }
+                break;
+            }
             let mut frame = self.frames.last_mut().unwrap();
             let mut result: Result = Result::Continue;
-            let mut panic: Option<PanicData> = None;
-            let yield_unit = 1024;
-            for _ in 0..yield_unit {
+            if frame.pc > 0 {
+                check_allocation!(ctx, panic, frame, code);
+            }
+            for _ in 0..TIME_SLICE {
                 let inst = &code[frame.pc as usize];
                 let inst_op = inst.op0;
-                total_inst += 1;
-                //stats.entry(*inst).and_modify(|e| *e += 1).or_insert(1);
+                #[cfg(feature = "opcode_stats")]
+                if let Some(c) = op_stats {
+                    c.borrow_mut().add(inst_op);
+                }
                 frame.pc += 1;
                 //dbg!(inst);
                 match inst_op {
@@ -411,12 +1065,15 @@ impl<'a> Fiber<'a> {
                                             old.data(),
                                             inst.t0,
                                             inst.op1,
+                                            inst.t1,
                                             inst.s1,
                                             sb,
                                             &consts,
+                                            gcc,
                                         );
-                                        let result =
-                                            array.caller(caller).array_set(&array, &val, i);
+                                        let result = val.and_then(|val| {
+                                            array.caller(caller).array_set(&array, &val, i)
+                                        });
                                         panic_if_err!(result, panic, frame, code);
                                     }
                                     Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -454,12 +1111,15 @@ impl<'a> Fiber<'a> {
                                         old.data(),
                                         inst.t0,
                                         inst.op1,
+                                        inst.t1,
                                         inst.s1,
                                         sb,
                                         &consts,
+                                        gcc,
                                     );
-                                    let result =
-                                        array.caller(caller).array_set(&array, &val, index);
+                                    let result = val.and_then(|val| {
+                                        array.caller(caller).array_set(&array, &val, index)
+                                    });
                                     panic_if_err!(result, panic, frame, code);
                                 }
                                 Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -476,6 +1136,10 @@ impl<'a> Fiber<'a> {
                         frame.pc += 1;
                         let map = stack.read(inst.s0, sb, consts);
                         let key = stack.read(inst.s1, sb, consts);
+                        if let Err(e) = check_hashable(key, objs) {
+                            go_panic_str!(panic, e.as_str(), frame, code);
+                            continue;
+                        }
                         let val = match map.as_map() {
                             Some(map) => map.0.get(&key),
                             None => None,
@@ -497,14 +1161,20 @@ impl<'a> Fiber<'a> {
                         let inst_ex = &code[frame.pc as usize];
                         frame.pc += 1;
                         let dest = stack.read(inst.d, sb, consts);
-                        match dest.as_non_nil_map() {
-                            Ok(map) => {
+                        match dest.as_map() {
+                            Some(map) => {
                                 let key = stack.read(inst.s0, sb, consts);
+                                if let Err(e) = check_hashable(key, objs) {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
                                 match inst.op1 {
                                     Opcode::VOID => {
                                         let val =
                                             stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                        map.0.insert(key.clone(), val);
+                                        if map.0.insert(key.clone(), val).is_none() {
+                                            gcc.add_allocated(MapObj::ENTRY_SIZE);
+                                        }
                                     }
                                     _ => {
                                         let old = match map.0.get(&key) {
@@ -515,15 +1185,26 @@ impl<'a> Fiber<'a> {
                                             old.data(),
                                             inst.t0,
                                             inst.op1,
+                                            inst.t1,
                                             inst.s1,
                                             sb,
                                             &consts,
+                                            gcc,
                                         );
-                                        map.0.insert(key.clone(), val);
+                                        match val {
+                                            Ok(val) => {
+                                                if map.0.insert(key.clone(), val).is_none() {
+                                                    gcc.add_allocated(MapObj::ENTRY_SIZE);
+                                                }
+                                            }
+                                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                        }
                                     }
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            None => {
+                                go_panic_str!(panic, "assignment to entry in nil map", frame, code)
+                            }
                         }
                     }
                     // desc: local
@@ -551,11 +1232,16 @@ impl<'a> Fiber<'a> {
                                     old.data(),
                                     inst.t0,
                                     inst.op1,
+                                    inst.t1,
                                     inst.s1,
                                     sb,
                                     &consts,
+                                    gcc,
                                 );
-                                *old = val;
+                                match val {
+                                    Ok(val) => *old = val,
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
                             }
                         }
                     }
@@ -568,7 +1254,7 @@ impl<'a> Fiber<'a> {
                             src.clone(),
                             &indices[inst.s1 as usize],
                             stack,
-                            objs,
+                            pkgs,
                         );
                         match struct_ {
                             Ok(s) => {
@@ -587,7 +1273,7 @@ impl<'a> Fiber<'a> {
                             dest.clone(),
                             &indices[inst.s0 as usize],
                             stack,
-                            objs,
+                            pkgs,
                         );
                         match struct_ {
                             Ok(s) => match inst.op1 {
@@ -596,16 +1282,22 @@ impl<'a> Fiber<'a> {
                                     s.as_struct().0.borrow_fields_mut()[index] = val;
                                 }
                                 _ => {
-                                    let old = &s.as_struct().0.borrow_fields()[index as usize];
+                                    let mut fields = s.as_struct().0.borrow_fields_mut();
+                                    let old = &mut fields[index];
                                     let val = stack.read_and_op(
                                         old.data(),
                                         inst.t0,
                                         inst.op1,
+                                        inst.t1,
                                         inst.s1,
                                         sb,
                                         &consts,
+                                        gcc,
                                     );
-                                    s.as_struct().0.borrow_fields_mut()[index as usize] = val;
+                                    match val {
+                                        Ok(val) => *old = val,
+                                        Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                    }
                                 }
                             },
                             Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -617,7 +1309,7 @@ impl<'a> Fiber<'a> {
                     Opcode::LOAD_PKG => {
                         let src = stack.read(inst.s0, sb, consts);
                         let index = inst.s1;
-                        let pkg = &objs.packages[*src.as_package()];
+                        let pkg = &pkgs[*src.as_package()];
                         let val = pkg.member(index).clone();
                         stack.set(inst.d + sb, val);
                     }
@@ -628,7 +1320,7 @@ impl<'a> Fiber<'a> {
                         let dest = stack.read(inst.d, sb, consts);
                         let index = inst.s0;
 
-                        let pkg = &objs.packages[*dest.as_package()];
+                        let pkg = &pkgs[*dest.as_package()];
                         match inst.op1 {
                             Opcode::VOID => {
                                 let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
@@ -640,11 +1332,16 @@ impl<'a> Fiber<'a> {
                                     old.data(),
                                     inst.t0,
                                     inst.op1,
+                                    inst.t1,
                                     inst.s1,
                                     sb,
                                     &consts,
+                                    gcc,
                                 );
-                                *old = val;
+                                match val {
+                                    Ok(val) => *old = val,
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
                             }
                         }
                     }
@@ -653,7 +1350,7 @@ impl<'a> Fiber<'a> {
                     Opcode::LOAD_POINTER => {
                         let src = stack.read(inst.s0, sb, consts);
                         match src.as_non_nil_pointer() {
-                            Ok(p) => match p.deref(stack, &objs.packages) {
+                            Ok(p) => match p.deref(stack, pkgs) {
                                 Ok(val) => stack.set(inst.d + sb, val),
                                 Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                             },
@@ -668,15 +1365,17 @@ impl<'a> Fiber<'a> {
                             let val = match inst.op1 {
                                 Opcode::VOID => stack.read(inst.s0, sb, consts).copy_semantic(gcc),
                                 _ => {
-                                    let old = p.deref(stack, &objs.packages)?;
+                                    let old = p.deref(stack, pkgs)?;
                                     stack.read_and_op(
                                         old.data(),
                                         inst.t0,
                                         inst.op1,
+                                        inst.t1,
                                         inst.s0,
                                         sb,
                                         &consts,
-                                    )
+                                        gcc,
+                                    )?
                                 }
                             };
                             match p {
@@ -694,7 +1393,7 @@ impl<'a> Fiber<'a> {
                                     Ok(())
                                 }
                                 PointerObj::PkgMember(p, index) => {
-                                    let pkg = &objs.packages[*p];
+                                    let pkg = &pkgs[*p];
                                     *pkg.member_mut(*index) = val;
                                     Ok(())
                                 }
@@ -709,6 +1408,9 @@ impl<'a> Fiber<'a> {
                         let val = uvs[inst.s0 as usize].value(stack).into_owned();
                         stack.set(inst.d + sb, val);
                     }
+                    Opcode::CLOSE_UP_VALUES => {
+                        frame.close_up_values(inst.s0..inst.s1, stack, &self.stack, func);
+                    }
                     Opcode::STORE_UP_VALUE => {
                         let uvs = frame.var_ptrs.as_ref().unwrap();
                         let uv = &uvs[inst.d as usize];
@@ -723,15 +1425,26 @@ impl<'a> Fiber<'a> {
                                     old.data(),
                                     inst.t0,
                                     inst.op1,
+                                    inst.t1,
                                     inst.s0,
                                     sb,
                                     &consts,
+                                    gcc,
                                 );
-                                uv.set_value(val, stack);
+                                match val {
+                                    Ok(val) => uv.set_value(val, stack),
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
                             }
                         }
                     }
-                    Opcode::ADD => binary_op!(stack, binary_op_add, inst, sb, consts),
+                    Opcode::ADD => {
+                        binary_op!(stack, binary_op_add, inst, sb, consts);
+                        if inst.t0 == ValueType::String {
+                            gcc.add_allocated(stack.get(inst.d + sb).as_string().len());
+                            check_allocation!(ctx, panic, frame, code);
+                        }
+                    }
                     Opcode::SUB => binary_op!(stack, binary_op_sub, inst, sb, consts),
                     Opcode::MUL => binary_op!(stack, binary_op_mul, inst, sb, consts),
                     Opcode::QUO => binary_op!(stack, binary_op_quo, inst, sb, consts),
@@ -740,9 +1453,21 @@ impl<'a> Fiber<'a> {
                     Opcode::OR => binary_op!(stack, binary_op_or, inst, sb, consts),
                     Opcode::XOR => binary_op!(stack, binary_op_xor, inst, sb, consts),
                     Opcode::AND_NOT => binary_op!(stack, binary_op_and_not, inst, sb, consts),
-                    Opcode::SHL => shift_op!(stack, binary_op_shl, inst, sb, consts),
-                    Opcode::SHR => shift_op!(stack, binary_op_shr, inst, sb, consts),
-                    Opcode::ADD_ASSIGN => binary_op_assign!(stack, binary_op_add, inst, sb, consts),
+                    Opcode::SHL => {
+                        let result = shift_op!(stack, binary_op_shl, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::SHR => {
+                        let result = shift_op!(stack, binary_op_shr, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::ADD_ASSIGN => {
+                        binary_op_assign!(stack, binary_op_add, inst, sb, consts);
+                        if inst.t0 == ValueType::String {
+                            gcc.add_allocated(stack.get(inst.d + sb).as_string().len());
+                            check_allocation!(ctx, panic, frame, code);
+                        }
+                    }
                     Opcode::SUB_ASSIGN => binary_op_assign!(stack, binary_op_sub, inst, sb, consts),
                     Opcode::MUL_ASSIGN => binary_op_assign!(stack, binary_op_mul, inst, sb, consts),
                     Opcode::QUO_ASSIGN => binary_op_assign!(stack, binary_op_quo, inst, sb, consts),
@@ -753,8 +1478,14 @@ impl<'a> Fiber<'a> {
                     Opcode::AND_NOT_ASSIGN => {
                         binary_op_assign!(stack, binary_op_and_not, inst, sb, consts)
                     }
-                    Opcode::SHL_ASSIGN => shift_op_assign!(stack, binary_op_shl, inst, sb, consts),
-                    Opcode::SHR_ASSIGN => shift_op_assign!(stack, binary_op_shr, inst, sb, consts),
+                    Opcode::SHL_ASSIGN => {
+                        let result = shift_op_assign!(stack, binary_op_shl, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::SHR_ASSIGN => {
+                        let result = shift_op_assign!(stack, binary_op_shr, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
                     Opcode::INC => unsafe {
                         let v = stack.get_mut(inst.d + sb).data_mut();
                         *v = v.inc(inst.t0);
@@ -772,7 +1503,13 @@ impl<'a> Fiber<'a> {
                         let eq = if inst.t0.copyable() && inst.t0 == inst.t1 {
                             a.data().compare_eql(b.data(), inst.t0)
                         } else {
-                            a.eq(b)
+                            match iface_eq(a, b, objs) {
+                                Ok(eq) => eq,
+                                Err(e) => {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
+                            }
                         };
                         stack.set(inst.d + sb, eq.into());
                     }
@@ -782,7 +1519,13 @@ impl<'a> Fiber<'a> {
                         let neq = if inst.t0.copyable() {
                             a.data().compare_neq(b.data(), inst.t0)
                         } else {
-                            !a.eq(b)
+                            match iface_eq(a, b, objs) {
+                                Ok(eq) => !eq,
+                                Err(e) => {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
+                            }
                         };
                         stack.set(inst.d + sb, neq.into());
                     }
@@ -826,6 +1569,27 @@ impl<'a> Fiber<'a> {
                         };
                         stack.set(inst.d + sb, geq.into());
                     }
+                    Opcode::MIN | Opcode::MAX => {
+                        let a = stack.read(inst.s0, sb, consts);
+                        let b = stack.read(inst.s1, sb, consts);
+                        let max = inst_op == Opcode::MAX;
+                        let take_b = match inst.t0 {
+                            ValueType::Float32 => float_min_max_takes_b(
+                                a.as_float32().0 as f64,
+                                b.as_float32().0 as f64,
+                                max,
+                            ),
+                            ValueType::Float64 => {
+                                float_min_max_takes_b(a.as_float64().0, b.as_float64().0, max)
+                            }
+                            t if t.copyable() && max => b.data().compare_gtr(a.data(), t),
+                            t if t.copyable() => b.data().compare_lss(a.data(), t),
+                            _ if max => b.cmp(a) == Ordering::Greater,
+                            _ => b.cmp(a) == Ordering::Less,
+                        };
+                        let val = if take_b { b.clone() } else { a.clone() };
+                        stack.set(inst.d + sb, val);
+                    }
                     Opcode::REF => {
                         let val = stack.read(inst.s0, sb, consts);
                         let boxed = PointerObj::new_closed_up_value(&val);
@@ -867,7 +1631,7 @@ impl<'a> Fiber<'a> {
                             src.clone(),
                             &indices[inst.s1 as usize],
                             stack,
-                            objs,
+                            pkgs,
                         );
                         match struct_ {
                             Ok(target) => {
@@ -894,66 +1658,83 @@ impl<'a> Fiber<'a> {
                     #[cfg(feature = "async")]
                     Opcode::SEND => {
                         let chan = stack.read(inst.s0, sb, consts).as_channel().cloned();
-                        let val = stack.read(inst.s1, sb, consts).clone();
+                        let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
                         drop(stack_mut_ref);
                         let re = match chan {
-                            Some(c) => c.send(&val).await,
-                            None => loop {
-                                future::yield_now().await;
-                            },
+                            Some(c) => ctx.unless_interrupted(c.send(&val)).await,
+                            None => ctx.unless_interrupted(future::pending()).await,
                         };
                         restore_stack_ref!(self, stack, stack_mut_ref);
-                        panic_if_err!(re, panic, frame, code);
+                        match re {
+                            Some(re) => panic_if_err!(re, panic, frame, code),
+                            None => break,
+                        }
                     }
                     #[cfg(not(feature = "async"))]
                     Opcode::RECV => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
                     Opcode::RECV => {
-                        match stack.read(inst.s0, sb, consts).as_channel().cloned() {
-                            Some(chan) => {
-                                drop(stack_mut_ref);
-                                let val = chan.recv().await;
-                                restore_stack_ref!(self, stack, stack_mut_ref);
-                                let (unwrapped, ok) = unwrap_recv_val!(chan, val, gcc);
-                                stack.set(inst.d + sb, unwrapped);
-                                if inst.t1 == ValueType::FlagB {
-                                    stack.set(inst.s1 + sb, ok.into());
-                                }
-                            }
-                            None => loop {
-                                future::yield_now().await;
-                            },
+                        let chan = stack.read(inst.s0, sb, consts).as_channel().cloned();
+                        drop(stack_mut_ref);
+                        let val = match &chan {
+                            Some(c) => ctx.unless_interrupted(c.recv()).await,
+                            None => ctx.unless_interrupted(future::pending()).await,
                         };
+                        restore_stack_ref!(self, stack, stack_mut_ref);
+                        // a nil channel is only left by the interruption
+                        let (chan, val) = match (chan, val) {
+                            (Some(chan), Some(val)) => (chan, val),
+                            _ => break,
+                        };
+                        let (unwrapped, ok) = unwrap_recv_val!(chan, val, gcc);
+                        stack.set(inst.d + sb, unwrapped);
+                        if inst.t1 == ValueType::FlagB {
+                            stack.set(inst.s1 + sb, ok.into());
+                        }
                     }
                     Opcode::PACK_VARIADIC => {
-                        let v = stack.move_vec(inst.s0 + sb, inst.s1 + sb);
-                        let val = GosValue::slice_with_data(v, caller.get(inst.t0), gcc);
+                        // with no arguments for it, the variadic parameter is nil
+                        let val = if inst.s0 == inst.s1 {
+                            GosValue::new_nil_slice(inst.t0)
+                        } else {
+                            let v = stack.move_vec(inst.s0 + sb, inst.s1 + sb);
+                            GosValue::slice_with_data(v, caller.get(inst.t0), gcc)
+                        };
                         stack.set(inst.d + sb, val);
                     }
                     // t0: call style
+                    // t1: FlagA if the register for the receiver is left
                     // d: closure
                     // s0: next stack base
                     Opcode::CALL => {
                         let call_style = inst.t0;
-                        let cls = stack
-                            .read(inst.d, sb, consts)
-                            .as_closure()
-                            .unwrap()
-                            .0
-                            .clone();
+                        let cls = match stack.read(inst.d, sb, consts).as_non_nil_closure() {
+                            Ok(c) => c.0.clone(),
+                            Err(e) => {
+                                go_panic_str!(panic, e.as_str(), frame, code);
+                                continue;
+                            }
+                        };
                         let next_sb = sb + inst.s0;
                         match &cls {
                             ClosureObj::Gos(gosc) => {
                                 let next_func = &objs.functions[gosc.func];
                                 let mut returns_recv = next_func.ret_zeros.clone();
+                                stack.set_min_size(
+                                    (next_sb + next_func.max_write_index + 1) as usize,
+                                );
                                 if let Some(r) = &gosc.recv {
+                                    if inst.t1 != ValueType::FlagA {
+                                        // a method value called like a function, the
+                                        // arguments are where the receiver goes
+                                        let begin = next_sb + next_func.ret_count();
+                                        let end = begin + next_func.param_count() - 1;
+                                        stack.shift_up(begin, end);
+                                    }
                                     // push receiver on stack as the first parameter
                                     // don't call copy_semantic because BIND_METHOD did it already
                                     returns_recv.push(r.clone());
                                 }
-                                stack.set_min_size(
-                                    (next_sb + next_func.max_write_index + 1) as usize,
-                                );
                                 stack.set_vec(next_sb, returns_recv);
                             }
                             _ => {}
@@ -1001,10 +1782,12 @@ impl<'a> Fiber<'a> {
                                         let end = begin
                                             + nfunc.ret_count()
                                             + nfunc.param_count() as OpIndex;
-                                        let vec = stack.move_vec(begin, end);
+                                        let mut vec = self.context.pool.borrow_mut().take_stack();
+                                        stack.move_to(begin, end, &mut vec);
                                         let nstack = Stack::with_vec(vec);
                                         nframe.stack_base = 0;
-                                        self.context.spawn_fiber(nstack, nframe);
+                                        let local = self.local.borrow().clone();
+                                        self.context.spawn_fiber(nstack, nframe, local);
                                     }
                                     ValueType::FlagC => {
                                         // deferred
@@ -1017,7 +1800,7 @@ impl<'a> Fiber<'a> {
                                             frame: nframe,
                                             vec: vec,
                                         };
-                                        frame.defer_stack.get_or_insert(vec![]).push(deferred);
+                                        defer_call!(self, deferred, inst, frame, panic, code);
                                     }
                                     _ => unreachable!(),
                                 }
@@ -1028,33 +1811,27 @@ impl<'a> Fiber<'a> {
                                 let param_begin = result_begin + 1 + sig.results.len() as OpIndex;
                                 let end = param_begin + sig.params.len() as OpIndex;
                                 let params = stack.move_vec(param_begin, end);
-                                // release stack so that code in ffi can yield
-                                drop(stack_mut_ref);
-                                let returns = {
-                                    let mut ctx = FfiCtx {
-                                        func_name: &ffic.func_name,
-                                        vm_objs: objs,
-                                        user_data: ctx.ffi_factory.user_data(),
-                                        stack: &mut self.stack.borrow_mut(),
-                                        gcc,
-                                        array_slice_caller: caller,
+                                if call_style == ValueType::FlagC {
+                                    // deferred, the results are discarded
+                                    let deferred = DeferredCall {
+                                        frame: nframe,
+                                        vec: params,
                                     };
-                                    if !ffic.is_async {
-                                        ffic.ffi.call(&mut ctx, params)
-                                    } else {
-                                        #[cfg(not(feature = "async"))]
-                                        {
-                                            Err("Async features disabled".to_owned().into())
-                                        }
-                                        #[cfg(feature = "async")]
-                                        ffic.ffi.async_call(&mut ctx, params).await
+                                    defer_call!(self, deferred, inst, frame, panic, code);
+                                } else {
+                                    // release stack so that code in ffi can yield
+                                    drop(stack_mut_ref);
+                                    let returns = call_ffi!(self, ctx, &ffic, params);
+                                    restore_stack_ref!(self, stack, stack_mut_ref);
+                                    // os.Exit and the interruption stop right away
+                                    if ctx.is_interrupted() {
+                                        break;
                                     }
-                                };
-                                restore_stack_ref!(self, stack, stack_mut_ref);
-                                match returns {
-                                    Ok(result) => stack.set_vec(result_begin, result),
-                                    Err(e) => {
-                                        go_panic_str!(panic, e.as_str(), frame, code);
+                                    match returns {
+                                        Ok(result) => stack.set_vec(result_begin, result),
+                                        Err(e) => {
+                                            go_panic_str!(panic, e.as_str(), frame, code);
+                                        }
                                     }
                                 }
                             }
@@ -1071,14 +1848,13 @@ impl<'a> Fiber<'a> {
                             ValueType::FlagA => true,
                             // init_package func
                             ValueType::FlagB => {
-                                let pkey = stack.read(inst.d, sb, consts).as_package();
-                                let pkg = &objs.packages[*pkey];
-                                // the var values left on the stack are for pkg members
-                                let func = frame.func_obj(objs);
-                                let begin = sb;
-                                let end = begin + func.local_count();
-                                pkg.init_vars(stack.move_vec(begin, end));
-                                false
+                                let pkey = *stack.read(inst.d, sb, consts).as_package();
+                                let pkg = &pkgs[pkey];
+                                pkg.set_inited();
+                                if let Some(g) = &ctx.limits.globals {
+                                    g.apply(pkey, pkg, gcc);
+                                }
+                                true
                             }
                             // func with deferred calls
                             ValueType::FlagC => {
@@ -1088,6 +1864,19 @@ impl<'a> Fiber<'a> {
                                     // run Opcode::RETURN to check if deferred_stack is empty
                                     frame.pc -= 1;
 
+                                    if let ClosureObj::Ffi(ffic) = &call.frame.closure {
+                                        drop(stack_mut_ref);
+                                        let returns = call_ffi!(self, ctx, ffic, call.vec);
+                                        restore_stack_ref!(self, stack, stack_mut_ref);
+                                        if ctx.is_interrupted() {
+                                            break;
+                                        }
+                                        if let Err(e) = returns {
+                                            go_panic_str!(panic, e.as_str(), frame, code);
+                                        }
+                                        continue;
+                                    }
+
                                     let call_vec_len = call.vec.len() as OpIndex;
                                     let cur_func = frame.func_obj(objs);
                                     // dont overwrite locals of current function
@@ -1096,7 +1885,10 @@ impl<'a> Fiber<'a> {
                                         + cur_func.param_count()
                                         + cur_func.local_count();
                                     stack.set_vec(new_sb, call.vec);
-                                    let nframe = call.frame;
+                                    let mut nframe = call.frame;
+                                    // the frame is unwinding, the deferred call holds the
+                                    // panic until it returns, so that it can be recovered
+                                    nframe.recoverable = panic.take();
 
                                     self.frames.push(nframe);
                                     frame_height += 1;
@@ -1130,14 +1922,13 @@ impl<'a> Fiber<'a> {
                             let end = begin + func.param_count() + func.local_count();
                             stack.move_vec(begin, end);
                         }
+                        let unrecovered = frame.recoverable.take();
 
                         // We used to need this to make the compiler happy:
                         // drop(frame);
                         self.frames.pop();
                         frame_height -= 1;
                         if self.frames.is_empty() {
-                            dbg!(total_inst);
-
                             result = Result::End;
                             break;
                         }
@@ -1150,6 +1941,10 @@ impl<'a> Fiber<'a> {
                         if let Some(p) = &mut panic {
                             p.call_stack.push((frame.func(), frame.pc - 1));
                             frame.pc = code.len() as OpIndex - 1;
+                        } else if unrecovered.is_some() {
+                            // the deferred call returned without recovering, the
+                            // caller goes on unwinding, a new panic replaces it
+                            panic = unrecovered;
                         }
                     }
                     Opcode::JUMP => frame.pc += inst.d,
@@ -1170,14 +1965,36 @@ impl<'a> Fiber<'a> {
                         let ok = if t.copyable() {
                             a.data().compare_eql(b.data(), t)
                         } else if t != ValueType::Metadata {
-                            a.eq(&b)
+                            a.go_eq(&b)
                         } else {
-                            a.as_metadata().identical(b.as_metadata(), &objs.metas)
+                            type_matches(a.as_metadata(), b.as_metadata(), iface_memo, objs)
                         };
                         if ok {
                             frame.pc += inst.d;
                         }
                     }
+                    Opcode::SWITCH_TABLE => {
+                        // followed by inst.d entries, each jumps to a case, and then the
+                        // jump to default
+                        let tag = stack.read(inst.s0, sb, consts);
+                        let index = match inst.t0 {
+                            ValueType::String => {
+                                (switch_hash(&tag.as_string().as_bytes()) & (inst.d as usize - 1))
+                                    as u64
+                            }
+                            t => {
+                                let min = *stack.read(inst.s1, sb, consts).as_int64();
+                                let val = *tag.data().cast_copyable(t, ValueType::Int64).as_int64();
+                                val.wrapping_sub(min) as u64
+                            }
+                        };
+                        if index < inst.d as u64 {
+                            frame.pc += index as OpIndex;
+                            frame.pc += code[frame.pc as usize].d + 1;
+                        } else {
+                            frame.pc += inst.d;
+                        }
+                    }
                     #[cfg(not(feature = "async"))]
                     Opcode::SELECT => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
@@ -1207,8 +2024,12 @@ impl<'a> Fiber<'a> {
                         let selector = channel::Selector::new(comms, default_offset);
 
                         drop(stack_mut_ref);
-                        let re = selector.select().await;
+                        let re = ctx.unless_interrupted(selector.select()).await;
                         restore_stack_ref!(self, stack, stack_mut_ref);
+                        let re = match re {
+                            Some(re) => re,
+                            None => break,
+                        };
 
                         match re {
                             Ok((i, val)) => {
@@ -1248,16 +2069,25 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::RANGE_INIT => {
                         let target = stack.read(inst.s0, sb, consts);
-                        let re = self.rstack.range_init(target, inst.t0, caller.get(inst.t1));
+                        let re = self.rstack.range_init(
+                            (frame_height, inst.s1),
+                            target,
+                            inst.t0,
+                            caller.get(inst.t1),
+                            gcc,
+                        );
                         panic_if_err!(re, panic, frame, code);
                     }
                     Opcode::RANGE => {
+                        let inst_ex = &code[frame.pc as usize];
+                        frame.pc += 1;
                         if self.rstack.range_body(
-                            inst.t0,
+                            (frame_height, inst.s1),
                             caller.get(inst.t1),
                             stack,
                             inst.d + sb,
-                            inst.s1 + sb,
+                            inst_ex.d + sb,
+                            gcc,
                         ) {
                             frame.pc += inst.s0;
                         }
@@ -1266,7 +2096,7 @@ impl<'a> Fiber<'a> {
                     Opcode::LOAD_INIT_FUNC => {
                         let src = stack.read(inst.s0, sb, consts);
                         let index = *stack.read(inst.s1, sb, consts).as_int32();
-                        let pkg = &objs.packages[*src.as_package()];
+                        let pkg = &pkgs[*src.as_package()];
                         match pkg.init_func(index) {
                             Some(f) => {
                                 stack.set(inst.d + sb, f.clone());
@@ -1291,7 +2121,14 @@ impl<'a> Fiber<'a> {
                     Opcode::BIND_I_METHOD => {
                         match stack.read(inst.s0, sb, consts).as_non_nil_interface() {
                             Ok(iface) => {
-                                match bind_iface_method(iface, inst.s1 as usize, stack, objs, gcc) {
+                                match bind_iface_method(
+                                    iface,
+                                    inst.s1 as usize,
+                                    stack,
+                                    objs,
+                                    pkgs,
+                                    gcc,
+                                ) {
                                     Ok(cls) => stack.set(inst.d + sb, cls),
                                     Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                                 }
@@ -1319,12 +2156,25 @@ impl<'a> Fiber<'a> {
                                 .read(inst.s0, sb, consts)
                                 .cast_copyable(from_type, to_type),
                             ValueType::Interface => {
-                                let binding = ifaces[inst.s1 as usize].clone();
-                                let under = stack.read(inst.s0, sb, consts).copy_semantic(gcc);
-                                GosValue::new_interface(InterfaceObj::with_value(
-                                    under,
-                                    Some(binding),
-                                ))
+                                let val = stack.read(inst.s0, sb, consts);
+                                if val.typ() == ValueType::Interface {
+                                    // from another interface, the binding holds the
+                                    // meta of the interface to bind the dynamic type to
+                                    let iface = &ifaces[inst.s1 as usize].0;
+                                    match rebind_iface(val, iface, iface_memo, objs) {
+                                        Ok(v) => v,
+                                        Err(e) => {
+                                            go_panic_str!(panic, e.as_str(), frame, code);
+                                            continue;
+                                        }
+                                    }
+                                } else {
+                                    let binding = ifaces[inst.s1 as usize].clone();
+                                    GosValue::new_interface(InterfaceObj::with_value(
+                                        val.copy_semantic(gcc),
+                                        Some(binding),
+                                    ))
+                                }
                             }
                             ValueType::String => match from_type {
                                 ValueType::Slice => match inst.op1_as_t() {
@@ -1345,7 +2195,9 @@ impl<'a> Fiber<'a> {
                                     }
                                     ValueType::Uint8 => {
                                         match stack.read(inst.s0, sb, consts).as_slice::<Elem8>() {
-                                            Some(slice) => GosValue::new_string(slice.0.clone()),
+                                            Some(slice) => {
+                                                GosValue::with_bytes(&slice.0.as_bytes())
+                                            }
                                             None => GosValue::with_str(""),
                                         }
                                     }
@@ -1362,10 +2214,8 @@ impl<'a> Fiber<'a> {
                                 let from = stack.read(inst.s0, sb, consts).as_string();
                                 match inst.op1_as_t() {
                                     ValueType::Int32 => {
-                                        let data = from
-                                            .as_str()
-                                            .chars()
-                                            .map(|x| (x as i32).into())
+                                        let data = StringIter::new(&from.as_bytes())
+                                            .map(|(_, x)| (x as i32).into())
                                             .collect();
                                         GosValue::slice_with_data(
                                             data,
@@ -1373,9 +2223,10 @@ impl<'a> Fiber<'a> {
                                             gcc,
                                         )
                                     }
-                                    ValueType::Uint8 => {
-                                        GosValue::new_slice(from.clone(), ValueType::Uint8)
-                                    }
+                                    ValueType::Uint8 => GosValue::new_slice(
+                                        StringObj::with_bytes(&from.as_bytes()),
+                                        ValueType::Uint8,
+                                    ),
                                     _ => unreachable!(),
                                 }
                             }
@@ -1389,7 +2240,7 @@ impl<'a> Fiber<'a> {
                                                     match h.ptr().cast(
                                                         inst.op1_as_t(),
                                                         &stack,
-                                                        &objs.packages,
+                                                        pkgs,
                                                     ) {
                                                         Ok(p) => GosValue::new_pointer(p),
                                                         Err(e) => {
@@ -1425,14 +2276,34 @@ impl<'a> Fiber<'a> {
                                 unimplemented!()
                             }
                         };
+                        // strings and the bytes of a []byte(s) don't go through new_array
+                        let bytes = match to_type {
+                            ValueType::String => val.as_string().len(),
+                            ValueType::Slice if inst.op1_as_t() == ValueType::Uint8 => {
+                                val.as_slice::<Elem8>().map_or(0, |s| s.0.len())
+                            }
+                            _ => 0,
+                        };
                         stack.set(inst.d + sb, val);
+                        if bytes > 0 {
+                            gcc.add_allocated(bytes);
+                            check_allocation!(ctx, panic, frame, code);
+                        }
                     }
                     Opcode::TYPE_ASSERT => {
                         let val = stack.read(inst.s0, sb, consts);
-                        match type_assert(val, cst(consts, inst.s1), gcc, &objs.metas) {
+                        let comma_ok = inst.t1 == ValueType::FlagB;
+                        match type_assert(
+                            val,
+                            cst(consts, inst.s1),
+                            comma_ok,
+                            gcc,
+                            iface_memo,
+                            objs,
+                        ) {
                             Ok((val, ok)) => {
                                 stack.set(inst.d + sb, val);
-                                if inst.t1 == ValueType::FlagB {
+                                if comma_ok {
                                     let inst_ex = &code[frame.pc as usize];
                                     frame.pc += 1;
                                     stack.set(inst_ex.d + sb, ok.into());
@@ -1468,7 +2339,7 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::IMPORT => {
                         let pkey = *stack.read(inst.s0, sb, consts).as_package();
-                        if objs.packages[pkey].inited() {
+                        if pkgs[pkey].inited() {
                             frame.pc += inst.d
                         }
                     }
@@ -1503,8 +2374,10 @@ impl<'a> Fiber<'a> {
                                     // We used to need this to make the compiler happy:
                                     //drop(frame);
                                     for (_, uv) in uvs.iter_mut() {
-                                        let r: &mut UpValueState = &mut uv.inner.borrow_mut();
-                                        if let UpValueState::Open(d) = r {
+                                        let mut shared = None;
+                                        if let UpValueState::Open(d) =
+                                            &mut uv.inner.borrow_mut() as &mut UpValueState
+                                        {
                                             // get frame index, and add_referred_by
                                             for i in 1..frame_height {
                                                 let index = frame_height - i;
@@ -1512,12 +2385,19 @@ impl<'a> Fiber<'a> {
                                                     let upframe = &mut self.frames[index];
                                                     d.stack = Rc::downgrade(&self.stack);
                                                     d.stack_base = upframe.stack_base as OpIndex;
-                                                    upframe.add_referred_by(d.index, d.typ, uv);
+                                                    // closures capturing the same variable share the upvalue
+                                                    shared = upframe.referred_upvalue(d.index);
+                                                    if shared.is_none() {
+                                                        upframe.add_referred_by(d.index, d.typ, uv);
+                                                    }
                                                     // if not found, the upvalue is already closed, nothing to be done
                                                     break;
                                                 }
                                             }
                                         }
+                                        if let Some(shared) = shared {
+                                            *uv = shared;
+                                        }
                                     }
                                     frame = self.frames.last_mut().unwrap();
                                 }
@@ -1533,13 +2413,22 @@ impl<'a> Fiber<'a> {
 
                         let begin = inst.s0 + sb;
                         let count = inst.s1;
-                        let build_val = |m: &Meta| {
+                        if let MetadataType::Map(_, _) = &objs.metas[md.key] {
+                            let unhashable = (0..count)
+                                .find_map(|i| check_hashable(stack.get(begin + i * 2), objs).err());
+                            if let Some(e) = unhashable {
+                                go_panic_str!(panic, e.as_str(), frame, code);
+                                continue;
+                            }
+                        }
+                        // the elements not in an array literal are zero values
+                        let build_val = |m: &Meta, len: usize| {
                             let zero_val = m.zero(&objs.metas, gcc);
                             let mut val = vec![];
                             let mut cur_index = -1;
                             for i in 0..count {
                                 let index = *stack.get(begin + i * 2).as_int32();
-                                let elem = stack.get(begin + 1 + i * 2).clone();
+                                let elem = stack.get(begin + 1 + i * 2).copy_semantic(gcc);
                                 if index < 0 {
                                     cur_index += 1;
                                 } else {
@@ -1550,32 +2439,36 @@ impl<'a> Fiber<'a> {
                                     val.push(elem);
                                 } else if gap > 0 {
                                     for _ in 0..gap {
-                                        val.push(zero_val.clone());
+                                        val.push(zero_val.copy_semantic(gcc));
                                     }
                                     val.push(elem);
                                 } else {
                                     val[cur_index as usize] = elem;
                                 }
                             }
+                            while val.len() < len {
+                                val.push(zero_val.copy_semantic(gcc));
+                            }
                             (val, zero_val.typ())
                         };
                         let new_val = match &objs.metas[md.key] {
                             MetadataType::Slice(m) => {
-                                let (val, typ) = build_val(m);
+                                let (val, typ) = build_val(m, 0);
                                 GosValue::slice_with_data(val, caller.get(typ), gcc)
                             }
-                            MetadataType::Array(m, _) => {
-                                let (val, typ) = build_val(m);
+                            MetadataType::Array(m, size) => {
+                                let (val, typ) = build_val(m, *size);
                                 GosValue::array_with_data(val, caller.get(typ), gcc)
                             }
                             MetadataType::Map(_, _) => {
                                 let map_val = GosValue::new_map(gcc);
                                 let map = map_val.as_map().unwrap();
                                 for i in 0..count {
-                                    let k = stack.get(begin + i * 2).clone();
-                                    let v = stack.get(begin + 1 + i * 2).clone();
+                                    let k = stack.get(begin + i * 2).copy_semantic(gcc);
+                                    let v = stack.get(begin + 1 + i * 2).copy_semantic(gcc);
                                     map.0.insert(k, v);
                                 }
+                                gcc.add_allocated(map.0.len() * MapObj::ENTRY_SIZE);
                                 map_val
                             }
                             MetadataType::Struct(_) => {
@@ -1584,7 +2477,7 @@ impl<'a> Fiber<'a> {
                                     let fields = &mut struct_val.as_struct().0.borrow_fields_mut();
                                     for i in 0..count {
                                         let index = *stack.get(begin + i * 2).as_uint();
-                                        fields[index] = stack.get(begin + 1 + i * 2).clone();
+                                        fields[index] = stack.get(begin + 1 + i * 2).copy_semantic(gcc);
                                     }
                                 }
                                 struct_val
@@ -1592,6 +2485,7 @@ impl<'a> Fiber<'a> {
                             _ => unreachable!(),
                         };
                         stack.set(inst.d + sb, new_val);
+                        check_allocation!(ctx, panic, frame, code);
                     }
                     Opcode::NEW => {
                         let md = stack.read(inst.s0, sb, consts).as_metadata();
@@ -1603,32 +2497,42 @@ impl<'a> Fiber<'a> {
                         let md = stack.read(inst.s0, sb, consts).as_metadata();
                         let val = match md.mtype_unwraped(&objs.metas) {
                             MetadataType::Slice(vmeta) => {
-                                let (cap, len) = match inst.t0 {
+                                let (len, cap) = match inst.t0 {
                                     // 3 args
                                     ValueType::FlagC => {
                                         let inst_ex = &code[frame.pc as usize];
                                         frame.pc += 1;
                                         (
-                                            *stack.read(inst.s1, sb, consts).as_int() as usize,
-                                            *stack.read(inst_ex.s0, sb, consts).as_int() as usize,
+                                            stack.read(inst.s1, sb, consts).as_index(),
+                                            stack.read(inst_ex.s0, sb, consts).as_index(),
                                         )
                                     }
                                     // 2 args
                                     ValueType::FlagB => {
-                                        let len =
-                                            *stack.read(inst.s1, sb, consts).as_int() as usize;
+                                        let len = stack.read(inst.s1, sb, consts).as_index();
                                         (len, len)
                                     }
                                     _ => unreachable!(),
                                 };
+                                // negative sizes come in wrapped around by as_index
+                                if len > MAX_MAKE_SIZE {
+                                    let msg = "runtime error: makeslice: len out of range";
+                                    go_panic_str!(panic, msg, frame, code);
+                                    continue;
+                                }
+                                if cap < len || cap > MAX_MAKE_SIZE {
+                                    let msg = "runtime error: makeslice: cap out of range";
+                                    go_panic_str!(panic, msg, frame, code);
+                                    continue;
+                                }
                                 let zero = vmeta.zero(&objs.metas, gcc);
-                                GosValue::slice_with_size(
-                                    len,
-                                    cap,
-                                    &zero,
-                                    caller.get(zero.typ()),
-                                    gcc,
-                                )
+                                let disp = caller.get(zero.typ());
+                                let bytes = cap.saturating_mul(disp.elem_size());
+                                if let Err(e) = ctx.reserve_allocation(bytes) {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
+                                GosValue::slice_with_size(len, cap, &zero, disp, gcc)
                             }
                             MetadataType::Map(_, _) => GosValue::new_map(gcc),
                             #[cfg(not(feature = "async"))]
@@ -1640,19 +2544,28 @@ impl<'a> Fiber<'a> {
                             MetadataType::Channel(_, val_meta) => {
                                 let cap = match inst.t0 {
                                     // 2 args
-                                    ValueType::FlagB => {
-                                        *stack.read(inst.s1, sb, consts).as_int() as usize
-                                    }
+                                    ValueType::FlagB => stack.read(inst.s1, sb, consts).as_index(),
                                     // 1 arg
                                     ValueType::FlagA => 0,
                                     _ => unreachable!(),
                                 };
+                                if cap > MAX_MAKE_SIZE {
+                                    go_panic_str!(panic, "makechan: size out of range", frame, code);
+                                    continue;
+                                }
+                                let bytes = cap.saturating_mul(std::mem::size_of::<GosValue>());
+                                if let Err(e) = ctx.reserve_allocation(bytes) {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
+                                gcc.add_allocated(bytes);
                                 let zero = val_meta.zero(&objs.metas, gcc);
                                 GosValue::new_channel(ChannelObj::new(cap, zero))
                             }
                             _ => unreachable!(),
                         };
                         stack.set(inst.d + sb, val);
+                        check_allocation!(ctx, panic, frame, code);
                     }
                     Opcode::COMPLEX => {
                         // for the specs: For complex, the two arguments must be of the same
@@ -1718,7 +2631,10 @@ impl<'a> Fiber<'a> {
                         };
 
                         match caller.get(inst.t1).slice_append(a, b, gcc) {
-                            Ok(slice) => stack.set(inst.d + sb, slice),
+                            Ok(slice) => {
+                                stack.set(inst.d + sb, slice);
+                                check_allocation!(ctx, panic, frame, code);
+                            }
                             Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                         };
                     }
@@ -1740,11 +2656,34 @@ impl<'a> Fiber<'a> {
                     Opcode::DELETE => {
                         let map = stack.read(inst.s0, sb, consts);
                         let key = stack.read(inst.s1, sb, consts);
+                        if let Err(e) = check_hashable(key, objs) {
+                            go_panic_str!(panic, e.as_str(), frame, code);
+                            continue;
+                        }
                         match map.as_map() {
                             Some(m) => m.0.delete(key),
                             None => {}
                         }
                     }
+                    Opcode::CLEAR => {
+                        let val = stack.read(inst.s0, sb, consts);
+                        let md = stack.read(inst.s1, sb, consts).as_metadata();
+                        match md.mtype_unwraped(&objs.metas) {
+                            MetadataType::Slice(vmeta) => {
+                                // a nil slice has no elements to zero
+                                let zero = vmeta.zero(&objs.metas, gcc);
+                                let dispatcher = caller.get(zero.typ());
+                                for i in 0..val.len() {
+                                    let _ = dispatcher.slice_set(val, &zero.copy_semantic(gcc), i);
+                                }
+                            }
+                            _ => {
+                                if let Some(m) = val.as_map() {
+                                    m.0.clear();
+                                }
+                            }
+                        }
+                    }
                     #[cfg(not(feature = "async"))]
                     Opcode::CLOSE => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
@@ -1754,13 +2693,33 @@ impl<'a> Fiber<'a> {
                     },
                     Opcode::PANIC => {
                         let val = stack.read(inst.s0, sb, consts).clone();
+                        // a string is a runtime error from the generated code,
+                        // panic() is called with an interface
+                        let val = match val.typ() {
+                            ValueType::String => GosValue::empty_iface_with_val(val),
+                            _ => val,
+                        };
                         go_panic!(panic, val, frame, code);
                     }
                     Opcode::RECOVER => {
-                        let p = panic.take();
-                        let val = p.map_or(GosValue::new_nil(ValueType::Void), |x| {
-                            GosValue::new_interface(InterfaceObj::with_value(x.msg, None))
-                        });
+                        // only a deferred call run by a panicking frame can stop the
+                        // panic, a recover() anywhere else returns nil
+                        let val = match frame.recoverable.take() {
+                            // runtime errors are panicked with strings that
+                            // have no type info attached
+                            Some(p) => match p.msg.as_interface() {
+                                Some(InterfaceObj::Gos(v, None))
+                                    if v.typ() == ValueType::String =>
+                                {
+                                    let info = Some((prim_meta.mstr, vec![]));
+                                    let iface = InterfaceObj::with_value(v.clone(), info);
+                                    GosValue::new_interface(iface)
+                                }
+                                // the value panic() was called with, as it is
+                                _ => p.msg,
+                            },
+                            None => GosValue::new_nil(ValueType::Interface),
+                        };
                         stack.set(inst.d + sb, val);
                     }
                     Opcode::ASSERT => {
@@ -1791,16 +2750,27 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::VOID => unreachable!(),
                 }
-            } //yield unit
+            } //time slice
             match result {
                 Result::End => {
-                    *ctx.panic_data.borrow_mut() = panic.take();
+                    match &self.callback {
+                        Some(call) => call.finish(match panic.take() {
+                            Some(p) => Err(p),
+                            None => Ok(stack.move_vec(0, func.ret_count())),
+                        }),
+                        None => *ctx.panic_data.borrow_mut() = panic.take(),
+                    }
                     break;
                 }
                 Result::Continue => {
                     drop(stack_mut_ref);
+                    // stops right away, see the check at the top
                     #[cfg(feature = "async")]
-                    future::yield_now().await;
+                    if !ctx.is_interrupted() {
+                        future::yield_now().await;
+                    }
+                    #[cfg(not(feature = "async"))]
+                    ctx.run_callbacks();
                     restore_stack_ref!(self, stack, stack_mut_ref);
                 }
             };
@@ -1810,6 +2780,22 @@ impl<'a> Fiber<'a> {
     }
 }
 
+/// Whether min or max of floats `a` and `b` is `b`, as in Go: NaN if
+/// either is, and -0 is less than +0.
+#[inline]
+fn float_min_max_takes_b(a: f64, b: f64, max: bool) -> bool {
+    if a.is_nan() {
+        false
+    } else if b.is_nan() {
+        true
+    } else if a == b {
+        // only differs for zeros of different signs
+        a.is_sign_negative() == max && b.is_sign_negative() != max
+    } else {
+        (b > a) == max
+    }
+}
+
 #[inline]
 fn char_from_u32(u: u32) -> char {
     unsafe { char::from_u32_unchecked(u) }
@@ -1821,8 +2807,8 @@ fn char_from_i32(i: i32) -> char {
 }
 
 #[inline]
-fn deref_value(v: &GosValue, stack: &Stack, objs: &VMObjects) -> RuntimeResult<GosValue> {
-    v.as_non_nil_pointer()?.deref(stack, &objs.packages)
+fn deref_value(v: &GosValue, stack: &Stack, pkgs: &PackageObjs) -> RuntimeResult<GosValue> {
+    v.as_non_nil_pointer()?.deref(stack, pkgs)
 }
 
 #[inline(always)]
@@ -1830,22 +2816,207 @@ fn cst(consts: &Vec<GosValue>, i: OpIndex) -> &GosValue {
     &consts[(-i - 1) as usize]
 }
 
+/// Compares two values the way Go compares interfaces if both are, the dynamic
+/// types first and then the values, which must be comparable.
+fn iface_eq(a: &GosValue, b: &GosValue, objs: &VMObjects) -> RuntimeResult<bool> {
+    if a.typ() != ValueType::Interface || b.typ() != ValueType::Interface {
+        return Ok(a.go_eq(b));
+    }
+    match (a.as_interface(), b.as_interface()) {
+        (Some(InterfaceObj::Gos(x, Some((xm, _)))), Some(InterfaceObj::Gos(y, Some((ym, _))))) => {
+            if !xm.identical(ym, &objs.metas) {
+                Ok(false)
+            } else if !xm.comparable(&objs.metas) {
+                Err(format!(
+                    "runtime error: comparing uncomparable type {}",
+                    MetaView::new(*xm, objs)
+                )
+                .into())
+            } else {
+                Ok(x.go_eq(y))
+            }
+        }
+        _ => Ok(a.go_eq(b)),
+    }
+}
+
+/// A map key holding a dynamic value of a type that can't be compared, like
+/// a func in an interface, can't be hashed either. Only the keys that are or
+/// have interfaces can, the others are not walked.
+#[inline]
+fn check_hashable(key: &GosValue, objs: &VMObjects) -> RuntimeResult<()> {
+    match key.typ() {
+        ValueType::Interface | ValueType::Struct | ValueType::Array => {
+            match unhashable_type(key, objs) {
+                Some(m) => Err(format!(
+                    "runtime error: hash of unhashable type {}",
+                    MetaView::new(m, objs)
+                )
+                .into()),
+                None => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The first dynamic type in `key` that can't be compared.
+fn unhashable_type(key: &GosValue, objs: &VMObjects) -> Option<Meta> {
+    match key.typ() {
+        ValueType::Interface => match key.as_interface() {
+            Some(InterfaceObj::Gos(v, Some((m, _)))) => match m.comparable(&objs.metas) {
+                true => unhashable_type(v, objs),
+                false => Some(*m),
+            },
+            _ => None,
+        },
+        ValueType::Struct => key
+            .as_struct()
+            .0
+            .borrow_fields()
+            .iter()
+            .find_map(|x| unhashable_type(x, objs)),
+        ValueType::Array if !key.t_elem().copyable() => key
+            .as_array::<GosElem>()
+            .0
+            .borrow_data()
+            .iter()
+            .find_map(|x| unhashable_type(&x.borrow(), objs)),
+        _ => None,
+    }
+}
+
+/// The results of `Meta::implements` for the pairs of a dynamic type and an
+/// interface met by assertions, type switches and conversions between
+/// interfaces, the failures included, so the method sets are only compared
+/// once.
+///
+/// It's kept by the run, so a run of newly loaded or relinked Bytecode
+/// always starts with an empty one.
+#[derive(Default)]
+struct IfaceMemo(RefCell<Map<(Meta, Meta), Implements>>);
+
+/// The bindings if the type implements the interface.
+type Implements = Option<Vec<Binding4Runtime>>;
+
+impl IfaceMemo {
+    /// Same as `meta.implements(iface, objs)`.
+    fn implements(
+        &self,
+        meta: &Meta,
+        iface: &Meta,
+        objs: &VMObjects,
+    ) -> Option<Vec<Binding4Runtime>> {
+        self.lookup(meta, iface, objs, |x| x.cloned())
+    }
+
+    /// Same as `meta.implements(iface, objs).is_some()`, without copying the
+    /// bindings.
+    fn satisfies(&self, meta: &Meta, iface: &Meta, objs: &VMObjects) -> bool {
+        self.lookup(meta, iface, objs, |x| x.is_some())
+    }
+
+    fn lookup<R>(
+        &self,
+        meta: &Meta,
+        iface: &Meta,
+        objs: &VMObjects,
+        f: impl FnOnce(Option<&Vec<Binding4Runtime>>) -> R,
+    ) -> R {
+        let key = (*meta, *iface);
+        if let Some(re) = self.0.borrow().get(&key) {
+            return f(re.as_ref());
+        }
+        let re = meta.implements(iface, objs);
+        let result = f(re.as_ref());
+        self.0.borrow_mut().insert(key, re);
+        result
+    }
+}
+
+/// Checks if the dynamic type `meta` of a value matches the type of a type switch case.
 #[inline]
+fn type_matches(meta: &Meta, want_meta: &Meta, memo: &IfaceMemo, objs: &VMObjects) -> bool {
+    if want_meta.is_interface(&objs.metas) {
+        *meta != objs.prim_meta.none && memo.satisfies(meta, want_meta, objs)
+    } else {
+        want_meta.identical(meta, &objs.metas)
+    }
+}
+
+/// Binds the dynamic value of the interface `val` to the interface type
+/// `iface`, a nil interface stays nil.
+fn rebind_iface(
+    val: &GosValue,
+    iface: &Meta,
+    memo: &IfaceMemo,
+    objs: &VMObjects,
+) -> RuntimeResult<GosValue> {
+    match val.as_interface() {
+        Some(i) => match &i as &InterfaceObj {
+            InterfaceObj::Gos(v, Some((meta, _))) => match memo.implements(meta, iface, objs) {
+                Some(binding) => Ok(GosValue::new_interface(InterfaceObj::with_value(
+                    v.clone(),
+                    Some((*meta, binding)),
+                ))),
+                None => Err(format!(
+                    "interface conversion: {} is not {}: missing method {}",
+                    MetaView::new(*meta, objs),
+                    MetaView::new(*iface, objs),
+                    meta.missing_method(iface, objs).unwrap_or_default()
+                )
+                .into()),
+            },
+            _ => Ok(val.clone()),
+        },
+        None => Ok(val.clone()),
+    }
+}
+
+#[inline]
+/// Without `comma_ok`, a failed assertion is an error with Go's message, the
+/// static type of the interface is unknown here and shown as "interface".
 fn type_assert(
     val: &GosValue,
     want_meta: &GosValue,
+    comma_ok: bool,
     gcc: &GcContainer,
-    metas: &MetadataObjs,
+    memo: &IfaceMemo,
+    objs: &VMObjects,
 ) -> RuntimeResult<(GosValue, bool)> {
+    let metas = &objs.metas;
     let want_meta = want_meta.as_metadata();
+    let want = || MetaView::new(*want_meta, objs);
+    let failed = |msg: String| match comma_ok {
+        true => Ok((want_meta.zero(metas, gcc), false)),
+        false => Err(format!("interface conversion: {}", msg).into()),
+    };
     match val.as_interface() {
         Some(iface) => match &iface as &InterfaceObj {
             InterfaceObj::Gos(v, mb) => match mb {
                 Some((meta, _)) => {
-                    if want_meta.identical(meta, metas) {
+                    let have = MetaView::new(*meta, objs);
+                    if want_meta.is_interface(metas) {
+                        // x.(I) yields an interface value of type I with the same dynamic type
+                        match memo.implements(meta, want_meta, objs) {
+                            Some(binding) => Ok((
+                                GosValue::new_interface(InterfaceObj::with_value(
+                                    v.clone(),
+                                    Some((*meta, binding)),
+                                )),
+                                true,
+                            )),
+                            None => failed(format!(
+                                "{} is not {}: missing method {}",
+                                have,
+                                want(),
+                                meta.missing_method(want_meta, objs).unwrap_or_default()
+                            )),
+                        }
+                    } else if want_meta.identical(meta, metas) {
                         Ok((v.copy_semantic(gcc), true))
                     } else {
-                        Ok((want_meta.zero(metas, gcc), false))
+                        failed(format!("interface is {}, not {}", have, want()))
                     }
                 }
                 None => Err("No type info available for interface value"
@@ -1856,7 +3027,7 @@ fn type_assert(
                 .to_owned()
                 .into()),
         },
-        None => Ok((want_meta.zero(metas, gcc), false)),
+        None => failed(format!("interface is nil, not {}", want())),
     }
 }
 
@@ -1865,16 +3036,16 @@ fn get_struct_and_index(
     val: GosValue,
     indices: &Vec<OpIndex>,
     stack: &mut Stack,
-    objs: &VMObjects,
+    pkgs: &PackageObjs,
 ) -> (RuntimeResult<GosValue>, usize) {
     let (target, index) = {
-        let val = get_embeded(val, &indices[..indices.len() - 1], stack, &objs.packages);
+        let val = get_embeded(val, &indices[..indices.len() - 1], stack, pkgs);
         (val, *indices.last().unwrap())
     };
     (
         match target {
             Ok(v) => match v.typ() {
-                ValueType::Pointer => deref_value(&v, stack, objs),
+                ValueType::Pointer => deref_value(&v, stack, pkgs),
                 _ => Ok(v.clone()),
             },
             Err(e) => Err(e),
@@ -1890,12 +3061,12 @@ fn get_embeded(
     stack: &Stack,
     pkgs: &PackageObjs,
 ) -> RuntimeResult<GosValue> {
-    let typ = val.typ();
     let mut cur_val: GosValue = val;
-    if typ == ValueType::Pointer {
-        cur_val = cur_val.as_non_nil_pointer()?.deref(stack, pkgs)?;
-    }
     for &i in indices.iter() {
+        // the struct itself or an embedded field can be a pointer
+        if cur_val.typ() == ValueType::Pointer {
+            cur_val = cur_val.as_non_nil_pointer()?.deref(stack, pkgs)?;
+        }
         let s = &cur_val.as_struct().0;
         let v = s.borrow_fields()[i as usize].clone();
         cur_val = v;
@@ -1903,12 +3074,32 @@ fn get_embeded(
     Ok(cur_val)
 }
 
+/// The pointer receiver of a method promoted from an embedded field, the
+/// method sees the field in the struct rather than a copy of it.
+fn get_embeded_ref(
+    val: GosValue,
+    indices: &[OpIndex],
+    stack: &Stack,
+    pkgs: &PackageObjs,
+) -> RuntimeResult<GosValue> {
+    let (last, path) = indices.split_last().unwrap();
+    let mut parent = get_embeded(val, path, stack, pkgs)?;
+    if parent.typ() == ValueType::Pointer {
+        parent = parent.as_non_nil_pointer()?.deref(stack, pkgs)?;
+    }
+    let field = parent.as_struct().0.borrow_fields()[*last as usize].clone();
+    Ok(match field.typ() {
+        ValueType::Pointer => field,
+        _ => GosValue::new_pointer(PointerObj::StructField(parent, *last)),
+    })
+}
+
 #[inline]
 fn cast_receiver(
     receiver: GosValue,
     b1: bool,
     stack: &Stack,
-    objs: &VMObjects,
+    pkgs: &PackageObjs,
 ) -> RuntimeResult<GosValue> {
     let b0 = receiver.typ() == ValueType::Pointer;
     if b0 == b1 {
@@ -1918,7 +3109,7 @@ fn cast_receiver(
             UpValue::new_closed(receiver.clone()),
         )))
     } else {
-        deref_value(&receiver, stack, objs)
+        deref_value(&receiver, stack, pkgs)
     }
 }
 
@@ -1928,6 +3119,7 @@ fn bind_iface_method(
     index: usize,
     stack: &Stack,
     objs: &VMObjects,
+    pkgs: &PackageObjs,
     gcc: &GcContainer,
 ) -> RuntimeResult<GosValue> {
     match iface {
@@ -1937,20 +3129,22 @@ fn bind_iface_method(
                 Binding4Runtime::Struct(func, ptr_recv, indices) => {
                     let obj = match indices {
                         None => obj.copy_semantic(gcc),
-                        Some(inds) => get_embeded(obj.clone(), inds, stack, &objs.packages)?
-                            .copy_semantic(gcc),
+                        Some(inds) if *ptr_recv => get_embeded_ref(obj.clone(), inds, stack, pkgs)?,
+                        Some(inds) => {
+                            get_embeded(obj.clone(), inds, stack, pkgs)?.copy_semantic(gcc)
+                        }
                     };
-                    let obj = cast_receiver(obj, *ptr_recv, stack, objs)?;
+                    let obj = cast_receiver(obj, *ptr_recv, stack, pkgs)?;
                     let cls = ClosureObj::gos_from_func(*func, &objs.functions, Some(obj));
                     Ok(GosValue::new_closure(cls, gcc))
                 }
                 Binding4Runtime::Iface(i, indices) => {
                     let bind = |obj: &GosValue| {
-                        bind_iface_method(&obj.as_interface().unwrap(), *i, stack, objs, gcc)
+                        bind_iface_method(obj.as_interface().unwrap(), *i, stack, objs, pkgs, gcc)
                     };
                     match indices {
                         None => bind(&obj),
-                        Some(inds) => bind(&get_embeded(obj.clone(), inds, stack, &objs.packages)?),
+                        Some(inds) => bind(&get_embeded(obj.clone(), inds, stack, pkgs)?),
                     }
                 }
             }
@@ -1968,3 +3162,34 @@ fn bind_iface_method(
         }
     }
 }
+
+#[cfg(all(test, feature = "async"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_wait() {
+        let interrupt = Interrupt::new();
+        let handle = interrupt.clone();
+        let timer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle.interrupt();
+        });
+        // block_on parks the thread, only the waker gets it going again
+        future::block_on(interrupt.wait());
+        timer.join().unwrap();
+        assert!(interrupt.0.wakers.lock().unwrap().is_empty());
+
+        // the waits that end otherwise don't leave their wakers behind
+        interrupt.reset();
+        let re = future::block_on(future::or(
+            async {
+                interrupt.wait().await;
+                0
+            },
+            async { 1 },
+        ));
+        assert_eq!(re, 1);
+        assert!(interrupt.0.wakers.lock().unwrap().is_empty());
+    }
+}